@@ -0,0 +1,34 @@
+//! Builds the runtime that native executables link, `src/x86/runtime/lib.rs`, as a static library the compiler embeds,
+//! and the `main` that calls it, `src/x86/runtime/main.rs`, as an object
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn main() {
+    let out = PathBuf::from(env::var("OUT_DIR").expect("Expected cargo to set `OUT_DIR`"));
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    println!("cargo:rerun-if-changed=src/x86/runtime");
+
+    let mut command = Command::new(&rustc);
+    command.args(["--crate-type", "staticlib", "--crate-name", "whyrt"]);
+    command.args(["-C", "panic=abort", "-C", "opt-level=2", "-C", "lto"]);
+    run(command, &rustc, "src/x86/runtime/lib.rs", &out.join("libwhyrt.a"));
+
+    let mut command = Command::new(&rustc);
+    command.args(["--crate-type", "lib", "--crate-name", "whymain", "--emit", "obj"]);
+    command.args(["-C", "panic=abort", "-C", "codegen-units=1"]);
+    run(command, &rustc, "src/x86/runtime/main.rs", &out.join("whymain.o"));
+}
+
+/// Runs rustc to compile `source` to `output`, for the target cargo is building for
+fn run(mut command: Command, rustc: &str, source: &str, output: &Path) {
+    if let Ok(target) = env::var("TARGET") {
+        command.arg("--target").arg(target);
+    }
+    let status = command.arg(source).arg("-o").arg(output).status()
+        .unwrap_or_else(|e| panic!("can't run `{}`: {}", rustc, e));
+    if !status.success() {
+        panic!("`{}` failed to compile `{}`", rustc, source);
+    }
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bignum::Integer;
+use parser::BinOp;
+use text::TextSpan;
+
+/// What happens when the result of integer arithmetic doesn't fit in an `Int`
+///
+/// The mode is chosen once per compilation, and every way of running a program applies it through `int_binary`, so a
+/// program behaves the same however it's run.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OverflowMode {
+    /// Stops the program with an error pointing at the operation
+    Trap,
+
+    /// Keeps the low 64 bits of the result, as two's complement
+    Wrap,
+
+    /// Clamps the result to the nearest `Int`
+    Saturate,
+
+    /// Switches to an arbitrary precision integer, so a result always fits
+    Promote,
+}
+
+impl OverflowMode {
+    /// The name the mode is chosen by
+    pub fn name(self) -> &'static str {
+        match self {
+            OverflowMode::Trap => "trap",
+            OverflowMode::Wrap => "wrap",
+            OverflowMode::Saturate => "saturate",
+            OverflowMode::Promote => "promote",
+        }
+    }
+}
+
+impl Default for OverflowMode {
+    fn default() -> OverflowMode { OverflowMode::Trap }
+}
+
+impl FromStr for OverflowMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OverflowMode, String> {
+        match s {
+            "trap" => Ok(OverflowMode::Trap),
+            "wrap" => Ok(OverflowMode::Wrap),
+            "saturate" => Ok(OverflowMode::Saturate),
+            "promote" => Ok(OverflowMode::Promote),
+            _ => Err(format!("unknown overflow mode `{}`, expected `trap`, `wrap`, `saturate` or `promote`", s)),
+        }
+    }
+}
+
+/// The reason integer arithmetic failed
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ArithError {
+    /// The result doesn't fit in an `Int`, and the mode is `Trap`
+    Overflow(BinOp),
+
+    /// The divisor is zero, which fails in every mode since no result would make sense
+    DivideByZero,
+
+    /// The exponent of `**` is negative, so the result isn't an integer
+    NegativeExponent,
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArithError::Overflow(op) => {
+                let verb = match op {
+                    BinOp::Add => "add",
+                    BinOp::Subtract => "subtract",
+                    BinOp::Multiply => "multiply",
+                    BinOp::Power => "raise to a power",
+                    _ => "divide",
+                };
+                write!(f, "attempt to {} with overflow", verb)
+            }
+            ArithError::DivideByZero => write!(f, "attempt to divide by zero"),
+            ArithError::NegativeExponent => write!(f, "attempt to raise to a negative power"),
+        }
+    }
+}
+
+/// A failed operation in a running program, with the span of the operator that failed
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Trap {
+    error: ArithError,
+    span: TextSpan,
+}
+
+impl Trap {
+    pub fn new(error: ArithError, span: TextSpan) -> Trap {
+        Trap { error, span }
+    }
+
+    pub fn error(&self) -> ArithError { self.error }
+    pub fn span(&self) -> TextSpan { self.span }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+/// Applies an arithmetic operator to two integers under the overflow mode
+///
+/// Division rounds towards zero. The only division that overflows is `i64::MIN / -1`, which wraps to `i64::MIN` and
+/// saturates to `i64::MAX`. Only `Promote` produces values outside the range of an `i64`, and the other modes treat
+/// such an operand as an overflow, since it can only come from a literal that was already reported as too large.
+///
+/// ## Panics
+/// If the operator is a comparison.
+pub fn int_binary(mode: OverflowMode, op: BinOp, a: &Integer, b: &Integer) -> Result<Integer, ArithError> {
+    if op == BinOp::Divide && b.is_zero() {
+        return Err(ArithError::DivideByZero);
+    }
+    if op == BinOp::Power && b.is_negative() {
+        return Err(ArithError::NegativeExponent);
+    }
+
+    if mode == OverflowMode::Promote {
+        return match op {
+            BinOp::Add => Ok(a + b),
+            BinOp::Subtract => Ok(a - b),
+            BinOp::Multiply => Ok(a * b),
+            BinOp::Divide => Ok(a / b),
+            BinOp::Power => promoted_pow(a, b).ok_or(ArithError::Overflow(op)),
+            _ => panic!("`{}` is not an arithmetic operator", op.symbol()),
+        };
+    }
+
+    let (a, b) = match (a.to_i64(), b.to_i64()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(ArithError::Overflow(op)),
+    };
+    let result = match mode {
+        OverflowMode::Trap => {
+            let result = match op {
+                BinOp::Add => a.checked_add(b),
+                BinOp::Subtract => a.checked_sub(b),
+                BinOp::Multiply => a.checked_mul(b),
+                BinOp::Divide => a.checked_div(b),
+                BinOp::Power => pow_by_squaring(a, b as u64, i64::checked_mul),
+                _ => panic!("`{}` is not an arithmetic operator", op.symbol()),
+            };
+            result.ok_or(ArithError::Overflow(op))?
+        }
+        OverflowMode::Wrap => match op {
+            BinOp::Add => a.wrapping_add(b),
+            BinOp::Subtract => a.wrapping_sub(b),
+            BinOp::Multiply => a.wrapping_mul(b),
+            BinOp::Divide => a.wrapping_div(b),
+            BinOp::Power => pow_by_squaring(a, b as u64, |x, y| Some(x.wrapping_mul(y))).unwrap(),
+            _ => panic!("`{}` is not an arithmetic operator", op.symbol()),
+        },
+        OverflowMode::Saturate => match op {
+            BinOp::Add => a.saturating_add(b),
+            BinOp::Subtract => a.saturating_sub(b),
+            BinOp::Multiply => a.saturating_mul(b),
+            BinOp::Divide => a.checked_div(b).unwrap_or(i64::max_value()),
+            BinOp::Power => pow_by_squaring(a, b as u64, i64::checked_mul).unwrap_or_else(|| {
+                if a < 0 && b % 2 == 1 { i64::min_value() } else { i64::max_value() }
+            }),
+            _ => panic!("`{}` is not an arithmetic operator", op.symbol()),
+        },
+        OverflowMode::Promote => unreachable!(),
+    };
+    Ok(Integer::Small(result))
+}
+
+/// Raises `base` to a power, multiplying with `mul`, which returns `None` on overflow
+///
+/// Every square of the base is used by the result, so if squaring overflows, so does the result.
+fn pow_by_squaring<F: Fn(i64, i64) -> Option<i64>>(mut base: i64, mut exp: u64, mul: F) -> Option<i64> {
+    let mut result = 1i64;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul(result, base)?;
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = mul(base, base)?;
+        }
+    }
+    Some(result)
+}
+
+/// Raises an arbitrary precision integer to a non-negative power
+///
+/// ## Returns
+/// `None` if the exponent is so large that the result couldn't be stored.
+fn promoted_pow(base: &Integer, exp: &Integer) -> Option<Integer> {
+    match base.to_i64() {
+        // These have the same magnitude whatever the exponent, so the exponent may be arbitrarily large
+        Some(0) | Some(1) | Some(-1) => {
+            let half = exp / &Integer::Small(2);
+            let odd = &(&half + &half) != exp;
+            let exp = if exp.is_zero() { 0 } else if odd { 1 } else { 2 };
+            Some(base.pow(exp))
+        }
+        _ => match exp.to_i64() {
+            Some(e) if e <= u32::max_value() as i64 => Some(base.pow(e as u32)),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arith::{int_binary, ArithError, OverflowMode};
+    use bignum::Integer;
+    use parser::BinOp;
+
+    const MAX: i64 = ::std::i64::MAX;
+    const MIN: i64 = ::std::i64::MIN;
+
+    fn apply(mode: OverflowMode, op: BinOp, a: i64, b: i64) -> Result<i64, ArithError> {
+        int_binary(mode, op, &a.into(), &b.into()).map(|r| r.to_i64().unwrap())
+    }
+
+    #[test]
+    pub fn trap_reports_overflow() {
+        assert_eq!(Ok(7), apply(OverflowMode::Trap, BinOp::Add, 3, 4));
+        assert_eq!(Err(ArithError::Overflow(BinOp::Add)), apply(OverflowMode::Trap, BinOp::Add, MAX, 1));
+        assert_eq!(Err(ArithError::Overflow(BinOp::Divide)), apply(OverflowMode::Trap, BinOp::Divide, MIN, -1));
+        assert_eq!(Ok(1024), apply(OverflowMode::Trap, BinOp::Power, 2, 10));
+        assert_eq!(Err(ArithError::Overflow(BinOp::Power)), apply(OverflowMode::Trap, BinOp::Power, 2, 63));
+        assert_eq!("attempt to multiply with overflow", ArithError::Overflow(BinOp::Multiply).to_string());
+    }
+
+    #[test]
+    pub fn wrap_keeps_the_low_bits() {
+        assert_eq!(Ok(MIN), apply(OverflowMode::Wrap, BinOp::Add, MAX, 1));
+        assert_eq!(Ok(MAX), apply(OverflowMode::Wrap, BinOp::Subtract, MIN, 1));
+        assert_eq!(Ok(MIN), apply(OverflowMode::Wrap, BinOp::Divide, MIN, -1));
+        assert_eq!(Ok(MIN), apply(OverflowMode::Wrap, BinOp::Power, 2, 63));
+        assert_eq!(Ok(0), apply(OverflowMode::Wrap, BinOp::Power, 2, 64));
+    }
+
+    #[test]
+    pub fn saturate_clamps() {
+        assert_eq!(Ok(MAX), apply(OverflowMode::Saturate, BinOp::Multiply, MAX, 2));
+        assert_eq!(Ok(MIN), apply(OverflowMode::Saturate, BinOp::Subtract, MIN, 1));
+        assert_eq!(Ok(MAX), apply(OverflowMode::Saturate, BinOp::Divide, MIN, -1));
+        assert_eq!(Ok(MIN), apply(OverflowMode::Saturate, BinOp::Power, -3, 41));
+        assert_eq!(Ok(MAX), apply(OverflowMode::Saturate, BinOp::Power, -3, 42));
+    }
+
+    #[test]
+    pub fn promote_never_overflows() {
+        let two_to_200: Integer = "1606938044258990275541962092341162602522202993782792835301376".parse().unwrap();
+        assert_eq!(Ok(two_to_200.clone()), int_binary(OverflowMode::Promote, BinOp::Power, &2.into(), &200.into()));
+        assert_eq!(Ok(Integer::Small(2)),
+                   int_binary(OverflowMode::Promote, BinOp::Divide, &two_to_200, &Integer::Small(2).pow(199)));
+        assert_eq!(Ok(Integer::Small(-1)),
+                   int_binary(OverflowMode::Promote, BinOp::Power, &(-1).into(), &"100000000000000000001".parse().unwrap()));
+    }
+
+    #[test]
+    pub fn division_by_zero_and_negative_powers_fail_in_every_mode() {
+        for &mode in &[OverflowMode::Trap, OverflowMode::Wrap, OverflowMode::Saturate, OverflowMode::Promote] {
+            assert_eq!(Err(ArithError::DivideByZero), apply(mode, BinOp::Divide, 1, 0));
+            assert_eq!(Err(ArithError::NegativeExponent), apply(mode, BinOp::Power, 2, -1));
+        }
+        assert_eq!(Ok(-3), apply(OverflowMode::Trap, BinOp::Divide, -7, 2));
+    }
+
+    #[test]
+    pub fn modes_parse_from_their_names() {
+        assert_eq!(Ok(OverflowMode::Saturate), "saturate".parse());
+        assert_eq!(Ok(OverflowMode::Promote), "promote".parse());
+        assert!("clamp".parse::<OverflowMode>().is_err());
+    }
+}
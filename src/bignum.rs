@@ -0,0 +1,475 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::str::FromStr;
+
+/// An integer of any size, stored as a sign and a magnitude of 32-bit digits, least significant first
+///
+/// The magnitude never has leading zero digits, and zero is never negative, so every value has one representation.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> BigInt {
+        BigInt {
+            negative: false,
+            digits: Vec::new(),
+        }
+    }
+
+    pub fn from_i64(v: i64) -> BigInt {
+        BigInt::new(v < 0, from_u64(v.unsigned_abs()))
+    }
+
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        trim(&mut digits);
+        BigInt {
+            negative: negative && !digits.is_empty(),
+            digits,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool { self.digits.is_empty() }
+    pub fn is_negative(&self) -> bool { self.negative }
+
+    /// Gets the value as an `i64`, if it fits in one
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None;
+        }
+        let magnitude = self.digits.iter().rev().fold(0u64, |acc, &d| acc << 32 | d as u64);
+        if self.negative {
+            if magnitude <= 1 << 63 { Some((magnitude as i64).wrapping_neg()) } else { None }
+        } else {
+            if magnitude < 1 << 63 { Some(magnitude as i64) } else { None }
+        }
+    }
+
+    /// Raises the value to a power, by repeated squaring
+    pub fn pow(&self, mut exp: u32) -> BigInt {
+        let mut base = self.clone();
+        let mut result = BigInt::new(false, vec![1]);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = &result * &base;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    /// Divides, rounding towards zero
+    ///
+    /// ## Panics
+    /// If `divisor` is zero.
+    pub fn div_trunc(&self, divisor: &BigInt) -> BigInt {
+        assert!(!divisor.is_zero(), "attempt to divide by zero");
+        let (quotient, _) = divrem(&self.digits, &divisor.digits);
+        BigInt::new(self.negative != divisor.negative, quotient)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_digits(&self.digits, &other.digits),
+            (true, true) => cmp_digits(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl<'a> Neg for &'a BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl<'a> Add for &'a BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_digits(&self.digits, &other.digits));
+        }
+
+        // The signs differ, so the result has the sign of whichever has the larger magnitude
+        match cmp_digits(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::new(other.negative, sub_digits(&other.digits, &self.digits)),
+            _ => BigInt::new(self.negative, sub_digits(&self.digits, &other.digits)),
+        }
+    }
+}
+
+impl<'a> Sub for &'a BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl<'a> Mul for &'a BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(self.negative != other.negative, mul_digits(&self.digits, &other.digits))
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = ();
+
+    /// Parses decimal digits, with an optional leading `-`
+    fn from_str(s: &str) -> Result<BigInt, ()> {
+        let (negative, digits) = match s.starts_with('-') {
+            true => (true, &s[1..]),
+            false => (false, s),
+        };
+        if digits.is_empty() {
+            return Err(());
+        }
+
+        let mut magnitude = Vec::new();
+        for c in digits.chars() {
+            let d = c.to_digit(10).ok_or(())?;
+            mul_add_small(&mut magnitude, 10, d);
+        }
+        Ok(BigInt::new(negative, magnitude))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        // Peel off nine decimal digits at a time, least significant first
+        let mut chunks = Vec::new();
+        let mut rest = self.digits.clone();
+        while !rest.is_empty() {
+            let (quotient, remainder) = divrem_small(&rest, 1_000_000_000);
+            chunks.push(remainder);
+            rest = quotient;
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn from_u64(v: u64) -> Vec<u32> {
+    let mut digits = vec![v as u32, (v >> 32) as u32];
+    trim(&mut digits);
+    digits
+}
+
+fn trim(digits: &mut Vec<u32>) {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+}
+
+fn cmp_digits(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// Subtracts magnitudes, where `a` is at least `b`
+fn sub_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for i in 0..a.len() {
+        let mut diff = a[i] as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += 1 << 32;
+            borrow = 1;
+        }
+        result.push(diff as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let product = x as u64 * y as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    trim(&mut result);
+    result
+}
+
+/// Multiplies the magnitude by a single digit and adds another, in place
+fn mul_add_small(digits: &mut Vec<u32>, m: u32, a: u32) {
+    let mut carry = a as u64;
+    for d in digits.iter_mut() {
+        let value = *d as u64 * m as u64 + carry;
+        *d = value as u32;
+        carry = value >> 32;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+}
+
+fn divrem_small(digits: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; digits.len()];
+    let mut remainder = 0u64;
+    for i in (0..digits.len()).rev() {
+        let value = remainder << 32 | digits[i] as u64;
+        quotient[i] = (value / divisor as u64) as u32;
+        remainder = value % divisor as u64;
+    }
+    trim(&mut quotient);
+    (quotient, remainder as u32)
+}
+
+/// Divides magnitudes by shifting in one bit of the dividend at a time
+fn divrem(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let (quotient, remainder) = divrem_small(a, b[0]);
+        return (quotient, from_u64(remainder as u64));
+    }
+
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for i in (0..a.len() * 32).rev() {
+        mul_add_small(&mut remainder, 2, (a[i / 32] >> (i % 32)) & 1);
+        if cmp_digits(&remainder, b) != Ordering::Less {
+            remainder = sub_digits(&remainder, b);
+            quotient[i / 32] |= 1 << (i % 32);
+        }
+    }
+    trim(&mut quotient);
+    (quotient, remainder)
+}
+
+/// An `Int` value, which is kept in a machine word unless it's too large to fit in one
+///
+/// `Big` only ever holds values outside the range of an `i64`, so two equal values always have the same variant.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Integer {
+    Small(i64),
+    Big(Box<BigInt>),
+}
+
+impl Integer {
+    /// Gets the value as an `i64`, if it fits in one
+    pub fn to_i64(&self) -> Option<i64> {
+        match self {
+            &Integer::Small(v) => Some(v),
+            &Integer::Big(_) => None,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self == &Integer::Small(0)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            &Integer::Small(v) => v < 0,
+            &Integer::Big(ref b) => b.is_negative(),
+        }
+    }
+
+    fn to_big(&self) -> BigInt {
+        match self {
+            &Integer::Small(v) => BigInt::from_i64(v),
+            &Integer::Big(ref b) => (**b).clone(),
+        }
+    }
+
+    /// Raises the value to a power
+    pub fn pow(&self, exp: u32) -> Integer {
+        if let Integer::Small(v) = *self {
+            if let Some(result) = v.checked_pow(exp) {
+                return Integer::Small(result);
+            }
+        }
+        self.to_big().pow(exp).into()
+    }
+
+    /// Divides, rounding towards zero
+    ///
+    /// ## Returns
+    /// `None` if `divisor` is zero.
+    pub fn div_trunc(&self, divisor: &Integer) -> Option<Integer> {
+        if divisor.is_zero() {
+            return None;
+        }
+        if let (&Integer::Small(a), &Integer::Small(b)) = (self, divisor) {
+            if let Some(result) = a.checked_div(b) {
+                return Some(Integer::Small(result));
+            }
+        }
+        Some(self.to_big().div_trunc(&divisor.to_big()).into())
+    }
+}
+
+impl From<i64> for Integer {
+    fn from(v: i64) -> Integer { Integer::Small(v) }
+}
+
+impl From<BigInt> for Integer {
+    fn from(v: BigInt) -> Integer {
+        match v.to_i64() {
+            Some(small) => Integer::Small(small),
+            None => Integer::Big(Box::new(v)),
+        }
+    }
+}
+
+impl Ord for Integer {
+    fn cmp(&self, other: &Integer) -> Ordering {
+        match (self, other) {
+            (&Integer::Small(a), &Integer::Small(b)) => a.cmp(&b),
+            _ => self.to_big().cmp(&other.to_big()),
+        }
+    }
+}
+
+impl PartialOrd for Integer {
+    fn partial_cmp(&self, other: &Integer) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+/// Implements an operator on `&Integer` by trying it on machine words, and falling back to `BigInt` if it overflows
+macro_rules! integer_op {
+    ($op:ident, $method:ident, $checked:ident) => {
+        impl<'a> $op for &'a Integer {
+            type Output = Integer;
+
+            fn $method(self, other: &Integer) -> Integer {
+                if let (&Integer::Small(a), &Integer::Small(b)) = (self, other) {
+                    if let Some(result) = a.$checked(b) {
+                        return Integer::Small(result);
+                    }
+                }
+                $op::$method(&self.to_big(), &other.to_big()).into()
+            }
+        }
+    }
+}
+
+integer_op!(Add, add, checked_add);
+integer_op!(Sub, sub, checked_sub);
+integer_op!(Mul, mul, checked_mul);
+
+impl<'a> Div for &'a Integer {
+    type Output = Integer;
+
+    /// ## Panics
+    /// If `other` is zero.
+    fn div(self, other: &Integer) -> Integer {
+        self.div_trunc(other).expect("attempt to divide by zero")
+    }
+}
+
+impl FromStr for Integer {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Integer, ()> {
+        match s.parse::<i64>() {
+            Ok(v) => Ok(Integer::Small(v)),
+            Err(_) => s.parse::<BigInt>().map(Integer::from),
+        }
+    }
+}
+
+impl fmt::Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Integer::Small(v) => write!(f, "{}", v),
+            &Integer::Big(ref b) => write!(f, "{}", b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bignum::{BigInt, Integer};
+
+    fn big(s: &str) -> Integer {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    pub fn parses_and_prints_any_length() {
+        let digits = "123456789012345678901234567890123456789";
+        assert_eq!(digits, big(digits).to_string());
+        assert_eq!(format!("-{}", digits), big(&format!("-{}", digits)).to_string());
+        assert_eq!("1000000000000000000000", big("1000000000000000000000").to_string());
+        assert_eq!(Integer::Small(42), big("42"));
+        assert!("12a".parse::<Integer>().is_err());
+    }
+
+    #[test]
+    pub fn promotes_on_overflow_and_demotes_when_it_fits() {
+        let max = Integer::Small(::std::i64::MAX);
+        let one = Integer::Small(1);
+        let promoted = &max + &one;
+        assert_eq!(big("9223372036854775808"), promoted);
+        assert!(match promoted { Integer::Big(_) => true, _ => false });
+        assert_eq!(max, &promoted - &one);
+        assert_eq!(Integer::Small(::std::i64::MIN), &Integer::Small(0) - &promoted);
+    }
+
+    #[test]
+    pub fn arithmetic_matches_known_values() {
+        assert_eq!(big("1606938044258990275541962092341162602522202993782792835301376"), Integer::Small(2).pow(200));
+        assert_eq!(big("-121932631137021795226185032733622923332237463801111263526900"),
+                   &big("-123456789012345678901234567890") * &big("987654321098765432109876543210"));
+        assert_eq!(big("123456789012345678901234567890"),
+                   &(&big("123456789012345678901234567890") * &big("98765432109876543210")) / &big("98765432109876543210"));
+        assert_eq!(Some(big("-3")), big("-100000000000000000000").div_trunc(&big("30000000000000000000")));
+        assert_eq!(None, big("1").div_trunc(&Integer::Small(0)));
+    }
+
+    #[test]
+    pub fn compares_by_value() {
+        assert!(big("-100000000000000000000") < Integer::Small(-1));
+        assert!(big("100000000000000000000") > Integer::Small(::std::i64::MAX));
+        assert!(BigInt::from_i64(-5) < BigInt::from_i64(3));
+    }
+}
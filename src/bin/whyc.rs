@@ -0,0 +1,5 @@
+extern crate whylang;
+
+fn main() {
+    ::std::process::exit(whylang::driver::main(::std::env::args().skip(1).collect()));
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use arith::OverflowMode;
+use cfg::{BasicBlock, BlockId, Cfg, Local, LocalId, Node, Terminator};
+use fold::fold_expr;
+use parser::{Block, DefDecl, Expr, ExprKind, Lit, MatchArm, Pattern, PatternKind, Stmt};
+use resolve::{DefId, Resolution};
+use text::TextSpan;
+
+/// Builds the control flow graph of a function body that resolved without errors
+///
+/// Branches that can't be taken because a guard or the scrutinee of a `match` is a constant aren't added, so the code
+/// they lead to is left unreachable.
+pub fn build_cfg(def: &DefDecl, resolution: &Resolution) -> Cfg {
+    let mut builder = Builder {
+        resolution,
+        blocks: Vec::new(),
+        locals: Vec::new(),
+        local_ids: HashMap::new(),
+        current: BlockId(0),
+    };
+    builder.current = builder.new_block();
+    for param in def.params() {
+        builder.bind(param.pattern());
+    }
+    builder.tail_block(def.body());
+
+    let blocks = builder.blocks.into_iter().map(|b| {
+        let mut block = BasicBlock::new(b.nodes, b.terminator);
+        if let Some(span) = b.span {
+            block = block.with_span(span);
+        }
+        if let Some((after, expr)) = b.diverged {
+            block = block.with_diverged(after, expr);
+        }
+        block
+    });
+    Cfg::new(blocks.collect(), builder.locals)
+}
+
+/// A block that's still being built
+struct PendingBlock {
+    span: Option<TextSpan>,
+    nodes: Vec<Node>,
+    terminator: Terminator,
+    diverged: Option<(BlockId, TextSpan)>,
+}
+
+struct Builder<'a> {
+    resolution: &'a Resolution,
+    blocks: Vec<PendingBlock>,
+    locals: Vec<Local>,
+    local_ids: HashMap<DefId, LocalId>,
+    current: BlockId,
+}
+
+impl<'a> Builder<'a> {
+    /// Adds a block, which is unreachable until it's given a predecessor and never finishes until it's terminated
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(PendingBlock { span: None, nodes: Vec::new(), terminator: Terminator::Unreachable, diverged: None });
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current.index()].terminator = terminator;
+    }
+
+    fn push(&mut self, node: Node) {
+        self.blocks[self.current.index()].nodes.push(node);
+    }
+
+    /// Records the span of the code being lowered, if it's the first in the current block
+    fn note_span(&mut self, span: TextSpan) {
+        let block = &mut self.blocks[self.current.index()];
+        if block.span.is_none() {
+            block.span = Some(span);
+        }
+    }
+
+    /// Gets the local a name refers to, if it's a variable or parameter of this function
+    fn local(&self, name: TextSpan) -> Option<LocalId> {
+        self.resolution.use_at(name).and_then(|def| self.local_ids.get(&def).cloned())
+    }
+
+    /// Initializes the locals bound by a pattern
+    fn bind(&mut self, pattern: &Pattern) {
+        match pattern.kind() {
+            &PatternKind::Wildcard | &PatternKind::Literal(_) => {}
+            &PatternKind::Binding(ref name) => {
+                if let Some(def) = self.resolution.decl_at(name.span()) {
+                    let id = LocalId(self.locals.len() as u32);
+                    self.locals.push(Local::new(def, name.name(), name.span()));
+                    self.local_ids.insert(def, id);
+                    self.push(Node::Init(id, name.span()));
+                }
+            }
+            &PatternKind::Tuple(ref elements) | &PatternKind::Constructor(_, ref elements) => {
+                for e in elements {
+                    self.bind(e);
+                }
+            }
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                &Stmt::Decl(ref d) => self.note_span(d.span()),
+                &Stmt::Let(ref l) => {
+                    self.note_span(l.span());
+                    self.expr(l.value());
+                    self.bind(l.pattern());
+                }
+                &Stmt::Expr(ref e) => {
+                    self.note_span(e.span());
+                    self.expr(e);
+                }
+            }
+        }
+    }
+
+    /// Lowers a block whose value is returned from the function
+    fn tail_block(&mut self, block: &Block) {
+        self.stmts(block.stmts());
+        match block.result() {
+            Some(result) => self.tail(result),
+            None => self.terminate(Terminator::ReturnUnit(block.span())),
+        }
+    }
+
+    /// Lowers an expression whose value is returned from the function, ending every path through it with a return
+    fn tail(&mut self, expr: &Expr) {
+        self.note_span(expr.span());
+        match expr.kind() {
+            &ExprKind::Block(ref block) => self.tail_block(block),
+            &ExprKind::Match(ref scrutinee, ref arms) => self.match_(expr.span(), scrutinee, arms, true),
+            _ => {
+                self.expr(expr);
+                self.terminate(Terminator::Return(expr.span()));
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr.kind() {
+            &ExprKind::Constant(_) => {}
+            &ExprKind::Var(ref name) => {
+                if let Some(id) = self.local(name.span()) {
+                    self.push(Node::Use(id, name.span()));
+                }
+            }
+            &ExprKind::Binary(ref l, ref r, _, _) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            &ExprKind::Assign(ref target, ref value) => {
+                self.expr(value);
+                match target.kind() {
+                    &ExprKind::Var(ref name) => {
+                        if let Some(id) = self.local(name.span()) {
+                            self.push(Node::Init(id, name.span()));
+                        }
+                    }
+                    _ => self.expr(target),
+                }
+            }
+            &ExprKind::Struct(_, ref fields) => {
+                for f in fields {
+                    self.expr(f.value());
+                }
+            }
+            &ExprKind::Field(ref target, _) => self.expr(target),
+            &ExprKind::Call(ref callee, ref args) | &ExprKind::MethodCall(ref callee, _, ref args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            &ExprKind::Match(ref scrutinee, ref arms) => self.match_(expr.span(), scrutinee, arms, false),
+            &ExprKind::Tuple(ref elements) => {
+                for e in elements {
+                    self.expr(e);
+                }
+            }
+            &ExprKind::Block(ref block) => {
+                self.stmts(block.stmts());
+                if let Some(result) = block.result() {
+                    self.note_span(result.span());
+                    self.expr(result);
+                }
+            }
+            &ExprKind::Return(ref value) => {
+                let returned = self.current;
+                if let Some(ref value) = *value {
+                    self.expr(value);
+                }
+                self.terminate(Terminator::Return(expr.span()));
+                // Whatever follows is lowered into a block that nothing leads to
+                self.current = self.new_block();
+                self.blocks[self.current.index()].diverged = Some((returned, expr.span()));
+            }
+        }
+    }
+
+    /// Lowers a `match`, which tries each arm in turn until one's pattern matches and its guard is `true`
+    ///
+    /// Each arm gets a block that binds its pattern and evaluates its guard, which continues at the arm's body or, if
+    /// the guard is `false`, at the next arm that might match.
+    fn match_(&mut self, span: TextSpan, scrutinee: &Expr, arms: &[MatchArm], tail: bool) {
+        self.expr(scrutinee);
+        let start = self.current;
+        let value = constant(scrutinee);
+        let entries: Vec<BlockId> = arms.iter().map(|_| self.new_block()).collect();
+        let candidates = |from: usize, this: &Builder| {
+            let mut targets = Vec::new();
+            for (i, arm) in arms.iter().enumerate().skip(from) {
+                if !this.may_match(arm.pattern(), value.as_ref()) {
+                    continue;
+                }
+                targets.push(entries[i]);
+                if this.always_matches(arm.pattern(), value.as_ref()) && arm.guard().map_or(true, is_true) {
+                    break;
+                }
+            }
+            targets
+        };
+
+        let first = candidates(0, self);
+        let diverges = first.is_empty();
+        self.terminate(if diverges { Terminator::Unreachable } else { Terminator::Branch(scrutinee.span(), first) });
+
+        let join = if tail { None } else { Some(self.new_block()) };
+        for (i, arm) in arms.iter().enumerate() {
+            self.current = entries[i];
+            self.note_span(arm.span());
+            self.bind(arm.pattern());
+            let body = self.new_block();
+            match arm.guard() {
+                Some(guard) => {
+                    self.expr(guard);
+                    let mut targets = candidates(i + 1, self);
+                    match constant(guard) {
+                        Some(Lit::Bool(true)) => targets = vec![body],
+                        Some(Lit::Bool(false)) => {}
+                        _ => targets.insert(0, body),
+                    }
+                    self.terminate(if targets.is_empty() {
+                        Terminator::Unreachable
+                    } else {
+                        Terminator::Branch(guard.span(), targets)
+                    });
+                }
+                None => self.terminate(Terminator::Goto(body)),
+            }
+
+            self.current = body;
+            match join {
+                None => self.tail(arm.body()),
+                Some(join) => {
+                    self.note_span(arm.body().span());
+                    self.expr(arm.body());
+                    self.terminate(Terminator::Goto(join));
+                }
+            }
+        }
+
+        if let Some(join) = join {
+            self.current = join;
+            if diverges {
+                self.blocks[join.index()].diverged = Some((start, span));
+            }
+        }
+    }
+
+    /// Returns `false` if the pattern can't match the value of the scrutinee, when that's known
+    fn may_match(&self, pattern: &Pattern, value: Option<&Lit>) -> bool {
+        match (pattern.kind(), value) {
+            (&PatternKind::Literal(ref lit), Some(value)) => lit == value,
+            _ => true,
+        }
+    }
+
+    /// Returns `true` if the pattern matches every value the scrutinee could have
+    fn always_matches(&self, pattern: &Pattern, value: Option<&Lit>) -> bool {
+        match pattern.kind() {
+            &PatternKind::Wildcard => true,
+            // A name that refers to a variant doesn't declare anything
+            &PatternKind::Binding(ref name) => self.resolution.decl_at(name.span()).is_some(),
+            &PatternKind::Literal(ref lit) => value == Some(lit),
+            &PatternKind::Tuple(ref elements) => elements.iter().all(|e| self.always_matches(e, None)),
+            &PatternKind::Constructor(..) => false,
+        }
+    }
+}
+
+/// Gets the value of an expression if it's known when compiling
+fn constant(expr: &Expr) -> Option<Lit> {
+    match fold_expr(expr, OverflowMode::Trap).kind() {
+        &ExprKind::Constant(ref lit) => Some(lit.clone()),
+        _ => None,
+    }
+}
+
+fn is_true(guard: &Expr) -> bool {
+    constant(guard) == Some(Lit::Bool(true))
+}
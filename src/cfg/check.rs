@@ -0,0 +1,73 @@
+use cfg::{reachable, solve, Analysis, BitSet, Cfg, Direction, LocalId, Meet, Node, Terminator};
+use text::TextSpan;
+
+/// Finds the locals that may be read before they're given a value, with the span of each such read
+pub fn uninitialized_uses(cfg: &Cfg) -> Vec<(LocalId, TextSpan)> {
+    let solution = solve(cfg, &Initialized);
+    let mut uses = Vec::new();
+    for (block, facts) in cfg.blocks().iter().zip(solution.before) {
+        let mut facts = facts;
+        for node in block.nodes() {
+            match *node {
+                Node::Init(local, _) => {
+                    facts.insert(local.index());
+                }
+                Node::Use(local, span) => {
+                    if !facts.contains(local.index()) {
+                        uses.push((local, span));
+                    }
+                }
+            }
+        }
+    }
+    uses
+}
+
+/// The locals that have been given a value along every path
+struct Initialized;
+
+impl Analysis<Cfg> for Initialized {
+    fn direction(&self) -> Direction { Direction::Forward }
+    fn meet(&self) -> Meet { Meet::Intersection }
+    fn domain_size(&self, cfg: &Cfg) -> usize { cfg.locals().len() }
+    fn boundary(&self, cfg: &Cfg) -> BitSet { BitSet::empty(cfg.locals().len()) }
+
+    fn transfer(&self, cfg: &Cfg, block: usize, facts: &mut BitSet) {
+        for node in cfg.blocks()[block].nodes() {
+            if let Node::Init(local, _) = *node {
+                facts.insert(local.index());
+            }
+        }
+    }
+}
+
+/// Finds the blocks without a final expression that the function can return from
+pub fn missing_values(cfg: &Cfg) -> Vec<TextSpan> {
+    let reached = reachable(cfg);
+    cfg.blocks().iter().enumerate()
+        .filter(|&(i, _)| reached[i])
+        .filter_map(|(_, block)| match block.terminator() {
+            &Terminator::ReturnUnit(span) => Some(span),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds code that can't run because the expression before it never produces a value
+///
+/// Each result is the span of the first unreachable statement or expression, then the span of the expression it
+/// follows. Code that's unreachable for other reasons, like a guard that's always `false`, isn't included, since the
+/// condition is reported itself.
+pub fn unreachable_code(cfg: &Cfg) -> Vec<(TextSpan, TextSpan)> {
+    let reached = reachable(cfg);
+    let mut found = Vec::new();
+    for (i, block) in cfg.blocks().iter().enumerate() {
+        // Code after an expression that can't run itself is already covered by whatever makes that unreachable
+        if let (false, Some(span), Some((after, expr))) = (reached[i], block.span(), block.diverged()) {
+            if reached[after.index()] {
+                found.push((span, expr));
+            }
+        }
+    }
+    found
+}
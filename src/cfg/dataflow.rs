@@ -0,0 +1,261 @@
+use cfg::{reverse_postorder, Graph};
+
+/// A fixed-size set of small integers, like the indices of locals
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct BitSet {
+    len: usize,
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn empty(len: usize) -> BitSet {
+        BitSet { len, words: vec![0; (len + 63) / 64] }
+    }
+
+    pub fn full(len: usize) -> BitSet {
+        let mut set = BitSet::empty(len);
+        for i in 0..len {
+            set.insert(i);
+        }
+        set
+    }
+
+    /// The number of elements the set has room for
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// Adds an element, returning `true` if it wasn't already there
+    pub fn insert(&mut self, i: usize) -> bool {
+        assert!(i < self.len, "{} is out of range for a set of {}", i, self.len);
+        let had = self.contains(i);
+        self.words[i / 64] |= 1 << (i % 64);
+        !had
+    }
+
+    /// Removes an element, returning `true` if it was there
+    pub fn remove(&mut self, i: usize) -> bool {
+        let had = self.contains(i);
+        self.words[i / 64] &= !(1 << (i % 64));
+        had
+    }
+
+    /// Adds every element of `other`, returning `true` if that changed the set
+    pub fn union(&mut self, other: &BitSet) -> bool {
+        let mut changed = false;
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            changed |= *w | o != *w;
+            *w |= o;
+        }
+        changed
+    }
+
+    /// Removes every element not in `other`, returning `true` if that changed the set
+    pub fn intersect(&mut self, other: &BitSet) -> bool {
+        let mut changed = false;
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            changed |= *w & o != *w;
+            *w &= o;
+        }
+        changed
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        (0..self.len).filter(move |&i| self.contains(i))
+    }
+}
+
+/// Which way facts flow through the graph
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    /// From a block to its successors, like whether a variable has been given a value
+    Forward,
+
+    /// From a block to its predecessors, like whether a variable will be read later
+    Backward,
+}
+
+/// How the facts from several neighbours are combined
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Meet {
+    /// A fact holds if it holds along any path
+    Union,
+
+    /// A fact holds only if it holds along every path
+    Intersection,
+}
+
+/// A dataflow problem over sets of facts, numbered from zero
+pub trait Analysis<G: Graph + ?Sized> {
+    fn direction(&self) -> Direction;
+    fn meet(&self) -> Meet;
+
+    /// The number of facts
+    fn domain_size(&self, graph: &G) -> usize;
+
+    /// The facts where control enters the function, or leaves it for a backward analysis
+    fn boundary(&self, graph: &G) -> BitSet;
+
+    /// Applies the effect of a block to the facts, in the direction of the analysis
+    fn transfer(&self, graph: &G, block: usize, facts: &mut BitSet);
+}
+
+/// The facts that hold at the start and end of every block, in program order whatever the direction of the analysis
+///
+/// Blocks that can't be reached from the entry hold every fact for an `Intersection` analysis and none for a `Union`
+/// one, so they don't affect the blocks around them.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Solution {
+    pub before: Vec<BitSet>,
+    pub after: Vec<BitSet>,
+}
+
+/// Runs an analysis to a fixed point
+pub fn solve<G: Graph + ?Sized, A: Analysis<G>>(graph: &G, analysis: &A) -> Solution {
+    let size = analysis.domain_size(graph);
+    let initial = match analysis.meet() {
+        Meet::Union => BitSet::empty(size),
+        Meet::Intersection => BitSet::full(size),
+    };
+    let mut before = vec![initial.clone(); graph.num_blocks()];
+    let mut after = before.clone();
+
+    let forward = analysis.direction() == Direction::Forward;
+    let preds = graph.predecessors();
+    let mut order = reverse_postorder(graph);
+    if !forward {
+        order.reverse();
+    }
+    // The blocks facts flow in from, and out to
+    let sources = |block: usize| if forward { preds[block].clone() } else { graph.successors(block) };
+    let is_boundary = |block: usize| if forward { block == graph.entry() } else { graph.successors(block).is_empty() };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order {
+            let mut facts = initial.clone();
+            if is_boundary(block) {
+                facts = analysis.boundary(graph);
+            }
+            for source in sources(block) {
+                let theirs = if forward { &after[source] } else { &before[source] };
+                match analysis.meet() {
+                    Meet::Union => facts.union(theirs),
+                    Meet::Intersection => facts.intersect(theirs),
+                };
+            }
+
+            let (input, output) = if forward { (&mut before, &mut after) } else { (&mut after, &mut before) };
+            input[block] = facts.clone();
+            analysis.transfer(graph, block, &mut facts);
+            if output[block] != facts {
+                output[block] = facts;
+                changed = true;
+            }
+        }
+    }
+    Solution { before, after }
+}
+
+#[cfg(test)]
+mod tests {
+    use cfg::{dominates, dominators, solve, Analysis, BitSet, Direction, Graph, Meet};
+
+    /// A graph with the edges 0 -> 1, 0 -> 2, 1 -> 3, 2 -> 3, 3 -> 1, where each block defines and uses some of the
+    /// variables 0 to 2
+    struct Diamond {
+        defs: Vec<Vec<usize>>,
+        uses: Vec<Vec<usize>>,
+    }
+
+    impl Graph for Diamond {
+        fn num_blocks(&self) -> usize { 4 }
+        fn entry(&self) -> usize { 0 }
+
+        fn successors(&self, block: usize) -> Vec<usize> {
+            match block {
+                0 => vec![1, 2],
+                1 | 2 => vec![3],
+                _ => vec![1],
+            }
+        }
+    }
+
+    struct Liveness;
+
+    impl Analysis<Diamond> for Liveness {
+        fn direction(&self) -> Direction { Direction::Backward }
+        fn meet(&self) -> Meet { Meet::Union }
+        fn domain_size(&self, _: &Diamond) -> usize { 3 }
+        fn boundary(&self, _: &Diamond) -> BitSet { BitSet::empty(3) }
+
+        fn transfer(&self, graph: &Diamond, block: usize, facts: &mut BitSet) {
+            for &d in &graph.defs[block] {
+                facts.remove(d);
+            }
+            for &u in &graph.uses[block] {
+                facts.insert(u);
+            }
+        }
+    }
+
+    struct Assigned;
+
+    impl Analysis<Diamond> for Assigned {
+        fn direction(&self) -> Direction { Direction::Forward }
+        fn meet(&self) -> Meet { Meet::Intersection }
+        fn domain_size(&self, _: &Diamond) -> usize { 3 }
+        fn boundary(&self, _: &Diamond) -> BitSet { BitSet::empty(3) }
+
+        fn transfer(&self, graph: &Diamond, block: usize, facts: &mut BitSet) {
+            for &d in &graph.defs[block] {
+                facts.insert(d);
+            }
+        }
+    }
+
+    fn set(elements: &[usize]) -> BitSet {
+        let mut set = BitSet::empty(3);
+        for &e in elements {
+            set.insert(e);
+        }
+        set
+    }
+
+    #[test]
+    pub fn liveness_flows_around_loops() {
+        let graph = Diamond {
+            defs: vec![vec![0], vec![1], vec![1], vec![2]],
+            uses: vec![vec![], vec![0], vec![], vec![1, 2]],
+        };
+        let solution = solve(&graph, &Liveness);
+        // Block 1 reads 0 every time around the loop, so it stays live through block 3
+        assert_eq!(vec![set(&[2]), set(&[0, 2]), set(&[0, 2]), set(&[0, 1, 2])], solution.before);
+        assert_eq!(vec![set(&[0, 2]), set(&[0, 1, 2]), set(&[0, 1, 2]), set(&[0, 2])], solution.after);
+    }
+
+    #[test]
+    pub fn definite_assignment_needs_every_path() {
+        let graph = Diamond {
+            defs: vec![vec![], vec![0, 1], vec![1], vec![2]],
+            uses: vec![vec![]; 4],
+        };
+        let solution = solve(&graph, &Assigned);
+        assert_eq!(vec![set(&[]), set(&[]), set(&[]), set(&[1])], solution.before);
+        assert_eq!(set(&[1, 2]), solution.after[3]);
+    }
+
+    #[test]
+    pub fn loop_headers_are_dominated_by_the_entry_only() {
+        let graph = Diamond { defs: vec![vec![]; 4], uses: vec![vec![]; 4] };
+        let idom = dominators(&graph);
+        // Block 1 can be reached from both 0 and 3, so only the entry dominates it
+        assert_eq!(vec![Some(0), Some(0), Some(0), Some(0)], idom);
+        assert!(dominates(&idom, 0, 3));
+        assert!(!dominates(&idom, 1, 3));
+        assert!(dominates(&idom, 2, 2));
+    }
+}
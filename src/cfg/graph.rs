@@ -0,0 +1,257 @@
+use resolve::DefId;
+use text::TextSpan;
+
+/// A directed graph of basic blocks, numbered from zero, which the dataflow solver can run over
+///
+/// Both the control flow graphs built from `def` bodies and the functions of the backend's IR implement this, so
+/// analyses like liveness are written once.
+pub trait Graph {
+    fn num_blocks(&self) -> usize;
+    fn entry(&self) -> usize;
+    fn successors(&self, block: usize) -> Vec<usize>;
+
+    /// Gets the predecessors of every block, indexed by block
+    fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut preds = vec![Vec::new(); self.num_blocks()];
+        for block in 0..self.num_blocks() {
+            for succ in self.successors(block) {
+                if !preds[succ].contains(&block) {
+                    preds[succ].push(block);
+                }
+            }
+        }
+        preds
+    }
+}
+
+/// Gets the blocks reachable from the entry, in reverse postorder
+///
+/// Every block comes before its successors, except along the back edges of loops, which makes this the order forward
+/// analyses converge fastest in.
+pub fn reverse_postorder<G: Graph + ?Sized>(graph: &G) -> Vec<usize> {
+    let mut visited = vec![false; graph.num_blocks()];
+    let mut order = Vec::new();
+    // Each entry is a block and the successors of it that are left to visit
+    let mut stack = vec![(graph.entry(), graph.successors(graph.entry()).into_iter().rev().collect::<Vec<_>>())];
+    visited[graph.entry()] = true;
+    while let Some(&mut (block, ref mut succs)) = stack.last_mut() {
+        match succs.pop() {
+            Some(succ) => {
+                if !visited[succ] {
+                    visited[succ] = true;
+                    let next = graph.successors(succ);
+                    // Visiting successors in order keeps the result close to the order blocks were written in
+                    stack.push((succ, next.into_iter().rev().collect()));
+                }
+            }
+            None => {
+                order.push(block);
+                stack.pop();
+            }
+        }
+    }
+    order.reverse();
+    order
+}
+
+/// Finds which blocks can be reached from the entry, indexed by block
+pub fn reachable<G: Graph + ?Sized>(graph: &G) -> Vec<bool> {
+    let mut reached = vec![false; graph.num_blocks()];
+    for block in reverse_postorder(graph) {
+        reached[block] = true;
+    }
+    reached
+}
+
+/// Finds the immediate dominator of every block, indexed by block
+///
+/// The entry is its own immediate dominator, and blocks that can't be reached have none. This is the iterative
+/// algorithm of Cooper, Harvey and Kennedy, which walks up the tree built so far to find common dominators.
+pub fn dominators<G: Graph + ?Sized>(graph: &G) -> Vec<Option<usize>> {
+    let order = reverse_postorder(graph);
+    let mut position = vec![usize::max_value(); graph.num_blocks()];
+    for (i, &block) in order.iter().enumerate() {
+        position[block] = i;
+    }
+    let preds = graph.predecessors();
+    let mut idom = vec![None; graph.num_blocks()];
+    idom[graph.entry()] = Some(graph.entry());
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in order.iter().skip(1) {
+            let mut new_idom: Option<usize> = None;
+            for &pred in &preds[block] {
+                if idom[pred].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => pred,
+                    Some(mut other) => {
+                        let mut pred = pred;
+                        while pred != other {
+                            while position[pred] > position[other] {
+                                pred = idom[pred].unwrap();
+                            }
+                            while position[other] > position[pred] {
+                                other = idom[other].unwrap();
+                            }
+                        }
+                        pred
+                    }
+                });
+            }
+            if new_idom != idom[block] {
+                idom[block] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+/// Returns `true` if every path from the entry to `b` passes through `a`, given the result of `dominators`
+pub fn dominates(idom: &[Option<usize>], a: usize, mut b: usize) -> bool {
+    loop {
+        if a == b {
+            return true;
+        }
+        match idom[b] {
+            Some(up) if up != b => b = up,
+            _ => return false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+impl BlockId {
+    pub fn index(self) -> usize { self.0 as usize }
+}
+
+/// A variable or parameter of the function, numbered in the order they're bound
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct LocalId(pub u32);
+
+impl LocalId {
+    pub fn index(self) -> usize { self.0 as usize }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Local {
+    def: DefId,
+    name: String,
+    span: TextSpan,
+}
+
+impl Local {
+    pub fn new<S: Into<String>>(def: DefId, name: S, span: TextSpan) -> Local {
+        Local { def, name: name.into(), span }
+    }
+
+    pub fn def(&self) -> DefId { self.def }
+    pub fn name(&self) -> &str { &self.name }
+
+    /// The span of the name where it's bound
+    pub fn span(&self) -> TextSpan { self.span }
+}
+
+/// Something that happens to a local within a block, in the order it happens
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Node {
+    /// The local is given a value, by a parameter, pattern or assignment
+    Init(LocalId, TextSpan),
+
+    /// The value of the local is read, including to assign to one of its fields
+    Use(LocalId, TextSpan),
+}
+
+/// How control leaves a block
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Terminator {
+    Goto(BlockId),
+
+    /// Continues at one of the targets, chosen by the value with the span: the scrutinee of a `match`, or a guard
+    Branch(TextSpan, Vec<BlockId>),
+
+    /// Returns the value of the expression with the span
+    Return(TextSpan),
+
+    /// Returns from a block with no final expression, so the function returns `()`
+    ReturnUnit(TextSpan),
+
+    /// Control never reaches the end of the block, like after a `match` with no arms
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            &Terminator::Goto(target) => vec![target],
+            &Terminator::Branch(_, ref targets) => targets.clone(),
+            &Terminator::Return(_) | &Terminator::ReturnUnit(_) | &Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BasicBlock {
+    span: Option<TextSpan>,
+    nodes: Vec<Node>,
+    terminator: Terminator,
+    diverged: Option<(BlockId, TextSpan)>,
+}
+
+impl BasicBlock {
+    pub fn new(nodes: Vec<Node>, terminator: Terminator) -> BasicBlock {
+        BasicBlock { span: None, nodes, terminator, diverged: None }
+    }
+
+    /// Sets the span of the first statement or expression in the block
+    pub fn with_span(mut self, span: TextSpan) -> BasicBlock {
+        self.span = Some(span);
+        self
+    }
+
+    /// Marks the block as the code following an expression that never produces a value, which ends the block `after`
+    pub fn with_diverged(mut self, after: BlockId, expr: TextSpan) -> BasicBlock {
+        self.diverged = Some((after, expr));
+        self
+    }
+
+    pub fn span(&self) -> Option<TextSpan> { self.span }
+    pub fn nodes(&self) -> &[Node] { &self.nodes }
+    pub fn terminator(&self) -> &Terminator { &self.terminator }
+
+    /// The block and span of the expression this block follows, if control can't continue past that expression
+    pub fn diverged(&self) -> Option<(BlockId, TextSpan)> { self.diverged }
+}
+
+/// The control flow graph of a function body, which starts at its first block
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cfg {
+    blocks: Vec<BasicBlock>,
+    locals: Vec<Local>,
+}
+
+impl Cfg {
+    pub fn new(blocks: Vec<BasicBlock>, locals: Vec<Local>) -> Cfg {
+        Cfg { blocks, locals }
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] { &self.blocks }
+    pub fn block(&self, id: BlockId) -> &BasicBlock { &self.blocks[id.index()] }
+    pub fn locals(&self) -> &[Local] { &self.locals }
+    pub fn local(&self, id: LocalId) -> &Local { &self.locals[id.index()] }
+}
+
+impl Graph for Cfg {
+    fn num_blocks(&self) -> usize { self.blocks.len() }
+    fn entry(&self) -> usize { 0 }
+
+    fn successors(&self, block: usize) -> Vec<usize> {
+        self.blocks[block].terminator.successors().into_iter().map(BlockId::index).collect()
+    }
+}
@@ -0,0 +1,141 @@
+mod build;
+mod check;
+mod dataflow;
+mod graph;
+
+pub use self::build::build_cfg;
+pub use self::check::{missing_values, uninitialized_uses, unreachable_code};
+pub use self::dataflow::{solve, Analysis, BitSet, Direction, Meet, Solution};
+pub use self::graph::{dominates, dominators, reachable, reverse_postorder, BasicBlock, BlockId, Cfg, Graph, Local,
+                      LocalId, Node, Terminator};
+
+use diagnostics::Diagnostic;
+use parser::{DefDecl, Program, TypeExprKind};
+use parser::visit::{self, Visitor};
+use resolve::Resolution;
+
+/// Builds the control flow graph of every function in a program that resolved without errors, reporting variables
+/// that might be used before they have a value and functions that don't return a value on every path
+pub fn check_program(program: &Program, resolution: &Resolution) -> Vec<Diagnostic> {
+    let mut checker = FlowChecker { resolution, diags: Vec::new() };
+    visit::walk_program(&mut checker, program);
+    checker.diags
+}
+
+struct FlowChecker<'a> {
+    resolution: &'a Resolution,
+    diags: Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> Visitor<'ast> for FlowChecker<'a> {
+    fn visit_def(&mut self, def: &'ast DefDecl) {
+        let cfg = build_cfg(def, self.resolution);
+
+        for (local, span) in uninitialized_uses(&cfg) {
+            let local = cfg.local(local);
+            self.diags.push(Diagnostic::error(span, format!("`{}` might be used before it has a value", local.name()))
+                .with_label(local.span(), format!("`{}` is declared here", local.name())));
+        }
+
+        // Returning `()` from a block without a value is fine if that's what the function is declared to return
+        let ret = def.ret().filter(|ty| match ty.kind() {
+            &TypeExprKind::Tuple(ref elements) => !elements.is_empty(),
+            _ => true,
+        });
+        let missing = missing_values(&cfg);
+        if let (Some(ret), false) = (ret, missing.is_empty()) {
+            let mut diag = Diagnostic::error(def.name().span(),
+                                             format!("`{}` doesn't return a value on every path", def.name().name()));
+            for span in missing {
+                diag = diag.with_label(span, "this block has no final expression");
+            }
+            self.diags.push(diag.with_label(ret.span(), "a value is expected because of this return type"));
+        }
+
+        visit::walk_def(self, def);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use text::TextSpan;
+    use parser::{DefDecl, Stmt, Decl};
+    use diagnostics::Diagnostic;
+    use cfg::{build_cfg, check_program, reachable, uninitialized_uses, BasicBlock, BlockId, Cfg, Local, LocalId, Node,
+              Terminator};
+    use resolve::DefId;
+    use testing::{parse_and_resolve, span_at, span_of};
+
+    fn errors(code: &str) -> Vec<Diagnostic> {
+        let (program, resolution) = parse_and_resolve(code);
+        check_program(&program, &resolution)
+    }
+
+    /// Builds the graph of the first `def` in the program
+    fn first_cfg(code: &str) -> Cfg {
+        let (program, resolution) = parse_and_resolve(code);
+        let def: &DefDecl = program.stmts().iter().filter_map(|s| match s {
+            &Stmt::Decl(Decl::Def(ref d)) => Some(d),
+            _ => None,
+        }).next().unwrap();
+        build_cfg(def, &resolution)
+    }
+
+    #[test]
+    pub fn match_arms_branch_and_join() {
+        let code = "def f(x) { let y = match x { 0 => 1, n if n > 5 => n, _ => 2 }; y }";
+        let cfg = first_cfg(code);
+        let terminators: Vec<Terminator> = cfg.blocks().iter().map(|b| b.terminator().clone()).collect();
+        assert_eq!(vec![
+            Terminator::Branch(span_at(code, 25, "x"), vec![BlockId(1), BlockId(2), BlockId(3)]),
+            Terminator::Goto(BlockId(5)),
+            Terminator::Branch(span_of(code, "n > 5"), vec![BlockId(6), BlockId(3)]),
+            Terminator::Goto(BlockId(7)),
+            Terminator::Return(span_at(code, 64, "y")),
+            Terminator::Goto(BlockId(4)),
+            Terminator::Goto(BlockId(4)),
+            Terminator::Goto(BlockId(4)),
+        ], terminators);
+        assert_eq!(vec!["x", "n", "y"], cfg.locals().iter().map(|l| l.name()).collect::<Vec<_>>());
+        assert_eq!(&[Node::Init(LocalId(1), span_at(code, 37, "n")), Node::Use(LocalId(1), span_at(code, 42, "n"))],
+                   cfg.blocks()[2].nodes());
+    }
+
+    #[test]
+    pub fn constant_conditions_prune_branches() {
+        let code = "def f(x) { match 3 { 1 => x, n if false => n, 3 => 0, _ => 1 } }";
+        let cfg = first_cfg(code);
+        assert_eq!(&Terminator::Branch(span_at(code, 17, "3"), vec![BlockId(2), BlockId(3)]), cfg.blocks()[0].terminator());
+        assert_eq!(vec![true, false, true, true, false, false, false, true, false], reachable(&cfg));
+    }
+
+    #[test]
+    pub fn missing_values_are_reported() {
+        let code = "def f(x) -> Int { match x { 0 => { 1 }, _ => { x; } } } def g() -> () { 1; } def h() { } 0";
+        assert_eq!(vec![
+            Diagnostic::error(span_at(code, 4, "f"), "`f` doesn't return a value on every path")
+                .with_label(span_of(code, "{ x; }"), "this block has no final expression")
+                .with_label(span_of(code, "Int"), "a value is expected because of this return type"),
+        ], errors(code));
+
+        let code = "def f(x) -> Int { match x { n if false => { }, n => n } } f(1)";
+        assert_eq!(Vec::<Diagnostic>::new(), errors(code));
+    }
+
+    #[test]
+    pub fn uses_before_every_path_initializes_are_found() {
+        let span = |start| TextSpan::new(start, start + 1);
+        let cfg = Cfg::new(vec![
+            BasicBlock::new(vec![], Terminator::Branch(span(0), vec![BlockId(1), BlockId(2)])),
+            BasicBlock::new(vec![Node::Init(LocalId(0), span(1))], Terminator::Goto(BlockId(3))),
+            BasicBlock::new(vec![], Terminator::Goto(BlockId(3))),
+            BasicBlock::new(vec![Node::Use(LocalId(0), span(2))], Terminator::Return(span(2))),
+            BasicBlock::new(vec![Node::Use(LocalId(0), span(3))], Terminator::Return(span(3))),
+        ], vec![Local::new(DefId(0), "x", span(1))]);
+        // The last block can't be reached, so its use doesn't count
+        assert_eq!(vec![(LocalId(0), span(2))], uninitialized_uses(&cfg));
+
+        let code = "def f(x) { let y = x; y = match y { 0 => y, z => z }; y } f(1)";
+        assert_eq!(Vec::<Diagnostic>::new(), errors(code));
+    }
+}
@@ -0,0 +1,588 @@
+//! Compiles the IR to C, as a portable alternative to native code
+//!
+//! Each function becomes a C function whose blocks are labels, with a variable for each value. Every value but a
+//! `float` is an `int64_t`, so objects, strings and functions are cast to pointers where they're used. A phi gets a
+//! second variable, which each predecessor sets before it jumps, so phis that read each other all see the values from
+//! before the jump. Integer arithmetic goes through small functions that handle overflow as the module says, using
+//! the compiler's checked arithmetic builtins, and the rest of the runtime is in `RUNTIME`, which is compiled
+//! alongside the program.
+//!
+//! Instructions from the source are preceded by `#line` directives, so a C debugger or compiler error points at the
+//! line of the Why program they came from.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use arith::OverflowMode;
+use cfg::BlockId;
+use ir::{Callee, Const, Function, Inst, InstKind, Module, Terminator, Ty, Value};
+use parser::BinOp;
+use text::{Document, TextSpan};
+use x86::{source_location, CodegenError};
+
+/// The runtime functions compiled programs call, as C source
+pub const RUNTIME: &'static str = include_str!("runtime.c");
+
+const PRELUDE: &'static str = "\
+#include <stdint.h>
+
+__attribute__((noreturn)) void why_trap(int64_t kind, const char *location);
+int64_t why_new(int64_t count, uint64_t pointers);
+int64_t why_str_concat(int64_t a, int64_t b);
+int64_t why_str_cmp(int64_t a, int64_t b);
+int64_t why_str_from_c(const char *s);
+int64_t why_int_pow(int64_t a, int64_t b, int64_t mode, const char *location);
+double why_float_pow(double a, double b);
+
+static inline double why_to_float(int64_t bits) {
+    union { int64_t bits; double value; } u = { .bits = bits };
+    return u.value;
+}
+
+static inline int64_t why_from_float(double value) {
+    union { double value; int64_t bits; } u = { .value = value };
+    return u.bits;
+}
+";
+
+const TRAP: &'static str = "
+static inline int64_t why_add(int64_t a, int64_t b, const char *at) {
+    int64_t result;
+    if (__builtin_add_overflow(a, b, &result)) why_trap(0, at);
+    return result;
+}
+
+static inline int64_t why_sub(int64_t a, int64_t b, const char *at) {
+    int64_t result;
+    if (__builtin_sub_overflow(a, b, &result)) why_trap(0, at);
+    return result;
+}
+
+static inline int64_t why_mul(int64_t a, int64_t b, const char *at) {
+    int64_t result;
+    if (__builtin_mul_overflow(a, b, &result)) why_trap(0, at);
+    return result;
+}
+
+static inline int64_t why_div(int64_t a, int64_t b, const char *at) {
+    if (b == 0) why_trap(1, at);
+    if (a == INT64_MIN && b == -1) why_trap(0, at);
+    return a / b;
+}
+";
+
+const WRAP: &'static str = "
+static inline int64_t why_add(int64_t a, int64_t b, const char *at) {
+    (void)at;
+    return (int64_t)((uint64_t)a + (uint64_t)b);
+}
+
+static inline int64_t why_sub(int64_t a, int64_t b, const char *at) {
+    (void)at;
+    return (int64_t)((uint64_t)a - (uint64_t)b);
+}
+
+static inline int64_t why_mul(int64_t a, int64_t b, const char *at) {
+    (void)at;
+    return (int64_t)((uint64_t)a * (uint64_t)b);
+}
+
+static inline int64_t why_div(int64_t a, int64_t b, const char *at) {
+    if (b == 0) why_trap(1, at);
+    if (a == INT64_MIN && b == -1) return INT64_MIN;
+    return a / b;
+}
+";
+
+const SATURATE: &'static str = "
+static inline int64_t why_add(int64_t a, int64_t b, const char *at) {
+    int64_t result;
+    (void)at;
+    if (__builtin_add_overflow(a, b, &result)) return b < 0 ? INT64_MIN : INT64_MAX;
+    return result;
+}
+
+static inline int64_t why_sub(int64_t a, int64_t b, const char *at) {
+    int64_t result;
+    (void)at;
+    if (__builtin_sub_overflow(a, b, &result)) return b < 0 ? INT64_MAX : INT64_MIN;
+    return result;
+}
+
+static inline int64_t why_mul(int64_t a, int64_t b, const char *at) {
+    int64_t result;
+    (void)at;
+    if (__builtin_mul_overflow(a, b, &result)) return (a < 0) != (b < 0) ? INT64_MIN : INT64_MAX;
+    return result;
+}
+
+static inline int64_t why_div(int64_t a, int64_t b, const char *at) {
+    if (b == 0) why_trap(1, at);
+    if (a == INT64_MIN && b == -1) return INT64_MAX;
+    return a / b;
+}
+";
+
+/// Gets the C type a value of a type is held in
+fn value_type(ty: Ty) -> &'static str {
+    if ty == Ty::Float { "double" } else { "int64_t" }
+}
+
+/// Gets the C type of a parameter or result of a C function declared by an `extern`
+fn extern_type(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Unit | Ty::Int => "int64_t",
+        Ty::Float => "double",
+        Ty::Bool => "_Bool",
+        Ty::Str => "const char *",
+        Ty::Ptr | Ty::Fn => "void *",
+    }
+}
+
+/// Writes a string as a C string literal, escaping everything but printable ASCII
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in s.as_bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(byte as char);
+            }
+            // A `?` could start a trigraph
+            b'?' => quoted.push_str("\\?"),
+            0x20...0x7e => quoted.push(byte as char),
+            _ => write!(quoted, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Turns the name of a function into a C identifier starting with `w_`, which can't clash with the C library or the
+/// runtime. `main` is `why_main`, as in native code
+fn identifier(name: &str, taken: &mut HashSet<String>) -> String {
+    if name == "main" {
+        return "why_main".into();
+    }
+    let mut ident = String::from("w_");
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            ident.push(c);
+        } else if !ident.ends_with('_') {
+            ident.push('_');
+        }
+    }
+    while ident.ends_with('_') && ident.len() > 2 {
+        ident.pop();
+    }
+    let mut unique = ident.clone();
+    let mut n = 2;
+    while !taken.insert(unique.clone()) {
+        unique = format!("{}_{}", ident, n);
+        n += 1;
+    }
+    unique
+}
+
+fn extern_identifier(name: &str) -> String {
+    format!("why_extern_{}", name)
+}
+
+/// Compiles a module to a C translation unit, which defines `main` to run the program and print its result
+///
+/// Failures are reported at locations in the document if there is one, and its lines are used for `#line`
+/// directives.
+pub fn c_source(module: &Module, document: Option<&Document>) -> Result<String, CodegenError> {
+    if module.overflow == OverflowMode::Promote {
+        return Err(CodegenError {
+            function: "main".into(),
+            message: "integers that promote to arbitrary precision aren't supported in C".into(),
+        });
+    }
+
+    let mut taken = HashSet::new();
+    let names: HashMap<&str, String> = module.functions.iter()
+        .map(|f| (f.name.as_str(), identifier(&f.name, &mut taken)))
+        .collect();
+
+    let mut out = String::from(PRELUDE);
+    out.push_str(match module.overflow {
+        OverflowMode::Trap => TRAP,
+        OverflowMode::Wrap => WRAP,
+        _ => SATURATE,
+    });
+
+    if !module.externs.is_empty() {
+        writeln!(out).unwrap();
+    }
+    // Externs are declared under names of their own and linked by their symbols, so a declaration that doesn't match
+    // the C library's, like a `size_t` result read as an `Int`, doesn't conflict with the compiler's built-in one
+    for e in &module.externs {
+        let params: Vec<&str> = e.params.iter().map(|&ty| extern_type(ty)).collect();
+        let ret = match e.ret {
+            Ty::Unit => "void",
+            _ if e.c_int => "int",
+            ty => extern_type(ty),
+        };
+        let params = if params.is_empty() { "void".into() } else { params.join(", ") };
+        writeln!(out, "{} {}({}) __asm__(\"{}\");", ret, extern_identifier(&e.name), params, e.name).unwrap();
+    }
+
+    writeln!(out).unwrap();
+    for function in &module.functions {
+        writeln!(out, "{};", signature(function, &names[function.name.as_str()])).unwrap();
+    }
+
+    let mut emitter = Emitter { module, document, names: &names, strings: Vec::new(), body: String::new(), line: None };
+    for function in &module.functions {
+        emitter.function(function)?;
+    }
+
+    // Strings and vtables are defined before the functions that use them
+    for (i, s) in emitter.strings.iter().enumerate() {
+        if i == 0 {
+            writeln!(out).unwrap();
+        }
+        writeln!(out, "static const struct {{ int64_t header, len; char bytes[{}]; }} why_str{} = {{ 0, {}, {} }};",
+                 s.len() + 1, i, s.len(), quote(s)).unwrap();
+    }
+    for (i, functions) in module.vtables.iter().enumerate() {
+        let functions: Vec<String> = functions.iter().map(|f| format!("(void *){}", names[f.as_str()])).collect();
+        writeln!(out, "static void *const why_vtable{}[] = {{ {} }};", i, functions.join(", ")).unwrap();
+    }
+    out.push_str(&emitter.body);
+
+    let ret = module.function("main").map_or(Ty::Unit, |main| main.ret);
+    writeln!(out).unwrap();
+    // An object is printed field by field, from the description of its type
+    let layout = if ret == Ty::Ptr { format!(", {}", quote(&module.layout)) } else { String::new() };
+    let layout_param = if ret == Ty::Ptr { ", const char *" } else { "" };
+    writeln!(out, "void why_print_{}({}{});", ret.name(), value_type(ret), layout_param).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "int main(void) {{").unwrap();
+    if module.function("main").is_some() {
+        writeln!(out, "    why_print_{}({}(){});", ret.name(), names["main"], layout).unwrap();
+    }
+    writeln!(out, "    return 0;").unwrap();
+    writeln!(out, "}}").unwrap();
+    Ok(out)
+}
+
+fn signature(function: &Function, name: &str) -> String {
+    let params: Vec<String> = function.params.iter().map(|&(v, ty)| format!("{} v{}", value_type(ty), v.0)).collect();
+    let params = if params.is_empty() { "void".into() } else { params.join(", ") };
+    format!("{} {}({})", value_type(function.ret), name, params)
+}
+
+struct Emitter<'a> {
+    module: &'a Module,
+    document: Option<&'a Document>,
+    names: &'a HashMap<&'a str, String>,
+
+    /// The string constants, each of which is defined once as `why_str` and its index
+    strings: Vec<String>,
+    body: String,
+
+    /// The source line the next line of C is counted as, once a `#line` has been written
+    line: Option<u64>,
+}
+
+impl<'a> Emitter<'a> {
+    /// Writes a line of C, preceded by a `#line` directive if it came from a different line of the source than the
+    /// line it's counted as
+    fn line(&mut self, span: Option<TextSpan>, text: &str) {
+        if let (Some(document), Some(span)) = (self.document, span) {
+            let (line, _) = document.line_map().map_offset(span.start() as u64);
+            if self.line != Some(line + 1) {
+                let path = document.path().display().to_string();
+                writeln!(self.body, "#line {} {}", line + 1, quote(&path)).unwrap();
+                self.line = Some(line + 1);
+            }
+        }
+        writeln!(self.body, "{}", text).unwrap();
+        self.line = self.line.map(|l| l + 1);
+    }
+
+    fn string(&mut self, s: &str) -> usize {
+        match self.strings.iter().position(|t| t == s) {
+            Some(i) => i,
+            None => {
+                self.strings.push(s.into());
+                self.strings.len() - 1
+            }
+        }
+    }
+
+    fn function(&mut self, function: &'a Function) -> Result<(), CodegenError> {
+        let name = &self.names[function.name.as_str()];
+        self.line(None, "");
+        self.line(function.span, &format!("{} {{", signature(function, name)));
+
+        // Every value is declared up front, since jumps between blocks can skip over where they're defined
+        let params: HashSet<Value> = function.params.iter().map(|&(v, _)| v).collect();
+        let mut phis = Vec::new();
+        for inst in function.blocks.iter().flat_map(|b| b.insts.iter()) {
+            if !params.contains(&inst.value) {
+                self.line(None, &format!("    {} v{};", value_type(inst.ty), inst.value.0));
+            }
+            if let InstKind::Phi(_) = inst.kind {
+                self.line(None, &format!("    {} p{};", value_type(inst.ty), inst.value.0));
+                phis.push(inst.value);
+            }
+        }
+
+        // Only the blocks that are jumped to need labels, since a jump to the next block is left out
+        let mut targets = HashSet::new();
+        for (b, block) in function.blocks.iter().enumerate() {
+            match block.terminator {
+                Terminator::Jump(to) if to.index() == b + 1 => {}
+                ref terminator => targets.extend(terminator.successors()),
+            }
+        }
+
+        let types = function.value_types();
+        for (b, block) in function.blocks.iter().enumerate() {
+            if targets.contains(&BlockId(b as u32)) {
+                self.line(None, &format!("bb{}:", b));
+            }
+            for inst in &block.insts {
+                let statement = self.inst(function, &types, inst)?;
+                self.line(inst.span, &format!("    {}", statement));
+            }
+            self.terminator(function, b);
+        }
+        self.line(None, "}");
+        Ok(())
+    }
+
+    fn error<T, S: Into<String>>(&self, function: &Function, message: S) -> Result<T, CodegenError> {
+        Err(CodegenError { function: function.name.clone(), message: message.into() })
+    }
+
+    fn inst(&mut self, function: &Function, types: &[Option<Ty>], inst: &Inst) -> Result<String, CodegenError> {
+        let types = |v: Value| types[v.index()].unwrap_or(Ty::Unit);
+        let dst = format!("v{}", inst.value.0);
+        Ok(match inst.kind {
+            InstKind::Const(ref c) => match *c {
+                Const::Unit => format!("{} = 0;", dst),
+                Const::Int(ref i) => match i.to_i64() {
+                    // The most negative integer has no literal, since `-` applies to a positive one
+                    Some(i) if i == i64::min_value() => format!("{} = INT64_MIN;", dst),
+                    Some(i) => format!("{} = {};", dst, i),
+                    None => return self.error(function, format!("the integer {} doesn't fit in 64 bits", i)),
+                },
+                // The shortest digits that read back as the float, which C reads back the same way
+                Const::Float(f) if f.is_finite() => format!("{} = {:?};", dst, f),
+                Const::Float(f) => format!("{} = why_to_float({});", dst, f.to_bits() as i64),
+                Const::Bool(b) => format!("{} = {};", dst, b as i64),
+                Const::Str(ref s) => format!("{} = (int64_t)why_str{}.bytes;", dst, self.string(s)),
+            },
+            InstKind::Binary(op, a, b) => {
+                let location = quote(&source_location(self.document, inst.span));
+                match types(a) {
+                    Ty::Int => match op {
+                        BinOp::Add => format!("{} = why_add(v{}, v{}, {});", dst, a.0, b.0, location),
+                        BinOp::Subtract => format!("{} = why_sub(v{}, v{}, {});", dst, a.0, b.0, location),
+                        BinOp::Multiply => format!("{} = why_mul(v{}, v{}, {});", dst, a.0, b.0, location),
+                        BinOp::Divide => format!("{} = why_div(v{}, v{}, {});", dst, a.0, b.0, location),
+                        BinOp::Power => {
+                            let mode = match self.module.overflow {
+                                OverflowMode::Trap => 0,
+                                OverflowMode::Wrap => 1,
+                                _ => 2,
+                            };
+                            format!("{} = why_int_pow(v{}, v{}, {}, {});", dst, a.0, b.0, mode, location)
+                        }
+                        _ => format!("{} = v{} {} v{};", dst, a.0, op.symbol(), b.0),
+                    },
+                    Ty::Float if op == BinOp::Power => format!("{} = why_float_pow(v{}, v{});", dst, a.0, b.0),
+                    // Strings compare by their contents, which the runtime orders like `strcmp`
+                    Ty::Str if op == BinOp::Add => format!("{} = why_str_concat(v{}, v{});", dst, a.0, b.0),
+                    Ty::Str => format!("{} = why_str_cmp(v{}, v{}) {} 0;", dst, a.0, b.0, op.symbol()),
+                    // Everything else compares by its bits, which for heap objects and functions is by identity
+                    _ => format!("{} = v{} {} v{};", dst, a.0, op.symbol(), b.0),
+                }
+            }
+            InstKind::Call(Callee::Direct(ref name), ref args) => {
+                if let Some(e) = self.module.extern_(name) {
+                    let args: Vec<String> = args.iter().zip(&e.params).map(|(a, &ty)| match ty {
+                        Ty::Str | Ty::Ptr | Ty::Fn => format!("({})v{}", extern_type(ty), a.0),
+                        _ => format!("v{}", a.0),
+                    }).collect();
+                    let call = format!("{}({})", extern_identifier(name), args.join(", "));
+                    match e.ret {
+                        // A C function returning `void` has no value to use
+                        Ty::Unit => format!("{}; {} = 0;", call, dst),
+                        // A C string has no length word and may be null, so it's copied to a string of the program
+                        Ty::Str => format!("{} = why_str_from_c({});", dst, call),
+                        Ty::Ptr | Ty::Fn => format!("{} = (int64_t){};", dst, call),
+                        _ => format!("{} = {};", dst, call),
+                    }
+                } else {
+                    let args: Vec<String> = args.iter().map(|a| format!("v{}", a.0)).collect();
+                    format!("{} = {}({});", dst, self.names[name.as_str()], args.join(", "))
+                }
+            }
+            InstKind::Call(Callee::Indirect(f), ref args) => {
+                let params: Vec<&str> = args.iter().map(|&a| value_type(types(a))).collect();
+                let params = if params.is_empty() { "void".into() } else { params.join(", ") };
+                let args: Vec<String> = args.iter().map(|a| format!("v{}", a.0)).collect();
+                format!("{} = (({} (*)({}))v{})({});", dst, value_type(inst.ty), params, f.0, args.join(", "))
+            }
+            InstKind::New(ref fields) => {
+                if fields.len() > 64 {
+                    return self.error(function, "objects with more than 64 fields aren't supported in C");
+                }
+                // The runtime needs to know which fields refer to other heap objects to find the live ones
+                let pointers = fields.iter().enumerate()
+                    .filter(|&(_, &f)| types(f).is_reference())
+                    .fold(0u64, |mask, (i, _)| mask | 1 << i);
+                let mut statement = format!("{} = why_new({}, {:#x});", dst, fields.len(), pointers);
+                for (i, &field) in fields.iter().enumerate() {
+                    write!(statement, " ((int64_t *){})[{}] = {};", dst, i, word(field, types(field))).unwrap();
+                }
+                statement
+            }
+            InstKind::Get(object, field) => {
+                let word = format!("((int64_t *)v{})[{}]", object.0, field);
+                if inst.ty == Ty::Float {
+                    format!("{} = why_to_float({});", dst, word)
+                } else {
+                    format!("{} = {};", dst, word)
+                }
+            }
+            InstKind::Set(object, field, value) => {
+                format!("((int64_t *)v{})[{}] = {}; {} = 0;", object.0, field, word(value, types(value)), dst)
+            }
+            InstKind::Phi(_) => format!("{} = p{};", dst, inst.value.0),
+            InstKind::Func(ref name) => match self.names.get(name.as_str()) {
+                Some(ident) => format!("{} = (int64_t){};", dst, ident),
+                None => format!("{} = (int64_t){};", dst, extern_identifier(name)),
+            },
+            InstKind::VTable(n) => format!("{} = (int64_t)why_vtable{};", dst, n),
+        })
+    }
+
+    /// Writes the jump from a block to another, setting the phis of the target first. The jump is left out if the
+    /// target comes next and `fall_through` allows it
+    fn jump(&self, function: &Function, from: usize, to: BlockId, fall_through: bool) -> String {
+        let mut statements = Vec::new();
+        for inst in &function.block(to).insts {
+            if let InstKind::Phi(ref incoming) = inst.kind {
+                if let Some(&(_, v)) = incoming.iter().find(|&&(b, _)| b.index() == from) {
+                    statements.push(format!("p{} = v{};", inst.value.0, v.0));
+                }
+            }
+        }
+        if !fall_through || to.index() != from + 1 {
+            statements.push(format!("goto bb{};", to.index()));
+        }
+        statements.join(" ")
+    }
+
+    fn terminator(&mut self, function: &Function, b: usize) {
+        let statement = match function.blocks[b].terminator {
+            Terminator::Jump(to) => self.jump(function, b, to, true),
+            Terminator::Branch(cond, then, otherwise) => {
+                let (then, otherwise) = (self.jump(function, b, then, false), self.jump(function, b, otherwise, false));
+                format!("if (v{}) {{ {} }} else {{ {} }}", cond.0, then, otherwise)
+            }
+            Terminator::Return(v) => format!("return v{};", v.0),
+            Terminator::Unreachable => "__builtin_unreachable();".into(),
+        };
+        if !statement.is_empty() {
+            self.line(None, &format!("    {}", statement));
+        }
+    }
+}
+
+/// Gets a value as a word, for storing in an object
+fn word(value: Value, ty: Ty) -> String {
+    if ty == Ty::Float { format!("why_from_float(v{})", value.0) } else { format!("v{}", value.0) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::parse_module;
+
+    fn compile(text: &str) -> String {
+        let module = parse_module(text).expect("Expected the module to parse");
+        c_source(&module, None).expect("Expected the module to compile")
+    }
+
+    #[test]
+    pub fn functions_become_c_functions() {
+        let c = compile("\
+overflow wrap
+
+fn @\"add<Int>\"(%0: int, %1: int) -> int {
+bb0:
+    %2: int = add %0, %1 !0..1
+    ret %2
+}
+
+fn @main() -> int {
+bb0:
+    %0: int = const 1
+    %1: int = call @\"add<Int>\"(%0, %0)
+    ret %1
+}
+");
+        assert!(c.contains("\
+int64_t w_add_Int(int64_t v0, int64_t v1) {
+    int64_t v2;
+    v2 = why_add(v0, v1, \"offset 0\");
+    return v2;
+}
+"), "{}", c);
+        assert!(c.contains("return (int64_t)((uint64_t)a + (uint64_t)b);"));
+        assert!(c.contains("    v1 = w_add_Int(v0, v0);\n"));
+        assert!(c.contains("int main(void) {\n    why_print_int(why_main());\n"));
+    }
+
+    #[test]
+    pub fn phis_are_set_before_jumps() {
+        // The phis swap their values each time around, which copying one after the other would get wrong
+        let c = compile("\
+overflow trap
+
+fn @swap(%0: int, %1: int, %2: bool) -> int {
+bb0:
+    jump bb1
+bb1:
+    %3: int = phi [bb0: %0], [bb1: %4]
+    %4: int = phi [bb0: %1], [bb1: %3]
+    branch %2, bb1, bb2
+bb2:
+    ret %3
+}
+");
+        assert!(c.contains("    p3 = v0; p4 = v1;\nbb1:\n    v3 = p3;\n    v4 = p4;\n"), "{}", c);
+        assert!(c.contains("    if (v2) { p3 = v4; p4 = v3; goto bb1; } else { goto bb2; }\n"), "{}", c);
+    }
+
+    #[test]
+    pub fn line_directives_point_at_the_source() {
+        let document = Document::new("test.why", "extern puts(s: Str) -> CInt;\nputs(\"hi\") * 2");
+        let module = parse_module("\
+overflow saturate
+extern @puts(str) -> cint
+
+fn @main() -> int !0..0 {
+bb0:
+    %0: str = const \"hi\"
+    %1: int = call @puts(%0) !29..39
+    %2: int = const 2
+    %3: int = mul %1, %2 !29..43
+    ret %3
+}
+").unwrap();
+        let c = c_source(&module, Some(&document)).unwrap();
+        assert!(c.contains("int why_extern_puts(const char *) __asm__(\"puts\");\n"), "{}", c);
+        assert!(c.contains("static const struct { int64_t header, len; char bytes[3]; } why_str0 = { 0, 2, \"hi\" };"));
+        assert!(c.contains("#line 1 \"test.why\"\nint64_t why_main(void) {\n"), "{}", c);
+        assert!(c.contains("#line 2 \"test.why\"\n    v1 = why_extern_puts((const char *)v0);\n    v2 = 2;\n\
+                            #line 2 \"test.why\"\n    v3 = why_mul(v1, v2, \"test.why:2:1\");\n"), "{}", c);
+    }
+}
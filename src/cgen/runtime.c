@@ -0,0 +1,356 @@
+/*
+ * The runtime for Why programs compiled to C, which whyc compiles alongside them.
+ *
+ * Every value but a float is passed as an int64_t. A string points at its bytes, which are followed by a zero and
+ * preceded by its length and a header word, and an object points at its first field.
+ */
+
+#include <inttypes.h>
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static const char *const trap_messages[] = {
+    "attempt to compute a result that overflows",
+    "attempt to divide by zero",
+    "attempt to raise to a negative power",
+};
+
+__attribute__((noreturn)) void why_trap(int64_t kind, const char *location) {
+    fflush(stdout);
+    fprintf(stderr, "%s: error: %s\n", location, trap_messages[kind]);
+    exit(101);
+}
+
+static void *allocate(size_t words) {
+    void *memory = calloc(words ? words : 1, sizeof(int64_t));
+    if (!memory) {
+        fputs("error: out of memory\n", stderr);
+        abort();
+    }
+    return memory;
+}
+
+int64_t why_new(int64_t count, uint64_t pointers) {
+    (void)pointers;
+    return (int64_t)allocate((size_t)count);
+}
+
+static int64_t length(int64_t s) {
+    return ((const int64_t *)s)[-1];
+}
+
+int64_t why_str_concat(int64_t a, int64_t b) {
+    size_t len = (size_t)(length(a) + length(b));
+    int64_t *words = allocate(2 + (len + 8) / 8);
+    char *bytes = (char *)(words + 2);
+    words[1] = (int64_t)len;
+    memcpy(bytes, (const char *)a, (size_t)length(a));
+    memcpy(bytes + length(a), (const char *)b, (size_t)length(b));
+    return (int64_t)bytes;
+}
+
+int64_t why_str_from_c(const char *s) {
+    size_t len = s ? strlen(s) : 0;
+    int64_t *words = allocate(2 + (len + 8) / 8);
+    words[1] = (int64_t)len;
+    if (len) {
+        memcpy(words + 2, s, len);
+    }
+    return (int64_t)(words + 2);
+}
+
+int64_t why_str_cmp(int64_t a, int64_t b) {
+    size_t shorter = (size_t)(length(a) < length(b) ? length(a) : length(b));
+    int order = memcmp((const char *)a, (const char *)b, shorter);
+    if (order != 0) {
+        return order < 0 ? -1 : 1;
+    }
+    return (length(a) > length(b)) - (length(a) < length(b));
+}
+
+int64_t why_int_pow(int64_t a, int64_t b, int64_t mode, const char *location) {
+    if (b < 0) {
+        why_trap(2, location);
+    }
+    /* Every square of the base is used by the result, so if squaring overflows, so does the result */
+    int64_t result = 1, base = a;
+    int overflowed = 0;
+    for (uint64_t exp = (uint64_t)b; exp > 0 && !overflowed; exp >>= 1) {
+        if (exp & 1) {
+            overflowed |= __builtin_mul_overflow(result, base, &result);
+        }
+        if (exp > 1) {
+            overflowed |= __builtin_mul_overflow(base, base, &base);
+        }
+    }
+    if (!overflowed) {
+        return result;
+    }
+    switch (mode) {
+    case 0:
+        why_trap(0, location);
+    case 1:
+        result = 1;
+        for (uint64_t exp = (uint64_t)b, base = (uint64_t)a; exp > 0; exp >>= 1, base *= base) {
+            if (exp & 1) {
+                result = (int64_t)((uint64_t)result * base);
+            }
+        }
+        return result;
+    default:
+        return a < 0 && b % 2 == 1 ? INT64_MIN : INT64_MAX;
+    }
+}
+
+double why_float_pow(double a, double b) {
+    return pow(a, b);
+}
+
+void why_print_unit(int64_t value) {
+    (void)value;
+    puts("()");
+}
+
+void why_print_int(int64_t value) {
+    printf("%" PRId64 "\n", value);
+}
+
+/* Writes the shortest digits that read back as the float, positionally unless it's very large or small */
+static void format_float(double value, char out[64]) {
+    if (isnan(value) || isinf(value) || value == 0) {
+        const char *special = isinf(value) ? (value < 0 ? "-inf" : "inf") : signbit(value) ? "-0.0" : "0.0";
+        strcpy(out, isnan(value) ? "NaN" : special);
+        return;
+    }
+
+    char scientific[32];
+    for (int precision = 0; precision < 17; precision++) {
+        snprintf(scientific, sizeof scientific, "%.*e", precision, value);
+        if (strtod(scientific, NULL) == value) {
+            break;
+        }
+    }
+    char digits[20];
+    int n = 0;
+    const char *c = scientific + (value < 0);
+    for (; *c != 'e'; c++) {
+        if (*c != '.') {
+            digits[n++] = *c;
+        }
+    }
+    int exponent = atoi(c + 1);
+
+    char *o = out;
+    if (value < 0) {
+        *o++ = '-';
+    }
+    if (fabs(value) < 1e-4 || fabs(value) >= 1e16) {
+        *o++ = digits[0];
+        if (n > 1) {
+            *o++ = '.';
+            memcpy(o, digits + 1, (size_t)(n - 1));
+            o += n - 1;
+        }
+        sprintf(o, "e%d", exponent);
+    } else if (exponent < 0) {
+        *o++ = '0';
+        *o++ = '.';
+        for (int i = -1; i > exponent; i--) {
+            *o++ = '0';
+        }
+        memcpy(o, digits, (size_t)n);
+        o[n] = 0;
+    } else {
+        for (int i = 0; i <= exponent; i++) {
+            *o++ = i < n ? digits[i] : '0';
+        }
+        *o++ = '.';
+        if (n > exponent + 1) {
+            memcpy(o, digits + exponent + 1, (size_t)(n - exponent - 1));
+            o += n - exponent - 1;
+        } else {
+            *o++ = '0';
+        }
+        *o = 0;
+    }
+}
+
+void why_print_float(double value) {
+    char out[64];
+    format_float(value, out);
+    puts(out);
+}
+
+void why_print_bool(int64_t value) {
+    puts(value ? "true" : "false");
+}
+
+void why_print_str(int64_t value) {
+    fwrite((const char *)value, 1, (size_t)length(value), stdout);
+    putchar('\n');
+}
+
+/*
+ * Objects are written field by field from a description of their type, which is read as in the native runtime's
+ * `show.rs`: the type, then a line for each struct and enum, which types refer to by number.
+ */
+
+/* How deep values are written before the rest is left out, in case they refer to themselves */
+#define DEPTH 32
+
+static const char *show(const char *layout, const char *at, int64_t value, int depth);
+
+/* Moves past the description of a type */
+static const char *skip(const char *at);
+
+/* Moves past the types of a tuple or variant, up to and past the character that ends them */
+static const char *skip_list(const char *at, char end) {
+    while (*at != end) {
+        at = skip(at);
+    }
+    return at + 1;
+}
+
+static const char *skip(const char *at) {
+    char kind = *at++;
+    if (kind == '(') {
+        return skip_list(at, ')');
+    }
+    if (kind == 'D' || (kind >= '0' && kind <= '9')) {
+        return strchr(at, ';') + 1;
+    }
+    return at;
+}
+
+/* Gets where the struct or enum on a line starts */
+static const char *line(const char *layout, long number) {
+    const char *at = strchr(layout, '\n');
+    for (; number > 0; number--) {
+        at = strchr(at + 1, '\n');
+    }
+    return at + 1;
+}
+
+/* Writes a string within an object, quoted */
+static void show_str(int64_t value) {
+    putchar('"');
+    for (const char *c = (const char *)value; c < (const char *)value + length(value); c++) {
+        switch (*c) {
+        case '"': fputs("\\\"", stdout); break;
+        case '\\': fputs("\\\\", stdout); break;
+        case '\n': fputs("\\n", stdout); break;
+        case '\t': fputs("\\t", stdout); break;
+        case '\r': fputs("\\r", stdout); break;
+        case '\0': fputs("\\0", stdout); break;
+        default: putchar(*c);
+        }
+    }
+    putchar('"');
+}
+
+/* Writes a struct as `P { x: 1, y: 2 }` */
+static void show_struct(const char *layout, const char *at, const int64_t *fields, int depth) {
+    const char *end = strchr(at, ';');
+    printf("%.*s {", (int)(end - at), at);
+    at = end + 1;
+    int count = 0;
+    for (; *at && *at != '\n'; count++) {
+        end = strchr(at, ':');
+        printf("%s%.*s: ", count > 0 ? ", " : " ", (int)(end - at), at);
+        at = show(layout, end + 1, fields[count], depth);
+    }
+    fputs(count > 0 ? " }" : "}", stdout);
+}
+
+/* Writes the variant an enum's tag picks, as `Some(1)` or `None` */
+static void show_enum(const char *layout, const char *at, const int64_t *fields, int depth) {
+    for (int64_t tag = fields[0]; tag > 0; tag--) {
+        at = skip_list(strchr(at, '(') + 1, ')');
+    }
+    const char *end = strchr(at, '(');
+    printf("%.*s", (int)(end - at - 1), at + 1);
+    at = end + 1;
+    if (*at == ')') {
+        return;
+    }
+    putchar('(');
+    for (int count = 1; *at != ')'; count++) {
+        fputs(count > 1 ? ", " : "", stdout);
+        at = show(layout, at, fields[count], depth);
+    }
+    putchar(')');
+}
+
+/* Writes a value of the type described at `at`, returning where its description ends */
+static const char *show(const char *layout, const char *at, int64_t value, int depth) {
+    const int64_t *fields = (const int64_t *)value;
+    char kind = *at++;
+    switch (kind) {
+    case 'u':
+        fputs("()", stdout);
+        return at;
+    case 'i':
+        printf("%" PRId64, value);
+        return at;
+    case 'f': {
+        char out[64];
+        double d;
+        memcpy(&d, &value, sizeof d);
+        format_float(d, out);
+        fputs(out, stdout);
+        return at;
+    }
+    case 'b':
+        fputs(value ? "true" : "false", stdout);
+        return at;
+    case 's':
+        show_str(value);
+        return at;
+    case 'F':
+        fputs("<fn>", stdout);
+        return at;
+    case 'D': {
+        const char *end = strchr(at, ';');
+        printf("<dyn %.*s>", (int)(end - at), at);
+        return end + 1;
+    }
+    case '(': {
+        if (depth >= DEPTH) {
+            fputs("(...)", stdout);
+            return skip_list(at, ')');
+        }
+        int count = 0;
+        putchar('(');
+        for (; *at != ')'; count++) {
+            fputs(count > 0 ? ", " : "", stdout);
+            at = show(layout, at, fields[count], depth + 1);
+        }
+        fputs(count == 1 ? ",)" : ")", stdout);
+        return at + 1;
+    }
+    default: {
+        char *end;
+        const char *def = line(layout, strtol(at - 1, &end, 10));
+        if (depth >= DEPTH) {
+            fputs("...", stdout);
+        } else if (*def == 'S') {
+            show_struct(layout, def + 1, fields, depth + 1);
+        } else {
+            show_enum(layout, def + 1, fields, depth + 1);
+        }
+        return end + 1;
+    }
+    }
+}
+
+void why_print_ptr(int64_t value, const char *layout) {
+    show(layout, layout, value, 0);
+    putchar('\n');
+}
+
+void why_print_fn(int64_t value) {
+    (void)value;
+    puts("<fn>");
+}
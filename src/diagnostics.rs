@@ -0,0 +1,62 @@
+use text::TextSpan;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A secondary span attached to a `Diagnostic`, explaining how that part of the source
+/// contributed to the problem
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Label {
+    span: TextSpan,
+    message: String,
+}
+
+impl Label {
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn message(&self) -> &str { &self.message }
+}
+
+/// A problem found in a program, reported against the span of source that caused it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    severity: Severity,
+    span: TextSpan,
+    message: String,
+    labels: Vec<Label>,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(severity: Severity, span: TextSpan, message: S) -> Diagnostic {
+        Diagnostic {
+            severity,
+            span,
+            message: message.into(),
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn error<S: Into<String>>(span: TextSpan, message: S) -> Diagnostic {
+        Diagnostic::new(Severity::Error, span, message)
+    }
+
+    pub fn warning<S: Into<String>>(span: TextSpan, message: S) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, span, message)
+    }
+
+    /// Attaches a secondary span to the diagnostic
+    pub fn with_label<S: Into<String>>(mut self, span: TextSpan, message: S) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn severity(&self) -> Severity { self.severity }
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn message(&self) -> &str { &self.message }
+    pub fn labels(&self) -> &[Label] { &self.labels }
+}
@@ -0,0 +1,687 @@
+//! Runs the compiler from the command line, as `whyc [options] file.why`
+//!
+//! A program is checked in full before anything is written: it's parsed, its names are resolved and its types
+//! checked, then its patterns, control flow and lints, and constant arithmetic is evaluated. Any error stops the
+//! compilation, and warnings are reported either way. The program is then lowered to the IR, optimized at the level
+//! asked for, and written in the form asked for, or with `whyc run`, compiled into memory and run. Executables are
+//! linked with the runtime in `x86::RUNTIME_LIBRARY` and the `main` in `x86::RUNTIME_MAIN`, or built from C with the
+//! runtime in `cgen::RUNTIME`.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use arith::OverflowMode;
+use cfg;
+use cgen::{c_source, RUNTIME};
+use diagnostics::{Diagnostic, Severity};
+use fold::fold_program;
+use ir::{lower_program, Module, Ty};
+use jit::{format_value, Jit};
+use lint::{lint_program, Level, LintLevels};
+use mono::monomorphize;
+use opt::{OptLevel, PassManager};
+use parser::{Error, Parser};
+use patterns;
+use resolve::resolve_program;
+use text::{Document, TextSpan};
+use tokenizer::{self, Tokenizer};
+use typeck;
+use wasm::{wasm_module, wat};
+use x86::{assembly, compile_module, object_file, RUNTIME_LIBRARY, RUNTIME_MAIN};
+
+pub const USAGE: &'static str = "\
+usage: whyc [options] file.why
+       whyc run [options] file.why
+
+options:
+    --emit=KIND           what to write: the optimized IR (ir), assembler source (asm), an object file (obj, the
+                          default), an executable linked with the runtime by `cc` (exe), C source (c), an
+                          executable built from the C with `cc` (c-exe), or a WebAssembly module (wasm) or its
+                          text (wat)
+    -o PATH               where to write it, by default the input with the extension of what's written
+    -O0, -O1, -O2         how much to optimize (`whyc run` optimizes the functions it calls often with -O2 anyway)
+    --overflow=MODE       what integer overflow does: trap (the default), wrap, saturate, or promote to arbitrary
+                          precision, which only `whyc run` supports and which never frees the integers it boxes
+    -A, -W, -D LINT       allows, warns about or denies a lint
+    --time-passes         reports how long each optimization pass took and what it changed
+    --gc-stats            with `whyc run`, reports what the program allocated and collected (an executable does
+                          with `WHY_GC_STATS=1`)
+    --gc-stress           with `whyc run`, collects before every allocation (an executable does with
+                          `WHY_GC_STRESS=1`)";
+
+/// What the compiler writes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Emit {
+    Ir,
+    Asm,
+    Obj,
+
+    /// An executable, linked from the object and the runtime by the system's C compiler
+    Exe,
+    C,
+
+    /// An executable, built by compiling the program as C with the system's C compiler
+    CExe,
+    Wasm,
+
+    /// A WebAssembly module in the text format
+    Wat,
+}
+
+impl Emit {
+    /// The extension of the file written by default
+    pub fn extension(self) -> &'static str {
+        match self {
+            Emit::Ir => "ir",
+            Emit::Asm => "s",
+            Emit::Obj => "o",
+            Emit::C => "c",
+            Emit::Exe | Emit::CExe => "",
+            Emit::Wasm => "wasm",
+            Emit::Wat => "wat",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub input: PathBuf,
+    pub output: Option<PathBuf>,
+    pub emit: Emit,
+    pub opt_level: OptLevel,
+    pub overflow: OverflowMode,
+    pub lints: LintLevels,
+
+    /// Whether to report the optimization passes that ran
+    pub time_passes: bool,
+
+    /// Whether `whyc run` reports what the program allocated and collected
+    pub gc_stats: bool,
+
+    /// Whether `whyc run` collects before every allocation, so a missing root shows up right away
+    pub gc_stress: bool,
+}
+
+impl Options {
+    /// Reads the options from the arguments the compiler was run with, leaving out the name it was run by
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut input = None;
+        let mut output = None;
+        let mut emit = Emit::Obj;
+        let mut opt_level = OptLevel::default();
+        let mut overflow = OverflowMode::default();
+        let mut lints = LintLevels::new();
+        let mut time_passes = false;
+        let (mut gc_stats, mut gc_stress) = (false, false);
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg.starts_with("--emit=") {
+                emit = match &arg["--emit=".len()..] {
+                    "ir" => Emit::Ir,
+                    "asm" => Emit::Asm,
+                    "obj" => Emit::Obj,
+                    "c" => Emit::C,
+                    "exe" => Emit::Exe,
+                    "c-exe" => Emit::CExe,
+                    "wasm" => Emit::Wasm,
+                    "wat" => Emit::Wat,
+                    other => {
+                        let kinds = "`ir`, `asm`, `obj`, `exe`, `c`, `c-exe`, `wasm` or `wat`";
+                        return Err(format!("unknown output `{}`, expected {}", other, kinds));
+                    }
+                };
+            } else if arg.starts_with("--overflow=") {
+                overflow = arg["--overflow=".len()..].parse()?;
+            } else if arg == "--time-passes" {
+                time_passes = true;
+            } else if arg == "--gc-stats" {
+                gc_stats = true;
+            } else if arg == "--gc-stress" {
+                gc_stress = true;
+            } else if arg == "-o" {
+                output = Some(PathBuf::from(args.next().ok_or("expected a path after `-o`")?));
+            } else if let Some(level) = OptLevel::from_flag(&arg) {
+                opt_level = level;
+            } else if let Some(level) = Level::from_flag(&arg) {
+                let lint = args.next().ok_or_else(|| format!("expected a lint after `{}`", arg))?;
+                lints.set_by_name(&lint, level)?;
+            } else if arg.starts_with('-') {
+                return Err(format!("unknown option `{}`", arg));
+            } else if input.is_some() {
+                return Err(format!("unexpected argument `{}`, only one file can be compiled", arg));
+            } else {
+                input = Some(PathBuf::from(arg));
+            }
+        }
+
+        let input = input.ok_or("expected a file to compile")?;
+        Ok(Options { input, output, emit, opt_level, overflow, lints, time_passes, gc_stats, gc_stress })
+    }
+
+    /// Gets where the output goes: the path asked for, or the input with the output's extension
+    pub fn output_path(&self) -> PathBuf {
+        self.output.clone().unwrap_or_else(|| self.input.with_extension(self.emit.extension()))
+    }
+}
+
+/// Writes a diagnostic as `file:line:column: severity: message`, with a line for each of its labels
+pub fn format_diagnostic(document: &Document, diagnostic: &Diagnostic) -> String {
+    let severity = match diagnostic.severity() {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let mut message = format!("{}: {}: {}", position(document, diagnostic.span()), severity, diagnostic.message());
+    for label in diagnostic.labels() {
+        message.push_str(&format!("\n{}: note: {}", position(document, label.span()), label.message()));
+    }
+    message
+}
+
+fn position(document: &Document, span: TextSpan) -> String {
+    let (line, column) = document.line_map().map_offset(span.start() as u64);
+    format!("{}:{}:{}", document.path().display(), line + 1, column + 1)
+}
+
+/// Turns a parse error into a diagnostic at the token it's about
+fn parse_error(error: Error, end: usize) -> Diagnostic {
+    let at_end = TextSpan::new(end, end);
+    match error {
+        Error::UnexpectedEndOfFile => Diagnostic::error(at_end, "unexpected end of file"),
+        Error::UnexpectedToken(token) => Diagnostic::error(token.span(), format!("unexpected {:?}", token.typ())),
+        Error::ExpectedToken(expected, token) => {
+            Diagnostic::error(token.span(), format!("expected {:?}, found {:?}", expected, token.typ()))
+        }
+        Error::InvalidAssignmentTarget(span) => Diagnostic::error(span, "only variables and fields can be assigned"),
+        Error::TokenizerError(tokenizer::Error::UnterminatedString(span)) => {
+            Diagnostic::error(span, "unterminated string")
+        }
+        Error::TokenizerError(tokenizer::Error::InvalidEscape(span)) => {
+            Diagnostic::error(span, "unknown escape sequence")
+        }
+        Error::TokenizerError(error) => Diagnostic::error(at_end, format!("invalid text: {:?}", error)),
+    }
+}
+
+/// Checks a document and lowers it to optimized IR, adding the messages to report to `messages`
+///
+/// Returns `None` if the program has errors.
+pub fn lower(document: &Document, options: &Options, messages: &mut Vec<String>) -> Option<Module> {
+    let mut diagnostics = Vec::new();
+    let mut passes = None;
+    let lowered = {
+        let report = |diags: &[Diagnostic], diagnostics: &mut Vec<Diagnostic>| {
+            diagnostics.extend(diags.iter().cloned());
+            diags.iter().any(|d| d.severity() == Severity::Error)
+        };
+        let code = document.content();
+        match Parser::new(Tokenizer::new(code)).program() {
+            Err(error) => {
+                diagnostics.push(parse_error(error, code.len()));
+                None
+            }
+            Ok(program) => {
+                let resolution = resolve_program(&program);
+                let results = if report(resolution.diagnostics(), &mut diagnostics) {
+                    None
+                } else {
+                    let results = typeck::check_program(&program);
+                    if report(results.diagnostics(), &mut diagnostics) { None } else { Some(results) }
+                };
+                results.and_then(|results| {
+                    let mut failed = report(&patterns::check_program(&program), &mut diagnostics);
+                    failed |= report(&cfg::check_program(&program, &resolution), &mut diagnostics);
+                    failed |= report(&lint_program(&program, &resolution, &options.lints), &mut diagnostics);
+                    // Folded expressions keep the spans of what they replace, so the names and types found for the
+                    // program still describe the folded one
+                    let folded = fold_program(&program, options.overflow);
+                    failed |= report(folded.diagnostics(), &mut diagnostics);
+                    if failed {
+                        return None;
+                    }
+                    let program = folded.into_program();
+                    let mono = monomorphize(&program, &results);
+                    let mut module = lower_program(&program, &resolution, &results, &mono, options.overflow);
+                    passes = Some(PassManager::for_level(options.opt_level).run(&mut module));
+                    Some(module)
+                })
+            }
+        }
+    };
+    diagnostics.sort_by_key(|d| d.span().start());
+    messages.extend(diagnostics.iter().map(|d| format_diagnostic(document, d)));
+    match passes {
+        Some(report) if options.time_passes => messages.extend(report.to_string().lines().map(String::from)),
+        _ => {}
+    }
+    lowered
+}
+
+/// Compiles a document to the output the options ask for, adding the messages to report to `messages`
+///
+/// Returns `None` if the program has errors or can't be compiled.
+pub fn compile(document: &Document, options: &Options, messages: &mut Vec<String>) -> Option<Vec<u8>> {
+    let module = lower(document, options, messages)?;
+    let native = options.emit == Emit::Asm || options.emit == Emit::Obj || options.emit == Emit::Exe;
+    if native && module.overflow == OverflowMode::Promote {
+        // The code would need the runtime of `whyc run` for its integers
+        let error = "integers that promote to arbitrary precision are only supported by `whyc run`";
+        messages.push(format!("{}: error: {}", document.path().display(), error));
+        return None;
+    }
+    let output = match options.emit {
+        Emit::Ir => return Some(module.to_string().into_bytes()),
+        Emit::Asm => compile_module(&module, Some(document)).map(|p| assembly(&p, Some(document)).into_bytes()),
+        Emit::Obj => compile_module(&module, Some(document)).map(|p| object_file(&p, Some(document)).to_bytes()),
+        Emit::Exe => match compile_module(&module, Some(document)) {
+            Ok(program) => {
+                let object = object_file(&program, Some(document)).to_bytes();
+                let files = [("program.o", &object[..]), ("main.o", RUNTIME_MAIN), ("libwhyrt.a", RUNTIME_LIBRARY)];
+                return build_executable(&files).map_err(|e| messages.push(format!("error: {}", e))).ok();
+            }
+            Err(error) => Err(error),
+        },
+        Emit::C => c_source(&module, Some(document)).map(String::into_bytes),
+        Emit::CExe => match c_source(&module, Some(document)) {
+            Ok(source) => {
+                let files = [("program.c", source.as_bytes()), ("runtime.c", RUNTIME.as_bytes())];
+                return build_executable(&files).map_err(|e| messages.push(format!("error: {}", e))).ok();
+            }
+            Err(error) => Err(error),
+        },
+        Emit::Wasm => wasm_module(&module, Some(document)).map(|m| m.to_bytes()),
+        Emit::Wat => wasm_module(&module, Some(document)).map(|m| wat(&m).into_bytes()),
+    };
+    match output {
+        Ok(output) => Some(output),
+        Err(error) => {
+            messages.push(format!("{}: error: {}", document.path().display(), error));
+            None
+        }
+    }
+}
+
+/// Builds an executable from the files of a program and its runtime, C sources or objects and libraries, with the C
+/// compiler named by `CC`, or `cc`
+fn build_executable(files: &[(&str, &[u8])]) -> Result<Vec<u8>, String> {
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    let dir = env::temp_dir().join(format!("whyc-{}-{}", process::id(), BUILDS.fetch_add(1, Ordering::SeqCst)));
+    let build = || -> Result<Vec<u8>, String> {
+        fs::create_dir_all(&dir).map_err(|e| format!("can't make `{}`: {}", dir.display(), e))?;
+        let cc = env::var("CC").unwrap_or_else(|_| "cc".into());
+        let exe = dir.join("program");
+        let mut command = Command::new(&cc);
+        command.arg("-O2").arg("-o").arg(&exe);
+        for &(name, bytes) in files {
+            let path = dir.join(name);
+            fs::write(&path, bytes).map_err(|e| format!("can't write `{}`: {}", path.display(), e))?;
+            command.arg(path);
+        }
+        let status = command.arg("-lm").status().map_err(|e| format!("can't run `{}`: {}", cc, e))?;
+        if !status.success() {
+            return Err(format!("`{}` failed to build the program", cc));
+        }
+        fs::read(&exe).map_err(|e| format!("can't read the executable: {}", e))
+    };
+    let result = build();
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// Compiles a document into memory and runs it, returning the value of its final expression
+///
+/// Returns `None` if the program has errors or can't be compiled.
+pub fn run(document: &Document, options: &Options, messages: &mut Vec<String>) -> Option<String> {
+    let module = lower(document, options, messages)?;
+    let ret = module.function("main").map_or(Ty::Unit, |main| main.ret);
+    // What isn't compiled with every optimization to begin with is compiled again once it's hot
+    match Jit::new(&module, Some(document), options.opt_level < OptLevel::O2) {
+        Ok(mut jit) => {
+            let value = format_value(jit.run(ret, options.gc_stress), ret, &module.layout, module.overflow);
+            if let (true, Some(stats)) = (options.gc_stats, jit.heap_stats()) {
+                messages.push(format!("heap: {}", stats));
+            }
+            Some(value)
+        }
+        Err(error) => {
+            messages.push(format!("{}: error: {}", document.path().display(), error));
+            None
+        }
+    }
+}
+
+/// Runs the compiler with the arguments it was run with, returning the code to exit with
+///
+/// `whyc run file.why` runs the program instead of writing it, printing the value of its final expression.
+pub fn main(mut args: Vec<String>) -> i32 {
+    let running = args.first().map_or(false, |a| a == "run");
+    if running {
+        args.remove(0);
+    }
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return 2;
+        }
+    };
+
+    let document = match File::open(&options.input).and_then(|mut f| Document::read(options.input.clone(), &mut f)) {
+        Ok(document) => document,
+        Err(error) => {
+            eprintln!("error: can't read `{}`: {}", options.input.display(), error);
+            return 1;
+        }
+    };
+
+    if !running && (options.gc_stats || options.gc_stress) {
+        eprintln!("error: `--gc-stats` and `--gc-stress` are for `whyc run`; an executable reads `WHY_GC_STATS` and \
+                   `WHY_GC_STRESS` from its environment\n\n{}", USAGE);
+        return 2;
+    }
+
+    let mut messages = Vec::new();
+    if running {
+        let value = run(&document, &options, &mut messages);
+        for message in messages {
+            eprintln!("{}", message);
+        }
+        return match value {
+            Some(value) => {
+                println!("{}", value);
+                0
+            }
+            None => 1,
+        };
+    }
+
+    let output = compile(&document, &options, &mut messages);
+    for message in messages {
+        eprintln!("{}", message);
+    }
+    let output = match output {
+        Some(output) => output,
+        None => return 1,
+    };
+
+    let path = options.output_path();
+    let written = File::create(&path).and_then(|mut f| {
+        f.write_all(&output)?;
+        if options.emit == Emit::Exe || options.emit == Emit::CExe {
+            f.set_permissions(fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    });
+    match written {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("error: can't write `{}`: {}", path.display(), error);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Output;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn compile_str(code: &str, extra: &[&str]) -> (Option<String>, Vec<String>) {
+        let mut all = args(extra);
+        all.push("test.why".to_string());
+        let options = Options::parse(all).unwrap();
+        let document = Document::new("test.why", code);
+        let mut messages = Vec::new();
+        let output = compile(&document, &options, &mut messages).map(|o| String::from_utf8(o).unwrap());
+        (output, messages)
+    }
+
+    #[test]
+    pub fn options_are_parsed() {
+        let options = Options::parse(args(&["--emit=asm", "-O0", "--overflow=wrap", "-D", "shadowing", "a.why"]));
+        let options = options.unwrap();
+        assert_eq!(PathBuf::from("a.why"), options.input);
+        assert_eq!(Emit::Asm, options.emit);
+        assert_eq!(OptLevel::O0, options.opt_level);
+        assert_eq!(OverflowMode::Wrap, options.overflow);
+        assert_eq!(PathBuf::from("a.s"), options.output_path());
+        assert!(!options.time_passes);
+        assert!(Options::parse(args(&["--time-passes", "a.why"])).unwrap().time_passes);
+
+        let options = Options::parse(args(&["a.why", "-o", "out/b.o"])).unwrap();
+        assert_eq!(Emit::Obj, options.emit);
+        assert_eq!(PathBuf::from("out/b.o"), options.output_path());
+
+        let options = Options::parse(args(&["--emit=exe", "dir/a.why"])).unwrap();
+        assert_eq!(PathBuf::from("dir/a"), options.output_path());
+
+        assert!(Options::parse(args(&["--emit=pdf", "a.why"])).is_err());
+        assert!(Options::parse(args(&["a.why", "b.why"])).is_err());
+        assert!(Options::parse(args(&["-o"])).is_err());
+        assert!(Options::parse(args(&[])).is_err());
+    }
+
+    #[test]
+    pub fn assembly_is_annotated_with_source_locations() {
+        let code = "def f(x: Int) -> Int {\n    x * 2\n}\nf(20) + 2";
+        let (output, messages) = compile_str(code, &["--emit=asm"]);
+        assert_eq!(Vec::<String>::new(), messages);
+        let output = output.unwrap();
+        assert!(output.starts_with("    .file \"test.why\"\n    .text\n"));
+        assert!(output.contains("why_main:\n    # test.why:1:1\n"));
+        assert!(output.contains("f:\n    # test.why:1:1\n"));
+        assert!(output.contains("    # test.why:4:1\n"));
+        assert!(output.contains("    .size w_f, .-w_f\n"));
+    }
+
+    #[test]
+    pub fn programs_run_in_memory() {
+        let options = Options::parse(args(&["--overflow=wrap", "test.why"])).unwrap();
+        let document = Document::new("test.why", "def twice(s: Str) { s + s }\ntwice(\"why\")");
+        let mut messages = Vec::new();
+        assert_eq!(Some("whywhy".to_string()), run(&document, &options, &mut messages));
+        assert_eq!(Vec::<String>::new(), messages);
+        let options = Options::parse(args(&["--gc-stats", "--gc-stress", "test.why"])).unwrap();
+        assert_eq!(Some("whywhy".to_string()), run(&document, &options, &mut messages));
+        assert_eq!(1, messages.len());
+        assert!(messages[0].starts_with("heap: 1 collections, 1 objects allocated"), "{:?}", messages);
+        messages.clear();
+
+        // Only `whyc run` has the runtime for integers that promote
+        let code = "let two = 2;\ntwo ** 200";
+        let options = Options::parse(args(&["--overflow=promote", "test.why"])).unwrap();
+        let value = run(&Document::new("test.why", code), &options, &mut messages);
+        assert_eq!(Some("1606938044258990275541962092341162602522202993782792835301376".to_string()), value);
+        let (output, messages) = compile_str(code, &["--overflow=promote"]);
+        assert_eq!(None, output);
+        assert!(messages[0].contains("only supported by `whyc run`"), "{:?}", messages);
+    }
+
+    /// Builds a program into an executable of the kind and runs it
+    fn build_and_run(code: &str, emit: &str) -> Output {
+        build_and_run_with(code, &[emit], &[])
+    }
+
+    /// Builds an executable with the options given, and runs it with the environment variables given
+    fn build_and_run_with(code: &str, options: &[&str], vars: &[(&str, &str)]) -> Output {
+        let options = Options::parse(args(options).into_iter().chain(Some("test.why".to_string()))).unwrap();
+        let document = Document::new("test.why", code);
+        let mut messages = Vec::new();
+        let output = compile(&document, &options, &mut messages);
+        assert_eq!(Vec::<String>::new(), messages);
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!("whyc-test-{}-{}", process::id(), RUNS.fetch_add(1, Ordering::SeqCst)));
+        fs::write(&path, output.unwrap()).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let result = Command::new(&path).envs(vars.iter().cloned()).output();
+        fs::remove_file(&path).unwrap();
+        result.unwrap()
+    }
+
+    #[test]
+    pub fn programs_compile_to_executables() {
+        // Linked with the runtime or built from C, an executable prints its value as `whyc run` does
+        let programs = [
+            ("def twice(x: Float) { x * 2.0 }\ntwice(0.05)", "0.1"),
+            ("1.0 / 3.0", "0.3333333333333333"),
+            ("10000000000000000.0 * 10.0", "1e17"),
+            ("def twice(s: Str) { s + s }\ntwice(\"why\")", "whywhy"),
+            ("2 ** 10 - 1", "1023"),
+            ("1 < 2", "true"),
+            ("struct P { x: Int, s: Str }\nP { x: 1, s: \"a\\\"b\" }", "P { x: 1, s: \"a\\\"b\" }"),
+            ("enum L { C(Float, L), N }\nC(0.5, C(100000000000000000000.0, N))", "C(0.5, C(1e20, N))"),
+            ("(1, (true,), ())", "(1, (true,), ())"),
+        ];
+        for &emit in &["--emit=exe", "--emit=c-exe"] {
+            for &(code, value) in &programs {
+                let result = build_and_run(code, emit);
+                assert!(result.status.success(), "{}", code);
+                assert_eq!(format!("{}\n", value), String::from_utf8_lossy(&result.stdout));
+
+                let options = Options::parse(args(&["test.why"])).unwrap();
+                let ran = run(&Document::new("test.why", code), &options, &mut Vec::new());
+                assert_eq!(Some(value.to_string()), ran);
+            }
+        }
+    }
+
+    #[test]
+    pub fn values_spilled_under_pressure_keep_their_values() {
+        // Eleven values are live at once, twice over, so some spill after they start, into slots others had
+        let a: Vec<String> = (1..11).map(|k| format!("a{}", k)).collect();
+        let b: Vec<String> = (1..10).map(|k| format!("b{}", k)).collect();
+        let mut code = "def f(x: Int) -> Int {\n    let a0 = x;\n".to_string();
+        for k in 1..11 {
+            code += &format!("    let a{} = a{} * 3 + {};\n", k, k - 1, k);
+        }
+        code += &format!("    let xx = a10 * a10;\n    let t = {};\n", a.join(" + "));
+        code += "    let v = t - 7;\n    let b0 = t + xx;\n";
+        for k in 1..10 {
+            code += &format!("    let b{} = b{} * 3 + {};\n", k, k - 1, k);
+        }
+        code += &format!("    b9 * 5 + b0 + {} + v\n}}\nf(5)", b.join(" + "));
+
+        let options = Options::parse(args(&["-O2", "--overflow=wrap", "test.why"])).unwrap();
+        let ran = run(&Document::new("test.why", &code), &options, &mut Vec::new());
+        assert_eq!(Some("14748605000395415".to_string()), ran);
+        for &emit in &["--emit=exe", "--emit=c-exe"] {
+            let result = build_and_run_with(&code, &[emit, "-O2", "--overflow=wrap"], &[]);
+            assert_eq!("14748605000395415\n", String::from_utf8_lossy(&result.stdout), "{}", emit);
+        }
+    }
+
+    #[test]
+    pub fn executables_report_failures_where_they_happen() {
+        let result = build_and_run("def f(x: Int) -> Int {\n    x * x\n}\nf(5000000000)", "--emit=exe");
+        assert_eq!(Some(101), result.status.code());
+        let error = "test.why:2:5: error: attempt to compute a result that overflows\n";
+        assert_eq!(error, String::from_utf8_lossy(&result.stderr));
+        // What the program printed through C comes before the failure
+        let code = "extern puts(s: Str) -> CInt;\ndef p(x: Int) -> Int { 2 ** x }\nputs(\"hi\");\np(0 - 1)";
+        let result = build_and_run(code, "--emit=exe");
+        assert_eq!(Some(101), result.status.code());
+        assert_eq!("hi\n", String::from_utf8_lossy(&result.stdout));
+        assert!(String::from_utf8_lossy(&result.stderr).contains("negative power"));
+    }
+
+    #[test]
+    pub fn objects_link_into_c_programs_with_their_own_main() {
+        let code = "def square(x: Int) -> Int { x * x }\ndef shout(s: Str) -> Str { s + \"!\" }\n0";
+        let options = Options::parse(args(&["--emit=obj", "test.why"])).unwrap();
+        let mut messages = Vec::new();
+        let object = compile(&Document::new("test.why", code), &options, &mut messages).unwrap();
+        // Exported functions aren't reported as unused
+        assert_eq!(Vec::<String>::new(), messages);
+
+        // The C program gets a string of the program's from `shout`, which has its length in the word before it
+        let main = b"#include <stdint.h>\n#include <stdio.h>\n\
+                     int64_t w_square(int64_t);\nconst char *w_shout(const char *);\n\
+                     static const struct { int64_t header, len; char bytes[3]; } hi = { 0, 2, \"hi\" };\n\
+                     int main(void) { printf(\"%ld %s\\n\", (long)w_square(7), w_shout(hi.bytes)); return 0; }\n";
+        let files = [("main.c", &main[..]), ("program.o", &object[..]), ("libwhyrt.a", RUNTIME_LIBRARY)];
+        let exe = build_executable(&files).unwrap();
+        let path = env::temp_dir().join(format!("whyc-test-{}-c-main", process::id()));
+        fs::write(&path, exe).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let result = Command::new(&path).output();
+        fs::remove_file(&path).unwrap();
+        assert_eq!("49 hi!\n", String::from_utf8_lossy(&result.unwrap().stdout));
+    }
+
+    #[test]
+    pub fn executables_copy_c_strings_returned_by_externs() {
+        let code = "extern getenv(s: Str) -> Str;\n(getenv(\"WHY_TEST_NAME\") + \"!\", getenv(\"WHY_TEST_UNSET\"))";
+        for &emit in &["--emit=exe", "--emit=c-exe"] {
+            let result = build_and_run_with(code, &[emit], &[("WHY_TEST_NAME", "why")]);
+            assert_eq!("(\"why!\", \"\")\n", String::from_utf8_lossy(&result.stdout), "{}", emit);
+        }
+    }
+
+    #[test]
+    pub fn executables_collect_garbage() {
+        // Every concatenation but the last few is garbage by the time the next one collects
+        let code = "extern strlen(s: Str) -> CLong;\nstruct Box { s: Str, n: Int }\n\
+                    def grow(s: Str, n: Int) -> Str { match n { 0 => s, _ => grow(s + \"ab\", n - 1) } }\n\
+                    def go(n: Int, b: Box) -> Int {\n\
+                        let next = Box { s: b.s + \"!\", n: strlen(grow(\"\", 20)) };\n\
+                        match n { 0 => strlen(b.s) + b.n, _ => go(n - 1, next) }\n\
+                    }\ngo(300, Box { s: \"\", n: 0 })";
+        for &level in &["-O0", "-O2"] {
+            let result = build_and_run_with(code, &["--emit=exe", level], &[("WHY_GC_STATS", "1")]);
+            assert_eq!("340\n", String::from_utf8_lossy(&result.stdout));
+            assert!(String::from_utf8_lossy(&result.stderr).starts_with("heap: 0 collections, 6623 objects"));
+
+            // Collecting at every allocation leaves the program's own values alone
+            let vars = [("WHY_GC_STRESS", "1"), ("WHY_GC_STATS", "1")];
+            let result = build_and_run_with(code, &["--emit=exe", level], &vars);
+            assert!(result.status.success());
+            assert_eq!("340\n", String::from_utf8_lossy(&result.stdout));
+            let stats = String::from_utf8_lossy(&result.stderr).into_owned();
+            assert!(stats.starts_with("heap: 6623 collections, 6623 objects allocated"), "{}", stats);
+            assert!(!stats.contains("given up"), "{}", stats);
+        }
+    }
+
+    #[test]
+    pub fn constants_are_folded_before_lowering() {
+        let code = "def f(x: Int) -> Int { x * (2 + 3) + 0 }\nf(1)";
+        let (output, messages) = compile_str(code, &["--emit=ir", "-O0"]);
+        assert_eq!(Vec::<String>::new(), messages);
+        let output = output.unwrap();
+        assert!(output.contains("= const 5\n    %2: int = mul %0, %1"), "{}", output);
+        assert!(!output.contains("add"), "{}", output);
+    }
+
+    #[test]
+    pub fn passes_are_reported_when_asked_for() {
+        let code = "def f(x: Int) -> Int { x * (2 + 3) }\nf(1)";
+        let (_, messages) = compile_str(code, &["--emit=ir", "-O2"]);
+        assert_eq!(Vec::<String>::new(), messages);
+
+        let (_, messages) = compile_str(code, &["--emit=ir", "-O2", "--time-passes"]);
+        let names: Vec<&str> = messages.iter().map(|m| m.split_whitespace().next().unwrap()).collect();
+        assert_eq!(vec!["inline", "tailrec", "sccp", "gvn", "licm", "dce", "sccp", "dce"], names);
+        assert!(messages[0].ends_with("1 calls inlined"), "{}", messages[0]);
+    }
+
+    #[test]
+    pub fn diagnostics_stop_the_compilation() {
+        let (output, messages) = compile_str("let x = 1;\ny + 1", &[]);
+        assert_eq!(None, output);
+        assert_eq!(1, messages.len());
+        assert!(messages[0].starts_with("test.why:2:1: error: "), "{}", messages[0]);
+
+        let (output, messages) = compile_str("1 +", &[]);
+        assert_eq!(None, output);
+        assert_eq!(vec!["test.why:1:4: error: unexpected end of file".to_string()], messages);
+    }
+}
@@ -0,0 +1,443 @@
+//! Writes ELF64 relocatable object files for x86-64, which the system linker can link with C code
+//!
+//! An object holds the code in `.text`, constants in `.rodata` and anything the linker has to fill in addresses of in
+//! `.data`, and can hold DWARF debug information in sections that aren't loaded. Symbols a section refers to but no
+//! section defines are left undefined for the linker to find.
+
+/// What a section holds, which decides how it's loaded
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SectionKind {
+    Text,
+    Data,
+    ReadOnly,
+
+    /// Debug information, which isn't loaded with the program
+    Debug,
+}
+
+/// How a relocation computes what it writes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RelocationKind {
+    /// The 64-bit address of the target
+    Abs64,
+
+    /// The 32-bit address of the target, which debug information uses for offsets into other sections
+    Abs32,
+
+    /// The 32-bit distance from the relocation to the target
+    Pc32,
+
+    /// Like `Pc32`, but the linker can go through a stub if the target is a function in a shared library
+    Plt32,
+}
+
+impl RelocationKind {
+    fn code(self) -> u64 {
+        match self {
+            RelocationKind::Abs64 => 1,
+            RelocationKind::Pc32 => 2,
+            RelocationKind::Plt32 => 4,
+            RelocationKind::Abs32 => 10,
+        }
+    }
+}
+
+/// What a relocation refers to
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Target {
+    /// A symbol by name, which is undefined if no section defines it
+    Symbol(String),
+
+    /// The start of a section of the object, by its position
+    Section(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Relocation {
+    /// Where in the section the address goes
+    pub offset: u64,
+    pub target: Target,
+    pub kind: RelocationKind,
+    pub addend: i64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Section {
+    pub name: String,
+    pub kind: SectionKind,
+    pub align: u64,
+    pub bytes: Vec<u8>,
+    pub relocations: Vec<Relocation>,
+}
+
+/// A symbol an object defines
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub section: usize,
+    pub offset: u64,
+    pub size: u64,
+
+    /// Whether the symbol is a function rather than data
+    pub function: bool,
+
+    /// Whether other objects can refer to the symbol
+    pub global: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ObjectFile {
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+
+    /// Symbols defined elsewhere that are undefined in the object even if nothing refers to them
+    pub imports: Vec<String>,
+}
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+
+/// A string table, which starts with an empty string
+struct Strings {
+    bytes: Vec<u8>,
+}
+
+impl Strings {
+    fn new() -> Strings {
+        Strings { bytes: vec![0] }
+    }
+
+    fn add(&mut self, s: &str) -> u32 {
+        let at = self.bytes.len() as u32;
+        self.bytes.extend_from_slice(s.as_bytes());
+        self.bytes.push(0);
+        at
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    typ: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+/// An entry of the symbol table
+struct Entry {
+    name: u32,
+    info: u8,
+    section: u16,
+    value: u64,
+    size: u64,
+}
+
+/// Writes a number in little-endian order, as are the rest of these
+pub fn put16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+}
+
+pub fn put32(out: &mut Vec<u8>, value: u32) {
+    put16(out, value as u16);
+    put16(out, (value >> 16) as u16);
+}
+
+pub fn put64(out: &mut Vec<u8>, value: u64) {
+    put32(out, value as u32);
+    put32(out, (value >> 32) as u32);
+}
+
+impl ObjectFile {
+    pub fn new() -> ObjectFile {
+        ObjectFile::default()
+    }
+
+    /// Adds a section, returning its position
+    pub fn add_section(&mut self, name: &str, kind: SectionKind, align: u64) -> usize {
+        self.sections.push(Section { name: name.into(), kind, align, bytes: Vec::new(), relocations: Vec::new() });
+        self.sections.len() - 1
+    }
+
+    /// Gets the names of the symbols the object refers to but doesn't define
+    pub fn undefined_symbols(&self) -> Vec<&str> {
+        let targets = self.sections.iter().flat_map(|s| s.relocations.iter()).filter_map(|r| match r.target {
+            Target::Symbol(ref name) => Some(name),
+            Target::Section(_) => None,
+        });
+        let mut undefined: Vec<&str> = Vec::new();
+        for name in self.imports.iter().chain(targets) {
+            if !self.symbols.iter().any(|s| s.name == *name) && !undefined.contains(&name.as_str()) {
+                undefined.push(name);
+            }
+        }
+        undefined
+    }
+
+    /// Lays the object out as the bytes of an ELF file
+    ///
+    /// The file is the header, then the contents of each section aligned as it asks, then the section headers. The
+    /// symbol table starts with an entry for each section the relocations can refer to, since ELF needs the local
+    /// symbols first, followed by the defined symbols and then the undefined ones.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut names = Strings::new();
+        let mut strings = Strings::new();
+        let mut headers: Vec<SectionHeader> = Vec::new();
+        let mut out = vec![0; 64];
+
+        // The null section comes first, so the object's sections are numbered from 1
+        headers.push(SectionHeader {
+            name: 0, typ: 0, flags: 0, offset: 0, size: 0, link: 0, info: 0, align: 0, entsize: 0,
+        });
+        for section in &self.sections {
+            let flags = match section.kind {
+                SectionKind::Text => SHF_ALLOC | SHF_EXECINSTR,
+                SectionKind::Data => SHF_ALLOC | SHF_WRITE,
+                SectionKind::ReadOnly => SHF_ALLOC,
+                SectionKind::Debug => 0,
+            };
+            let offset = align(&mut out, section.align);
+            out.extend_from_slice(&section.bytes);
+            headers.push(SectionHeader {
+                name: names.add(&section.name),
+                typ: SHT_PROGBITS,
+                flags,
+                offset,
+                size: section.bytes.len() as u64,
+                link: 0,
+                info: 0,
+                align: section.align,
+                entsize: 0,
+            });
+        }
+
+        let mut entries = vec![Entry { name: 0, info: 0, section: 0, value: 0, size: 0 }];
+        for i in 0..self.sections.len() {
+            entries.push(Entry { name: 0, info: STB_LOCAL << 4 | STT_SECTION, section: i as u16 + 1, value: 0, size: 0 });
+        }
+        let mut sorted: Vec<&Symbol> = self.symbols.iter().filter(|s| !s.global).collect();
+        sorted.extend(self.symbols.iter().filter(|s| s.global));
+        let first_global = entries.len() + sorted.iter().filter(|s| !s.global).count();
+        let mut indices: Vec<(&str, usize)> = Vec::new();
+        for symbol in sorted {
+            indices.push((&symbol.name, entries.len()));
+            let bind = if symbol.global { STB_GLOBAL } else { STB_LOCAL };
+            let typ = if symbol.function { STT_FUNC } else { STT_OBJECT };
+            entries.push(Entry {
+                name: strings.add(&symbol.name),
+                info: bind << 4 | typ,
+                section: symbol.section as u16 + 1,
+                value: symbol.offset,
+                size: symbol.size,
+            });
+        }
+        for name in self.undefined_symbols() {
+            indices.push((name, entries.len()));
+            let name = strings.add(name);
+            entries.push(Entry { name, info: STB_GLOBAL << 4 | STT_NOTYPE, section: 0, value: 0, size: 0 });
+        }
+
+        let symtab = headers.len() + self.sections.iter().filter(|s| !s.relocations.is_empty()).count();
+        for (i, section) in self.sections.iter().enumerate().filter(|&(_, s)| !s.relocations.is_empty()) {
+            let offset = align(&mut out, 8);
+            for relocation in &section.relocations {
+                let symbol = match relocation.target {
+                    Target::Symbol(ref name) => indices.iter().find(|&&(n, _)| n == name).unwrap().1,
+                    Target::Section(s) => s + 1,
+                };
+                put64(&mut out, relocation.offset);
+                put64(&mut out, (symbol as u64) << 32 | relocation.kind.code());
+                put64(&mut out, relocation.addend as u64);
+            }
+            headers.push(SectionHeader {
+                name: names.add(&format!(".rela{}", section.name)),
+                typ: SHT_RELA,
+                flags: SHF_INFO_LINK,
+                offset,
+                size: 24 * section.relocations.len() as u64,
+                link: symtab as u32,
+                info: i as u32 + 1,
+                align: 8,
+                entsize: 24,
+            });
+        }
+
+        let offset = align(&mut out, 8);
+        for entry in &entries {
+            put32(&mut out, entry.name);
+            out.push(entry.info);
+            out.push(0);
+            put16(&mut out, entry.section);
+            put64(&mut out, entry.value);
+            put64(&mut out, entry.size);
+        }
+        headers.push(SectionHeader {
+            name: names.add(".symtab"),
+            typ: SHT_SYMTAB,
+            flags: 0,
+            offset,
+            size: 24 * entries.len() as u64,
+            link: symtab as u32 + 1,
+            info: first_global as u32,
+            align: 8,
+            entsize: 24,
+        });
+
+        let offset = out.len() as u64;
+        out.extend_from_slice(&strings.bytes);
+        headers.push(SectionHeader {
+            name: names.add(".strtab"),
+            typ: SHT_STRTAB,
+            flags: 0,
+            offset,
+            size: strings.bytes.len() as u64,
+            link: 0,
+            info: 0,
+            align: 1,
+            entsize: 0,
+        });
+
+        // Without this the linker assumes the code needs an executable stack
+        headers.push(SectionHeader {
+            name: names.add(".note.GNU-stack"),
+            typ: SHT_PROGBITS,
+            flags: 0,
+            offset: out.len() as u64,
+            size: 0,
+            link: 0,
+            info: 0,
+            align: 1,
+            entsize: 0,
+        });
+
+        let name = names.add(".shstrtab");
+        let offset = out.len() as u64;
+        out.extend_from_slice(&names.bytes);
+        headers.push(SectionHeader {
+            name,
+            typ: SHT_STRTAB,
+            flags: 0,
+            offset,
+            size: names.bytes.len() as u64,
+            link: 0,
+            info: 0,
+            align: 1,
+            entsize: 0,
+        });
+
+        let section_headers = align(&mut out, 8);
+        for header in &headers {
+            put32(&mut out, header.name);
+            put32(&mut out, header.typ);
+            put64(&mut out, header.flags);
+            put64(&mut out, 0);
+            put64(&mut out, header.offset);
+            put64(&mut out, header.size);
+            put32(&mut out, header.link);
+            put32(&mut out, header.info);
+            put64(&mut out, header.align);
+            put64(&mut out, header.entsize);
+        }
+
+        let mut header = Vec::with_capacity(64);
+        // The magic number, then 64-bit, little endian, version 1 and the System V ABI
+        header.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        put16(&mut header, 1); // A relocatable file
+        put16(&mut header, 62); // For x86-64
+        put32(&mut header, 1);
+        put64(&mut header, 0); // No entry point
+        put64(&mut header, 0); // No program headers
+        put64(&mut header, section_headers);
+        put32(&mut header, 0);
+        put16(&mut header, 64);
+        put16(&mut header, 0);
+        put16(&mut header, 0);
+        put16(&mut header, 64);
+        put16(&mut header, headers.len() as u16);
+        put16(&mut header, headers.len() as u16 - 1);
+        out[..64].copy_from_slice(&header);
+        out
+    }
+}
+
+/// Pads the file to a multiple of `align` bytes, returning where the next thing goes
+fn align(out: &mut Vec<u8>, align: u64) -> u64 {
+    let align = align.max(1) as usize;
+    while out.len() % align != 0 {
+        out.push(0);
+    }
+    out.len() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use elf::{ObjectFile, Relocation, RelocationKind, SectionKind, Symbol, Target};
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        bytes[at] as u16 | (bytes[at + 1] as u16) << 8
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        (0..8).map(|i| (bytes[at + i] as u64) << (8 * i)).sum()
+    }
+
+    fn object() -> ObjectFile {
+        let mut object = ObjectFile::new();
+        let text = object.add_section(".text", SectionKind::Text, 16);
+        // call puts; ret
+        object.sections[text].bytes = vec![0xe8, 0, 0, 0, 0, 0xc3];
+        object.sections[text].relocations.push(Relocation {
+            offset: 1,
+            target: Target::Symbol("puts".into()),
+            kind: RelocationKind::Plt32,
+            addend: -4,
+        });
+        object.symbols.push(Symbol { name: "f".into(), section: text, offset: 0, size: 6, function: true, global: true });
+        object
+    }
+
+    #[test]
+    pub fn symbols_nothing_defines_are_undefined() {
+        let mut object = object();
+        object.imports = vec!["abs".into(), "puts".into(), "f".into()];
+        assert_eq!(vec!["abs", "puts"], object.undefined_symbols());
+    }
+
+    #[test]
+    pub fn objects_have_an_elf_header_and_section_headers() {
+        let bytes = object().to_bytes();
+        assert_eq!(b"\x7fELF", &bytes[..4]);
+        // A relocatable x86-64 file
+        assert_eq!(1, u16_at(&bytes, 16));
+        assert_eq!(62, u16_at(&bytes, 18));
+
+        let section_headers = u64_at(&bytes, 40) as usize;
+        let count = u16_at(&bytes, 60) as usize;
+        // The null section, `.text`, `.rela.text`, `.symtab`, `.strtab`, `.note.GNU-stack` and `.shstrtab`
+        assert_eq!(7, count);
+        assert_eq!(6, u16_at(&bytes, 62));
+        assert_eq!(bytes.len(), section_headers + 64 * count);
+
+        // `.text` holds the code where its header says
+        let text = section_headers + 64;
+        let offset = u64_at(&bytes, text + 24) as usize;
+        assert_eq!(&[0xe8, 0, 0, 0, 0, 0xc3], &bytes[offset..offset + 6]);
+    }
+}
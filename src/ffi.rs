@@ -0,0 +1,95 @@
+//! Finds C functions in shared libraries, so `extern`s can be run without linking a program
+//!
+//! Libraries are opened with `dlopen` and functions found with `dlsym`. The JIT calls what it finds from compiled
+//! code, with the same convention as native executables.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlclose(handle: *mut c_void) -> c_int;
+    fn dlerror() -> *mut c_char;
+}
+
+const RTLD_NOW: c_int = 2;
+
+/// Gets the message for the last `dl` function that failed
+fn last_error() -> String {
+    unsafe {
+        let message = dlerror();
+        if message.is_null() {
+            "unknown error".into()
+        } else {
+            CStr::from_ptr(message).to_string_lossy().into_owned()
+        }
+    }
+}
+
+/// A shared library opened with `dlopen`, which is closed when it's dropped
+#[derive(Debug)]
+pub struct Library {
+    handle: *mut c_void,
+}
+
+impl Library {
+    /// Opens a library by the name or path `dlopen` looks for, or the program itself and everything it's linked
+    /// against if there's no name
+    pub fn open(name: Option<&str>) -> Result<Library, String> {
+        let name = match name {
+            Some(name) => Some(CString::new(name).map_err(|_| format!("the library name `{}` has a NUL", name))?),
+            None => None,
+        };
+        let handle = unsafe { dlopen(name.as_ref().map_or(ptr::null(), |n| n.as_ptr()), RTLD_NOW) };
+        if handle.is_null() {
+            Err(last_error())
+        } else {
+            Ok(Library { handle })
+        }
+    }
+
+    /// Opens the C library
+    pub fn libc() -> Result<Library, String> {
+        Library::open(Some("libc.so.6"))
+    }
+
+    /// Finds the address of a function
+    pub fn symbol(&self, name: &str) -> Result<*const c_void, String> {
+        let symbol = CString::new(name).map_err(|_| format!("the symbol `{}` has a NUL", name))?;
+        let address = unsafe { dlsym(self.handle, symbol.as_ptr()) };
+        if address.is_null() {
+            Err(format!("can't find `{}`: {}", name, last_error()))
+        } else {
+            Ok(address)
+        }
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe {
+            dlclose(self.handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn functions_are_found_in_libraries() {
+        let libc = Library::libc().unwrap();
+        let strlen = libc.symbol("strlen").unwrap();
+        let strlen: unsafe extern "C" fn(*const c_char) -> usize = unsafe { ::std::mem::transmute(strlen) };
+        let hello = CString::new("hello").unwrap();
+        assert_eq!(5, unsafe { strlen(hello.as_ptr()) });
+
+        let missing = libc.symbol("no_such_function");
+        assert!(missing.unwrap_err().contains("can't find"));
+        assert!(libc.symbol("str\0len").is_err());
+        assert!(Library::open(Some("libnothing.so")).is_err());
+    }
+}
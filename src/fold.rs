@@ -0,0 +1,292 @@
+use arith::{int_binary, ArithError, OverflowMode};
+use bignum::Integer;
+use diagnostics::Diagnostic;
+use parser::{BinOp, Block, Decl, DefDecl, Expr, ExprKind, FieldInit, ImplDecl, LetStmt, Lit, MatchArm, Program, Stmt};
+use text::TextSpan;
+
+/// A program with its constant expressions folded
+pub struct Folded {
+    program: Program,
+    diags: Vec<Diagnostic>,
+}
+
+impl Folded {
+    pub fn program(&self) -> &Program { &self.program }
+    pub fn into_program(self) -> Program { self.program }
+
+    /// The constant operations that would fail when the program runs, like dividing by zero
+    pub fn diagnostics(&self) -> &[Diagnostic] { &self.diags }
+}
+
+/// Evaluates arithmetic and comparisons on `Int` constants, and removes operations that don't change their operand,
+/// like `x + 0` and `x * 1`
+///
+/// `x * 0` is only replaced by `0` when evaluating `x` has no effect and can't fail. A folded expression has the span of
+/// the expression it replaced, and a simplified one the span of the operand that's left.
+///
+/// The pass assumes the program is well typed, since it's the types that make `x + 0` an addition of integers.
+/// Arithmetic follows the overflow mode the program is compiled with. An operation on constants that would trap is
+/// reported at its operator, and left for the program to fail on.
+pub fn fold_program(program: &Program, mode: OverflowMode) -> Folded {
+    let mut folder = Folder {
+        mode,
+        diags: Vec::new(),
+    };
+    let stmts = program.stmts().iter().map(|s| folder.stmt(s)).collect();
+    let result = program.result().map(|e| folder.expr(e));
+    Folded {
+        program: Program::new(stmts, result).with_attrs(program.attrs().to_vec()),
+        diags: folder.diags,
+    }
+}
+
+/// Folds a single expression, like `fold_program`, but without reporting problems with its constants
+pub fn fold_expr(expr: &Expr, mode: OverflowMode) -> Expr {
+    let mut folder = Folder {
+        mode,
+        diags: Vec::new(),
+    };
+    folder.expr(expr)
+}
+
+struct Folder {
+    mode: OverflowMode,
+    diags: Vec<Diagnostic>,
+}
+
+impl Folder {
+    fn stmt(&mut self, stmt: &Stmt) -> Stmt {
+        match stmt {
+            &Stmt::Decl(Decl::Def(ref d)) => Stmt::Decl(Decl::Def(self.def(d))),
+            &Stmt::Decl(Decl::Impl(ref i)) => {
+                let methods = i.methods().iter().map(|m| self.def(m)).collect();
+                Stmt::Decl(Decl::Impl(ImplDecl::new(i.span(), i.trait_name().clone(), i.ty().clone(), methods)))
+            }
+            &Stmt::Decl(ref d) => Stmt::Decl(d.clone()),
+            &Stmt::Let(ref l) => Stmt::Let(LetStmt::new(l.span(), l.pattern().clone(), l.ty().cloned(), self.expr(l.value()))),
+            &Stmt::Expr(ref e) => Stmt::Expr(self.expr(e)),
+        }
+    }
+
+    fn def(&mut self, def: &DefDecl) -> DefDecl {
+        DefDecl::new(def.span(), def.name().clone(), def.type_params().to_vec(), def.params().to_vec(), def.ret().cloned(),
+                     self.block(def.body()))
+            .with_attrs(def.attrs().to_vec())
+    }
+
+    fn block(&mut self, block: &Block) -> Block {
+        let stmts = block.stmts().iter().map(|s| self.stmt(s)).collect();
+        let result = block.result().map(|e| self.expr(e));
+        Block::new(block.span(), stmts, result)
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Expr {
+        let kind = match expr.kind() {
+            &ExprKind::Constant(Lit::Int(Integer::Big(_))) if self.mode != OverflowMode::Promote => {
+                self.diags.push(Diagnostic::error(expr.span(), "integer literal is too large")
+                    .with_label(expr.span(), format!("an `Int` is at most {}", i64::max_value())));
+                return expr.clone();
+            }
+            &ExprKind::Constant(_) | &ExprKind::Var(_) => return expr.clone(),
+            &ExprKind::Binary(ref l, ref r, op, op_span) => {
+                let l = self.expr(l);
+                let r = self.expr(r);
+                return self.binary(expr.span(), l, r, op, op_span);
+            }
+            &ExprKind::Struct(ref name, ref fields) => {
+                let fields = fields.iter().map(|f| FieldInit::new(f.name().clone(), self.expr(f.value()))).collect();
+                ExprKind::Struct(name.clone(), fields)
+            }
+            &ExprKind::Field(ref target, ref name) => ExprKind::Field(Box::new(self.expr(target)), name.clone()),
+            &ExprKind::Assign(ref target, ref value) => ExprKind::Assign(Box::new(self.expr(target)), Box::new(self.expr(value))),
+            &ExprKind::Call(ref callee, ref args) => {
+                ExprKind::Call(Box::new(self.expr(callee)), args.iter().map(|a| self.expr(a)).collect())
+            }
+            &ExprKind::MethodCall(ref receiver, ref name, ref args) => {
+                ExprKind::MethodCall(Box::new(self.expr(receiver)), name.clone(), args.iter().map(|a| self.expr(a)).collect())
+            }
+            &ExprKind::Match(ref scrutinee, ref arms) => {
+                let arms = arms.iter()
+                    .map(|a| MatchArm::new(a.pattern().clone(), a.guard().map(|g| self.expr(g)), self.expr(a.body())))
+                    .collect();
+                ExprKind::Match(Box::new(self.expr(scrutinee)), arms)
+            }
+            &ExprKind::Tuple(ref elements) => ExprKind::Tuple(elements.iter().map(|e| self.expr(e)).collect()),
+            &ExprKind::Block(ref block) => ExprKind::Block(self.block(block)),
+            &ExprKind::Return(ref value) => ExprKind::Return(value.as_ref().map(|v| Box::new(self.expr(v)))),
+        };
+        Expr::new(expr.span(), kind)
+    }
+
+    fn binary(&mut self, span: TextSpan, l: Expr, r: Expr, op: BinOp, op_span: TextSpan) -> Expr {
+        match (constant(&l), constant(&r)) {
+            (Some(&Lit::Int(ref a)), Some(&Lit::Int(ref b))) => {
+                if let Some(value) = self.evaluate(a, b, op, op_span, &r) {
+                    return Expr::constant(span, value);
+                }
+            }
+            (Some(&Lit::Bool(a)), Some(&Lit::Bool(b))) if op == BinOp::Equal || op == BinOp::NotEqual => {
+                return Expr::constant(span, (a == b) == (op == BinOp::Equal));
+            }
+            (_, Some(&Lit::Int(Integer::Small(0)))) if op == BinOp::Add || op == BinOp::Subtract => return l,
+            (Some(&Lit::Int(Integer::Small(0))), _) if op == BinOp::Add => return r,
+            (_, Some(&Lit::Int(Integer::Small(1)))) if op == BinOp::Multiply || op == BinOp::Divide || op == BinOp::Power => {
+                return l;
+            }
+            (Some(&Lit::Int(Integer::Small(1))), _) if op == BinOp::Multiply => return r,
+            (_, Some(&Lit::Int(Integer::Small(0)))) if op == BinOp::Multiply && is_pure(&l) => return Expr::constant(span, 0),
+            (Some(&Lit::Int(Integer::Small(0))), _) if op == BinOp::Multiply && is_pure(&r) => return Expr::constant(span, 0),
+            _ => {}
+        }
+        Expr::binary(l, r, op, op_span)
+    }
+
+    /// Applies the operator to two integers, reporting it if the result can't be computed
+    fn evaluate(&mut self, a: &Integer, b: &Integer, op: BinOp, op_span: TextSpan, rhs: &Expr) -> Option<Lit> {
+        if op.is_comparison() {
+            return Some(Lit::Bool(match op {
+                BinOp::Equal => a == b,
+                BinOp::NotEqual => a != b,
+                BinOp::Less => a < b,
+                BinOp::LessEqual => a <= b,
+                BinOp::Greater => a > b,
+                _ => a >= b,
+            }));
+        }
+
+        match int_binary(self.mode, op, a, b) {
+            Ok(result) => Some(Lit::Int(result)),
+
+            // A literal too large for the mode has already been reported
+            Err(_) if self.mode != OverflowMode::Promote && (a.to_i64().is_none() || b.to_i64().is_none()) => None,
+            Err(ArithError::DivideByZero) => {
+                self.diags.push(Diagnostic::error(op_span, "this operation will divide by zero")
+                    .with_label(rhs.span(), "the divisor is zero"));
+                None
+            }
+            Err(ArithError::Overflow(_)) => {
+                self.diags.push(Diagnostic::error(op_span, "this arithmetic operation will overflow")
+                    .with_label(op_span, format!("attempt to compute `{} {} {}`, which would overflow", a, op.symbol(), b)));
+                None
+            }
+            Err(ArithError::NegativeExponent) => {
+                self.diags.push(Diagnostic::error(op_span, "this operation will raise an integer to a negative power")
+                    .with_label(rhs.span(), "the exponent is negative"));
+                None
+            }
+        }
+    }
+}
+
+fn constant(expr: &Expr) -> Option<&Lit> {
+    match expr.kind() {
+        &ExprKind::Constant(ref lit) => Some(lit),
+        _ => None,
+    }
+}
+
+/// Returns `true` if evaluating the expression has no effect and can't fail, so it can be removed
+///
+/// Arithmetic isn't pure, since it can overflow or divide by zero.
+fn is_pure(expr: &Expr) -> bool {
+    match expr.kind() {
+        &ExprKind::Constant(_) | &ExprKind::Var(_) => true,
+        &ExprKind::Binary(ref l, ref r, op, _) => op.is_comparison() && is_pure(l) && is_pure(r),
+        &ExprKind::Field(ref target, _) => is_pure(target),
+        &ExprKind::Struct(_, ref fields) => fields.iter().all(|f| is_pure(f.value())),
+        &ExprKind::Tuple(ref elements) => elements.iter().all(is_pure),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arith::OverflowMode;
+    use diagnostics::Diagnostic;
+    use fold::fold_program;
+    use parser::{Decl, Expr, ExprKind, Ident, Stmt};
+    use testing::{parse, span_of};
+    use text::TextSpan;
+
+    /// Folds the program and returns its final expression
+    fn fold(code: &str) -> Expr {
+        let folded = fold_program(&parse(code), OverflowMode::Trap);
+        assert_eq!(Vec::<Diagnostic>::new(), folded.diagnostics().to_vec());
+        folded.program().result().unwrap().clone()
+    }
+
+    #[test]
+    pub fn folds_constant_arithmetic() {
+        assert_eq!(Expr::constant(TextSpan::new(0, 9), 7), fold("1 + 2 * 3"));
+        assert_eq!(Expr::constant(TextSpan::new(1, 11), -1), fold("(1 - 4) / 2"));
+        assert_eq!(Expr::constant(TextSpan::new(0, 10), true), fold("1 + 1 == 2"));
+        assert_eq!(Expr::constant(TextSpan::new(0, 13), true), fold("true != false"));
+    }
+
+    #[test]
+    pub fn simplifies_identities() {
+        let code = "let x = 5; x * 1 + 0";
+        assert_eq!(Expr::var(Ident::new(TextSpan::new(11, 12), "x")), fold(code));
+        assert_eq!(Expr::constant(TextSpan::new(11, 16), 0), fold("let x = 5; x * 0"));
+
+        let code = "def f() { 1 } f() * 0";
+        assert_eq!(parse(code).result().unwrap().clone(), fold(code));
+    }
+
+    #[test]
+    pub fn folds_function_bodies() {
+        let code = "def f(x) { x + 2 * 3 } 0";
+        let folded = fold_program(&parse(code), OverflowMode::Trap);
+        let body = match folded.program().stmts()[0] {
+            Stmt::Decl(Decl::Def(ref d)) => d.body().result().unwrap().clone(),
+            _ => panic!("Expected a def"),
+        };
+        match body.kind() {
+            &ExprKind::Binary(_, ref r, _, _) => assert_eq!(&Expr::constant(span_of(code, "2 * 3"), 6), &**r),
+            _ => panic!("Expected a binary expression"),
+        }
+    }
+
+    #[test]
+    pub fn constant_division_by_zero_is_reported() {
+        let code = "1 / (2 - 2)";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "/"), "this operation will divide by zero")
+                .with_label(span_of(code, "2 - 2"), "the divisor is zero"),
+        ], fold_program(&parse(code), OverflowMode::Trap).diagnostics().to_vec());
+    }
+
+    #[test]
+    pub fn constant_overflow_is_reported() {
+        let code = "9223372036854775807 + 1";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "+"), "this arithmetic operation will overflow")
+                .with_label(span_of(code, "+"), "attempt to compute `9223372036854775807 + 1`, which would overflow"),
+        ], fold_program(&parse(code), OverflowMode::Trap).diagnostics().to_vec());
+    }
+
+    #[test]
+    pub fn constant_overflow_follows_the_mode() {
+        let code = "9223372036854775807 + 1";
+        let folded = fold_program(&parse(code), OverflowMode::Wrap);
+        assert_eq!(Vec::<Diagnostic>::new(), folded.diagnostics().to_vec());
+        assert_eq!(Some(&Expr::constant(TextSpan::new(0, 23), ::std::i64::MIN)), folded.program().result());
+
+        let folded = fold_program(&parse(code), OverflowMode::Saturate);
+        assert_eq!(Some(&Expr::constant(TextSpan::new(0, 23), ::std::i64::MAX)), folded.program().result());
+    }
+
+    #[test]
+    pub fn large_integers_need_the_promote_mode() {
+        let code = "2 ** 200 / 2 ** 199 + 100000000000000000000 / 10000000000000000000";
+        let folded = fold_program(&parse(code), OverflowMode::Promote);
+        assert_eq!(Vec::<Diagnostic>::new(), folded.diagnostics().to_vec());
+        assert_eq!(Some(&Expr::constant(TextSpan::new(0, code.len()), 12)), folded.program().result());
+
+        let code = "100000000000000000000 + 1";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "100000000000000000000"), "integer literal is too large")
+                .with_label(span_of(code, "100000000000000000000"), "an `Int` is at most 9223372036854775807"),
+        ], fold_program(&parse(code), OverflowMode::Trap).diagnostics().to_vec());
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+
+use arith::OverflowMode;
+use bignum::Integer;
+use cfg::{reachable, reverse_postorder, BlockId, Graph};
+use parser::BinOp;
+use text::TextSpan;
+
+/// The type of a value in the IR, which says how it's stored rather than what it means
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Ty {
+    Unit,
+    Int,
+    Float,
+    Bool,
+    Str,
+
+    /// A pointer to a heap object made by `new`: a struct, tuple, enum value or `dyn` value
+    Ptr,
+
+    /// A function, made by `func`
+    Fn,
+}
+
+impl Ty {
+    pub fn name(self) -> &'static str {
+        match self {
+            Ty::Unit => "unit",
+            Ty::Int => "int",
+            Ty::Float => "float",
+            Ty::Bool => "bool",
+            Ty::Str => "str",
+            Ty::Ptr => "ptr",
+            Ty::Fn => "fn",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Ty> {
+        [Ty::Unit, Ty::Int, Ty::Float, Ty::Bool, Ty::Str, Ty::Ptr, Ty::Fn].iter().find(|t| t.name() == name).cloned()
+    }
+
+    /// Returns `true` for types whose values point into the heap
+    pub fn is_reference(self) -> bool {
+        self == Ty::Str || self == Ty::Ptr
+    }
+}
+
+/// A value defined by a parameter or instruction, which is never redefined
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+impl Value {
+    pub fn index(self) -> usize { self.0 as usize }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Const {
+    Unit,
+    Int(Integer),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+// Floats come from literals and folding, which never produce NaN
+impl Eq for Const {}
+
+impl Const {
+    pub fn ty(&self) -> Ty {
+        match self {
+            &Const::Unit => Ty::Unit,
+            &Const::Int(_) => Ty::Int,
+            &Const::Float(_) => Ty::Float,
+            &Const::Bool(_) => Ty::Bool,
+            &Const::Str(_) => Ty::Str,
+        }
+    }
+}
+
+/// The function a call runs
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Callee {
+    /// A function or extern of the module, by name
+    Direct(String),
+
+    /// A function value
+    Indirect(Value),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum InstKind {
+    Const(Const),
+
+    /// Applies an operator to two values of the same type. Arithmetic on `int`s handles overflow as the module says
+    Binary(BinOp, Value, Value),
+
+    Call(Callee, Vec<Value>),
+
+    /// Allocates a heap object holding the values. An enum value holds the index of its variant, then its fields, and a
+    /// `dyn` value holds the value it was made from, then its vtable
+    New(Vec<Value>),
+
+    /// Reads a field of a heap object, or a function from a vtable
+    Get(Value, u32),
+
+    /// Writes a field of a heap object, producing `unit`
+    Set(Value, u32, Value),
+
+    /// Takes the value from whichever predecessor control came from. Phis come before any other instruction in their
+    /// block
+    Phi(Vec<(BlockId, Value)>),
+
+    /// Refers to a function of the module, by name
+    Func(String),
+
+    /// Refers to a vtable of the module, by index
+    VTable(u32),
+}
+
+impl InstKind {
+    /// Gets every value the instruction reads, in order
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            &InstKind::Const(_) | &InstKind::Func(_) | &InstKind::VTable(_) => Vec::new(),
+            &InstKind::Binary(_, a, b) | &InstKind::Set(a, _, b) => vec![a, b],
+            &InstKind::Call(ref callee, ref args) => {
+                let mut operands = match callee {
+                    &Callee::Indirect(f) => vec![f],
+                    &Callee::Direct(_) => Vec::new(),
+                };
+                operands.extend(args.iter().cloned());
+                operands
+            }
+            &InstKind::New(ref fields) => fields.clone(),
+            &InstKind::Get(object, _) => vec![object],
+            &InstKind::Phi(ref incoming) => incoming.iter().map(|&(_, v)| v).collect(),
+        }
+    }
+
+    /// Replaces every value the instruction reads
+    pub fn map_operands<F: FnMut(Value) -> Value>(&mut self, mut f: F) {
+        match self {
+            &mut InstKind::Const(_) | &mut InstKind::Func(_) | &mut InstKind::VTable(_) => {}
+            &mut InstKind::Binary(_, ref mut a, ref mut b) | &mut InstKind::Set(ref mut a, _, ref mut b) => {
+                *a = f(*a);
+                *b = f(*b);
+            }
+            &mut InstKind::Call(ref mut callee, ref mut args) => {
+                if let &mut Callee::Indirect(ref mut v) = callee {
+                    *v = f(*v);
+                }
+                for arg in args.iter_mut() {
+                    *arg = f(*arg);
+                }
+            }
+            &mut InstKind::New(ref mut fields) => {
+                for field in fields.iter_mut() {
+                    *field = f(*field);
+                }
+            }
+            &mut InstKind::Get(ref mut object, _) => *object = f(*object),
+            &mut InstKind::Phi(ref mut incoming) => {
+                for &mut (_, ref mut v) in incoming.iter_mut() {
+                    *v = f(*v);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Inst {
+    pub value: Value,
+    pub ty: Ty,
+    pub kind: InstKind,
+
+    /// The source of an instruction that can stop the program, to report where it stopped, or of a call, for debug
+    /// information
+    pub span: Option<TextSpan>,
+}
+
+impl Inst {
+    pub fn new(value: Value, ty: Ty, kind: InstKind) -> Inst {
+        Inst { value, ty, kind, span: None }
+    }
+
+    pub fn with_span(mut self, span: TextSpan) -> Inst {
+        self.span = Some(span);
+        self
+    }
+
+    /// Returns `true` if running the instruction can do more than produce its value, so it can't be removed even if
+    /// its value is unused: calls, writes, and `int` arithmetic, which can stop the program
+    pub fn has_effects(&self) -> bool {
+        match self.kind {
+            InstKind::Call(..) | InstKind::Set(..) => true,
+            InstKind::Binary(op, _, _) => !op.is_comparison() && self.ty == Ty::Int,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Terminator {
+    Jump(BlockId),
+
+    /// Continues at the first block if the `bool` is true, and at the second otherwise
+    Branch(Value, BlockId, BlockId),
+
+    Return(Value),
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            &Terminator::Jump(target) => vec![target],
+            &Terminator::Branch(_, then, otherwise) => vec![then, otherwise],
+            &Terminator::Return(_) | &Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            &Terminator::Branch(v, _, _) | &Terminator::Return(v) => vec![v],
+            &Terminator::Jump(_) | &Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn map_operands<F: FnMut(Value) -> Value>(&mut self, mut f: F) {
+        match self {
+            &mut Terminator::Branch(ref mut v, _, _) | &mut Terminator::Return(ref mut v) => *v = f(*v),
+            &mut Terminator::Jump(_) | &mut Terminator::Unreachable => {}
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+impl Block {
+    pub fn new() -> Block {
+        Block { insts: Vec::new(), terminator: Terminator::Unreachable }
+    }
+}
+
+/// A variable of the source and the value it was declared with, for debug information
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Variable {
+    pub name: String,
+    pub value: Value,
+
+    /// Where the variable is declared
+    pub span: TextSpan,
+}
+
+/// A function in SSA form, whose first block is its entry
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<(Value, Ty)>,
+    pub ret: Ty,
+    pub blocks: Vec<Block>,
+
+    /// The source the function was lowered from, for debug information
+    pub span: Option<TextSpan>,
+
+    /// The variables declared in the function, for debug information. A variable's value can be removed by
+    /// optimization, and then it has none
+    pub variables: Vec<Variable>,
+}
+
+impl Function {
+    pub fn new<S: Into<String>>(name: S, params: Vec<(Value, Ty)>, ret: Ty) -> Function {
+        Function { name: name.into(), params, ret, blocks: Vec::new(), span: None, variables: Vec::new() }
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block { &self.blocks[id.index()] }
+    pub fn block_mut(&mut self, id: BlockId) -> &mut Block { &mut self.blocks[id.index()] }
+
+    /// Gets one more than the largest value defined in the function, so values can index a table
+    pub fn value_bound(&self) -> usize {
+        let params = self.params.iter().map(|&(v, _)| v.index() + 1);
+        let insts = self.blocks.iter().flat_map(|b| b.insts.iter()).map(|i| i.value.index() + 1);
+        params.chain(insts).max().unwrap_or(0)
+    }
+
+    /// Gets the type of every value, indexed by value
+    pub fn value_types(&self) -> Vec<Option<Ty>> {
+        let mut types = vec![None; self.value_bound()];
+        for &(v, ty) in &self.params {
+            types[v.index()] = Some(ty);
+        }
+        for inst in self.blocks.iter().flat_map(|b| b.insts.iter()) {
+            types[inst.value.index()] = Some(inst.ty);
+        }
+        types
+    }
+
+    /// Keeps only the listed blocks, in the order they're listed, renumbering the jumps between them and dropping the
+    /// values phis take from blocks that are removed
+    ///
+    /// ## Panics
+    /// If a block that's kept jumps to one that isn't.
+    pub fn retain_blocks(&mut self, order: &[usize]) {
+        let mut renumbered = vec![None; self.blocks.len()];
+        for (i, &b) in order.iter().enumerate() {
+            renumbered[b] = Some(BlockId(i as u32));
+        }
+        let mut blocks: Vec<Option<Block>> = ::std::mem::replace(&mut self.blocks, Vec::new()).into_iter().map(Some).collect();
+        for &b in order {
+            let mut block = blocks[b].take().expect("Expected each block to be kept at most once");
+            for inst in &mut block.insts {
+                if let InstKind::Phi(ref mut incoming) = inst.kind {
+                    *incoming = incoming.iter().filter_map(|&(b, v)| renumbered[b.index()].map(|b| (b, v))).collect();
+                }
+            }
+            let target = |b: BlockId| renumbered[b.index()].expect("Expected the target of a kept block to be kept");
+            block.terminator = match block.terminator {
+                Terminator::Jump(t) => Terminator::Jump(target(t)),
+                Terminator::Branch(c, t, f) => Terminator::Branch(c, target(t), target(f)),
+                other => other,
+            };
+            self.blocks.push(block);
+        }
+    }
+
+    /// Numbers the blocks in reverse postorder, so each comes after the blocks that lead to it and the block a branch
+    /// takes when its condition is true comes right after it, and removes those that can't be reached, along with the
+    /// values phis would take from them
+    pub fn sort_blocks(&mut self) {
+        let order = reverse_postorder(&FirstSuccessorFirst(self));
+        self.retain_blocks(&order);
+    }
+
+    /// Removes the blocks that can't be reached from the entry, returning how many there were
+    pub fn remove_unreachable_blocks(&mut self) -> usize {
+        let reached = reachable(self);
+        let order: Vec<usize> = (0..self.blocks.len()).filter(|&b| reached[b]).collect();
+        let removed = self.blocks.len() - order.len();
+        if removed > 0 {
+            self.retain_blocks(&order);
+        }
+        removed
+    }
+
+    /// Makes every instruction, terminator and variable that reads a value in the map read its replacement instead,
+    /// following replacements that are themselves replaced
+    pub fn replace_uses(&mut self, replacements: &HashMap<Value, Value>) {
+        if replacements.is_empty() {
+            return;
+        }
+        let replace = |mut v: Value| {
+            while let Some(&r) = replacements.get(&v) {
+                v = r;
+            }
+            v
+        };
+        for block in &mut self.blocks {
+            for inst in &mut block.insts {
+                inst.kind.map_operands(&replace);
+            }
+            block.terminator.map_operands(&replace);
+        }
+        for variable in &mut self.variables {
+            variable.value = replace(variable.value);
+        }
+    }
+
+    /// Counts the uses of every value, indexed by value
+    pub fn use_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.value_bound()];
+        for block in &self.blocks {
+            let operands = block.insts.iter().flat_map(|i| i.kind.operands()).chain(block.terminator.operands());
+            for v in operands {
+                if v.index() < counts.len() {
+                    counts[v.index()] += 1;
+                }
+            }
+        }
+        counts
+    }
+}
+
+impl Graph for Function {
+    fn num_blocks(&self) -> usize { self.blocks.len() }
+    fn entry(&self) -> usize { 0 }
+
+    fn successors(&self, block: usize) -> Vec<usize> {
+        self.blocks[block].terminator.successors().into_iter().map(BlockId::index).collect()
+    }
+}
+
+/// A function whose branches are followed from the last successor, so that in reverse postorder, the block a branch
+/// takes when its condition is true comes right after it
+struct FirstSuccessorFirst<'a>(&'a Function);
+
+impl<'a> Graph for FirstSuccessorFirst<'a> {
+    fn num_blocks(&self) -> usize { self.0.num_blocks() }
+    fn entry(&self) -> usize { self.0.entry() }
+
+    fn successors(&self, block: usize) -> Vec<usize> {
+        let mut successors = self.0.successors(block);
+        successors.reverse();
+        successors
+    }
+}
+
+/// A function implemented outside the module
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Extern {
+    pub name: String,
+    pub params: Vec<Ty>,
+    pub ret: Ty,
+
+    /// Whether the function returns a C `int`, whose 32 bits are sign extended to make the `int` it's declared with
+    pub c_int: bool,
+}
+
+/// A whole program, which starts by calling the function `main`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Module {
+    pub overflow: OverflowMode,
+    pub externs: Vec<Extern>,
+
+    /// The functions of each vtable, by name
+    pub vtables: Vec<Vec<String>>,
+    pub functions: Vec<Function>,
+
+    /// The type of the object `main` returns, described for the runtimes that print it, or empty if it returns none
+    pub layout: String,
+}
+
+impl Module {
+    pub fn new(overflow: OverflowMode) -> Module {
+        Module { overflow, externs: Vec::new(), vtables: Vec::new(), functions: Vec::new(), layout: String::new() }
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    pub fn extern_(&self, name: &str) -> Option<&Extern> {
+        self.externs.iter().find(|e| e.name == name)
+    }
+}
@@ -0,0 +1,94 @@
+//! Describes the type of a program's result to the runtimes that print it, in the form `x86/runtime/show.rs` reads
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use patterns::Enums;
+use typeck::{Type, TypeEnv};
+
+/// Describes a type, followed by a line for every struct and enum a value of it can hold
+pub fn describe(ty: &Type, env: &TypeEnv, enums: &Enums) -> String {
+    let mut describer = Describer { env, enums, lines: Vec::new(), numbers: HashMap::new() };
+    let mut text = String::new();
+    describer.ty(ty, &mut text);
+    for line in describer.lines {
+        text.push('\n');
+        text.push_str(&line);
+    }
+    text
+}
+
+struct Describer<'a> {
+    env: &'a TypeEnv,
+    enums: &'a Enums,
+    lines: Vec<String>,
+
+    // The line of each struct and enum, which is given before it's described so it can refer to itself
+    numbers: HashMap<Type, usize>,
+}
+
+impl<'a> Describer<'a> {
+    fn ty(&mut self, ty: &Type, text: &mut String) {
+        match *ty {
+            Type::Int => text.push('i'),
+            Type::Float => text.push('f'),
+            Type::Bool => text.push('b'),
+            Type::Str => text.push('s'),
+            Type::Tuple(ref elements) if elements.is_empty() => text.push('u'),
+            Type::Tuple(ref elements) => {
+                text.push('(');
+                for element in elements {
+                    self.ty(element, text);
+                }
+                text.push(')');
+            }
+            Type::Fn(..) => text.push('F'),
+            Type::Dyn(ref trait_name) => write!(text, "D{};", trait_name).unwrap(),
+            Type::Struct(..) | Type::Enum(..) => {
+                let line = self.line(ty);
+                write!(text, "{};", line).unwrap();
+            }
+            // What's still unknown after type checking is `()`, as in the IR
+            Type::Var(_) | Type::Param(_) | Type::Unknown => text.push('u'),
+        }
+    }
+
+    /// Gets the line of a struct or enum, describing it the first time it's seen
+    fn line(&mut self, ty: &Type) -> usize {
+        if let Some(&line) = self.numbers.get(ty) {
+            return line;
+        }
+        let line = self.lines.len();
+        self.numbers.insert(ty.clone(), line);
+        self.lines.push(String::new());
+
+        let mut text = String::new();
+        match *ty {
+            Type::Struct(ref name, ref args) => {
+                let info = self.env.struct_info(name).expect("Expected the struct to exist");
+                let mapping: HashMap<String, Type> = info.params.iter().cloned().zip(args.iter().cloned()).collect();
+                write!(text, "S{};", name).unwrap();
+                for field in &info.fields {
+                    write!(text, "{}:", field.name).unwrap();
+                    self.ty(&field.ty.substitute_params(&mapping), &mut text);
+                }
+            }
+            Type::Enum(ref name, ref args) => {
+                let params = self.env.enum_info(name).map_or(Vec::new(), |e| e.params.clone());
+                let mapping: HashMap<String, Type> = params.into_iter().zip(args.iter().cloned()).collect();
+                text.push('E');
+                for variant in self.enums.variants(name) {
+                    write!(text, "|{}(", variant).unwrap();
+                    let info = self.env.variant(variant).expect("Expected the variant to exist");
+                    for field in &info.fields {
+                        self.ty(&field.substitute_params(&mapping), &mut text);
+                    }
+                    text.push(')');
+                }
+            }
+            _ => unreachable!("Expected a struct or enum"),
+        }
+        self.lines[line] = text;
+        line
+    }
+}
@@ -0,0 +1,852 @@
+use std::collections::{HashMap, HashSet};
+
+use arith::OverflowMode;
+use cfg::BlockId;
+use ir::layout;
+use ir::{Block, Callee, Const, Extern, Function, Inst, InstKind, Module, Terminator, Ty, Value, Variable};
+use mono::Mono;
+use parser::{BinOp, Decl, DefDecl, EnumDecl, Expr, ExprKind, ExternDecl, Ident, Lit, MatchArm, Pattern, PatternKind,
+             Program, Stmt, TypeExpr, TypeExprKind};
+use parser::visit::{self, Visitor};
+use patterns::{self, Ctor, Decision, Enums, Occurrence, Pat};
+use resolve::{DefId, DefKind, Resolution};
+use text::TextSpan;
+use typeck::{Dispatch, Type, TypeckResults};
+
+/// Lowers a program that type checked without errors to a module in SSA form
+///
+/// The code at the top level of the program becomes the function `main`, and every instance of a function it uses
+/// becomes a function named after the instance.
+pub fn lower_program(program: &Program, resolution: &Resolution, results: &TypeckResults, mono: &Mono,
+                     overflow: OverflowMode) -> Module {
+    let mut decls = DeclCollector { variants: HashMap::new(), enums: Enums::new(), externs: Vec::new() };
+    visit::walk_program(&mut decls, program);
+
+    let mut module = Module::new(overflow);
+    let mut taken: HashSet<String> = HashSet::new();
+    taken.insert("main".into());
+    for e in decls.externs {
+        if taken.insert(e.name().name().into()) {
+            module.externs.push(Extern {
+                name: e.name().name().into(),
+                params: e.params().iter().map(|p| p.ty().map_or(Ty::Unit, annotation_ty)).collect(),
+                ret: e.ret().map_or(Ty::Unit, annotation_ty),
+                c_int: e.ret().map_or(false, is_c_int),
+            });
+        }
+    }
+    let names: Vec<String> = mono.instances().iter().map(|i| unique_name(&mut taken, i.name())).collect();
+
+    let signatures: Vec<(Vec<Ty>, Ty)> = mono.instances().iter().enumerate().map(|(i, instance)| {
+        let def = instance.def();
+        let params = def.params().iter().map(|p| ty_of(mono.type_at(Some(i), p.pattern().span()))).collect();
+        let scheme = results.scheme_of(def.name().span()).expect("Expected every def to have a scheme");
+        let ret = match scheme.apply(instance.args()) {
+            Type::Fn(_, ret) => ty_of(Some(*ret)),
+            _ => Ty::Unit,
+        };
+        (params, ret)
+    }).collect();
+
+    let (variants, enums) = (decls.variants, decls.enums);
+    let lowerer = Lowerer { resolution, results, mono, variants, enums, names, signatures };
+    let mut wrappers = Vec::new();
+    module.functions.push(lowerer.lower_main(program, &mut wrappers));
+    // Only objects need describing to be printed; everything else is printed from its type alone
+    if let Some(ty) = program.result().and_then(|r| mono.type_at(None, r.span())) {
+        if ty_of(Some(ty.clone())) == Ty::Ptr {
+            module.layout = layout::describe(&ty, results.env(), &lowerer.enums);
+        }
+    }
+    for i in 0..mono.instances().len() {
+        module.functions.push(lowerer.lower_instance(i, &mut wrappers));
+    }
+
+    // The value a `dyn` value holds is always a pointer, so values of other types are boxed, and their methods are
+    // called through functions that unbox them first
+    for vtable in mono.vtables() {
+        let boxed = ty_of(Some(vtable.ty().clone())) != Ty::Ptr;
+        let methods = vtable.methods().iter().map(|&m| {
+            if !boxed {
+                return lowerer.names[m].clone();
+            }
+            let name = unique_name(&mut taken, format!("{} for dyn", lowerer.names[m]));
+            module.functions.push(unboxing_thunk(&name, &lowerer.names[m], &lowerer.signatures[m]));
+            name
+        }).collect();
+        module.vtables.push(methods);
+    }
+
+    for (name, tag, fields) in wrappers {
+        let params: Vec<(Value, Ty)> = fields.iter().enumerate().map(|(i, &ty)| (Value(i as u32), ty)).collect();
+        let mut function = Function::new(name, params.clone(), Ty::Ptr);
+        let tag_value = Value(params.len() as u32);
+        let object = Value(params.len() as u32 + 1);
+        let mut block = Block::new();
+        block.insts.push(Inst::new(tag_value, Ty::Int, InstKind::Const(Const::Int((tag as i64).into()))));
+        let mut values = vec![tag_value];
+        values.extend(params.iter().map(|&(v, _)| v));
+        block.insts.push(Inst::new(object, Ty::Ptr, InstKind::New(values)));
+        block.terminator = Terminator::Return(object);
+        function.blocks.push(block);
+        module.functions.push(function);
+    }
+    module
+}
+
+/// Picks a name for a function that no other function or extern has, by adding a number to it if it's taken
+fn unique_name(taken: &mut HashSet<String>, name: String) -> String {
+    let mut unique = name.clone();
+    let mut n = 2;
+    while !taken.insert(unique.clone()) {
+        unique = format!("{}#{}", name, n);
+        n += 1;
+    }
+    unique
+}
+
+/// Builds a function that takes a boxed value in place of the first parameter of the named function, and calls it
+fn unboxing_thunk(name: &str, target: &str, signature: &(Vec<Ty>, Ty)) -> Function {
+    let &(ref params, ret) = signature;
+    let mut thunk_params = vec![(Value(0), Ty::Ptr)];
+    thunk_params.extend(params.iter().enumerate().skip(1).map(|(i, &ty)| (Value(i as u32), ty)));
+    let mut function = Function::new(name, thunk_params.clone(), ret);
+
+    let unboxed = Value(params.len().max(1) as u32);
+    let result = Value(unboxed.0 + 1);
+    let mut args = vec![unboxed];
+    args.extend(thunk_params.iter().skip(1).map(|&(v, _)| v));
+    let mut block = Block::new();
+    block.insts.push(Inst::new(unboxed, params.first().cloned().unwrap_or(Ty::Unit), InstKind::Get(Value(0), 0)));
+    block.insts.push(Inst::new(result, ret, InstKind::Call(Callee::Direct(target.into()), args)));
+    block.terminator = Terminator::Return(result);
+    function.blocks.push(block);
+    function
+}
+
+/// Gets the IR type that values of a type are stored as. Types that are still unknown can't affect how the program
+/// runs, so they're treated as `()`
+pub fn ty_of(ty: Option<Type>) -> Ty {
+    match ty {
+        Some(Type::Int) => Ty::Int,
+        Some(Type::Float) => Ty::Float,
+        Some(Type::Bool) => Ty::Bool,
+        Some(Type::Str) => Ty::Str,
+        Some(Type::Tuple(ref elements)) if elements.is_empty() => Ty::Unit,
+        Some(Type::Tuple(_)) | Some(Type::Struct(..)) | Some(Type::Enum(..)) | Some(Type::Dyn(_)) => Ty::Ptr,
+        Some(Type::Fn(..)) => Ty::Fn,
+        Some(Type::Var(_)) | Some(Type::Param(_)) | Some(Type::Unknown) | None => Ty::Unit,
+    }
+}
+
+/// Gets the IR type of an annotation on an extern, which can only name concrete types
+fn annotation_ty(ty: &TypeExpr) -> Ty {
+    match ty.kind() {
+        &TypeExprKind::Named(ref name, _) => match name.name() {
+            "Int" | "CInt" | "CLong" => Ty::Int,
+            "Float" => Ty::Float,
+            "Bool" => Ty::Bool,
+            "Str" => Ty::Str,
+            _ => Ty::Ptr,
+        },
+        &TypeExprKind::Tuple(ref elements) if elements.is_empty() => Ty::Unit,
+        &TypeExprKind::Tuple(_) | &TypeExprKind::Dyn(_) => Ty::Ptr,
+    }
+}
+
+fn is_c_int(ty: &TypeExpr) -> bool {
+    match ty.kind() {
+        &TypeExprKind::Named(ref name, _) => name.name() == "CInt",
+        _ => false,
+    }
+}
+
+/// Finds the position of every variant in its enum, which is the tag its values hold, and every extern
+struct DeclCollector<'a> {
+    variants: HashMap<String, u32>,
+    enums: Enums,
+    externs: Vec<&'a ExternDecl>,
+}
+
+impl<'a> Visitor<'a> for DeclCollector<'a> {
+    fn visit_decl(&mut self, decl: &'a Decl) {
+        match decl {
+            &Decl::Enum(ref e) => self.enum_(e),
+            &Decl::Extern(ref e) => self.externs.push(e),
+            _ => {}
+        }
+        visit::walk_decl(self, decl);
+    }
+}
+
+impl<'a> DeclCollector<'a> {
+    fn enum_(&mut self, decl: &EnumDecl) {
+        for (i, variant) in decl.variants().iter().enumerate() {
+            self.variants.insert(variant.name().name().into(), i as u32);
+        }
+        // Variants declared twice were reported when the patterns were checked
+        self.enums.add(decl, &mut Vec::new());
+    }
+}
+
+struct Lowerer<'a, 'm: 'a> {
+    resolution: &'a Resolution,
+    results: &'a TypeckResults,
+    mono: &'a Mono<'m>,
+    variants: HashMap<String, u32>,
+
+    /// The enums, for compiling matches to decision trees
+    enums: Enums,
+
+    /// The name of the function for each instance, and its parameter and return types
+    names: Vec<String>,
+    signatures: Vec<(Vec<Ty>, Ty)>,
+}
+
+/// The functions that build a value of a variant, with the variant's tag and the types of its fields, which are
+/// created for variants used as values
+type Wrappers = Vec<(String, u32, Vec<Ty>)>;
+
+impl<'a, 'm> Lowerer<'a, 'm> {
+    fn lower_main(&self, program: &Program, wrappers: &mut Wrappers) -> Function {
+        let ret = ty_of(program.result().and_then(|r| self.mono.type_at(None, r.span())));
+        let mut function = Function::new("main", Vec::new(), ret);
+        // `main` is the top level of the program, which starts where the file does
+        function.span = Some(TextSpan::new(0, 0));
+        let mut builder = FnBuilder::new(self, None, wrappers, function);
+        builder.stmts(program.stmts());
+        let result = match program.result() {
+            Some(result) => builder.expr(result),
+            None => builder.constant(Const::Unit),
+        };
+        builder.terminate(Terminator::Return(result));
+        builder.finish()
+    }
+
+    fn lower_instance(&self, index: usize, wrappers: &mut Wrappers) -> Function {
+        let def: &DefDecl = self.mono.instances()[index].def();
+        let (ref param_types, ret) = self.signatures[index];
+        let params = param_types.iter().enumerate().map(|(i, &ty)| (Value(i as u32), ty)).collect();
+        let mut function = Function::new(self.names[index].clone(), params, ret);
+        function.span = Some(def.span());
+        let mut builder = FnBuilder::new(self, Some(index), wrappers, function);
+        for (i, param) in def.params().iter().enumerate() {
+            builder.bind(param.pattern(), Value(i as u32));
+        }
+        let result = builder.block(def.body().stmts(), def.body().result());
+        builder.terminate(Terminator::Return(result));
+        builder.finish()
+    }
+}
+
+/// Builds the blocks of one function, constructing SSA form as it goes
+///
+/// Each variable's value is tracked per block. Reading a variable in a block that doesn't assign it looks through the
+/// block's predecessors, adding a phi where they disagree. Code is lowered in an order where every predecessor of a
+/// block is finished before the block is started, so the predecessors are always known.
+struct FnBuilder<'a, 'b, 'm: 'a> {
+    lowerer: &'a Lowerer<'a, 'm>,
+    instance: Option<usize>,
+    wrappers: &'b mut Wrappers,
+    function: Function,
+    preds: Vec<Vec<BlockId>>,
+    current: BlockId,
+    next_value: u32,
+    vars: HashMap<(BlockId, DefId), Value>,
+    var_types: HashMap<DefId, Ty>,
+}
+
+impl<'a, 'b, 'm> FnBuilder<'a, 'b, 'm> {
+    fn new(lowerer: &'a Lowerer<'a, 'm>, instance: Option<usize>, wrappers: &'b mut Wrappers, function: Function)
+           -> FnBuilder<'a, 'b, 'm> {
+        let next_value = function.params.len() as u32;
+        let mut builder = FnBuilder {
+            lowerer,
+            instance,
+            wrappers,
+            function,
+            preds: Vec::new(),
+            current: BlockId(0),
+            next_value,
+            vars: HashMap::new(),
+            var_types: HashMap::new(),
+        };
+        builder.current = builder.new_block();
+        builder
+    }
+
+    /// Sorts the blocks, removing those that can't be reached, like the ones after a `match` arm that always matches
+    fn finish(mut self) -> Function {
+        self.function.sort_blocks();
+        self.function
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.function.blocks.push(Block::new());
+        self.preds.push(Vec::new());
+        BlockId(self.function.blocks.len() as u32 - 1)
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        for succ in terminator.successors() {
+            self.preds[succ.index()].push(self.current);
+        }
+        self.function.block_mut(self.current).terminator = terminator;
+    }
+
+    fn emit(&mut self, ty: Ty, kind: InstKind) -> Value {
+        self.emit_inst(ty, kind, None)
+    }
+
+    fn emit_inst(&mut self, ty: Ty, kind: InstKind, span: Option<TextSpan>) -> Value {
+        let value = Value(self.next_value);
+        self.next_value += 1;
+        let mut inst = Inst::new(value, ty, kind);
+        inst.span = span;
+        let current = self.current;
+        self.function.block_mut(current).insts.push(inst);
+        value
+    }
+
+    fn constant(&mut self, c: Const) -> Value {
+        let ty = c.ty();
+        self.emit(ty, InstKind::Const(c))
+    }
+
+    /// Adds a phi to the start of a block, after any others
+    fn phi(&mut self, block: BlockId, ty: Ty, incoming: Vec<(BlockId, Value)>) -> Value {
+        let value = Value(self.next_value);
+        self.next_value += 1;
+        let insts = &mut self.function.block_mut(block).insts;
+        let position = insts.iter().position(|i| match i.kind {
+            InstKind::Phi(_) => false,
+            _ => true,
+        }).unwrap_or(insts.len());
+        insts.insert(position, Inst::new(value, ty, InstKind::Phi(incoming)));
+        value
+    }
+
+    fn type_at(&self, span: TextSpan) -> Ty {
+        ty_of(self.lowerer.mono.type_at(self.instance, span))
+    }
+
+    fn write_var(&mut self, def: DefId, value: Value, ty: Ty) {
+        self.var_types.insert(def, ty);
+        self.vars.insert((self.current, def), value);
+    }
+
+    /// Records the value a variable is declared with, for debug information
+    fn declare(&mut self, name: &Ident, value: Value) {
+        self.function.variables.push(Variable { name: name.name().into(), value, span: name.span() });
+    }
+
+    fn read_var(&mut self, block: BlockId, def: DefId) -> Value {
+        if let Some(&value) = self.vars.get(&(block, def)) {
+            return value;
+        }
+        let preds = self.preds[block.index()].clone();
+        let value = if preds.len() == 1 {
+            self.read_var(preds[0], def)
+        } else {
+            let incoming: Vec<(BlockId, Value)> = preds.iter().map(|&p| (p, self.read_var(p, def))).collect();
+            // A block without predecessors can't be reached, so it gets a phi without any values
+            match incoming.first() {
+                Some(&(_, first)) if incoming.iter().all(|&(_, v)| v == first) => first,
+                _ => {
+                    let ty = self.var_types.get(&def).cloned().unwrap_or(Ty::Unit);
+                    self.phi(block, ty, incoming)
+                }
+            }
+        };
+        self.vars.insert((block, def), value);
+        value
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                // Functions are lowered separately, once for each instance
+                &Stmt::Decl(_) => {}
+                &Stmt::Let(ref l) => {
+                    let value = self.expr(l.value());
+                    self.bind(l.pattern(), value);
+                }
+                &Stmt::Expr(ref e) => {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt], result: Option<&Expr>) -> Value {
+        self.stmts(stmts);
+        match result {
+            Some(result) => self.expr(result),
+            None => self.constant(Const::Unit),
+        }
+    }
+
+    /// Lowers an expression, converting it to a `dyn` value if that's where it's used
+    fn expr(&mut self, expr: &Expr) -> Value {
+        let value = self.expr_kind(expr);
+        match self.lowerer.mono.vtable_at(self.instance, expr.span()) {
+            Some(vtable) => {
+                let data = match self.type_at(expr.span()) {
+                    Ty::Ptr => value,
+                    _ => self.emit(Ty::Ptr, InstKind::New(vec![value])),
+                };
+                let vtable = self.emit(Ty::Ptr, InstKind::VTable(vtable as u32));
+                self.emit(Ty::Ptr, InstKind::New(vec![data, vtable]))
+            }
+            None => value,
+        }
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) -> Value {
+        let ty = self.type_at(expr.span());
+        // Calls record where they are, for debug information
+        let call_span = Some(expr.span());
+        match expr.kind() {
+            &ExprKind::Constant(ref lit) => self.constant(lit_const(lit)),
+            &ExprKind::Var(ref name) => {
+                let resolution = self.lowerer.resolution;
+                let def = resolution.use_at(name.span()).expect("Expected every name to be resolved");
+                match resolution.symbols().def(def).kind() {
+                    DefKind::Function => {
+                        let callee = self.callee(name.span());
+                        self.emit(Ty::Fn, InstKind::Func(callee))
+                    }
+                    DefKind::Extern => self.emit(Ty::Fn, InstKind::Func(name.name().into())),
+                    DefKind::Variant => {
+                        let fields = self.lowerer.results.env().variant(name.name()).map_or(0, |v| v.fields.len());
+                        if fields == 0 {
+                            self.variant(name.name(), Vec::new())
+                        } else {
+                            let wrapper = self.wrapper(name.name(), name.span());
+                            self.emit(Ty::Fn, InstKind::Func(wrapper))
+                        }
+                    }
+                    _ => {
+                        let current = self.current;
+                        self.read_var(current, def)
+                    }
+                }
+            }
+            &ExprKind::Binary(ref l, ref r, op, _) => {
+                let lhs = self.expr(l);
+                let rhs = self.expr(r);
+                if let Some(callee) = self.lowerer.mono.callee(self.instance, expr.span()) {
+                    let name = self.lowerer.names[callee].clone();
+                    return self.emit_inst(ty, InstKind::Call(Callee::Direct(name), vec![lhs, rhs]), call_span);
+                }
+                // Arithmetic on integers can overflow, which stops the program with an error pointing here
+                let span = if ty == Ty::Int && !op.is_comparison() { Some(expr.span()) } else { None };
+                self.emit_inst(ty, InstKind::Binary(op, lhs, rhs), span)
+            }
+            &ExprKind::Struct(ref name, ref fields) => {
+                let values: HashMap<&str, Value> = fields.iter().map(|f| (f.name().name(), self.expr(f.value()))).collect();
+                let info = self.lowerer.results.env().struct_info(name.name()).expect("Expected the struct to exist");
+                let ordered = info.fields.iter().map(|f| values[f.name.as_str()]).collect();
+                self.emit(Ty::Ptr, InstKind::New(ordered))
+            }
+            &ExprKind::Field(ref target, ref name) => {
+                let object = self.expr(target);
+                let index = self.field_index(target.span(), name.name());
+                self.emit(ty, InstKind::Get(object, index))
+            }
+            &ExprKind::Assign(ref target, ref value) => {
+                let value = self.expr(value);
+                match target.kind() {
+                    &ExprKind::Var(ref name) => {
+                        let def = self.lowerer.resolution.use_at(name.span()).expect("Expected every name to be resolved");
+                        let var_ty = self.type_at(target.span());
+                        self.write_var(def, value, var_ty);
+                    }
+                    &ExprKind::Field(ref object, ref name) => {
+                        let index = self.field_index(object.span(), name.name());
+                        let object = self.expr(object);
+                        self.emit(Ty::Unit, InstKind::Set(object, index, value));
+                    }
+                    _ => unreachable!("Expected the target of an assignment to be a place"),
+                }
+                self.constant(Const::Unit)
+            }
+            &ExprKind::Call(ref callee, ref args) => {
+                if let &ExprKind::Var(ref name) = callee.kind() {
+                    let def = self.lowerer.resolution.use_at(name.span()).expect("Expected every name to be resolved");
+                    let kind = self.lowerer.resolution.symbols().def(def).kind();
+                    if kind == DefKind::Function || kind == DefKind::Extern || kind == DefKind::Variant {
+                        let args: Vec<Value> = args.iter().map(|a| self.expr(a)).collect();
+                        return match kind {
+                            DefKind::Function => {
+                                let callee = self.callee(name.span());
+                                self.emit_inst(ty, InstKind::Call(Callee::Direct(callee), args), call_span)
+                            }
+                            DefKind::Extern => {
+                                let callee = Callee::Direct(name.name().into());
+                                self.emit_inst(ty, InstKind::Call(callee, args), call_span)
+                            }
+                            _ => self.variant(name.name(), args),
+                        };
+                    }
+                }
+                let function = self.expr(callee);
+                let args = args.iter().map(|a| self.expr(a)).collect();
+                self.emit_inst(ty, InstKind::Call(Callee::Indirect(function), args), call_span)
+            }
+            &ExprKind::MethodCall(ref receiver, ref name, ref args) => {
+                let receiver = self.expr(receiver);
+                let mut values = vec![receiver];
+                values.extend(args.iter().map(|a| self.expr(a)));
+                if let Some(callee) = self.lowerer.mono.callee(self.instance, name.span()) {
+                    let callee = self.lowerer.names[callee].clone();
+                    return self.emit_inst(ty, InstKind::Call(Callee::Direct(callee), values), call_span);
+                }
+                let slot = match self.lowerer.results.dispatch_at(name.span()) {
+                    Some(&Dispatch::Dynamic { slot, .. }) => slot,
+                    _ => panic!("Expected a method call without a callee to be dispatched dynamically"),
+                };
+                // A `dyn` value holds its data, then its vtable
+                values[0] = self.emit(Ty::Ptr, InstKind::Get(receiver, 0));
+                let vtable = self.emit(Ty::Ptr, InstKind::Get(receiver, 1));
+                let method = self.emit(Ty::Fn, InstKind::Get(vtable, slot as u32));
+                self.emit_inst(ty, InstKind::Call(Callee::Indirect(method), values), call_span)
+            }
+            &ExprKind::Match(ref scrutinee, ref arms) => self.match_(scrutinee, arms, ty),
+            &ExprKind::Tuple(ref elements) => {
+                if elements.is_empty() {
+                    return self.constant(Const::Unit);
+                }
+                let values = elements.iter().map(|e| self.expr(e)).collect();
+                self.emit(Ty::Ptr, InstKind::New(values))
+            }
+            &ExprKind::Block(ref block) => self.block(block.stmts(), block.result()),
+            &ExprKind::Return(ref value) => {
+                let value = match *value {
+                    Some(ref value) => self.expr(value),
+                    None => self.constant(Const::Unit),
+                };
+                self.terminate(Terminator::Return(value));
+                // The code after a `return` goes in a block without predecessors, which `finish` removes
+                self.current = self.new_block();
+                self.constant(Const::Unit)
+            }
+        }
+    }
+
+    /// Gets the name of the function a call or reference at the span uses
+    fn callee(&self, span: TextSpan) -> String {
+        let instance = self.lowerer.mono.callee(self.instance, span).expect("Expected every function used to be instantiated");
+        self.lowerer.names[instance].clone()
+    }
+
+    fn field_index(&self, object: TextSpan, field: &str) -> u32 {
+        let env = self.lowerer.results.env();
+        let index = match self.lowerer.mono.type_at(self.instance, object) {
+            Some(Type::Struct(ref name, _)) => env.struct_info(name).and_then(|s| s.fields.iter().position(|f| f.name == field)),
+            _ => None,
+        };
+        index.expect("Expected a field of a struct") as u32
+    }
+
+    /// Builds a value of a variant, which holds its tag followed by its fields
+    fn variant(&mut self, name: &str, fields: Vec<Value>) -> Value {
+        let tag = self.constant(Const::Int((self.lowerer.variants[name] as i64).into()));
+        let mut values = vec![tag];
+        values.extend(fields);
+        self.emit(Ty::Ptr, InstKind::New(values))
+    }
+
+    /// Gets the name of a function that builds a value of the variant, whose type is at the span
+    fn wrapper(&mut self, name: &str, span: TextSpan) -> String {
+        let fields = match self.lowerer.mono.type_at(self.instance, span) {
+            Some(Type::Fn(params, _)) => params.into_iter().map(|p| ty_of(Some(p))).collect(),
+            _ => Vec::new(),
+        };
+        let field_names: Vec<&str> = fields.iter().map(|f: &Ty| f.name()).collect();
+        let wrapper = format!("{}({})", name, field_names.join(", "));
+        if !self.wrappers.iter().any(|w| w.0 == wrapper) {
+            self.wrappers.push((wrapper.clone(), self.lowerer.variants[name], fields));
+        }
+        wrapper
+    }
+
+    /// Binds the names in a pattern that always matches
+    fn bind(&mut self, pattern: &Pattern, value: Value) {
+        match pattern.kind() {
+            &PatternKind::Wildcard | &PatternKind::Literal(_) => {}
+            &PatternKind::Binding(ref name) => {
+                if let Some(def) = self.lowerer.resolution.decl_at(name.span()) {
+                    let ty = self.type_at(pattern.span());
+                    self.write_var(def, value, ty);
+                    self.declare(name, value);
+                }
+            }
+            &PatternKind::Tuple(ref elements) => self.bind_fields(elements, value, 0),
+            &PatternKind::Constructor(_, ref fields) => self.bind_fields(fields, value, 1),
+        }
+    }
+
+    fn bind_fields(&mut self, fields: &[Pattern], object: Value, first: u32) {
+        for (i, field) in fields.iter().enumerate() {
+            if self.binds(field) {
+                let ty = self.type_at(field.span());
+                let value = self.emit(ty, InstKind::Get(object, first + i as u32));
+                self.bind(field, value);
+            }
+        }
+    }
+
+    /// Returns `true` if the pattern binds a name
+    fn binds(&self, pattern: &Pattern) -> bool {
+        match pattern.kind() {
+            &PatternKind::Wildcard | &PatternKind::Literal(_) => false,
+            &PatternKind::Binding(ref name) => self.lowerer.resolution.decl_at(name.span()).is_some(),
+            &PatternKind::Tuple(ref elements) | &PatternKind::Constructor(_, ref elements) => {
+                elements.iter().any(|e| self.binds(e))
+            }
+        }
+    }
+
+    /// Lowers a `match` from its decision tree, which tests each part of the scrutinee at most once on any path
+    ///
+    /// An arm that only one leaf of the tree picks has its body lowered there. The body of an arm that several leaves
+    /// pick gets a block of its own, which they jump to once they've bound the arm's names, and which is lowered after
+    /// the whole tree so every path into it is known.
+    fn match_(&mut self, scrutinee: &Expr, arms: &[MatchArm], ty: Ty) -> Value {
+        let value = self.expr(scrutinee);
+        let decision = {
+            let enums = &self.lowerer.enums;
+            let pats: Vec<(Pat, bool)> =
+                arms.iter().map(|a| (enums.lower(a.pattern(), &mut Vec::new()), a.guard().is_some())).collect();
+            patterns::compile(enums, &pats)
+        };
+        let scrutinee_ty = self.lowerer.mono.type_at(self.instance, scrutinee.span()).unwrap_or(Type::Unknown);
+        let mut occurrences = Occurrences {
+            values: vec![(Occurrence::root(), value)].into_iter().collect(),
+            types: vec![(Occurrence::root(), scrutinee_ty)].into_iter().collect(),
+        };
+
+        let mut leaves = vec![0; arms.len()];
+        count_leaves(&decision, &mut leaves);
+        let mut lowering = MatchLowering {
+            arms,
+            shared: leaves.iter().map(|&n| if n > 1 { Some(self.new_block()) } else { None }).collect(),
+            join: self.new_block(),
+            results: Vec::new(),
+        };
+        self.decision(&decision, &mut lowering, &mut occurrences);
+        for (arm, shared) in lowering.shared.clone().into_iter().enumerate() {
+            if let Some(block) = shared {
+                self.current = block;
+                self.arm_body(arm, &mut lowering);
+            }
+        }
+
+        let MatchLowering { join, results, .. } = lowering;
+        self.current = join;
+        match results.first() {
+            Some(&(_, first)) if results.iter().all(|&(_, v)| v == first) => first,
+            _ => self.phi(join, ty, results),
+        }
+    }
+
+    fn decision(&mut self, decision: &Decision, lowering: &mut MatchLowering, occurrences: &mut Occurrences) {
+        match *decision {
+            // Matches are exhaustive, so this can't happen
+            Decision::Fail => self.terminate(Terminator::Unreachable),
+            Decision::Leaf { arm, ref bindings } => {
+                self.bind_occurrences(lowering.arms[arm].pattern(), bindings, occurrences);
+                match lowering.shared[arm] {
+                    Some(block) => self.terminate(Terminator::Jump(block)),
+                    None => self.arm_body(arm, lowering),
+                }
+            }
+            Decision::Guard { arm, ref bindings, ref otherwise } => {
+                self.bind_occurrences(lowering.arms[arm].pattern(), bindings, occurrences);
+                let guard = lowering.arms[arm].guard().expect("Expected an arm with a guard");
+                let condition = self.expr(guard);
+                let next = self.new_block();
+                match lowering.shared[arm] {
+                    Some(block) => self.terminate(Terminator::Branch(condition, block, next)),
+                    None => {
+                        let body = self.new_block();
+                        self.terminate(Terminator::Branch(condition, body, next));
+                        self.current = body;
+                        self.arm_body(arm, lowering);
+                    }
+                }
+                self.current = next;
+                self.decision(otherwise, lowering, occurrences);
+            }
+            Decision::Switch { ref occurrence, ref cases, ref default } => {
+                let value = self.occurrence(occurrence, occurrences);
+                let ty = occurrences.types[occurrence].clone();
+                // A variant is told apart by its tag
+                let tested = match ty {
+                    Type::Enum(..) => self.emit(Ty::Int, InstKind::Get(value, 0)),
+                    _ => value,
+                };
+                for (i, &(ctor, ref case)) in cases.iter().enumerate() {
+                    let mut inner = occurrences.clone();
+                    for (index, field) in self.field_types(&ty, ctor).into_iter().enumerate() {
+                        inner.types.insert(occurrence.child(index), field);
+                    }
+                    // The last case needs no test when the cases cover every constructor
+                    if i + 1 == cases.len() && default.is_none() {
+                        self.decision(case, lowering, &mut inner);
+                        return;
+                    }
+                    let matched = self.new_block();
+                    let next = self.new_block();
+                    // A `Bool` is its own test
+                    let terminator = match ctor {
+                        Ctor::Bool(true) => Terminator::Branch(tested, matched, next),
+                        Ctor::Bool(false) => Terminator::Branch(tested, next, matched),
+                        _ => {
+                            let expected = self.constant(self.ctor_const(ctor));
+                            let equal = self.emit(Ty::Bool, InstKind::Binary(BinOp::Equal, tested, expected));
+                            Terminator::Branch(equal, matched, next)
+                        }
+                    };
+                    self.terminate(terminator);
+                    self.current = matched;
+                    self.decision(case, lowering, &mut inner);
+                    self.current = next;
+                }
+                match *default {
+                    Some(ref default) => self.decision(default, lowering, occurrences),
+                    None => self.terminate(Terminator::Unreachable),
+                }
+            }
+        }
+    }
+
+    /// Lowers the body of an arm whose names have been bound, continuing after the `match`
+    fn arm_body(&mut self, arm: usize, lowering: &mut MatchLowering) {
+        let arm = &lowering.arms[arm];
+        for (name, def) in self.bindings(arm.pattern()) {
+            let current = self.current;
+            let value = self.read_var(current, def);
+            self.declare(name, value);
+        }
+        let result = self.expr(arm.body());
+        lowering.results.push((self.current, result));
+        self.terminate(Terminator::Jump(lowering.join));
+    }
+
+    /// Gets the value at the occurrence, reading it out of the value it's a field of if that hasn't been done yet
+    fn occurrence(&mut self, occurrence: &Occurrence, occurrences: &mut Occurrences) -> Value {
+        if let Some(&value) = occurrences.values.get(occurrence) {
+            return value;
+        }
+        let (parent, index) = occurrence.parent().expect("Expected the value of the scrutinee to be known");
+        let object = self.occurrence(&parent, occurrences);
+        // A variant holds its tag before its fields
+        let first = match occurrences.types[&parent] {
+            Type::Enum(..) => 1,
+            _ => 0,
+        };
+        let ty = ty_of(Some(occurrences.types[occurrence].clone()));
+        let value = self.emit(ty, InstKind::Get(object, first + index as u32));
+        occurrences.values.insert(occurrence.clone(), value);
+        value
+    }
+
+    /// Gets the types of the fields of a value of the type that the constructor built
+    fn field_types(&self, ty: &Type, ctor: Ctor) -> Vec<Type> {
+        let env = self.lowerer.results.env();
+        match (ty, ctor) {
+            (&Type::Tuple(ref elements), _) => elements.clone(),
+            (&Type::Enum(ref name, ref args), Ctor::Variant(id)) => {
+                let params = env.enum_info(name).map_or(Vec::new(), |e| e.params.clone());
+                let mapping: HashMap<String, Type> = params.into_iter().zip(args.iter().cloned()).collect();
+                let variant = env.variant(self.lowerer.enums.variant_name(id)).expect("Expected the variant to exist");
+                variant.fields.iter().map(|f| f.substitute_params(&mapping)).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gets the constant a value built by the constructor is compared with, which for a variant is its tag
+    fn ctor_const(&self, ctor: Ctor) -> Const {
+        match ctor {
+            Ctor::Int(i) => Const::Int(i.into()),
+            Ctor::Variant(id) => Const::Int((self.lowerer.variants[self.lowerer.enums.variant_name(id)] as i64).into()),
+            Ctor::Bool(_) | Ctor::Tuple(_) => unreachable!("Expected only integers and variants to be compared"),
+        }
+    }
+
+    /// Gives the names an arm's pattern binds the values at their occurrences
+    fn bind_occurrences(&mut self, pattern: &Pattern, bindings: &[(String, Occurrence)],
+                        occurrences: &mut Occurrences) {
+        let defs = self.bindings(pattern);
+        for &(ref name, ref occurrence) in bindings {
+            let def = defs.iter().find(|&&(n, _)| n.name() == name).expect("Expected the pattern to bind the name").1;
+            let value = self.occurrence(occurrence, occurrences);
+            let ty = ty_of(Some(occurrences.types[occurrence].clone()));
+            self.write_var(def, value, ty);
+        }
+    }
+
+    /// Gets the names a pattern binds, with what they're declared as
+    fn bindings<'p>(&self, pattern: &'p Pattern) -> Vec<(&'p Ident, DefId)> {
+        match pattern.kind() {
+            &PatternKind::Wildcard | &PatternKind::Literal(_) => Vec::new(),
+            &PatternKind::Binding(ref name) => {
+                self.lowerer.resolution.decl_at(name.span()).map(|def| (name, def)).into_iter().collect()
+            }
+            &PatternKind::Tuple(ref elements) | &PatternKind::Constructor(_, ref elements) => {
+                elements.iter().flat_map(|e| self.bindings(e)).collect()
+            }
+        }
+    }
+}
+
+/// The arms of a `match` being lowered, and the values they produce
+struct MatchLowering<'e> {
+    arms: &'e [MatchArm],
+
+    // The block that starts the body of each arm that's picked by more than one leaf of the decision tree
+    shared: Vec<Option<BlockId>>,
+
+    join: BlockId,
+    results: Vec<(BlockId, Value)>,
+}
+
+/// Counts the leaves of the decision tree that pick each arm
+fn count_leaves(decision: &Decision, leaves: &mut [usize]) {
+    match *decision {
+        Decision::Fail => {}
+        Decision::Leaf { arm, .. } => leaves[arm] += 1,
+        Decision::Guard { arm, ref otherwise, .. } => {
+            leaves[arm] += 1;
+            count_leaves(otherwise, leaves);
+        }
+        Decision::Switch { ref cases, ref default, .. } => {
+            for &(_, ref case) in cases {
+                count_leaves(case, leaves);
+            }
+            if let Some(ref default) = *default {
+                count_leaves(default, leaves);
+            }
+        }
+    }
+}
+
+/// The values within a scrutinee that have been read so far on the path through a decision tree, and the types of
+/// those that can be
+#[derive(Clone)]
+struct Occurrences {
+    values: HashMap<Occurrence, Value>,
+    types: HashMap<Occurrence, Type>,
+}
+
+fn lit_const(lit: &Lit) -> Const {
+    match lit {
+        &Lit::Int(ref i) => Const::Int(i.clone()),
+        &Lit::Float(v) => Const::Float(v),
+        &Lit::Bool(b) => Const::Bool(b),
+        &Lit::Str(ref s) => Const::Str(s.clone()),
+    }
+}
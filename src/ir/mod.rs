@@ -0,0 +1,289 @@
+//! The intermediate representation that programs are lowered to for optimization and code generation
+//!
+//! A module holds functions in SSA form: every value is defined by exactly one parameter or instruction, and where
+//! control flow joins, phis pick the value from the predecessor control came from. Values have simple types that say
+//! how they're stored, since the language's types have all been checked and made concrete by then.
+
+mod func;
+mod layout;
+mod lower;
+mod parse;
+mod print;
+mod verify;
+
+pub use self::func::{Block, Callee, Const, Extern, Function, Inst, InstKind, Module, Terminator, Ty, Value,
+                        Variable};
+pub use self::lower::{lower_program, ty_of};
+pub use self::parse::{parse_module, ParseError};
+pub use self::print::{from_mnemonic, mnemonic};
+pub use self::verify::{operator_accepts, verify_module, VerifyError};
+
+#[cfg(test)]
+mod tests {
+    use arith::OverflowMode;
+    use tokenizer::Tokenizer;
+    use parser::Parser;
+    use resolve::resolve_program;
+    use typeck;
+    use mono::monomorphize;
+    use ir::{lower_program, parse_module, verify_module, Module, ParseError};
+
+    fn lower(code: &str) -> Module {
+        let program = Parser::new(Tokenizer::new(code)).program().expect("Expected the parse to succeed");
+        let resolution = resolve_program(&program);
+        let results = typeck::check_program(&program);
+        assert_eq!(0, results.diagnostics().len(), "{:?}", results.diagnostics());
+        let mono = monomorphize(&program, &results);
+        let module = lower_program(&program, &resolution, &results, &mono, OverflowMode::Trap);
+        assert_eq!(Vec::<::ir::VerifyError>::new(), verify_module(&module), "{}", module);
+        module
+    }
+
+    fn function(module: &Module, name: &str) -> String {
+        module.function(name).expect("Expected the function to exist").to_string()
+    }
+
+    #[test]
+    pub fn matches_test_each_arm_and_join_with_a_phi() {
+        let module = lower("def abs(x) { match x { 0 => 0, n if n < 0 => 0 - n, n => n } } abs(3) + 1");
+        assert_eq!("\
+fn @abs(%0: int) -> int !0..62 {
+    let x = %0 !8..9
+    let n = %0 !31..32
+    let n = %0 !52..53
+bb0:
+    %1: int = const 0
+    %2: bool = eq %0, %1
+    branch %2, bb1, bb2
+bb1:
+    %3: int = const 0
+    jump bb5
+bb2:
+    %4: int = const 0
+    %5: bool = lt %0, %4
+    branch %5, bb3, bb4
+bb3:
+    %6: int = const 0
+    %7: int = sub %6, %0 !45..50
+    jump bb5
+bb4:
+    jump bb5
+bb5:
+    %8: int = phi [bb1: %3], [bb3: %7], [bb4: %0]
+    ret %8
+}
+", function(&module, "abs"));
+    }
+
+    #[test]
+    pub fn arms_picked_by_several_leaves_share_their_body() {
+        let code = "def both(p) { match p { (true, true) => 1, _ => 0 } } both((true, false))";
+        assert_eq!("\
+fn @both(%0: ptr) -> int !0..53 {
+    let p = %0 !9..10
+bb0:
+    %1: bool = get %0, 0
+    branch %1, bb1, bb4
+bb1:
+    %2: bool = get %0, 1
+    branch %2, bb2, bb3
+bb2:
+    %3: int = const 1
+    jump bb6
+bb3:
+    jump bb5
+bb4:
+    jump bb5
+bb5:
+    %4: int = const 0
+    jump bb6
+bb6:
+    %5: int = phi [bb2: %3], [bb5: %4]
+    ret %5
+}
+", function(&lower(code), "both"));
+    }
+
+    #[test]
+    pub fn assignments_make_new_values() {
+        let code = "def f(x) { let y = 1; match x { 0 => { y = 2; }, _ => { } }; y = y + x; y } f(0)";
+        assert_eq!("\
+fn @f(%0: int) -> int !0..75 {
+    let x = %0 !6..7
+    let y = %1 !15..16
+bb0:
+    %1: int = const 1
+    %2: int = const 0
+    %3: bool = eq %0, %2
+    branch %3, bb1, bb2
+bb1:
+    %4: int = const 2
+    %5: unit = const ()
+    %6: unit = const ()
+    jump bb3
+bb2:
+    %7: unit = const ()
+    jump bb3
+bb3:
+    %8: unit = phi [bb1: %6], [bb2: %7]
+    %9: int = phi [bb1: %4], [bb2: %1]
+    %10: int = add %9, %0 !65..70
+    %11: unit = const ()
+    ret %10
+}
+", function(&lower(code), "f"));
+    }
+
+    #[test]
+    pub fn code_after_a_return_is_dropped() {
+        let code = "def f(x) { match x { 0 => return 1, _ => {} }; return x; x + 1 } f(0)";
+        assert_eq!("\
+fn @f(%0: int) -> int !0..64 {
+    let x = %0 !6..7
+bb0:
+    %1: int = const 0
+    %2: bool = eq %0, %1
+    branch %2, bb1, bb2
+bb1:
+    %3: int = const 1
+    ret %3
+bb2:
+    %5: unit = const ()
+    jump bb3
+bb3:
+    %6: unit = phi [bb2: %5]
+    %8: int = phi [bb2: %0]
+    ret %8
+}
+", function(&lower(code), "f"));
+    }
+
+    #[test]
+    pub fn enums_hold_their_tag_then_their_fields() {
+        let code = "enum Shape { Circle(Int), Empty } \
+                    def area(s: Shape) -> Int { match s { Circle(r) => r, Empty => 0 } } \
+                    let make = Circle; area(make(2)) + area(Empty)";
+        let module = lower(code);
+        assert_eq!("\
+fn @\"Circle(int)\"(%0: int) -> ptr {
+bb0:
+    %1: int = const 0
+    %2: ptr = new (%1, %0)
+    ret %2
+}
+", function(&module, "Circle(int)"));
+        assert!(function(&module, "main").contains("%4: int = const 1\n    %5: ptr = new (%4)\n"));
+        assert!(function(&module, "area").contains("%1: int = get %0, 0\n"));
+    }
+
+    #[test]
+    pub fn dyn_values_hold_their_data_then_their_vtable() {
+        let code = "trait Show { def show(self) -> Str; } \
+                    struct P { x: Int } \
+                    impl Show for P { def show(self) -> Str { \"p\" } } \
+                    impl Show for Int { def show(self) -> Str { \"i\" } } \
+                    def f(s: dyn Show) -> Str { s.show() } \
+                    (f(P { x: 1 }), f(2))";
+        let module = lower(code);
+        assert_eq!(vec![vec!["<P as Show>::show".to_string()], vec!["<Int as Show>::show for dyn".to_string()]],
+                   module.vtables);
+        assert!(function(&module, "f").contains("\
+    %1: ptr = get %0, 0
+    %2: ptr = get %0, 1
+    %3: fn = get %2, 0
+    %4: str = call %3(%1) !188..196
+"));
+        // An `Int` is boxed so it can be held like any other value
+        assert!(function(&module, "main").contains("\
+    %6: ptr = new (%5)
+    %7: ptr = vtable 1
+    %8: ptr = new (%6, %7)
+"));
+    }
+
+    #[test]
+    pub fn objects_returned_from_main_are_described() {
+        assert_eq!("", lower("1").layout);
+        assert_eq!("(ib)", lower("(1, true)").layout);
+        // A struct or enum is described once on a line of its own, which its fields and variants can refer back to
+        let code = "enum List<T> { Cons(T, List<T>), Nil } \
+                    struct P { x: Float, xs: List<Str> } \
+                    P { x: 1.0, xs: Cons(\"a\", Nil) }";
+        assert_eq!("0;\nSP;x:fxs:1;\nE|Cons(s1;)|Nil()", lower(code).layout);
+    }
+
+    #[test]
+    pub fn printed_modules_parse_back() {
+        for code in &[
+            "extern puts(s: Str) -> (); def id<T>(x: T) -> T { x } puts(id(\"a\\n\\\"b\\\"\")); (id(1.5), id(true))",
+            "extern abs(x: Int) -> CInt; abs(1)",
+            "struct P { x: Int, y: Int } def f(p: P) -> Int { p.x = p.y; p.x } let f2 = f; f2(P { y: 2, x: 1 })",
+        ] {
+            let module = lower(code);
+            assert_eq!(Ok(module.clone()), parse_module(&module.to_string()), "{}", module);
+        }
+    }
+
+    #[test]
+    pub fn parse_errors_give_the_line() {
+        let text = "overflow wrap\n\nfn @f() -> int {\nbb0:\n    %0: int = frob %1\n    ret %0\n}\n";
+        assert_eq!(Err(ParseError { line: 5, message: "unknown instruction `frob`".into() }), parse_module(text));
+        let text = "overflow trap\nfn @f() -> bits {\n}";
+        assert_eq!(Err(ParseError { line: 2, message: "unknown type `bits`".into() }), parse_module(text));
+    }
+
+    fn verify(text: &str) -> Vec<String> {
+        let module = parse_module(text).expect("Expected the module to parse");
+        verify_module(&module).iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    pub fn verifier_finds_values_used_before_they_are_defined() {
+        assert_eq!(vec![
+            "in `f`: %2 isn't defined at the end of bb1",
+            "in `f`: %2 is used by %3 before it's defined",
+        ], verify("\
+overflow trap
+
+fn @f(%0: bool) -> int {
+bb0:
+    branch %0, bb1, bb2
+bb1:
+    jump bb3
+bb2:
+    %2: int = const 1
+    jump bb3
+bb3:
+    %1: int = phi [bb1: %2], [bb2: %2]
+    %3: int = add %2, %1
+    ret %3
+}
+"));
+    }
+
+    #[test]
+    pub fn verifier_checks_types_and_phis() {
+        assert_eq!(vec![
+            "in `f`: the phi %2 doesn't have one value for each predecessor of bb1",
+            "in `f`: %3 applies `lt` to `bool`s",
+            "in `f`: %4 calls the unknown function `g`",
+            "in `f`: the branch of bb1 expects %1 to be `bool`, but it's `int`",
+            "in `f`: the return of bb2 expects %3 to be `int`, but it's `bool`",
+        ], verify("\
+overflow trap
+
+fn @f(%0: bool) -> int {
+bb0:
+    %1: int = const 1
+    jump bb1
+bb1:
+    %2: int = phi [bb0: %1], [bb2: %1]
+    %3: bool = lt %0, %0
+    %4: int = call @g()
+    branch %1, bb2, bb2
+bb2:
+    ret %3
+}
+"));
+    }
+}
@@ -0,0 +1,531 @@
+use std::fmt;
+
+use arith::OverflowMode;
+use cfg::BlockId;
+use ir::{Block, Callee, Const, Extern, Function, Inst, InstKind, Module, Terminator, Ty, Value, Variable};
+use ir::print::from_mnemonic;
+use text::TextSpan;
+
+/// A problem with the text of a module, and the line it's on, counting from 1
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Reads a module in the form it's displayed in
+pub fn parse_module(text: &str) -> Result<Module, ParseError> {
+    let tokens = lex(text)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    parser.module()
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Tok {
+    Word(String),
+    Name(String),
+    Value(u32),
+    Int(String),
+    Float(f64),
+    Str(String),
+    Punct(&'static str),
+    End,
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Tok::Word(ref w) => write!(f, "`{}`", w),
+            &Tok::Name(ref n) => write!(f, "`@{}`", n),
+            &Tok::Value(v) => write!(f, "`%{}`", v),
+            &Tok::Int(ref i) => write!(f, "`{}`", i),
+            &Tok::Float(v) => write!(f, "`{:?}`", v),
+            &Tok::Str(ref s) => write!(f, "{:?}", s),
+            &Tok::Punct(p) => write!(f, "`{}`", p),
+            &Tok::End => write!(f, "the end of the module"),
+        }
+    }
+}
+
+const PUNCTUATION: &'static [&'static str] = &["->", "..", "(", ")", "[", "]", "{", "}", ",", ":", "=", "!"];
+
+fn lex(text: &str) -> Result<Vec<(Tok, usize)>, ParseError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    let error = |line, message: String| Err(ParseError { line, message });
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || (c == '@' && chars.get(i + 1) == Some(&'"')) {
+            let quoted = c == '@';
+            i += if quoted { 2 } else { 1 };
+            let mut s = String::new();
+            loop {
+                match chars.get(i) {
+                    None | Some(&'\n') => return error(line, "unterminated string".into()),
+                    Some(&'"') => break,
+                    Some(&'\\') => {
+                        s.push(match chars.get(i + 1) {
+                            Some(&'n') => '\n',
+                            Some(&'t') => '\t',
+                            Some(&'r') => '\r',
+                            Some(&'0') => '\0',
+                            Some(&'"') => '"',
+                            Some(&'\\') => '\\',
+                            _ => return error(line, "unknown escape in string".into()),
+                        });
+                        i += 2;
+                    }
+                    Some(&c) => {
+                        s.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push((if quoted { Tok::Name(s) } else { Tok::Str(s) }, line));
+        } else if c == '@' || c == '%' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && is_word(chars[i]) {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if c == '@' {
+                if word.is_empty() {
+                    return error(line, "expected a name after `@`".into());
+                }
+                tokens.push((Tok::Name(word), line));
+            } else {
+                match word.parse() {
+                    Ok(v) => tokens.push((Tok::Value(v), line)),
+                    Err(_) => return error(line, format!("`%{}` is not a value", word)),
+                }
+            }
+        } else if c.is_digit(10) || (c == '-' && chars.get(i + 1).map_or(false, |c| c.is_digit(10))) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i].is_digit(10) {
+                i += 1;
+            }
+            let mut float = false;
+            // `..` separates the ends of a span, so only a `.` followed by a digit makes a float
+            if chars.get(i) == Some(&'.') && chars.get(i + 1).map_or(false, |c| c.is_digit(10)) {
+                float = true;
+                i += 1;
+                while i < chars.len() && chars[i].is_digit(10) {
+                    i += 1;
+                }
+            }
+            if chars.get(i) == Some(&'e') {
+                float = true;
+                i += 1;
+                if chars.get(i) == Some(&'-') || chars.get(i) == Some(&'+') {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_digit(10) {
+                    i += 1;
+                }
+            }
+            let s: String = chars[start..i].iter().collect();
+            if float {
+                match s.parse() {
+                    Ok(v) => tokens.push((Tok::Float(v), line)),
+                    Err(_) => return error(line, format!("`{}` is not a number", s)),
+                }
+            } else {
+                tokens.push((Tok::Int(s), line));
+            }
+        } else if is_word(c) {
+            let start = i;
+            while i < chars.len() && is_word(chars[i]) {
+                i += 1;
+            }
+            tokens.push((Tok::Word(chars[start..i].iter().collect()), line));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            match PUNCTUATION.iter().find(|p| rest.starts_with(*p)) {
+                Some(p) => {
+                    tokens.push((Tok::Punct(p), line));
+                    i += p.len();
+                }
+                None => return error(line, format!("unexpected character `{}`", c)),
+            }
+        }
+    }
+    tokens.push((Tok::End, line));
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Tok, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].0
+    }
+
+    fn next(&mut self) -> Tok {
+        let tok = self.tokens[self.pos].0.clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, ParseError> {
+        Err(ParseError { line: self.tokens[self.pos].1, message: message.into() })
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, ParseError> {
+        self.error(format!("expected {}, found {}", expected, self.peek()))
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        match self.peek() {
+            &Tok::Word(ref w) => w == word,
+            _ => false,
+        }
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        *self.peek() == Tok::Punct(match PUNCTUATION.iter().find(|&&p| p == punct) {
+            Some(p) => p,
+            None => return false,
+        })
+    }
+
+    fn word(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            Tok::Word(w) => {
+                self.next();
+                Ok(w)
+            }
+            _ => self.unexpected("a word"),
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), ParseError> {
+        if self.is_word(word) {
+            self.next();
+            Ok(())
+        } else {
+            self.unexpected(&format!("`{}`", word))
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), ParseError> {
+        if self.is_punct(punct) {
+            self.next();
+            Ok(())
+        } else {
+            self.unexpected(&format!("`{}`", punct))
+        }
+    }
+
+    /// Parses items separated by commas, up to the closing punctuation, which is consumed
+    fn list<T, F: FnMut(&mut Parser) -> Result<T, ParseError>>(&mut self, close: &str, mut item: F) -> Result<Vec<T>, ParseError> {
+        let mut items = Vec::new();
+        while !self.is_punct(close) {
+            items.push(item(self)?);
+            if !self.is_punct(close) {
+                self.expect(",")?;
+            }
+        }
+        self.next();
+        Ok(items)
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            Tok::Name(n) => {
+                self.next();
+                Ok(n)
+            }
+            _ => self.unexpected("a name"),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match *self.peek() {
+            Tok::Value(v) => {
+                self.next();
+                Ok(Value(v))
+            }
+            _ => self.unexpected("a value"),
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, ParseError> {
+        match self.peek().clone() {
+            Tok::Int(ref i) => match i.parse() {
+                Ok(n) => {
+                    self.next();
+                    Ok(n)
+                }
+                Err(_) => self.error(format!("`{}` is out of range", i)),
+            },
+            _ => self.unexpected("a number"),
+        }
+    }
+
+    fn block_id(&mut self) -> Result<BlockId, ParseError> {
+        let found = match *self.peek() {
+            Tok::Word(ref w) if w.starts_with("bb") => w[2..].parse().ok(),
+            _ => None,
+        };
+        match found {
+            Some(b) => {
+                self.next();
+                Ok(BlockId(b))
+            }
+            None => self.unexpected("a block"),
+        }
+    }
+
+    fn ty(&mut self) -> Result<Ty, ParseError> {
+        let name = self.word()?;
+        match Ty::from_name(&name) {
+            Some(ty) => Ok(ty),
+            None => {
+                self.pos -= 1;
+                self.error(format!("unknown type `{}`", name))
+            }
+        }
+    }
+
+    fn module(&mut self) -> Result<Module, ParseError> {
+        self.expect_word("overflow")?;
+        let mode = self.word()?;
+        let overflow: OverflowMode = match mode.parse() {
+            Ok(mode) => mode,
+            Err(e) => return self.error(e),
+        };
+        let mut module = Module::new(overflow);
+        if self.is_word("layout") {
+            self.next();
+            match self.next() {
+                Tok::Str(s) => module.layout = s,
+                _ => {
+                    self.pos -= 1;
+                    return self.unexpected("a string");
+                }
+            }
+        }
+
+        loop {
+            if self.is_word("extern") {
+                self.next();
+                let name = self.name()?;
+                self.expect("(")?;
+                let params = self.list(")", |p| p.ty())?;
+                self.expect("->")?;
+                let c_int = self.is_word("cint");
+                let ret = if c_int {
+                    self.next();
+                    Ty::Int
+                } else {
+                    self.ty()?
+                };
+                module.externs.push(Extern { name, params, ret, c_int });
+            } else if self.is_word("vtable") {
+                self.next();
+                if self.number()? as usize != module.vtables.len() {
+                    self.pos -= 1;
+                    return self.error(format!("expected vtable {}", module.vtables.len()));
+                }
+                self.expect("[")?;
+                let methods = self.list("]", |p| p.name())?;
+                module.vtables.push(methods);
+            } else if self.is_word("fn") {
+                let function = self.function()?;
+                module.functions.push(function);
+            } else if *self.peek() == Tok::End {
+                return Ok(module);
+            } else {
+                return self.unexpected("`extern`, `vtable` or `fn`");
+            }
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, ParseError> {
+        self.expect_word("fn")?;
+        let name = self.name()?;
+        self.expect("(")?;
+        let params = self.list(")", |p| {
+            let value = p.value()?;
+            p.expect(":")?;
+            Ok((value, p.ty()?))
+        })?;
+        self.expect("->")?;
+        let ret = self.ty()?;
+        let mut function = Function::new(name, params, ret);
+        function.span = self.span()?;
+
+        self.expect("{")?;
+        while self.is_word("let") {
+            self.next();
+            let name = self.word()?;
+            self.expect("=")?;
+            let value = self.value()?;
+            let span = match self.span()? {
+                Some(span) => span,
+                None => return self.unexpected("`!`"),
+            };
+            function.variables.push(Variable { name, value, span });
+        }
+        while !self.is_punct("}") {
+            let id = self.block_id()?;
+            if id.index() != function.blocks.len() {
+                self.pos -= 1;
+                return self.error(format!("expected bb{}", function.blocks.len()));
+            }
+            self.expect(":")?;
+            function.blocks.push(self.block()?);
+        }
+        self.next();
+        Ok(function)
+    }
+
+    fn block(&mut self) -> Result<Block, ParseError> {
+        let mut block = Block::new();
+        while let Tok::Value(_) = *self.peek() {
+            block.insts.push(self.inst()?);
+        }
+        block.terminator = match self.word()?.as_str() {
+            "jump" => Terminator::Jump(self.block_id()?),
+            "branch" => {
+                let cond = self.value()?;
+                self.expect(",")?;
+                let then = self.block_id()?;
+                self.expect(",")?;
+                Terminator::Branch(cond, then, self.block_id()?)
+            }
+            "ret" => Terminator::Return(self.value()?),
+            "unreachable" => Terminator::Unreachable,
+            other => {
+                self.pos -= 1;
+                return self.error(format!("expected an instruction or terminator, found `{}`", other));
+            }
+        };
+        Ok(block)
+    }
+
+    fn inst(&mut self) -> Result<Inst, ParseError> {
+        let value = self.value()?;
+        self.expect(":")?;
+        let ty = self.ty()?;
+        self.expect("=")?;
+        let op = self.word()?;
+        let kind = match op.as_str() {
+            "const" => InstKind::Const(self.constant()?),
+            "call" => {
+                let callee = match *self.peek() {
+                    Tok::Value(_) => Callee::Indirect(self.value()?),
+                    _ => Callee::Direct(self.name()?),
+                };
+                self.expect("(")?;
+                InstKind::Call(callee, self.list(")", |p| p.value())?)
+            }
+            "new" => {
+                self.expect("(")?;
+                InstKind::New(self.list(")", |p| p.value())?)
+            }
+            "get" => {
+                let object = self.value()?;
+                self.expect(",")?;
+                InstKind::Get(object, self.number()?)
+            }
+            "set" => {
+                let object = self.value()?;
+                self.expect(",")?;
+                let index = self.number()?;
+                self.expect(",")?;
+                InstKind::Set(object, index, self.value()?)
+            }
+            "phi" => {
+                let mut incoming = Vec::new();
+                // A phi in a block that can't be reached has no values
+                while self.is_punct("[") {
+                    self.next();
+                    let block = self.block_id()?;
+                    self.expect(":")?;
+                    incoming.push((block, self.value()?));
+                    self.expect("]")?;
+                    if !self.is_punct(",") {
+                        break;
+                    }
+                    self.next();
+                    if !self.is_punct("[") {
+                        return self.unexpected("`[`");
+                    }
+                }
+                InstKind::Phi(incoming)
+            }
+            "func" => InstKind::Func(self.name()?),
+            "vtable" => InstKind::VTable(self.number()?),
+            _ => match from_mnemonic(&op) {
+                Some(bin) => {
+                    let a = self.value()?;
+                    self.expect(",")?;
+                    InstKind::Binary(bin, a, self.value()?)
+                }
+                None => {
+                    self.pos -= 1;
+                    return self.error(format!("unknown instruction `{}`", op));
+                }
+            },
+        };
+
+        let mut inst = Inst::new(value, ty, kind);
+        inst.span = self.span()?;
+        Ok(inst)
+    }
+
+    /// Reads the span a function or instruction came from, if it has one
+    fn span(&mut self) -> Result<Option<TextSpan>, ParseError> {
+        if !self.is_punct("!") {
+            return Ok(None);
+        }
+        self.next();
+        let start = self.number()? as usize;
+        self.expect("..")?;
+        Ok(Some(TextSpan::new(start, self.number()? as usize)))
+    }
+
+    fn constant(&mut self) -> Result<Const, ParseError> {
+        let c = match self.next() {
+            Tok::Punct("(") => {
+                self.expect(")")?;
+                Const::Unit
+            }
+            Tok::Int(i) => Const::Int(i.parse().unwrap()),
+            Tok::Float(v) => Const::Float(v),
+            Tok::Str(s) => Const::Str(s),
+            Tok::Word(ref w) if w == "true" => Const::Bool(true),
+            Tok::Word(ref w) if w == "false" => Const::Bool(false),
+            Tok::Word(ref w) if w == "inf" => Const::Float(::std::f64::INFINITY),
+            _ => {
+                self.pos -= 1;
+                return self.unexpected("a constant");
+            }
+        };
+        Ok(c)
+    }
+}
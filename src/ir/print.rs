@@ -0,0 +1,193 @@
+use std::fmt;
+
+use ir::{Block, Callee, Const, Function, Inst, InstKind, Module, Terminator};
+use parser::BinOp;
+
+/// The name an operator is written with in the IR
+pub fn mnemonic(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "add",
+        BinOp::Subtract => "sub",
+        BinOp::Multiply => "mul",
+        BinOp::Divide => "div",
+        BinOp::Power => "pow",
+        BinOp::Equal => "eq",
+        BinOp::NotEqual => "ne",
+        BinOp::Less => "lt",
+        BinOp::LessEqual => "le",
+        BinOp::Greater => "gt",
+        BinOp::GreaterEqual => "ge",
+    }
+}
+
+pub fn from_mnemonic(name: &str) -> Option<BinOp> {
+    let ops = [BinOp::Add, BinOp::Subtract, BinOp::Multiply, BinOp::Divide, BinOp::Power, BinOp::Equal,
+               BinOp::NotEqual, BinOp::Less, BinOp::LessEqual, BinOp::Greater, BinOp::GreaterEqual];
+    ops.iter().find(|&&op| mnemonic(op) == name).cloned()
+}
+
+/// Writes a function name, quoting it unless it's made only of letters, digits and underscores
+pub fn write_name(f: &mut fmt::Formatter, name: &str) -> fmt::Result {
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        write!(f, "@{}", name)
+    } else {
+        write!(f, "@")?;
+        write_str(f, name)
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            '\0' => write!(f, "\\0")?,
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Const {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Const::Unit => write!(f, "()"),
+            &Const::Int(ref i) => write!(f, "{}", i),
+            // Unlike `Display`, `Debug` always includes a `.` or exponent, so the value reads back as a float
+            &Const::Float(v) => write!(f, "{:?}", v),
+            &Const::Bool(b) => write!(f, "{}", b),
+            &Const::Str(ref s) => write_str(f, s),
+        }
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}: {} = ", self.value.0, self.ty.name())?;
+        match self.kind {
+            InstKind::Const(ref c) => write!(f, "const {}", c)?,
+            InstKind::Binary(op, a, b) => write!(f, "{} %{}, %{}", mnemonic(op), a.0, b.0)?,
+            InstKind::Call(ref callee, ref args) => {
+                write!(f, "call ")?;
+                match callee {
+                    &Callee::Direct(ref name) => write_name(f, name)?,
+                    &Callee::Indirect(v) => write!(f, "%{}", v.0)?,
+                }
+                write!(f, "(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    write!(f, "{}%{}", if i > 0 { ", " } else { "" }, arg.0)?;
+                }
+                write!(f, ")")?;
+            }
+            InstKind::New(ref fields) => {
+                write!(f, "new (")?;
+                for (i, field) in fields.iter().enumerate() {
+                    write!(f, "{}%{}", if i > 0 { ", " } else { "" }, field.0)?;
+                }
+                write!(f, ")")?;
+            }
+            InstKind::Get(object, index) => write!(f, "get %{}, {}", object.0, index)?,
+            InstKind::Set(object, index, value) => write!(f, "set %{}, {}, %{}", object.0, index, value.0)?,
+            InstKind::Phi(ref incoming) => {
+                write!(f, "phi")?;
+                for (i, &(block, value)) in incoming.iter().enumerate() {
+                    write!(f, "{} [bb{}: %{}]", if i > 0 { "," } else { "" }, block.0, value.0)?;
+                }
+            }
+            InstKind::Func(ref name) => {
+                write!(f, "func ")?;
+                write_name(f, name)?;
+            }
+            InstKind::VTable(index) => write!(f, "vtable {}", index)?,
+        }
+        if let Some(span) = self.span {
+            write!(f, " !{}..{}", span.start(), span.end())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Terminator::Jump(target) => write!(f, "jump bb{}", target.0),
+            &Terminator::Branch(cond, then, otherwise) => write!(f, "branch %{}, bb{}, bb{}", cond.0, then.0, otherwise.0),
+            &Terminator::Return(value) => write!(f, "ret %{}", value.0),
+            &Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter, index: usize, block: &Block) -> fmt::Result {
+    writeln!(f, "bb{}:", index)?;
+    for inst in &block.insts {
+        writeln!(f, "    {}", inst)?;
+    }
+    writeln!(f, "    {}", block.terminator)
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fn ")?;
+        write_name(f, &self.name)?;
+        write!(f, "(")?;
+        for (i, &(value, ty)) in self.params.iter().enumerate() {
+            write!(f, "{}%{}: {}", if i > 0 { ", " } else { "" }, value.0, ty.name())?;
+        }
+        write!(f, ") -> {}", self.ret.name())?;
+        if let Some(span) = self.span {
+            write!(f, " !{}..{}", span.start(), span.end())?;
+        }
+        writeln!(f, " {{")?;
+        for variable in &self.variables {
+            let span = variable.span;
+            writeln!(f, "    let {} = %{} !{}..{}", variable.name, variable.value.0, span.start(), span.end())?;
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            write_block(f, i, block)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// Writes the module in the form `parse_module` reads
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "overflow {}", self.overflow.name())?;
+        if !self.layout.is_empty() {
+            write!(f, "layout ")?;
+            write_str(f, &self.layout)?;
+            writeln!(f)?;
+        }
+        if !self.externs.is_empty() {
+            writeln!(f)?;
+        }
+        for e in &self.externs {
+            write!(f, "extern ")?;
+            write_name(f, &e.name)?;
+            let params: Vec<&str> = e.params.iter().map(|p| p.name()).collect();
+            writeln!(f, "({}) -> {}", params.join(", "), if e.c_int { "cint" } else { e.ret.name() })?;
+        }
+        if !self.vtables.is_empty() {
+            writeln!(f)?;
+        }
+        for (i, vtable) in self.vtables.iter().enumerate() {
+            write!(f, "vtable {} [", i)?;
+            for (j, name) in vtable.iter().enumerate() {
+                if j > 0 {
+                    write!(f, ", ")?;
+                }
+                write_name(f, name)?;
+            }
+            writeln!(f, "]")?;
+        }
+        for function in &self.functions {
+            write!(f, "\n{}", function)?;
+        }
+        Ok(())
+    }
+}
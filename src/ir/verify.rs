@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use cfg::{dominates, dominators, Graph};
+use ir::{Callee, Const, Function, InstKind, Module, Terminator, Ty, Value};
+use ir::print::mnemonic;
+use parser::BinOp;
+
+/// Something wrong with a module, and the function it's in, if any
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VerifyError {
+    pub function: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.function {
+            Some(ref name) => write!(f, "in `{}`: {}", name, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Checks that the module is well formed: every value is defined once before it's used, phis agree with the
+/// predecessors of their block, and every instruction gets operands of the types it expects
+///
+/// Code in blocks that can't be reached isn't held to the rule about definitions coming before uses, since passes
+/// are free to leave it behind for a later pass to remove.
+pub fn verify_module(module: &Module) -> Vec<VerifyError> {
+    let mut errors = Vec::new();
+    let mut names = HashSet::new();
+    for name in module.externs.iter().map(|e| &e.name).chain(module.functions.iter().map(|f| &f.name)) {
+        if !names.insert(name) {
+            errors.push(VerifyError { function: None, message: format!("`{}` is defined more than once", name) });
+        }
+    }
+    for (i, vtable) in module.vtables.iter().enumerate() {
+        for name in vtable {
+            if module.function(name).is_none() {
+                errors.push(VerifyError {
+                    function: None,
+                    message: format!("vtable {} refers to the unknown function `{}`", i, name),
+                });
+            }
+        }
+    }
+    for function in &module.functions {
+        let mut verifier = Verifier { module, function, types: Vec::new(), errors: Vec::new() };
+        verifier.verify();
+        errors.extend(verifier.errors.into_iter().map(|message| VerifyError {
+            function: Some(function.name.clone()),
+            message,
+        }));
+    }
+    errors
+}
+
+struct Verifier<'a> {
+    module: &'a Module,
+    function: &'a Function,
+    types: Vec<Option<Ty>>,
+    errors: Vec<String>,
+}
+
+/// Where a value is defined: the block, and the position in it, with parameters coming before any block
+#[derive(Clone, Copy)]
+enum Def {
+    Param,
+    Inst(usize, usize),
+}
+
+impl<'a> Verifier<'a> {
+    fn verify(&mut self) {
+        let function = self.function;
+        if function.blocks.is_empty() {
+            self.errors.push("the function has no blocks".into());
+            return;
+        }
+
+        let mut defs = vec![None; function.value_bound()];
+        let values = function.params.iter().map(|&(v, _)| (v, Def::Param)).chain(
+            function.blocks.iter().enumerate().flat_map(|(b, block)| {
+                block.insts.iter().enumerate().map(move |(i, inst)| (inst.value, Def::Inst(b, i)))
+            }));
+        for (value, def) in values {
+            if defs[value.index()].is_some() {
+                self.errors.push(format!("%{} is defined more than once", value.0));
+            }
+            defs[value.index()] = Some(def);
+        }
+        self.types = function.value_types();
+
+        for (b, block) in function.blocks.iter().enumerate() {
+            for target in block.terminator.successors() {
+                if target.index() >= function.blocks.len() {
+                    self.errors.push(format!("bb{} jumps to bb{}, which doesn't exist", b, target.0));
+                }
+            }
+        }
+        if !self.errors.is_empty() {
+            return;
+        }
+
+        let preds = function.predecessors();
+        let idom = dominators(function);
+        // Checks that the definition of a value comes before a use at the given position in a reachable block
+        let available = |value: Value, block: usize, position: usize| match defs.get(value.index()).cloned() {
+            Some(Some(Def::Param)) => true,
+            Some(Some(Def::Inst(b, i))) => if b == block { i < position } else { dominates(&idom, b, block) },
+            _ => false,
+        };
+
+        for (b, block) in function.blocks.iter().enumerate() {
+            let reached = idom[b].is_some();
+            let mut past_phis = false;
+            for (i, inst) in block.insts.iter().enumerate() {
+                if let InstKind::Phi(ref incoming) = inst.kind {
+                    if past_phis {
+                        self.errors.push(format!("the phi %{} comes after other instructions in bb{}", inst.value.0, b));
+                    }
+                    let mut from: Vec<usize> = incoming.iter().map(|&(block, _)| block.index()).collect();
+                    from.sort();
+                    let mut expected = preds[b].clone();
+                    expected.sort();
+                    if from != expected {
+                        self.errors.push(format!("the phi %{} doesn't have one value for each predecessor of bb{}",
+                                                 inst.value.0, b));
+                    }
+                    for &(pred, value) in incoming {
+                        let end = function.blocks.get(pred.index()).map_or(0, |p| p.insts.len());
+                        if reached && idom[pred.index()].is_some() && !available(value, pred.index(), end) {
+                            self.errors.push(format!("%{} isn't defined at the end of bb{}", value.0, pred.0));
+                        }
+                    }
+                } else {
+                    past_phis = true;
+                    for value in inst.kind.operands() {
+                        if reached && !available(value, b, i) {
+                            self.errors.push(format!("%{} is used by %{} before it's defined", value.0, inst.value.0));
+                        }
+                    }
+                }
+                self.check_types(inst.value, inst.ty, &inst.kind);
+            }
+
+            for value in block.terminator.operands() {
+                if reached && !available(value, b, block.insts.len()) {
+                    self.errors.push(format!("%{} is used by the terminator of bb{} before it's defined", value.0, b));
+                }
+            }
+            match block.terminator {
+                Terminator::Branch(cond, _, _) => self.expect(cond, Ty::Bool, || format!("the branch of bb{}", b)),
+                Terminator::Return(value) => {
+                    let ret = function.ret;
+                    self.expect(value, ret, || format!("the return of bb{}", b));
+                }
+                Terminator::Jump(_) | Terminator::Unreachable => {}
+            }
+        }
+    }
+
+    fn ty(&self, value: Value) -> Option<Ty> {
+        self.types.get(value.index()).cloned().and_then(|ty| ty)
+    }
+
+    /// Reports an operand of the wrong type. Undefined operands are reported elsewhere
+    fn expect<F: FnOnce() -> String>(&mut self, value: Value, expected: Ty, user: F) {
+        if let Some(found) = self.ty(value) {
+            if found != expected {
+                self.errors.push(format!("{} expects %{} to be `{}`, but it's `{}`", user(), value.0, expected.name(),
+                                         found.name()));
+            }
+        }
+    }
+
+    fn check_result(&mut self, value: Value, ty: Ty, expected: Ty) {
+        if ty != expected {
+            self.errors.push(format!("%{} should be `{}`, but it's declared as `{}`", value.0, expected.name(), ty.name()));
+        }
+    }
+
+    fn check_types(&mut self, value: Value, ty: Ty, kind: &InstKind) {
+        let user = || format!("%{}", value.0);
+        match kind {
+            &InstKind::Const(ref c) => {
+                if let &Const::Float(v) = c {
+                    if v.is_nan() {
+                        self.errors.push(format!("%{} is NaN, which can't be a constant", value.0));
+                    }
+                }
+                self.check_result(value, ty, c.ty());
+            }
+            &InstKind::Binary(op, a, b) => {
+                if let (Some(lhs), Some(rhs)) = (self.ty(a), self.ty(b)) {
+                    if lhs != rhs {
+                        self.errors.push(format!("%{} applies `{}` to a `{}` and a `{}`", value.0, mnemonic(op),
+                                                 lhs.name(), rhs.name()));
+                    } else if !operator_accepts(op, lhs) {
+                        self.errors.push(format!("%{} applies `{}` to `{}`s", value.0, mnemonic(op), lhs.name()));
+                    } else {
+                        self.check_result(value, ty, if op.is_comparison() { Ty::Bool } else { lhs });
+                    }
+                }
+            }
+            &InstKind::Call(Callee::Direct(ref name), ref args) => {
+                let module = self.module;
+                let signature = match (module.function(name), module.extern_(name)) {
+                    (Some(f), _) => Some((f.params.iter().map(|&(_, ty)| ty).collect::<Vec<_>>(), f.ret)),
+                    (None, Some(e)) => Some((e.params.clone(), e.ret)),
+                    (None, None) => None,
+                };
+                match signature {
+                    Some((params, ret)) => {
+                        if params.len() != args.len() {
+                            self.errors.push(format!("%{} passes {} arguments to `{}`, which takes {}", value.0,
+                                                     args.len(), name, params.len()));
+                        } else {
+                            for (&arg, &param) in args.iter().zip(&params) {
+                                self.expect(arg, param, &user);
+                            }
+                        }
+                        self.check_result(value, ty, ret);
+                    }
+                    None => self.errors.push(format!("%{} calls the unknown function `{}`", value.0, name)),
+                }
+            }
+            &InstKind::Call(Callee::Indirect(f), _) => self.expect(f, Ty::Fn, &user),
+            &InstKind::New(_) => self.check_result(value, ty, Ty::Ptr),
+            &InstKind::Get(object, _) => self.expect(object, Ty::Ptr, &user),
+            &InstKind::Set(object, _, _) => {
+                self.expect(object, Ty::Ptr, &user);
+                self.check_result(value, ty, Ty::Unit);
+            }
+            &InstKind::Phi(ref incoming) => {
+                for &(_, v) in incoming {
+                    self.expect(v, ty, &user);
+                }
+            }
+            &InstKind::Func(ref name) => {
+                if self.module.function(name).is_none() && self.module.extern_(name).is_none() {
+                    self.errors.push(format!("%{} refers to the unknown function `{}`", value.0, name));
+                }
+                self.check_result(value, ty, Ty::Fn);
+            }
+            &InstKind::VTable(index) => {
+                if index as usize >= self.module.vtables.len() {
+                    self.errors.push(format!("%{} refers to vtable {}, which doesn't exist", value.0, index));
+                }
+                self.check_result(value, ty, Ty::Ptr);
+            }
+        }
+    }
+}
+
+/// Returns `true` if the operator works on values of the type: any type can be compared for equality, which compares
+/// the contents of heap objects, but only numbers do arithmetic, and only numbers and strings are ordered
+pub fn operator_accepts(op: BinOp, ty: Ty) -> bool {
+    match op {
+        BinOp::Equal | BinOp::NotEqual => true,
+        BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual => {
+            ty == Ty::Int || ty == Ty::Float || ty == Ty::Str
+        }
+        BinOp::Add => ty == Ty::Int || ty == Ty::Float || ty == Ty::Str,
+        BinOp::Subtract | BinOp::Multiply | BinOp::Divide | BinOp::Power => ty == Ty::Int || ty == Ty::Float,
+    }
+}
+
@@ -0,0 +1,899 @@
+//! Runs compiled programs in the compiler's own process, for `whyc run`
+//!
+//! A program is laid out in memory the way the linker would lay out its object: the code of every function, then a
+//! stub for each import, then the constants. The stubs jump to the imports through an address stored next to them,
+//! so calls only need a 32-bit distance however far away `mmap` put the code from the C library. The memory is
+//! written first and only made executable once it's done, so it's never writable and executable at once.
+//!
+//! Runtime functions like `why_trap` and `why_str_concat` are defined here, and externs are found with `dlsym` in the
+//! compiler and the C and maths libraries.
+//!
+//! Strings and objects are allocated in a heap that `x86::gc` collects, as in executables. Each run of a program gets
+//! a heap of its own, which the functions that allocate find through the thread running it. The stack maps are laid
+//! out in memory with the addresses the calls return to, and the functions that allocate are entered through stubs
+//! that save the callee-saved registers, like the runtime's `collecting!`, so the collector walks the stack the same
+//! way. The heap lasts until the program is run again or dropped, so its final value can still be read.
+//!
+//! Functions can tier up: each is then called through an entry that counts its calls down, and jumps to
+//! `whyc_jit_tier_up` when the count gets to zero. That compiles the function again with every optimization, in pages
+//! of its own whose calls to the other functions go through their entries, and points the entry at the new code, so
+//! calls already compiled to go through it get the new code too. The counters and targets the entries read are on
+//! pages that stay writable, apart from the code. The new code's stack maps are added to those the heap walks.
+
+use std::arch::global_asm;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fmt::Write;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::slice;
+
+use arith::{int_binary, ArithError, OverflowMode};
+use bignum::Integer;
+use ffi::Library;
+use ir::{Module, Ty};
+use opt::{OptLevel, PassManager};
+use parser::BinOp;
+use text::Document;
+use x86::gc::{Heap, Stats};
+use x86::show::show;
+use x86::{compile_function, compile_module, encode, function_stack_maps, Datum, FixupKind, MapWord, Program, Sym};
+
+extern "C" {
+    fn mmap(addr: *mut c_void, length: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, length: usize, prot: c_int) -> c_int;
+    fn munmap(addr: *mut c_void, length: usize) -> c_int;
+    fn fflush(stream: *mut c_void) -> c_int;
+
+    fn whyc_jit_new(count: i64, pointers: u64) -> *mut u64;
+    fn whyc_jit_str_concat(a: *const u8, b: *const u8) -> *const u8;
+    fn whyc_jit_str_from_c(s: *const u8) -> *const u8;
+
+    /// Compiles a hot function again, given its number in `r11`, and jumps to the new code
+    fn whyc_jit_tier_up();
+
+    /// Calls the `main` of a program from a frame the collector knows is the last
+    fn whyc_jit_start(main: *const u8) -> i64;
+    static whyc_jit_returned: u8;
+}
+
+/// Defines a function that allocates, which calls `$target` with its arguments and the frame it makes in `$frame`,
+/// the register after them. The frame holds the callee-saved registers of the code that called it, in the order the
+/// stack maps number them
+macro_rules! collecting {
+    ($name:expr, $target:ident, $frame:expr) => {
+        global_asm!(
+            concat!(".globl ", $name),
+            concat!(".hidden ", $name),
+            concat!(".type ", $name, ", @function"),
+            concat!($name, ":"),
+            "    push rbp",
+            "    mov rbp, rsp",
+            "    push rbx",
+            "    push r12",
+            "    push r13",
+            "    push r14",
+            "    push r15",
+            "    sub rsp, 8",
+            concat!("    mov ", $frame, ", rbp"),
+            "    call {target}",
+            "    leave",
+            "    ret",
+            target = sym $target,
+        );
+    };
+}
+
+collecting!("whyc_jit_new", why_new, "rdx");
+collecting!("whyc_jit_str_concat", why_str_concat, "rdx");
+collecting!("whyc_jit_str_from_c", why_str_from_c, "rsi");
+
+// A function's entry jumps here with the function's number in `r11` once it's hot, to compile it again and go on to
+// the code that's made, keeping the arguments in their registers. There's a frame so the call into Rust is aligned
+global_asm!(
+    ".globl whyc_jit_tier_up",
+    ".hidden whyc_jit_tier_up",
+    ".type whyc_jit_tier_up, @function",
+    "whyc_jit_tier_up:",
+    "    push rbp",
+    "    mov rbp, rsp",
+    "    push rdi",
+    "    push rsi",
+    "    push rdx",
+    "    push rcx",
+    "    push r8",
+    "    push r9",
+    "    push rax",
+    "    sub rsp, 136",
+    "    movdqu [rsp], xmm0",
+    "    movdqu [rsp + 16], xmm1",
+    "    movdqu [rsp + 32], xmm2",
+    "    movdqu [rsp + 48], xmm3",
+    "    movdqu [rsp + 64], xmm4",
+    "    movdqu [rsp + 80], xmm5",
+    "    movdqu [rsp + 96], xmm6",
+    "    movdqu [rsp + 112], xmm7",
+    "    mov rdi, r11",
+    "    call {tier_up}",
+    "    mov r11, rax",
+    "    movdqu xmm0, [rsp]",
+    "    movdqu xmm1, [rsp + 16]",
+    "    movdqu xmm2, [rsp + 32]",
+    "    movdqu xmm3, [rsp + 48]",
+    "    movdqu xmm4, [rsp + 64]",
+    "    movdqu xmm5, [rsp + 80]",
+    "    movdqu xmm6, [rsp + 96]",
+    "    movdqu xmm7, [rsp + 112]",
+    "    add rsp, 136",
+    "    pop rax",
+    "    pop r9",
+    "    pop r8",
+    "    pop rcx",
+    "    pop rdx",
+    "    pop rsi",
+    "    pop rdi",
+    "    pop rbp",
+    "    jmp r11",
+    tier_up = sym why_tier_up,
+);
+
+// The collector stops walking the stack once it gets back to `whyc_jit_start`
+global_asm!(
+    ".globl whyc_jit_start",
+    ".hidden whyc_jit_start",
+    ".type whyc_jit_start, @function",
+    "whyc_jit_start:",
+    "    push rbp",
+    "    mov rbp, rsp",
+    "    call rdi",
+    ".globl whyc_jit_returned",
+    ".hidden whyc_jit_returned",
+    "whyc_jit_returned:",
+    "    pop rbp",
+    "    ret",
+);
+
+thread_local! {
+    /// The heap of the program running on the thread, if one is
+    static HEAP: Cell<*mut Heap> = Cell::new(ptr::null_mut());
+
+    /// The program running on the thread, if one is, whose hot functions are compiled again
+    static RUNNING: Cell<*mut Jit> = Cell::new(ptr::null_mut());
+}
+
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const PROT_EXEC: c_int = 4;
+const MAP_PRIVATE: c_int = 2;
+const MAP_ANONYMOUS: c_int = 0x20;
+
+const PAGE_SIZE: usize = 4096;
+
+/// The size of a stub: `jmp *0(%rip)` followed by the address it jumps to
+const STUB_SIZE: usize = 16;
+
+/// The size of the entry of a function whose calls are counted
+const ENTRY_SIZE: usize = 32;
+
+/// How many times a function is called before it's compiled again with every optimization
+pub const HOT_CALLS: u64 = 1000;
+
+/// Pages of memory from `mmap`, which are unmapped when it's dropped
+#[derive(Debug)]
+struct Pages {
+    base: *mut u8,
+    size: usize,
+}
+
+impl Pages {
+    fn new(size: usize) -> Result<Pages, String> {
+        let size = align(size.max(1), PAGE_SIZE);
+        let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+        let base = unsafe { mmap(ptr::null_mut(), size, PROT_READ | PROT_WRITE, flags, -1, 0) };
+        if base as isize == -1 {
+            return Err(format!("can't map {} bytes of memory", size));
+        }
+        Ok(Pages { base: base as *mut u8, size })
+    }
+
+    /// Makes the pages that hold the first `size` bytes executable, and no longer writable
+    fn make_executable(&mut self, size: usize) -> Result<(), String> {
+        match unsafe { mprotect(self.base as *mut c_void, align(size, PAGE_SIZE), PROT_READ | PROT_EXEC) } {
+            0 => Ok(()),
+            _ => Err("can't make the code executable".into()),
+        }
+    }
+}
+
+impl Drop for Pages {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.base as *mut c_void, self.size);
+        }
+    }
+}
+
+/// Compiled code laid out in memory the way the linker would lay out its object, ahead of running it
+#[derive(Debug)]
+struct Image {
+    pages: Pages,
+
+    /// Where calls to each function go: its entry if it has one, or else its code
+    functions: HashMap<String, usize>,
+
+    /// The offset of the code of each function
+    code: Vec<usize>,
+
+    /// The offset of the counter and target of the entry of each function, if they have entries
+    counters: Option<usize>,
+
+    /// The stack maps of the calls, laid out as `why_stack_maps` is but without the count it starts with
+    maps: Vec<u64>,
+    calls: u64,
+}
+
+impl Image {
+    /// Lays a compiled program out in memory, with `resolve` giving the address of each symbol it uses but doesn't
+    /// define. With `entries` set, each function is called through an entry that counts down from `HOT_CALLS` and
+    /// goes to `whyc_jit_tier_up` once it gets to zero
+    fn load<F: FnMut(&str) -> Result<usize, String>>(program: &Program, entries: bool, mut resolve: F)
+                                                       -> Result<Image, String> {
+        let codes: Vec<_> = program.functions.iter().map(|f| encode(&f.insts)).collect();
+
+        // Work out where everything goes before writing anything
+        let mut code = Vec::new();
+        let mut size = 0;
+        for bytes in codes.iter().map(|c| &c.bytes) {
+            size = align(size, 16);
+            code.push(size);
+            size += bytes.len();
+        }
+        size = align(size, 16);
+        let first_entry = size;
+        let mut functions = HashMap::new();
+        for (i, function) in program.functions.iter().enumerate() {
+            functions.insert(function.symbol.clone(), if entries { first_entry + ENTRY_SIZE * i } else { code[i] });
+        }
+        if entries {
+            size += ENTRY_SIZE * program.functions.len();
+        }
+        let stubs = size;
+        size += STUB_SIZE * program.imports.len();
+        let mut data = Vec::new();
+        for datum in program.data.items() {
+            size = align(size, 8);
+            match *datum {
+                Datum::Str(ref s) => {
+                    data.push(size + 16);
+                    size += 16 + s.len() + 1;
+                }
+                Datum::Location(ref location) => {
+                    data.push(size);
+                    size += location.len() + 1;
+                }
+                Datum::VTable(ref functions) => {
+                    data.push(size);
+                    size += 8 * functions.len();
+                }
+            }
+        }
+        // The counters are written while the program runs, so they're on pages of their own
+        let executable = size;
+        let counters = if entries { Some(align(size, PAGE_SIZE)) } else { None };
+        if let Some(counters) = counters {
+            size = counters + 16 * program.functions.len() + 8;
+        }
+
+        let mut pages = Pages::new(size)?;
+        let base = pages.base as usize;
+        let memory = unsafe { slice::from_raw_parts_mut(pages.base, pages.size) };
+        let address = |sym: &Sym| -> usize {
+            match *sym {
+                Sym::Global(ref name) => match functions.get(name) {
+                    Some(&offset) => offset,
+                    None => stubs + STUB_SIZE * program.imports.iter().position(|i| i == name).unwrap(),
+                },
+                Sym::Data(n) => data[n as usize],
+            }
+        };
+
+        for (start, code) in code.iter().zip(&codes) {
+            memory[*start..*start + code.bytes.len()].copy_from_slice(&code.bytes);
+            for fixup in &code.fixups {
+                // Both kinds are a distance, since the stubs are as near as the functions
+                let at = start + fixup.offset;
+                let distance = address(&fixup.sym) as i64 + fixup.addend - at as i64;
+                match fixup.kind {
+                    FixupKind::Pc32 | FixupKind::Plt32 => write_le(&mut memory[at..at + 4], distance as u64),
+                }
+            }
+        }
+
+        if let Some(counters) = counters {
+            // dec qword [rip + counter]; jz 1f; jmp [rip + target]; 1: mov r11d, i; jmp [rip + tier_up]
+            let tier_up = counters + 16 * program.functions.len();
+            for (i, &start) in code.iter().enumerate() {
+                let (at, counter) = (first_entry + ENTRY_SIZE * i, counters + 16 * i);
+                let entry = &mut memory[at..at + ENTRY_SIZE];
+                entry.copy_from_slice(&[0xcc; ENTRY_SIZE]);
+                entry[0..3].copy_from_slice(&[0x48, 0xff, 0x0d]);
+                write_le(&mut entry[3..7], (counter - (at + 7)) as u64);
+                entry[7..11].copy_from_slice(&[0x74, 0x06, 0xff, 0x25]);
+                write_le(&mut entry[11..15], (counter + 8 - (at + 15)) as u64);
+                entry[15..17].copy_from_slice(&[0x41, 0xbb]);
+                write_le(&mut entry[17..21], i as u64);
+                entry[21..23].copy_from_slice(&[0xff, 0x25]);
+                write_le(&mut entry[23..27], (tier_up - (at + 27)) as u64);
+                write_le(&mut memory[counter..counter + 8], HOT_CALLS);
+                write_le(&mut memory[counter + 8..counter + 16], (base + start) as u64);
+            }
+            write_le(&mut memory[tier_up..tier_up + 8], whyc_jit_tier_up as usize as u64);
+        }
+
+        for (i, import) in program.imports.iter().enumerate() {
+            let at = stubs + STUB_SIZE * i;
+            memory[at..at + 6].copy_from_slice(&[0xff, 0x25, 0, 0, 0, 0]);
+            write_le(&mut memory[at + 6..at + 14], resolve(import)? as u64);
+        }
+
+        for (datum, &at) in program.data.items().iter().zip(&data) {
+            match *datum {
+                Datum::Str(ref s) => {
+                    write_le(&mut memory[at - 8..at], s.len() as u64);
+                    memory[at..at + s.len()].copy_from_slice(s.as_bytes());
+                }
+                Datum::Location(ref location) => memory[at..at + location.len()].copy_from_slice(location.as_bytes()),
+                Datum::VTable(ref vtable) => {
+                    for (i, function) in vtable.iter().enumerate() {
+                        let target = match functions.get(function) {
+                            Some(&offset) => base + offset,
+                            None => resolve(function)?,
+                        };
+                        write_le(&mut memory[at + 8 * i..at + 8 * i + 8], target as u64);
+                    }
+                }
+            }
+        }
+
+        let mut maps = Vec::new();
+        for f in 0..program.functions.len() {
+            maps.extend(function_stack_maps(program, f).into_iter().map(|word| match word {
+                MapWord::Value(value) => value,
+                MapWord::Return(f, k) => {
+                    let (encoded, inst) = (&codes[f], program.functions[f].safepoints[k].inst);
+                    let offset = encoded.offsets.get(inst + 1).map_or(encoded.bytes.len(), |&offset| offset);
+                    (base + code[f] + offset) as u64
+                }
+            }));
+        }
+        let calls = program.functions.iter().map(|f| f.safepoints.len() as u64).sum();
+
+        pages.make_executable(executable)?;
+        Ok(Image { pages, functions, code, counters, maps, calls })
+    }
+
+    /// Gets the address calls to a function of the image go to
+    fn address(&self, symbol: &str) -> Option<usize> {
+        self.functions.get(symbol).map(|&offset| self.pages.base as usize + offset)
+    }
+
+    /// Sends the calls through the entry of function `f` straight to `target` from now on
+    fn retarget(&mut self, f: usize, target: usize) {
+        let counter = self.counters.expect("Expected the functions to have entries") + 16 * f;
+        unsafe {
+            let words = self.pages.base.offset(counter as isize) as *mut u64;
+            *words = ::std::u64::MAX;
+            *words.offset(1) = target as u64;
+        }
+    }
+}
+
+/// A program loaded into memory to run, whose functions are compiled again with every optimization once they've
+/// been called `HOT_CALLS` times, if it's loaded to tier up
+#[derive(Debug)]
+pub struct Jit {
+    image: Image,
+
+    /// The name in the module of each function of the image
+    names: Vec<String>,
+
+    /// The module the program was compiled from, and a copy with every optimization made once a function is hot
+    module: Module,
+    optimized: Option<Module>,
+
+    /// The functions that were compiled again, each in an image of its own
+    hot: Vec<(String, Image)>,
+
+    /// The libraries externs were found in, which have to stay open while the code can call them
+    libraries: Vec<Library>,
+
+    /// The stack maps of every image, laid out as `why_stack_maps` is in an executable
+    maps: Vec<u64>,
+
+    /// The heap of the last run
+    heap: Option<Heap>,
+}
+
+impl Jit {
+    /// Compiles a module into memory, finding each of its imports in the runtime or a library, with failures reported
+    /// at locations in the document if there is one. With `tier_up` set, functions are compiled again when they're hot
+    pub fn new(module: &Module, document: Option<&Document>, tier_up: bool) -> Result<Jit, String> {
+        let program = compile_module(module, document).map_err(|e| e.to_string())?;
+        let libraries = vec![Library::open(None)?, Library::libc()?, Library::open(Some("libm.so.6"))?];
+        let image = Image::load(&program, tier_up, |name| import(&libraries, name))?;
+        let mut maps = vec![image.calls];
+        maps.extend(&image.maps);
+        Ok(Jit {
+            image,
+            names: program.functions.iter().map(|f| f.name.clone()).collect(),
+            module: module.clone(),
+            optimized: None,
+            hot: Vec::new(),
+            libraries,
+            maps,
+            heap: None,
+        })
+    }
+
+    /// Gets the address calls to a function of the program go to
+    pub fn function(&self, symbol: &str) -> Option<*const u8> {
+        self.image.address(symbol).map(|address| address as *const u8)
+    }
+
+    /// Gets the names of the functions that were compiled again because they were hot, in the order they got hot
+    pub fn hot_functions(&self) -> Vec<&str> {
+        self.hot.iter().map(|&(ref name, _)| name.as_str()).collect()
+    }
+
+    /// Runs the program, returning the bits of the value of its final expression, which has the type `ret`. With
+    /// `stress` set, the heap is collected before every allocation
+    ///
+    /// A program that fails stops the process, as a compiled program would.
+    pub fn run(&mut self, ret: Ty, stress: bool) -> i64 {
+        let main = self.function("why_main").expect("Expected the program to have a `why_main`");
+        let returned = unsafe { &whyc_jit_returned as *const u8 as u64 };
+        self.heap = Some(Heap::new(self.maps.as_ptr(), returned, Some(stress)));
+        let heap: *mut Heap = self.heap.as_mut().unwrap();
+        HEAP.with(|h| h.set(heap));
+        RUNNING.with(|r| r.set(self));
+        unsafe {
+            // A `float` comes back in `xmm0` rather than `rax`
+            let value = if ret == Ty::Float {
+                let start: unsafe extern "C" fn(*const u8) -> i64 = whyc_jit_start;
+                let start: extern "C" fn(*const u8) -> f64 = ::std::mem::transmute(start);
+                start(main).to_bits() as i64
+            } else {
+                whyc_jit_start(main)
+            };
+            HEAP.with(|h| h.set(ptr::null_mut()));
+            RUNNING.with(|r| r.set(ptr::null_mut()));
+            // What externs like `puts` wrote comes before anything printed after
+            fflush(ptr::null_mut());
+            value
+        }
+    }
+
+    /// Gets how much the last run allocated and collected
+    pub fn heap_stats(&self) -> Option<Stats> {
+        self.heap.as_ref().map(|heap| heap.stats)
+    }
+
+    /// Compiles function `f` of the image again with every optimization, returning where its calls go from now on:
+    /// the new code, or the old if it couldn't be compiled
+    fn tier_up(&mut self, f: usize) -> usize {
+        let target = match self.compile_hot(f) {
+            Some(target) => target,
+            None => self.image.pages.base as usize + self.image.code[f],
+        };
+        self.image.retarget(f, target);
+        target
+    }
+
+    fn compile_hot(&mut self, f: usize) -> Option<usize> {
+        let module = &self.module;
+        let optimized = self.optimized.get_or_insert_with(|| {
+            let mut optimized = module.clone();
+            PassManager::for_level(OptLevel::O2).run(&mut optimized);
+            optimized
+        });
+        let program = match compile_function(optimized, &self.names[f], None) {
+            Some(Ok(program)) => program,
+            _ => return None,
+        };
+        let (image, libraries) = (&self.image, &self.libraries);
+        let hot = Image::load(&program, false, |name| match image.address(name) {
+            Some(address) => Ok(address),
+            None => import(libraries, name),
+        }).ok()?;
+        let target = hot.address(&program.functions[0].symbol)?;
+
+        // The new code's frames are walked like any other's
+        self.maps[0] += hot.calls;
+        self.maps.extend(&hot.maps);
+        if let Some(ref mut heap) = self.heap {
+            heap.set_maps(self.maps.as_ptr());
+        }
+        self.hot.push((self.names[f].clone(), hot));
+        Some(target)
+    }
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+/// Writes the low bytes of a value, least significant first
+fn write_le(bytes: &mut [u8], value: u64) {
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (value >> (8 * i)) as u8;
+    }
+}
+
+/// Finds a symbol a program imports in the runtime or a library
+fn import(libraries: &[Library], name: &str) -> Result<usize, String> {
+    match runtime_function(name) {
+        Some(target) => Ok(target),
+        None => find(libraries, name),
+    }
+}
+
+fn find(libraries: &[Library], name: &str) -> Result<usize, String> {
+    libraries.iter().filter_map(|l| l.symbol(name).ok()).next()
+        .map(|address| address as usize)
+        .ok_or_else(|| format!("can't find `{}` in the runtime or the C library", name))
+}
+
+/// Formats the value of a program's final expression, given the type of `main`, the description of the type of an
+/// object it returns, and the overflow mode of the program, which says how its `int`s are stored
+pub fn format_value(value: i64, ty: Ty, layout: &str, overflow: OverflowMode) -> String {
+    match ty {
+        Ty::Unit => "()".into(),
+        Ty::Int if overflow == OverflowMode::Promote => promoted(value).to_string(),
+        Ty::Int => value.to_string(),
+        Ty::Float => format!("{:?}", f64::from_bits(value as u64)),
+        Ty::Bool => (value != 0).to_string(),
+        Ty::Str => unsafe { String::from_utf8_lossy(str_bytes(value as *const u8)).into_owned() },
+        Ty::Ptr => {
+            let mut text = String::new();
+            let int = |out: &mut String, i: i64| match overflow {
+                OverflowMode::Promote => write!(out, "{}", promoted(i)),
+                _ => write!(out, "{}", i),
+            };
+            show(&mut text, layout.as_bytes(), value, &int).expect("Expected writing to a string to succeed");
+            text
+        }
+        Ty::Fn => "<fn>".into(),
+    }
+}
+
+/// Gets the bytes of a string, whose length is in the word before them
+unsafe fn str_bytes<'a>(s: *const u8) -> &'a [u8] {
+    let len = *(s as *const u64).offset(-1);
+    slice::from_raw_parts(s, len as usize)
+}
+
+fn runtime_function(name: &str) -> Option<usize> {
+    Some(match name {
+        "why_trap" => why_trap as usize,
+        "why_int_pow" => why_int_pow as usize,
+        "why_float_pow" => why_float_pow as usize,
+        "why_str_concat" => whyc_jit_str_concat as usize,
+        "why_str_from_c" => whyc_jit_str_from_c as usize,
+        "why_str_cmp" => why_str_cmp as usize,
+        "why_new" => whyc_jit_new as usize,
+        "why_big_parse" => why_big_parse as usize,
+        "why_big_binary" => why_big_binary as usize,
+        "why_big_cmp" => why_big_cmp as usize,
+        "why_big_to_word" => why_big_to_word as usize,
+        "why_big_from_word" => why_big_from_word as usize,
+        _ => return None,
+    })
+}
+
+/// Compiles function `f` of the program running on the thread again, returning where to go on to
+extern "C" fn why_tier_up(f: u64) -> u64 {
+    let jit = RUNNING.with(|r| r.get());
+    assert!(!jit.is_null(), "Expected a program to be running");
+    unsafe { (*jit).tier_up(f as usize) as u64 }
+}
+
+/// Gets the heap of the program running on the thread
+fn heap() -> &'static mut Heap {
+    let heap = HEAP.with(|h| h.get());
+    assert!(!heap.is_null(), "Expected a program to be running");
+    unsafe { &mut *heap }
+}
+
+fn trap_message(kind: i64) -> String {
+    match kind {
+        0 => "attempt to compute a result that overflows".into(),
+        1 => ArithError::DivideByZero.to_string(),
+        _ => ArithError::NegativeExponent.to_string(),
+    }
+}
+
+extern "C" fn why_trap(kind: i64, location: *const c_char) -> ! {
+    let location = unsafe {
+        fflush(ptr::null_mut());
+        CStr::from_ptr(location).to_string_lossy()
+    };
+    eprintln!("{}: error: {}", location, trap_message(kind));
+    ::std::process::exit(101)
+}
+
+extern "C" fn why_int_pow(a: i64, b: i64, mode: i64, location: *const c_char) -> i64 {
+    let mode = match mode {
+        0 => OverflowMode::Trap,
+        1 => OverflowMode::Wrap,
+        _ => OverflowMode::Saturate,
+    };
+    match int_binary(mode, BinOp::Power, &Integer::from(a), &Integer::from(b)) {
+        Ok(result) => result.to_i64().expect("Expected the result to fit in 64 bits"),
+        Err(ArithError::NegativeExponent) => why_trap(2, location),
+        Err(_) => why_trap(0, location),
+    }
+}
+
+extern "C" fn why_float_pow(a: f64, b: f64) -> f64 {
+    a.powf(b)
+}
+
+extern "C" fn why_str_concat(a: *const u8, b: *const u8, frame: *const u64) -> *const u8 {
+    unsafe { heap().concat(a, b, frame) }
+}
+
+extern "C" fn why_str_from_c(s: *const u8, frame: *const u64) -> *const u8 {
+    unsafe { heap().from_c(s, frame) }
+}
+
+extern "C" fn why_str_cmp(a: *const u8, b: *const u8) -> i64 {
+    unsafe { str_bytes(a).cmp(str_bytes(b)) as i64 }
+}
+
+extern "C" fn why_new(count: i64, pointers: u64, frame: *const u64) -> *mut u64 {
+    unsafe { heap().new_object(count, pointers, frame) }
+}
+
+/// Gets an `int` of a program compiled in the `promote` mode, which is an integer of 63 bits shifted left with the
+/// low bit set, or else the address of a boxed `Integer`
+pub fn promoted(value: i64) -> Integer {
+    if value & 1 == 1 {
+        Integer::Small(value >> 1)
+    } else {
+        unsafe { (*(value as *const Integer)).clone() }
+    }
+}
+
+/// Stores an integer as an `int` of the `promote` mode, boxing it if it needs more than 63 bits. Boxes are never freed
+fn promote(value: Integer) -> i64 {
+    match value.to_i64() {
+        Some(v) if v >> 62 == 0 || v >> 62 == -1 => v << 1 | 1,
+        _ => Box::into_raw(Box::new(value)) as i64,
+    }
+}
+
+extern "C" fn why_big_parse(digits: *const u8) -> i64 {
+    let digits = unsafe { String::from_utf8_lossy(str_bytes(digits)).into_owned() };
+    promote(digits.parse().expect("Expected the constant to be an integer"))
+}
+
+/// Applies an arithmetic operator, numbered in the order `BinOp` declares them, to promoted integers
+extern "C" fn why_big_binary(a: i64, b: i64, op: i64, location: *const c_char) -> i64 {
+    let op = [BinOp::Add, BinOp::Subtract, BinOp::Multiply, BinOp::Divide, BinOp::Power][op as usize];
+    match int_binary(OverflowMode::Promote, op, &promoted(a), &promoted(b)) {
+        Ok(result) => promote(result),
+        Err(ArithError::DivideByZero) => why_trap(1, location),
+        Err(ArithError::NegativeExponent) => why_trap(2, location),
+        Err(ArithError::Overflow(_)) => why_trap(0, location),
+    }
+}
+
+extern "C" fn why_big_cmp(a: i64, b: i64) -> i64 {
+    if a & b & 1 == 1 {
+        return (a >> 1).cmp(&(b >> 1)) as i64;
+    }
+    promoted(a).cmp(&promoted(b)) as i64
+}
+
+/// Gets a promoted integer as a plain word to pass to C, stopping the program if it doesn't fit in one
+extern "C" fn why_big_to_word(value: i64, location: *const c_char) -> i64 {
+    match promoted(value).to_i64() {
+        Some(word) => word,
+        None => why_trap(0, location),
+    }
+}
+
+extern "C" fn why_big_from_word(word: i64) -> i64 {
+    promote(Integer::Small(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::{lower_program, Module};
+    use mono::monomorphize;
+    use opt::{OptLevel, PassManager};
+    use parser::Parser;
+    use resolve::resolve_program;
+    use tokenizer::Tokenizer;
+    use typeck;
+
+    /// Loads a program optimized at `level`, which tiers up if that's below `-O2`
+    fn load_at(code: &str, mode: OverflowMode, level: OptLevel) -> (Module, Jit) {
+        let program = Parser::new(Tokenizer::new(code)).program().expect("Expected the parse to succeed");
+        let resolution = resolve_program(&program);
+        let results = typeck::check_program(&program);
+        let mono = monomorphize(&program, &results);
+        let mut module = lower_program(&program, &resolution, &results, &mono, mode);
+        PassManager::for_level(level).run(&mut module);
+        let jit = Jit::new(&module, None, level < OptLevel::O2).expect("Expected the program to load");
+        (module, jit)
+    }
+
+    fn load(code: &str, mode: OverflowMode) -> (Module, Jit) {
+        load_at(code, mode, OptLevel::O2)
+    }
+
+    fn run(code: &str, mode: OverflowMode) -> String {
+        let (module, mut jit) = load(code, mode);
+        let ret = module.function("main").unwrap().ret;
+        format_value(jit.run(ret, false), ret, &module.layout, mode)
+    }
+
+    #[test]
+    pub fn runs_compiled_code() {
+        let code = "\
+def fib(n) { match n < 2 { true => n, false => fib(n - 1) + fib(n - 2) } }
+fib(20) + 2 ** 10";
+        assert_eq!("7789", run(code, OverflowMode::Trap));
+        assert_eq!("-9223372036854775808", run("let m = 9223372036854775807; m + 1", OverflowMode::Wrap));
+        assert_eq!("9223372036854775807", run("let m = 2; m ** 100", OverflowMode::Saturate));
+        assert_eq!("2.75", run("def half(x: Float) { x / 2.0 } half(5.5)", OverflowMode::Trap));
+        let code = "def clamp(x: Int) -> Int { match x { n if n > 10 => return 10, _ => {} }; x } clamp(3) + clamp(20)";
+        assert_eq!("13", run(code, OverflowMode::Trap));
+        let code = "\
+enum Option<T> { Some(T), None }
+def get(o, default) { match o { Some((x, 0)) => x, Some((_, y)) => y, None => default } }
+get(Some((1, 0)), 5) + get(Some((2, 20)), 5) + get(None, 300)";
+        assert_eq!("321", run(code, OverflowMode::Trap));
+    }
+
+    #[test]
+    pub fn hot_functions_are_compiled_again() {
+        let code = "\
+def fib(n) { match n < 2 { true => n, false => fib(n - 1) + fib(n - 2) } }
+def twice(n) { n * 2 }
+twice(fib(20))";
+        let (_, mut jit) = load_at(code, OverflowMode::Trap, OptLevel::O0);
+        assert_eq!(13530, jit.run(Ty::Int, false));
+        assert_eq!(vec!["fib"], jit.hot_functions());
+        // Calls after it tiers up go straight to the new code, in this run and the next
+        assert_eq!(13530, jit.run(Ty::Int, false));
+        assert_eq!(vec!["fib"], jit.hot_functions());
+
+        // Nothing tiers up that's optimized already
+        let (_, mut jit) = load(code, OverflowMode::Trap);
+        assert_eq!(13530, jit.run(Ty::Int, false));
+        assert!(jit.hot_functions().is_empty());
+    }
+
+    #[test]
+    pub fn strings_objects_and_vtables_use_the_runtime() {
+        assert_eq!("why not", run("def greet(a: Str) { a + \" not\" } greet(\"why\")", OverflowMode::Trap));
+        assert_eq!("true", run("let a = \"apple\" + \"s\"; a < \"apricot\"", OverflowMode::Trap));
+        let code = "\
+struct Point { x: Int, y: Int }
+trait Area { def area(self) -> Int; }
+impl Area for Point { def area(self) -> Int { self.x * self.y } }
+def measure(s: dyn Area) -> Int { s.area() }
+measure(Point { x: 6, y: 7 })";
+        assert_eq!("42", run(code, OverflowMode::Trap));
+    }
+
+    #[test]
+    pub fn final_objects_are_printed_field_by_field() {
+        let code = "struct P { x: Int, name: Str } P { x: 0 - 1, name: \"a\\n\" }";
+        assert_eq!("P { x: -1, name: \"a\\n\" }", run(code, OverflowMode::Trap));
+        let code = "enum Option<T> { Some(T), None } (Some(2.5), None, Some((1, true)))";
+        assert_eq!("(Some(2.5), None, Some((1, true)))", run(code, OverflowMode::Trap));
+        let code = "trait T {} struct E {} impl T for E {} def f(x: Int) { x } let t: dyn T = E {}; (E {}, t, f)";
+        assert_eq!("(E {}, <dyn T>, <fn>)", run(code, OverflowMode::Trap));
+        // An `int` in an object is stored as it is anywhere else
+        assert_eq!("(1267650600228229401496703205376, 1)", run("let two = 2; (two ** 100, 1)", OverflowMode::Promote));
+    }
+
+    #[test]
+    pub fn garbage_is_collected() {
+        // Every concatenation but the last few is garbage by the time the next one collects
+        let code = "extern strlen(s: Str) -> CLong;\nstruct Box { s: Str, n: Int }\n\
+                    def grow(s: Str, n: Int) -> Str { match n { 0 => s, _ => grow(s + \"ab\", n - 1) } }\n\
+                    def go(n: Int, b: Box) -> Box {\n\
+                        let next = Box { s: b.s + \"!\", n: strlen(grow(\"\", 20)) + b.n };\n\
+                        match n { 0 => b, _ => go(n - 1, next) }\n\
+                    }\ngo(300, Box { s: \"\", n: 0 })";
+        let (module, mut jit) = load(code, OverflowMode::Trap);
+        let value = jit.run(Ty::Ptr, false);
+        assert_eq!(format!("Box {{ s: \"{}\", n: 12000 }}", "!".repeat(300)),
+                   format_value(value, Ty::Ptr, &module.layout, OverflowMode::Trap));
+        let stats = jit.heap_stats().unwrap();
+        assert_eq!((0, 6623), (stats.collections, stats.allocated));
+
+        // Collecting at every allocation leaves the program's own values alone, including the one it returns
+        let value = jit.run(Ty::Ptr, true);
+        assert_eq!(format!("Box {{ s: \"{}\", n: 12000 }}", "!".repeat(300)),
+                   format_value(value, Ty::Ptr, &module.layout, OverflowMode::Trap));
+        let stats = jit.heap_stats().unwrap();
+        assert_eq!((6623, 0, 6623), (stats.collections, stats.abandoned, stats.allocated));
+        assert!(stats.allocated - stats.freed < 10, "{}", stats);
+    }
+
+    #[test]
+    pub fn frames_of_hot_functions_are_walked_by_the_collector() {
+        // `grow` and `concat` get hot while strings are live in their frames, and `area` is called through a vtable
+        let code = "\
+struct Square { s: Int }
+trait Area { def area(self) -> Int; }
+impl Area for Square { def area(self) -> Int { self.s * self.s } }
+def concat(a: Str, b: Str) -> Str { a + b }
+def grow(s: Str, n: Int, shape: dyn Area) -> Str {
+    match n { 0 => s, _ => grow(concat(s, \"ab\"), n - shape.area(), shape) }
+}
+def go(n: Int, s: Str) -> Str { match n { 0 => s, _ => go(n - 1, concat(s, grow(\"\", 20, Square { s: 1 }))) } }
+go(100, \"\")";
+        let (module, mut jit) = load_at(code, OverflowMode::Trap, OptLevel::O0);
+        for &stress in &[false, true] {
+            let value = jit.run(Ty::Str, stress);
+            assert_eq!("ab".repeat(2000), format_value(value, Ty::Str, &module.layout, OverflowMode::Trap));
+        }
+        let mut hot = jit.hot_functions();
+        hot.sort();
+        assert_eq!(vec!["<Square as Area>::area", "concat", "grow"], hot);
+        let stats = jit.heap_stats().unwrap();
+        assert_eq!(stats.collections, stats.allocated);
+    }
+
+    #[test]
+    pub fn promoted_integers_are_boxed_when_they_overflow() {
+        let code = "def pow(a, b) { a ** b } def fact(n) { match n < 2 { true => 1, false => n * fact(n - 1) } }";
+        let run = |expr: &str| run(&format!("{}\n{}", code, expr), OverflowMode::Promote);
+        assert_eq!("1606938044258990275541962092341162602522202993782792835301376", run("pow(2, 200)"));
+        assert_eq!("30414093201713378043612608166064768844377641568960512000000000000", run("fact(50)"));
+        assert_eq!("2", run("pow(2, 200) / pow(2, 199)"));
+        assert_eq!("true", run("pow(2, 64) > pow(2, 63) + 1"));
+        assert_eq!("9223372036854775807", run("let m = 9223372036854775806; m + 1"));
+        assert_eq!("-99999999999999999999", run("0 - 99999999999999999999"));
+        // C gets and gives back plain words
+        let code = "extern labs(x: Int) -> CLong;\ndef neg(x) { 0 - x }\nlabs(neg(4611686018427387904)) + 1";
+        assert_eq!("4611686018427387905", self::run(code, OverflowMode::Promote));
+    }
+
+    #[test]
+    pub fn externs_are_found_in_the_c_library() {
+        let code = "\
+extern labs(x: Int) -> CLong;
+extern ldexp(x: Float, e: Int) -> Float;
+labs(0 - 7) + match ldexp(1.5, 3) == 12.0 { true => 100, false => 0 }";
+        assert_eq!("107", run(code, OverflowMode::Trap));
+    }
+
+    #[test]
+    pub fn c_ints_returned_by_externs_keep_their_sign() {
+        let code = "extern strcmp(a: Str, b: Str) -> CInt; strcmp(\"a\", \"b\") * 10";
+        assert_eq!("-10", run(code, OverflowMode::Trap));
+        assert_eq!("-10", run(code, OverflowMode::Promote));
+    }
+
+    #[test]
+    pub fn c_strings_returned_by_externs_are_copied() {
+        ::std::env::set_var("WHYC_JIT_TEST_GETENV", "why");
+        let code = "extern getenv(s: Str) -> Str;\n\
+                    (getenv(\"WHYC_JIT_TEST_GETENV\") + \"!\", getenv(\"WHYC_JIT_TEST_UNSET\"))";
+        let (module, mut jit) = load(code, OverflowMode::Trap);
+        // A null pointer is an empty string, and copies are collected like any other string
+        for &stress in &[false, true] {
+            let value = jit.run(Ty::Ptr, stress);
+            assert_eq!("(\"why!\", \"\")", format_value(value, Ty::Ptr, &module.layout, OverflowMode::Trap));
+        }
+    }
+}
@@ -1,5 +1,7 @@
 #![feature(inclusive_range_syntax, inclusive_range, range_contains, fn_traits)]
 
+// `x86::show` is shared with the `no_std` runtime, so it names `core` rather than `std`
+extern crate core;
 extern crate lazy_init;
 
 #[macro_use]
@@ -7,6 +9,28 @@ mod macros;
 
 mod utils;
 
+#[cfg(test)]
+mod testing;
+
+pub mod arith;
+pub mod bignum;
+pub mod cfg;
+pub mod cgen;
+pub mod diagnostics;
+pub mod driver;
+pub mod elf;
+pub mod ffi;
+pub mod fold;
+pub mod ir;
+pub mod jit;
+pub mod lint;
+pub mod mono;
+pub mod opt;
 pub mod parser;
+pub mod patterns;
+pub mod resolve;
 pub mod text;
+pub mod typeck;
 pub mod tokenizer;
+pub mod wasm;
+pub mod x86;
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use diagnostics::Severity;
+
+/// How a lint's findings are reported
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Allow => "allow",
+            Level::Warn => "warn",
+            Level::Deny => "deny",
+        }
+    }
+
+    /// Gets the severity findings at this level are reported with, or `None` if they aren't reported
+    pub fn severity(self) -> Option<Severity> {
+        match self {
+            Level::Allow => None,
+            Level::Warn => Some(Severity::Warning),
+            Level::Deny => Some(Severity::Error),
+        }
+    }
+
+    /// Gets the level set by a driver option: `-A`, `-W` or `-D`, or their long forms `--allow`, `--warn` and `--deny`
+    pub fn from_flag(flag: &str) -> Option<Level> {
+        match flag {
+            "-A" | "--allow" => Some(Level::Allow),
+            "-W" | "--warn" => Some(Level::Warn),
+            "-D" | "--deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Level, ()> {
+        match s {
+            "allow" => Ok(Level::Allow),
+            "warn" => Ok(Level::Warn),
+            "deny" => Ok(Level::Deny),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A check for code that's valid, but probably not what was meant
+#[derive(Debug)]
+pub struct Lint {
+    pub name: &'static str,
+    pub default: Level,
+    pub description: &'static str,
+}
+
+pub static UNUSED_VARIABLES: Lint = Lint {
+    name: "unused_variables",
+    default: Level::Warn,
+    description: "names bound by a `let` or `match` arm that are never used",
+};
+
+pub static UNUSED_FUNCTIONS: Lint = Lint {
+    name: "unused_functions",
+    default: Level::Warn,
+    description: "functions that are never used",
+};
+
+pub static SHADOWING: Lint = Lint {
+    name: "shadowing",
+    default: Level::Allow,
+    description: "bindings that hide an earlier variable or parameter of the same name",
+};
+
+pub static SELF_ASSIGNMENT: Lint = Lint {
+    name: "self_assignment",
+    default: Level::Warn,
+    description: "assignments of a variable or field to itself",
+};
+
+pub static CONSTANT_CONDITIONS: Lint = Lint {
+    name: "constant_conditions",
+    default: Level::Warn,
+    description: "`match` guards and scrutinees whose value is known when compiling",
+};
+
+pub static UNREACHABLE_CODE: Lint = Lint {
+    name: "unreachable_code",
+    default: Level::Warn,
+    description: "code after a `return`, or after any other expression that never produces a value",
+};
+
+/// Every lint, in the order they're documented
+pub static LINTS: &[&Lint] = &[
+    &UNUSED_VARIABLES,
+    &UNUSED_FUNCTIONS,
+    &SHADOWING,
+    &SELF_ASSIGNMENT,
+    &CONSTANT_CONDITIONS,
+    &UNREACHABLE_CODE,
+];
+
+pub fn find_lint(name: &str) -> Option<&'static Lint> {
+    LINTS.iter().find(|l| l.name == name).cloned()
+}
+
+/// The level of every lint, which starts out as its default
+#[derive(Debug, Clone)]
+pub struct LintLevels {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl LintLevels {
+    pub fn new() -> LintLevels {
+        LintLevels {
+            levels: LINTS.iter().map(|l| (l.name, l.default)).collect(),
+        }
+    }
+
+    pub fn level(&self, lint: &Lint) -> Level {
+        self.levels[lint.name]
+    }
+
+    pub fn set(&mut self, lint: &'static Lint, level: Level) {
+        self.levels.insert(lint.name, level);
+    }
+
+    /// Sets the level of a lint given by name, as a driver option does
+    pub fn set_by_name(&mut self, name: &str, level: Level) -> Result<(), String> {
+        match find_lint(name) {
+            Some(lint) => {
+                self.set(lint, level);
+                Ok(())
+            }
+            None => Err(format!("unknown lint `{}`", name)),
+        }
+    }
+}
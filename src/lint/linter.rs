@@ -0,0 +1,239 @@
+use arith::OverflowMode;
+use cfg::{build_cfg, unreachable_code};
+use diagnostics::{Diagnostic, Severity};
+use fold::fold_expr;
+use lint::{find_lint, Level, Lint, LintLevels, CONSTANT_CONDITIONS, SELF_ASSIGNMENT, SHADOWING, UNREACHABLE_CODE,
+           UNUSED_FUNCTIONS, UNUSED_VARIABLES};
+use parser::{Attribute, Block, Decl, DefDecl, Expr, ExprKind, Lit, Pattern, PatternKind, Program, Stmt};
+use resolve::{DefKind, Namespace, Resolution};
+
+/// Runs every lint over a program that resolved without errors
+///
+/// `levels` holds the levels set by the driver. `#![allow(...)]`, `#![warn(...)]` and `#![deny(...)]` at the start of
+/// the program override them, and the same attributes on a `def` override those within it.
+pub fn lint_program(program: &Program, resolution: &Resolution, levels: &LintLevels) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        resolution,
+        levels: levels.clone(),
+        diags: Vec::new(),
+    };
+    linter.apply_attrs(program.attrs());
+    linter.stmts(program.stmts(), program.result(), true);
+    linter.diags
+}
+
+struct Linter<'a> {
+    resolution: &'a Resolution,
+    levels: LintLevels,
+    diags: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn apply_attrs(&mut self, attrs: &[Attribute]) {
+        for attr in attrs {
+            let level: Level = match attr.name().name().parse() {
+                Ok(level) => level,
+                Err(_) => {
+                    self.diags.push(Diagnostic::error(attr.name().span(), format!("unknown attribute `{}`", attr.name().name())));
+                    continue;
+                }
+            };
+            for name in attr.args() {
+                match find_lint(name.name()) {
+                    Some(lint) => self.levels.set(lint, level),
+                    None => self.diags.push(Diagnostic::warning(name.span(), format!("unknown lint `{}`", name.name()))),
+                }
+            }
+        }
+    }
+
+    /// Gets the severity to report the lint's findings with, or `None` if it's allowed
+    fn severity(&self, lint: &Lint) -> Option<Severity> {
+        self.levels.level(lint).severity()
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt], result: Option<&Expr>, top_level: bool) {
+        for stmt in stmts {
+            match stmt {
+                // A def at the top level that isn't generic is exported from objects, so C code can use it
+                &Stmt::Decl(Decl::Def(ref d)) => self.def(d, !top_level || !d.type_params().is_empty()),
+                &Stmt::Decl(Decl::Impl(ref i)) => {
+                    for method in i.methods() {
+                        self.def(method, false);
+                    }
+                }
+                &Stmt::Decl(_) => {}
+                &Stmt::Let(ref l) => {
+                    self.expr(l.value());
+                    self.bindings(l.pattern());
+                }
+                &Stmt::Expr(ref e) => self.expr(e),
+            }
+        }
+        if let Some(result) = result {
+            self.expr(result);
+        }
+    }
+
+    /// Lints a function, checking that it's used if `check_used` is set, which it isn't for methods, used through their
+    /// trait, or for exported functions
+    fn def(&mut self, def: &DefDecl, check_used: bool) {
+        let outer = self.levels.clone();
+        self.apply_attrs(def.attrs());
+
+        if check_used {
+            // Calls the function makes to itself don't count
+            let span = def.span();
+            let used = self.resolution.decl_at(def.name().span()).map_or(true, |id| {
+                self.resolution.uses_of(id).iter().any(|u| u.start() < span.start() || u.end() > span.end())
+            });
+            if let (false, Some(severity)) = (used, self.severity(&UNUSED_FUNCTIONS)) {
+                self.diags.push(Diagnostic::new(severity, def.name().span(), format!("function `{}` is never used", def.name().name())));
+            }
+        }
+        if let Some(severity) = self.severity(&UNREACHABLE_CODE) {
+            for (span, expr) in unreachable_code(&build_cfg(def, self.resolution)) {
+                self.diags.push(Diagnostic::new(severity, span, "unreachable code")
+                    .with_label(expr, "any code following this expression is unreachable"));
+            }
+        }
+        self.block(def.body());
+
+        self.levels = outer;
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.stmts(block.stmts(), block.result(), false);
+    }
+
+    /// Checks the names bound by a `let` or `match` arm
+    fn bindings(&mut self, pattern: &Pattern) {
+        match pattern.kind() {
+            &PatternKind::Wildcard | &PatternKind::Literal(_) => {}
+            &PatternKind::Binding(ref name) => {
+                // A name that matches a variant doesn't bind anything
+                let id = match self.resolution.decl_at(name.span()) {
+                    Some(id) => id,
+                    None => return,
+                };
+
+                if !self.resolution.is_used(id) && !name.name().starts_with('_') {
+                    if let Some(severity) = self.severity(&UNUSED_VARIABLES) {
+                        self.diags.push(Diagnostic::new(severity, name.span(), format!("unused variable `{}`", name.name()))
+                            .with_label(name.span(), format!("if this is intentional, prefix it with an underscore: `_{}`", name.name())));
+                    }
+                }
+
+                let symbols = self.resolution.symbols();
+                let outer = symbols.scope(symbols.def(id).scope()).parent()
+                    .and_then(|scope| symbols.lookup(scope, Namespace::Value, name.name()))
+                    .map(|prev| symbols.def(prev))
+                    .filter(|prev| prev.kind() == DefKind::Local || prev.kind() == DefKind::Param);
+                if let (Some(prev), Some(severity)) = (outer, self.severity(&SHADOWING)) {
+                    let mut diag = Diagnostic::new(severity, name.span(), format!("`{}` shadows an earlier binding", name.name()));
+                    if let Some(span) = prev.span() {
+                        diag = diag.with_label(span, format!("`{}` was first bound here", name.name()));
+                    }
+                    self.diags.push(diag);
+                }
+            }
+            &PatternKind::Tuple(ref elements) | &PatternKind::Constructor(_, ref elements) => {
+                for e in elements {
+                    self.bindings(e);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr.kind() {
+            &ExprKind::Constant(_) | &ExprKind::Var(_) => {}
+            &ExprKind::Binary(ref l, ref r, _, _) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            &ExprKind::Assign(ref target, ref value) => {
+                if self.same_place(target, value) {
+                    if let Some(severity) = self.severity(&SELF_ASSIGNMENT) {
+                        self.diags.push(Diagnostic::new(severity, expr.span(), format!("`{}` is assigned to itself", place_name(target))));
+                    }
+                }
+                self.expr(target);
+                self.expr(value);
+            }
+            &ExprKind::Struct(_, ref fields) => {
+                for f in fields {
+                    self.expr(f.value());
+                }
+            }
+            &ExprKind::Field(ref target, _) => self.expr(target),
+            &ExprKind::Call(ref callee, ref args) | &ExprKind::MethodCall(ref callee, _, ref args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            &ExprKind::Match(ref scrutinee, ref arms) => {
+                if let Some(severity) = self.severity(&CONSTANT_CONDITIONS) {
+                    if constant(scrutinee).is_some() {
+                        self.diags.push(Diagnostic::new(severity, scrutinee.span(), "this `match` is on a constant value"));
+                    }
+                    for guard in arms.iter().filter_map(|a| a.guard()) {
+                        if let Some(Lit::Bool(value)) = constant(guard) {
+                            self.diags.push(Diagnostic::new(severity, guard.span(), format!("this guard is always `{}`", value)));
+                        }
+                    }
+                }
+
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.bindings(arm.pattern());
+                    if let Some(guard) = arm.guard() {
+                        self.expr(guard);
+                    }
+                    self.expr(arm.body());
+                }
+            }
+            &ExprKind::Tuple(ref elements) => {
+                for e in elements {
+                    self.expr(e);
+                }
+            }
+            &ExprKind::Block(ref block) => self.block(block),
+            &ExprKind::Return(ref value) => {
+                if let Some(ref value) = *value {
+                    self.expr(value);
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if both expressions name the same variable or field of it
+    fn same_place(&self, a: &Expr, b: &Expr) -> bool {
+        match (a.kind(), b.kind()) {
+            (&ExprKind::Var(ref x), &ExprKind::Var(ref y)) => {
+                let x = self.resolution.use_at(x.span());
+                x.is_some() && x == self.resolution.use_at(y.span())
+            }
+            (&ExprKind::Field(ref x, ref f), &ExprKind::Field(ref y, ref g)) => f.name() == g.name() && self.same_place(x, y),
+            _ => false,
+        }
+    }
+}
+
+/// Gets the value of an expression if it's known when compiling
+fn constant(expr: &Expr) -> Option<Lit> {
+    match fold_expr(expr, OverflowMode::Trap).kind() {
+        &ExprKind::Constant(ref lit) => Some(lit.clone()),
+        _ => None,
+    }
+}
+
+/// Writes out a place expression, like `p.x`
+fn place_name(expr: &Expr) -> String {
+    match expr.kind() {
+        &ExprKind::Var(ref name) => name.name().into(),
+        &ExprKind::Field(ref target, ref name) => format!("{}.{}", place_name(target), name.name()),
+        _ => "_".into(),
+    }
+}
@@ -0,0 +1,114 @@
+mod levels;
+mod linter;
+
+pub use self::levels::{find_lint, Level, Lint, LintLevels, CONSTANT_CONDITIONS, LINTS, SELF_ASSIGNMENT, SHADOWING,
+                       UNREACHABLE_CODE, UNUSED_FUNCTIONS, UNUSED_VARIABLES};
+pub use self::linter::lint_program;
+
+#[cfg(test)]
+mod tests {
+    use diagnostics::Diagnostic;
+    use lint::{lint_program, Level, LintLevels, SHADOWING};
+    use testing::{parse_and_resolve, span_at, span_of};
+
+    fn lint_with(code: &str, levels: &LintLevels) -> Vec<Diagnostic> {
+        let (program, resolution) = parse_and_resolve(code);
+        lint_program(&program, &resolution, levels)
+    }
+
+    fn lint(code: &str) -> Vec<Diagnostic> {
+        lint_with(code, &LintLevels::new())
+    }
+
+    #[test]
+    pub fn unused_variables_and_functions_are_reported() {
+        let code = "def f(x) { let y = x; let _z = 1; def g() { g() } 0 } def h<T>(x: T) { x } f(1)";
+        assert_eq!(vec![
+            Diagnostic::warning(span_of(code, "y"), "unused variable `y`")
+                .with_label(span_of(code, "y"), "if this is intentional, prefix it with an underscore: `_y`"),
+            Diagnostic::warning(span_at(code, 38, "g"), "function `g` is never used"),
+            Diagnostic::warning(span_of(code, "h"), "function `h` is never used"),
+        ], lint(code));
+
+        // Defs at the top level that aren't generic are exported from objects, for C code to call
+        assert_eq!(Vec::<Diagnostic>::new(), lint("def square(x: Int) -> Int { x * x } 0"));
+
+        let code = "enum E { A(Int), B } match A(1) { A(n) => 1, B => 0 }";
+        assert_eq!(vec![
+            Diagnostic::warning(span_at(code, 36, "n"), "unused variable `n`")
+                .with_label(span_at(code, 36, "n"), "if this is intentional, prefix it with an underscore: `_n`"),
+        ], lint(code));
+    }
+
+    #[test]
+    pub fn shadowing_is_allowed_by_default() {
+        let code = "let x = 1; let x = x + 1; x";
+        assert_eq!(Vec::<Diagnostic>::new(), lint(code));
+
+        let mut levels = LintLevels::new();
+        levels.set(&SHADOWING, Level::Warn);
+        assert_eq!(vec![
+            Diagnostic::warning(span_at(code, 15, "x"), "`x` shadows an earlier binding")
+                .with_label(span_at(code, 4, "x"), "`x` was first bound here"),
+        ], lint_with(code, &levels));
+    }
+
+    #[test]
+    pub fn self_assignment_is_reported() {
+        let code = "struct P { x: Int } let p = P { x: 1 }; p.x = p.x; p = p; p.x";
+        assert_eq!(vec![
+            Diagnostic::warning(span_of(code, "p.x = p.x"), "`p.x` is assigned to itself"),
+            Diagnostic::warning(span_of(code, "p = p"), "`p` is assigned to itself"),
+        ], lint(code));
+    }
+
+    #[test]
+    pub fn constant_conditions_are_reported() {
+        let code = "let x = 1; match x { n if 1 < 2 => n, _ => 0 } + match 3 { _ => 0 }";
+        assert_eq!(vec![
+            Diagnostic::warning(span_of(code, "1 < 2"), "this guard is always `true`"),
+            Diagnostic::warning(span_of(code, "3"), "this `match` is on a constant value"),
+        ], lint(code));
+    }
+
+    #[test]
+    pub fn attributes_override_driver_levels() {
+        let code = "#![deny(unused_variables)] #[allow(unused_variables)] def f() { let a = 1; 0 } let b = f(); 0";
+        let mut levels = LintLevels::new();
+        levels.set_by_name("unused_variables", Level::Allow).unwrap();
+        assert_eq!(vec![
+            Diagnostic::error(span_at(code, 83, "b"), "unused variable `b`")
+                .with_label(span_at(code, 83, "b"), "if this is intentional, prefix it with an underscore: `_b`"),
+        ], lint_with(code, &levels));
+
+        assert!(levels.set_by_name("unused", Level::Warn).is_err());
+        assert_eq!(Some(Level::Deny), Level::from_flag("-D"));
+    }
+
+    #[test]
+    pub fn unknown_attributes_and_lints_are_reported() {
+        let code = "#![forbid(shadowing)] #![warn(shadows)] 0";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "forbid"), "unknown attribute `forbid`"),
+            Diagnostic::warning(span_of(code, "shadows"), "unknown lint `shadows`"),
+        ], lint(code));
+    }
+
+    #[test]
+    pub fn code_after_a_match_with_no_arms_is_unreachable() {
+        let code = "enum Never {} #[allow(unused_functions)] def f(n: Never) -> Int { let m = match n {}; m + 1 } 0";
+        assert_eq!(vec![
+            Diagnostic::warning(span_of(code, "m + 1"), "unreachable code")
+                .with_label(span_of(code, "match n {}"), "any code following this expression is unreachable"),
+        ], lint(code));
+    }
+
+    #[test]
+    pub fn code_after_a_return_is_unreachable() {
+        let code = "def f(n) { match n { 0 => return 1, _ => {} }; return n; n + 1 } f(0)";
+        assert_eq!(vec![
+            Diagnostic::warning(span_of(code, "n + 1"), "unreachable code")
+                .with_label(span_of(code, "return n"), "any code following this expression is unreachable"),
+        ], lint(code));
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use text::TextSpan;
+use parser::{Decl, DefDecl, Expr, ExprKind, Program, Stmt};
+use parser::visit::{self, Visitor};
+use typeck::{Dispatch, Type, TypeVar, TypeckResults};
+
+/// A copy of a function specialized to particular type arguments
+pub struct Instance<'a> {
+    def: &'a DefDecl,
+    args: Vec<Type>,
+    params: HashMap<String, Type>,
+    vars: HashMap<TypeVar, Type>,
+
+    // The trait and type of the impl the function is a method of, if it is one
+    owner: Option<(String, Type)>,
+}
+
+impl<'a> Instance<'a> {
+    pub fn def(&self) -> &'a DefDecl { self.def }
+
+    /// The types substituted for the function's type parameters, followed by those substituted for the other
+    /// variables in its scheme
+    pub fn args(&self) -> &[Type] { &self.args }
+
+    /// The name of the instance, which includes its type arguments if the function is generic: `id<Int>`. Methods are
+    /// qualified by their impl: `<Point as Show>::show`
+    pub fn name(&self) -> String {
+        let mut name = match self.owner {
+            Some((ref trait_name, ref ty)) => format!("<{} as {}>::{}", ty, trait_name, self.def.name().name()),
+            None => self.def.name().name().into(),
+        };
+        if !self.args.is_empty() {
+            let args: Vec<String> = self.args.iter().map(|a| a.to_string()).collect();
+            name = format!("{}<{}>", name, args.join(", "));
+        }
+        name
+    }
+
+    fn concrete(&self, ty: &Type) -> Type {
+        concrete(&ty.substitute_params(&self.params).substitute(&self.vars))
+    }
+}
+
+/// The methods of a trait for a particular type, which `dyn` values of the trait made from that type are called
+/// through
+pub struct VTable {
+    trait_name: String,
+    ty: Type,
+    methods: Vec<usize>,
+}
+
+impl VTable {
+    pub fn trait_name(&self) -> &str { &self.trait_name }
+    pub fn ty(&self) -> &Type { &self.ty }
+
+    /// The instance of each of the trait's methods, in the order the trait declares them
+    pub fn methods(&self) -> &[usize] { &self.methods }
+}
+
+/// The instances of every function reachable from the top level of a program, or declared there without being
+/// generic, and the vtables of its `dyn` values
+///
+/// Code is identified by the index of the instance it belongs to, or `None` for the top level of the program.
+pub struct Mono<'a> {
+    results: &'a TypeckResults,
+    instances: Vec<Instance<'a>>,
+    vtables: Vec<VTable>,
+    callees: HashMap<(Option<usize>, TextSpan), usize>,
+    coercions: HashMap<(Option<usize>, TextSpan), usize>,
+}
+
+impl<'a> Mono<'a> {
+    pub fn instances(&self) -> &[Instance<'a>] {
+        &self.instances
+    }
+
+    pub fn vtables(&self) -> &[VTable] {
+        &self.vtables
+    }
+
+    /// Gets the concrete type of the expression or pattern at the span, within the code of the provided instance
+    pub fn type_at(&self, instance: Option<usize>, span: TextSpan) -> Option<Type> {
+        let ty = self.results.raw_type_at(span)?;
+        match instance {
+            Some(i) => Some(self.instances[i].concrete(ty)),
+            None => Some(concrete(ty)),
+        }
+    }
+
+    /// Gets the instance of the function called at the span, within the code of the provided instance. The span is
+    /// that of a function's name, a method's name in a method call, or a binary expression with an overloaded operator.
+    ///
+    /// Calls to methods of `dyn` values, and operators on built-in types, have no callee.
+    pub fn callee(&self, instance: Option<usize>, span: TextSpan) -> Option<usize> {
+        self.callees.get(&(instance, span)).cloned()
+    }
+
+    /// Gets the vtable for the `dyn` value that the expression at the span is converted to, within the code of the
+    /// provided instance
+    pub fn vtable_at(&self, instance: Option<usize>, span: TextSpan) -> Option<usize> {
+        self.coercions.get(&(instance, span)).cloned()
+    }
+}
+
+/// Finds every function instance used by the program, starting from the code at its top level
+///
+/// The program must have been type checked without errors.
+pub fn monomorphize<'a>(program: &'a Program, results: &'a TypeckResults) -> Mono<'a> {
+    let mut defs = DefCollector { defs: HashMap::new() };
+    visit::walk_program(&mut defs, program);
+
+    let mut uses = UseCollector { spans: Vec::new() };
+    visit::walk_program(&mut uses, program);
+
+    let mut collector = Collector {
+        results,
+        defs: defs.defs,
+        mono: Mono {
+            results,
+            instances: Vec::new(),
+            vtables: Vec::new(),
+            callees: HashMap::new(),
+            coercions: HashMap::new(),
+        },
+        known: HashMap::new(),
+        vtables: HashMap::new(),
+        pending: vec![(None, uses.spans)],
+    };
+    collector.visit_pending();
+
+    // Defs at the top level that aren't generic are compiled even if nothing calls them, so code linked with the
+    // program can
+    for stmt in program.stmts() {
+        if let Stmt::Decl(Decl::Def(ref def)) = *stmt {
+            let decl = def.name().span();
+            match results.scheme_of(decl) {
+                Some(scheme) if !scheme.is_generic() => {
+                    collector.instantiate(decl, Vec::new());
+                }
+                _ => {}
+            }
+        }
+    }
+    collector.visit_pending();
+    collector.mono
+}
+
+/// Replaces the variables left in a type with `()`. A variable that's still unsolved after type checking can't affect
+/// how the program runs, so any type would do.
+fn concrete(ty: &Type) -> Type {
+    let mut vars = Vec::new();
+    ty.vars(&mut vars);
+    ty.substitute(&vars.into_iter().map(|v| (v, Type::unit())).collect())
+}
+
+struct Collector<'a> {
+    results: &'a TypeckResults,
+    defs: HashMap<TextSpan, &'a DefDecl>,
+    mono: Mono<'a>,
+    known: HashMap<(TextSpan, Vec<Type>), usize>,
+    vtables: HashMap<(String, Type), usize>,
+
+    // The spans within instances whose code hasn't been visited yet
+    pending: Vec<(Option<usize>, Vec<TextSpan>)>,
+}
+
+impl<'a> Collector<'a> {
+    fn visit_pending(&mut self) {
+        while let Some((context, spans)) = self.pending.pop() {
+            for span in spans {
+                self.visit_span(context, span);
+            }
+        }
+    }
+
+    /// Records whatever the code at the span calls, or the vtable it needs
+    fn visit_span(&mut self, context: Option<usize>, span: TextSpan) {
+        let results = self.results;
+        if let Some((decl, args)) = results.use_at(span) {
+            if self.defs.contains_key(&decl) {
+                let args = args.iter().map(|a| self.concrete(context, a)).collect();
+                let index = self.instantiate(decl, args);
+                self.mono.callees.insert((context, span), index);
+            }
+        }
+
+        let method = match results.dispatch_at(span) {
+            Some(&Dispatch::Static(decl)) => Some(decl),
+            Some(&Dispatch::Bound { ref param, ref trait_name, ref method }) => {
+                // Built-in types implement the operator traits without any methods to call
+                let ty = self.concrete(context, &Type::Param(param.clone()));
+                results.env().find_impl(trait_name, &ty).map(|i| i.methods[method])
+            }
+            Some(&Dispatch::Dynamic { .. }) | None => None,
+        };
+        if let Some(decl) = method {
+            let index = self.instantiate(decl, Vec::new());
+            self.mono.callees.insert((context, span), index);
+        }
+
+        if let Some(trait_name) = results.coercion_at(span) {
+            let ty = self.mono.type_at(context, span).expect("Expected a converted value to have a type");
+            let index = self.vtable(trait_name, ty);
+            self.mono.coercions.insert((context, span), index);
+        }
+    }
+
+    fn concrete(&self, context: Option<usize>, ty: &Type) -> Type {
+        match context {
+            Some(i) => self.mono.instances[i].concrete(ty),
+            None => concrete(ty),
+        }
+    }
+
+    /// Gets the instance of the function with the provided arguments, creating it if this is the first use
+    fn instantiate(&mut self, decl: TextSpan, args: Vec<Type>) -> usize {
+        let key = (decl, args);
+        if let Some(&index) = self.known.get(&key) {
+            return index;
+        }
+
+        let def = self.defs[&decl];
+        let scheme = self.results.scheme_of(decl).expect("Expected every def to have a scheme");
+        let owner = self.results.env().impl_of_method(decl).map(|i| (i.trait_name.clone(), i.ty.clone()));
+        let index = self.mono.instances.len();
+        self.mono.instances.push(Instance {
+            def,
+            args: key.1.clone(),
+            params: scheme.params().iter().cloned().zip(key.1.iter().cloned()).collect(),
+            vars: scheme.vars().iter().cloned().zip(key.1[scheme.params().len()..].iter().cloned()).collect(),
+            owner,
+        });
+        self.known.insert(key, index);
+
+        let mut uses = UseCollector { spans: Vec::new() };
+        visit::walk_block(&mut uses, def.body());
+        self.pending.insert(0, (Some(index), uses.spans));
+        index
+    }
+
+    /// Gets the vtable of the trait for the type, creating it and instances of its methods if this is the first use
+    fn vtable(&mut self, trait_name: &str, ty: Type) -> usize {
+        let key = (trait_name.to_string(), ty);
+        if let Some(&index) = self.vtables.get(&key) {
+            return index;
+        }
+
+        let env = self.results.env();
+        let info = env.find_impl(trait_name, &key.1).expect("Expected a converted value to implement the trait");
+        let methods = env.trait_info(trait_name).unwrap().methods.iter()
+            .map(|m| self.instantiate(info.methods[&m.name], Vec::new()))
+            .collect();
+        let index = self.mono.vtables.len();
+        self.mono.vtables.push(VTable {
+            trait_name: trait_name.into(),
+            ty: key.1.clone(),
+            methods,
+        });
+        self.vtables.insert(key, index);
+        index
+    }
+}
+
+struct DefCollector<'a> {
+    defs: HashMap<TextSpan, &'a DefDecl>,
+}
+
+impl<'a> Visitor<'a> for DefCollector<'a> {
+    fn visit_def(&mut self, def: &'a DefDecl) {
+        self.defs.insert(def.name().span(), def);
+        visit::walk_def(self, def);
+    }
+}
+
+/// Collects the spans of every expression, along with the names of methods that are called, except within nested
+/// functions, which are instantiated separately
+struct UseCollector {
+    spans: Vec<TextSpan>,
+}
+
+impl<'ast> Visitor<'ast> for UseCollector {
+    fn visit_def(&mut self, _def: &'ast DefDecl) {}
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        self.spans.push(expr.span());
+        if let &ExprKind::MethodCall(_, ref name, _) = expr.kind() {
+            self.spans.push(name.span());
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use text::TextSpan;
+    use parser::Program;
+    use typeck::{self, Type, TypeckResults};
+    use mono::monomorphize;
+    use testing::{parse, span_of};
+
+    fn check(code: &str) -> (Program, TypeckResults) {
+        let program = parse(code);
+        let results = typeck::check_program(&program);
+        assert_eq!(0, results.diagnostics().len(), "{:?}", results.diagnostics());
+        (program, results)
+    }
+
+    #[test]
+    pub fn generic_def_is_instantiated_once_per_type() {
+        let code = "def id<T>(x: T) -> T { x } (id(1), id(\"a\"), id(2))";
+        let (program, results) = check(code);
+        let mono = monomorphize(&program, &results);
+
+        let names: Vec<_> = mono.instances().iter().map(|i| i.name()).collect();
+        assert_eq!(vec!["id<Int>", "id<Str>"], names);
+        assert_eq!(Some(0), mono.callee(None, TextSpan::new(28, 30)));
+        assert_eq!(Some(1), mono.callee(None, TextSpan::new(35, 37)));
+        assert_eq!(Some(0), mono.callee(None, TextSpan::new(44, 46)));
+
+        let body = TextSpan::new(23, 24);
+        assert_eq!(Some(Type::Int), mono.type_at(Some(0), body));
+        assert_eq!(Some(Type::Str), mono.type_at(Some(1), body));
+    }
+
+    #[test]
+    pub fn instances_are_found_through_other_instances() {
+        let code = "def id(x) { x } def twice(x) { (id(x), id(x)) } def unused<T>(x: T) { x } twice(1.5)";
+        let (program, results) = check(code);
+        let mono = monomorphize(&program, &results);
+
+        let names: Vec<_> = mono.instances().iter().map(|i| i.name()).collect();
+        assert_eq!(vec!["twice<Float>", "id<Float>"], names);
+        assert_eq!(Some(1), mono.callee(Some(0), TextSpan::new(32, 34)));
+        assert_eq!(
+            Some(Type::Tuple(vec![Type::Float, Type::Float])),
+            mono.type_at(Some(0), span_of(code, "(id(x), id(x))")));
+    }
+
+    #[test]
+    pub fn unused_defs_are_instantiated_unless_generic() {
+        let code = "def used(x: Int) { x } def unused(x: Int) { helper(x) } def helper(x: Int) { x } \
+                    def pick(x) { x } used(1)";
+        let (program, results) = check(code);
+        let mono = monomorphize(&program, &results);
+
+        let names: Vec<_> = mono.instances().iter().map(|i| i.name()).collect();
+        assert_eq!(vec!["used", "unused", "helper"], names);
+    }
+
+    #[test]
+    pub fn non_generic_defs_have_plain_names() {
+        let code = "def f(x: Int) { x } f(1)";
+        let (program, results) = check(code);
+        let mono = monomorphize(&program, &results);
+
+        let names: Vec<_> = mono.instances().iter().map(|i| i.name()).collect();
+        assert_eq!(vec!["f"], names);
+    }
+
+    #[test]
+    pub fn bound_methods_are_resolved_for_each_instance() {
+        let code = "
+            trait Show { def show(self) -> Str }
+            struct P { x: Int }
+            impl Show for Int { def show(self) -> Str { \"int\" } }
+            impl Show for P { def show(self) -> Str { \"p\" } }
+            def describe<T: Show>(x: T) -> Str { x.show() }
+            (describe(1), describe(P { x: 1 }))";
+        let (program, results) = check(code);
+        let mono = monomorphize(&program, &results);
+
+        let names: Vec<_> = mono.instances().iter().map(|i| i.name()).collect();
+        assert_eq!(vec!["describe<Int>", "describe<P>", "<Int as Show>::show", "<P as Show>::show"], names);
+        let call = code.rfind("show").unwrap();
+        assert_eq!(Some(2), mono.callee(Some(0), TextSpan::new(call, call + 4)));
+        assert_eq!(Some(3), mono.callee(Some(1), TextSpan::new(call, call + 4)));
+    }
+
+    #[test]
+    pub fn dyn_conversions_build_one_vtable_per_type() {
+        let code = "
+            trait Shape { def area(self) -> Int; def name(self) -> Str }
+            struct Sq { s: Int }
+            impl Shape for Sq { def name(self) -> Str { \"square\" } def area(self) -> Int { self.s * self.s } }
+            def total(a: dyn Shape, b: dyn Shape) -> Int { a.area() + b.area() }
+            (total(Sq { s: 1 }, Sq { s: 2 }), total(Sq { s: 3 }, Sq { s: 4 }))";
+        let (program, results) = check(code);
+        let mono = monomorphize(&program, &results);
+
+        assert_eq!(1, mono.vtables().len());
+        let vtable = &mono.vtables()[0];
+        assert_eq!("Shape", vtable.trait_name());
+        assert_eq!(&Type::Struct("Sq".into(), vec![]), vtable.ty());
+        let methods: Vec<_> = vtable.methods().iter().map(|&m| mono.instances()[m].name()).collect();
+        assert_eq!(vec!["<Sq as Shape>::area", "<Sq as Shape>::name"], methods);
+        assert_eq!(Some(0), mono.vtable_at(None, span_of(code, "Sq { s: 3 }")));
+
+        // Methods of `dyn` values are found through the vtable when the program runs
+        let call = code.find("area() +").unwrap();
+        assert_eq!(None, mono.callee(Some(0), TextSpan::new(call, call + 4)));
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use cfg::{BlockId, Graph};
+use ir::{Function, InstKind, Module, Terminator, Value};
+use opt::{Pass, Stats};
+
+/// Removes instructions whose values are never used and that have no effect, blocks that can't be reached, and phis
+/// that only ever take one value, and merges blocks that can only follow one another
+pub struct DeadCode;
+
+impl Pass for DeadCode {
+    fn name(&self) -> &'static str { "dce" }
+
+    fn run(&self, module: &mut Module, stats: &mut Stats) {
+        for function in &mut module.functions {
+            stats.add("blocks removed", function.remove_unreachable_blocks());
+            stats.add("phis removed", remove_trivial_phis(function));
+            stats.add("blocks merged", merge_blocks(function));
+            stats.add("instructions removed", remove_dead_insts(function));
+        }
+    }
+}
+
+/// Replaces phis whose values are all the same, apart from the phi itself, with that value
+pub fn remove_trivial_phis(function: &mut Function) -> usize {
+    let mut removed = 0;
+    loop {
+        let mut replacements = HashMap::new();
+        for block in &function.blocks {
+            for inst in &block.insts {
+                if let InstKind::Phi(ref incoming) = inst.kind {
+                    let mut values = incoming.iter().map(|&(_, v)| v).filter(|&v| v != inst.value);
+                    if let Some(first) = values.next() {
+                        if values.all(|v| v == first) {
+                            replacements.insert(inst.value, first);
+                        }
+                    }
+                }
+            }
+        }
+        if replacements.is_empty() {
+            return removed;
+        }
+        removed += replacements.len();
+        for block in &mut function.blocks {
+            block.insts.retain(|i| !replacements.contains_key(&i.value));
+        }
+        // Removing one phi can leave another with only one value
+        function.replace_uses(&replacements);
+    }
+}
+
+/// Merges each block that ends in a jump with the block it jumps to, when it's that block's only predecessor
+fn merge_blocks(function: &mut Function) -> usize {
+    let mut merged = 0;
+    let mut replacements: HashMap<Value, Value> = HashMap::new();
+    loop {
+        let preds = function.predecessors();
+        let pair = (0..function.blocks.len()).filter_map(|b| match function.blocks[b].terminator {
+            Terminator::Jump(next) if next.index() != b && next.index() != 0 && preds[next.index()] == [b] => {
+                Some((b, next.index()))
+            }
+            _ => None,
+        }).next();
+        let (first, second) = match pair {
+            Some(pair) => pair,
+            None => break,
+        };
+
+        let block = ::std::mem::replace(&mut function.blocks[second], ::ir::Block::new());
+        for inst in block.insts {
+            match inst.kind {
+                // The only predecessor decides the value of each phi
+                InstKind::Phi(ref incoming) => {
+                    replacements.insert(inst.value, incoming[0].1);
+                }
+                _ => function.blocks[first].insts.push(inst),
+            }
+        }
+        for succ in block.terminator.successors() {
+            for inst in &mut function.blocks[succ.index()].insts {
+                if let InstKind::Phi(ref mut incoming) = inst.kind {
+                    for entry in incoming.iter_mut().filter(|e| e.0 == BlockId(second as u32)) {
+                        entry.0 = BlockId(first as u32);
+                    }
+                }
+            }
+        }
+        function.blocks[first].terminator = block.terminator;
+        merged += 1;
+    }
+    function.replace_uses(&replacements);
+    function.remove_unreachable_blocks();
+    merged
+}
+
+/// Removes the instructions without effects whose values aren't needed by anything that has one
+fn remove_dead_insts(function: &mut Function) -> usize {
+    let mut live = vec![false; function.value_bound()];
+    let mut work: Vec<Value> = Vec::new();
+    let mut defs = HashMap::new();
+    for block in &function.blocks {
+        for inst in &block.insts {
+            defs.insert(inst.value, inst);
+            if inst.has_effects() {
+                work.push(inst.value);
+            }
+        }
+        work.extend(block.terminator.operands());
+    }
+    while let Some(v) = work.pop() {
+        if live[v.index()] {
+            continue;
+        }
+        live[v.index()] = true;
+        if let Some(inst) = defs.get(&v) {
+            work.extend(inst.kind.operands().into_iter().filter(|o| !live[o.index()]));
+        }
+    }
+
+    let mut removed = 0;
+    for block in &mut function.blocks {
+        let before = block.insts.len();
+        block.insts.retain(|i| live[i.value.index()]);
+        removed += before - block.insts.len();
+    }
+    removed
+}
@@ -0,0 +1,77 @@
+use std::collections::{HashMap, HashSet};
+
+use cfg::{dominates, dominators, reverse_postorder};
+use ir::{Function, InstKind, Module, Ty, Value};
+use opt::{Pass, Stats};
+use parser::BinOp;
+
+/// Replaces instructions that compute the same value as one that always runs before them
+///
+/// Constants, operators, and references to functions and vtables are numbered by what they compute. An instruction is
+/// replaced if one with the same number is in a block that dominates it, or earlier in its own block. Arithmetic that
+/// can fail is numbered too: if the earlier instruction failed, the later one never runs. Reads from and allocations of
+/// heap objects aren't, since a write or a call could come between them.
+pub struct ValueNumbering;
+
+impl Pass for ValueNumbering {
+    fn name(&self) -> &'static str { "gvn" }
+
+    fn run(&self, module: &mut Module, stats: &mut Stats) {
+        for function in &mut module.functions {
+            stats.add("instructions removed", number_values(function));
+        }
+    }
+}
+
+fn number_values(function: &mut Function) -> usize {
+    let idom = dominators(function);
+    let mut available: HashMap<String, Vec<(usize, Value)>> = HashMap::new();
+    let mut replacements: HashMap<Value, Value> = HashMap::new();
+
+    for b in reverse_postorder(function) {
+        for inst in &function.blocks[b].insts {
+            let mut kind = inst.kind.clone();
+            kind.map_operands(|mut v| {
+                while let Some(&r) = replacements.get(&v) {
+                    v = r;
+                }
+                v
+            });
+            let key = match kind {
+                InstKind::Const(_) | InstKind::Func(_) | InstKind::VTable(_) | InstKind::Phi(_) => {
+                    format!("{} {:?}", inst.ty.name(), kind)
+                }
+                InstKind::Binary(op, a, c) => {
+                    // The operands of an operator that doesn't care about their order are numbered in a fixed order.
+                    // Adding strings joins them, so their order matters
+                    let commutative = is_commutative(op) && inst.ty != Ty::Str;
+                    let (a, c) = if commutative && c < a { (c, a) } else { (a, c) };
+                    format!("{} {:?}", inst.ty.name(), InstKind::Binary(op, a, c))
+                }
+                InstKind::Call(..) | InstKind::New(_) | InstKind::Get(..) | InstKind::Set(..) => continue,
+            };
+
+            let entries = available.entry(key).or_insert_with(Vec::new);
+            match entries.iter().find(|&&(block, _)| dominates(&idom, block, b)) {
+                Some(&(_, earlier)) => {
+                    replacements.insert(inst.value, earlier);
+                }
+                None => entries.push((b, inst.value)),
+            }
+        }
+    }
+
+    let replaced: HashSet<Value> = replacements.keys().cloned().collect();
+    for block in &mut function.blocks {
+        block.insts.retain(|i| !replaced.contains(&i.value));
+    }
+    function.replace_uses(&replacements);
+    replaced.len()
+}
+
+fn is_commutative(op: BinOp) -> bool {
+    match op {
+        BinOp::Add | BinOp::Multiply | BinOp::Equal | BinOp::NotEqual => true,
+        _ => false,
+    }
+}
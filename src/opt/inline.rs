@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+
+use cfg::{BlockId, Graph};
+use ir::{Block, Callee, Function, Inst, InstKind, Module, Terminator, Value};
+use opt::{Pass, Stats};
+
+/// Replaces direct calls to small functions with a copy of their body
+///
+/// A function costs as many instructions as it has, and is only copied if it costs no more than the threshold.
+/// Functions that can call themselves, directly or through others, are never copied, so inlining always stops.
+pub struct Inline {
+    threshold: usize,
+}
+
+impl Inline {
+    pub fn new(threshold: usize) -> Inline {
+        Inline { threshold }
+    }
+}
+
+impl Pass for Inline {
+    fn name(&self) -> &'static str { "inline" }
+
+    fn run(&self, module: &mut Module, stats: &mut Stats) {
+        let recursive = recursive_functions(module);
+        // Callees are copied as they were before the pass, so how much a function grows doesn't depend on the order
+        // functions are visited in
+        let callees: HashMap<String, Function> = module.functions.iter()
+            .filter(|f| !recursive.contains(&f.name) && cost(f) <= self.threshold && f.predecessors()[0].is_empty())
+            .map(|f| (f.name.clone(), f.clone()))
+            .collect();
+
+        for function in &mut module.functions {
+            let mut inlined = 0;
+            while let Some((block, index, callee)) = find_call(function, &callees) {
+                inline_call(function, block, index, callee);
+                inlined += 1;
+            }
+            if inlined > 0 {
+                function.sort_blocks();
+            }
+            stats.add("calls inlined", inlined);
+        }
+    }
+}
+
+fn cost(function: &Function) -> usize {
+    function.blocks.iter().map(|b| b.insts.len()).sum()
+}
+
+/// Finds the next call to a function that can be copied, by block and position in the block
+fn find_call<'a>(function: &Function, callees: &'a HashMap<String, Function>) -> Option<(usize, usize, &'a Function)> {
+    for (b, block) in function.blocks.iter().enumerate() {
+        for (i, inst) in block.insts.iter().enumerate() {
+            if let InstKind::Call(Callee::Direct(ref name), _) = inst.kind {
+                if let Some(callee) = callees.get(name) {
+                    return Some((b, i, callee));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Splits the block at the call, jumps from the first half to a copy of the callee, and jumps from each of the copy's
+/// returns to the second half, where a phi takes the value returned in place of the call
+fn inline_call(function: &mut Function, block: usize, index: usize, callee: &Function) {
+    let value_offset = function.value_bound() as u32;
+    let block_offset = function.blocks.len() as u32;
+    let continuation = BlockId(block_offset + callee.blocks.len() as u32);
+
+    let mut rest = function.blocks[block].insts.split_off(index);
+    let call = rest.remove(0);
+    let args = match call.kind {
+        InstKind::Call(_, args) => args,
+        _ => unreachable!("Expected a call to inline"),
+    };
+    let terminator = ::std::mem::replace(&mut function.blocks[block].terminator, Terminator::Jump(BlockId(block_offset)));
+
+    let params: HashMap<Value, Value> = callee.params.iter().map(|&(p, _)| p).zip(args).collect();
+    let value = |v: Value| params.get(&v).cloned().unwrap_or(Value(v.0 + value_offset));
+    let target = |b: BlockId| BlockId(b.0 + block_offset);
+
+    let mut returned = Vec::new();
+    for (b, original) in callee.blocks.iter().enumerate() {
+        let mut copy = Block::new();
+        for inst in &original.insts {
+            let mut kind = inst.kind.clone();
+            kind.map_operands(&value);
+            if let InstKind::Phi(ref mut incoming) = kind {
+                for entry in incoming.iter_mut() {
+                    entry.0 = target(entry.0);
+                }
+            }
+            copy.insts.push(Inst { value: value(inst.value), ty: inst.ty, kind, span: inst.span });
+        }
+        copy.terminator = match original.terminator {
+            Terminator::Jump(t) => Terminator::Jump(target(t)),
+            Terminator::Branch(c, t, f) => Terminator::Branch(value(c), target(t), target(f)),
+            Terminator::Return(v) => {
+                returned.push((target(BlockId(b as u32)), value(v)));
+                Terminator::Jump(continuation)
+            }
+            Terminator::Unreachable => Terminator::Unreachable,
+        };
+        function.blocks.push(copy);
+    }
+
+    let mut second = Block::new();
+    second.insts.push(Inst::new(call.value, call.ty, InstKind::Phi(returned)));
+    second.insts.extend(rest);
+    for succ in terminator.successors() {
+        for inst in &mut function.blocks[succ.index()].insts {
+            if let InstKind::Phi(ref mut incoming) = inst.kind {
+                for entry in incoming.iter_mut().filter(|e| e.0 == BlockId(block as u32)) {
+                    entry.0 = continuation;
+                }
+            }
+        }
+    }
+    second.terminator = terminator;
+    function.blocks.push(second);
+}
+
+/// Finds the functions that can call themselves, directly or through other functions. Calls through function values
+/// aren't followed, but those are never inlined either
+fn recursive_functions(module: &Module) -> HashSet<String> {
+    let calls: HashMap<&str, Vec<&str>> = module.functions.iter().map(|f| {
+        let callees = f.blocks.iter().flat_map(|b| b.insts.iter()).filter_map(|i| match i.kind {
+            InstKind::Call(Callee::Direct(ref name), _) => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        (f.name.as_str(), callees)
+    }).collect();
+
+    let mut recursive = HashSet::new();
+    for function in &module.functions {
+        let mut seen = HashSet::new();
+        let mut work: Vec<&str> = calls[function.name.as_str()].clone();
+        while let Some(name) = work.pop() {
+            if name == function.name {
+                recursive.insert(function.name.clone());
+                break;
+            }
+            if seen.insert(name) {
+                work.extend(calls.get(name).into_iter().flat_map(|c| c.iter().cloned()));
+            }
+        }
+    }
+    recursive
+}
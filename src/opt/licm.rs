@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+
+use cfg::{dominates, dominators, reverse_postorder, BlockId, Graph};
+use ir::{Block, Function, Inst, InstKind, Module, Terminator, Value};
+use opt::{Pass, Stats};
+
+/// Moves instructions that compute the same value on every iteration of a loop to just before the loop
+///
+/// Only instructions that can't fail or have effects are moved, since moving them runs them even when the loop would
+/// have skipped them. They're moved to a block that runs only right before the loop, which is added if the loop
+/// doesn't have one.
+pub struct LoopInvariantMotion;
+
+impl Pass for LoopInvariantMotion {
+    fn name(&self) -> &'static str { "licm" }
+
+    fn run(&self, module: &mut Module, stats: &mut Stats) {
+        for function in &mut module.functions {
+            let (hoisted, preheaders) = hoist_invariants(function);
+            stats.add("instructions hoisted", hoisted);
+            stats.add("preheaders added", preheaders);
+        }
+    }
+}
+
+fn hoist_invariants(function: &mut Function) -> (usize, usize) {
+    // Inner loops come after the loops around them, so going backwards moves instructions out one loop at a time
+    let headers: Vec<usize> = {
+        let idom = dominators(function);
+        let preds = function.predecessors();
+        reverse_postorder(function).into_iter()
+            .filter(|&h| h != 0 && preds[h].iter().any(|&p| idom[p].is_some() && dominates(&idom, h, p)))
+            .collect()
+    };
+
+    let (mut hoisted, mut preheaders) = (0, 0);
+    for &header in headers.iter().rev() {
+        let body = loop_body(function, header);
+        let invariant = invariant_insts(function, &body);
+        if invariant.is_empty() {
+            continue;
+        }
+
+        let preheader = match existing_preheader(function, header, &body) {
+            Some(preheader) => preheader,
+            None => {
+                preheaders += 1;
+                add_preheader(function, header, &body)
+            }
+        };
+        let mut moved = Vec::new();
+        for &b in &body {
+            let insts = ::std::mem::replace(&mut function.blocks[b].insts, Vec::new());
+            let (out, stay): (Vec<Inst>, Vec<Inst>) = insts.into_iter().partition(|i| invariant.contains(&i.value));
+            function.blocks[b].insts = stay;
+            moved.extend(out);
+        }
+        // Blocks are visited in order, so each moved instruction still comes after the ones it reads
+        moved.sort_by_key(|i| invariant.iter().position(|&v| v == i.value));
+        hoisted += moved.len();
+        function.blocks[preheader].insts.extend(moved);
+    }
+    if preheaders > 0 {
+        function.sort_blocks();
+    }
+    (hoisted, preheaders)
+}
+
+/// Gets the blocks of the loop with a header, in the order they run in: the header, and the blocks that can reach
+/// one of its back edges without going through it
+fn loop_body(function: &Function, header: usize) -> Vec<usize> {
+    let idom = dominators(function);
+    let preds = function.predecessors();
+    let mut body = HashSet::new();
+    body.insert(header);
+    let mut work: Vec<usize> = preds[header].iter().cloned()
+        .filter(|&p| idom[p].is_some() && dominates(&idom, header, p))
+        .collect();
+    while let Some(b) = work.pop() {
+        if body.insert(b) {
+            work.extend(preds[b].iter().cloned());
+        }
+    }
+    reverse_postorder(function).into_iter().filter(|b| body.contains(b)).collect()
+}
+
+/// Finds the instructions of a loop that can be moved out of it, in an order where each comes after those it reads
+fn invariant_insts(function: &Function, body: &[usize]) -> Vec<Value> {
+    let defined: HashSet<Value> = body.iter().flat_map(|&b| function.blocks[b].insts.iter()).map(|i| i.value).collect();
+    let mut invariant = Vec::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for inst in body.iter().flat_map(|&b| function.blocks[b].insts.iter()) {
+            let movable = match inst.kind {
+                InstKind::Const(_) | InstKind::Func(_) | InstKind::VTable(_) => true,
+                InstKind::Binary(..) => !inst.has_effects(),
+                _ => false,
+            };
+            if movable && !invariant.contains(&inst.value)
+                && inst.kind.operands().iter().all(|v| !defined.contains(v) || invariant.contains(v)) {
+                invariant.push(inst.value);
+                changed = true;
+            }
+        }
+    }
+    invariant
+}
+
+/// Gets the block that jumps to the header from outside the loop, if there's only one and it only jumps there
+fn existing_preheader(function: &Function, header: usize, body: &[usize]) -> Option<usize> {
+    let preds = function.predecessors();
+    let outside: Vec<usize> = preds[header].iter().cloned().filter(|p| !body.contains(p)).collect();
+    match outside[..] {
+        [pred] if function.blocks[pred].terminator == Terminator::Jump(BlockId(header as u32)) => Some(pred),
+        _ => None,
+    }
+}
+
+/// Adds a block between the header and the blocks that jump to it from outside the loop. The values the header's
+/// phis take from those blocks move to phis of the new block
+fn add_preheader(function: &mut Function, header: usize, body: &[usize]) -> usize {
+    let preheader = BlockId(function.blocks.len() as u32);
+    let header_id = BlockId(header as u32);
+    let mut next_value = function.value_bound() as u32;
+    let mut block = Block::new();
+    block.terminator = Terminator::Jump(header_id);
+
+    let outside: HashSet<BlockId> = function.predecessors()[header].iter().cloned()
+        .filter(|p| !body.contains(p))
+        .map(|p| BlockId(p as u32))
+        .collect();
+    for inst in &mut function.blocks[header].insts {
+        if let InstKind::Phi(ref mut incoming) = inst.kind {
+            let (from_outside, from_loop): (Vec<_>, Vec<_>) =
+                incoming.iter().cloned().partition(|e| outside.contains(&e.0));
+            let value = match from_outside[..] {
+                [(_, v)] => v,
+                _ => {
+                    let v = Value(next_value);
+                    next_value += 1;
+                    block.insts.push(Inst::new(v, inst.ty, InstKind::Phi(from_outside)));
+                    v
+                }
+            };
+            *incoming = from_loop;
+            incoming.push((preheader, value));
+        }
+    }
+    function.blocks.push(block);
+
+    let retarget = |b: BlockId| if b == header_id { preheader } else { b };
+    for &pred in &outside {
+        let terminator = &mut function.blocks[pred.index()].terminator;
+        *terminator = match *terminator {
+            Terminator::Jump(t) => Terminator::Jump(retarget(t)),
+            Terminator::Branch(c, t, f) => Terminator::Branch(c, retarget(t), retarget(f)),
+            ref other => other.clone(),
+        };
+    }
+    preheader.index()
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use ir::{verify_module, Module};
+use opt::{ConstantPropagation, DeadCode, Inline, LoopInvariantMotion, TailCalls, ValueNumbering};
+
+/// A transformation of a module that keeps what it computes the same
+pub trait Pass {
+    /// The short name the pass is reported by
+    fn name(&self) -> &'static str;
+
+    /// Runs the pass over every function of the module, counting the changes it makes
+    fn run(&self, module: &mut Module, stats: &mut Stats);
+}
+
+/// Counts of the changes a pass made, by what was changed, in the order they were first counted
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Stats {
+    counts: Vec<(&'static str, usize)>,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    /// Adds to a count. Nothing is recorded for no changes, so a pass that changed nothing has no counts
+    pub fn add(&mut self, what: &'static str, n: usize) {
+        if n == 0 {
+            return;
+        }
+        match self.counts.iter_mut().find(|&&mut (w, _)| w == what) {
+            Some(&mut (_, ref mut count)) => *count += n,
+            None => self.counts.push((what, n)),
+        }
+    }
+
+    pub fn get(&self, what: &str) -> usize {
+        self.counts.iter().find(|&&(w, _)| w == what).map_or(0, |&(_, n)| n)
+    }
+
+    pub fn counts(&self) -> &[(&'static str, usize)] {
+        &self.counts
+    }
+}
+
+/// How much to optimize, as chosen by `-O0`, `-O1` or `-O2`
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// Run no passes, so the code matches the source as closely as possible
+    O0,
+
+    /// Run the passes that only make code smaller
+    O1,
+
+    /// Also inline functions and move code out of loops, which can make code larger
+    O2,
+}
+
+impl OptLevel {
+    /// Gets the level set by a driver option: `-O0`, `-O1` or `-O2`
+    pub fn from_flag(flag: &str) -> Option<OptLevel> {
+        if flag.starts_with("-O") { flag[2..].parse().ok() } else { None }
+    }
+}
+
+impl Default for OptLevel {
+    fn default() -> OptLevel { OptLevel::O0 }
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OptLevel, String> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            _ => Err(format!("unknown optimization level `{}`, expected `0`, `1` or `2`", s)),
+        }
+    }
+}
+
+/// What one run of a pass did, and how long it took
+#[derive(Debug, Clone)]
+pub struct PassReport {
+    pub name: &'static str,
+    pub time: Duration,
+    pub stats: Stats,
+}
+
+/// What every pass the manager ran did, in the order they ran
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub passes: Vec<PassReport>,
+}
+
+impl Report {
+    /// Sums a count over every pass that reported it
+    pub fn total(&self, what: &str) -> usize {
+        self.passes.iter().map(|p| p.stats.get(what)).sum()
+    }
+}
+
+/// Writes a line for each pass, with its time in milliseconds and the changes it made
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.passes.iter().map(|p| p.name.len()).max().unwrap_or(0);
+        for pass in &self.passes {
+            let millis = pass.time.as_secs() as f64 * 1000.0 + pass.time.subsec_nanos() as f64 / 1_000_000.0;
+            let counts: Vec<String> = pass.stats.counts().iter().map(|&(what, n)| format!("{} {}", n, what)).collect();
+            let counts = if counts.is_empty() { "no changes".to_string() } else { counts.join(", ") };
+            writeln!(f, "{:width$}  {:>8.3}ms  {}", pass.name, millis, counts, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs passes over a module in order
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    verify: bool,
+}
+
+impl PassManager {
+    pub fn new() -> PassManager {
+        PassManager { passes: Vec::new(), verify: false }
+    }
+
+    /// Gets the passes for an optimization level
+    pub fn for_level(level: OptLevel) -> PassManager {
+        let mut manager = PassManager::new();
+        match level {
+            OptLevel::O0 => {}
+            OptLevel::O1 => {
+                manager.add(ConstantPropagation);
+                manager.add(ValueNumbering);
+                manager.add(DeadCode);
+            }
+            OptLevel::O2 => {
+                manager.add(Inline::new(40));
+                manager.add(TailCalls);
+                manager.add(ConstantPropagation);
+                manager.add(ValueNumbering);
+                manager.add(LoopInvariantMotion);
+                manager.add(DeadCode);
+                // Removing code can leave more to fold, like phis whose other values came from branches that are gone
+                manager.add(ConstantPropagation);
+                manager.add(DeadCode);
+            }
+        }
+        manager
+    }
+
+    pub fn add<P: Pass + 'static>(&mut self, pass: P) {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Checks the module after every pass, so a pass that breaks it is caught straight away
+    pub fn with_verify(mut self) -> PassManager {
+        self.verify = true;
+        self
+    }
+
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    /// ## Panics
+    /// If verifying is on and a pass leaves the module malformed.
+    pub fn run(&self, module: &mut Module) -> Report {
+        let mut report = Report::default();
+        for pass in &self.passes {
+            let mut stats = Stats::new();
+            let start = Instant::now();
+            pass.run(module, &mut stats);
+            report.passes.push(PassReport { name: pass.name(), time: start.elapsed(), stats });
+
+            if self.verify {
+                let errors = verify_module(module);
+                if !errors.is_empty() {
+                    let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                    panic!("`{}` left the module malformed:\n{}\n{}", pass.name(), errors.join("\n"), module);
+                }
+            }
+        }
+        report
+    }
+}
@@ -0,0 +1,503 @@
+//! Optimizations of the IR, run by a pass manager at the level chosen with `-O0`, `-O1` or `-O2`
+//!
+//! Every pass takes a well formed module to a well formed module that computes the same values, makes the same calls
+//! and stops at the same places. Passes count the changes they make, and the manager times each one.
+
+mod dce;
+mod gvn;
+mod inline;
+mod licm;
+mod manager;
+mod sccp;
+mod tailrec;
+
+pub use self::dce::{remove_trivial_phis, DeadCode};
+pub use self::gvn::ValueNumbering;
+pub use self::inline::Inline;
+pub use self::licm::LoopInvariantMotion;
+pub use self::manager::{OptLevel, Pass, PassManager, PassReport, Report, Stats};
+pub use self::sccp::ConstantPropagation;
+pub use self::tailrec::TailCalls;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use arith::OverflowMode;
+    use tokenizer::Tokenizer;
+    use parser::Parser;
+    use resolve::resolve_program;
+    use typeck;
+    use mono::monomorphize;
+    use ir::{lower_program, parse_module, Module};
+    use opt::{ConstantPropagation, DeadCode, Inline, LoopInvariantMotion, OptLevel, Pass, PassManager, PassReport,
+              Report, Stats, TailCalls, ValueNumbering};
+
+    fn optimize<P: Pass + 'static>(pass: P, text: &str) -> (String, Report) {
+        let mut module = parse_module(text).expect("Expected the module to parse");
+        let mut manager = PassManager::new().with_verify();
+        manager.add(pass);
+        let report = manager.run(&mut module);
+        (module.to_string(), report)
+    }
+
+    fn lower(code: &str) -> Module {
+        let program = Parser::new(Tokenizer::new(code)).program().expect("Expected the parse to succeed");
+        let resolution = resolve_program(&program);
+        let results = typeck::check_program(&program);
+        let mono = monomorphize(&program, &results);
+        lower_program(&program, &resolution, &results, &mono, OverflowMode::Trap)
+    }
+
+    #[test]
+    pub fn constants_fold_and_branches_they_decide_go() {
+        let (module, report) = optimize(ConstantPropagation, "\
+overflow trap
+
+fn @f(%0: int) -> int {
+bb0:
+    %1: int = const 2
+    %2: int = const 3
+    %3: int = add %1, %2 !0..1
+    %4: bool = lt %1, %2
+    branch %4, bb1, bb2
+bb1:
+    %5: int = add %3, %0 !0..1
+    jump bb3
+bb2:
+    jump bb3
+bb3:
+    %6: int = phi [bb1: %5], [bb2: %3]
+    ret %6
+}
+");
+        assert_eq!("\
+overflow trap
+
+fn @f(%0: int) -> int {
+bb0:
+    %1: int = const 2
+    %2: int = const 3
+    %3: int = const 5
+    %4: bool = const true
+    jump bb1
+bb1:
+    %5: int = add %3, %0 !0..1
+    jump bb2
+bb2:
+    %6: int = phi [bb1: %5]
+    ret %6
+}
+", module);
+        assert_eq!(2, report.total("values folded"));
+        assert_eq!(1, report.total("branches folded"));
+    }
+
+    #[test]
+    pub fn arithmetic_that_would_stop_the_program_is_not_folded() {
+        let text = "\
+overflow trap
+
+fn @f() -> int {
+bb0:
+    %0: int = const 1
+    %1: int = const 0
+    %2: int = div %0, %1 !0..1
+    ret %2
+}
+";
+        let (module, report) = optimize(ConstantPropagation, text);
+        assert_eq!(text, module);
+        assert_eq!(0, report.total("values folded"));
+    }
+
+    #[test]
+    pub fn phis_are_constant_when_every_branch_that_runs_agrees() {
+        // The loop only ever adds zero, so `%2` is always 1
+        let (module, _) = optimize(ConstantPropagation, "\
+overflow trap
+
+fn @f(%0: bool) -> int {
+bb0:
+    %1: int = const 1
+    jump bb1
+bb1:
+    %2: int = phi [bb0: %1], [bb2: %4]
+    branch %0, bb2, bb3
+bb2:
+    %3: int = const 0
+    %4: int = add %2, %3 !0..1
+    jump bb1
+bb3:
+    ret %2
+}
+");
+        assert!(module.contains("bb1:\n    %2: int = const 1\n"), "{}", module);
+        assert!(module.contains("%4: int = const 1\n"), "{}", module);
+    }
+
+    #[test]
+    pub fn repeated_values_are_computed_once() {
+        let (module, report) = optimize(ValueNumbering, "\
+overflow trap
+
+fn @f(%0: int, %1: int, %2: str) -> bool {
+bb0:
+    %3: int = mul %0, %1 !0..1
+    %4: int = mul %1, %0 !2..3
+    %5: str = add %2, %2
+    %6: str = const \"a\"
+    %7: str = add %6, %2
+    %8: str = add %2, %6
+    %9: bool = eq %3, %4
+    ret %9
+}
+");
+        assert_eq!("\
+overflow trap
+
+fn @f(%0: int, %1: int, %2: str) -> bool {
+bb0:
+    %3: int = mul %0, %1 !0..1
+    %5: str = add %2, %2
+    %6: str = const \"a\"
+    %7: str = add %6, %2
+    %8: str = add %2, %6
+    %9: bool = eq %3, %3
+    ret %9
+}
+", module);
+        assert_eq!(1, report.total("instructions removed"));
+    }
+
+    #[test]
+    pub fn values_are_only_reused_where_they_were_computed_first() {
+        let (module, _) = optimize(ValueNumbering, "\
+overflow trap
+
+fn @f(%0: bool) -> int {
+bb0:
+    branch %0, bb1, bb2
+bb1:
+    %1: int = const 1
+    jump bb3
+bb2:
+    %2: int = const 1
+    jump bb3
+bb3:
+    %3: int = phi [bb1: %1], [bb2: %2]
+    %4: int = const 1
+    ret %3
+}
+");
+        assert!(module.contains("%2: int = const 1"), "{}", module);
+        assert!(module.contains("%4: int = const 1"), "{}", module);
+    }
+
+    #[test]
+    pub fn unused_values_without_effects_are_removed() {
+        let (module, report) = optimize(DeadCode, "\
+overflow trap
+
+extern @puts(str) -> unit
+
+fn @f(%0: int) -> int {
+bb0:
+    %1: str = const \"hi\"
+    %2: unit = call @puts(%1)
+    %3: int = const 2
+    %4: bool = lt %0, %3
+    %5: int = add %0, %3 !0..1
+    jump bb1
+bb1:
+    %6: int = phi [bb0: %0]
+    ret %6
+}
+");
+        assert_eq!("\
+overflow trap
+
+extern @puts(str) -> unit
+
+fn @f(%0: int) -> int {
+bb0:
+    %1: str = const \"hi\"
+    %2: unit = call @puts(%1)
+    %3: int = const 2
+    %5: int = add %0, %3 !0..1
+    ret %0
+}
+", module);
+        assert_eq!(1, report.total("phis removed"));
+        assert_eq!(1, report.total("blocks merged"));
+        assert_eq!(1, report.total("instructions removed"));
+    }
+
+    #[test]
+    pub fn small_functions_are_inlined() {
+        let (module, report) = optimize(Inline::new(5), "\
+overflow trap
+
+fn @abs(%0: int) -> int {
+bb0:
+    %1: int = const 0
+    %2: bool = lt %0, %1
+    branch %2, bb1, bb2
+bb1:
+    %3: int = sub %1, %0 !0..1
+    ret %3
+bb2:
+    ret %0
+}
+
+fn @main() -> int {
+bb0:
+    %0: int = const 5
+    %1: int = call @abs(%0)
+    %2: int = add %1, %0 !2..3
+    ret %2
+}
+");
+        assert!(module.ends_with("\
+fn @main() -> int {
+bb0:
+    %0: int = const 5
+    jump bb1
+bb1:
+    %4: int = const 0
+    %5: bool = lt %0, %4
+    branch %5, bb2, bb3
+bb2:
+    %6: int = sub %4, %0 !0..1
+    jump bb4
+bb3:
+    jump bb4
+bb4:
+    %1: int = phi [bb2: %6], [bb3: %0]
+    %2: int = add %1, %0 !2..3
+    ret %2
+}
+"), "{}", module);
+        assert_eq!(1, report.total("calls inlined"));
+    }
+
+    #[test]
+    pub fn large_and_recursive_functions_are_not_inlined() {
+        let text = "\
+overflow trap
+
+fn @even(%0: int) -> bool {
+bb0:
+    %1: bool = call @odd(%0)
+    ret %1
+}
+
+fn @odd(%0: int) -> bool {
+bb0:
+    %1: bool = call @even(%0)
+    ret %1
+}
+
+fn @big() -> int {
+bb0:
+    %0: int = const 1
+    %1: int = const 2
+    ret %1
+}
+
+fn @main() -> int {
+bb0:
+    %0: int = const 1
+    %1: bool = call @even(%0)
+    %2: int = call @big()
+    ret %2
+}
+";
+        let (module, report) = optimize(Inline::new(1), text);
+        assert_eq!(text, module);
+        assert_eq!(0, report.total("calls inlined"));
+    }
+
+    #[test]
+    pub fn invariant_values_move_out_of_loops() {
+        // `%5` can't overflow so it moves, but `%6` could stop the program, so it stays in case the loop never runs
+        let (module, report) = optimize(LoopInvariantMotion, "\
+overflow trap
+
+fn @f(%0: float, %1: int, %2: bool) -> float {
+bb0:
+    branch %2, bb1, bb3
+bb1:
+    %3: float = phi [bb0: %0], [bb2: %7]
+    %4: bool = lt %3, %0
+    branch %4, bb2, bb3
+bb2:
+    %5: float = mul %0, %0
+    %6: int = mul %1, %1 !0..1
+    %7: float = add %3, %5
+    jump bb1
+bb3:
+    %8: float = phi [bb0: %0], [bb1: %3]
+    ret %8
+}
+");
+        assert_eq!("\
+overflow trap
+
+fn @f(%0: float, %1: int, %2: bool) -> float {
+bb0:
+    branch %2, bb1, bb4
+bb1:
+    %5: float = mul %0, %0
+    jump bb2
+bb2:
+    %3: float = phi [bb3: %7], [bb1: %0]
+    %4: bool = lt %3, %0
+    branch %4, bb3, bb4
+bb3:
+    %6: int = mul %1, %1 !0..1
+    %7: float = add %3, %5
+    jump bb2
+bb4:
+    %8: float = phi [bb0: %0], [bb2: %3]
+    ret %8
+}
+", module);
+        assert_eq!(1, report.total("instructions hoisted"));
+        assert_eq!(1, report.total("preheaders added"));
+    }
+
+    #[test]
+    pub fn calls_returned_right_away_jump_back_to_the_start() {
+        let (module, report) = optimize(TailCalls, "\
+overflow trap
+
+fn @count(%0: int, %1: int) -> int {
+bb0:
+    %2: int = const 0
+    %3: bool = eq %0, %2
+    branch %3, bb1, bb2
+bb1:
+    jump bb3
+bb2:
+    %4: int = const 1
+    %5: int = sub %0, %4 !0..1
+    %6: int = add %1, %4 !0..1
+    %7: int = call @count(%5, %6)
+    jump bb3
+bb3:
+    %8: int = phi [bb1: %1], [bb2: %7]
+    ret %8
+}
+");
+        assert_eq!("\
+overflow trap
+
+fn @count(%0: int, %1: int) -> int {
+bb0:
+    jump bb1
+bb1:
+    %9: int = phi [bb0: %0], [bb4: %5]
+    %10: int = phi [bb0: %1], [bb4: %6]
+    %2: int = const 0
+    %3: bool = eq %9, %2
+    branch %3, bb2, bb4
+bb2:
+    jump bb3
+bb3:
+    %8: int = phi [bb2: %10]
+    ret %8
+bb4:
+    %4: int = const 1
+    %5: int = sub %9, %4 !0..1
+    %6: int = add %10, %4 !0..1
+    jump bb1
+}
+", module);
+        assert_eq!(1, report.total("tail calls turned into jumps"));
+    }
+
+    #[test]
+    pub fn values_that_tail_calls_pass_on_unchanged_move_out_of_the_loop() {
+        let mut module = lower("\
+def total(n, sum: Float, x: Float) -> Float { match n { 0 => sum, _ => total(n - 1, sum + x / 3.0, x) } }
+total(3, 0.0, 1.5)");
+        let report = PassManager::for_level(OptLevel::O2).with_verify().run(&mut module);
+        assert_eq!("\
+fn @total(%0: int, %1: float, %2: float) -> float !0..105 {
+    let n = %12 !10..11
+    let sum = %13 !13..16
+    let x = %2 !25..26
+bb0:
+    %3: int = const 0
+    %5: int = const 1
+    %7: float = const 3.0
+    jump bb1
+bb1:
+    %12: int = phi [bb0: %0], [bb3: %6]
+    %13: float = phi [bb0: %1], [bb3: %9]
+    %4: bool = eq %12, %3
+    branch %4, bb2, bb3
+bb2:
+    ret %13
+bb3:
+    %6: int = sub %12, %5 !77..82
+    %8: float = div %2, %7
+    %9: float = add %13, %8
+    jump bb1
+}
+", module.function("total").unwrap().to_string());
+        assert_eq!(1, report.total("tail calls turned into jumps"));
+        assert_eq!(3, report.total("instructions hoisted"));
+    }
+
+    #[test]
+    pub fn optimizing_a_program_leaves_what_it_computes() {
+        let mut module = lower("def square(x) { x * x } def f(y) { square(y) + square(3) } f(1)");
+        let report = PassManager::for_level(OptLevel::O2).with_verify().run(&mut module);
+        assert_eq!("\
+fn @f(%0: int) -> int !24..58 {
+    let y = %0 !30..31
+bb0:
+    %6: int = mul %0, %0 !16..21
+    %8: int = const 9
+    %4: int = add %6, %8 !35..56
+    ret %4
+}
+", module.function("f").unwrap().to_string());
+        // Both calls in `f`, then the call to `f` in `main`, and the two calls to `square` that brings with it
+        assert_eq!(5, report.total("calls inlined"));
+    }
+
+    #[test]
+    pub fn levels_choose_passes() {
+        assert_eq!(Some(OptLevel::O2), OptLevel::from_flag("-O2"));
+        assert_eq!(None, OptLevel::from_flag("-O3"));
+        assert_eq!(None, OptLevel::from_flag("O1"));
+        assert_eq!(OptLevel::O0, OptLevel::default());
+        assert!("fast".parse::<OptLevel>().is_err());
+
+        assert!(PassManager::for_level(OptLevel::O0).pass_names().is_empty());
+        assert_eq!(vec!["sccp", "gvn", "dce"], PassManager::for_level(OptLevel::O1).pass_names());
+        assert_eq!(vec!["inline", "tailrec", "sccp", "gvn", "licm", "dce", "sccp", "dce"],
+                   PassManager::for_level(OptLevel::O2).pass_names());
+    }
+
+    #[test]
+    pub fn reports_list_the_time_and_changes_of_each_pass() {
+        let mut stats = Stats::new();
+        stats.add("instructions removed", 2);
+        stats.add("blocks merged", 0);
+        stats.add("instructions removed", 1);
+        let report = Report {
+            passes: vec![
+                PassReport { name: "sccp", time: Duration::from_millis(2), stats: Stats::new() },
+                PassReport { name: "dce", time: Duration::from_micros(1500), stats },
+            ],
+        };
+        assert_eq!("\
+sccp     2.000ms  no changes
+dce      1.500ms  3 instructions removed
+", report.to_string());
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+
+use arith::{int_binary, OverflowMode};
+use cfg::{reverse_postorder, BlockId};
+use ir::{Const, Function, Inst, InstKind, Module, Terminator};
+use opt::{Pass, Stats};
+use parser::BinOp;
+
+/// Sparse conditional constant propagation: finds the values that are the same every time they're computed, assuming
+/// only the branches that can be taken are, replaces them with constants, and removes the branches that can't be taken
+///
+/// Values start out unknown and only ever become constant or varying, so the analysis reaches a fixed point even
+/// around loops. Arithmetic that would fail isn't folded, so the program still fails where it did.
+pub struct ConstantPropagation;
+
+impl Pass for ConstantPropagation {
+    fn name(&self) -> &'static str { "sccp" }
+
+    fn run(&self, module: &mut Module, stats: &mut Stats) {
+        let mode = module.overflow;
+        for function in &mut module.functions {
+            let (constants, branches) = propagate(function, mode);
+            stats.add("values folded", constants);
+            stats.add("branches folded", branches);
+        }
+    }
+}
+
+/// What's known about a value
+#[derive(Debug, PartialEq, Clone)]
+enum Lattice {
+    /// Nothing that computes it has been found to run yet
+    Unknown,
+    Constant(Const),
+    Varying,
+}
+
+impl Lattice {
+    fn meet(&self, other: &Lattice) -> Lattice {
+        match (self, other) {
+            (&Lattice::Unknown, x) | (x, &Lattice::Unknown) => x.clone(),
+            (&Lattice::Constant(ref a), &Lattice::Constant(ref b)) if a == b => self.clone(),
+            _ => Lattice::Varying,
+        }
+    }
+}
+
+fn propagate(function: &mut Function, mode: OverflowMode) -> (usize, usize) {
+    let mut values = vec![Lattice::Unknown; function.value_bound()];
+    for &(v, _) in &function.params {
+        values[v.index()] = Lattice::Varying;
+    }
+    let mut edges: HashSet<(usize, usize)> = HashSet::new();
+    let mut executable = vec![false; function.blocks.len()];
+    executable[0] = true;
+
+    let order = reverse_postorder(function);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &b in &order {
+            if !executable[b] {
+                continue;
+            }
+            let block = &function.blocks[b];
+            for inst in &block.insts {
+                let value = evaluate(inst, &values, &edges, b, mode);
+                if value != values[inst.value.index()] {
+                    values[inst.value.index()] = value;
+                    changed = true;
+                }
+            }
+
+            let taken = match block.terminator {
+                Terminator::Jump(target) => vec![target],
+                Terminator::Branch(cond, then, otherwise) => match values[cond.index()] {
+                    Lattice::Unknown => vec![],
+                    Lattice::Constant(Const::Bool(true)) => vec![then],
+                    Lattice::Constant(Const::Bool(false)) => vec![otherwise],
+                    _ => vec![then, otherwise],
+                },
+                Terminator::Return(_) | Terminator::Unreachable => vec![],
+            };
+            for target in taken {
+                if edges.insert((b, target.index())) {
+                    executable[target.index()] = true;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut folded = 0;
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            if let (&Lattice::Constant(ref c), false) = (&values[inst.value.index()], is_const(inst)) {
+                inst.kind = InstKind::Const(c.clone());
+                inst.span = None;
+                folded += 1;
+            }
+        }
+        // Phis that became constants move after the phis that are left, keeping the phis at the start of the block
+        block.insts.sort_by_key(|i| !is_phi(i));
+    }
+
+    let mut branches = 0;
+    for b in 0..function.blocks.len() {
+        if let Terminator::Branch(cond, then, otherwise) = function.blocks[b].terminator {
+            if let Lattice::Constant(Const::Bool(taken)) = values[cond.index()] {
+                let (target, dropped) = if taken { (then, otherwise) } else { (otherwise, then) };
+                function.blocks[b].terminator = Terminator::Jump(target);
+                if dropped != target {
+                    remove_incoming(function, dropped, BlockId(b as u32));
+                }
+                branches += 1;
+            }
+        }
+    }
+    function.remove_unreachable_blocks();
+    (folded, branches)
+}
+
+fn is_const(inst: &Inst) -> bool {
+    match inst.kind {
+        InstKind::Const(_) => true,
+        _ => false,
+    }
+}
+
+fn is_phi(inst: &Inst) -> bool {
+    match inst.kind {
+        InstKind::Phi(_) => true,
+        _ => false,
+    }
+}
+
+/// Removes the values the phis of a block take from a predecessor that no longer jumps to it
+fn remove_incoming(function: &mut Function, block: BlockId, pred: BlockId) {
+    for inst in &mut function.block_mut(block).insts {
+        if let InstKind::Phi(ref mut incoming) = inst.kind {
+            incoming.retain(|&(b, _)| b != pred);
+        }
+    }
+}
+
+fn evaluate(inst: &Inst, values: &[Lattice], edges: &HashSet<(usize, usize)>, block: usize, mode: OverflowMode)
+            -> Lattice {
+    match inst.kind {
+        InstKind::Const(ref c) => Lattice::Constant(c.clone()),
+        InstKind::Binary(op, a, b) => match (&values[a.index()], &values[b.index()]) {
+            (&Lattice::Constant(ref a), &Lattice::Constant(ref b)) => {
+                binary(mode, op, a, b).map_or(Lattice::Varying, Lattice::Constant)
+            }
+            (&Lattice::Varying, _) | (_, &Lattice::Varying) => Lattice::Varying,
+            _ => Lattice::Unknown,
+        },
+        // Only the values from predecessors that can reach the block count
+        InstKind::Phi(ref incoming) => incoming.iter()
+            .filter(|&&(pred, _)| edges.contains(&(pred.index(), block)))
+            .fold(Lattice::Unknown, |acc, &(_, v)| acc.meet(&values[v.index()])),
+        _ => Lattice::Varying,
+    }
+}
+
+/// Applies an operator to constants, or returns `None` if it would fail or doesn't give a constant
+pub fn binary(mode: OverflowMode, op: BinOp, a: &Const, b: &Const) -> Option<Const> {
+    if op.is_comparison() {
+        let ordering = match (a, b) {
+            (&Const::Int(ref a), &Const::Int(ref b)) => a.partial_cmp(b),
+            (&Const::Float(a), &Const::Float(b)) => a.partial_cmp(&b),
+            (&Const::Str(ref a), &Const::Str(ref b)) => a.partial_cmp(b),
+            (&Const::Bool(a), &Const::Bool(b)) => a.partial_cmp(&b),
+            (&Const::Unit, &Const::Unit) => Some(::std::cmp::Ordering::Equal),
+            _ => None,
+        };
+        return Some(Const::Bool(match op {
+            BinOp::Equal => ordering == Some(::std::cmp::Ordering::Equal),
+            BinOp::NotEqual => ordering != Some(::std::cmp::Ordering::Equal),
+            BinOp::Less => ordering == Some(::std::cmp::Ordering::Less),
+            BinOp::LessEqual => ordering.map_or(false, |o| o != ::std::cmp::Ordering::Greater),
+            BinOp::Greater => ordering == Some(::std::cmp::Ordering::Greater),
+            _ => ordering.map_or(false, |o| o != ::std::cmp::Ordering::Less),
+        }));
+    }
+
+    match (a, b) {
+        (&Const::Int(ref a), &Const::Int(ref b)) => int_binary(mode, op, a, b).ok().map(Const::Int),
+        (&Const::Float(a), &Const::Float(b)) => {
+            let result = match op {
+                BinOp::Add => a + b,
+                BinOp::Subtract => a - b,
+                BinOp::Multiply => a * b,
+                BinOp::Divide => a / b,
+                _ => return None,
+            };
+            // NaN isn't a constant the IR can hold, since it isn't equal to itself
+            if result.is_nan() { None } else { Some(Const::Float(result)) }
+        }
+        (&Const::Str(ref a), &Const::Str(ref b)) if op == BinOp::Add => Some(Const::Str(format!("{}{}", a, b))),
+        _ => None,
+    }
+}
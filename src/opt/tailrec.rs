@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use cfg::BlockId;
+use ir::{Block, Callee, Function, Inst, InstKind, Module, Terminator, Value};
+use opt::{Pass, Stats};
+
+/// Turns calls a function makes to itself, whose result it returns right away, into jumps back to its start
+///
+/// The body moves to a block after a new entry, where a phi for each parameter takes the argument from the entry or
+/// from whichever call jumped back. Recursion like this is the only way Why code repeats, so this is what gives the
+/// other passes loops to work on.
+pub struct TailCalls;
+
+impl Pass for TailCalls {
+    fn name(&self) -> &'static str { "tailrec" }
+
+    fn run(&self, module: &mut Module, stats: &mut Stats) {
+        for function in &mut module.functions {
+            stats.add("tail calls turned into jumps", eliminate_tail_calls(function));
+        }
+    }
+}
+
+fn eliminate_tail_calls(function: &mut Function) -> usize {
+    let calls = tail_calls(function);
+    if calls.is_empty() {
+        return 0;
+    }
+
+    // The body's first block becomes the loop's header, which the new entry and every tail call jump to
+    let entry = BlockId(function.blocks.len() as u32);
+    let mut next_value = function.value_bound() as u32;
+    let phis: HashMap<Value, Value> = function.params.iter().map(|&(p, _)| {
+        next_value += 1;
+        (p, Value(next_value - 1))
+    }).collect();
+    function.replace_uses(&phis);
+
+    let mut incoming: Vec<Vec<(BlockId, Value)>> = function.params.iter().map(|&(p, _)| vec![(entry, p)]).collect();
+    for &(block, join) in &calls {
+        let call = function.blocks[block].insts.pop().expect("Expected the block to end with the call");
+        let args = match call.kind {
+            InstKind::Call(_, args) => args,
+            _ => unreachable!("Expected a call"),
+        };
+        for (values, arg) in incoming.iter_mut().zip(args) {
+            values.push((BlockId(block as u32), arg));
+        }
+        function.blocks[block].terminator = Terminator::Jump(BlockId(0));
+        // The block no longer goes on to the block that returned the call's value
+        if let Some(join) = join {
+            for inst in &mut function.blocks[join].insts {
+                if let InstKind::Phi(ref mut values) = inst.kind {
+                    values.retain(|&(b, _)| b.index() != block);
+                }
+            }
+        }
+    }
+
+    let header: Vec<Inst> = function.params.iter().zip(incoming).map(|(&(p, ty), values)| {
+        Inst::new(phis[&p], ty, InstKind::Phi(values))
+    }).collect();
+    function.blocks[0].insts.splice(0..0, header);
+    let mut block = Block::new();
+    block.terminator = Terminator::Jump(BlockId(0));
+    function.blocks.push(block);
+
+    let mut order = vec![entry.index()];
+    order.extend(0..entry.index());
+    function.retain_blocks(&order);
+    function.sort_blocks();
+    calls.len()
+}
+
+/// Finds the blocks that end with a call the function makes to itself, where the value returned is the call's
+///
+/// The value is either returned by the block itself, or by the block it jumps to, which is given with it. That block
+/// can only hold phis, which is how the arms of a `match` in tail position return.
+fn tail_calls(function: &Function) -> Vec<(usize, Option<usize>)> {
+    let mut calls = Vec::new();
+    for (b, block) in function.blocks.iter().enumerate() {
+        let call = match block.insts.last() {
+            Some(&Inst { value, kind: InstKind::Call(Callee::Direct(ref name), _), .. }) if *name == function.name => {
+                value
+            }
+            _ => continue,
+        };
+        match block.terminator {
+            Terminator::Return(v) if v == call => calls.push((b, None)),
+            Terminator::Jump(join) => {
+                let join_block = &function.blocks[join.index()];
+                let only_phis = join_block.insts.iter().all(|i| match i.kind {
+                    InstKind::Phi(_) => true,
+                    _ => false,
+                });
+                let returns_call = join_block.insts.iter().any(|i| match (&i.kind, &join_block.terminator) {
+                    (&InstKind::Phi(ref values), &Terminator::Return(r)) => {
+                        r == i.value && values.contains(&(BlockId(b as u32), call))
+                    }
+                    _ => false,
+                });
+                if only_phis && returns_call {
+                    calls.push((b, Some(join.index())));
+                }
+            }
+            _ => {}
+        }
+    }
+    calls
+}
@@ -0,0 +1,23 @@
+use text::TextSpan;
+use parser::Ident;
+
+/// Extra information about the code it's attached to, which doesn't change what the code means: `#[allow(shadowing)]`
+///
+/// Attributes written `#[...]` apply to the declaration after them, while those written `#![...]` at the start of a
+/// program apply to the whole program.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Attribute {
+    span: TextSpan,
+    name: Ident,
+    args: Vec<Ident>,
+}
+
+impl Attribute {
+    pub fn new(span: TextSpan, name: Ident, args: Vec<Ident>) -> Attribute {
+        Attribute { span, name, args }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn args(&self) -> &[Ident] { &self.args }
+}
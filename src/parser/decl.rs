@@ -0,0 +1,281 @@
+use text::TextSpan;
+use parser::{Attribute, Block, Ident, Pattern, TypeExpr};
+
+/// A top-level declaration
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Decl {
+    Struct(StructDecl),
+    Enum(EnumDecl),
+    Def(DefDecl),
+    Extern(ExternDecl),
+    Trait(TraitDecl),
+    Impl(ImplDecl),
+}
+
+impl Decl {
+    pub fn span(&self) -> TextSpan {
+        match self {
+            &Decl::Struct(ref s) => s.span(),
+            &Decl::Enum(ref e) => e.span(),
+            &Decl::Def(ref d) => d.span(),
+            &Decl::Extern(ref e) => e.span(),
+            &Decl::Trait(ref t) => t.span(),
+            &Decl::Impl(ref i) => i.span(),
+        }
+    }
+}
+
+/// A struct declaration: `struct Point { x: Int, y: Int }`
+///
+/// A struct may have type parameters, which can be used in the types of its fields: `struct Pair<T> { a: T, b: T }`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StructDecl {
+    span: TextSpan,
+    name: Ident,
+    type_params: Vec<Ident>,
+    fields: Vec<FieldDecl>,
+}
+
+impl StructDecl {
+    pub fn new(span: TextSpan, name: Ident, type_params: Vec<Ident>, fields: Vec<FieldDecl>) -> StructDecl {
+        StructDecl { span, name, type_params, fields }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn type_params(&self) -> &[Ident] { &self.type_params }
+    pub fn fields(&self) -> &[FieldDecl] { &self.fields }
+
+    /// Gets the position of the named field within the struct, which is also its
+    /// position in the struct's memory layout
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f.name().name() == name)
+    }
+
+    /// Gets the declaration of the named field, if the struct has one
+    pub fn field(&self, name: &str) -> Option<&FieldDecl> {
+        self.field_index(name).map(|i| &self.fields[i])
+    }
+}
+
+/// A single field within a struct declaration: `x: Int`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FieldDecl {
+    name: Ident,
+    ty: TypeExpr,
+}
+
+impl FieldDecl {
+    pub fn new(name: Ident, ty: TypeExpr) -> FieldDecl {
+        FieldDecl { name, ty }
+    }
+
+    pub fn span(&self) -> TextSpan { self.name.span().to(self.ty.span()) }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn ty(&self) -> &TypeExpr { &self.ty }
+}
+
+/// An enum declaration: `enum Shape { Circle(Int), Rect(Int, Int) }`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EnumDecl {
+    span: TextSpan,
+    name: Ident,
+    type_params: Vec<Ident>,
+    variants: Vec<VariantDecl>,
+}
+
+impl EnumDecl {
+    pub fn new(span: TextSpan, name: Ident, type_params: Vec<Ident>, variants: Vec<VariantDecl>) -> EnumDecl {
+        EnumDecl { span, name, type_params, variants }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn type_params(&self) -> &[Ident] { &self.type_params }
+    pub fn variants(&self) -> &[VariantDecl] { &self.variants }
+}
+
+/// A single variant within an enum declaration: `Rect(Int, Int)`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VariantDecl {
+    span: TextSpan,
+    name: Ident,
+    fields: Vec<TypeExpr>,
+}
+
+impl VariantDecl {
+    pub fn new(span: TextSpan, name: Ident, fields: Vec<TypeExpr>) -> VariantDecl {
+        VariantDecl { span, name, fields }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn fields(&self) -> &[TypeExpr] { &self.fields }
+}
+
+/// A function declaration: `def swap((a, b)) { (b, a) }`
+///
+/// The parameters and result may be given type annotations: `def f(x: Int) -> Int { x }`, which may refer to the
+/// function's type parameters: `def id<T>(x: T) -> T { x }`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DefDecl {
+    span: TextSpan,
+    name: Ident,
+    type_params: Vec<TypeParam>,
+    params: Vec<Param>,
+    ret: Option<TypeExpr>,
+    body: Block,
+    attrs: Vec<Attribute>,
+}
+
+impl DefDecl {
+    pub fn new(span: TextSpan, name: Ident, type_params: Vec<TypeParam>, params: Vec<Param>, ret: Option<TypeExpr>, body: Block) -> DefDecl {
+        DefDecl { span, name, type_params, params, ret, body, attrs: Vec::new() }
+    }
+
+    pub fn with_attrs(mut self, attrs: Vec<Attribute>) -> DefDecl {
+        self.attrs = attrs;
+        self
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn type_params(&self) -> &[TypeParam] { &self.type_params }
+    pub fn params(&self) -> &[Param] { &self.params }
+    pub fn ret(&self) -> Option<&TypeExpr> { self.ret.as_ref() }
+    pub fn body(&self) -> &Block { &self.body }
+
+    /// The attributes written before the `def`, which aren't part of its span
+    pub fn attrs(&self) -> &[Attribute] { &self.attrs }
+}
+
+/// A type parameter of a function, along with the traits its type arguments must implement: `T: Show + Eq`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TypeParam {
+    name: Ident,
+    bounds: Vec<Ident>,
+}
+
+impl TypeParam {
+    pub fn new(name: Ident, bounds: Vec<Ident>) -> TypeParam {
+        TypeParam { name, bounds }
+    }
+
+    pub fn span(&self) -> TextSpan {
+        match self.bounds.last() {
+            Some(b) => self.name.span().to(b.span()),
+            None => self.name.span(),
+        }
+    }
+
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn bounds(&self) -> &[Ident] { &self.bounds }
+}
+
+/// A declaration of a function defined outside of Why: `extern puts(s: Str) -> CInt;`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExternDecl {
+    span: TextSpan,
+    name: Ident,
+    params: Vec<Param>,
+    ret: Option<TypeExpr>,
+}
+
+impl ExternDecl {
+    pub fn new(span: TextSpan, name: Ident, params: Vec<Param>, ret: Option<TypeExpr>) -> ExternDecl {
+        ExternDecl { span, name, params, ret }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn params(&self) -> &[Param] { &self.params }
+    pub fn ret(&self) -> Option<&TypeExpr> { self.ret.as_ref() }
+}
+
+/// A trait declaration, listing the methods its implementations provide: `trait Show { def show(self) -> Str }`
+///
+/// The first parameter of each method is the value it's called on, which must be named `self`. The type of `self`, and
+/// any other use of `Self` within the trait, is the type implementing it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TraitDecl {
+    span: TextSpan,
+    name: Ident,
+    methods: Vec<MethodSig>,
+}
+
+impl TraitDecl {
+    pub fn new(span: TextSpan, name: Ident, methods: Vec<MethodSig>) -> TraitDecl {
+        TraitDecl { span, name, methods }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn methods(&self) -> &[MethodSig] { &self.methods }
+}
+
+/// The signature of a method within a trait declaration: `def show(self) -> Str`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MethodSig {
+    span: TextSpan,
+    name: Ident,
+    params: Vec<Param>,
+    ret: Option<TypeExpr>,
+}
+
+impl MethodSig {
+    pub fn new(span: TextSpan, name: Ident, params: Vec<Param>, ret: Option<TypeExpr>) -> MethodSig {
+        MethodSig { span, name, params, ret }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn params(&self) -> &[Param] { &self.params }
+    pub fn ret(&self) -> Option<&TypeExpr> { self.ret.as_ref() }
+}
+
+/// An implementation of a trait for a type: `impl Show for Point { def show(self) -> Str { "point" } }`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ImplDecl {
+    span: TextSpan,
+    trait_name: Ident,
+    ty: TypeExpr,
+    methods: Vec<DefDecl>,
+}
+
+impl ImplDecl {
+    pub fn new(span: TextSpan, trait_name: Ident, ty: TypeExpr, methods: Vec<DefDecl>) -> ImplDecl {
+        ImplDecl { span, trait_name, ty, methods }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+
+    /// Gets the span of everything before the methods: `impl Show for Point`
+    pub fn header_span(&self) -> TextSpan { self.span.to(self.ty.span()) }
+
+    pub fn trait_name(&self) -> &Ident { &self.trait_name }
+    pub fn ty(&self) -> &TypeExpr { &self.ty }
+    pub fn methods(&self) -> &[DefDecl] { &self.methods }
+}
+
+/// A function parameter. Arguments are bound to the names in the pattern as if by a `let`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Param {
+    pattern: Pattern,
+    ty: Option<TypeExpr>,
+}
+
+impl Param {
+    pub fn new(pattern: Pattern, ty: Option<TypeExpr>) -> Param {
+        Param { pattern, ty }
+    }
+
+    pub fn span(&self) -> TextSpan {
+        match self.ty {
+            Some(ref ty) => self.pattern.span().to(ty.span()),
+            None => self.pattern.span(),
+        }
+    }
+
+    pub fn pattern(&self) -> &Pattern { &self.pattern }
+    pub fn ty(&self) -> Option<&TypeExpr> { self.ty.as_ref() }
+}
@@ -1,14 +1,24 @@
-use tokenizer;
+use tokenizer::{self, Token, TokenType};
+use text::TextSpan;
 
-// TODO: We'll want Error IDs and Text Spans
+// TODO: We'll want Error IDs
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Error {
     UnexpectedEndOfFile,
     TokenizerError(tokenizer::Error),
+
+    /// The token could not be used at this point in the program
+    UnexpectedToken(Token),
+
+    /// A token of the specified type was required, but a different token was found
+    ExpectedToken(TokenType, Token),
+
+    /// The left-hand side of an assignment is not a variable or field
+    InvalidAssignmentTarget(TextSpan),
 }
 
 impl From<tokenizer::Error> for Error {
     fn from(e: tokenizer::Error) -> Error {
         Error::TokenizerError(e)
     }
-}
\ No newline at end of file
+}
@@ -1,48 +1,238 @@
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+use bignum::Integer;
+use text::TextSpan;
+use parser::{Block, Ident, Pattern};
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Lit {
-    Int(i64)
+    Int(Integer),
+    Float(f64),
+    Bool(bool),
+    Str(String),
 }
 
+// Float literals are parsed from digits, so they are never NaN, which is the only value that breaks `Eq`
+impl Eq for Lit {}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum BinOp {
     Add,
     Subtract,
     Multiply,
     Divide,
+    Power,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
 }
 
 impl BinOp {
     pub fn precedence(self) -> usize {
         match self {
+            BinOp::Equal | BinOp::NotEqual |
+            BinOp::Less | BinOp::LessEqual |
+            BinOp::Greater | BinOp::GreaterEqual => 5,
             BinOp::Add | BinOp::Subtract => 10,
             BinOp::Multiply | BinOp::Divide => 20,
+            BinOp::Power => 30,
+        }
+    }
+
+    /// Gets the operator as it is written in source
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Subtract => "-",
+            BinOp::Multiply => "*",
+            BinOp::Divide => "/",
+            BinOp::Power => "**",
+            BinOp::Equal => "==",
+            BinOp::NotEqual => "!=",
+            BinOp::Less => "<",
+            BinOp::LessEqual => "<=",
+            BinOp::Greater => ">",
+            BinOp::GreaterEqual => ">=",
+        }
+    }
+
+    /// Returns `true` for the operators that compare their operands, producing a `Bool`
+    pub fn is_comparison(self) -> bool {
+        match self {
+            BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide | BinOp::Power => false,
+            _ => true,
         }
     }
+
+    /// Returns `true` if a chain of the operator groups from the right, like `2 ** 3 ** 2`
+    pub fn is_right_associative(self) -> bool {
+        self == BinOp::Power
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Expr {
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Expr {
+    span: TextSpan,
+    kind: ExprKind,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExprKind {
     Constant(Lit),
-    Binary(Box<Expr>, Box<Expr>, BinOp),
+    Var(Ident),
+    /// Applies an operator to two operands: `a + b`. The span is the operator's own
+    Binary(Box<Expr>, Box<Expr>, BinOp, TextSpan),
+
+    /// Constructs a new struct value: `Point { x: 1, y: 2 }`
+    Struct(Ident, Vec<FieldInit>),
+
+    /// Reads a field from a struct value: `p.x`
+    Field(Box<Expr>, Ident),
+
+    /// Assigns a new value to a variable or field: `p.x = 3`
+    Assign(Box<Expr>, Box<Expr>),
+
+    /// Applies a callee to a list of arguments: `Circle(2)`
+    Call(Box<Expr>, Vec<Expr>),
+
+    /// Calls a method of a trait implemented by the type of the receiver: `p.show()`
+    MethodCall(Box<Expr>, Ident, Vec<Expr>),
+
+    /// Selects the first arm whose pattern matches the value of the scrutinee
+    Match(Box<Expr>, Vec<MatchArm>),
+
+    /// Groups several values into one: `(a, b)`
+    Tuple(Vec<Expr>),
+
+    /// Runs a sequence of statements, producing the value of its final expression: `{ let x = 1; x + 1 }`
+    Block(Block),
+
+    /// Leaves the enclosing function with a value, or with `()` if there isn't one: `return x + 1`
+    Return(Option<Box<Expr>>),
 }
 
 impl Expr {
-    pub fn constant<I: Into<Lit>>(val: I) -> Expr {
-        Expr::Constant(val.into())
+    pub fn new(span: TextSpan, kind: ExprKind) -> Expr {
+        Expr { span, kind }
+    }
+
+    pub fn constant<I: Into<Lit>>(span: TextSpan, val: I) -> Expr {
+        Expr::new(span, ExprKind::Constant(val.into()))
+    }
+
+    pub fn var(name: Ident) -> Expr {
+        Expr::new(name.span(), ExprKind::Var(name))
+    }
+
+    pub fn binary(l: Expr, r: Expr, op: BinOp, op_span: TextSpan) -> Expr {
+        Expr::new(l.span.to(r.span), ExprKind::Binary(Box::new(l), Box::new(r), op, op_span))
+    }
+
+    pub fn structure(span: TextSpan, name: Ident, fields: Vec<FieldInit>) -> Expr {
+        Expr::new(span, ExprKind::Struct(name, fields))
+    }
+
+    pub fn field(target: Expr, name: Ident) -> Expr {
+        Expr::new(target.span.to(name.span()), ExprKind::Field(Box::new(target), name))
+    }
+
+    pub fn assign(target: Expr, value: Expr) -> Expr {
+        Expr::new(target.span.to(value.span), ExprKind::Assign(Box::new(target), Box::new(value)))
+    }
+
+    pub fn call(span: TextSpan, callee: Expr, args: Vec<Expr>) -> Expr {
+        Expr::new(span, ExprKind::Call(Box::new(callee), args))
+    }
+
+    pub fn method_call(span: TextSpan, receiver: Expr, name: Ident, args: Vec<Expr>) -> Expr {
+        Expr::new(span, ExprKind::MethodCall(Box::new(receiver), name, args))
+    }
+
+    pub fn match_(span: TextSpan, scrutinee: Expr, arms: Vec<MatchArm>) -> Expr {
+        Expr::new(span, ExprKind::Match(Box::new(scrutinee), arms))
+    }
+
+    pub fn tuple(span: TextSpan, elements: Vec<Expr>) -> Expr {
+        Expr::new(span, ExprKind::Tuple(elements))
     }
 
-    pub fn binary(l: Expr, r: Expr, op: BinOp) -> Expr {
-        Expr::Binary(Box::new(l), Box::new(r), op)
+    pub fn block(block: Block) -> Expr {
+        Expr::new(block.span(), ExprKind::Block(block))
+    }
+
+    pub fn return_(span: TextSpan, value: Option<Expr>) -> Expr {
+        Expr::new(span, ExprKind::Return(value.map(Box::new)))
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn kind(&self) -> &ExprKind { &self.kind }
+
+    /// Returns `true` if this expression denotes a location that can be assigned to
+    pub fn is_place(&self) -> bool {
+        match self.kind {
+            ExprKind::Var(_) => true,
+            ExprKind::Field(ref target, _) => target.is_place(),
+            _ => false,
+        }
     }
 
     pub fn precedence(&self) -> usize {
-        match self {
-            &Expr::Constant(_) => 0,
-            &Expr::Binary(_, _, op) => op.precedence()
+        match self.kind {
+            ExprKind::Binary(_, _, op, _) => op.precedence(),
+            _ => 0,
         }
     }
 }
 
+/// A single field initializer within a struct expression: `x: 1`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FieldInit {
+    name: Ident,
+    value: Expr,
+}
+
+impl FieldInit {
+    pub fn new(name: Ident, value: Expr) -> FieldInit {
+        FieldInit { name, value }
+    }
+
+    pub fn span(&self) -> TextSpan { self.name.span().to(self.value.span()) }
+    pub fn name(&self) -> &Ident { &self.name }
+    pub fn value(&self) -> &Expr { &self.value }
+}
+
+/// A single arm of a match expression: `Rect(w, h) if w == h => w * w`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MatchArm {
+    pattern: Pattern,
+    guard: Option<Expr>,
+    body: Expr,
+}
+
+impl MatchArm {
+    pub fn new(pattern: Pattern, guard: Option<Expr>, body: Expr) -> MatchArm {
+        MatchArm { pattern, guard, body }
+    }
+
+    pub fn span(&self) -> TextSpan { self.pattern.span().to(self.body.span()) }
+    pub fn pattern(&self) -> &Pattern { &self.pattern }
+    pub fn guard(&self) -> Option<&Expr> { self.guard.as_ref() }
+    pub fn body(&self) -> &Expr { &self.body }
+}
+
 impl From<i64> for Lit {
-    fn from(v: i64) -> Lit { Lit::Int(v) }
-}
\ No newline at end of file
+    fn from(v: i64) -> Lit { Lit::Int(v.into()) }
+}
+
+impl From<bool> for Lit {
+    fn from(v: bool) -> Lit { Lit::Bool(v) }
+}
+
+impl From<f64> for Lit {
+    fn from(v: f64) -> Lit { Lit::Float(v) }
+}
+
+impl<'a> From<&'a str> for Lit {
+    fn from(v: &'a str) -> Lit { Lit::Str(v.into()) }
+}
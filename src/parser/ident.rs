@@ -0,0 +1,20 @@
+use text::TextSpan;
+
+/// An identifier, along with the span at which it appeared in the source
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Ident {
+    span: TextSpan,
+    name: String,
+}
+
+impl Ident {
+    pub fn new<S: Into<String>>(span: TextSpan, name: S) -> Ident {
+        Ident {
+            span,
+            name: name.into(),
+        }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn name(&self) -> &str { &self.name }
+}
@@ -1,7 +1,21 @@
+mod attr;
+mod decl;
 mod error;
 mod expr;
+mod ident;
 mod parser;
+mod pattern;
+mod stmt;
+mod types;
+pub mod visit;
 
+pub use self::attr::Attribute;
+pub use self::decl::{Decl, StructDecl, FieldDecl, EnumDecl, VariantDecl, DefDecl, TypeParam, ExternDecl, TraitDecl, MethodSig,
+                     ImplDecl, Param};
 pub use self::error::Error;
-pub use self::expr::{Expr, Lit, BinOp};
+pub use self::expr::{Expr, ExprKind, FieldInit, MatchArm, Lit, BinOp};
+pub use self::ident::Ident;
 pub use self::parser::Parser;
+pub use self::pattern::{Pattern, PatternKind};
+pub use self::stmt::{Stmt, LetStmt, Block, Program};
+pub use self::types::{TypeExpr, TypeExprKind};
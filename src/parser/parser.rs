@@ -1,9 +1,17 @@
-use tokenizer::{self, Token, TokenType, TokenValue};
-use parser::{Expr, BinOp, Error};
+use bignum::Integer;
+use tokenizer::{self, Token, TokenType, TokenValue, Keyword};
+use text::TextSpan;
+use parser::{Expr, FieldInit, MatchArm, Lit, BinOp, Error, Ident, Pattern, TypeExpr, Decl, StructDecl, FieldDecl,
+             EnumDecl, VariantDecl, DefDecl, TypeParam, ExternDecl, TraitDecl, MethodSig, ImplDecl, Param, Stmt, LetStmt,
+             Block, Program, Attribute};
 
 pub struct Parser<I: Iterator<Item=Result<Token, tokenizer::Error>>> {
     tokens: I,
-    current: Option<Result<Token, tokenizer::Error>>
+    current: Option<Result<Token, tokenizer::Error>>,
+
+    // When set, an identifier followed by '{' is not treated as a struct literal.
+    // This is needed wherever a '{' can legitimately follow an expression, such as `match x { ... }`
+    no_struct: bool,
 }
 
 impl<I: Iterator<Item=Result<Token, tokenizer::Error>>> Parser<I> {
@@ -11,12 +19,286 @@ impl<I: Iterator<Item=Result<Token, tokenizer::Error>>> Parser<I> {
         let first = tokens.next();
         Parser {
             tokens,
-            current: first
+            current: first,
+            no_struct: false,
+        }
+    }
+
+    /// Parses an entire program, consuming all remaining tokens
+    pub fn program(&mut self) -> Result<Program, Error> {
+        let mut attrs = Vec::new();
+        while self.at(TokenType::HashBang) {
+            attrs.push(self.attribute(TokenType::HashBang)?);
+        }
+        let (stmts, result) = self.stmts(None)?;
+        Ok(Program::new(stmts, result).with_attrs(attrs))
+    }
+
+    /// Parses statements up to the provided closing token, or the end of the file if there is none
+    ///
+    /// An expression that isn't followed by a semicolon must be the last thing before the closing
+    /// token, and is returned as the final expression.
+    fn stmts(&mut self, close: Option<TokenType>) -> Result<(Vec<Stmt>, Option<Expr>), Error> {
+        let mut stmts = Vec::new();
+        while !self.at_close(close) {
+            if self.at(TokenType::Hash) {
+                let mut attrs = Vec::new();
+                while self.at(TokenType::Hash) {
+                    attrs.push(self.attribute(TokenType::Hash)?);
+                }
+                stmts.push(Stmt::Decl(Decl::Def(self.def_decl()?.with_attrs(attrs))));
+                continue;
+            }
+            match self.cur()?.value() {
+                &TokenValue::Kwd(Keyword::Struct) |
+                &TokenValue::Kwd(Keyword::Enum) |
+                &TokenValue::Kwd(Keyword::Def) |
+                &TokenValue::Kwd(Keyword::Extern) |
+                &TokenValue::Kwd(Keyword::Trait) |
+                &TokenValue::Kwd(Keyword::Impl) => stmts.push(Stmt::Decl(self.decl()?)),
+                &TokenValue::Kwd(Keyword::Let) => stmts.push(Stmt::Let(self.let_stmt()?)),
+                _ => {
+                    let expr = self.expr()?;
+                    if self.at_close(close) {
+                        // No semicolon, and nothing else before the end. This is the final expression.
+                        return Ok((stmts, Some(expr)));
+                    }
+                    self.expect(TokenType::Semicolon)?;
+                    stmts.push(Stmt::Expr(expr));
+                }
+            }
+        }
+        Ok((stmts, None))
+    }
+
+    pub fn decl(&mut self) -> Result<Decl, Error> {
+        match self.cur()?.value() {
+            &TokenValue::Kwd(Keyword::Struct) => Ok(Decl::Struct(self.struct_decl()?)),
+            &TokenValue::Kwd(Keyword::Enum) => Ok(Decl::Enum(self.enum_decl()?)),
+            &TokenValue::Kwd(Keyword::Def) => Ok(Decl::Def(self.def_decl()?)),
+            &TokenValue::Kwd(Keyword::Extern) => Ok(Decl::Extern(self.extern_decl()?)),
+            &TokenValue::Kwd(Keyword::Trait) => Ok(Decl::Trait(self.trait_decl()?)),
+            &TokenValue::Kwd(Keyword::Impl) => Ok(Decl::Impl(self.impl_decl()?)),
+            _ => Err(Error::UnexpectedToken(self.cur()?.clone())),
+        }
+    }
+
+    fn struct_decl(&mut self) -> Result<StructDecl, Error> {
+        let start = self.expect_keyword(Keyword::Struct)?.span();
+        let name = self.ident()?;
+        let type_params = self.type_param_names()?;
+        self.expect(TokenType::LBrace)?;
+        let mut fields = Vec::new();
+        while !self.at(TokenType::RBrace) {
+            let field_name = self.ident()?;
+            self.expect(TokenType::Colon)?;
+            let ty = self.type_expr()?;
+            fields.push(FieldDecl::new(field_name, ty));
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::RBrace)?.span();
+        Ok(StructDecl::new(start.to(end), name, type_params, fields))
+    }
+
+    fn enum_decl(&mut self) -> Result<EnumDecl, Error> {
+        let start = self.expect_keyword(Keyword::Enum)?.span();
+        let name = self.ident()?;
+        let type_params = self.type_param_names()?;
+        self.expect(TokenType::LBrace)?;
+        let mut variants = Vec::new();
+        while !self.at(TokenType::RBrace) {
+            let variant_name = self.ident()?;
+            let mut span = variant_name.span();
+            let mut fields = Vec::new();
+            if self.at(TokenType::LParen) {
+                let (list_span, types, _) = self.paren_list(|p| p.type_expr())?;
+                span = span.to(list_span);
+                fields = types;
+            }
+            variants.push(VariantDecl::new(span, variant_name, fields));
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::RBrace)?.span();
+        Ok(EnumDecl::new(start.to(end), name, type_params, variants))
+    }
+
+    fn def_decl(&mut self) -> Result<DefDecl, Error> {
+        let start = self.expect_keyword(Keyword::Def)?.span();
+        let name = self.ident()?;
+        let type_params = self.type_params()?;
+        let (_, params, _) = self.paren_list(|p| p.param())?;
+        let ret = self.return_type()?;
+        let body = self.block()?;
+        Ok(DefDecl::new(start.to(body.span()), name, type_params, params, ret, body))
+    }
+
+    /// Parses an attribute opened by `open`, which is either `#` or `#!`: `#[allow(shadowing)]`
+    fn attribute(&mut self, open: TokenType) -> Result<Attribute, Error> {
+        let start = self.expect(open)?.span();
+        self.expect(TokenType::LBracket)?;
+        let name = self.ident()?;
+        let args = if self.at(TokenType::LParen) { self.paren_list(|p| p.ident())?.1 } else { Vec::new() };
+        let end = self.expect(TokenType::RBracket)?.span();
+        Ok(Attribute::new(start.to(end), name, args))
+    }
+
+    fn extern_decl(&mut self) -> Result<ExternDecl, Error> {
+        let start = self.expect_keyword(Keyword::Extern)?.span();
+        let name = self.ident()?;
+        let (_, params, _) = self.paren_list(|p| p.param())?;
+        let ret = self.return_type()?;
+        let end = self.expect(TokenType::Semicolon)?.span();
+        Ok(ExternDecl::new(start.to(end), name, params, ret))
+    }
+
+    fn trait_decl(&mut self) -> Result<TraitDecl, Error> {
+        let start = self.expect_keyword(Keyword::Trait)?.span();
+        let name = self.ident()?;
+        self.expect(TokenType::LBrace)?;
+        let mut methods = Vec::new();
+        while !self.at(TokenType::RBrace) {
+            let def = self.expect_keyword(Keyword::Def)?.span();
+            let method_name = self.ident()?;
+            let (params_span, params, _) = self.paren_list(|p| p.param())?;
+            let ret = self.return_type()?;
+            let end = ret.as_ref().map(|r| r.span()).unwrap_or(params_span);
+            methods.push(MethodSig::new(def.to(end), method_name, params, ret));
+
+            // Signatures can be separated by semicolons, but don't need to be
+            self.eat(TokenType::Semicolon);
+        }
+        let end = self.expect(TokenType::RBrace)?.span();
+        Ok(TraitDecl::new(start.to(end), name, methods))
+    }
+
+    fn impl_decl(&mut self) -> Result<ImplDecl, Error> {
+        let start = self.expect_keyword(Keyword::Impl)?.span();
+        let trait_name = self.ident()?;
+        self.expect_keyword(Keyword::For)?;
+        let ty = self.type_expr()?;
+        self.expect(TokenType::LBrace)?;
+        let mut methods = Vec::new();
+        while !self.at(TokenType::RBrace) {
+            methods.push(self.def_decl()?);
+        }
+        let end = self.expect(TokenType::RBrace)?.span();
+        Ok(ImplDecl::new(start.to(end), trait_name, ty, methods))
+    }
+
+    /// Parses an optional list of type parameters, each of which may have bounds: `<T: Show + Eq, U>`
+    fn type_params(&mut self) -> Result<Vec<TypeParam>, Error> {
+        if !self.at(TokenType::Less) {
+            return Ok(Vec::new());
+        }
+        let (_, params) = self.angle_list(|p| {
+            let name = p.ident()?;
+            let mut bounds = Vec::new();
+            if p.eat(TokenType::Colon) {
+                bounds.push(p.ident()?);
+                while p.eat(TokenType::Plus) {
+                    bounds.push(p.ident()?);
+                }
+            }
+            Ok(TypeParam::new(name, bounds))
+        })?;
+        Ok(params)
+    }
+
+    /// Parses an optional list of type parameters without bounds, as taken by structs and enums: `<T, U>`
+    fn type_param_names(&mut self) -> Result<Vec<Ident>, Error> {
+        if self.at(TokenType::Less) {
+            Ok(self.angle_list(|p| p.ident())?.1)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn param(&mut self) -> Result<Param, Error> {
+        let pattern = self.pattern()?;
+        let ty = self.type_annotation()?;
+        Ok(Param::new(pattern, ty))
+    }
+
+    /// Parses an optional `: Type` annotation
+    fn type_annotation(&mut self) -> Result<Option<TypeExpr>, Error> {
+        if self.eat(TokenType::Colon) {
+            Ok(Some(self.type_expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parses an optional `-> Type` annotation
+    fn return_type(&mut self) -> Result<Option<TypeExpr>, Error> {
+        if self.eat(TokenType::Arrow) {
+            Ok(Some(self.type_expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn let_stmt(&mut self) -> Result<LetStmt, Error> {
+        let start = self.expect_keyword(Keyword::Let)?.span();
+        let pattern = self.pattern()?;
+        let ty = self.type_annotation()?;
+        self.expect(TokenType::Assign)?;
+        let value = self.expr()?;
+        let end = self.expect(TokenType::Semicolon)?.span();
+        Ok(LetStmt::new(start.to(end), pattern, ty, value))
+    }
+
+    pub fn block(&mut self) -> Result<Block, Error> {
+        let start = self.expect(TokenType::LBrace)?.span();
+        let (stmts, result) = self.restrict(false, |p| p.stmts(Some(TokenType::RBrace)))?;
+        let end = self.expect(TokenType::RBrace)?.span();
+        Ok(Block::new(start.to(end), stmts, result))
+    }
+
+    pub fn type_expr(&mut self) -> Result<TypeExpr, Error> {
+        if self.at(TokenType::LParen) {
+            let (span, mut elements, trailing_comma) = self.paren_list(|p| p.type_expr())?;
+            if elements.len() == 1 && !trailing_comma {
+                // Just a parenthesized type, not a tuple
+                Ok(elements.remove(0))
+            } else {
+                Ok(TypeExpr::tuple(span, elements))
+            }
+        } else if self.at_keyword(Keyword::Dyn) {
+            let start = self.expect_keyword(Keyword::Dyn)?.span();
+            let trait_name = self.ident()?;
+            Ok(TypeExpr::dyn_(start.to(trait_name.span()), trait_name))
+        } else {
+            let name = self.ident()?;
+            if self.at(TokenType::Less) {
+                let (span, args) = self.angle_list(|p| p.type_expr())?;
+                Ok(TypeExpr::generic(name.span().to(span), name, args))
+            } else {
+                Ok(TypeExpr::named(name))
+            }
         }
     }
 
     pub fn expr(&mut self) -> Result<Expr, Error> {
-        let primary = self.primary_expr()?;
+        let lhs = self.binary_expr()?;
+        if self.eat(TokenType::Assign) {
+            if !lhs.is_place() {
+                return Err(Error::InvalidAssignmentTarget(lhs.span()));
+            }
+
+            // Assignment is right-associative, so just parse the whole right-hand side
+            let rhs = self.expr()?;
+            Ok(Expr::assign(lhs, rhs))
+        } else {
+            Ok(lhs)
+        }
+    }
+
+    fn binary_expr(&mut self) -> Result<Expr, Error> {
+        let primary = self.postfix_expr()?;
         self.expr_rhs(primary, 0)
     }
 
@@ -28,20 +310,23 @@ impl<I: Iterator<Item=Result<Token, tokenizer::Error>>> Parser<I> {
             }
 
             // Consume the binop
+            let op_span = self.cur()?.span();
             self.next();
 
             // Parse the next primary expression
-            let mut rhs = self.primary_expr().expect("TODO: Error handling for trailing binary operator");
+            let mut rhs = self.postfix_expr()?;
             if let Some(next_binop) = self.peek_binop() {
-                // There's another binary operator, does it bind more strongly?
-                if next_binop.precedence() > binop.precedence() {
+                // There's another binary operator, does it bind more strongly? A right associative operator also
+                // takes the rest of a chain of itself as its right side
+                let min = if binop.is_right_associative() { binop.precedence() } else { binop.precedence() + 1 };
+                if next_binop.precedence() >= min {
                     // It does. Parse the right-side of this operator
-                    rhs = self.expr_rhs(rhs, binop.precedence() + 1)?
+                    rhs = self.expr_rhs(rhs, min)?
                 }
             }
 
             // Merge lhs/rhs
-            lhs = Expr::binary(lhs, rhs, binop);
+            lhs = Expr::binary(lhs, rhs, binop, op_span);
         }
         Ok(lhs)
     }
@@ -52,7 +337,14 @@ impl<I: Iterator<Item=Result<Token, tokenizer::Error>>> Parser<I> {
                 TokenType::Plus => Some(BinOp::Add),
                 TokenType::Minus => Some(BinOp::Subtract),
                 TokenType::Star => Some(BinOp::Multiply),
+                TokenType::StarStar => Some(BinOp::Power),
                 TokenType::Slash => Some(BinOp::Divide),
+                TokenType::Equal => Some(BinOp::Equal),
+                TokenType::NotEqual => Some(BinOp::NotEqual),
+                TokenType::Less => Some(BinOp::Less),
+                TokenType::LessEqual => Some(BinOp::LessEqual),
+                TokenType::Greater => Some(BinOp::Greater),
+                TokenType::GreaterEqual => Some(BinOp::GreaterEqual),
                 _ => None,
             }
         } else {
@@ -60,22 +352,294 @@ impl<I: Iterator<Item=Result<Token, tokenizer::Error>>> Parser<I> {
         }
     }
 
+    fn postfix_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.primary_expr()?;
+        loop {
+            if self.eat(TokenType::Dot) {
+                let name = self.ident()?;
+                if self.at(TokenType::LParen) {
+                    let (args_span, args, _) = self.paren_list(|p| p.expr())?;
+                    expr = Expr::method_call(expr.span().to(args_span), expr, name, args);
+                } else {
+                    expr = Expr::field(expr, name);
+                }
+            } else if self.at(TokenType::LParen) {
+                let (args_span, args, _) = self.paren_list(|p| p.expr())?;
+                expr = Expr::call(expr.span().to(args_span), expr, args);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
     fn primary_expr(&mut self) -> Result<Expr, Error> {
         match self.cur()?.typ() {
-            TokenType::Number => self.literal(),
-            _ => panic!("TODO: Implement other primary_exprs"),
+            TokenType::Number | TokenType::String | TokenType::Minus => self.literal(),
+            TokenType::LParen => {
+                let (span, mut elements, trailing_comma) = self.paren_list(|p| p.expr())?;
+                if elements.len() == 1 && !trailing_comma {
+                    // Just a parenthesized expression, not a tuple
+                    Ok(elements.remove(0))
+                } else {
+                    Ok(Expr::tuple(span, elements))
+                }
+            }
+            TokenType::LBrace => Ok(Expr::block(self.block()?)),
+            TokenType::Keyword => match self.cur()?.value() {
+                &TokenValue::Kwd(Keyword::True) | &TokenValue::Kwd(Keyword::False) => self.literal(),
+                &TokenValue::Kwd(Keyword::Match) => self.match_expr(),
+                &TokenValue::Kwd(Keyword::Return) => self.return_expr(),
+                _ => Err(Error::UnexpectedToken(self.cur()?.clone())),
+            },
+            TokenType::Identifier => {
+                let name = self.ident()?;
+                if self.at(TokenType::LBrace) && !self.no_struct {
+                    self.struct_expr(name)
+                } else {
+                    Ok(Expr::var(name))
+                }
+            }
+            _ => Err(Error::UnexpectedToken(self.cur()?.clone())),
         }
     }
 
+    fn struct_expr(&mut self, name: Ident) -> Result<Expr, Error> {
+        self.expect(TokenType::LBrace)?;
+        let mut fields = Vec::new();
+        while !self.at(TokenType::RBrace) {
+            let field_name = self.ident()?;
+            self.expect(TokenType::Colon)?;
+            let value = self.restrict(false, |p| p.expr())?;
+            fields.push(FieldInit::new(field_name, value));
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::RBrace)?.span();
+        Ok(Expr::structure(name.span().to(end), name, fields))
+    }
+
+    fn match_expr(&mut self) -> Result<Expr, Error> {
+        let start = self.expect_keyword(Keyword::Match)?.span();
+        let scrutinee = self.restrict(true, |p| p.expr())?;
+        self.expect(TokenType::LBrace)?;
+        let mut arms = Vec::new();
+        while !self.at(TokenType::RBrace) {
+            let pattern = self.pattern()?;
+            let guard = if self.eat_keyword(Keyword::If) {
+                Some(self.restrict(false, |p| p.expr())?)
+            } else {
+                None
+            };
+            self.expect(TokenType::FatArrow)?;
+            let body = self.restrict(false, |p| p.expr())?;
+            arms.push(MatchArm::new(pattern, guard, body));
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::RBrace)?.span();
+        Ok(Expr::match_(start.to(end), scrutinee, arms))
+    }
+
+    fn return_expr(&mut self) -> Result<Expr, Error> {
+        let start = self.expect_keyword(Keyword::Return)?.span();
+        // Without a value, `return` is followed by whatever ends the expression it's in
+        let ends = [TokenType::Semicolon, TokenType::RBrace, TokenType::RParen, TokenType::RBracket, TokenType::Comma];
+        if self.current.is_none() || ends.iter().any(|&t| self.at(t)) {
+            return Ok(Expr::return_(start, None));
+        }
+        let value = self.expr()?;
+        Ok(Expr::return_(start.to(value.span()), Some(value)))
+    }
+
+    pub fn pattern(&mut self) -> Result<Pattern, Error> {
+        match self.cur()?.typ() {
+            TokenType::Number | TokenType::Minus | TokenType::Keyword => {
+                let tok = self.cur()?.clone();
+                match self.lit()? {
+                    (_, Lit::Float(_)) => Err(Error::UnexpectedToken(tok)),
+                    (span, lit) => Ok(Pattern::literal(span, lit)),
+                }
+            }
+            TokenType::Identifier => {
+                let name = self.ident()?;
+                if name.name() == "_" {
+                    Ok(Pattern::wildcard(name.span()))
+                } else if self.at(TokenType::LParen) {
+                    let (span, fields, _) = self.paren_list(|p| p.pattern())?;
+                    Ok(Pattern::constructor(name.span().to(span), name, fields))
+                } else {
+                    Ok(Pattern::binding(name))
+                }
+            }
+            TokenType::LParen => {
+                let (span, mut elements, trailing_comma) = self.paren_list(|p| p.pattern())?;
+                if elements.len() == 1 && !trailing_comma {
+                    // Just a parenthesized pattern, not a tuple
+                    Ok(elements.remove(0))
+                } else {
+                    Ok(Pattern::tuple(span, elements))
+                }
+            }
+            _ => Err(Error::UnexpectedToken(self.cur()?.clone())),
+        }
+    }
+
+    /// Parses a parenthesized, comma-separated list of items, using `item` to parse each one
+    ///
+    /// ## Returns
+    /// The span of the list, including the parentheses, the items within it, and
+    /// `true` if the last item was followed by a comma.
+    fn paren_list<T, F: FnMut(&mut Self) -> Result<T, Error>>(&mut self, mut item: F) -> Result<(TextSpan, Vec<T>, bool), Error> {
+        let start = self.expect(TokenType::LParen)?.span();
+        let mut items = Vec::new();
+        let mut trailing_comma = false;
+        while !self.at(TokenType::RParen) {
+            items.push(self.restrict(false, &mut item)?);
+            trailing_comma = self.eat(TokenType::Comma);
+            if !trailing_comma {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::RParen)?.span();
+        Ok((start.to(end), items, trailing_comma))
+    }
+
+    /// Parses a comma-separated list of items in angle brackets, like the `<T, U>` in `def f<T, U>()`
+    ///
+    /// ## Returns
+    /// The span of the list, including the brackets, and the items within it.
+    fn angle_list<T, F: FnMut(&mut Self) -> Result<T, Error>>(&mut self, mut item: F) -> Result<(TextSpan, Vec<T>), Error> {
+        let start = self.expect(TokenType::Less)?.span();
+        let mut items = Vec::new();
+        while !self.at(TokenType::Greater) {
+            items.push(item(self)?);
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::Greater)?.span();
+        Ok((start.to(end), items))
+    }
+
     fn literal(&mut self) -> Result<Expr, Error> {
-        let result = match self.cur()?.value() {
-            &TokenValue::Int(i) => Ok(Expr::constant(i)),
-            _ => panic!("TODO: Implement other literals"),
+        let (span, lit) = self.lit()?;
+        Ok(Expr::constant(span, lit))
+    }
+
+    fn lit(&mut self) -> Result<(TextSpan, Lit), Error> {
+        // A `-` right before a number makes a negative literal, since there's nothing before it to subtract from
+        if self.at(TokenType::Minus) {
+            let minus = self.cur()?.clone();
+            self.next();
+            let (typ, (span, lit)) = (self.cur()?.typ(), self.lit()?);
+            let lit = match (typ, lit) {
+                (TokenType::Number, Lit::Int(i)) => Lit::Int(&Integer::from(0) - &i),
+                (TokenType::Number, Lit::Float(f)) => Lit::Float(-f),
+                _ => return Err(Error::UnexpectedToken(minus)),
+            };
+            return Ok((minus.span().to(span), lit));
+        }
+        let result = {
+            let tok = self.cur()?;
+            match tok.value() {
+                &TokenValue::Int(ref i) => Ok((tok.span(), Lit::Int(i.clone()))),
+                &TokenValue::Float(f) => Ok((tok.span(), Lit::Float(f))),
+                &TokenValue::Str(ref s) => Ok((tok.span(), Lit::Str(s.clone()))),
+                &TokenValue::Kwd(Keyword::True) => Ok((tok.span(), Lit::Bool(true))),
+                &TokenValue::Kwd(Keyword::False) => Ok((tok.span(), Lit::Bool(false))),
+                _ => Err(Error::UnexpectedToken(tok.clone())),
+            }
         };
         self.next();
         result
     }
 
+    /// Runs the provided parse function with struct literals allowed or forbidden,
+    /// restoring the previous setting afterwards
+    fn restrict<T, F: FnOnce(&mut Self) -> Result<T, Error>>(&mut self, no_struct: bool, f: F) -> Result<T, Error> {
+        let old = self.no_struct;
+        self.no_struct = no_struct;
+        let result = f(self);
+        self.no_struct = old;
+        result
+    }
+
+    fn ident(&mut self) -> Result<Ident, Error> {
+        let tok = self.expect(TokenType::Identifier)?;
+        match tok.value() {
+            &TokenValue::Sym(ref name) => Ok(Ident::new(tok.span(), name.as_str())),
+            _ => Err(Error::UnexpectedToken(tok.clone())),
+        }
+    }
+
+    /// Consumes the current token if it is of the provided type, failing otherwise
+    fn expect(&mut self, typ: TokenType) -> Result<Token, Error> {
+        let tok = self.cur()?.clone();
+        if tok.typ() == typ {
+            self.next();
+            Ok(tok)
+        } else {
+            Err(Error::ExpectedToken(typ, tok))
+        }
+    }
+
+    fn expect_keyword(&mut self, kwd: Keyword) -> Result<Token, Error> {
+        let tok = self.cur()?.clone();
+        if tok.value() == &TokenValue::Kwd(kwd) {
+            self.next();
+            Ok(tok)
+        } else {
+            Err(Error::ExpectedToken(TokenType::Keyword, tok))
+        }
+    }
+
+    fn eat_keyword(&mut self, kwd: Keyword) -> bool {
+        let found = self.at_keyword(kwd);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn at_keyword(&self, kwd: Keyword) -> bool {
+        match self.current {
+            Some(Ok(ref t)) => t.value() == &TokenValue::Kwd(kwd),
+            _ => false,
+        }
+    }
+
+    /// Checks if the current token is the provided closing token, or if we've reached the end of the file
+    /// when there isn't one
+    fn at_close(&self, close: Option<TokenType>) -> bool {
+        match close {
+            Some(typ) => self.at(typ),
+            None => self.current.is_none(),
+        }
+    }
+
+    /// Checks if the current token is of the provided type, without consuming it
+    fn at(&self, typ: TokenType) -> bool {
+        match self.current {
+            Some(Ok(ref t)) => t.typ() == typ,
+            _ => false,
+        }
+    }
+
+    /// Consumes the current token if it is of the provided type
+    ///
+    /// ## Returns
+    /// `true` if a token was consumed
+    fn eat(&mut self, typ: TokenType) -> bool {
+        if self.at(typ) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
     fn cur(&self) -> Result<&Token, Error> {
         match self.current {
             Some(Ok(ref t)) => Ok(t),
@@ -91,8 +655,10 @@ impl<I: Iterator<Item=Result<Token, tokenizer::Error>>> Parser<I> {
 
 #[cfg(test)]
 mod tests {
-    use tokenizer::{Token, TokenType, TokenValue, Tokenizer};
-    use parser::{Parser, Expr, BinOp};
+    use tokenizer::{Tokenizer, TokenType};
+    use parser::{Parser, Expr, FieldInit, MatchArm, BinOp, Error, Ident, Pattern, TypeExpr, Decl, StructDecl, FieldDecl,
+                 EnumDecl, VariantDecl, DefDecl, TypeParam, ExternDecl, TraitDecl, MethodSig, ImplDecl, Param, Stmt,
+                 LetStmt, Block, Attribute};
     use text::TextSpan;
 
     macro_rules! expr_tests {
@@ -111,30 +677,481 @@ mod tests {
         };
     }
 
+    fn int(start: usize, end: usize, val: i64) -> Expr {
+        Expr::constant(TextSpan::new(start, end), val)
+    }
+
+    fn ident(start: usize, end: usize, name: &str) -> Ident {
+        Ident::new(TextSpan::new(start, end), name)
+    }
+
+    fn parse_program(text: &str) -> Vec<Stmt> {
+        let mut parser = Parser::new(Tokenizer::new(text));
+        let program = parser.program().expect("Expected the parse to succeed");
+        assert!(program.result().is_none(), "Expected no final expression");
+        program.stmts().to_vec()
+    }
+
     expr_tests! {
-        expr_int_literal: "42" => Expr::constant(42);
-        bin_add_literals: "40 + 2" => Expr::binary(Expr::constant(40), Expr::constant(2), BinOp::Add);
+        expr_int_literal: "42" => int(0, 2, 42);
+        bin_add_literals: "40 + 2" => Expr::binary(int(0, 2, 40), int(5, 6, 2), BinOp::Add, TextSpan::new(3, 4));
         bin_add_literal_sequence: "1 + 2 + 3 + 4" =>
             Expr::binary(
                 Expr::binary(
                     Expr::binary(
-                        Expr::constant(1),
-                        Expr::constant(2),
-                        BinOp::Add),
-                    Expr::constant(3),
-                    BinOp::Add),
-                Expr::constant(4),
-                BinOp::Add);
+                        int(0, 1, 1),
+                        int(4, 5, 2),
+                        BinOp::Add,
+                        TextSpan::new(2, 3)),
+                    int(8, 9, 3),
+                    BinOp::Add,
+                    TextSpan::new(6, 7)),
+                int(12, 13, 4),
+                BinOp::Add,
+                TextSpan::new(10, 11));
         bin_add_mult_precedence: "1 + 2 * 3 + 4" =>
             Expr::binary(
                 Expr::binary(
-                    Expr::constant(1),
+                    int(0, 1, 1),
                     Expr::binary( // 2*3 are associated, because the precedence of * is higher 
-                        Expr::constant(2),
-                        Expr::constant(3),
-                        BinOp::Multiply),
-                    BinOp::Add),
-                Expr::constant(4),
-                BinOp::Add);
-    }
-}
\ No newline at end of file
+                        int(4, 5, 2),
+                        int(8, 9, 3),
+                        BinOp::Multiply,
+                        TextSpan::new(6, 7)),
+                    BinOp::Add,
+                    TextSpan::new(2, 3)),
+                int(12, 13, 4),
+                BinOp::Add,
+                TextSpan::new(10, 11));
+        power_is_right_associative: "2 * 3 ** 2 ** 4" =>
+            Expr::binary(
+                int(0, 1, 2),
+                Expr::binary(
+                    int(4, 5, 3),
+                    Expr::binary(int(9, 10, 2), int(14, 15, 4), BinOp::Power, TextSpan::new(11, 13)),
+                    BinOp::Power,
+                    TextSpan::new(6, 8)),
+                BinOp::Multiply,
+                TextSpan::new(2, 3));
+        var: "foo" => Expr::var(ident(0, 3, "foo"));
+        struct_literal: "Point { x: 1, y: 2 }" =>
+            Expr::structure(
+                TextSpan::new(0, 20),
+                ident(0, 5, "Point"),
+                vec![
+                    FieldInit::new(ident(8, 9, "x"), int(11, 12, 1)),
+                    FieldInit::new(ident(14, 15, "y"), int(17, 18, 2)),
+                ]);
+        struct_literal_trailing_comma: "P { x: 1, }" =>
+            Expr::structure(
+                TextSpan::new(0, 11),
+                ident(0, 1, "P"),
+                vec![FieldInit::new(ident(4, 5, "x"), int(7, 8, 1))]);
+        field_access_chain: "a.b.c" =>
+            Expr::field(
+                Expr::field(Expr::var(ident(0, 1, "a")), ident(2, 3, "b")),
+                ident(4, 5, "c"));
+        field_binds_tighter_than_binop: "p.x * 2" =>
+            Expr::binary(
+                Expr::field(Expr::var(ident(0, 1, "p")), ident(2, 3, "x")),
+                int(6, 7, 2),
+                BinOp::Multiply,
+                TextSpan::new(4, 5));
+        field_assignment: "p.x = 1 + 2" =>
+            Expr::assign(
+                Expr::field(Expr::var(ident(0, 1, "p")), ident(2, 3, "x")),
+                Expr::binary(int(6, 7, 1), int(10, 11, 2), BinOp::Add, TextSpan::new(8, 9)));
+        assignment_is_right_associative: "a = b = 1" =>
+            Expr::assign(
+                Expr::var(ident(0, 1, "a")),
+                Expr::assign(Expr::var(ident(4, 5, "b")), int(8, 9, 1)));
+        comparison_binds_looser_than_arithmetic: "1 + 2 == 3" =>
+            Expr::binary(
+                Expr::binary(int(0, 1, 1), int(4, 5, 2), BinOp::Add, TextSpan::new(2, 3)),
+                int(9, 10, 3),
+                BinOp::Equal,
+                TextSpan::new(6, 8));
+        bool_literal: "true" => Expr::constant(TextSpan::new(0, 4), true);
+        string_literal: "\"hi\"" => Expr::constant(TextSpan::new(0, 4), "hi");
+        negative_int_literal: "-123" => int(0, 4, -123);
+        negative_float_literal: "-0.5" => Expr::constant(TextSpan::new(0, 4), -0.5);
+        subtract_negative_literal: "n-1 - -2" =>
+            Expr::binary(
+                Expr::binary(Expr::var(ident(0, 1, "n")), int(2, 3, 1), BinOp::Subtract, TextSpan::new(1, 2)),
+                int(6, 8, -2),
+                BinOp::Subtract,
+                TextSpan::new(4, 5));
+        parenthesized: "(1 + 2) * 3" =>
+            Expr::binary(
+                Expr::binary(int(1, 2, 1), int(5, 6, 2), BinOp::Add, TextSpan::new(3, 4)),
+                int(10, 11, 3),
+                BinOp::Multiply,
+                TextSpan::new(8, 9));
+        tuple: "(1, p.x)" =>
+            Expr::tuple(TextSpan::new(0, 8), vec![
+                int(1, 2, 1),
+                Expr::field(Expr::var(ident(4, 5, "p")), ident(6, 7, "x")),
+            ]);
+        single_element_tuple: "(1,)" => Expr::tuple(TextSpan::new(0, 4), vec![int(1, 2, 1)]);
+        unit: "()" => Expr::tuple(TextSpan::new(0, 2), vec![]);
+        struct_literal_allowed_in_parenthesized_scrutinee: "match (P { x: 1 }) { _ => 1 }" =>
+            Expr::match_(
+                TextSpan::new(0, 29),
+                Expr::structure(TextSpan::new(7, 17), ident(7, 8, "P"), vec![FieldInit::new(ident(11, 12, "x"), int(14, 15, 1))]),
+                vec![MatchArm::new(Pattern::wildcard(TextSpan::new(21, 22)), None, int(26, 27, 1))]);
+        block: "{ let x = 1; x }" =>
+            Expr::block(Block::new(
+                TextSpan::new(0, 16),
+                vec![Stmt::Let(LetStmt::new(TextSpan::new(2, 12), Pattern::binding(ident(6, 7, "x")), None, int(10, 11, 1)))],
+                Some(Expr::var(ident(13, 14, "x")))));
+        call: "Rect(1, 2)" =>
+            Expr::call(
+                TextSpan::new(0, 10),
+                Expr::var(ident(0, 4, "Rect")),
+                vec![int(5, 6, 1), int(8, 9, 2)]);
+        match_scrutinee_is_not_a_struct_literal: "match s { _ => 1 }" =>
+            Expr::match_(
+                TextSpan::new(0, 18),
+                Expr::var(ident(6, 7, "s")),
+                vec![MatchArm::new(Pattern::wildcard(TextSpan::new(10, 11)), None, int(15, 16, 1))]);
+        method_call: "p.show(1)" =>
+            Expr::method_call(TextSpan::new(0, 9), Expr::var(ident(0, 1, "p")), ident(2, 6, "show"), vec![int(7, 8, 1)]);
+        match_arm_guard: "match p { (a, 0) if a > 1 => a, }" =>
+            Expr::match_(
+                TextSpan::new(0, 33),
+                Expr::var(ident(6, 7, "p")),
+                vec![MatchArm::new(
+                    Pattern::tuple(TextSpan::new(10, 16), vec![
+                        Pattern::binding(ident(11, 12, "a")),
+                        Pattern::literal(TextSpan::new(14, 15), 0),
+                    ]),
+                    Some(Expr::binary(Expr::var(ident(20, 21, "a")), int(24, 25, 1), BinOp::Greater, TextSpan::new(22, 23))),
+                    Expr::var(ident(29, 30, "a")))]);
+        return_value: "return a + 1" =>
+            Expr::return_(
+                TextSpan::new(0, 12),
+                Some(Expr::binary(Expr::var(ident(7, 8, "a")), int(11, 12, 1), BinOp::Add, TextSpan::new(9, 10))));
+        return_without_a_value: "(return, 1)" =>
+            Expr::tuple(TextSpan::new(0, 11), vec![Expr::return_(TextSpan::new(1, 7), None), int(9, 10, 1)]);
+    }
+
+    macro_rules! pattern_tests {
+        ($(
+            $name: ident: $text: expr => $result: expr;
+         )*) => {
+           $(
+               #[test]
+               pub fn $name() {
+                   let mut parser = Parser::new(Tokenizer::new($text));
+                   let pattern = parser.pattern().expect("Expected the parse the succeed");
+                   assert_eq!($result, pattern);
+               }
+           )*
+        };
+    }
+
+    pattern_tests! {
+        pattern_wildcard: "_" => Pattern::wildcard(TextSpan::new(0, 1));
+        pattern_binding: "x" => Pattern::binding(ident(0, 1, "x"));
+        pattern_negative_literal: "-1" => Pattern::literal(TextSpan::new(0, 2), -1);
+        pattern_constructor: "Rect(w, _)" =>
+            Pattern::constructor(TextSpan::new(0, 10), ident(0, 4, "Rect"), vec![
+                Pattern::binding(ident(5, 6, "w")),
+                Pattern::wildcard(TextSpan::new(8, 9)),
+            ]);
+        pattern_parenthesized: "(x)" => Pattern::binding(ident(1, 2, "x"));
+        pattern_single_element_tuple: "(x,)" => Pattern::tuple(TextSpan::new(0, 4), vec![Pattern::binding(ident(1, 2, "x"))]);
+        pattern_unit: "()" => Pattern::tuple(TextSpan::new(0, 2), vec![]);
+    }
+
+    #[test]
+    pub fn assignment_to_non_place_is_an_error() {
+        let mut parser = Parser::new(Tokenizer::new("1 + 2 = 3"));
+        assert_eq!(Err(Error::InvalidAssignmentTarget(TextSpan::new(0, 5))), parser.expr());
+    }
+
+    #[test]
+    pub fn struct_decl() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Struct(StructDecl::new(
+                TextSpan::new(0, 31),
+                ident(7, 12, "Point"),
+                vec![],
+                vec![
+                    FieldDecl::new(ident(15, 16, "x"), TypeExpr::named(ident(18, 21, "Int"))),
+                    FieldDecl::new(ident(23, 24, "y"), TypeExpr::named(ident(26, 29, "Int"))),
+                ])))],
+            parse_program("struct Point { x: Int, y: Int }"));
+    }
+
+    #[test]
+    pub fn enum_decl() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Enum(EnumDecl::new(
+                TextSpan::new(0, 43),
+                ident(5, 10, "Shape"),
+                vec![],
+                vec![
+                    VariantDecl::new(TextSpan::new(13, 22), ident(13, 19, "Circle"), vec![
+                        TypeExpr::named(ident(20, 21, "r")),
+                    ]),
+                    VariantDecl::new(TextSpan::new(24, 34), ident(24, 28, "Rect"), vec![
+                        TypeExpr::named(ident(29, 30, "w")),
+                        TypeExpr::named(ident(32, 33, "h")),
+                    ]),
+                    VariantDecl::new(TextSpan::new(36, 41), ident(36, 41, "Empty"), vec![]),
+                ])))],
+            parse_program("enum Shape { Circle(r), Rect(w, h), Empty }"));
+    }
+
+    #[test]
+    pub fn let_stmt() {
+        assert_eq!(
+            vec![Stmt::Let(LetStmt::new(TextSpan::new(0, 10), Pattern::binding(ident(4, 5, "x")), None, int(8, 9, 1)))],
+            parse_program("let x = 1;"));
+    }
+
+    #[test]
+    pub fn destructuring_let_stmt() {
+        assert_eq!(
+            vec![Stmt::Let(LetStmt::new(
+                TextSpan::new(0, 20),
+                Pattern::tuple(TextSpan::new(4, 10), vec![
+                    Pattern::binding(ident(5, 6, "x")),
+                    Pattern::binding(ident(8, 9, "y")),
+                ]),
+                None,
+                Expr::call(TextSpan::new(13, 19), Expr::var(ident(13, 17, "pair")), vec![])))],
+            parse_program("let (x, y) = pair();"));
+    }
+
+    #[test]
+    pub fn def_decl() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Def(DefDecl::new(
+                TextSpan::new(0, 36),
+                ident(4, 8, "swap"),
+                vec![],
+                vec![
+                    Param::new(Pattern::tuple(TextSpan::new(9, 15), vec![
+                        Pattern::binding(ident(10, 11, "a")),
+                        Pattern::binding(ident(13, 14, "b")),
+                    ]), None),
+                    Param::new(Pattern::binding(ident(17, 18, "c")), None),
+                ],
+                None,
+                Block::new(
+                    TextSpan::new(20, 36),
+                    vec![Stmt::Expr(Expr::var(ident(22, 23, "c")))],
+                    Some(Expr::tuple(TextSpan::new(25, 34), vec![
+                        Expr::var(ident(26, 27, "b")),
+                        Expr::var(ident(29, 30, "a")),
+                        Expr::var(ident(32, 33, "c")),
+                    ]))))))],
+            parse_program("def swap((a, b), c) { c; (b, a, c) }"));
+    }
+
+    #[test]
+    pub fn attributes() {
+        let program = Parser::new(Tokenizer::new("#![deny(shadowing)] #[allow(a, b)] #[inline] def f() { 1 } 2"))
+            .program().unwrap();
+        assert_eq!(&[Attribute::new(TextSpan::new(0, 19), ident(3, 7, "deny"), vec![ident(8, 17, "shadowing")])],
+                   program.attrs());
+        match program.stmts()[0] {
+            Stmt::Decl(Decl::Def(ref d)) => {
+                assert_eq!(TextSpan::new(45, 58), d.span());
+                assert_eq!(&[
+                    Attribute::new(TextSpan::new(20, 34), ident(22, 27, "allow"), vec![ident(28, 29, "a"), ident(31, 32, "b")]),
+                    Attribute::new(TextSpan::new(35, 44), ident(37, 43, "inline"), vec![]),
+                ], d.attrs());
+            }
+            _ => panic!("Expected a def"),
+        }
+        assert!(Parser::new(Tokenizer::new("#[allow(a)] let x = 1;")).program().is_err());
+    }
+
+    #[test]
+    pub fn annotated_def_decl() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Def(DefDecl::new(
+                TextSpan::new(0, 26),
+                ident(4, 5, "f"),
+                vec![],
+                vec![Param::new(Pattern::binding(ident(6, 7, "x")), Some(TypeExpr::named(ident(9, 12, "Int"))))],
+                Some(TypeExpr::named(ident(17, 20, "Int"))),
+                Block::new(TextSpan::new(21, 26), vec![], Some(Expr::var(ident(23, 24, "x"))))))),
+            ],
+            parse_program("def f(x: Int) -> Int { x }"));
+    }
+
+    #[test]
+    pub fn generic_def_decl() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Def(DefDecl::new(
+                TextSpan::new(0, 26),
+                ident(4, 6, "id"),
+                vec![TypeParam::new(ident(7, 8, "T"), vec![])],
+                vec![Param::new(Pattern::binding(ident(10, 11, "x")), Some(TypeExpr::named(ident(13, 14, "T"))))],
+                Some(TypeExpr::named(ident(19, 20, "T"))),
+                Block::new(TextSpan::new(21, 26), vec![], Some(Expr::var(ident(23, 24, "x"))))))),
+            ],
+            parse_program("def id<T>(x: T) -> T { x }"));
+    }
+
+    #[test]
+    pub fn generic_struct_decl() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Struct(StructDecl::new(
+                TextSpan::new(0, 41),
+                ident(7, 11, "List"),
+                vec![ident(12, 13, "T")],
+                vec![
+                    FieldDecl::new(ident(17, 21, "head"), TypeExpr::named(ident(23, 24, "T"))),
+                    FieldDecl::new(ident(26, 30, "tail"), TypeExpr::generic(
+                        TextSpan::new(32, 39),
+                        ident(32, 36, "List"),
+                        vec![TypeExpr::named(ident(37, 38, "T"))])),
+                ])))],
+            parse_program("struct List<T> { head: T, tail: List<T> }"));
+    }
+
+    #[test]
+    pub fn nested_type_arguments() {
+        let mut parser = Parser::new(Tokenizer::new("Map<Str, List<Int>>"));
+        assert_eq!(
+            Ok(TypeExpr::generic(TextSpan::new(0, 19), ident(0, 3, "Map"), vec![
+                TypeExpr::named(ident(4, 7, "Str")),
+                TypeExpr::generic(TextSpan::new(9, 18), ident(9, 13, "List"), vec![TypeExpr::named(ident(14, 17, "Int"))]),
+            ])),
+            parser.type_expr());
+    }
+
+    #[test]
+    pub fn bounded_type_params() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Def(DefDecl::new(
+                TextSpan::new(0, 31),
+                ident(4, 5, "f"),
+                vec![TypeParam::new(ident(6, 7, "T"), vec![ident(9, 13, "Show"), ident(16, 18, "Eq")])],
+                vec![Param::new(Pattern::binding(ident(20, 21, "x")), Some(TypeExpr::named(ident(23, 24, "T"))))],
+                None,
+                Block::new(TextSpan::new(26, 31), vec![], Some(Expr::var(ident(28, 29, "x"))))))),
+            ],
+            parse_program("def f<T: Show + Eq>(x: T) { x }"));
+    }
+
+    #[test]
+    pub fn trait_decl() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Trait(TraitDecl::new(
+                TextSpan::new(0, 36),
+                ident(6, 10, "Show"),
+                vec![MethodSig::new(
+                    TextSpan::new(13, 34),
+                    ident(17, 21, "show"),
+                    vec![Param::new(Pattern::binding(ident(22, 26, "self")), None)],
+                    Some(TypeExpr::named(ident(31, 34, "Str"))))])))],
+            parse_program("trait Show { def show(self) -> Str }"));
+    }
+
+    #[test]
+    pub fn impl_decl() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Impl(ImplDecl::new(
+                TextSpan::new(0, 49),
+                ident(5, 9, "Show"),
+                TypeExpr::named(ident(14, 15, "P")),
+                vec![DefDecl::new(
+                    TextSpan::new(18, 47),
+                    ident(22, 26, "show"),
+                    vec![],
+                    vec![Param::new(Pattern::binding(ident(27, 31, "self")), None)],
+                    Some(TypeExpr::named(ident(36, 39, "Str"))),
+                    Block::new(TextSpan::new(40, 47), vec![], Some(Expr::constant(TextSpan::new(42, 45), "p"))))])))],
+            parse_program("impl Show for P { def show(self) -> Str { \"p\" } }"));
+    }
+
+    #[test]
+    pub fn dyn_type() {
+        let mut parser = Parser::new(Tokenizer::new("dyn Show"));
+        assert_eq!(Ok(TypeExpr::dyn_(TextSpan::new(0, 8), ident(4, 8, "Show"))), parser.type_expr());
+    }
+
+    #[test]
+    pub fn extern_decl() {
+        assert_eq!(
+            vec![Stmt::Decl(Decl::Extern(ExternDecl::new(
+                TextSpan::new(0, 27),
+                ident(7, 11, "puts"),
+                vec![Param::new(Pattern::binding(ident(12, 13, "s")), Some(TypeExpr::named(ident(15, 18, "Str"))))],
+                Some(TypeExpr::named(ident(23, 26, "Int")))))),
+            ],
+            parse_program("extern puts(s: Str) -> Int; "));
+    }
+
+    #[test]
+    pub fn annotated_let_stmt() {
+        assert_eq!(
+            vec![Stmt::Let(LetStmt::new(
+                TextSpan::new(0, 19),
+                Pattern::binding(ident(4, 5, "x")),
+                Some(TypeExpr::named(ident(7, 12, "Float"))),
+                Expr::constant(TextSpan::new(15, 18), 1.5)))],
+            parse_program("let x: Float = 1.5;"));
+    }
+
+    #[test]
+    pub fn float_literal_is_not_a_pattern() {
+        let mut parser = Parser::new(Tokenizer::new("1.5"));
+        match parser.pattern() {
+            Err(Error::UnexpectedToken(_)) => {},
+            r => panic!("Expected an unexpected token error, but got {:?}", r),
+        }
+    }
+
+    #[test]
+    pub fn tuple_types() {
+        let mut parser = Parser::new(Tokenizer::new("((Int), (Int,), ())"));
+        assert_eq!(
+            Ok(TypeExpr::tuple(TextSpan::new(0, 19), vec![
+                TypeExpr::named(ident(2, 5, "Int")),
+                TypeExpr::tuple(TextSpan::new(8, 14), vec![TypeExpr::named(ident(9, 12, "Int"))]),
+                TypeExpr::tuple(TextSpan::new(16, 18), vec![]),
+            ])),
+            parser.type_expr());
+    }
+
+    #[test]
+    pub fn block_requires_closing_brace() {
+        let mut parser = Parser::new(Tokenizer::new("{ 1"));
+        assert_eq!(Err(Error::UnexpectedEndOfFile), parser.expr());
+    }
+
+    #[test]
+    pub fn program_with_final_expression() {
+        let text = "struct P { x: Int } let p = P { x: 1 }; p.x = 2; p.x";
+        let program = Parser::new(Tokenizer::new(text)).program().expect("Expected the parse to succeed");
+        assert_eq!(3, program.stmts().len());
+        assert_eq!(
+            Some(&Expr::field(Expr::var(ident(49, 50, "p")), ident(51, 52, "x"))),
+            program.result());
+    }
+
+    #[test]
+    pub fn negative_literal_after_a_declaration() {
+        for &(text, start) in &[("def f() { 1 }\n-1", 14), ("struct P { x: Int }\n-1", 20)] {
+            let program = Parser::new(Tokenizer::new(text)).program().expect("Expected the parse to succeed");
+            assert_eq!(1, program.stmts().len());
+            assert_eq!(Some(&int(start, start + 2, -1)), program.result());
+        }
+    }
+
+    #[test]
+    pub fn statement_requires_semicolon() {
+        let mut parser = Parser::new(Tokenizer::new("1 2"));
+        match parser.program() {
+            Err(Error::ExpectedToken(TokenType::Semicolon, _)) => {},
+            r => panic!("Expected a missing semicolon error, but got {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use text::TextSpan;
+use parser::{Ident, Lit};
+
+/// A pattern, as used in the arms of a match expression
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Pattern {
+    span: TextSpan,
+    kind: PatternKind,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PatternKind {
+    /// Matches anything, without binding it: `_`
+    Wildcard,
+
+    /// Matches anything and binds it to a name: `x`
+    ///
+    /// The parser can't tell a binding from a reference to a variant with no fields (`Empty`), so
+    /// both are parsed as a `Binding`. Later passes look the name up to tell them apart.
+    Binding(Ident),
+
+    /// Matches a single literal value: `42` or `true`
+    Literal(Lit),
+
+    /// Matches each element of a tuple: `(a, _)`
+    Tuple(Vec<Pattern>),
+
+    /// Matches a variant of an enum and its fields: `Rect(w, h)`
+    Constructor(Ident, Vec<Pattern>),
+}
+
+impl Pattern {
+    pub fn new(span: TextSpan, kind: PatternKind) -> Pattern {
+        Pattern { span, kind }
+    }
+
+    pub fn wildcard(span: TextSpan) -> Pattern {
+        Pattern::new(span, PatternKind::Wildcard)
+    }
+
+    pub fn binding(name: Ident) -> Pattern {
+        Pattern::new(name.span(), PatternKind::Binding(name))
+    }
+
+    pub fn literal<L: Into<Lit>>(span: TextSpan, lit: L) -> Pattern {
+        Pattern::new(span, PatternKind::Literal(lit.into()))
+    }
+
+    pub fn tuple(span: TextSpan, elements: Vec<Pattern>) -> Pattern {
+        Pattern::new(span, PatternKind::Tuple(elements))
+    }
+
+    pub fn constructor(span: TextSpan, name: Ident, fields: Vec<Pattern>) -> Pattern {
+        Pattern::new(span, PatternKind::Constructor(name, fields))
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn kind(&self) -> &PatternKind { &self.kind }
+}
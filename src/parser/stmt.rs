@@ -0,0 +1,79 @@
+use text::TextSpan;
+use parser::{Attribute, Decl, Expr, Pattern, TypeExpr};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Stmt {
+    Decl(Decl),
+    Let(LetStmt),
+    Expr(Expr),
+}
+
+/// A `let` statement, binding the value of an expression to the names in a pattern: `let (x, y) = pair;`
+///
+/// The type of the value may be given with an annotation: `let x: Float = 1.5;`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LetStmt {
+    span: TextSpan,
+    pattern: Pattern,
+    ty: Option<TypeExpr>,
+    value: Expr,
+}
+
+impl LetStmt {
+    pub fn new(span: TextSpan, pattern: Pattern, ty: Option<TypeExpr>, value: Expr) -> LetStmt {
+        LetStmt { span, pattern, ty, value }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn pattern(&self) -> &Pattern { &self.pattern }
+    pub fn ty(&self) -> Option<&TypeExpr> { self.ty.as_ref() }
+    pub fn value(&self) -> &Expr { &self.value }
+}
+
+/// A braced sequence of statements, concluding with an optional final expression that provides its value
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Block {
+    span: TextSpan,
+    stmts: Vec<Stmt>,
+    result: Option<Box<Expr>>,
+}
+
+impl Block {
+    pub fn new(span: TextSpan, stmts: Vec<Stmt>, result: Option<Expr>) -> Block {
+        Block {
+            span,
+            stmts,
+            result: result.map(Box::new),
+        }
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn stmts(&self) -> &[Stmt] { &self.stmts }
+    pub fn result(&self) -> Option<&Expr> { self.result.as_ref().map(|e| &**e) }
+}
+
+/// A complete Why program: a sequence of statements and declarations, concluding with an
+/// optional final expression whose value is the result of the program.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Program {
+    stmts: Vec<Stmt>,
+    result: Option<Expr>,
+    attrs: Vec<Attribute>,
+}
+
+impl Program {
+    pub fn new(stmts: Vec<Stmt>, result: Option<Expr>) -> Program {
+        Program { stmts, result, attrs: Vec::new() }
+    }
+
+    pub fn with_attrs(mut self, attrs: Vec<Attribute>) -> Program {
+        self.attrs = attrs;
+        self
+    }
+
+    pub fn stmts(&self) -> &[Stmt] { &self.stmts }
+    pub fn result(&self) -> Option<&Expr> { self.result.as_ref() }
+
+    /// The `#![...]` attributes at the start of the program
+    pub fn attrs(&self) -> &[Attribute] { &self.attrs }
+}
@@ -0,0 +1,46 @@
+use text::TextSpan;
+use parser::Ident;
+
+/// A type as written in the source, such as the `Int` in `x: Int`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TypeExpr {
+    span: TextSpan,
+    kind: TypeExprKind,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TypeExprKind {
+    /// A type referred to by name, like `Int` or `Point`, along with any type arguments: `List<Int>`
+    Named(Ident, Vec<TypeExpr>),
+
+    /// A tuple of types: `(Int, Int)`
+    Tuple(Vec<TypeExpr>),
+
+    /// A value of any type that implements the trait, whose methods are found when it's called: `dyn Show`
+    Dyn(Ident),
+}
+
+impl TypeExpr {
+    pub fn new(span: TextSpan, kind: TypeExprKind) -> TypeExpr {
+        TypeExpr { span, kind }
+    }
+
+    pub fn named(name: Ident) -> TypeExpr {
+        TypeExpr::new(name.span(), TypeExprKind::Named(name, Vec::new()))
+    }
+
+    pub fn generic(span: TextSpan, name: Ident, args: Vec<TypeExpr>) -> TypeExpr {
+        TypeExpr::new(span, TypeExprKind::Named(name, args))
+    }
+
+    pub fn tuple(span: TextSpan, elements: Vec<TypeExpr>) -> TypeExpr {
+        TypeExpr::new(span, TypeExprKind::Tuple(elements))
+    }
+
+    pub fn dyn_(span: TextSpan, trait_name: Ident) -> TypeExpr {
+        TypeExpr::new(span, TypeExprKind::Dyn(trait_name))
+    }
+
+    pub fn span(&self) -> TextSpan { self.span }
+    pub fn kind(&self) -> &TypeExprKind { &self.kind }
+}
@@ -0,0 +1,144 @@
+use parser::{Block, Decl, DefDecl, Expr, ExprKind, Param, Pattern, Program, Stmt, TypeExpr};
+
+/// Walks the syntax tree. Each method's default implementation visits the node's children,
+/// so implementations can override just the nodes they are interested in and call the
+/// matching `walk_` function to carry on into the children.
+pub trait Visitor<'ast> {
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) { walk_stmt(self, stmt) }
+    fn visit_decl(&mut self, decl: &'ast Decl) { walk_decl(self, decl) }
+    fn visit_def(&mut self, def: &'ast DefDecl) { walk_def(self, def) }
+    fn visit_block(&mut self, block: &'ast Block) { walk_block(self, block) }
+    fn visit_expr(&mut self, expr: &'ast Expr) { walk_expr(self, expr) }
+    fn visit_pattern(&mut self, _pattern: &'ast Pattern) {}
+    fn visit_type_expr(&mut self, _ty: &'ast TypeExpr) {}
+}
+
+pub fn walk_program<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, program: &'ast Program) {
+    for stmt in program.stmts() {
+        v.visit_stmt(stmt);
+    }
+    if let Some(result) = program.result() {
+        v.visit_expr(result);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, stmt: &'ast Stmt) {
+    match stmt {
+        &Stmt::Decl(ref decl) => v.visit_decl(decl),
+        &Stmt::Let(ref l) => {
+            v.visit_pattern(l.pattern());
+            if let Some(ty) = l.ty() {
+                v.visit_type_expr(ty);
+            }
+            v.visit_expr(l.value());
+        }
+        &Stmt::Expr(ref e) => v.visit_expr(e),
+    }
+}
+
+pub fn walk_decl<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, decl: &'ast Decl) {
+    match decl {
+        &Decl::Struct(ref s) => {
+            for field in s.fields() {
+                v.visit_type_expr(field.ty());
+            }
+        }
+        &Decl::Enum(ref e) => {
+            for variant in e.variants() {
+                for ty in variant.fields() {
+                    v.visit_type_expr(ty);
+                }
+            }
+        }
+        &Decl::Def(ref d) => v.visit_def(d),
+        &Decl::Extern(ref e) => {
+            walk_params(v, e.params());
+            if let Some(ret) = e.ret() {
+                v.visit_type_expr(ret);
+            }
+        }
+        &Decl::Trait(ref t) => {
+            for method in t.methods() {
+                walk_params(v, method.params());
+                if let Some(ret) = method.ret() {
+                    v.visit_type_expr(ret);
+                }
+            }
+        }
+        &Decl::Impl(ref i) => {
+            v.visit_type_expr(i.ty());
+            for method in i.methods() {
+                v.visit_def(method);
+            }
+        }
+    }
+}
+
+pub fn walk_def<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, def: &'ast DefDecl) {
+    walk_params(v, def.params());
+    if let Some(ret) = def.ret() {
+        v.visit_type_expr(ret);
+    }
+    v.visit_block(def.body());
+}
+
+fn walk_params<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, params: &'ast [Param]) {
+    for param in params {
+        v.visit_pattern(param.pattern());
+        if let Some(ty) = param.ty() {
+            v.visit_type_expr(ty);
+        }
+    }
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, block: &'ast Block) {
+    for stmt in block.stmts() {
+        v.visit_stmt(stmt);
+    }
+    if let Some(result) = block.result() {
+        v.visit_expr(result);
+    }
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, expr: &'ast Expr) {
+    match expr.kind() {
+        &ExprKind::Constant(_) | &ExprKind::Var(_) => {}
+        &ExprKind::Binary(ref l, ref r, _, _) | &ExprKind::Assign(ref l, ref r) => {
+            v.visit_expr(l);
+            v.visit_expr(r);
+        }
+        &ExprKind::Struct(_, ref fields) => {
+            for field in fields {
+                v.visit_expr(field.value());
+            }
+        }
+        &ExprKind::Field(ref target, _) => v.visit_expr(target),
+        &ExprKind::Call(ref callee, ref args) | &ExprKind::MethodCall(ref callee, _, ref args) => {
+            v.visit_expr(callee);
+            for arg in args {
+                v.visit_expr(arg);
+            }
+        }
+        &ExprKind::Match(ref scrutinee, ref arms) => {
+            v.visit_expr(scrutinee);
+            for arm in arms {
+                v.visit_pattern(arm.pattern());
+                if let Some(guard) = arm.guard() {
+                    v.visit_expr(guard);
+                }
+                v.visit_expr(arm.body());
+            }
+        }
+        &ExprKind::Tuple(ref elements) => {
+            for e in elements {
+                v.visit_expr(e);
+            }
+        }
+        &ExprKind::Block(ref block) => v.visit_block(block),
+        &ExprKind::Return(ref value) => {
+            if let Some(ref value) = *value {
+                v.visit_expr(value);
+            }
+        }
+    }
+}
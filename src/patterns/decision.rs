@@ -0,0 +1,271 @@
+//! Compiles the arms of a match into a decision tree, following Maranget's "Compiling pattern
+//! matching to good decision trees". Each value in the scrutinee is tested at most once along any
+//! path through the tree, which is why matches are lowered to IR from their tree.
+
+use patterns::{Ctor, Enums, Pat};
+
+/// The path from the scrutinee to one of the values inside it.
+///
+/// Each element is the index of a field within the value reached so far, so the root
+/// occurrence is the scrutinee itself and `[1, 0]` is the first field of its second field.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Occurrence(Vec<usize>);
+
+impl Occurrence {
+    pub fn root() -> Occurrence {
+        Occurrence(Vec::new())
+    }
+
+    pub fn child(&self, index: usize) -> Occurrence {
+        let mut path = self.0.clone();
+        path.push(index);
+        Occurrence(path)
+    }
+
+    pub fn path(&self) -> &[usize] {
+        &self.0
+    }
+
+    /// Gets the occurrence this one is a field of, and the index of the field, or `None` for the root
+    pub fn parent(&self) -> Option<(Occurrence, usize)> {
+        self.0.split_last().map(|(&index, path)| (Occurrence(path.to_vec()), index))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Decision {
+    /// No arm matches the value. This is only reachable if the match is not exhaustive.
+    Fail,
+
+    /// The arm at index `arm` matches, once each name has been bound to the value at its occurrence
+    Leaf {
+        arm: usize,
+        bindings: Vec<(String, Occurrence)>,
+    },
+
+    /// The patterns of the arm at index `arm` match, but its guard must be checked with the bindings in
+    /// scope. If the guard fails, matching continues with `otherwise`.
+    Guard {
+        arm: usize,
+        bindings: Vec<(String, Occurrence)>,
+        otherwise: Box<Decision>,
+    },
+
+    /// Tests which constructor built the value at `occurrence`. If none of the `cases` apply, `default`
+    /// is taken. There is no default when the cases cover every constructor of the type.
+    Switch {
+        occurrence: Occurrence,
+        cases: Vec<(Ctor, Decision)>,
+        default: Option<Box<Decision>>,
+    },
+}
+
+#[derive(Clone)]
+struct Row {
+    arm: usize,
+    has_guard: bool,
+    tests: Vec<(Occurrence, Pat)>,
+    bindings: Vec<(String, Occurrence)>,
+}
+
+impl Row {
+    /// Moves bindings out of the row's tests and drops any tests that can't fail
+    fn normalize(mut self) -> Row {
+        let tests = ::std::mem::replace(&mut self.tests, Vec::new());
+        for (occurrence, pat) in tests {
+            match pat {
+                Pat::Wild => {}
+                Pat::Bind(name) => self.bindings.push((name, occurrence)),
+                p => self.tests.push((occurrence, p)),
+            }
+        }
+        self
+    }
+
+    fn test_at(&self, occurrence: &Occurrence) -> Option<usize> {
+        self.tests.iter().position(|&(ref o, _)| o == occurrence)
+    }
+}
+
+/// Compiles a list of arms, each given as its pattern and whether it has a guard, into a decision tree
+pub fn compile(enums: &Enums, arms: &[(Pat, bool)]) -> Decision {
+    let rows = arms.iter().enumerate().map(|(arm, &(ref pat, has_guard))| {
+        Row {
+            arm,
+            has_guard,
+            tests: vec![(Occurrence::root(), pat.clone())],
+            bindings: Vec::new(),
+        }
+    }).collect();
+    compile_rows(enums, rows)
+}
+
+fn compile_rows(enums: &Enums, rows: Vec<Row>) -> Decision {
+    let mut rows: Vec<Row> = rows.into_iter().map(Row::normalize).collect();
+    if rows.is_empty() {
+        return Decision::Fail;
+    }
+
+    if rows[0].tests.is_empty() {
+        // Everything in the first row matches
+        let first = rows.remove(0);
+        return if first.has_guard {
+            Decision::Guard {
+                arm: first.arm,
+                bindings: first.bindings,
+                otherwise: Box::new(compile_rows(enums, rows)),
+            }
+        } else {
+            Decision::Leaf {
+                arm: first.arm,
+                bindings: first.bindings,
+            }
+        };
+    }
+
+    // Test whatever the first row needs tested first
+    let occurrence = rows[0].tests[0].0.clone();
+    let mut ctors = Vec::new();
+    for row in rows.iter() {
+        if let Some(idx) = row.test_at(&occurrence) {
+            if let Pat::Ctor(ctor, _) = row.tests[idx].1 {
+                if !ctors.contains(&ctor) {
+                    ctors.push(ctor);
+                }
+            }
+        }
+    }
+
+    let cases = ctors.iter().map(|&ctor| {
+        let specialized = rows.iter().filter_map(|row| {
+            let mut row = row.clone();
+            match row.test_at(&occurrence) {
+                // This row doesn't care about the value here, so it matches whatever the constructor is
+                None => Some(row),
+                Some(idx) => match row.tests.remove(idx).1 {
+                    Pat::Ctor(c, args) if c == ctor => {
+                        for (i, arg) in args.into_iter().enumerate() {
+                            row.tests.insert(idx + i, (occurrence.child(i), arg));
+                        }
+                        Some(row)
+                    }
+                    _ => None,
+                },
+            }
+        }).collect();
+        (ctor, compile_rows(enums, specialized))
+    }).collect();
+
+    let complete = match enums.all_ctors(ctors[0]) {
+        Some(all) => all.iter().all(|c| ctors.contains(c)),
+        None => false,
+    };
+    let default = if complete {
+        None
+    } else {
+        let remaining = rows.into_iter().filter(|r| r.test_at(&occurrence).is_none()).collect();
+        Some(Box::new(compile_rows(enums, remaining)))
+    };
+
+    Decision::Switch {
+        occurrence,
+        cases,
+        default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diagnostics::Diagnostic;
+    use tokenizer::Tokenizer;
+    use parser::{Decl, Parser, Stmt, ExprKind};
+    use patterns::{compile, Ctor, Decision, Enums, Occurrence, VariantId};
+
+    // Parses a program ending in a match expression, and compiles that match
+    fn compile_match(text: &str) -> Decision {
+        let program = Parser::new(Tokenizer::new(text)).program().expect("Expected the parse to succeed");
+        let mut diags: Vec<Diagnostic> = Vec::new();
+        let mut enums = Enums::new();
+        for stmt in program.stmts() {
+            if let &Stmt::Decl(Decl::Enum(ref e)) = stmt {
+                enums.add(e, &mut diags);
+            }
+        }
+        let arms = match program.result().expect("Expected a final expression").kind() {
+            &ExprKind::Match(_, ref arms) => arms.iter()
+                .map(|a| (enums.lower(a.pattern(), &mut diags), a.guard().is_some()))
+                .collect::<Vec<_>>(),
+            _ => panic!("Expected a match expression"),
+        };
+        assert!(diags.is_empty());
+        compile(&enums, &arms)
+    }
+
+    fn leaf(arm: usize, bindings: Vec<(&str, Vec<usize>)>) -> Decision {
+        Decision::Leaf {
+            arm,
+            bindings: bindings.into_iter().map(|(n, o)| (n.to_string(), occurrence(o))).collect(),
+        }
+    }
+
+    fn occurrence(path: Vec<usize>) -> Occurrence {
+        path.into_iter().fold(Occurrence::root(), |o, i| o.child(i))
+    }
+
+    fn variant(index: usize) -> Ctor {
+        Ctor::Variant(VariantId { enum_id: 0, index })
+    }
+
+    #[test]
+    pub fn enum_match_switches_on_each_variant_without_default() {
+        assert_eq!(
+            Decision::Switch {
+                occurrence: Occurrence::root(),
+                cases: vec![
+                    (variant(0), leaf(0, vec![("r", vec![0])])),
+                    (variant(2), leaf(1, vec![])),
+                    (variant(1), leaf(2, vec![("w", vec![0]), ("h", vec![1])])),
+                ],
+                default: None,
+            },
+            compile_match("enum Shape { Circle(Int), Rect(Int, Int), Empty } match s { Circle(r) => r, Empty => 0, Rect(w, h) => w * h }"));
+    }
+
+    #[test]
+    pub fn wildcard_rows_are_shared_between_cases_and_default() {
+        assert_eq!(
+            Decision::Switch {
+                occurrence: Occurrence::root(),
+                cases: vec![
+                    (Ctor::Tuple(2), Decision::Switch {
+                        occurrence: occurrence(vec![0]),
+                        cases: vec![
+                            (Ctor::Int(1), leaf(0, vec![("y", vec![1])])),
+                        ],
+                        default: Some(Box::new(Decision::Switch {
+                            occurrence: occurrence(vec![1]),
+                            cases: vec![(Ctor::Int(2), leaf(1, vec![("x", vec![0])]))],
+                            default: Some(Box::new(leaf(2, vec![("p", vec![])]))),
+                        })),
+                    }),
+                ],
+                default: None,
+            },
+            compile_match("match t { (1, y) => y, (x, 2) => x, p => 0 }"));
+    }
+
+    #[test]
+    pub fn guard_falls_through_to_later_arms() {
+        assert_eq!(
+            Decision::Guard {
+                arm: 0,
+                bindings: vec![("n".to_string(), Occurrence::root())],
+                otherwise: Box::new(Decision::Switch {
+                    occurrence: Occurrence::root(),
+                    cases: vec![(Ctor::Int(0), leaf(1, vec![]))],
+                    default: Some(Box::new(Decision::Fail)),
+                }),
+            },
+            compile_match("match n { n if n > 10 => 1, 0 => 2 }"));
+    }
+}
@@ -0,0 +1,94 @@
+mod decision;
+mod pat;
+mod usefulness;
+
+pub use self::decision::{compile, Decision, Occurrence};
+pub use self::pat::{Ctor, Enums, Pat, VariantId};
+pub use self::usefulness::{check_match, is_useful, witnesses, CheckArm};
+
+use diagnostics::Diagnostic;
+use parser::{Decl, DefDecl, Expr, ExprKind, Pattern, Program, Stmt};
+use parser::visit::{self, Visitor};
+
+/// Checks every pattern in the program, reporting unreachable match arms, matches that don't
+/// cover every possible value and `let`s or parameters whose pattern might not match
+pub fn check_program(program: &Program) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    let mut enums = Enums::new();
+    visit::walk_program(&mut EnumCollector { enums: &mut enums, diags: &mut diags }, program);
+    visit::walk_program(&mut PatternChecker { enums: &enums, diags: &mut diags }, program);
+    diags
+}
+
+struct EnumCollector<'a> {
+    enums: &'a mut Enums,
+    diags: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> Visitor<'ast> for EnumCollector<'a> {
+    fn visit_decl(&mut self, decl: &'ast Decl) {
+        if let &Decl::Enum(ref e) = decl {
+            self.enums.add(e, self.diags);
+        }
+        visit::walk_decl(self, decl);
+    }
+}
+
+struct PatternChecker<'a> {
+    enums: &'a Enums,
+    diags: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a> PatternChecker<'a> {
+    /// Lowers the pattern, returning `None` if it has errors
+    fn lower(&mut self, pattern: &Pattern) -> Option<Pat> {
+        let errors = self.diags.len();
+        let pat = self.enums.lower(pattern, self.diags);
+        if self.diags.len() == errors { Some(pat) } else { None }
+    }
+
+    /// Reports an error if there is some value the pattern doesn't match
+    fn check_irrefutable(&mut self, pattern: &Pattern, context: &str) {
+        if let Some(pat) = self.lower(pattern) {
+            if let Some(missing) = witnesses(self.enums, &[vec![pat]], 1).first() {
+                self.diags.push(Diagnostic::error(
+                    pattern.span(),
+                    format!("refutable pattern in {}, `{}` is not covered", context, self.enums.display(&missing[0]))));
+            }
+        }
+    }
+}
+
+impl<'a, 'ast> Visitor<'ast> for PatternChecker<'a> {
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        if let &Stmt::Let(ref l) = stmt {
+            self.check_irrefutable(l.pattern(), "`let`");
+        }
+        visit::walk_stmt(self, stmt);
+    }
+
+    fn visit_def(&mut self, def: &'ast DefDecl) {
+        for param in def.params() {
+            self.check_irrefutable(param.pattern(), "function parameter");
+        }
+        visit::walk_def(self, def);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        if let &ExprKind::Match(ref scrutinee, ref arms) = expr.kind() {
+            let lowered: Vec<_> = arms.iter().map(|arm| {
+                self.lower(arm.pattern()).map(|pat| CheckArm {
+                    span: arm.pattern().span(),
+                    pat,
+                    has_guard: arm.guard().is_some(),
+                })
+            }).collect();
+
+            // Patterns with errors would only lead to confusing reports
+            if let Some(lowered) = lowered.into_iter().collect::<Option<Vec<_>>>() {
+                check_match(self.enums, scrutinee.span(), &lowered, self.diags);
+            }
+        }
+        visit::walk_expr(self, expr);
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use diagnostics::Diagnostic;
+use parser::{EnumDecl, Lit, Pattern, PatternKind};
+
+/// Identifies a single variant of one of the enums declared in a program
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct VariantId {
+    pub enum_id: usize,
+    pub index: usize,
+}
+
+/// The constructor at the head of a pattern. Every value is built from exactly one of these,
+/// which is what lets us reason about which values a set of patterns covers.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Ctor {
+    Bool(bool),
+    Int(i64),
+    Tuple(usize),
+    Variant(VariantId),
+}
+
+/// A pattern with names resolved and literals turned into constructors,
+/// which is the form the usefulness checker and decision tree compiler work on
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Pat {
+    Wild,
+    Bind(String),
+    Ctor(Ctor, Vec<Pat>),
+}
+
+impl Pat {
+    /// Returns `true` if the pattern matches any value
+    pub fn is_wild(&self) -> bool {
+        match self {
+            &Pat::Wild | &Pat::Bind(_) => true,
+            &Pat::Ctor(..) => false,
+        }
+    }
+}
+
+struct EnumInfo {
+    name: String,
+    variants: Vec<(String, usize)>,
+}
+
+/// Knows about every enum declared in a program, so that patterns can be checked against them
+///
+/// Variants are referred to without naming their enum, so variant names must be unique across
+/// all the enums in a program.
+pub struct Enums {
+    enums: Vec<EnumInfo>,
+    by_variant: HashMap<String, VariantId>,
+}
+
+impl Enums {
+    pub fn new() -> Enums {
+        Enums {
+            enums: Vec::new(),
+            by_variant: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, decl: &EnumDecl, diags: &mut Vec<Diagnostic>) {
+        let enum_id = self.enums.len();
+        let mut info = EnumInfo {
+            name: decl.name().name().into(),
+            variants: Vec::new(),
+        };
+        for variant in decl.variants() {
+            let name = variant.name().name();
+            if let Some(existing) = self.by_variant.get(name) {
+                let owner = if existing.enum_id == enum_id { &info.name } else { &self.enums[existing.enum_id].name };
+                diags.push(Diagnostic::error(
+                    variant.name().span(),
+                    format!("a variant named `{}` is already declared in enum `{}`", name, owner)));
+                continue;
+            }
+            let id = VariantId { enum_id, index: info.variants.len() };
+            self.by_variant.insert(name.into(), id);
+            info.variants.push((name.into(), variant.fields().len()));
+        }
+        self.enums.push(info);
+    }
+
+    pub fn variant(&self, name: &str) -> Option<VariantId> {
+        self.by_variant.get(name).cloned()
+    }
+
+    pub fn enum_name(&self, enum_id: usize) -> &str {
+        &self.enums[enum_id].name
+    }
+
+    /// Gets the names of an enum's variants, in the order of their tags
+    pub fn variants(&self, enum_name: &str) -> Vec<&str> {
+        self.enums.iter().find(|e| e.name == enum_name)
+            .map_or(Vec::new(), |e| e.variants.iter().map(|&(ref name, _)| name.as_str()).collect())
+    }
+
+    pub fn variant_name(&self, id: VariantId) -> &str {
+        &self.enums[id.enum_id].variants[id.index].0
+    }
+
+    /// Gets the number of fields a value built by the constructor has
+    pub fn arity(&self, ctor: Ctor) -> usize {
+        match ctor {
+            Ctor::Bool(_) | Ctor::Int(_) => 0,
+            Ctor::Tuple(n) => n,
+            Ctor::Variant(id) => self.enums[id.enum_id].variants[id.index].1,
+        }
+    }
+
+    /// Gets every constructor of the type that `ctor` builds, or `None` if there are too many to list
+    pub fn all_ctors(&self, ctor: Ctor) -> Option<Vec<Ctor>> {
+        match ctor {
+            Ctor::Bool(_) => Some(vec![Ctor::Bool(false), Ctor::Bool(true)]),
+            Ctor::Int(_) => None,
+            Ctor::Tuple(n) => Some(vec![Ctor::Tuple(n)]),
+            Ctor::Variant(id) => Some(
+                (0..self.enums[id.enum_id].variants.len())
+                    .map(|index| Ctor::Variant(VariantId { enum_id: id.enum_id, index }))
+                    .collect()),
+        }
+    }
+
+    /// Lowers a parsed pattern, reporting any references to unknown variants
+    /// or variants used with the wrong number of fields
+    pub fn lower(&self, pattern: &Pattern, diags: &mut Vec<Diagnostic>) -> Pat {
+        match pattern.kind() {
+            &PatternKind::Wildcard => Pat::Wild,
+            &PatternKind::Binding(ref name) => match self.variant(name.name()) {
+                Some(id) => self.lower_variant(pattern, id, &[], diags),
+                None => Pat::Bind(name.name().into()),
+            },
+            &PatternKind::Literal(Lit::Int(ref i)) => match i.to_i64() {
+                Some(i) => Pat::Ctor(Ctor::Int(i), Vec::new()),
+                None => {
+                    diags.push(Diagnostic::error(pattern.span(), "integer literals in patterns must fit in 64 bits"));
+                    Pat::Wild
+                }
+            },
+            &PatternKind::Literal(Lit::Bool(b)) => Pat::Ctor(Ctor::Bool(b), Vec::new()),
+            &PatternKind::Literal(Lit::Float(_)) | &PatternKind::Literal(Lit::Str(_)) => {
+                diags.push(Diagnostic::error(pattern.span(), "only integer and boolean literals can be used in patterns"));
+                Pat::Wild
+            }
+            &PatternKind::Tuple(ref elements) => Pat::Ctor(
+                Ctor::Tuple(elements.len()),
+                elements.iter().map(|e| self.lower(e, diags)).collect()),
+            &PatternKind::Constructor(ref name, ref fields) => match self.variant(name.name()) {
+                Some(id) => self.lower_variant(pattern, id, fields, diags),
+                None => {
+                    diags.push(Diagnostic::error(name.span(), format!("no variant named `{}` has been declared", name.name())));
+                    Pat::Wild
+                }
+            },
+        }
+    }
+
+    fn lower_variant(&self, pattern: &Pattern, id: VariantId, fields: &[Pattern], diags: &mut Vec<Diagnostic>) -> Pat {
+        let ctor = Ctor::Variant(id);
+        let arity = self.arity(ctor);
+        if arity != fields.len() {
+            diags.push(Diagnostic::error(
+                pattern.span(),
+                format!(
+                    "variant `{}` has {} field(s), but the pattern has {}",
+                    self.variant_name(id), arity, fields.len())));
+            return Pat::Wild;
+        }
+        Pat::Ctor(ctor, fields.iter().map(|f| self.lower(f, diags)).collect())
+    }
+
+    /// Formats the pattern as it would be written in source
+    pub fn display(&self, pat: &Pat) -> String {
+        match pat {
+            &Pat::Wild => "_".into(),
+            &Pat::Bind(ref name) => name.clone(),
+            &Pat::Ctor(Ctor::Bool(b), _) => b.to_string(),
+            &Pat::Ctor(Ctor::Int(i), _) => i.to_string(),
+            &Pat::Ctor(Ctor::Tuple(n), ref args) => {
+                let args: Vec<_> = args.iter().map(|a| self.display(a)).collect();
+                if n == 1 {
+                    format!("({},)", args[0])
+                } else {
+                    format!("({})", args.join(", "))
+                }
+            }
+            &Pat::Ctor(Ctor::Variant(id), ref args) => {
+                if args.is_empty() {
+                    self.variant_name(id).into()
+                } else {
+                    let args: Vec<_> = args.iter().map(|a| self.display(a)).collect();
+                    format!("{}({})", self.variant_name(id), args.join(", "))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Exhaustiveness and reachability checking for match expressions.
+//!
+//! This is the usefulness algorithm from Maranget's "Warnings for pattern matching". A pattern
+//! vector is "useful" with respect to a matrix of pattern rows if there is some value it matches
+//! that none of the rows match. An arm is unreachable if its pattern isn't useful with respect to
+//! the arms before it, and a match is exhaustive if a wildcard isn't useful with respect to all
+//! of its arms.
+
+use std::collections::HashSet;
+
+use text::TextSpan;
+use diagnostics::Diagnostic;
+use patterns::{Ctor, Enums, Pat};
+
+type Row = Vec<Pat>;
+
+// Don't bury the user under a mountain of missing patterns
+const MAX_REPORTED_WITNESSES: usize = 3;
+
+/// An arm of a match, lowered and ready for checking
+pub struct CheckArm {
+    pub span: TextSpan,
+    pub pat: Pat,
+    pub has_guard: bool,
+}
+
+/// Checks a match for unreachable arms and for values that none of the arms match
+pub fn check_match(enums: &Enums, scrutinee: TextSpan, arms: &[CheckArm], diags: &mut Vec<Diagnostic>) {
+    let mut rows: Vec<Row> = Vec::new();
+    for arm in arms {
+        if !is_useful(enums, &rows, &[arm.pat.clone()]) {
+            diags.push(Diagnostic::warning(arm.span, "unreachable match arm")
+                .with_label(scrutinee, "every value of this expression is matched by an earlier arm"));
+        }
+
+        // A guarded arm might not match, so it can't be used to prove later arms are unreachable
+        if !arm.has_guard {
+            rows.push(vec![arm.pat.clone()]);
+        }
+    }
+
+    let missing = witnesses(enums, &rows, 1);
+    if !missing.is_empty() {
+        let mut names: Vec<_> = missing.iter()
+            .take(MAX_REPORTED_WITNESSES)
+            .map(|w| format!("`{}`", enums.display(&w[0])))
+            .collect();
+        if missing.len() > MAX_REPORTED_WITNESSES {
+            names.push(format!("{} more", missing.len() - MAX_REPORTED_WITNESSES));
+        }
+        diags.push(Diagnostic::error(
+            scrutinee,
+            format!("match is not exhaustive, patterns not covered: {}", names.join(", "))));
+    }
+}
+
+/// Returns `true` if there is a value matched by `v` that isn't matched by any row in `rows`
+pub fn is_useful(enums: &Enums, rows: &[Row], v: &[Pat]) -> bool {
+    if v.is_empty() {
+        return rows.is_empty();
+    }
+
+    match v[0] {
+        Pat::Ctor(ctor, ref args) => {
+            let mut specialized_v = args.clone();
+            specialized_v.extend_from_slice(&v[1..]);
+            is_useful(enums, &specialize(enums, rows, ctor), &specialized_v)
+        }
+        Pat::Wild | Pat::Bind(_) => match complete_signature(enums, rows) {
+            Some(ctors) => ctors.into_iter().any(|ctor| {
+                let mut specialized_v = vec![Pat::Wild; enums.arity(ctor)];
+                specialized_v.extend_from_slice(&v[1..]);
+                is_useful(enums, &specialize(enums, rows, ctor), &specialized_v)
+            }),
+            None => is_useful(enums, &default_rows(rows), &v[1..]),
+        }
+    }
+}
+
+/// Finds vectors of `width` patterns that together cover every value not matched by any row in `rows`
+pub fn witnesses(enums: &Enums, rows: &[Row], width: usize) -> Vec<Row> {
+    if width == 0 {
+        return if rows.is_empty() { vec![Vec::new()] } else { Vec::new() };
+    }
+
+    if let Some(ctors) = complete_signature(enums, rows) {
+        // Every constructor appears in the first column, so the missing values must be found inside one of them
+        return ctors.into_iter().flat_map(|ctor| ctor_witnesses(enums, rows, ctor, width)).collect();
+    }
+
+    // Some constructors are missing from the first column. The values built by those are missing,
+    // as long as the rest of the row isn't covered by the rows that match anything here.
+    let heads = head_ctors(rows);
+    let mut result = Vec::new();
+    let rest = witnesses(enums, &default_rows(rows), width - 1);
+    if !rest.is_empty() {
+        // Report the missing constructors themselves, unless nothing in the column is a constructor
+        // or the type has too many constructors to list
+        let missing: Vec<Pat> = match heads.first().and_then(|&c| enums.all_ctors(c)) {
+            Some(all) => all.into_iter()
+                .filter(|c| !heads.contains(c))
+                .map(|c| Pat::Ctor(c, vec![Pat::Wild; enums.arity(c)]))
+                .collect(),
+            None => vec![Pat::Wild],
+        };
+        for head in missing {
+            for w in rest.iter() {
+                let mut row = vec![head.clone()];
+                row.extend(w.iter().cloned());
+                result.push(row);
+            }
+        }
+    }
+
+    // There may also be missing values built by the constructors that are present
+    for ctor in heads {
+        result.extend(ctor_witnesses(enums, rows, ctor, width));
+    }
+    result
+}
+
+/// Finds the missing values built by `ctor`, as vectors of `width` patterns
+fn ctor_witnesses(enums: &Enums, rows: &[Row], ctor: Ctor, width: usize) -> Vec<Row> {
+    let arity = enums.arity(ctor);
+    witnesses(enums, &specialize(enums, rows, ctor), arity + width - 1)
+        .into_iter()
+        .map(|mut w| {
+            let rest = w.split_off(arity);
+            let mut row = vec![Pat::Ctor(ctor, w)];
+            row.extend(rest);
+            row
+        })
+        .collect()
+}
+
+/// Gets the distinct constructors at the head of each row, in the order they first appear
+fn head_ctors(rows: &[Row]) -> Vec<Ctor> {
+    let mut seen = HashSet::new();
+    let mut ctors = Vec::new();
+    for row in rows {
+        if let Pat::Ctor(ctor, _) = row[0] {
+            if seen.insert(ctor) {
+                ctors.push(ctor);
+            }
+        }
+    }
+    ctors
+}
+
+/// If the first column of `rows` mentions every constructor of its type, gets those constructors
+fn complete_signature(enums: &Enums, rows: &[Row]) -> Option<Vec<Ctor>> {
+    let heads = head_ctors(rows);
+    let all = match heads.first() {
+        Some(&first) => enums.all_ctors(first)?,
+        None => return None,
+    };
+    if all.iter().all(|c| heads.contains(c)) {
+        Some(all)
+    } else {
+        None
+    }
+}
+
+/// Keeps the rows that match values built by `ctor`, replacing their first pattern with the
+/// patterns for that constructor's fields
+fn specialize(enums: &Enums, rows: &[Row], ctor: Ctor) -> Vec<Row> {
+    rows.iter().filter_map(|row| {
+        let mut new_row = match row[0] {
+            Pat::Ctor(c, ref args) if c == ctor => args.clone(),
+            Pat::Ctor(..) => return None,
+            Pat::Wild | Pat::Bind(_) => vec![Pat::Wild; enums.arity(ctor)],
+        };
+        new_row.extend_from_slice(&row[1..]);
+        Some(new_row)
+    }).collect()
+}
+
+/// Keeps the rows whose first pattern matches anything, removing that pattern
+fn default_rows(rows: &[Row]) -> Vec<Row> {
+    rows.iter()
+        .filter(|row| row[0].is_wild())
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokenizer::Tokenizer;
+    use parser::Parser;
+    use diagnostics::{Diagnostic, Severity};
+    use patterns::check_program;
+
+    const SHAPES: &'static str = "enum Shape { Circle(Int), Rect(Int, Int), Empty } ";
+
+    fn check(text: &str) -> Vec<Diagnostic> {
+        let program = Parser::new(Tokenizer::new(text)).program().expect("Expected the parse to succeed");
+        check_program(&program)
+    }
+
+    fn messages(text: &str) -> Vec<(Severity, String)> {
+        check(text).into_iter().map(|d| (d.severity(), d.message().to_string())).collect()
+    }
+
+    fn shapes(text: &str) -> Vec<(Severity, String)> {
+        messages(&format!("{}{}", SHAPES, text))
+    }
+
+    #[test]
+    pub fn exhaustive_enum_match_has_no_diagnostics() {
+        assert!(shapes("match s { Circle(r) => r, Rect(w, h) => w * h, Empty => 0 }").is_empty());
+    }
+
+    #[test]
+    pub fn missing_variant_is_reported() {
+        assert_eq!(
+            vec![(Severity::Error, "match is not exhaustive, patterns not covered: `Rect(_, _)`".to_string())],
+            shapes("match s { Circle(r) => r, Empty => 0 }"));
+    }
+
+    #[test]
+    pub fn missing_case_is_reported_at_scrutinee() {
+        let diags = check("match 1 + 2 { 3 => 1 }");
+        assert_eq!(1, diags.len());
+        assert_eq!((6, 11), (diags[0].span().start(), diags[0].span().end()));
+        assert_eq!("match is not exhaustive, patterns not covered: `_`", diags[0].message());
+    }
+
+    #[test]
+    pub fn missing_nested_cases_are_reported() {
+        assert_eq!(
+            vec![(Severity::Error, "match is not exhaustive, patterns not covered: `(false, true)`, `(true, false)`".to_string())],
+            messages("match p { (true, true) => 1, (false, false) => 2 }"));
+    }
+
+    #[test]
+    pub fn unreachable_arm_is_reported_at_its_pattern() {
+        let text = format!("{}match s {{ _ => 0, Empty => 1 }}", SHAPES);
+        let diags = check(&text);
+        assert_eq!(1, diags.len());
+        assert_eq!(Severity::Warning, diags[0].severity());
+        assert_eq!("unreachable match arm", diags[0].message());
+        assert_eq!("Empty", &text[diags[0].span().start()..diags[0].span().end()]);
+        assert_eq!(1, diags[0].labels().len());
+    }
+
+    #[test]
+    pub fn arm_covered_by_several_earlier_arms_is_unreachable() {
+        assert_eq!(
+            vec![(Severity::Warning, "unreachable match arm".to_string())],
+            messages("match p { (true, _) => 1, (_, false) => 2, (false, true) => 3, (false, false) => 4 }"));
+    }
+
+    #[test]
+    pub fn guarded_arms_do_not_count_towards_exhaustiveness() {
+        assert_eq!(
+            vec![(Severity::Error, "match is not exhaustive, patterns not covered: `Circle(_)`".to_string())],
+            shapes("match s { Circle(r) if r > 0 => r, Rect(w, h) => w, Empty => 0 }"));
+    }
+
+    #[test]
+    pub fn integer_literals_need_a_wildcard() {
+        assert!(messages("match n { 0 => 1, 1 => 1, x => x }").is_empty());
+        assert_eq!(
+            vec![(Severity::Error, "match is not exhaustive, patterns not covered: `_`".to_string())],
+            messages("match n { 0 => 1, 1 => 1 }"));
+    }
+
+    #[test]
+    pub fn many_missing_cases_are_summarized() {
+        assert_eq!(
+            vec![(Severity::Error, "match is not exhaustive, patterns not covered: `(Circle(_), _)`, `(Rect(_, _), _)`, `(Empty, Circle(_))`, 1 more".to_string())],
+            shapes("match p { (Empty, Empty) => 1 }"));
+    }
+
+    #[test]
+    pub fn matches_inside_arms_are_checked() {
+        assert_eq!(
+            vec![(Severity::Error, "match is not exhaustive, patterns not covered: `false`".to_string())],
+            messages("match a { x => match x { true => 1 } }"));
+    }
+
+    #[test]
+    pub fn unknown_variant_is_reported() {
+        assert_eq!(
+            vec![(Severity::Error, "no variant named `Square` has been declared".to_string())],
+            shapes("match s { Square(x) => x, _ => 0 }"));
+    }
+
+    #[test]
+    pub fn variant_with_wrong_number_of_fields_is_reported() {
+        assert_eq!(
+            vec![(Severity::Error, "variant `Rect` has 2 field(s), but the pattern has 1".to_string())],
+            shapes("match s { Rect(w) => w, _ => 0 }"));
+    }
+
+    #[test]
+    pub fn matches_inside_functions_are_checked() {
+        assert_eq!(
+            vec![(Severity::Error, "match is not exhaustive, patterns not covered: `Empty`".to_string())],
+            messages("def area(s) { match s { Circle(r) => r, Rect(w, h) => w * h } } enum Shape { Circle(Int), Rect(Int, Int), Empty }"));
+    }
+
+    #[test]
+    pub fn destructuring_let_is_irrefutable() {
+        assert!(messages("def divmod(a, b) { (a / b, a - a / b * b) } let (q, (r, _)) = (divmod(7, 2), (1, 2)); q + r").is_empty());
+    }
+
+    #[test]
+    pub fn refutable_let_is_reported() {
+        assert_eq!(
+            vec![(Severity::Error, "refutable pattern in `let`, `(_, false)` is not covered".to_string())],
+            messages("let (x, true) = (1, true);"));
+    }
+
+    #[test]
+    pub fn refutable_parameter_is_reported() {
+        assert_eq!(
+            vec![(Severity::Error, "refutable pattern in function parameter, `Rect(_, _)` is not covered".to_string())],
+            shapes("def radius(Circle(r)) { r }"));
+    }
+
+    #[test]
+    pub fn duplicate_variant_is_reported() {
+        assert_eq!(
+            vec![(Severity::Error, "a variant named `Empty` is already declared in enum `Shape`".to_string())],
+            shapes("enum Other { Empty } 0"));
+    }
+}
@@ -0,0 +1,180 @@
+mod resolver;
+mod suggest;
+mod symbols;
+
+pub use self::resolver::{resolve_program, Resolution};
+pub use self::suggest::{best_match, edit_distance};
+pub use self::symbols::{Def, DefId, DefKind, Namespace, Scope, ScopeId, ScopeKind, SymbolTable};
+
+#[cfg(test)]
+mod tests {
+    use text::TextSpan;
+    use diagnostics::Diagnostic;
+    use resolve::{resolve_program, DefKind, Resolution, ScopeKind};
+    use testing::{last_span_of, parse, span_at, span_of};
+
+    fn resolve(code: &str) -> Resolution {
+        resolve_program(&parse(code))
+    }
+
+    fn errors(code: &str) -> Vec<Diagnostic> {
+        resolve(code).diagnostics().to_vec()
+    }
+
+    /// Gets the span of the definition the identifier at `span` refers to
+    fn target(result: &Resolution, span: TextSpan) -> Option<TextSpan> {
+        result.use_at(span).and_then(|id| result.symbols().def(id).span())
+    }
+
+    #[test]
+    pub fn uses_resolve_to_their_definitions() {
+        let code = "let x = 1; def f(y) { y + x } f(x)";
+        let result = resolve(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+
+        let x = span_of(code, "x");
+        assert_eq!(Some(x), target(&result, last_span_of(code, "x")));
+        assert_eq!(Some(x), target(&result, span_at(code, 26, "x")));
+        assert_eq!(Some(span_at(code, 17, "y")), target(&result, span_at(code, 22, "y")));
+        assert_eq!(Some(span_at(code, 15, "f")), target(&result, last_span_of(code, "f")));
+
+        assert_eq!(DefKind::Local, result.def_at(x).unwrap().kind());
+        assert_eq!(DefKind::Param, result.def_at(span_at(code, 17, "y")).unwrap().kind());
+        assert_eq!(DefKind::Function, result.def_at(span_at(code, 15, "f")).unwrap().kind());
+    }
+
+    #[test]
+    pub fn lets_open_a_scope_for_the_rest_of_the_block() {
+        let code = "let x = 1; let x = x + 1; let y = { let x = true; x }; x";
+        let result = resolve(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+
+        let first = span_at(code, 4, "x");
+        let second = span_at(code, 15, "x");
+        assert_eq!(Some(first), target(&result, span_at(code, 19, "x")));
+        assert_eq!(Some(span_at(code, 40, "x")), target(&result, span_at(code, 50, "x")));
+        assert_eq!(Some(second), target(&result, last_span_of(code, "x")));
+
+        let symbols = result.symbols();
+        let scope = symbols.scope(result.def_at(second).unwrap().scope());
+        assert_eq!(ScopeKind::Let, scope.kind());
+        assert_eq!(ScopeKind::Let, symbols.scope(scope.parent().unwrap()).kind());
+    }
+
+    #[test]
+    pub fn params_and_type_params_live_in_the_function_scope() {
+        let code = "def id<T>(x: T) -> T { let y = x; y }";
+        let result = resolve(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+
+        let t = span_of(code, "T");
+        assert_eq!(DefKind::TypeParam, result.def_at(t).unwrap().kind());
+        assert_eq!(Some(t), target(&result, last_span_of(code, "T")));
+
+        let symbols = result.symbols();
+        let x = result.def_at(span_of(code, "x")).unwrap();
+        assert_eq!(ScopeKind::Function, symbols.scope(x.scope()).kind());
+        let y = result.def_at(span_of(code, "y")).unwrap();
+        assert_eq!(ScopeKind::Let, symbols.scope(y.scope()).kind());
+    }
+
+    #[test]
+    pub fn types_variants_and_traits_are_global() {
+        let code = "
+            def area(s: Shape) -> Int { match s { Circle(r) => r, Empty => 0 } }
+            enum Shape { Circle(Int), Empty }
+            trait Show { def show(self) -> Str }
+            impl Show for Shape { def show(self) -> Str { \"shape\" } }
+            area(Circle(1))";
+        let result = resolve(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+
+        let shape = span_of(code, "Shape {");
+        assert_eq!(Some(TextSpan::new(shape.start(), shape.start() + 5)), target(&result, span_of(code, "Shape")));
+        assert_eq!(DefKind::Variant, result.def_at(span_of(code, "Circle")).unwrap().kind());
+        assert_eq!(DefKind::Variant, result.def_at(span_of(code, "Empty")).unwrap().kind());
+        assert_eq!(DefKind::Trait, result.def_at(last_span_of(code, "Show")).unwrap().kind());
+        assert_eq!(DefKind::BuiltinType, result.def_at(span_of(code, "Int")).unwrap().kind());
+    }
+
+    #[test]
+    pub fn undefined_names_suggest_similar_ones() {
+        let code = "let length = 1; lenght";
+        let span = span_of(code, "lenght");
+        assert_eq!(vec![
+            Diagnostic::error(span, "cannot find value `lenght` in this scope")
+                .with_label(span, "did you mean `length`?"),
+        ], errors(code));
+
+        let code = "struct Point { x: Int } let p: Pointt = Point { x: 1 }; zzz";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "Pointt"), "cannot find type `Pointt` in this scope")
+                .with_label(span_of(code, "Pointt"), "did you mean `Point`?"),
+            Diagnostic::error(span_of(code, "zzz"), "cannot find value `zzz` in this scope"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn suggestions_only_consider_the_expected_kind() {
+        let code = "enum Shape { Circle(Int) } let circle = 1; match circle { Circel(r) => r }";
+        let span = span_of(code, "Circel");
+        assert_eq!(vec![
+            Diagnostic::error(span, "cannot find variant `Circel` in this scope")
+                .with_label(span, "did you mean `Circle`?"),
+        ], errors(code));
+
+        let code = "enum Shape { Circle(Int) } Shape { r: 1 }";
+        assert_eq!(vec![
+            Diagnostic::error(last_span_of(code, "Shape"), "expected struct, found enum `Shape`")
+                .with_label(span_of(code, "Shape"), "`Shape` is defined here"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn duplicate_definitions_are_reported() {
+        let code = "def f() { 1 } def f() { 2 } 0";
+        assert_eq!(vec![
+            Diagnostic::error(span_at(code, 18, "f"), "the name `f` is defined multiple times")
+                .with_label(span_at(code, 4, "f"), "previous definition of `f` here"),
+        ], errors(code));
+
+        let code = "struct P { x: Int, x: Int } enum P { A }";
+        assert_eq!(vec![
+            Diagnostic::error(last_span_of(code, "P"), "the name `P` is defined multiple times")
+                .with_label(span_of(code, "P"), "previous definition of `P` here"),
+            Diagnostic::error(last_span_of(code, "x"), "field `x` is already declared")
+                .with_label(span_of(code, "x"), "`x` first declared here"),
+        ], errors(code));
+
+        let code = "def g(x, x) { x } let (y, y) = (1, 2); 0";
+        assert_eq!(vec![
+            Diagnostic::error(span_at(code, 9, "x"), "identifier `x` is bound more than once in this parameter list")
+                .with_label(span_at(code, 6, "x"), "previous definition of `x` here"),
+            Diagnostic::error(span_at(code, 26, "y"), "identifier `y` is bound more than once in the same pattern")
+                .with_label(span_at(code, 23, "y"), "previous definition of `y` here"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn using_a_let_before_it_is_reported() {
+        let code = "let y = x + 1; let x = 1; y";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "x"), "cannot use `x` before it is defined")
+                .with_label(last_span_of(code, "x"), "`x` is defined here"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn functions_can_be_used_before_their_declaration() {
+        assert_eq!(Vec::<Diagnostic>::new(), errors("let x = f(); def f() { g() } def g() { 1 } x"));
+    }
+
+    #[test]
+    pub fn unknown_traits_are_reported() {
+        let code = "def f<T: Shw>(x: T) { x } let s: dyn Show = 1; 0";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "Shw"), "cannot find trait `Shw` in this scope"),
+            Diagnostic::error(span_of(code, "Show"), "cannot find trait `Show` in this scope"),
+        ], errors(code));
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::{HashMap, HashSet};
+use diagnostics::Diagnostic;
+use parser::*;
+use parser::visit::{walk_decl, Visitor};
+use resolve::suggest::best_match;
+use resolve::{Def, DefId, DefKind, Namespace, ScopeId, ScopeKind, SymbolTable};
+use text::TextSpan;
+use typeck::operator_trait;
+
+const BUILTIN_TYPES: &[&str] = &["Int", "Float", "Bool", "Str", "CInt", "CLong"];
+
+/// What name resolution learned about a program
+pub struct Resolution {
+    symbols: SymbolTable,
+    uses: HashMap<TextSpan, DefId>,
+    decls: HashMap<TextSpan, DefId>,
+    used: HashSet<DefId>,
+    diags: Vec<Diagnostic>,
+}
+
+impl Resolution {
+    pub fn symbols(&self) -> &SymbolTable { &self.symbols }
+    pub fn diagnostics(&self) -> &[Diagnostic] { &self.diags }
+
+    /// Gets the definition an identifier refers to, given the span of the identifier
+    pub fn use_at(&self, span: TextSpan) -> Option<DefId> {
+        self.uses.get(&span).cloned()
+    }
+
+    /// Gets the definition introduced by a name, given the span of the name where it's defined
+    pub fn decl_at(&self, span: TextSpan) -> Option<DefId> {
+        self.decls.get(&span).cloned()
+    }
+
+    /// Returns `true` if anything in the program refers to the definition
+    pub fn is_used(&self, def: DefId) -> bool {
+        self.used.contains(&def)
+    }
+
+    /// Gets the spans of every identifier that refers to the definition, in order
+    pub fn uses_of(&self, def: DefId) -> Vec<TextSpan> {
+        let mut spans: Vec<TextSpan> = self.uses.iter().filter(|&(_, &d)| d == def).map(|(&span, _)| span).collect();
+        spans.sort_by_key(|s| s.start());
+        spans
+    }
+
+    /// Gets the definition either used or introduced at the span
+    pub fn def_at(&self, span: TextSpan) -> Option<&Def> {
+        self.use_at(span).or_else(|| self.decl_at(span)).map(|id| self.symbols.def(id))
+    }
+}
+
+/// Maps every identifier in the program to the definition it refers to
+///
+/// Structs, enums, their variants and traits are global, so they can be used anywhere. Functions are visible
+/// throughout the block they're declared in, while the names bound by a `let` are only visible after it. Fields and
+/// method names depend on the type of the value they're used on, so they're left to the type checker.
+///
+/// A program with resolution errors shouldn't be passed on to `check_program`, which assumes every name it meets is
+/// defined.
+pub fn resolve_program(program: &Program) -> Resolution {
+    let mut r = Resolver {
+        symbols: SymbolTable::new(),
+        scope: ScopeId(0),
+        pending: Vec::new(),
+        uses: HashMap::new(),
+        decls: HashMap::new(),
+        diags: Vec::new(),
+    };
+
+    let global = r.symbols.add_scope(ScopeKind::Global, None);
+    for name in BUILTIN_TYPES {
+        let _ = r.symbols.define(global, *name, DefKind::BuiltinType, None);
+    }
+    for op in &[BinOp::Add, BinOp::Subtract, BinOp::Multiply, BinOp::Divide] {
+        if let Some((trait_name, _)) = operator_trait(*op) {
+            let _ = r.symbols.define(global, trait_name, DefKind::Trait, None);
+        }
+    }
+
+    r.scope = global;
+    r.define_items(program.stmts());
+
+    r.scope = r.symbols.add_scope(ScopeKind::Program, Some(global));
+    r.stmts(program.stmts(), program.result());
+
+    let Resolver { symbols, uses, decls, diags, .. } = r;
+    let used = uses.values().cloned().collect();
+    Resolution { symbols, uses, decls, used, diags }
+}
+
+struct Resolver<'ast> {
+    symbols: SymbolTable,
+    scope: ScopeId,
+
+    // The names bound by each enclosing block's `let`s that haven't been reached yet, so using one early can be
+    // explained rather than reported as undefined
+    pending: Vec<Vec<&'ast Ident>>,
+
+    uses: HashMap<TextSpan, DefId>,
+    decls: HashMap<TextSpan, DefId>,
+    diags: Vec<Diagnostic>,
+}
+
+impl<'ast> Resolver<'ast> {
+    fn enter(&mut self, kind: ScopeKind) -> ScopeId {
+        let outer = self.scope;
+        self.scope = self.symbols.add_scope(kind, Some(outer));
+        outer
+    }
+
+    fn define(&mut self, name: &Ident, kind: DefKind) {
+        let existing = match self.symbols.define(self.scope, name.name(), kind, Some(name.span())) {
+            Ok(id) => {
+                self.decls.insert(name.span(), id);
+                return;
+            }
+            Err(existing) => existing,
+        };
+
+        let message = match kind {
+            DefKind::Local => format!("identifier `{}` is bound more than once in the same pattern", name.name()),
+            DefKind::Param => format!("identifier `{}` is bound more than once in this parameter list", name.name()),
+            DefKind::TypeParam => format!("the type parameter `{}` is declared more than once", name.name()),
+            _ => format!("the name `{}` is defined multiple times", name.name()),
+        };
+        let mut diag = Diagnostic::error(name.span(), message);
+        if let Some(span) = self.symbols.def(existing).span() {
+            diag = diag.with_label(span, format!("previous definition of `{}` here", name.name()));
+        }
+        self.diags.push(diag);
+    }
+
+    /// Resolves a use of a name, reporting it if it isn't defined or isn't the kind of definition expected
+    ///
+    /// `what` describes what was expected, like "value" or "trait", and `expected` filters the candidates, both for
+    /// the definition found and for suggestions.
+    fn lookup<F: Fn(DefKind) -> bool>(&mut self, ns: Namespace, name: &Ident, what: &str, expected: F) -> Option<DefId> {
+        if let Some(id) = self.symbols.lookup(self.scope, ns, name.name()) {
+            let kind = self.symbols.def(id).kind();
+            if expected(kind) {
+                self.uses.insert(name.span(), id);
+                return Some(id);
+            }
+
+            let mut diag = Diagnostic::error(name.span(), format!("expected {}, found {} `{}`", what, kind.describe(), name.name()));
+            if let Some(span) = self.symbols.def(id).span() {
+                diag = diag.with_label(span, format!("`{}` is defined here", name.name()));
+            }
+            self.diags.push(diag);
+            return None;
+        }
+
+        if ns == Namespace::Value {
+            let later = self.pending.iter().rev().flat_map(|names| names.iter()).find(|n| n.name() == name.name()).cloned();
+            if let Some(later) = later {
+                self.diags.push(Diagnostic::error(name.span(), format!("cannot use `{}` before it is defined", name.name()))
+                    .with_label(later.span(), format!("`{}` is defined here", name.name())));
+                return None;
+            }
+        }
+
+        let candidates: Vec<&str> = self.symbols.visible(self.scope, ns).into_iter()
+            .map(|id| self.symbols.def(id))
+            .filter(|def| expected(def.kind()))
+            .map(|def| def.name())
+            .collect();
+        let mut diag = Diagnostic::error(name.span(), format!("cannot find {} `{}` in this scope", what, name.name()));
+        if let Some(suggestion) = best_match(name.name(), candidates) {
+            diag = diag.with_label(name.span(), format!("did you mean `{}`?", suggestion));
+        }
+        self.diags.push(diag);
+        None
+    }
+
+    fn lookup_trait(&mut self, name: &Ident) {
+        self.lookup(Namespace::Type, name, "trait", |k| k == DefKind::Trait);
+    }
+
+    /// Defines the structs, enums, variants and traits declared anywhere in the statements
+    fn define_items(&mut self, stmts: &'ast [Stmt]) {
+        struct ItemCollector<'ast> {
+            items: Vec<&'ast Decl>,
+        }
+
+        impl<'ast> Visitor<'ast> for ItemCollector<'ast> {
+            fn visit_decl(&mut self, decl: &'ast Decl) {
+                match decl {
+                    &Decl::Struct(_) | &Decl::Enum(_) | &Decl::Trait(_) => self.items.push(decl),
+                    _ => {}
+                }
+                walk_decl(self, decl);
+            }
+        }
+
+        let mut collector = ItemCollector { items: Vec::new() };
+        for stmt in stmts {
+            collector.visit_stmt(stmt);
+        }
+
+        for decl in collector.items {
+            match decl {
+                &Decl::Struct(ref s) => self.define(s.name(), DefKind::Struct),
+                &Decl::Enum(ref e) => {
+                    self.define(e.name(), DefKind::Enum);
+                    for variant in e.variants() {
+                        self.define(variant.name(), DefKind::Variant);
+                    }
+                }
+                &Decl::Trait(ref t) => self.define(t.name(), DefKind::Trait),
+                _ => {}
+            }
+        }
+    }
+
+    fn stmts(&mut self, stmts: &'ast [Stmt], result: Option<&'ast Expr>) {
+        let block_scope = self.scope;
+
+        // Functions can be called before they're declared, so they're defined before anything in the block is resolved
+        for stmt in stmts {
+            match stmt {
+                &Stmt::Decl(Decl::Def(ref d)) => self.define(d.name(), DefKind::Function),
+                &Stmt::Decl(Decl::Extern(ref e)) => self.define(e.name(), DefKind::Extern),
+                _ => {}
+            }
+        }
+
+        let mut lets = Vec::new();
+        for stmt in stmts {
+            if let &Stmt::Let(ref l) = stmt {
+                bound_names(l.pattern(), &mut lets);
+            }
+        }
+        self.pending.push(lets);
+
+        for stmt in stmts {
+            match stmt {
+                &Stmt::Decl(ref d) => self.decl(d),
+                &Stmt::Expr(ref e) => self.expr(e),
+                &Stmt::Let(ref l) => {
+                    if let Some(ty) = l.ty() {
+                        self.type_expr(ty);
+                    }
+                    self.expr(l.value());
+
+                    // Each `let` starts a new scope, so it can shadow an earlier `let` of the same name
+                    self.enter(ScopeKind::Let);
+                    let mut bound = Vec::new();
+                    bound_names(l.pattern(), &mut bound);
+                    self.pending.last_mut().unwrap().retain(|n| !bound.iter().any(|b| b.span() == n.span()));
+                    self.pattern(l.pattern(), DefKind::Local);
+                }
+            }
+        }
+        if let Some(result) = result {
+            self.expr(result);
+        }
+
+        self.pending.pop();
+        self.scope = block_scope;
+    }
+
+    fn block(&mut self, block: &'ast Block) {
+        let outer = self.enter(ScopeKind::Block);
+        self.stmts(block.stmts(), block.result());
+        self.scope = outer;
+    }
+
+    fn decl(&mut self, decl: &'ast Decl) {
+        match decl {
+            &Decl::Struct(ref s) => {
+                let outer = self.enter(ScopeKind::Item);
+                for param in s.type_params() {
+                    self.define(param, DefKind::TypeParam);
+                }
+
+                let mut seen: Vec<&Ident> = Vec::new();
+                for field in s.fields() {
+                    if let Some(first) = seen.iter().find(|n| n.name() == field.name().name()) {
+                        self.diags.push(Diagnostic::error(field.name().span(), format!("field `{}` is already declared", field.name().name()))
+                            .with_label(first.span(), format!("`{}` first declared here", first.name())));
+                    }
+                    seen.push(field.name());
+                    self.type_expr(field.ty());
+                }
+                self.scope = outer;
+            }
+            &Decl::Enum(ref e) => {
+                let outer = self.enter(ScopeKind::Item);
+                for param in e.type_params() {
+                    self.define(param, DefKind::TypeParam);
+                }
+                for variant in e.variants() {
+                    for ty in variant.fields() {
+                        self.type_expr(ty);
+                    }
+                }
+                self.scope = outer;
+            }
+            &Decl::Def(ref d) => self.def(d),
+            &Decl::Extern(ref e) => {
+                let outer = self.enter(ScopeKind::Function);
+                self.signature(e.params(), e.ret());
+                self.scope = outer;
+            }
+            &Decl::Trait(ref t) => {
+                let outer = self.enter(ScopeKind::Item);
+                let _ = self.symbols.define(self.scope, "Self", DefKind::TypeParam, Some(t.name().span()));
+
+                let mut seen: Vec<&Ident> = Vec::new();
+                for method in t.methods() {
+                    if let Some(first) = seen.iter().find(|n| n.name() == method.name().name()) {
+                        self.diags.push(Diagnostic::error(method.name().span(), format!("the method `{}` is declared more than once", method.name().name()))
+                            .with_label(first.span(), format!("previous declaration of `{}` here", first.name())));
+                    }
+                    seen.push(method.name());
+
+                    let trait_scope = self.enter(ScopeKind::Function);
+                    self.signature(method.params(), method.ret());
+                    self.scope = trait_scope;
+                }
+                self.scope = outer;
+            }
+            &Decl::Impl(ref i) => {
+                self.lookup_trait(i.trait_name());
+                self.type_expr(i.ty());
+                for method in i.methods() {
+                    self.def(method);
+                }
+            }
+        }
+    }
+
+    fn def(&mut self, def: &'ast DefDecl) {
+        let outer = self.enter(ScopeKind::Function);
+        for param in def.type_params() {
+            self.define(param.name(), DefKind::TypeParam);
+        }
+        for param in def.type_params() {
+            for bound in param.bounds() {
+                self.lookup_trait(bound);
+            }
+        }
+        self.signature(def.params(), def.ret());
+
+        // The body can't see the pending `let`s of the block the `def` is in, since it may be called before them
+        let pending = ::std::mem::replace(&mut self.pending, Vec::new());
+        self.block(def.body());
+        self.pending = pending;
+        self.scope = outer;
+    }
+
+    fn signature(&mut self, params: &'ast [Param], ret: Option<&'ast TypeExpr>) {
+        for param in params {
+            if let Some(ty) = param.ty() {
+                self.type_expr(ty);
+            }
+        }
+        for param in params {
+            self.pattern(param.pattern(), DefKind::Param);
+        }
+        if let Some(ret) = ret {
+            self.type_expr(ret);
+        }
+    }
+
+    /// Defines the names the pattern binds in the current scope, and resolves the variants it matches
+    fn pattern(&mut self, pattern: &'ast Pattern, kind: DefKind) {
+        match pattern.kind() {
+            &PatternKind::Wildcard | &PatternKind::Literal(_) => {}
+            &PatternKind::Binding(ref name) => {
+                // A bare name that refers to a unit variant matches the variant instead of binding a new name
+                match self.symbols.lookup(self.scope, Namespace::Value, name.name()) {
+                    Some(id) if self.symbols.def(id).kind() == DefKind::Variant => {
+                        self.uses.insert(name.span(), id);
+                    }
+                    _ => self.define(name, kind),
+                }
+            }
+            &PatternKind::Tuple(ref elements) => {
+                for e in elements {
+                    self.pattern(e, kind);
+                }
+            }
+            &PatternKind::Constructor(ref name, ref fields) => {
+                self.lookup(Namespace::Value, name, "variant", |k| k == DefKind::Variant);
+                for f in fields {
+                    self.pattern(f, kind);
+                }
+            }
+        }
+    }
+
+    fn type_expr(&mut self, ty: &'ast TypeExpr) {
+        match ty.kind() {
+            &TypeExprKind::Named(ref name, ref args) => {
+                self.lookup(Namespace::Type, name, "type", |k| k != DefKind::Trait);
+                for arg in args {
+                    self.type_expr(arg);
+                }
+            }
+            &TypeExprKind::Tuple(ref elements) => {
+                for e in elements {
+                    self.type_expr(e);
+                }
+            }
+            &TypeExprKind::Dyn(ref name) => self.lookup_trait(name),
+        }
+    }
+
+    fn expr(&mut self, expr: &'ast Expr) {
+        match expr.kind() {
+            &ExprKind::Constant(_) => {}
+            &ExprKind::Var(ref name) => {
+                self.lookup(Namespace::Value, name, "value", |_| true);
+            }
+            &ExprKind::Binary(ref l, ref r, _, _) | &ExprKind::Assign(ref l, ref r) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            &ExprKind::Struct(ref name, ref fields) => {
+                self.lookup(Namespace::Type, name, "struct", |k| k == DefKind::Struct);
+                for field in fields {
+                    self.expr(field.value());
+                }
+            }
+            &ExprKind::Field(ref target, _) => self.expr(target),
+            &ExprKind::Call(ref callee, ref args) | &ExprKind::MethodCall(ref callee, _, ref args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            &ExprKind::Match(ref scrutinee, ref arms) => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    let outer = self.enter(ScopeKind::Arm);
+                    self.pattern(arm.pattern(), DefKind::Local);
+                    if let Some(guard) = arm.guard() {
+                        self.expr(guard);
+                    }
+                    self.expr(arm.body());
+                    self.scope = outer;
+                }
+            }
+            &ExprKind::Tuple(ref elements) => {
+                for e in elements {
+                    self.expr(e);
+                }
+            }
+            &ExprKind::Block(ref block) => self.block(block),
+            &ExprKind::Return(ref value) => {
+                if let Some(ref value) = *value {
+                    self.expr(value);
+                }
+            }
+        }
+    }
+}
+
+fn bound_names<'ast>(pattern: &'ast Pattern, names: &mut Vec<&'ast Ident>) {
+    match pattern.kind() {
+        &PatternKind::Wildcard | &PatternKind::Literal(_) => {}
+        &PatternKind::Binding(ref name) => names.push(name),
+        &PatternKind::Tuple(ref elements) | &PatternKind::Constructor(_, ref elements) => {
+            for e in elements {
+                bound_names(e, names);
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+/// Counts the single character insertions, deletions and substitutions needed to turn `a` into `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+
+    // The distances from the prefix of `a` seen so far to each prefix of `b`
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Finds the candidate closest to `name`, if any is close enough to plausibly be a misspelling of it
+///
+/// Candidates at the same distance are broken alphabetically, so the suggestion doesn't depend on the order they're
+/// provided in.
+pub fn best_match<'a, I: IntoIterator<Item=&'a str>>(name: &str, candidates: I) -> Option<&'a str> {
+    let limit = ::std::cmp::max(1, name.chars().count() / 3);
+    candidates.into_iter()
+        .filter(|&c| c != name)
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|&(d, _)| d <= limit)
+        .min()
+        .map(|(_, c)| c)
+}
+
+#[cfg(test)]
+mod tests {
+    use resolve::suggest::{best_match, edit_distance};
+
+    #[test]
+    pub fn edit_distance_counts_single_character_edits() {
+        assert_eq!(0, edit_distance("length", "length"));
+        assert_eq!(2, edit_distance("lenght", "length"));
+        assert_eq!(1, edit_distance("pint", "point"));
+        assert_eq!(3, edit_distance("", "abc"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+    }
+
+    #[test]
+    pub fn best_match_only_suggests_close_names() {
+        assert_eq!(Some("length"), best_match("lenght", vec!["width", "length", "area"]));
+        assert_eq!(None, best_match("zzz", vec!["a", "length"]));
+        assert_eq!(Some("ab"), best_match("ac", vec!["bc", "ab"]));
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use text::TextSpan;
+
+/// Identifies a single definition, like a local variable, a parameter or a struct
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct DefId(pub u32);
+
+/// Identifies a scope in the `SymbolTable`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct ScopeId(pub u32);
+
+/// Types and values are looked up separately, so a struct and a function can share a name
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Namespace {
+    Type,
+    Value,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DefKind {
+    /// One of the types the language provides, like `Int`
+    BuiltinType,
+    Struct,
+    Enum,
+    Variant,
+    Trait,
+
+    /// A type parameter of a generic declaration, or `Self` inside a trait
+    TypeParam,
+    Function,
+    Extern,
+    Param,
+    Local,
+}
+
+impl DefKind {
+    pub fn namespace(self) -> Namespace {
+        match self {
+            DefKind::BuiltinType | DefKind::Struct | DefKind::Enum | DefKind::Trait | DefKind::TypeParam => Namespace::Type,
+            DefKind::Variant | DefKind::Function | DefKind::Extern | DefKind::Param | DefKind::Local => Namespace::Value,
+        }
+    }
+
+    /// A description of the kind of definition, for use in error messages
+    pub fn describe(self) -> &'static str {
+        match self {
+            DefKind::BuiltinType => "builtin type",
+            DefKind::Struct => "struct",
+            DefKind::Enum => "enum",
+            DefKind::Variant => "variant",
+            DefKind::Trait => "trait",
+            DefKind::TypeParam => "type parameter",
+            DefKind::Function => "function",
+            DefKind::Extern => "extern function",
+            DefKind::Param => "parameter",
+            DefKind::Local => "local variable",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Def {
+    name: String,
+    kind: DefKind,
+    span: Option<TextSpan>,
+    scope: ScopeId,
+}
+
+impl Def {
+    pub fn name(&self) -> &str { &self.name }
+    pub fn kind(&self) -> DefKind { self.kind }
+
+    /// The span of the name in the definition, or `None` for builtins
+    pub fn span(&self) -> Option<TextSpan> { self.span }
+    pub fn scope(&self) -> ScopeId { self.scope }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ScopeKind {
+    /// The outermost scope, holding builtins and everything declared with `struct`, `enum` or `trait`
+    Global,
+
+    /// The top level statements of the program
+    Program,
+
+    /// A `def`, `extern` or trait method, holding its type parameters and parameters
+    Function,
+
+    /// A `struct`, `enum` or `trait`, holding its type parameters
+    Item,
+    Block,
+
+    /// The rest of a block after a `let`, holding the names it binds
+    Let,
+    Arm,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Scope {
+    kind: ScopeKind,
+    parent: Option<ScopeId>,
+    names: HashMap<(Namespace, String), DefId>,
+}
+
+impl Scope {
+    pub fn kind(&self) -> ScopeKind { self.kind }
+    pub fn parent(&self) -> Option<ScopeId> { self.parent }
+
+    /// Gets the definition made directly in this scope, ignoring its parents
+    pub fn get(&self, ns: Namespace, name: &str) -> Option<DefId> {
+        self.names.get(&(ns, name.to_string())).cloned()
+    }
+}
+
+/// Every definition in a program, along with the tree of scopes they were made in
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    defs: Vec<Def>,
+    scopes: Vec<Scope>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable {
+            defs: Vec::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn def(&self, id: DefId) -> &Def { &self.defs[id.0 as usize] }
+    pub fn defs(&self) -> &[Def] { &self.defs }
+    pub fn scope(&self, id: ScopeId) -> &Scope { &self.scopes[id.0 as usize] }
+    pub fn scopes(&self) -> &[Scope] { &self.scopes }
+
+    pub fn add_scope(&mut self, kind: ScopeKind, parent: Option<ScopeId>) -> ScopeId {
+        self.scopes.push(Scope {
+            kind,
+            parent,
+            names: HashMap::new(),
+        });
+        ScopeId(self.scopes.len() as u32 - 1)
+    }
+
+    /// Adds a definition to the scope, shadowing any definition of the same name in its parents
+    ///
+    /// ## Returns
+    /// The new definition, or the existing one if the scope already has a definition of the same name in the same
+    /// namespace.
+    pub fn define<S: Into<String>>(&mut self, scope: ScopeId, name: S, kind: DefKind, span: Option<TextSpan>) -> Result<DefId, DefId> {
+        let name = name.into();
+        let key = (kind.namespace(), name.clone());
+        if let Some(&existing) = self.scopes[scope.0 as usize].names.get(&key) {
+            return Err(existing);
+        }
+
+        let id = DefId(self.defs.len() as u32);
+        self.defs.push(Def {
+            name,
+            kind,
+            span,
+            scope,
+        });
+        self.scopes[scope.0 as usize].names.insert(key, id);
+        Ok(id)
+    }
+
+    /// Finds the innermost definition of the name visible from the scope
+    pub fn lookup(&self, scope: ScopeId, ns: Namespace, name: &str) -> Option<DefId> {
+        let mut current = Some(scope);
+        while let Some(id) = current {
+            let scope = self.scope(id);
+            if let Some(def) = scope.get(ns, name) {
+                return Some(def);
+            }
+            current = scope.parent;
+        }
+        None
+    }
+
+    /// Gets every definition visible from the scope, skipping those shadowed by an inner definition
+    pub fn visible(&self, scope: ScopeId, ns: Namespace) -> Vec<DefId> {
+        let mut seen = HashMap::new();
+        let mut current = Some(scope);
+        while let Some(id) = current {
+            let scope = self.scope(id);
+            for (&(def_ns, ref name), &def) in &scope.names {
+                if def_ns == ns {
+                    seen.entry(name.as_str()).or_insert(def);
+                }
+            }
+            current = scope.parent;
+        }
+
+        let mut defs: Vec<DefId> = seen.into_iter().map(|(_, def)| def).collect();
+        defs.sort();
+        defs
+    }
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by the tests of the passes over parsed programs
+
+use diagnostics::Diagnostic;
+use parser::{Parser, Program};
+use resolve::{resolve_program, Resolution};
+use text::TextSpan;
+use tokenizer::Tokenizer;
+
+/// Parses a program that's expected to be well formed
+pub fn parse(code: &str) -> Program {
+    Parser::new(Tokenizer::new(code)).program().expect("Expected the parse to succeed")
+}
+
+/// Parses a program and resolves its names, which are expected to all be found
+pub fn parse_and_resolve(code: &str) -> (Program, Resolution) {
+    let program = parse(code);
+    let resolution = resolve_program(&program);
+    assert_eq!(Vec::<Diagnostic>::new(), resolution.diagnostics().to_vec());
+    (program, resolution)
+}
+
+/// Gets the span of `needle`, which is expected to be at `start` in the code
+pub fn span_at(code: &str, start: usize, needle: &str) -> TextSpan {
+    assert_eq!(needle, &code[start..start + needle.len()]);
+    TextSpan::new(start, start + needle.len())
+}
+
+pub fn span_of(code: &str, needle: &str) -> TextSpan {
+    span_at(code, code.find(needle).unwrap(), needle)
+}
+
+pub fn last_span_of(code: &str, needle: &str) -> TextSpan {
+    span_at(code, code.rfind(needle).unwrap(), needle)
+}
@@ -1,8 +1,11 @@
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Hash)]
 pub struct TextSpan(usize, usize);
 
 impl TextSpan {
     pub fn new(start: usize, end: usize) -> TextSpan { TextSpan(start, end) }
     pub fn start(&self) -> usize { self.0 }
     pub fn end(&self) -> usize { self.1 }
+
+    /// Creates a span covering everything from the start of this span to the end of `other`
+    pub fn to(&self, other: TextSpan) -> TextSpan { TextSpan(self.0, other.1) }
 }
\ No newline at end of file
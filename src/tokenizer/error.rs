@@ -1,11 +1,18 @@
-use std::num::ParseIntError;
+use std::num::{ParseIntError, ParseFloatError};
 
-use text;
+use text::{self, TextSpan};
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Error {
     TextError(text::Error),
-    ParseIntError(ParseIntError)
+    ParseIntError(ParseIntError),
+    ParseFloatError(ParseFloatError),
+
+    /// The end of the file was reached before the closing quote of the string starting at the span
+    UnterminatedString(TextSpan),
+
+    /// The escape sequence at the span is not recognized
+    InvalidEscape(TextSpan),
 }
 
 impl From<text::Error> for Error {
@@ -18,4 +25,10 @@ impl From<ParseIntError> for Error {
     fn from(e: ParseIntError) -> Error {
         Error::ParseIntError(e)
     }
+}
+
+impl From<ParseFloatError> for Error {
+    fn from(e: ParseFloatError) -> Error {
+        Error::ParseFloatError(e)
+    }
 }
\ No newline at end of file
@@ -1,35 +1,72 @@
+use bignum::Integer;
 use text::TextSpan;
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum TokenType {
     Unknown,
     Number,
+    String,
     Identifier,
     Keyword,
     LParen,
     RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
     Comma,
+    Colon,
+    Semicolon,
+    Dot,
     Plus,
     Minus,
     Star,
+    StarStar,
     Slash,
     Assign,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    FatArrow,
+    Arrow,
+    Hash,
+    HashBang,
 }
 
 // TODO: Try to make this Copy. I don't want to have to copy all the bytes of the string (which is what Clone does).
 // A symbol table will help.
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum TokenValue {
     None,
-    Int(i64),
+    Int(Integer),
+    Float(f64),
+    Str(String),
     Sym(String),
     Kwd(Keyword),
 }
 
+// Float literals are parsed from digits, so they are never NaN, which is the only value that breaks `Eq`
+impl Eq for TokenValue {}
+
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Keyword {
     Def,
     Extern,
+    Struct,
+    Let,
+    Enum,
+    Match,
+    If,
+    True,
+    False,
+    Trait,
+    Impl,
+    For,
+    Dyn,
+    Return,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
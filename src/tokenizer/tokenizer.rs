@@ -1,5 +1,6 @@
+use bignum::Integer;
 use tokenizer::{Token, TokenType, TokenValue, Keyword, Error};
-use text::TextWindow;
+use text::{TextSpan, TextWindow};
 
 pub struct Tokenizer<'a> {
     imp: TokenizerImpl<'a>
@@ -33,31 +34,49 @@ impl<'a> Tokenizer<'a> {
 }
 
 struct TokenizerImpl<'a> {
-    win: TextWindow<'a>
+    win: TextWindow<'a>,
 }
 
 impl<'a> TokenizerImpl<'a> {
     pub fn new(document: &'a str) -> TokenizerImpl<'a> {
         TokenizerImpl {
-            win: TextWindow::new(document)
+            win: TextWindow::new(document),
         }
     }
 
     fn token(&mut self) -> Result<Token, Error> {
         match self.win.last().unwrap() {
-            '-' if self.win.peek('0'..='9') => self.number(),
             '0'...'9' => self.number(),
+            '"' => self.string(),
             '_' | 'a'...'z' | 'A'...'Z' => self.identifier(),
 
             // Simple operators
             '(' => self.emit(TokenType::LParen, TokenValue::None),
             ')' => self.emit(TokenType::RParen, TokenValue::None),
+            '{' => self.emit(TokenType::LBrace, TokenValue::None),
+            '}' => self.emit(TokenType::RBrace, TokenValue::None),
+            '[' => self.emit(TokenType::LBracket, TokenValue::None),
+            ']' => self.emit(TokenType::RBracket, TokenValue::None),
             ',' => self.emit(TokenType::Comma, TokenValue::None),
+            ':' => self.emit(TokenType::Colon, TokenValue::None),
+            ';' => self.emit(TokenType::Semicolon, TokenValue::None),
+            '.' => self.emit(TokenType::Dot, TokenValue::None),
             '+' => self.emit(TokenType::Plus, TokenValue::None),
+            '-' if self.win.peek('>') => self.emit_pair(TokenType::Arrow),
             '-' => self.emit(TokenType::Minus, TokenValue::None),
+            '*' if self.win.peek('*') => self.emit_pair(TokenType::StarStar),
             '*' => self.emit(TokenType::Star, TokenValue::None),
             '/' => self.emit(TokenType::Slash, TokenValue::None),
+            '=' if self.win.peek('>') => self.emit_pair(TokenType::FatArrow),
+            '=' if self.win.peek('=') => self.emit_pair(TokenType::Equal),
             '=' => self.emit(TokenType::Assign, TokenValue::None),
+            '!' if self.win.peek('=') => self.emit_pair(TokenType::NotEqual),
+            '<' if self.win.peek('=') => self.emit_pair(TokenType::LessEqual),
+            '<' => self.emit(TokenType::Less, TokenValue::None),
+            '>' if self.win.peek('=') => self.emit_pair(TokenType::GreaterEqual),
+            '>' => self.emit(TokenType::Greater, TokenValue::None),
+            '#' if self.win.peek('!') => self.emit_pair(TokenType::HashBang),
+            '#' => self.emit(TokenType::Hash, TokenValue::None),
 
             // Unexpected things.
             _ => self.emit(TokenType::Unknown, TokenValue::None)
@@ -73,25 +92,80 @@ impl<'a> TokenizerImpl<'a> {
         let (typ, val) = match self.win.as_str() {
             "def" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Def)),
             "extern" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Extern)),
+            "struct" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Struct)),
+            "let" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Let)),
+            "enum" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Enum)),
+            "match" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Match)),
+            "if" => (TokenType::Keyword, TokenValue::Kwd(Keyword::If)),
+            "true" => (TokenType::Keyword, TokenValue::Kwd(Keyword::True)),
+            "false" => (TokenType::Keyword, TokenValue::Kwd(Keyword::False)),
+            "trait" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Trait)),
+            "impl" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Impl)),
+            "for" => (TokenType::Keyword, TokenValue::Kwd(Keyword::For)),
+            "dyn" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Dyn)),
+            "return" => (TokenType::Keyword, TokenValue::Kwd(Keyword::Return)),
             x => (TokenType::Identifier, TokenValue::Sym(x.into()))
         };
         self.emit(typ, val)
     }
 
     fn number(&mut self) -> Result<Token, Error> {
-        if self.win.last_is('-') {
-            self.win.take()?;
-        }
-
         // Read all the digits
         self.win.scan_while('0'..='9')?;
 
-        // Parse the number
-        let num: i64 = self.win.as_str().parse()?;
+        // A '.' followed by more digits makes this a float
+        let marker = self.win.end();
+        if self.win.take_if('.')? {
+            if self.win.peek('0'..='9') {
+                self.win.scan_while('0'..='9')?;
+                let num: f64 = self.win.as_str().parse()?;
+                return self.emit(TokenType::Number, TokenValue::Float(num));
+            }
+            self.win.backtrack(marker);
+        }
+
+        // Parse the number, which is an arbitrary precision integer if it doesn't fit in a machine word
+        let num: Integer = self.win.as_str().parse().expect("Expected the window to only hold digits");
 
         self.emit(TokenType::Number, TokenValue::Int(num))
     }
 
+    fn string(&mut self) -> Result<Token, Error> {
+        // The opening quote has already been read
+        let mut value = String::new();
+        loop {
+            if !self.win.take()? {
+                return Err(Error::UnterminatedString(self.win.span()));
+            }
+            match self.win.last().unwrap() {
+                '"' => break,
+                '\\' => {
+                    let escape_start = self.win.end() - 1;
+                    if !self.win.take()? {
+                        return Err(Error::UnterminatedString(self.win.span()));
+                    }
+                    value.push(match self.win.last().unwrap() {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        '"' => '"',
+                        '\\' => '\\',
+                        _ => return Err(Error::InvalidEscape(TextSpan::new(escape_start, self.win.end()))),
+                    });
+                }
+                c => value.push(c),
+            }
+        }
+        self.emit(TokenType::String, TokenValue::Str(value))
+    }
+
+    /// Emits a two-character operator token, where the second character has only been peeked at
+    fn emit_pair(&mut self, typ: TokenType) -> Result<Token, Error> {
+        self.win.take()?;
+        self.emit(typ, TokenValue::None)
+    }
+
     fn emit(&mut self, typ: TokenType, value: TokenValue) -> Result<Token, Error> {
         let span = self.win.span();
         self.win.advance();
@@ -102,7 +176,8 @@ impl<'a> TokenizerImpl<'a> {
 
 #[cfg(test)]
 mod tests {
-    use tokenizer::{Token, Tokenizer, TokenType, TokenValue, Keyword};
+    use tokenizer::{Token, Tokenizer, TokenType, TokenValue, Keyword, Error};
+    use text::TextSpan;
 
     macro_rules! single_token_test {
         ($s: expr, $typ: expr, $val: expr) => {
@@ -131,23 +206,89 @@ mod tests {
     }
 
     token_tests! {
-        literal_zero => single_token_test!("0", TokenType::Number, TokenValue::Int(0));
-        literal_pos_int => single_token_test!("123", TokenType::Number, TokenValue::Int(123));
-        literal_neg_int => single_token_test!("-123", TokenType::Number, TokenValue::Int(-123));
+        literal_zero => single_token_test!("0", TokenType::Number, TokenValue::Int(0.into()));
+        literal_pos_int => single_token_test!("123", TokenType::Number, TokenValue::Int(123.into()));
+        literal_huge_int => single_token_test!("123456789012345678901234567890", TokenType::Number,
+            TokenValue::Int("123456789012345678901234567890".parse().unwrap()));
+        literal_float => single_token_test!("1.25", TokenType::Number, TokenValue::Float(1.25));
+        literal_string => single_token_test!("\"a b\"", TokenType::String, TokenValue::Str("a b".into()));
+        literal_string_escapes => single_token_test!("\"\\n\\t\\\"\\\\\"", TokenType::String, TokenValue::Str("\n\t\"\\".into()));
 
         identifier => single_token_test!("_123foo_bar", TokenType::Identifier, TokenValue::Sym("_123foo_bar".into()));
 
         keyword_def => single_token_test!("def", TokenType::Keyword, TokenValue::Kwd(Keyword::Def));
         keyword_extern => single_token_test!("extern", TokenType::Keyword, TokenValue::Kwd(Keyword::Extern));
+        keyword_struct => single_token_test!("struct", TokenType::Keyword, TokenValue::Kwd(Keyword::Struct));
+        keyword_let => single_token_test!("let", TokenType::Keyword, TokenValue::Kwd(Keyword::Let));
+        keyword_enum => single_token_test!("enum", TokenType::Keyword, TokenValue::Kwd(Keyword::Enum));
+        keyword_match => single_token_test!("match", TokenType::Keyword, TokenValue::Kwd(Keyword::Match));
+        keyword_if => single_token_test!("if", TokenType::Keyword, TokenValue::Kwd(Keyword::If));
+        keyword_true => single_token_test!("true", TokenType::Keyword, TokenValue::Kwd(Keyword::True));
+        keyword_false => single_token_test!("false", TokenType::Keyword, TokenValue::Kwd(Keyword::False));
+        keyword_trait => single_token_test!("trait", TokenType::Keyword, TokenValue::Kwd(Keyword::Trait));
+        keyword_impl => single_token_test!("impl", TokenType::Keyword, TokenValue::Kwd(Keyword::Impl));
+        keyword_for => single_token_test!("for", TokenType::Keyword, TokenValue::Kwd(Keyword::For));
+        keyword_dyn => single_token_test!("dyn", TokenType::Keyword, TokenValue::Kwd(Keyword::Dyn));
+        keyword_return => single_token_test!("return", TokenType::Keyword, TokenValue::Kwd(Keyword::Return));
+        underscore => single_token_test!("_", TokenType::Identifier, TokenValue::Sym("_".into()));
 
         lparen => single_token_test!("(", TokenType::LParen, TokenValue::None);
         rparen => single_token_test!(")", TokenType::RParen, TokenValue::None);
+        lbrace => single_token_test!("{", TokenType::LBrace, TokenValue::None);
+        rbrace => single_token_test!("}", TokenType::RBrace, TokenValue::None);
         comma => single_token_test!(",", TokenType::Comma, TokenValue::None);
+        colon => single_token_test!(":", TokenType::Colon, TokenValue::None);
+        semicolon => single_token_test!(";", TokenType::Semicolon, TokenValue::None);
+        dot => single_token_test!(".", TokenType::Dot, TokenValue::None);
         plus => single_token_test!("+", TokenType::Plus, TokenValue::None);
         minus => single_token_test!("-", TokenType::Minus, TokenValue::None);
         star => single_token_test!("*", TokenType::Star, TokenValue::None);
+        star_star => single_token_test!("**", TokenType::StarStar, TokenValue::None);
+        hash => single_token_test!("#", TokenType::Hash, TokenValue::None);
+        hash_bang => single_token_test!("#!", TokenType::HashBang, TokenValue::None);
+        left_bracket => single_token_test!("[", TokenType::LBracket, TokenValue::None);
         slash => single_token_test!("/", TokenType::Slash, TokenValue::None);
         assign => single_token_test!("=", TokenType::Assign, TokenValue::None);
+        equal => single_token_test!("==", TokenType::Equal, TokenValue::None);
+        not_equal => single_token_test!("!=", TokenType::NotEqual, TokenValue::None);
+        less => single_token_test!("<", TokenType::Less, TokenValue::None);
+        less_equal => single_token_test!("<=", TokenType::LessEqual, TokenValue::None);
+        greater => single_token_test!(">", TokenType::Greater, TokenValue::None);
+        greater_equal => single_token_test!(">=", TokenType::GreaterEqual, TokenValue::None);
+        fat_arrow => single_token_test!("=>", TokenType::FatArrow, TokenValue::None);
+        arrow => single_token_test!("->", TokenType::Arrow, TokenValue::None);
+    }
+
+    #[test]
+    pub fn dot_after_integer_is_not_a_float() {
+        let types: Vec<_> = Tokenizer::new("1.x").map(|t| t.unwrap().typ()).collect();
+        assert_eq!(vec![TokenType::Number, TokenType::Dot, TokenType::Identifier], types);
+    }
+
+    #[test]
+    pub fn minus_is_its_own_token() {
+        // Whether it subtracts or makes a literal negative is up to the parser
+        let types: Vec<_> = Tokenizer::new("n-1 }-3 f(-2.5)").map(|t| t.unwrap().typ()).collect();
+        let expected = vec![
+            TokenType::Identifier, TokenType::Minus, TokenType::Number,
+            TokenType::RBrace, TokenType::Minus, TokenType::Number,
+            TokenType::Identifier, TokenType::LParen, TokenType::Minus, TokenType::Number, TokenType::RParen,
+        ];
+        assert_eq!(expected, types);
+    }
+
+    #[test]
+    pub fn unterminated_string_is_an_error() {
+        assert_eq!(
+            Some(Err(Error::UnterminatedString(TextSpan::new(0, 4)))),
+            Tokenizer::new("\"abc").next());
+    }
+
+    #[test]
+    pub fn invalid_escape_is_an_error() {
+        assert_eq!(
+            Some(Err(Error::InvalidEscape(TextSpan::new(2, 4)))),
+            Tokenizer::new("\"a\\q\"").next());
     }
 
     fn get_single_token(s: &str) -> Token {
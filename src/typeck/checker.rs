@@ -0,0 +1,1217 @@
+use std::collections::HashMap;
+
+use text::TextSpan;
+use diagnostics::Diagnostic;
+use parser::{BinOp, Block, Decl, DefDecl, Expr, ExprKind, Ident, ImplDecl, Lit, Param, Pattern, PatternKind, Program, Stmt,
+             TypeExpr, TypeExprKind};
+use parser::visit::{self, Visitor};
+use typeck::{operator_trait, Scheme, Type, TypeEnv};
+use typeck::infer::{Unifier, UnifyError};
+use typeck::types::normalize;
+
+/// The result of type checking a program
+///
+/// Types within a generic `def` are in terms of its type parameters and the variables of its scheme, so they can be
+/// made concrete by substituting the arguments of a particular use of the function.
+pub struct TypeckResults {
+    env: TypeEnv,
+    types: HashMap<TextSpan, Type>,
+    schemes: HashMap<TextSpan, Scheme>,
+    uses: HashMap<TextSpan, (TextSpan, Vec<Type>)>,
+    dispatches: HashMap<TextSpan, Dispatch>,
+    coercions: HashMap<TextSpan, String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// How a method call, or an operator applied to a type that overloads it, finds the function to run
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Dispatch {
+    /// Calls the method of an impl, identified by the span of the method's name
+    Static(TextSpan),
+
+    /// Calls a method of a trait that bounds a type parameter, so the impl depends on the type argument the
+    /// enclosing function is instantiated with
+    Bound { param: String, trait_name: String, method: String },
+
+    /// Calls a method of a `dyn` value, through the slot of its vtable that holds the method
+    Dynamic { trait_name: String, slot: usize },
+}
+
+impl TypeckResults {
+    /// Gets the type of the expression or pattern at exactly the provided span, or the type of the function whose name
+    /// is at the span, with its variables renamed for display
+    pub fn type_at(&self, span: TextSpan) -> Option<Type> {
+        self.types.get(&span).map(|t| normalize(&[t]).remove(0))
+    }
+
+    /// Gets the type of the innermost expression or pattern containing the offset, along with its span
+    pub fn type_at_offset(&self, offset: usize) -> Option<(TextSpan, Type)> {
+        self.types.keys()
+            .filter(|span| span.start() <= offset && offset < span.end())
+            .min_by_key(|span| (span.end() - span.start(), span.start()))
+            .map(|&span| (span, self.type_at(span).unwrap()))
+    }
+
+    /// Gets the type of the expression or pattern at exactly the provided span, without renaming its variables
+    pub fn raw_type_at(&self, span: TextSpan) -> Option<&Type> {
+        self.types.get(&span)
+    }
+
+    /// Gets the scheme of the function whose name is declared at the span
+    pub fn scheme_of(&self, name: TextSpan) -> Option<&Scheme> {
+        self.schemes.get(&name)
+    }
+
+    /// Gets the function used by the name at the span, as the span of the function's name, along with the arguments
+    /// its scheme was instantiated with
+    pub fn use_at(&self, span: TextSpan) -> Option<(TextSpan, &[Type])> {
+        self.uses.get(&span).map(|&(decl, ref args)| (decl, args.as_slice()))
+    }
+
+    /// Gets how the method whose name is at the span is called, or for a binary expression, how the overloaded
+    /// operator is called
+    pub fn dispatch_at(&self, span: TextSpan) -> Option<&Dispatch> {
+        self.dispatches.get(&span)
+    }
+
+    /// Gets the trait of the `dyn` value that the expression at the span is converted to, if it is converted
+    pub fn coercion_at(&self, span: TextSpan) -> Option<&str> {
+        self.coercions.get(&span).map(|t| t.as_str())
+    }
+
+    /// The types, traits and impls declared in the program
+    pub fn env(&self) -> &TypeEnv {
+        &self.env
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+/// Infers the types of everything in the program, recording the type of every expression and pattern
+pub fn check_program(program: &Program) -> TypeckResults {
+    let mut diags = Vec::new();
+    let env = TypeEnv::collect(program, &mut diags);
+    let mut checker = Checker {
+        env: &env,
+        unifier: Unifier::new(),
+        scopes: vec![HashMap::new()],
+        item_depth: 0,
+        ret: None,
+        type_params: Vec::new(),
+        bounds: HashMap::new(),
+        types: HashMap::new(),
+        schemes: HashMap::new(),
+        uses: HashMap::new(),
+        dispatches: HashMap::new(),
+        coercions: HashMap::new(),
+        constraints: Vec::new(),
+        diags,
+    };
+    checker.check_stmts(program.stmts());
+    if let Some(result) = program.result() {
+        checker.check_expr(result);
+    }
+    checker.solve_constraints(true);
+
+    let unifier = &checker.unifier;
+    let types = checker.types.iter().map(|(&span, ty)| (span, unifier.resolve(ty))).collect();
+    let uses = checker.uses.iter()
+        .map(|(&span, &(decl, ref args))| (span, (decl, args.iter().map(|a| unifier.resolve(a)).collect())))
+        .collect();
+    let Checker { schemes, dispatches, coercions, diags, .. } = checker;
+    TypeckResults {
+        env,
+        types,
+        schemes,
+        uses,
+        dispatches,
+        coercions,
+        diagnostics: diags,
+    }
+}
+
+/// Why a value is expected to have a particular type
+#[derive(Clone, Copy)]
+enum Reason {
+    Annotation(TextSpan),
+    Inferred(TextSpan),
+}
+
+struct Binding {
+    scheme: Scheme,
+
+    // The span of the annotation that gave the binding its type, if it had one
+    annotation: Option<TextSpan>,
+
+    // Set when the binding is a function, so calls can point at the declaration and at why each parameter has its type
+    function: Option<(TextSpan, Vec<Option<Reason>>)>,
+
+    // Functions can be used from within other functions, but local variables can't
+    item: bool,
+
+    // The traits that the arguments for each of a generic function's type parameters must implement
+    bounds: Vec<Vec<String>>,
+}
+
+impl Binding {
+    fn local(ty: Type, annotation: Option<TextSpan>) -> Binding {
+        Binding {
+            scheme: Scheme::mono(ty),
+            annotation,
+            function: None,
+            item: false,
+            bounds: Vec::new(),
+        }
+    }
+}
+
+/// Something a type must support, which can only be checked once the type is known
+struct Constraint {
+    span: TextSpan,
+    requirement: Requirement,
+    ty: Type,
+
+    // The bounds of the type parameters in scope where the constraint arose
+    bounds: HashMap<String, Vec<String>>,
+}
+
+enum Requirement {
+    /// The operator must apply to two values of the type, whose spans are given
+    Operator(BinOp, (TextSpan, TextSpan)),
+
+    /// The type must implement the trait
+    Trait(String),
+}
+
+struct Checker<'a> {
+    env: &'a TypeEnv,
+    unifier: Unifier,
+    scopes: Vec<HashMap<String, Binding>>,
+
+    // Scopes below this depth belong to the code around the `def` being checked
+    item_depth: usize,
+
+    // The return type of the `def` being checked, which `return` values must have, and the annotation it came from
+    ret: Option<(Type, Option<Reason>)>,
+
+    // The type parameters of the `def` being checked, and the traits that bound them
+    type_params: Vec<String>,
+    bounds: HashMap<String, Vec<String>>,
+
+    types: HashMap<TextSpan, Type>,
+    schemes: HashMap<TextSpan, Scheme>,
+    uses: HashMap<TextSpan, (TextSpan, Vec<Type>)>,
+    dispatches: HashMap<TextSpan, Dispatch>,
+    coercions: HashMap<TextSpan, String>,
+    constraints: Vec<Constraint>,
+    diags: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn check_stmts(&mut self, stmts: &[Stmt]) {
+        // Functions can be called before they are declared, so bring them all into scope first
+        let mut defs = Vec::new();
+        let mut impls = Vec::new();
+        for stmt in stmts {
+            match stmt {
+                &Stmt::Decl(Decl::Def(ref d)) => defs.push(d),
+                &Stmt::Decl(Decl::Impl(ref i)) => impls.push(i),
+                &Stmt::Decl(Decl::Extern(ref e)) => {
+                    // There's no body to infer from, so unannotated parts of an extern stay unknown
+                    let params = e.params().iter().map(|p| self.extern_annotation(p.ty())).collect();
+                    let ret = self.extern_annotation(e.ret());
+                    // C returns an `int` in the low half of `rax`, so the result has to say how much of it to read
+                    if let Some(ty) = e.ret().filter(|ty| is_named(ty, "Int")) {
+                        let message = "an `extern` can't return `Int`: use `CInt` for a C `int`, or `CLong` for a C \
+                                       `long`";
+                        self.diags.push(Diagnostic::error(ty.span(), message));
+                    }
+                    self.bind(e.name().name(), Binding {
+                        scheme: Scheme::mono(Type::Fn(params, Box::new(ret))),
+                        annotation: None,
+                        function: Some((e.name().span(), annotation_reasons(e.params()))),
+                        item: true,
+                        bounds: Vec::new(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        self.check_defs(&defs);
+
+        // The types of methods come from the signatures in their traits, so they can be checked after every function
+        // they might call
+        for i in impls {
+            self.check_impl(i);
+        }
+
+        for stmt in stmts {
+            match stmt {
+                &Stmt::Decl(_) => {}
+                &Stmt::Let(ref l) => {
+                    // Variables are never generalized, since every value needs a single type when it's compiled.
+                    // Only functions can be generic.
+                    let annotated = l.ty().map(|ty| (self.env.resolve(ty, &self.type_params, &mut self.diags), ty.span()));
+                    let value = self.check_expr(l.value());
+                    match annotated {
+                        Some((annotated, span)) => {
+                            self.coerce(&annotated, &value, l.value().span(), Some(Reason::Annotation(span)));
+                            self.check_pattern(l.pattern(), &annotated, Some(span));
+                        }
+                        None => self.check_pattern(l.pattern(), &value, None),
+                    }
+                }
+                &Stmt::Expr(ref e) => {
+                    self.check_expr(e);
+                }
+            }
+        }
+    }
+
+    /// Infers the types of the functions declared together in a block
+    ///
+    /// Functions are inferred in groups that call each other, after every group they call, so that each group is
+    /// generalized before it's used.
+    fn check_defs(&mut self, defs: &[&DefDecl]) {
+        let indices: HashMap<&str, usize> = defs.iter().enumerate().map(|(i, d)| (d.name().name(), i)).collect();
+        let calls: Vec<Vec<usize>> = defs.iter().map(|d| {
+            let mut names = NameCollector { names: Vec::new() };
+            visit::walk_block(&mut names, d.body());
+            names.names.iter().filter_map(|n| indices.get(n).cloned()).collect()
+        }).collect();
+
+        for group in strongly_connected(&calls) {
+            self.unifier.enter_level();
+            let mut types = Vec::new();
+            for &i in group.iter() {
+                let d = defs[i];
+                self.check_type_params(d);
+                let outer_type_params = self.enter_type_params(d);
+                let params: Vec<Type> = d.params().iter().map(|p| self.annotation(p.ty(), true)).collect();
+                let ret = self.annotation(d.ret(), true);
+                self.exit_type_params(outer_type_params);
+                self.bind(d.name().name(), Binding {
+                    scheme: Scheme::mono(Type::Fn(params.clone(), Box::new(ret.clone()))),
+                    annotation: None,
+                    function: Some((d.name().span(), annotation_reasons(d.params()))),
+                    item: true,
+                    bounds: Vec::new(),
+                });
+                types.push((params, ret));
+            }
+
+            for (&i, &(ref params, ref ret)) in group.iter().zip(types.iter()) {
+                self.check_body(defs[i], params, ret);
+            }
+            self.unifier.exit_level();
+
+            // Operators on generalizable types are defaulted to `Int`, since there's no way to be generic over them
+            self.solve_constraints(false);
+
+            for (&i, (params, ret)) in group.iter().zip(types.into_iter()) {
+                let d = defs[i];
+                let reasons = d.params().iter().zip(params.iter()).map(|(p, ty)| match p.ty() {
+                    Some(ty) => Some(Reason::Annotation(ty.span())),
+                    None => self.unifier.origin(ty).map(Reason::Inferred),
+                }).collect();
+                let scheme = self.unifier.generalize(&Type::Fn(params, Box::new(ret)));
+                let scheme = Scheme::new(type_param_names(d), scheme.vars().to_vec(), scheme.ty().clone());
+                self.types.insert(d.name().span(), scheme.ty().clone());
+                self.schemes.insert(d.name().span(), scheme.clone());
+                self.bind(d.name().name(), Binding {
+                    scheme,
+                    annotation: None,
+                    function: Some((d.name().span(), reasons)),
+                    item: true,
+                    bounds: d.type_params().iter().map(|p| names(p.bounds())).collect(),
+                });
+            }
+        }
+    }
+
+    /// Infers the types within the body of the function, whose parameters and result have the provided types
+    fn check_body(&mut self, d: &DefDecl, params: &[Type], ret: &Type) {
+        let outer_type_params = self.enter_type_params(d);
+        let outer_item_depth = self.item_depth;
+        self.item_depth = self.scopes.len();
+        let reason = d.ret().map(|r| Reason::Annotation(r.span()));
+        let outer_ret = self.ret.replace((ret.clone(), reason));
+        self.scopes.push(HashMap::new());
+        for (param, ty) in d.params().iter().zip(params.iter()) {
+            self.check_pattern(param.pattern(), ty, param.ty().map(|t| t.span()));
+        }
+        let body = self.check_block(d.body());
+        let body_span = d.body().result().map(|r| r.span()).unwrap_or(d.body().span());
+        self.coerce(ret, &body, body_span, reason);
+        self.scopes.pop();
+        self.ret = outer_ret;
+        self.item_depth = outer_item_depth;
+        self.exit_type_params(outer_type_params);
+    }
+
+    /// Checks the methods of an impl against the signatures in its trait, and infers the types within them
+    fn check_impl(&mut self, decl: &ImplDecl) {
+        // Impls of unknown traits, or which conflict with another impl, were reported when they were collected
+        let env = self.env;
+        let info = env.impl_at(decl.header_span());
+        let trait_info = info.and_then(|i| env.trait_info(&i.trait_name));
+
+        let mut seen: Vec<&str> = Vec::new();
+        for m in decl.methods() {
+            let name = m.name().name();
+            if seen.contains(&name) {
+                self.diags.push(Diagnostic::error(m.name().span(), format!("the method `{}` is defined more than once", name)));
+            }
+            seen.push(name);
+
+            let expected = match (info, trait_info) {
+                (Some(info), Some(t)) => match t.method(name) {
+                    Some((_, sig)) => {
+                        let mapping = Some(("Self".to_string(), info.ty.clone())).into_iter().collect();
+                        Some((sig.ty.substitute_params(&mapping), info.trait_name.as_str(), sig.span))
+                    }
+                    None => {
+                        self.diags.push(Diagnostic::error(
+                            m.name().span(),
+                            format!("method `{}` is not a member of trait `{}`", name, info.trait_name)));
+                        None
+                    }
+                },
+                _ => None,
+            };
+            self.check_method(m, expected);
+        }
+
+        if let Some(t) = trait_info {
+            let missing: Vec<_> = t.methods.iter()
+                .filter(|m| !seen.contains(&m.name.as_str()))
+                .map(|m| format!("`{}`", m.name))
+                .collect();
+            if !missing.is_empty() {
+                self.diags.push(Diagnostic::error(
+                    decl.header_span(),
+                    format!("not all trait methods are implemented, missing {}", missing.join(", "))));
+            }
+        }
+    }
+
+    /// Checks a method of an impl, which must have the type the trait gives it, along with the name of the trait and
+    /// the span of its signature there
+    fn check_method(&mut self, m: &DefDecl, expected: Option<(Type, &str, Option<TextSpan>)>) {
+        self.unifier.enter_level();
+        self.check_type_params(m);
+        let outer_type_params = self.enter_type_params(m);
+        let params: Vec<Type> = m.params().iter().map(|p| self.annotation(p.ty(), true)).collect();
+        let ret = self.annotation(m.ret(), true);
+        self.exit_type_params(outer_type_params);
+
+        let ty = Type::Fn(params.clone(), Box::new(ret.clone()));
+        if let Some((expected, trait_name, sig)) = expected {
+            if self.unifier.unify(&expected, &ty, m.name().span()).is_err() {
+                let types = self.display(&[&expected, &ty]);
+                let mut diag = Diagnostic::error(
+                    m.name().span(),
+                    format!("method `{}` has an incompatible type for trait `{}`: expected `{}`, found `{}`",
+                            m.name().name(), trait_name, types[0], types[1]));
+                if let Some(sig) = sig {
+                    diag = diag.with_label(sig, "the trait declares the method here");
+                }
+                self.diags.push(diag);
+            }
+        }
+
+        self.check_body(m, &params, &ret);
+        self.unifier.exit_level();
+        self.solve_constraints(false);
+
+        let scheme = self.unifier.generalize(&ty);
+        self.types.insert(m.name().span(), scheme.ty().clone());
+        self.schemes.insert(m.name().span(), scheme);
+    }
+
+    /// Reports type parameters of the `def` that are declared more than once, or bounded by unknown traits
+    fn check_type_params(&mut self, def: &DefDecl) {
+        let mut seen: Vec<&str> = Vec::new();
+        for p in def.type_params() {
+            let name = p.name().name();
+            if seen.contains(&name) {
+                self.diags.push(Diagnostic::error(p.name().span(), format!("the type parameter `{}` is declared more than once", name)));
+            }
+            seen.push(name);
+            for b in p.bounds() {
+                if self.env.trait_info(b.name()).is_none() {
+                    self.diags.push(Diagnostic::error(b.span(), format!("cannot find trait `{}`", b.name())));
+                }
+            }
+        }
+    }
+
+    /// Brings the type parameters of the `def` into scope, replacing any from the code around it
+    ///
+    /// ## Returns
+    /// The type parameters and bounds that were in scope before, to be restored by `exit_type_params`.
+    fn enter_type_params(&mut self, def: &DefDecl) -> (Vec<String>, HashMap<String, Vec<String>>) {
+        let bounds = def.type_params().iter().map(|p| (p.name().name().to_string(), names(p.bounds()))).collect();
+        (::std::mem::replace(&mut self.type_params, type_param_names(def)), ::std::mem::replace(&mut self.bounds, bounds))
+    }
+
+    fn exit_type_params(&mut self, outer: (Vec<String>, HashMap<String, Vec<String>>)) {
+        self.type_params = outer.0;
+        self.bounds = outer.1;
+    }
+
+    /// Resolves the annotation. If there isn't one, the type is a fresh variable to infer if `infer` is set, or
+    /// unknown otherwise.
+    fn annotation(&mut self, ty: Option<&TypeExpr>, infer: bool) -> Type {
+        match ty {
+            Some(ty) => self.env.resolve(ty, &self.type_params, &mut self.diags),
+            None if infer => self.unifier.fresh(),
+            None => Type::Unknown,
+        }
+    }
+
+    /// Resolves a type in the signature of an extern, where a C `int` or `long` is an `Int`
+    fn extern_annotation(&mut self, ty: Option<&TypeExpr>) -> Type {
+        match ty {
+            Some(ty) if is_named(ty, "CInt") || is_named(ty, "CLong") => Type::Int,
+            _ => self.annotation(ty, false),
+        }
+    }
+
+    fn check_block(&mut self, block: &Block) -> Type {
+        self.scopes.push(HashMap::new());
+        self.check_stmts(block.stmts());
+        let ty = match block.result() {
+            Some(result) => self.check_expr(result),
+            None => Type::unit(),
+        };
+        self.scopes.pop();
+        ty
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        let ty = self.infer_expr(expr);
+        self.types.insert(expr.span(), ty.clone());
+        ty
+    }
+
+    fn infer_expr(&mut self, expr: &Expr) -> Type {
+        match expr.kind() {
+            &ExprKind::Constant(ref lit) => lit_type(lit),
+            &ExprKind::Var(ref name) => {
+                let scheme = match self.lookup(name.name()) {
+                    Some((binding, true)) =>
+                        Some((binding.scheme.clone(), binding.function.as_ref().map(|f| f.0), binding.bounds.clone())),
+                    Some((_, false)) => {
+                        self.diags.push(Diagnostic::error(
+                            name.span(),
+                            format!("cannot use `{}` here, because a `def` can't refer to the local variables around it", name.name())));
+                        return Type::Unknown;
+                    }
+                    None => None,
+                };
+                if let Some((scheme, function, bounds)) = scheme {
+                    let (ty, args) = self.unifier.instantiate(&scheme);
+                    for (arg, traits) in args.iter().zip(bounds.into_iter()) {
+                        for t in traits {
+                            self.require(name.span(), Requirement::Trait(t), arg.clone());
+                        }
+                    }
+                    if let Some(decl) = function {
+                        self.uses.insert(name.span(), (decl, args));
+                    }
+                    return ty;
+                }
+                if let Some((enum_ty, fields)) = self.instantiate_variant(name.name()) {
+                    return if fields.is_empty() {
+                        enum_ty
+                    } else {
+                        Type::Fn(fields, Box::new(enum_ty))
+                    };
+                }
+                self.diags.push(Diagnostic::error(name.span(), format!("cannot find value `{}` in this scope", name.name())));
+                Type::Unknown
+            }
+            &ExprKind::Binary(ref l, ref r, op, _) => {
+                let lhs = self.check_expr(l);
+                let rhs = self.check_expr(r);
+                self.binary(expr.span(), op, (l.span(), lhs), (r.span(), rhs))
+            }
+            &ExprKind::Struct(ref name, ref fields) => {
+                let info = match self.env.struct_info(name.name()) {
+                    Some(info) => info,
+                    None => {
+                        self.diags.push(Diagnostic::error(name.span(), format!("cannot find struct `{}`", name.name())));
+                        for f in fields {
+                            self.check_expr(f.value());
+                        }
+                        return Type::Unknown;
+                    }
+                };
+
+                let args: Vec<Type> = info.params.iter().map(|_| self.unifier.fresh()).collect();
+                let mapping: HashMap<String, Type> = info.params.iter().cloned().zip(args.iter().cloned()).collect();
+                let mut initialized: Vec<&str> = Vec::new();
+                for f in fields {
+                    let value = self.check_expr(f.value());
+                    let field_name = f.name().name();
+                    if initialized.contains(&field_name) {
+                        self.diags.push(Diagnostic::error(f.name().span(), format!("field `{}` is initialized more than once", field_name)));
+                        continue;
+                    }
+                    initialized.push(field_name);
+                    match info.field(field_name) {
+                        Some(field) => {
+                            // A field whose type involves the struct's type parameters gets its type from how the
+                            // struct is used, not just the annotation
+                            let field_ty = field.ty.substitute_params(&mapping);
+                            let reason = if field_ty == field.ty { Some(Reason::Annotation(field.span)) } else { None };
+                            self.coerce(&field_ty, &value, f.value().span(), reason);
+                        }
+                        None => self.diags.push(Diagnostic::error(
+                            f.name().span(),
+                            format!("struct `{}` has no field named `{}`", name.name(), field_name))),
+                    }
+                }
+
+                let missing: Vec<_> = info.fields.iter()
+                    .filter(|f| !initialized.contains(&f.name.as_str()))
+                    .map(|f| format!("`{}`", f.name))
+                    .collect();
+                if !missing.is_empty() {
+                    self.diags.push(Diagnostic::error(
+                        expr.span(),
+                        format!("missing fields {} in initializer of `{}`", missing.join(", "), name.name())));
+                }
+                Type::Struct(name.name().into(), args)
+            }
+            &ExprKind::Field(ref target, ref name) => {
+                let target_ty = self.check_expr(target);
+                self.field_type(&target_ty, target.span(), name.name(), name.span()).0
+            }
+            &ExprKind::Assign(ref target, ref value) => {
+                let target_ty = self.check_expr(target);
+                let value_ty = self.check_expr(value);
+                let annotation = match target.kind() {
+                    &ExprKind::Var(ref name) => self.lookup(name.name()).and_then(|b| b.0.annotation),
+                    &ExprKind::Field(ref inner, ref name) => {
+                        let inner_ty = self.types.get(&inner.span()).cloned().unwrap_or(Type::Unknown);
+                        self.field_type(&inner_ty, inner.span(), name.name(), name.span()).1
+                    }
+                    _ => None,
+                };
+                self.coerce(&target_ty, &value_ty, value.span(), annotation.map(Reason::Annotation));
+                Type::unit()
+            }
+            &ExprKind::Call(ref callee, ref args) => self.call(expr.span(), callee, args),
+            &ExprKind::MethodCall(ref receiver, ref name, ref args) => self.method_call(expr.span(), receiver, name, args),
+            &ExprKind::Match(ref scrutinee, ref arms) => {
+                let scrutinee_ty = self.check_expr(scrutinee);
+                let mut result: Option<(Type, TextSpan)> = None;
+                for arm in arms {
+                    self.scopes.push(HashMap::new());
+                    self.check_pattern(arm.pattern(), &scrutinee_ty, None);
+                    if let Some(guard) = arm.guard() {
+                        let guard_ty = self.check_expr(guard);
+                        self.expect(&Type::Bool, &guard_ty, guard.span(), None);
+                    }
+                    let body_ty = self.check_expr(arm.body());
+                    self.scopes.pop();
+
+                    match result {
+                        Some((ref expected, first_span)) => {
+                            if self.unifier.unify(expected, &body_ty, arm.body().span()).is_err() {
+                                let types = self.display(&[expected, &body_ty]);
+                                self.diags.push(Diagnostic::error(
+                                    arm.body().span(),
+                                    format!("match arms have incompatible types: expected `{}`, found `{}`", types[0], types[1]))
+                                    .with_label(first_span, format!("the first arm has type `{}`", types[0])));
+                            }
+                        }
+                        None => result = Some((body_ty, arm.body().span())),
+                    }
+                }
+                match result {
+                    Some((ty, _)) => ty,
+                    None => self.unifier.fresh(),
+                }
+            }
+            &ExprKind::Tuple(ref elements) => Type::Tuple(elements.iter().map(|e| self.check_expr(e)).collect()),
+            &ExprKind::Block(ref block) => self.check_block(block),
+            &ExprKind::Return(ref value) => {
+                let found = match *value {
+                    Some(ref value) => self.check_expr(value),
+                    None => Type::unit(),
+                };
+                match self.ret.clone() {
+                    Some((ret, reason)) => {
+                        let span = value.as_ref().map_or(expr.span(), |v| v.span());
+                        self.coerce(&ret, &found, span, reason);
+                    }
+                    None => self.diags.push(Diagnostic::error(expr.span(), "`return` outside of a `def`")),
+                }
+                // Control never continues past a `return`, so it can be used as a value of any type
+                self.unifier.fresh()
+            }
+        }
+    }
+
+    fn binary(&mut self, span: TextSpan, op: BinOp, lhs: (TextSpan, Type), rhs: (TextSpan, Type)) -> Type {
+        if self.unifier.unify(&lhs.1, &rhs.1, span).is_err() {
+            self.operator_error(span, op, lhs, rhs);
+            return if op.is_comparison() { Type::Bool } else { Type::Unknown };
+        }
+
+        match op {
+            BinOp::Equal | BinOp::NotEqual => Type::Bool,
+            _ => {
+                self.require(span, Requirement::Operator(op, (lhs.0, rhs.0)), lhs.1.clone());
+                if op.is_comparison() { Type::Bool } else { lhs.1 }
+            }
+        }
+    }
+
+    /// Checks the requirement of the type, or defers it until the type is known
+    fn require(&mut self, span: TextSpan, requirement: Requirement, ty: Type) {
+        let constraint = Constraint {
+            span,
+            requirement,
+            ty,
+            bounds: self.bounds.clone(),
+        };
+        if !self.solve(&constraint, false) {
+            self.constraints.push(constraint);
+        }
+    }
+
+    /// Checks the constraint, returning `false` if the type isn't known well enough to do so yet
+    fn solve(&mut self, constraint: &Constraint, default: bool) -> bool {
+        match constraint.requirement {
+            Requirement::Operator(op, operands) => self.solve_operator(constraint, op, operands, default),
+            Requirement::Trait(ref trait_name) => self.solve_trait(constraint, trait_name, default),
+        }
+    }
+
+    /// If `default` is set, or the type is about to be generalized, an unknown type is assumed to be `Int`
+    fn solve_operator(&mut self, constraint: &Constraint, op: BinOp, operands: (TextSpan, TextSpan), default: bool) -> bool {
+        let overload = operator_trait(op);
+        match self.unifier.shallow_resolve(&constraint.ty) {
+            Type::Int | Type::Float | Type::Unknown => true,
+            Type::Str if op == BinOp::Add || op.is_comparison() => true,
+            Type::Var(v) => {
+                if default || self.unifier.is_generalizable(v) {
+                    self.unifier.unify(&Type::Var(v), &Type::Int, constraint.span).unwrap();
+                    true
+                } else {
+                    false
+                }
+            }
+            Type::Param(ref p) if overload.map_or(false, |(t, _)| bounded_by(&constraint.bounds, p, t)) => {
+                let (trait_name, method) = overload.unwrap();
+                self.dispatches.insert(constraint.span, Dispatch::Bound {
+                    param: p.clone(),
+                    trait_name: trait_name.into(),
+                    method: method.into(),
+                });
+                true
+            }
+            ty => {
+                let ty = self.unifier.resolve(&ty);
+                let mut vars = Vec::new();
+                ty.vars(&mut vars);
+                if !vars.is_empty() && !default && !vars.iter().any(|&v| self.unifier.is_generalizable(v)) {
+                    return false;
+                }
+
+                // Any other type needs an impl of the operator's trait
+                let env = self.env;
+                let method = overload.and_then(|(t, m)| env.find_impl(t, &ty).map(|i| i.methods.get(m).cloned()));
+                match method {
+                    Some(method) => {
+                        // An impl missing the method has already been reported
+                        if let Some(span) = method {
+                            self.dispatches.insert(constraint.span, Dispatch::Static(span));
+                        }
+                    }
+                    None => {
+                        let (l, r) = operands;
+                        self.operator_error(constraint.span, op, (l, ty.clone()), (r, ty));
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    /// If `default` is set, or the type is about to be generalized, there's no way left to tell whether the type
+    /// implements the trait
+    fn solve_trait(&mut self, constraint: &Constraint, trait_name: &str, default: bool) -> bool {
+        let ty = self.unifier.resolve(&constraint.ty);
+        let mut vars = Vec::new();
+        ty.vars(&mut vars);
+        if !vars.is_empty() && !default && !vars.iter().any(|&v| self.unifier.is_generalizable(v)) {
+            return false;
+        }
+
+        let satisfied = match ty {
+            Type::Unknown => true,
+            Type::Param(ref p) => bounded_by(&constraint.bounds, p, trait_name),
+            ref ty => self.env.implements(trait_name, ty),
+        };
+        if !satisfied {
+            let message = if vars.is_empty() {
+                format!("the trait `{}` is not implemented for `{}`", trait_name, self.display(&[&ty])[0])
+            } else {
+                format!("type annotations needed: cannot tell whether `{}` implements `{}`", self.display(&[&ty])[0], trait_name)
+            };
+            self.diags.push(Diagnostic::error(constraint.span, message));
+        }
+        true
+    }
+
+    fn solve_constraints(&mut self, default: bool) {
+        let constraints = ::std::mem::replace(&mut self.constraints, Vec::new());
+        for c in constraints {
+            if !self.solve(&c, default) {
+                self.constraints.push(c);
+            }
+        }
+    }
+
+    fn operator_error(&mut self, span: TextSpan, op: BinOp, lhs: (TextSpan, Type), rhs: (TextSpan, Type)) {
+        let types = self.display(&[&lhs.1, &rhs.1]);
+        let mut diag = Diagnostic::error(span, format!("cannot apply `{}` to `{}` and `{}`", op.symbol(), types[0], types[1]))
+            .with_label(lhs.0, format!("this is `{}`", types[0]))
+            .with_label(rhs.0, format!("this is `{}`", types[1]));
+
+        // Point at whatever caused the operands to have their types, if they were inferred
+        for (i, &(_, ref ty)) in [lhs, rhs].iter().enumerate() {
+            if let Some(origin) = self.unifier.origin(ty) {
+                diag = diag.with_label(origin, format!("`{}` was inferred from this", types[i]));
+            }
+        }
+        self.diags.push(diag);
+    }
+
+    fn call(&mut self, span: TextSpan, callee: &Expr, args: &[Expr]) -> Type {
+        let callee_ty = self.check_expr(callee);
+        let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+
+        // Calls to a function by name can point at its declaration, and at why its parameters have their types
+        let function = match callee.kind() {
+            &ExprKind::Var(ref name) => self.lookup(name.name()).and_then(|b| b.0.function.clone()),
+            _ => None,
+        };
+
+        match self.unifier.shallow_resolve(&callee_ty) {
+            Type::Fn(params, ret) => {
+                if params.len() != args.len() {
+                    let mut diag = Diagnostic::error(
+                        span,
+                        format!("this function takes {} argument(s), but {} were supplied", params.len(), args.len()));
+                    if let Some((decl, _)) = function {
+                        diag = diag.with_label(decl, "the function is declared here");
+                    }
+                    self.diags.push(diag);
+                }
+                for (i, (arg, arg_ty)) in args.iter().zip(arg_types.iter()).enumerate() {
+                    if let Some(param) = params.get(i) {
+                        let reason = function.as_ref().and_then(|f| f.1.get(i).cloned().and_then(|r| r));
+                        self.coerce(param, arg_ty, arg.span(), reason);
+                    }
+                }
+                *ret
+            }
+            Type::Var(var) => {
+                // An argument of the callee's own type would make it contain itself, so errors can point at it
+                let reason = args.iter().zip(arg_types.iter())
+                    .find(|&(_, ty)| self.unifier.resolve(ty).contains(var))
+                    .map(|(arg, _)| Reason::Inferred(arg.span()));
+                let ret = self.unifier.fresh();
+                let fn_ty = Type::Fn(arg_types, Box::new(ret.clone()));
+                self.expect(&callee_ty, &fn_ty, span, reason);
+                ret
+            }
+            Type::Unknown => Type::Unknown,
+            t => {
+                let t = self.display(&[&t]).remove(0);
+                self.diags.push(Diagnostic::error(callee.span(), format!("`{}` is not a function", t)));
+                Type::Unknown
+            }
+        }
+    }
+
+    fn method_call(&mut self, span: TextSpan, receiver: &Expr, name: &Ident, args: &[Expr]) -> Type {
+        let receiver_ty = self.check_expr(receiver);
+        let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+        let method = name.name();
+
+        let env = self.env;
+        let resolved = self.unifier.resolve(&receiver_ty);
+        let candidates: Vec<(&str, Dispatch)> = match resolved {
+            Type::Unknown => return Type::Unknown,
+            Type::Var(_) => {
+                self.diags.push(Diagnostic::error(
+                    receiver.span(),
+                    format!("the type of this value must be known to call method `{}`", method))
+                    .with_label(name.span(), "add a type annotation so this method can be found"));
+                return Type::Unknown;
+            }
+            Type::Param(ref p) => self.bounds.get(p).map(|b| b.as_slice()).unwrap_or(&[]).iter()
+                .filter(|t| env.trait_info(t).map_or(false, |t| t.method(method).is_some()))
+                .map(|t| (t.as_str(), Dispatch::Bound {
+                    param: p.clone(),
+                    trait_name: t.clone(),
+                    method: method.into(),
+                }))
+                .collect(),
+            Type::Dyn(ref t) => env.trait_info(t).and_then(|info| info.method(method))
+                .map(|(slot, _)| (t.as_str(), Dispatch::Dynamic { trait_name: t.clone(), slot }))
+                .into_iter()
+                .collect(),
+            ref ty => env.impls_with_method(ty, method).into_iter()
+                .map(|i| (i.trait_name.as_str(), Dispatch::Static(i.methods[method])))
+                .collect(),
+        };
+
+        let (trait_name, dispatch) = match candidates.len() {
+            0 => {
+                let ty = self.display(&[&resolved]).remove(0);
+                self.diags.push(Diagnostic::error(name.span(), format!("no method named `{}` found for `{}`", method, ty)));
+                return Type::Unknown;
+            }
+            1 => candidates.into_iter().next().unwrap(),
+            _ => {
+                let traits: Vec<_> = candidates.iter().map(|&(t, _)| format!("`{}`", t)).collect();
+                self.diags.push(Diagnostic::error(
+                    name.span(),
+                    format!("multiple traits have a method named `{}`: {}", method, traits.join(", "))));
+                return Type::Unknown;
+            }
+        };
+        self.dispatches.insert(name.span(), dispatch);
+
+        let sig = env.trait_info(trait_name).unwrap().method(method).unwrap().1;
+        let mapping = Some(("Self".to_string(), resolved.clone())).into_iter().collect();
+        let (params, ret) = match sig.ty.substitute_params(&mapping) {
+            Type::Fn(params, ret) => (params, *ret),
+            _ => unreachable!("Expected a method to have a function type"),
+        };
+
+        // The first parameter is `self`, which is the receiver
+        if params.len() != args.len() + 1 {
+            let mut diag = Diagnostic::error(
+                span,
+                format!("this method takes {} argument(s), but {} were supplied", params.len() - 1, args.len()));
+            if let Some(sig_span) = sig.span {
+                diag = diag.with_label(sig_span, "the method is declared here");
+            }
+            self.diags.push(diag);
+        }
+        for (param, (arg, arg_ty)) in params.iter().skip(1).zip(args.iter().zip(arg_types.iter())) {
+            self.coerce(param, arg_ty, arg.span(), None);
+        }
+        ret
+    }
+
+    /// Gets the type of the named field, along with the span of its annotation
+    fn field_type(&mut self, target: &Type, target_span: TextSpan, name: &str, span: TextSpan) -> (Type, Option<TextSpan>) {
+        let (struct_name, args) = match self.unifier.shallow_resolve(target) {
+            Type::Unknown => return (Type::Unknown, None),
+            Type::Struct(struct_name, args) => (struct_name, args),
+            Type::Var(_) => {
+                // Without knowing the type, the only hope is that there's just one struct with a field of this name
+                let candidates = self.env.structs_with_field(name);
+                if candidates.len() != 1 {
+                    self.diags.push(Diagnostic::error(
+                        target_span,
+                        format!("the type of this value must be known to access field `{}`", name))
+                        .with_label(span, "add a type annotation so this field can be found"));
+                    return (Type::Unknown, None);
+                }
+                let struct_name = candidates[0].to_string();
+                let params = self.env.struct_info(&struct_name).unwrap().params.len();
+                let args: Vec<Type> = (0..params).map(|_| self.unifier.fresh()).collect();
+                self.unifier.unify(target, &Type::Struct(struct_name.clone(), args.clone()), target_span).unwrap();
+                (struct_name, args)
+            }
+            t => {
+                let t = self.display(&[&t]).remove(0);
+                self.diags.push(Diagnostic::error(span, format!("type `{}` has no fields", t)));
+                return (Type::Unknown, None);
+            }
+        };
+
+        let info = self.env.struct_info(&struct_name).unwrap();
+        match info.field(name) {
+            Some(field) => {
+                let mapping = info.params.iter().cloned().zip(args.into_iter()).collect();
+                (field.ty.substitute_params(&mapping), Some(field.span))
+            }
+            None => {
+                self.diags.push(Diagnostic::error(span, format!("struct `{}` has no field named `{}`", struct_name, name)));
+                (Type::Unknown, None)
+            }
+        }
+    }
+
+    /// Checks that the pattern can match values of the expected type, and binds the names within it
+    fn check_pattern(&mut self, pattern: &Pattern, expected: &Type, annotation: Option<TextSpan>) {
+        self.types.insert(pattern.span(), expected.clone());
+        let reason = annotation.map(Reason::Annotation);
+        match pattern.kind() {
+            &PatternKind::Wildcard => {}
+            &PatternKind::Binding(ref name) => {
+                if let Some((found, fields)) = self.instantiate_variant(name.name()) {
+                    if fields.is_empty() {
+                        self.expect(expected, &found, pattern.span(), reason);
+                        return;
+                    }
+                }
+                self.bind(name.name(), Binding::local(expected.clone(), annotation));
+            }
+            &PatternKind::Literal(ref lit) => {
+                self.expect(expected, &lit_type(lit), pattern.span(), reason);
+            }
+            &PatternKind::Tuple(ref elements) => {
+                let element_types: Vec<Type> = match self.unifier.shallow_resolve(expected) {
+                    Type::Tuple(ref types) if types.len() == elements.len() => types.clone(),
+                    _ => {
+                        let types: Vec<Type> = elements.iter().map(|_| self.unifier.fresh()).collect();
+                        if !self.expect(expected, &Type::Tuple(types.clone()), pattern.span(), reason) {
+                            // Don't report errors about the elements too
+                            self.unifier.unify(&Type::Tuple(types.clone()), &Type::Unknown, pattern.span()).unwrap();
+                            return;
+                        }
+                        types
+                    }
+                };
+                for (e, t) in elements.iter().zip(element_types.iter()) {
+                    self.check_pattern(e, t, None);
+                }
+            }
+            &PatternKind::Constructor(ref name, ref fields) => {
+                // Unknown variants and variants with the wrong number of fields are reported when checking patterns
+                let field_types = match self.instantiate_variant(name.name()) {
+                    Some((found, ref field_types)) if field_types.len() == fields.len() => {
+                        self.expect(expected, &found, pattern.span(), reason);
+                        field_types.clone()
+                    }
+                    _ => vec![Type::Unknown; fields.len()],
+                };
+                for (f, t) in fields.iter().zip(field_types.iter()) {
+                    self.check_pattern(f, t, None);
+                }
+            }
+        }
+    }
+
+    /// Unifies the types, reporting an error if a value of type `found` can't be used where `expected` is required
+    fn expect(&mut self, expected: &Type, found: &Type, span: TextSpan, reason: Option<Reason>) -> bool {
+        let error = match self.unifier.unify(expected, found, span) {
+            Ok(()) => return true,
+            Err(e) => e,
+        };
+
+        let diag = match error {
+            UnifyError::Mismatch => {
+                let types = self.display(&[expected, found]);
+                let mut diag = Diagnostic::error(span, format!("mismatched types: expected `{}`, found `{}`", types[0], types[1]));
+                match reason.or_else(|| self.unifier.origin(expected).map(Reason::Inferred)) {
+                    Some(Reason::Annotation(because)) =>
+                        diag = diag.with_label(because, format!("expected `{}` because of this annotation", types[0])),
+                    Some(Reason::Inferred(because)) if because != span =>
+                        diag = diag.with_label(because, format!("`{}` was inferred from this", types[0])),
+                    _ => {}
+                }
+                match self.unifier.origin(found) {
+                    Some(because) if because != span =>
+                        diag.with_label(because, format!("`{}` was inferred from this", types[1])),
+                    _ => diag,
+                }
+            }
+            UnifyError::Infinite(var, ty) => {
+                let types = normalize(&[&Type::Var(var), &ty]);
+                let diag = Diagnostic::error(
+                    span,
+                    format!("cannot construct the infinite type `{} = {}`", types[0], types[1]));
+                match reason.or_else(|| self.unifier.origin(&ty).map(Reason::Inferred)) {
+                    Some(Reason::Annotation(because)) | Some(Reason::Inferred(because)) if because != span =>
+                        diag.with_label(because, format!("`{}` is used here too", types[0])),
+                    _ => diag,
+                }
+            }
+        };
+        self.diags.push(diag);
+        false
+    }
+
+    /// Like `expect`, except that a value whose type implements a trait can also be used where a `dyn` value of the
+    /// trait is expected, in which case the conversion is recorded so a vtable can be built for it
+    fn coerce(&mut self, expected: &Type, found: &Type, span: TextSpan, reason: Option<Reason>) -> bool {
+        let trait_name = match self.unifier.shallow_resolve(expected) {
+            Type::Dyn(trait_name) => trait_name,
+            _ => return self.expect(expected, found, span, reason),
+        };
+        let found = match self.unifier.resolve(found) {
+            Type::Dyn(_) | Type::Var(_) | Type::Unknown => return self.expect(expected, found, span, reason),
+            found => found,
+        };
+
+        if self.env.implements(&trait_name, &found) {
+            self.coercions.insert(span, trait_name);
+            return true;
+        }
+        let found = self.display(&[&found]).remove(0);
+        let mut diag = Diagnostic::error(span, format!("the trait `{}` is not implemented for `{}`", trait_name, found));
+        if let Some(Reason::Annotation(because)) = reason {
+            diag = diag.with_label(because, format!("expected `dyn {}` because of this annotation", trait_name));
+        }
+        self.diags.push(diag);
+        false
+    }
+
+    /// Resolves the types as far as possible, with their variables named consistently
+    fn display(&self, types: &[&Type]) -> Vec<Type> {
+        let resolved: Vec<Type> = types.iter().map(|t| self.unifier.resolve(t)).collect();
+        normalize(&resolved.iter().collect::<Vec<_>>())
+    }
+
+    /// Gets the type of the enum the named variant belongs to, and the types of the variant's fields, with fresh
+    /// variables for the enum's type parameters
+    fn instantiate_variant(&mut self, name: &str) -> Option<(Type, Vec<Type>)> {
+        let variant = match self.env.variant(name) {
+            Some(v) => v,
+            None => return None,
+        };
+        let params = &self.env.enum_info(&variant.enum_name).unwrap().params;
+        let args: Vec<Type> = params.iter().map(|_| self.unifier.fresh()).collect();
+        let mapping = params.iter().cloned().zip(args.iter().cloned()).collect();
+        let fields = variant.fields.iter().map(|f| f.substitute_params(&mapping)).collect();
+        Some((Type::Enum(variant.enum_name.clone(), args), fields))
+    }
+
+    fn bind(&mut self, name: &str, binding: Binding) {
+        self.scopes.last_mut().unwrap().insert(name.into(), binding);
+    }
+
+    /// Finds the binding with the name, and whether it can be used from the current scope
+    fn lookup(&self, name: &str) -> Option<(&Binding, bool)> {
+        self.scopes.iter().enumerate().rev()
+            .filter_map(|(depth, s)| s.get(name).map(|b| (b, b.item || depth >= self.item_depth)))
+            .next()
+    }
+}
+
+fn type_param_names(def: &DefDecl) -> Vec<String> {
+    def.type_params().iter().map(|p| p.name().name().to_string()).collect()
+}
+
+fn names(idents: &[Ident]) -> Vec<String> {
+    idents.iter().map(|i| i.name().to_string()).collect()
+}
+
+/// Returns `true` if the type parameter is bounded by the trait
+/// Checks whether a type annotation is the type named `name`, without arguments
+fn is_named(ty: &TypeExpr, name: &str) -> bool {
+    match *ty.kind() {
+        TypeExprKind::Named(ref n, ref args) => n.name() == name && args.is_empty(),
+        _ => false,
+    }
+}
+
+fn bounded_by(bounds: &HashMap<String, Vec<String>>, param: &str, trait_name: &str) -> bool {
+    bounds.get(param).map_or(false, |b| b.iter().any(|t| t == trait_name))
+}
+
+fn annotation_reasons(params: &[Param]) -> Vec<Option<Reason>> {
+    params.iter().map(|p| p.ty().map(|t| Reason::Annotation(t.span()))).collect()
+}
+
+fn lit_type(lit: &Lit) -> Type {
+    match lit {
+        &Lit::Int(_) => Type::Int,
+        &Lit::Float(_) => Type::Float,
+        &Lit::Bool(_) => Type::Bool,
+        &Lit::Str(_) => Type::Str,
+    }
+}
+
+/// Collects every name used as a value, including names that are shadowed
+struct NameCollector<'ast> {
+    names: Vec<&'ast str>,
+}
+
+impl<'ast> Visitor<'ast> for NameCollector<'ast> {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        if let &ExprKind::Var(ref name) = expr.kind() {
+            self.names.push(name.name());
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// Splits the graph into its strongly connected components using Tarjan's algorithm. Components are returned so that
+/// every component comes after the components it has edges to.
+fn strongly_connected(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        edges: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    fn visit(s: &mut State, v: usize) {
+        s.index[v] = Some(s.next);
+        s.low[v] = s.next;
+        s.next += 1;
+        s.stack.push(v);
+        s.on_stack[v] = true;
+
+        for &w in s.edges[v].iter() {
+            match s.index[w] {
+                None => {
+                    visit(s, w);
+                    s.low[v] = s.low[v].min(s.low[w]);
+                }
+                Some(i) if s.on_stack[w] => s.low[v] = s.low[v].min(i),
+                Some(_) => {}
+            }
+        }
+
+        if Some(s.low[v]) == s.index[v] {
+            let mut component = Vec::new();
+            loop {
+                let w = s.stack.pop().unwrap();
+                s.on_stack[w] = false;
+                component.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            component.sort();
+            s.components.push(component);
+        }
+    }
+
+    let mut state = State {
+        edges,
+        index: vec![None; edges.len()],
+        low: vec![0; edges.len()],
+        on_stack: vec![false; edges.len()],
+        stack: Vec::new(),
+        next: 0,
+        components: Vec::new(),
+    };
+    for v in 0..edges.len() {
+        if state.index[v].is_none() {
+            visit(&mut state, v);
+        }
+    }
+    state.components
+}
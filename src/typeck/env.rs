@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+
+use text::TextSpan;
+use diagnostics::Diagnostic;
+use parser::{BinOp, Decl, EnumDecl, Ident, ImplDecl, Param, PatternKind, Program, StructDecl, TraitDecl, TypeExpr, TypeExprKind};
+use parser::visit::{self, Visitor};
+use typeck::Type;
+
+pub struct FieldInfo {
+    pub name: String,
+    pub ty: Type,
+    pub span: TextSpan,
+}
+
+pub struct StructInfo {
+    /// The names of the struct's type parameters, which may appear in the types of its fields
+    pub params: Vec<String>,
+    pub fields: Vec<FieldInfo>,
+}
+
+impl StructInfo {
+    pub fn field(&self, name: &str) -> Option<&FieldInfo> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+pub struct EnumInfo {
+    pub params: Vec<String>,
+    pub span: TextSpan,
+}
+
+pub struct VariantInfo {
+    pub enum_name: String,
+
+    /// The types of the variant's fields, which may refer to the type parameters of its enum
+    pub fields: Vec<Type>,
+    pub span: TextSpan,
+}
+
+pub struct MethodInfo {
+    pub name: String,
+
+    /// The type of the method as a function taking `self` first, in which the type implementing the trait is the
+    /// type parameter `Self`
+    pub ty: Type,
+
+    /// The span of the method's signature, or `None` if the trait is built in
+    pub span: Option<TextSpan>,
+}
+
+pub struct TraitInfo {
+    pub methods: Vec<MethodInfo>,
+}
+
+impl TraitInfo {
+    /// Gets the named method, along with its position in the trait, which is also its position in the vtables of
+    /// `dyn` values
+    pub fn method(&self, name: &str) -> Option<(usize, &MethodInfo)> {
+        self.methods.iter().enumerate().find(|&(_, m)| m.name == name)
+    }
+}
+
+pub struct ImplInfo {
+    pub trait_name: String,
+    pub ty: Type,
+
+    /// The spans of the names of the methods in the impl
+    pub methods: HashMap<String, TextSpan>,
+
+    /// The span of the impl's header: `impl Show for Point`
+    pub span: TextSpan,
+}
+
+/// The traits that overload the arithmetic operators, along with the method each operator calls
+const OPERATOR_TRAITS: &'static [(BinOp, &'static str, &'static str)] = &[
+    (BinOp::Add, "Add", "add"),
+    (BinOp::Subtract, "Sub", "sub"),
+    (BinOp::Multiply, "Mul", "mul"),
+    (BinOp::Divide, "Div", "div"),
+];
+
+/// Gets the trait that overloads the operator, and the name of its method, if the operator can be overloaded
+pub fn operator_trait(op: BinOp) -> Option<(&'static str, &'static str)> {
+    OPERATOR_TRAITS.iter().find(|&&(o, _, _)| o == op).map(|&(_, t, m)| (t, m))
+}
+
+/// The types and traits declared in a program, the variants of its enums and the impls of its traits
+pub struct TypeEnv {
+    structs: HashMap<String, StructInfo>,
+    enums: HashMap<String, EnumInfo>,
+    variants: HashMap<String, VariantInfo>,
+    traits: HashMap<String, TraitInfo>,
+    impls: Vec<ImplInfo>,
+}
+
+impl TypeEnv {
+    /// Collects all the structs, enums, traits and impls declared anywhere in the program
+    pub fn collect(program: &Program, diags: &mut Vec<Diagnostic>) -> TypeEnv {
+        let mut decls = DeclCollector {
+            structs: Vec::new(),
+            enums: Vec::new(),
+            traits: Vec::new(),
+            impls: Vec::new(),
+        };
+        visit::walk_program(&mut decls, program);
+
+        // Register every name first, so that types can refer to each other in any order
+        let mut env = TypeEnv {
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            variants: HashMap::new(),
+            traits: HashMap::new(),
+            impls: Vec::new(),
+        };
+        for &(_, trait_name, method) in OPERATOR_TRAITS {
+            // `def method(self, other: Self) -> Self`
+            let this = Type::Param("Self".into());
+            env.traits.insert(trait_name.into(), TraitInfo {
+                methods: vec![MethodInfo {
+                    name: method.into(),
+                    ty: Type::Fn(vec![this.clone(), this.clone()], Box::new(this)),
+                    span: None,
+                }],
+            });
+        }
+        for t in decls.traits.iter() {
+            env.traits.insert(t.name().name().into(), TraitInfo { methods: Vec::new() });
+        }
+        for s in decls.structs.iter() {
+            env.structs.insert(s.name().name().into(), StructInfo {
+                params: names(s.type_params()),
+                fields: Vec::new(),
+            });
+        }
+        for e in decls.enums.iter() {
+            env.enums.insert(e.name().name().into(), EnumInfo {
+                params: names(e.type_params()),
+                span: e.name().span(),
+            });
+        }
+
+        for s in decls.structs.iter() {
+            let params = names(s.type_params());
+            let fields = s.fields().iter().map(|f| FieldInfo {
+                name: f.name().name().into(),
+                ty: env.resolve(f.ty(), &params, diags),
+                span: f.ty().span(),
+            }).collect();
+            env.structs.get_mut(s.name().name()).unwrap().fields = fields;
+        }
+        for e in decls.enums.iter() {
+            let params = names(e.type_params());
+            for v in e.variants() {
+                let info = VariantInfo {
+                    enum_name: e.name().name().into(),
+                    fields: v.fields().iter().map(|f| env.resolve(f, &params, diags)).collect(),
+                    span: v.span(),
+                };
+                // Duplicate variants are reported when checking patterns
+                env.variants.entry(v.name().name().into()).or_insert(info);
+            }
+        }
+        for t in decls.traits.iter() {
+            let methods = t.methods().iter().map(|m| env.method_info(m.name(), m.params(), m.ret(), m.span(), diags)).collect();
+            env.traits.get_mut(t.name().name()).unwrap().methods = methods;
+        }
+        for i in decls.impls.iter() {
+            env.add_impl(i, diags);
+        }
+        env
+    }
+
+    fn method_info(&self, name: &Ident, params: &[Param], ret: Option<&TypeExpr>, span: TextSpan,
+                   diags: &mut Vec<Diagnostic>) -> MethodInfo {
+        let has_self = match params.first().map(|p| p.pattern().kind()) {
+            Some(&PatternKind::Binding(ref n)) => n.name() == "self",
+            _ => false,
+        };
+        if !has_self {
+            diags.push(Diagnostic::error(name.span(), format!("the first parameter of method `{}` must be `self`", name.name())));
+        }
+
+        // There's no body to infer from, so like an extern, unannotated parts of the signature stay unknown
+        let this = vec!["Self".to_string()];
+        let param_types = params.iter().enumerate().map(|(i, p)| match p.ty() {
+            Some(ty) => self.resolve(ty, &this, diags),
+            None if i == 0 && has_self => Type::Param("Self".into()),
+            None => Type::Unknown,
+        }).collect();
+        let ret = ret.map(|r| self.resolve(r, &this, diags)).unwrap_or(Type::Unknown);
+        MethodInfo {
+            name: name.name().into(),
+            ty: Type::Fn(param_types, Box::new(ret)),
+            span: Some(span),
+        }
+    }
+
+    fn add_impl(&mut self, decl: &ImplDecl, diags: &mut Vec<Diagnostic>) {
+        let trait_name = decl.trait_name();
+        if !self.traits.contains_key(trait_name.name()) {
+            diags.push(Diagnostic::error(trait_name.span(), format!("cannot find trait `{}`", trait_name.name())));
+            return;
+        }
+        let ty = self.resolve(decl.ty(), &[], diags);
+        if ty == Type::Unknown {
+            return;
+        }
+        if self.implements(trait_name.name(), &ty) {
+            let mut diag = Diagnostic::error(
+                decl.header_span(),
+                format!("conflicting implementations of trait `{}` for type `{}`", trait_name.name(), ty));
+            if let Some(first) = self.find_impl(trait_name.name(), &ty) {
+                diag = diag.with_label(first.span, "the first implementation is here");
+            }
+            diags.push(diag);
+            return;
+        }
+
+        let mut methods = HashMap::new();
+        for m in decl.methods() {
+            // Methods declared more than once are reported when checking the impl
+            methods.entry(m.name().name().into()).or_insert(m.name().span());
+        }
+        self.impls.push(ImplInfo {
+            trait_name: trait_name.name().into(),
+            ty,
+            methods,
+            span: decl.header_span(),
+        });
+    }
+
+    pub fn struct_info(&self, name: &str) -> Option<&StructInfo> {
+        self.structs.get(name)
+    }
+
+    /// Gets the names of the structs that have a field with the provided name, in alphabetical order
+    pub fn structs_with_field(&self, field: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = self.structs.iter()
+            .filter(|&(_, s)| s.field(field).is_some())
+            .map(|(n, _)| n.as_str())
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn enum_info(&self, name: &str) -> Option<&EnumInfo> {
+        self.enums.get(name)
+    }
+
+    pub fn variant(&self, name: &str) -> Option<&VariantInfo> {
+        self.variants.get(name)
+    }
+
+    pub fn trait_info(&self, name: &str) -> Option<&TraitInfo> {
+        self.traits.get(name)
+    }
+
+    /// Gets the impl of the trait for exactly the provided type
+    pub fn find_impl(&self, trait_name: &str, ty: &Type) -> Option<&ImplInfo> {
+        self.impls.iter().find(|i| i.trait_name == trait_name && &i.ty == ty)
+    }
+
+    /// Gets the impl whose header is at the span
+    pub fn impl_at(&self, span: TextSpan) -> Option<&ImplInfo> {
+        self.impls.iter().find(|i| i.span == span)
+    }
+
+    /// Gets the impl that declares the method whose name is at the span
+    pub fn impl_of_method(&self, span: TextSpan) -> Option<&ImplInfo> {
+        self.impls.iter().find(|i| i.methods.values().any(|&m| m == span))
+    }
+
+    /// Gets the impls for the type that have a method with the provided name, in the order they were declared
+    pub fn impls_with_method(&self, ty: &Type, name: &str) -> Vec<&ImplInfo> {
+        self.impls.iter().filter(|i| &i.ty == ty && i.methods.contains_key(name)).collect()
+    }
+
+    /// Returns `true` if the type implements the trait. A `dyn` value implements the trait it's named after, and the
+    /// built-in types implement the traits of the operators that apply to them.
+    pub fn implements(&self, trait_name: &str, ty: &Type) -> bool {
+        match ty {
+            &Type::Dyn(ref t) => t == trait_name,
+            &Type::Int | &Type::Float if OPERATOR_TRAITS.iter().any(|&(_, t, _)| t == trait_name) => true,
+            &Type::Str if trait_name == "Add" => true,
+            ty => self.find_impl(trait_name, ty).is_some(),
+        }
+    }
+
+    /// Converts a type annotation into the type it refers to, where `params` are the names of the type parameters in
+    /// scope
+    pub fn resolve(&self, ty: &TypeExpr, params: &[String], diags: &mut Vec<Diagnostic>) -> Type {
+        let (name, args) = match ty.kind() {
+            &TypeExprKind::Named(ref name, ref args) => (name, args),
+            &TypeExprKind::Tuple(ref elements) =>
+                return Type::Tuple(elements.iter().map(|e| self.resolve(e, params, diags)).collect()),
+            &TypeExprKind::Dyn(ref trait_name) => {
+                if !self.traits.contains_key(trait_name.name()) {
+                    diags.push(Diagnostic::error(trait_name.span(), format!("cannot find trait `{}`", trait_name.name())));
+                    return Type::Unknown;
+                }
+                return Type::Dyn(trait_name.name().into());
+            }
+        };
+        let args: Vec<Type> = args.iter().map(|a| self.resolve(a, params, diags)).collect();
+
+        let (expected_args, resolved) = match name.name() {
+            n if params.iter().any(|p| p == n) => (0, Type::Param(n.into())),
+            "Int" => (0, Type::Int),
+            "Float" => (0, Type::Float),
+            "Bool" => (0, Type::Bool),
+            "Str" => (0, Type::Str),
+            // Externs read them themselves, since they're only what C calls an `int` and a `long`
+            n @ "CInt" | n @ "CLong" => {
+                let message = format!("`{}` can only be used in the signature of an `extern`", n);
+                diags.push(Diagnostic::error(name.span(), message));
+                return Type::Unknown;
+            }
+            n if self.structs.contains_key(n) => (self.structs[n].params.len(), Type::Struct(n.into(), args.clone())),
+            n if self.enums.contains_key(n) => (self.enums[n].params.len(), Type::Enum(n.into(), args.clone())),
+            n => {
+                diags.push(Diagnostic::error(name.span(), format!("cannot find type `{}`", n)));
+                return Type::Unknown;
+            }
+        };
+
+        if args.len() != expected_args {
+            diags.push(Diagnostic::error(
+                ty.span(),
+                format!("wrong number of type arguments for `{}`: expected {}, found {}", name.name(), expected_args, args.len())));
+            return match resolved {
+                Type::Struct(n, _) => Type::Struct(n, vec![Type::Unknown; expected_args]),
+                Type::Enum(n, _) => Type::Enum(n, vec![Type::Unknown; expected_args]),
+                t => t,
+            };
+        }
+        resolved
+    }
+}
+
+fn names(idents: &[Ident]) -> Vec<String> {
+    idents.iter().map(|i| i.name().to_string()).collect()
+}
+
+struct DeclCollector<'a> {
+    structs: Vec<&'a StructDecl>,
+    enums: Vec<&'a EnumDecl>,
+    traits: Vec<&'a TraitDecl>,
+    impls: Vec<&'a ImplDecl>,
+}
+
+impl<'a> Visitor<'a> for DeclCollector<'a> {
+    fn visit_decl(&mut self, decl: &'a Decl) {
+        match decl {
+            &Decl::Struct(ref s) => self.structs.push(s),
+            &Decl::Enum(ref e) => self.enums.push(e),
+            &Decl::Trait(ref t) => self.traits.push(t),
+            &Decl::Impl(ref i) => self.impls.push(i),
+            _ => {}
+        }
+        visit::walk_decl(self, decl);
+    }
+}
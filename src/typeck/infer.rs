@@ -0,0 +1,244 @@
+use text::TextSpan;
+use typeck::{Scheme, Type, TypeVar};
+
+/// The reason two types couldn't be unified
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum UnifyError {
+    /// The types have different shapes, like `Int` and `Str`
+    Mismatch,
+
+    /// Unifying would require the variable to contain itself, like `'a = ('a, Int)`
+    Infinite(TypeVar, Type),
+}
+
+struct VarInfo {
+    binding: Option<Type>,
+    level: u32,
+
+    // The span that caused the variable to be bound, so errors can point at where a type was inferred from
+    origin: Option<TextSpan>,
+}
+
+/// Solves equations between types containing type variables
+///
+/// Variables are created at the current level, which is raised while inferring the body of a `def`. When the body is
+/// done, any variable still at a higher level than the surrounding code doesn't appear anywhere outside the `def`, so
+/// it can be generalized.
+pub struct Unifier {
+    vars: Vec<VarInfo>,
+    level: u32,
+}
+
+impl Unifier {
+    pub fn new() -> Unifier {
+        Unifier {
+            vars: Vec::new(),
+            level: 0,
+        }
+    }
+
+    pub fn fresh(&mut self) -> Type {
+        self.vars.push(VarInfo {
+            binding: None,
+            level: self.level,
+            origin: None,
+        });
+        Type::Var(TypeVar(self.vars.len() as u32 - 1))
+    }
+
+    pub fn enter_level(&mut self) {
+        self.level += 1;
+    }
+
+    pub fn exit_level(&mut self) {
+        self.level -= 1;
+    }
+
+    /// Returns `true` if the variable is unbound and was created at a deeper level than the current one
+    pub fn is_generalizable(&self, var: TypeVar) -> bool {
+        let info = &self.vars[var.0 as usize];
+        info.binding.is_none() && info.level > self.level
+    }
+
+    /// Makes the two types equal, binding variables in either of them as needed
+    ///
+    /// `span` is recorded as the origin of any variable that gets bound.
+    pub fn unify(&mut self, a: &Type, b: &Type, span: TextSpan) -> Result<(), UnifyError> {
+        let a = self.shallow_resolve(a);
+        let b = self.shallow_resolve(b);
+        match (&a, &b) {
+            (&Type::Unknown, _) | (_, &Type::Unknown) => Ok(()),
+            (&Type::Var(x), &Type::Var(y)) if x == y => Ok(()),
+            (&Type::Var(x), t) | (t, &Type::Var(x)) => self.bind(x, t, span),
+            (&Type::Tuple(ref xs), &Type::Tuple(ref ys)) if xs.len() == ys.len() => {
+                for (x, y) in xs.iter().zip(ys.iter()) {
+                    self.unify(x, y, span)?;
+                }
+                Ok(())
+            }
+            (&Type::Fn(ref xs, ref x_ret), &Type::Fn(ref ys, ref y_ret)) if xs.len() == ys.len() => {
+                for (x, y) in xs.iter().zip(ys.iter()) {
+                    self.unify(x, y, span)?;
+                }
+                self.unify(x_ret, y_ret, span)
+            }
+            (&Type::Struct(ref x, ref xs), &Type::Struct(ref y, ref ys)) |
+            (&Type::Enum(ref x, ref xs), &Type::Enum(ref y, ref ys)) if x == y && xs.len() == ys.len() => {
+                for (x, y) in xs.iter().zip(ys.iter()) {
+                    self.unify(x, y, span)?;
+                }
+                Ok(())
+            }
+            (a, b) if a == b => Ok(()),
+            _ => Err(UnifyError::Mismatch),
+        }
+    }
+
+    fn bind(&mut self, var: TypeVar, ty: &Type, span: TextSpan) -> Result<(), UnifyError> {
+        let ty = self.resolve(ty);
+        if ty.contains(var) {
+            return Err(UnifyError::Infinite(var, ty));
+        }
+
+        // Anything the variable is bound to is now visible wherever the variable is
+        let level = self.vars[var.0 as usize].level;
+        self.lower_levels(&ty, level);
+
+        let info = &mut self.vars[var.0 as usize];
+        info.binding = Some(ty);
+        info.origin = Some(span);
+        Ok(())
+    }
+
+    fn lower_levels(&mut self, ty: &Type, level: u32) {
+        let mut vars = Vec::new();
+        ty.vars(&mut vars);
+        for v in vars {
+            let info = &mut self.vars[v.0 as usize];
+            if info.level > level {
+                info.level = level;
+            }
+        }
+    }
+
+    /// Follows bindings until the outermost part of the type is not a bound variable
+    pub fn shallow_resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        loop {
+            ty = match ty {
+                Type::Var(v) => match self.vars[v.0 as usize].binding {
+                    Some(ref bound) => bound.clone(),
+                    None => return ty,
+                },
+                _ => return ty,
+            }
+        }
+    }
+
+    /// Replaces every bound variable in the type with what it's bound to
+    pub fn resolve(&self, ty: &Type) -> Type {
+        self.shallow_resolve(ty).map_children(|c| self.resolve(c))
+    }
+
+    /// Gets the span that caused the type to be inferred, if it was inferred rather than written down
+    pub fn origin(&self, ty: &Type) -> Option<TextSpan> {
+        let mut ty = ty.clone();
+        while let Type::Var(v) = ty {
+            let info = &self.vars[v.0 as usize];
+            if info.origin.is_some() {
+                return info.origin;
+            }
+            ty = match info.binding {
+                Some(ref bound) => bound.clone(),
+                None => return None,
+            };
+        }
+        None
+    }
+
+    /// Quantifies over every variable in the type that isn't visible outside of the current level
+    pub fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.resolve(ty);
+        let mut vars = Vec::new();
+        ty.vars(&mut vars);
+        vars.retain(|&v| self.is_generalizable(v));
+        Scheme::new(Vec::new(), vars, ty)
+    }
+
+    /// Replaces the type parameters and quantified variables of the scheme with fresh variables
+    ///
+    /// ## Returns
+    /// The instantiated type, and the variables that were substituted in the order `Scheme::apply` takes them.
+    pub fn instantiate(&mut self, scheme: &Scheme) -> (Type, Vec<Type>) {
+        let args: Vec<Type> = (0..scheme.params().len() + scheme.vars().len()).map(|_| self.fresh()).collect();
+        (scheme.apply(&args), args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use text::TextSpan;
+    use typeck::{Type, TypeVar};
+    use typeck::infer::{Unifier, UnifyError};
+
+    fn span() -> TextSpan {
+        TextSpan::new(0, 0)
+    }
+
+    #[test]
+    pub fn unify_binds_variables() {
+        let mut u = Unifier::new();
+        let a = u.fresh();
+        let b = u.fresh();
+        u.unify(&Type::Tuple(vec![a.clone(), Type::Int]), &Type::Tuple(vec![Type::Str, b.clone()]), span()).unwrap();
+        assert_eq!(Type::Str, u.resolve(&a));
+        assert_eq!(Type::Int, u.resolve(&b));
+    }
+
+    #[test]
+    pub fn unify_rejects_different_shapes() {
+        let mut u = Unifier::new();
+        assert_eq!(Err(UnifyError::Mismatch), u.unify(&Type::Int, &Type::Float, span()));
+        assert_eq!(Err(UnifyError::Mismatch), u.unify(&Type::Tuple(vec![Type::Int]), &Type::unit(), span()));
+        assert_eq!(Ok(()), u.unify(&Type::Unknown, &Type::Str, span()));
+    }
+
+    #[test]
+    pub fn unify_performs_occurs_check() {
+        let mut u = Unifier::new();
+        let a = u.fresh();
+        let f = Type::Fn(vec![a.clone()], Box::new(Type::Int));
+        assert_eq!(Err(UnifyError::Infinite(TypeVar(0), f.clone())), u.unify(&a, &f, span()));
+    }
+
+    #[test]
+    pub fn generalize_only_quantifies_inner_variables() {
+        let mut u = Unifier::new();
+        let outer = u.fresh();
+        u.enter_level();
+        let inner = u.fresh();
+        let ty = Type::Fn(vec![inner.clone()], Box::new(outer.clone()));
+        u.exit_level();
+
+        let scheme = u.generalize(&ty);
+        assert_eq!(&[TypeVar(1)], scheme.vars());
+
+        let (instance, args) = u.instantiate(&scheme);
+        assert_eq!(Type::Fn(vec![Type::Var(TypeVar(2))], Box::new(outer)), instance);
+        assert_eq!(vec![Type::Var(TypeVar(2))], args);
+    }
+
+    #[test]
+    pub fn binding_to_an_outer_variable_prevents_generalization() {
+        let mut u = Unifier::new();
+        let outer = u.fresh();
+        u.enter_level();
+        let inner = u.fresh();
+        let other = u.fresh();
+        u.unify(&outer, &Type::Tuple(vec![inner.clone()]), span()).unwrap();
+        u.exit_level();
+
+        let scheme = u.generalize(&Type::Tuple(vec![inner, other]));
+        assert_eq!(&[TypeVar(2)], scheme.vars());
+    }
+}
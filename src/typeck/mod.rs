@@ -0,0 +1,439 @@
+mod checker;
+mod env;
+mod infer;
+mod types;
+
+pub use self::checker::{check_program, Dispatch, TypeckResults};
+pub use self::env::{operator_trait, EnumInfo, FieldInfo, ImplInfo, MethodInfo, StructInfo, TraitInfo, TypeEnv, VariantInfo};
+pub use self::infer::{Unifier, UnifyError};
+pub use self::types::{normalize, Scheme, Type, TypeVar};
+
+#[cfg(test)]
+mod tests {
+    use text::TextSpan;
+    use diagnostics::Diagnostic;
+    use typeck::{check_program, Dispatch, Type, TypeckResults};
+    use testing::{last_span_of, parse, span_at, span_of};
+
+    fn check(code: &str) -> TypeckResults {
+        check_program(&parse(code))
+    }
+
+    fn errors(code: &str) -> Vec<Diagnostic> {
+        check(code).diagnostics().to_vec()
+    }
+
+    #[test]
+    pub fn well_typed_program_has_no_errors() {
+        let code = "
+            struct Point { x: Int, y: Int }
+            def add(a: Point, b: Point) -> Point { Point { x: a.x + b.x, y: a.y + b.y } }
+            let p: Point = add(Point { x: 1, y: 2 }, Point { x: 3, y: 4 });
+            p.x";
+        assert_eq!(Vec::<Diagnostic>::new(), errors(code));
+        assert_eq!(Some(Type::Int), check(code).type_at(span_of(code, "p.x")));
+    }
+
+    #[test]
+    pub fn adding_int_and_str_is_rejected() {
+        let code = "1 + \"a\"";
+        assert_eq!(vec![
+            Diagnostic::error(TextSpan::new(0, 7), "cannot apply `+` to `Int` and `Str`")
+                .with_label(TextSpan::new(0, 1), "this is `Int`")
+                .with_label(TextSpan::new(4, 7), "this is `Str`"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn annotation_mismatch_points_at_both_spans() {
+        let code = "let x: Int = true;";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "true"), "mismatched types: expected `Int`, found `Bool`")
+                .with_label(span_of(code, "Int"), "expected `Int` because of this annotation"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn argument_mismatch_points_at_parameter_annotation() {
+        let code = "def f(x: Float) { x } f(1)";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "1"), "mismatched types: expected `Float`, found `Int`")
+                .with_label(span_of(code, "Float"), "expected `Float` because of this annotation"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn functions_can_be_called_before_their_declaration() {
+        assert_eq!(Vec::<Diagnostic>::new(), errors("let x: Int = f(); def f() -> Int { 1 }"));
+    }
+
+    #[test]
+    pub fn power_takes_two_numbers_of_the_same_type() {
+        let code = "2 ** 10";
+        assert_eq!(Some(Type::Int), check(code).type_at(span_of(code, code)));
+        assert_eq!(vec![
+            Diagnostic::error(TextSpan::new(0, 8), "cannot apply `**` to `Float` and `Int`")
+                .with_label(TextSpan::new(0, 3), "this is `Float`")
+                .with_label(TextSpan::new(7, 8), "this is `Int`"),
+        ], errors("1.5 ** 2"));
+    }
+
+    #[test]
+    pub fn infers_signatures_of_unannotated_defs() {
+        let code = "def f(x, y) { (x + 1, y) } f(2, true)";
+        let result = check(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+        assert_eq!("def(Int, 'a) -> (Int, 'a)", result.type_at(TextSpan::new(4, 5)).unwrap().to_string());
+        assert_eq!("(Int, Bool)", result.type_at(span_of(code, "f(2, true)")).unwrap().to_string());
+    }
+
+    #[test]
+    pub fn inferred_parameter_mismatch_points_at_both_uses() {
+        let code = "def f(x) { x + 1 } f(\"a\")";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "\"a\""), "mismatched types: expected `Int`, found `Str`")
+                .with_label(span_of(code, "x + 1"), "`Int` was inferred from this"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn conflicting_uses_within_a_def_are_reported() {
+        let code = "def f(x) { let a = x + 1; x == \"s\" }";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "x == \"s\""), "cannot apply `==` to `Int` and `Str`")
+                .with_label(TextSpan::new(26, 27), "this is `Int`")
+                .with_label(span_of(code, "\"s\""), "this is `Str`")
+                .with_label(span_of(code, "x + 1"), "`Int` was inferred from this"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn defs_are_polymorphic() {
+        let code = "def id(x) { x } (id(1), id(\"a\"), id(true))";
+        let result = check(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+        assert_eq!("def('a) -> 'a", result.type_at(span_of(code, "id")).unwrap().to_string());
+        assert_eq!("(Int, Str, Bool)", result.type_at(span_of(code, "(id(1), id(\"a\"), id(true))")).unwrap().to_string());
+    }
+
+    #[test]
+    pub fn variables_are_not_polymorphic() {
+        let code = "def id(x) { x } let f = id; (f(1), f(true))";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "true"), "mismatched types: expected `Int`, found `Bool`")
+                .with_label(span_of(code, "1"), "`Int` was inferred from this"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn explicit_type_parameters() {
+        let code = "def pair<A, B>(a: A, b: B) -> (A, B) { (a, b) } pair(1, \"a\")";
+        let result = check(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+        assert_eq!("def(A, B) -> (A, B)", result.type_at(TextSpan::new(4, 8)).unwrap().to_string());
+        assert_eq!("(Int, Str)", result.type_at(span_of(code, "pair(1, \"a\")")).unwrap().to_string());
+    }
+
+    #[test]
+    pub fn type_parameters_stand_for_any_type() {
+        let code = "def bad<T>(x: T) -> Int { x }";
+        assert_eq!(vec![
+            Diagnostic::error(TextSpan::new(26, 27), "mismatched types: expected `Int`, found `T`")
+                .with_label(TextSpan::new(20, 23), "expected `Int` because of this annotation"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn generic_structs_and_enums() {
+        let code = "
+            struct Box<T> { value: T }
+            enum List<T> { Nil, Cons(T, List<T>) }
+            def head<T>(list: List<T>, default: T) -> T { match list { Cons(x, _) => x, Nil => default } }
+            let b = Box { value: Cons(1, Nil) };
+            head(b.value, 0)";
+        let result = check(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+        assert_eq!("Box<List<Int>>", result.type_at(span_of(code, "Box { value: Cons(1, Nil) }")).unwrap().to_string());
+        assert_eq!("Int", result.type_at(span_of(code, "head(b.value, 0)")).unwrap().to_string());
+    }
+
+    #[test]
+    pub fn generic_struct_fields_are_checked() {
+        let code = "struct Pair<T> { a: T, b: T } Pair { a: 1, b: true }";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "true"), "mismatched types: expected `Int`, found `Bool`")
+                .with_label(span_of(code, "1"), "`Int` was inferred from this"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn wrong_number_of_type_arguments() {
+        let code = "struct Box<T> { value: T } let b: Box = Box { value: 1 }; let c: Int<Str> = 1;";
+        assert_eq!(vec![
+            Diagnostic::error(TextSpan::new(34, 37), "wrong number of type arguments for `Box`: expected 1, found 0"),
+            Diagnostic::error(span_of(code, "Int<Str>"), "wrong number of type arguments for `Int`: expected 0, found 1"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn mutually_recursive_defs_are_inferred_together() {
+        let code = "
+            def even(n) { match n { 0 => true, _ => odd(n - 1) } }
+            def odd(n) { match n { 0 => false, _ => even(n - 1) } }
+            even(4)";
+        let result = check(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+        assert_eq!("def(Int) -> Bool", result.type_at(span_of(code, "odd")).unwrap().to_string());
+    }
+
+    #[test]
+    pub fn occurs_check_is_reported() {
+        let code = "def f(x) { x(x) }";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "x(x)"), "cannot construct the infinite type `'a = def('a) -> 'b`")
+                .with_label(span_at(code, 13, "x"), "`'a` is used here too"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn variables_holding_generic_functions_are_not_generalized() {
+        let code = "def id(x) { x } let h = id; h(true); h(1); id(1)";
+        let errors = errors(code);
+        assert_eq!(1, errors.len(), "{:?}", errors);
+        // The argument of `h(1)` is the error, since `h` already takes a `Bool`
+        let start = code.find("h(1)").unwrap() + 2;
+        assert_eq!(TextSpan::new(start, start + 1), errors[0].span());
+    }
+
+    #[test]
+    pub fn field_access_infers_the_struct() {
+        let code = "struct P { x: Int } def getx(p) { p.x } getx(P { x: 1 })";
+        let result = check(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+        assert_eq!("def(P) -> Int", result.type_at(span_of(code, "getx")).unwrap().to_string());
+    }
+
+    #[test]
+    pub fn defs_cannot_use_local_variables() {
+        let code = "def g(y) { def f() { y } f() }";
+        assert_eq!(vec![
+            Diagnostic::error(TextSpan::new(21, 22), "cannot use `y` here, because a `def` can't refer to the local variables around it"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn hover_finds_the_innermost_type() {
+        let code = "let x = 1.5; (x, \"a\")";
+        let result = check(code);
+        assert_eq!(Some((TextSpan::new(14, 15), Type::Float)), result.type_at_offset(14));
+        assert_eq!(Some((TextSpan::new(13, 21), Type::Tuple(vec![Type::Float, Type::Str]))), result.type_at_offset(15));
+        assert_eq!(None, result.type_at_offset(3));
+    }
+
+    #[test]
+    pub fn unknown_names_are_reported() {
+        let code = "let x: Widget = y;";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "Widget"), "cannot find type `Widget`"),
+            Diagnostic::error(span_of(code, "y"), "cannot find value `y` in this scope"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn c_ints_are_only_for_externs() {
+        let code = "extern abs(x: CInt) -> CInt; let x: CInt = abs(1);";
+        assert_eq!(vec![
+            Diagnostic::error(last_span_of(code, "CInt"), "`CInt` can only be used in the signature of an `extern`"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn externs_say_which_c_integer_they_return() {
+        let code = "extern strcmp(a: Str, b: Str) -> Int; extern labs(x: Int) -> CLong; let x: CLong = labs(1);";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "Int"), "an `extern` can't return `Int`: use `CInt` for a C `int`, or \
+                                                     `CLong` for a C `long`"),
+            Diagnostic::error(last_span_of(code, "CLong"), "`CLong` can only be used in the signature of an `extern`"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn struct_literals_are_checked() {
+        let code = "struct P { x: Int, y: Int } P { x: 1.5, z: 2 }";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "1.5"), "mismatched types: expected `Int`, found `Float`")
+                .with_label(TextSpan::new(14, 17), "expected `Int` because of this annotation"),
+            Diagnostic::error(span_of(code, "z"), "struct `P` has no field named `z`"),
+            Diagnostic::error(span_of(code, "P { x: 1.5, z: 2 }"), "missing fields `y` in initializer of `P`"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn returned_values_must_have_the_return_type() {
+        let code = "def f(x: Int) -> Int { match x { 0 => return true, _ => {} }; let y: Str = return; x } f(1)";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "true"), "mismatched types: expected `Int`, found `Bool`")
+                .with_label(span_at(code, 17, "Int"), "expected `Int` because of this annotation"),
+            Diagnostic::error(span_at(code, 75, "return"), "mismatched types: expected `Int`, found `()`")
+                .with_label(span_at(code, 17, "Int"), "expected `Int` because of this annotation"),
+        ], errors(code));
+
+        let code = "return 1";
+        assert_eq!(vec![Diagnostic::error(span_of(code, "return 1"), "`return` outside of a `def`")], errors(code));
+    }
+
+    #[test]
+    pub fn match_arms_must_agree() {
+        let code = "enum E { A, B } match A { A => 1, B => \"b\" }";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "\"b\""), "match arms have incompatible types: expected `Int`, found `Str`")
+                .with_label(span_of(code, "1"), "the first arm has type `Int`"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn destructured_bindings_get_element_types() {
+        let code = "let (a, b): (Int, Str) = (1, \"x\"); b";
+        assert_eq!(Vec::<Diagnostic>::new(), errors(code));
+        let result = check(code);
+        assert_eq!(Some(Type::Str), result.type_at(TextSpan::new(code.len() - 1, code.len())));
+    }
+
+    #[test]
+    pub fn methods_on_concrete_types_are_dispatched_statically() {
+        let code = "
+            struct Point { x: Int, y: Int }
+            trait Show { def show(self) -> Str }
+            impl Show for Point { def show(self) -> Str { \"point\" } }
+            let p = Point { x: 1, y: 2 };
+            p.show()";
+        let result = check(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+        assert_eq!(Some(Type::Str), result.type_at(span_of(code, "p.show()")));
+        let decl = code.find("show(self) -> Str {").unwrap();
+        let call = code.rfind("show").unwrap();
+        assert_eq!(
+            Some(&Dispatch::Static(TextSpan::new(decl, decl + 4))),
+            result.dispatch_at(TextSpan::new(call, call + 4)));
+    }
+
+    #[test]
+    pub fn bounds_allow_methods_on_type_parameters() {
+        let code = "
+            trait Show { def show(self) -> Str }
+            impl Show for Int { def show(self) -> Str { \"int\" } }
+            def describe<T: Show>(x: T) -> Str { x.show() }
+            (describe(1), describe(true))";
+        let call = code.find("show() }").unwrap();
+        let second = code.rfind("describe").unwrap();
+        let result = check(code);
+        assert_eq!(vec![
+            Diagnostic::error(TextSpan::new(second, second + 8), "the trait `Show` is not implemented for `Bool`"),
+        ], result.diagnostics().to_vec());
+        assert_eq!(
+            Some(&Dispatch::Bound { param: "T".into(), trait_name: "Show".into(), method: "show".into() }),
+            result.dispatch_at(TextSpan::new(call, call + 4)));
+    }
+
+    #[test]
+    pub fn methods_need_a_bound_or_an_impl() {
+        let code = "trait Show { def show(self) -> Str } def f<T>(x: T) -> Str { x.show() } 1.show()";
+        let first = code.find("show()").unwrap();
+        let second = code.rfind("show").unwrap();
+        assert_eq!(vec![
+            Diagnostic::error(TextSpan::new(first, first + 4), "no method named `show` found for `T`"),
+            Diagnostic::error(TextSpan::new(second, second + 4), "no method named `show` found for `Int`"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn dyn_values_are_dispatched_through_vtables() {
+        let code = "
+            trait Show { def show(self) -> Str }
+            struct P { x: Int }
+            impl Show for P { def show(self) -> Str { \"p\" } }
+            let s: dyn Show = P { x: 1 };
+            s.show()";
+        let result = check(code);
+        assert_eq!(Vec::<Diagnostic>::new(), result.diagnostics().to_vec());
+        assert_eq!(Some("Show"), result.coercion_at(span_of(code, "P { x: 1 }")));
+        assert_eq!(Some(Type::Dyn("Show".into())), result.type_at(TextSpan::new(code.find("s:").unwrap(), code.find("s:").unwrap() + 1)));
+        let call = code.rfind("show").unwrap();
+        assert_eq!(
+            Some(&Dispatch::Dynamic { trait_name: "Show".into(), slot: 0 }),
+            result.dispatch_at(TextSpan::new(call, call + 4)));
+    }
+
+    #[test]
+    pub fn only_implementations_convert_to_dyn() {
+        let code = "trait Show { def show(self) -> Str } let s: dyn Show = 1;";
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "1"), "the trait `Show` is not implemented for `Int`")
+                .with_label(span_of(code, "dyn Show"), "expected `dyn Show` because of this annotation"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn impls_are_checked_against_their_trait() {
+        let code = "
+            trait Shape { def area(self) -> Int; def name(self) -> Str }
+            impl Shape for Int { def area(self) -> Str { \"a\" } def size(self) -> Int { 1 } }";
+        let area = code.rfind("area").unwrap();
+        assert_eq!(vec![
+            Diagnostic::error(
+                TextSpan::new(area, area + 4),
+                "method `area` has an incompatible type for trait `Shape`: expected `def(Int) -> Int`, found `def(Int) -> Str`")
+                .with_label(span_of(code, "def area(self) -> Int"), "the trait declares the method here"),
+            Diagnostic::error(span_of(code, "size"), "method `size` is not a member of trait `Shape`"),
+            Diagnostic::error(span_of(code, "impl Shape for Int"), "not all trait methods are implemented, missing `name`"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn trait_declarations_and_impls_are_validated() {
+        let code = "
+            trait T { def f(x: Int) }
+            impl T for Int { def f(self) { () } }
+            impl T for Int { def f(self) { () } }
+            impl Missing for Int { }";
+        let second = code.rfind("impl T for Int").unwrap();
+        assert_eq!(vec![
+            Diagnostic::error(TextSpan::new(27, 28), "the first parameter of method `f` must be `self`"),
+            Diagnostic::error(TextSpan::new(second, second + 14), "conflicting implementations of trait `T` for type `Int`")
+                .with_label(span_of(code, "impl T for Int"), "the first implementation is here"),
+            Diagnostic::error(span_of(code, "Missing"), "cannot find trait `Missing`"),
+        ], errors(code));
+    }
+
+    #[test]
+    pub fn operators_are_overloaded_through_traits() {
+        let code = "
+            struct V { x: Int }
+            impl Add for V { def add(self, other: V) -> V { V { x: self.x + other.x } } }
+            let v = V { x: 1 } + V { x: 2 };
+            (v.x, v * v)";
+        let add = code.find("add").unwrap();
+        let result = check(code);
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "v * v"), "cannot apply `*` to `V` and `V`")
+                .with_label(TextSpan::new(code.len() - 6, code.len() - 5), "this is `V`")
+                .with_label(TextSpan::new(code.len() - 2, code.len() - 1), "this is `V`"),
+        ], result.diagnostics().to_vec());
+        assert_eq!(Some(Type::Struct("V".into(), vec![])), result.type_at(span_of(code, "V { x: 1 } + V { x: 2 }")));
+        assert_eq!(
+            Some(&Dispatch::Static(TextSpan::new(add, add + 3))),
+            result.dispatch_at(span_of(code, "V { x: 1 } + V { x: 2 }")));
+    }
+
+    #[test]
+    pub fn bounded_operators_are_dispatched_per_instance() {
+        let code = "impl Add for Int { def add(self, o: Int) -> Int { o } } def double<T: Add>(x: T) -> T { x + x } double(1.5)";
+        let result = check(code);
+        assert_eq!(vec![
+            Diagnostic::error(span_of(code, "impl Add for Int"), "conflicting implementations of trait `Add` for type `Int`"),
+        ], result.diagnostics().to_vec());
+        assert_eq!(
+            Some(&Dispatch::Bound { param: "T".into(), trait_name: "Add".into(), method: "add".into() }),
+            result.dispatch_at(span_of(code, "x + x")));
+    }
+}
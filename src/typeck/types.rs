@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// A type variable, standing for a type that hasn't been inferred yet
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct TypeVar(pub u32);
+
+/// The type of a value
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    Str,
+    Tuple(Vec<Type>),
+
+    /// A struct, along with the arguments for its type parameters
+    Struct(String, Vec<Type>),
+
+    /// An enum, along with the arguments for its type parameters
+    Enum(String, Vec<Type>),
+
+    Fn(Vec<Type>, Box<Type>),
+
+    /// A value of some type that implements the named trait, like `dyn Show`
+    Dyn(String),
+
+    Var(TypeVar),
+
+    /// A type parameter, like the `T` in `def id<T>(x: T) -> T`. Within the declaration it belongs to, it stands for
+    /// any type at all, so it's only equal to itself.
+    Param(String),
+
+    /// The type of a value whose type couldn't be determined because of an earlier error.
+    /// It unifies with every other type, so the error isn't reported again.
+    Unknown,
+}
+
+impl Type {
+    /// The empty tuple, which is the type of expressions that don't produce a useful value
+    pub fn unit() -> Type {
+        Type::Tuple(Vec::new())
+    }
+
+    /// Gets the types directly within this one, like the elements of a tuple
+    pub fn children(&self) -> Vec<&Type> {
+        match self {
+            &Type::Tuple(ref types) | &Type::Struct(_, ref types) | &Type::Enum(_, ref types) => types.iter().collect(),
+            &Type::Fn(ref params, ref ret) => params.iter().chain(Some(&**ret)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Rebuilds this type with `f` applied to each of the types directly within it
+    pub fn map_children<F: FnMut(&Type) -> Type>(&self, mut f: F) -> Type {
+        match self {
+            &Type::Tuple(ref types) => Type::Tuple(types.iter().map(f).collect()),
+            &Type::Struct(ref name, ref args) => Type::Struct(name.clone(), args.iter().map(f).collect()),
+            &Type::Enum(ref name, ref args) => Type::Enum(name.clone(), args.iter().map(f).collect()),
+            &Type::Fn(ref params, ref ret) => {
+                let params = params.iter().map(&mut f).collect();
+                Type::Fn(params, Box::new(f(ret)))
+            }
+            t => t.clone(),
+        }
+    }
+
+    /// Returns `true` if the variable appears anywhere within this type
+    pub fn contains(&self, var: TypeVar) -> bool {
+        match self {
+            &Type::Var(v) => v == var,
+            t => t.children().iter().any(|c| c.contains(var)),
+        }
+    }
+
+    /// Adds the variables within this type to `vars`, in the order they first appear
+    pub fn vars(&self, vars: &mut Vec<TypeVar>) {
+        match self {
+            &Type::Var(v) => if !vars.contains(&v) {
+                vars.push(v);
+            },
+            t => for c in t.children() {
+                c.vars(vars);
+            },
+        }
+    }
+
+    /// Replaces the variables in the mapping with the types they map to
+    pub fn substitute(&self, mapping: &HashMap<TypeVar, Type>) -> Type {
+        match self {
+            &Type::Var(v) => mapping.get(&v).cloned().unwrap_or(Type::Var(v)),
+            t => t.map_children(|c| c.substitute(mapping)),
+        }
+    }
+
+    /// Replaces the type parameters in the mapping with the types they map to
+    pub fn substitute_params(&self, mapping: &HashMap<String, Type>) -> Type {
+        match self {
+            &Type::Param(ref name) => mapping.get(name).cloned().unwrap_or(Type::Param(name.clone())),
+            t => t.map_children(|c| c.substitute_params(mapping)),
+        }
+    }
+}
+
+/// Renumbers the variables in the types so they're numbered from zero in the order they first appear, which makes
+/// them display as `'a`, `'b` and so on. Variables shared between the types stay shared.
+pub fn normalize(types: &[&Type]) -> Vec<Type> {
+    let mut vars = Vec::new();
+    for t in types {
+        t.vars(&mut vars);
+    }
+    let mapping = vars.iter().enumerate().map(|(i, &v)| (v, Type::Var(TypeVar(i as u32)))).collect();
+    types.iter().map(|t| t.substitute(&mapping)).collect()
+}
+
+/// A type that is polymorphic over some of the type parameters and variables in it, like the type of
+/// `def id<T>(x: T) -> T { x }` or `def id(x) { x }`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Scheme {
+    params: Vec<String>,
+    vars: Vec<TypeVar>,
+    ty: Type,
+}
+
+impl Scheme {
+    pub fn new(params: Vec<String>, vars: Vec<TypeVar>, ty: Type) -> Scheme {
+        Scheme { params, vars, ty }
+    }
+
+    /// A scheme that isn't polymorphic at all
+    pub fn mono(ty: Type) -> Scheme {
+        Scheme::new(Vec::new(), Vec::new(), ty)
+    }
+
+    pub fn params(&self) -> &[String] { &self.params }
+    pub fn vars(&self) -> &[TypeVar] { &self.vars }
+    pub fn ty(&self) -> &Type { &self.ty }
+
+    /// Returns `true` if the scheme has any type parameters or variables to instantiate
+    pub fn is_generic(&self) -> bool {
+        !self.params.is_empty() || !self.vars.is_empty()
+    }
+
+    /// Substitutes types for the parameters and variables of the scheme, in the order the parameters and then the
+    /// variables are listed
+    pub fn apply(&self, args: &[Type]) -> Type {
+        let params = self.params.iter().cloned().zip(args.iter().cloned()).collect();
+        let vars = self.vars.iter().cloned().zip(args[self.params.len()..].iter().cloned()).collect();
+        self.ty.substitute_params(&params).substitute(&vars)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Type::Int => write!(f, "Int"),
+            &Type::Float => write!(f, "Float"),
+            &Type::Bool => write!(f, "Bool"),
+            &Type::Str => write!(f, "Str"),
+            &Type::Tuple(ref elements) => {
+                write!(f, "(")?;
+                write_list(f, elements)?;
+                if elements.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            &Type::Struct(ref name, ref args) | &Type::Enum(ref name, ref args) => {
+                write!(f, "{}", name)?;
+                if !args.is_empty() {
+                    write!(f, "<")?;
+                    write_list(f, args)?;
+                    write!(f, ">")?;
+                }
+                Ok(())
+            }
+            &Type::Fn(ref params, ref ret) => {
+                write!(f, "def(")?;
+                write_list(f, params)?;
+                write!(f, ") -> {}", ret)
+            }
+            &Type::Var(TypeVar(v)) => {
+                write!(f, "'{}", (b'a' + (v % 26) as u8) as char)?;
+                if v >= 26 {
+                    write!(f, "{}", v / 26)?;
+                }
+                Ok(())
+            }
+            &Type::Dyn(ref trait_name) => write!(f, "dyn {}", trait_name),
+            &Type::Param(ref name) => write!(f, "{}", name),
+            &Type::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, types: &[Type]) -> fmt::Result {
+    for (i, t) in types.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", t)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use typeck::{Type, TypeVar};
+    use typeck::types::normalize;
+
+    #[test]
+    pub fn normalize_renumbers_shared_variables() {
+        let a = Type::Fn(vec![Type::Var(TypeVar(7))], Box::new(Type::Var(TypeVar(3))));
+        let b = Type::Tuple(vec![Type::Var(TypeVar(3)), Type::Var(TypeVar(9))]);
+        let normalized = normalize(&[&a, &b]);
+        assert_eq!("def('a) -> 'b", normalized[0].to_string());
+        assert_eq!("('b, 'c)", normalized[1].to_string());
+    }
+
+    #[test]
+    pub fn display_uses_source_syntax() {
+        assert_eq!("(Int, (Bool,), ())", Type::Tuple(vec![
+            Type::Int,
+            Type::Tuple(vec![Type::Bool]),
+            Type::unit(),
+        ]).to_string());
+        assert_eq!("def(Int, Str) -> Float", Type::Fn(vec![Type::Int, Type::Str], Box::new(Type::Float)).to_string());
+        assert_eq!("Map<Str, List<T>>", Type::Struct("Map".into(), vec![
+            Type::Str,
+            Type::Enum("List".into(), vec![Type::Param("T".into())]),
+        ]).to_string());
+        assert_eq!("(dyn Show,)", Type::Tuple(vec![Type::Dyn("Show".into())]).to_string());
+    }
+}
@@ -0,0 +1,239 @@
+use wasm::inst::{FuncType, Inst, ValType};
+use wasm::module::{ExportKind, WasmModule};
+
+const MAGIC: &'static [u8] = b"\0asm";
+const VERSION: u32 = 1;
+
+// Section IDs, which the sections have to come in the order of
+const TYPE: u8 = 1;
+const IMPORT: u8 = 2;
+const FUNCTION: u8 = 3;
+const TABLE: u8 = 4;
+const MEMORY: u8 = 5;
+const GLOBAL: u8 = 6;
+const EXPORT: u8 = 7;
+const ELEMENT: u8 = 9;
+const CODE: u8 = 10;
+const DATA: u8 = 11;
+
+const FUNCREF: u8 = 0x70;
+const EMPTY_BLOCK: u8 = 0x40;
+
+/// Writes an unsigned LEB128 number, seven bits to a byte with the top bit set on all but the last
+pub fn put_unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Writes a signed LEB128 number, which ends once the rest of the bits are all copies of the last sign bit written
+pub fn put_signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn put_name(out: &mut Vec<u8>, name: &str) {
+    put_unsigned(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn put_type(out: &mut Vec<u8>, ty: &FuncType) {
+    out.push(0x60);
+    put_unsigned(out, ty.params.len() as u64);
+    out.extend(ty.params.iter().map(|t| t.code()));
+    put_unsigned(out, ty.result.is_some() as u64);
+    out.extend(ty.result.map(ValType::code));
+}
+
+/// Writes a section from its ID and a vector of entries, if there are any
+fn put_section<T, F: FnMut(&mut Vec<u8>, &T)>(out: &mut Vec<u8>, id: u8, entries: &[T], mut put: F) {
+    if entries.is_empty() {
+        return;
+    }
+    let mut contents = Vec::new();
+    put_unsigned(&mut contents, entries.len() as u64);
+    for entry in entries {
+        put(&mut contents, entry);
+    }
+    out.push(id);
+    put_unsigned(out, contents.len() as u64);
+    out.extend_from_slice(&contents);
+}
+
+pub fn put_inst(out: &mut Vec<u8>, inst: &Inst) {
+    match *inst {
+        Inst::Unreachable => out.push(0x00),
+        Inst::Block => out.extend_from_slice(&[0x02, EMPTY_BLOCK]),
+        Inst::Loop => out.extend_from_slice(&[0x03, EMPTY_BLOCK]),
+        Inst::If => out.extend_from_slice(&[0x04, EMPTY_BLOCK]),
+        Inst::Else => out.push(0x05),
+        Inst::End => out.push(0x0b),
+        Inst::Br(depth) => {
+            out.push(0x0c);
+            put_unsigned(out, depth as u64);
+        }
+        Inst::BrIf(depth) => {
+            out.push(0x0d);
+            put_unsigned(out, depth as u64);
+        }
+        Inst::BrTable(ref depths, default) => {
+            out.push(0x0e);
+            put_unsigned(out, depths.len() as u64);
+            for &depth in depths {
+                put_unsigned(out, depth as u64);
+            }
+            put_unsigned(out, default as u64);
+        }
+        Inst::Return => out.push(0x0f),
+        Inst::Call(function) => {
+            out.push(0x10);
+            put_unsigned(out, function as u64);
+        }
+        Inst::CallIndirect(ty) => {
+            out.push(0x11);
+            put_unsigned(out, ty as u64);
+            out.push(0);
+        }
+        Inst::Drop => out.push(0x1a),
+        Inst::LocalGet(n) | Inst::LocalSet(n) | Inst::LocalTee(n) | Inst::GlobalGet(n) | Inst::GlobalSet(n) => {
+            out.push(match *inst {
+                Inst::LocalGet(_) => 0x20,
+                Inst::LocalSet(_) => 0x21,
+                Inst::LocalTee(_) => 0x22,
+                Inst::GlobalGet(_) => 0x23,
+                _ => 0x24,
+            });
+            put_unsigned(out, n as u64);
+        }
+        Inst::Load(..) | Inst::Load8U(_) | Inst::Store(..) => {
+            out.push(match *inst {
+                Inst::Load(ValType::I32, _) => 0x28,
+                Inst::Load(ValType::I64, _) => 0x29,
+                Inst::Load(ValType::F64, _) => 0x2b,
+                Inst::Load8U(_) => 0x2d,
+                Inst::Store(ValType::I32, _) => 0x36,
+                Inst::Store(ValType::I64, _) => 0x37,
+                _ => 0x39,
+            });
+            let (align, offset) = inst.memarg().unwrap();
+            put_unsigned(out, align as u64);
+            put_unsigned(out, offset as u64);
+        }
+        Inst::MemorySize => out.extend_from_slice(&[0x3f, 0]),
+        Inst::MemoryGrow => out.extend_from_slice(&[0x40, 0]),
+        Inst::MemoryCopy => out.extend_from_slice(&[0xfc, 10, 0, 0]),
+        Inst::I32Const(value) => {
+            out.push(0x41);
+            put_signed(out, value as i64);
+        }
+        Inst::I64Const(value) => {
+            out.push(0x42);
+            put_signed(out, value);
+        }
+        Inst::F64Const(value) => {
+            out.push(0x44);
+            let bits = value.to_bits();
+            out.extend((0..8).map(|i| (bits >> (i * 8)) as u8));
+        }
+        Inst::Op(op) => out.push(op.code()),
+    }
+}
+
+impl WasmModule {
+    /// Writes the module in the binary format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend((0..4).map(|i| (VERSION >> (i * 8)) as u8));
+
+        put_section(&mut out, TYPE, &self.types, put_type);
+        put_section(&mut out, IMPORT, &self.imports, |out, import| {
+            put_name(out, &import.module);
+            put_name(out, &import.name);
+            out.push(0);
+            put_unsigned(out, import.ty as u64);
+        });
+        put_section(&mut out, FUNCTION, &self.functions, |out, function| put_unsigned(out, function.ty as u64));
+
+        // The table and memory have a minimum size and no maximum
+        let size = self.table.len() as u64;
+        let tables = if self.table.is_empty() { Vec::new() } else { vec![size] };
+        put_section(&mut out, TABLE, &tables, |out, &size| {
+            out.extend_from_slice(&[FUNCREF, 0]);
+            put_unsigned(out, size);
+        });
+        put_section(&mut out, MEMORY, &[self.memory_pages], |out, &pages| {
+            out.push(0);
+            put_unsigned(out, pages as u64);
+        });
+        put_section(&mut out, GLOBAL, &[self.heap_start], |out, &start| {
+            out.extend_from_slice(&[ValType::I32.code(), 1]);
+            put_inst(out, &Inst::I32Const(start as i32));
+            put_inst(out, &Inst::End);
+        });
+
+        put_section(&mut out, EXPORT, &self.exports, |out, export| {
+            put_name(out, &export.name);
+            out.push(match export.kind {
+                ExportKind::Func => 0,
+                ExportKind::Memory => 2,
+            });
+            put_unsigned(out, export.index as u64);
+        });
+        let elements = if self.table.is_empty() { Vec::new() } else { vec![&self.table] };
+        put_section(&mut out, ELEMENT, &elements, |out, table| {
+            // An active segment for table 0, placed by a constant expression
+            out.push(0);
+            put_inst(out, &Inst::I32Const(0));
+            put_inst(out, &Inst::End);
+            put_unsigned(out, table.len() as u64);
+            for &function in table.iter() {
+                put_unsigned(out, function as u64);
+            }
+        });
+
+        put_section(&mut out, CODE, &self.functions, |out, function| {
+            let mut code = Vec::new();
+            // Locals are declared in runs of the same type
+            let mut runs: Vec<(u32, ValType)> = Vec::new();
+            for &ty in &function.locals {
+                match runs.last_mut() {
+                    Some(&mut (ref mut count, last)) if last == ty => *count += 1,
+                    _ => runs.push((1, ty)),
+                }
+            }
+            put_unsigned(&mut code, runs.len() as u64);
+            for &(count, ty) in &runs {
+                put_unsigned(&mut code, count as u64);
+                code.push(ty.code());
+            }
+            for inst in &function.body {
+                put_inst(&mut code, inst);
+            }
+            put_inst(&mut code, &Inst::End);
+            put_unsigned(out, code.len() as u64);
+            out.extend_from_slice(&code);
+        });
+        let segments = if self.data.is_empty() { Vec::new() } else { vec![&self.data] };
+        put_section(&mut out, DATA, &segments, |out, data| {
+            out.push(0);
+            put_inst(out, &Inst::I32Const(self.data_start as i32));
+            put_inst(out, &Inst::End);
+            put_unsigned(out, data.len() as u64);
+            out.extend_from_slice(data);
+        });
+        out
+    }
+}
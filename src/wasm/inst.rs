@@ -0,0 +1,251 @@
+/// The type of a WebAssembly value
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F64,
+}
+
+impl ValType {
+    pub fn code(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F64 => 0x7c,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F64 => "f64",
+        }
+    }
+
+    /// The log2 of the alignment of a load or store of the type
+    fn align(self) -> u32 {
+        match self {
+            ValType::I32 => 2,
+            ValType::I64 | ValType::F64 => 3,
+        }
+    }
+}
+
+/// The signature of a function, which takes at most one result
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub result: Option<ValType>,
+}
+
+/// An instruction that only takes operands from the stack
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32LtU,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32LeU,
+    I32GeS,
+    I32GeU,
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64GtS,
+    I64LeS,
+    I64GeS,
+    F64Eq,
+    F64Ne,
+    F64Lt,
+    F64Gt,
+    F64Le,
+    F64Ge,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32And,
+    I32Or,
+    I32Shl,
+    I32ShrU,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64And,
+    I64Xor,
+    I64ShrU,
+    F64Add,
+    F64Sub,
+    F64Mul,
+    F64Div,
+    I32WrapI64,
+    I64ExtendI32U,
+}
+
+impl Op {
+    pub fn code(self) -> u8 {
+        match self {
+            Op::I32Eqz => 0x45,
+            Op::I32Eq => 0x46,
+            Op::I32Ne => 0x47,
+            Op::I32LtS => 0x48,
+            Op::I32LtU => 0x49,
+            Op::I32GtS => 0x4a,
+            Op::I32GtU => 0x4b,
+            Op::I32LeS => 0x4c,
+            Op::I32LeU => 0x4d,
+            Op::I32GeS => 0x4e,
+            Op::I32GeU => 0x4f,
+            Op::I64Eqz => 0x50,
+            Op::I64Eq => 0x51,
+            Op::I64Ne => 0x52,
+            Op::I64LtS => 0x53,
+            Op::I64GtS => 0x55,
+            Op::I64LeS => 0x57,
+            Op::I64GeS => 0x59,
+            Op::F64Eq => 0x61,
+            Op::F64Ne => 0x62,
+            Op::F64Lt => 0x63,
+            Op::F64Gt => 0x64,
+            Op::F64Le => 0x65,
+            Op::F64Ge => 0x66,
+            Op::I32Add => 0x6a,
+            Op::I32Sub => 0x6b,
+            Op::I32Mul => 0x6c,
+            Op::I32And => 0x71,
+            Op::I32Or => 0x72,
+            Op::I32Shl => 0x74,
+            Op::I32ShrU => 0x76,
+            Op::I64Add => 0x7c,
+            Op::I64Sub => 0x7d,
+            Op::I64Mul => 0x7e,
+            Op::I64DivS => 0x7f,
+            Op::I64And => 0x83,
+            Op::I64Xor => 0x85,
+            Op::I64ShrU => 0x88,
+            Op::F64Add => 0xa0,
+            Op::F64Sub => 0xa1,
+            Op::F64Mul => 0xa2,
+            Op::F64Div => 0xa3,
+            Op::I32WrapI64 => 0xa7,
+            Op::I64ExtendI32U => 0xad,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::I32Eqz => "i32.eqz",
+            Op::I32Eq => "i32.eq",
+            Op::I32Ne => "i32.ne",
+            Op::I32LtS => "i32.lt_s",
+            Op::I32LtU => "i32.lt_u",
+            Op::I32GtS => "i32.gt_s",
+            Op::I32GtU => "i32.gt_u",
+            Op::I32LeS => "i32.le_s",
+            Op::I32LeU => "i32.le_u",
+            Op::I32GeS => "i32.ge_s",
+            Op::I32GeU => "i32.ge_u",
+            Op::I64Eqz => "i64.eqz",
+            Op::I64Eq => "i64.eq",
+            Op::I64Ne => "i64.ne",
+            Op::I64LtS => "i64.lt_s",
+            Op::I64GtS => "i64.gt_s",
+            Op::I64LeS => "i64.le_s",
+            Op::I64GeS => "i64.ge_s",
+            Op::F64Eq => "f64.eq",
+            Op::F64Ne => "f64.ne",
+            Op::F64Lt => "f64.lt",
+            Op::F64Gt => "f64.gt",
+            Op::F64Le => "f64.le",
+            Op::F64Ge => "f64.ge",
+            Op::I32Add => "i32.add",
+            Op::I32Sub => "i32.sub",
+            Op::I32Mul => "i32.mul",
+            Op::I32And => "i32.and",
+            Op::I32Or => "i32.or",
+            Op::I32Shl => "i32.shl",
+            Op::I32ShrU => "i32.shr_u",
+            Op::I64Add => "i64.add",
+            Op::I64Sub => "i64.sub",
+            Op::I64Mul => "i64.mul",
+            Op::I64DivS => "i64.div_s",
+            Op::I64And => "i64.and",
+            Op::I64Xor => "i64.xor",
+            Op::I64ShrU => "i64.shr_u",
+            Op::F64Add => "f64.add",
+            Op::F64Sub => "f64.sub",
+            Op::F64Mul => "f64.mul",
+            Op::F64Div => "f64.div",
+            Op::I32WrapI64 => "i32.wrap_i64",
+            Op::I64ExtendI32U => "i64.extend_i32_u",
+        }
+    }
+}
+
+/// A WebAssembly instruction. Blocks, loops and `if`s never take or leave values on the stack, so their types are left
+/// out
+#[derive(Debug, PartialEq, Clone)]
+pub enum Inst {
+    Unreachable,
+    Block,
+    Loop,
+    If,
+    Else,
+    End,
+
+    /// Branches to the end of a block or the start of a loop, counting outwards from the innermost one
+    Br(u32),
+    BrIf(u32),
+
+    /// Branches to the label the index on the stack picks, or the last one if it's out of range
+    BrTable(Vec<u32>, u32),
+    Return,
+
+    /// Calls a function by its index, which counts the imports first
+    Call(u32),
+
+    /// Calls the function in the table at the index on the stack, which must have the type of the given index
+    CallIndirect(u32),
+    Drop,
+
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+
+    /// Loads a value of the type from the address on the stack plus an offset
+    Load(ValType, u32),
+    Load8U(u32),
+
+    /// Stores the value on top of the stack at the address under it plus an offset
+    Store(ValType, u32),
+
+    MemorySize,
+    MemoryGrow,
+
+    /// Copies the count of bytes on top of the stack from the address under it to the one under that
+    MemoryCopy,
+
+    I32Const(i32),
+    I64Const(i64),
+    F64Const(f64),
+    Op(Op),
+}
+
+impl Inst {
+    /// Gets the alignment and offset of an instruction that accesses memory
+    pub fn memarg(&self) -> Option<(u32, u32)> {
+        match *self {
+            Inst::Load(ty, offset) | Inst::Store(ty, offset) => Some((ty.align(), offset)),
+            Inst::Load8U(offset) => Some((0, offset)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::{HashMap, HashSet};
+
+use arith::OverflowMode;
+use cfg::BlockId;
+use ir::{Callee, Const, Function, Inst as IrInst, InstKind, Module, Terminator, Ty, Value};
+use parser::BinOp;
+use text::Document;
+use wasm::inst::{FuncType, Inst, Op, ValType};
+use wasm::module::WasmModule;
+use wasm::runtime::Runtime;
+use x86::{source_location, CodegenError};
+
+/// Where the data starts, which leaves address 0 free for null pointers
+pub const DATA_START: u32 = 16;
+
+/// Gets the WebAssembly type a value of a type is held in. Strings, objects and `bool`s are `i32`s, and so are
+/// functions, which are indices into the table
+pub fn val_type(ty: Ty) -> ValType {
+    match ty {
+        Ty::Int => ValType::I64,
+        Ty::Float => ValType::F64,
+        Ty::Unit | Ty::Bool | Ty::Str | Ty::Ptr | Ty::Fn => ValType::I32,
+    }
+}
+
+/// The strings and vtables in memory, each aligned to a word
+pub struct Data {
+    pub bytes: Vec<u8>,
+    strings: HashMap<String, u32>,
+}
+
+impl Data {
+    pub fn new() -> Data {
+        Data { bytes: Vec::new(), strings: HashMap::new() }
+    }
+
+    fn align(&mut self) {
+        while self.bytes.len() % 8 != 0 {
+            self.bytes.push(0);
+        }
+    }
+
+    fn word(&mut self, word: u64) {
+        self.bytes.extend((0..8).map(|i| (word >> (i * 8)) as u8));
+    }
+
+    /// Gets the address of a string, which is that of its bytes. They're preceded by a header word and a length word,
+    /// and followed by a NUL
+    pub fn string(&mut self, s: &str) -> u32 {
+        if let Some(&address) = self.strings.get(s) {
+            return address;
+        }
+        self.align();
+        self.word(0);
+        self.word(s.len() as u64);
+        let address = DATA_START + self.bytes.len() as u32;
+        self.bytes.extend_from_slice(s.as_bytes());
+        self.bytes.push(0);
+        self.strings.insert(s.into(), address);
+        address
+    }
+
+    /// Adds a vtable, which holds a word for the table index of each function, returning its address
+    pub fn vtable(&mut self, functions: &[u32]) -> u32 {
+        self.align();
+        let address = DATA_START + self.bytes.len() as u32;
+        for &f in functions {
+            self.word(f as u64);
+        }
+        address
+    }
+
+    /// Gets the address after the data, aligned to a word
+    pub fn end(&mut self) -> u32 {
+        self.align();
+        DATA_START + self.bytes.len() as u32
+    }
+}
+
+/// What functions and data a function being lowered can refer to
+pub struct Context<'a> {
+    pub module: &'a Module,
+    pub document: Option<&'a Document>,
+    pub data: &'a mut Data,
+    pub wasm: &'a mut WasmModule,
+
+    /// The index of every function and extern, by name
+    pub functions: &'a HashMap<&'a str, u32>,
+
+    /// The table index of every function and extern, by name
+    pub table: &'a HashMap<&'a str, u32>,
+    pub vtables: &'a [u32],
+    pub runtime: &'a Runtime,
+}
+
+/// A function's locals and body
+pub struct Lowered {
+    pub locals: Vec<ValType>,
+    pub body: Vec<Inst>,
+}
+
+/// Lowers a function, whose blocks become nested WebAssembly blocks
+///
+/// Each IR block's code comes right after the end of its own WebAssembly block, so control falls from one into the
+/// next, and a jump to a later block is a `br` out to the end of the target's block. A jump back to an earlier block
+/// can't be written that way, so a function with any goes around a loop, which sets a local to the block to run and
+/// continues the loop, and the loop starts with a `br_table` that picks the block to run from the local.
+pub fn lower_function(cx: &mut Context, function: &Function) -> Result<Lowered, CodegenError> {
+    let mut lowering = Lowering {
+        function,
+        locals: Vec::new(),
+        values: HashMap::new(),
+        phis: HashMap::new(),
+        dispatch: None,
+        body: Vec::new(),
+    };
+    for (i, &(v, _)) in function.params.iter().enumerate() {
+        lowering.values.insert(v, i as u32);
+    }
+    let params = function.params.len() as u32;
+    for inst in function.blocks.iter().flat_map(|b| b.insts.iter()) {
+        let local = params + lowering.locals.len() as u32;
+        lowering.values.insert(inst.value, local);
+        lowering.locals.push(val_type(inst.ty));
+        if let InstKind::Phi(_) = inst.kind {
+            lowering.phis.insert(inst.value, local + 1);
+            lowering.locals.push(val_type(inst.ty));
+        }
+    }
+
+    let n = function.blocks.len();
+    let backwards = function.blocks.iter().enumerate()
+        .any(|(b, block)| block.terminator.successors().iter().any(|s| s.index() <= b));
+    if backwards {
+        lowering.dispatch = Some(params + lowering.locals.len() as u32);
+        lowering.locals.push(ValType::I32);
+        lowering.body.push(Inst::Loop);
+        lowering.body.extend((0..n).map(|_| Inst::Block));
+        lowering.body.push(Inst::LocalGet(lowering.dispatch.unwrap()));
+        lowering.body.push(Inst::BrTable((0..n as u32).collect(), 0));
+    } else {
+        lowering.body.extend((1..n).map(|_| Inst::Block));
+    }
+
+    let types = function.value_types();
+    for (b, block) in function.blocks.iter().enumerate() {
+        if b > 0 || backwards {
+            lowering.body.push(Inst::End);
+        }
+        for inst in &block.insts {
+            lowering.inst(cx, &types, inst)?;
+        }
+        lowering.terminator(b);
+    }
+    if backwards {
+        lowering.body.push(Inst::End);
+        lowering.body.push(Inst::Unreachable);
+    }
+    Ok(Lowered { locals: lowering.locals, body: lowering.body })
+}
+
+struct Lowering<'a> {
+    function: &'a Function,
+    locals: Vec<ValType>,
+
+    /// The local of each value
+    values: HashMap<Value, u32>,
+
+    /// The local each phi's predecessors set before they jump to it
+    phis: HashMap<Value, u32>,
+
+    /// The local that says which block to run, if any jumps go back
+    dispatch: Option<u32>,
+    body: Vec<Inst>,
+}
+
+impl<'a> Lowering<'a> {
+    fn get(&mut self, value: Value) {
+        let local = self.values[&value];
+        self.body.push(Inst::LocalGet(local));
+    }
+
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, CodegenError> {
+        Err(CodegenError { function: self.function.name.clone(), message: message.into() })
+    }
+
+    fn inst(&mut self, cx: &mut Context, types: &[Option<Ty>], inst: &IrInst) -> Result<(), CodegenError> {
+        let types = |v: Value| types[v.index()].unwrap_or(Ty::Unit);
+        match inst.kind {
+            InstKind::Const(ref c) => {
+                let inst = match *c {
+                    Const::Unit => Inst::I32Const(0),
+                    Const::Int(ref i) => match i.to_i64() {
+                        Some(i) => Inst::I64Const(i),
+                        None => return self.error(format!("the integer {} doesn't fit in 64 bits", i)),
+                    },
+                    Const::Float(f) => Inst::F64Const(f),
+                    Const::Bool(b) => Inst::I32Const(b as i32),
+                    Const::Str(ref s) => Inst::I32Const(cx.data.string(s) as i32),
+                };
+                self.body.push(inst);
+            }
+            InstKind::Binary(op, a, b) => {
+                self.get(a);
+                self.get(b);
+                // Only the runtime's integer arithmetic can stop the program, and needs to know where it was
+                let wrapping = op == BinOp::Add || op == BinOp::Subtract || op == BinOp::Multiply;
+                let wrapping = wrapping && cx.module.overflow == OverflowMode::Wrap;
+                let location = if types(a) == Ty::Int && !op.is_comparison() && !wrapping {
+                    cx.data.string(&source_location(cx.document, inst.span)) as i32
+                } else {
+                    0
+                };
+                let runtime = cx.runtime;
+                let insts = match (types(a), op) {
+                    (Ty::Int, BinOp::Add) if wrapping => vec![Inst::Op(Op::I64Add)],
+                    (Ty::Int, BinOp::Subtract) if wrapping => vec![Inst::Op(Op::I64Sub)],
+                    (Ty::Int, BinOp::Multiply) if wrapping => vec![Inst::Op(Op::I64Mul)],
+                    (Ty::Int, BinOp::Add) => vec![Inst::I32Const(location), Inst::Call(runtime.add)],
+                    (Ty::Int, BinOp::Subtract) => vec![Inst::I32Const(location), Inst::Call(runtime.sub)],
+                    (Ty::Int, BinOp::Multiply) => vec![Inst::I32Const(location), Inst::Call(runtime.mul)],
+                    (Ty::Int, BinOp::Divide) => vec![Inst::I32Const(location), Inst::Call(runtime.div)],
+                    (Ty::Int, BinOp::Power) => vec![Inst::I32Const(location), Inst::Call(runtime.int_pow)],
+                    (Ty::Int, _) => vec![Inst::Op(comparison(op, Op::I64Eq, Op::I64Ne, Op::I64LtS, Op::I64LeS,
+                                                             Op::I64GtS, Op::I64GeS))],
+                    (Ty::Float, BinOp::Add) => vec![Inst::Op(Op::F64Add)],
+                    (Ty::Float, BinOp::Subtract) => vec![Inst::Op(Op::F64Sub)],
+                    (Ty::Float, BinOp::Multiply) => vec![Inst::Op(Op::F64Mul)],
+                    (Ty::Float, BinOp::Divide) => vec![Inst::Op(Op::F64Div)],
+                    (Ty::Float, BinOp::Power) => vec![Inst::Call(runtime.float_pow)],
+                    (Ty::Float, _) => vec![Inst::Op(comparison(op, Op::F64Eq, Op::F64Ne, Op::F64Lt, Op::F64Le,
+                                                               Op::F64Gt, Op::F64Ge))],
+                    // Strings compare by their contents, which the runtime orders like `strcmp`
+                    (Ty::Str, BinOp::Add) => vec![Inst::Call(runtime.str_concat)],
+                    (Ty::Str, _) => vec![
+                        Inst::Call(runtime.str_cmp),
+                        Inst::I32Const(0),
+                        Inst::Op(comparison(op, Op::I32Eq, Op::I32Ne, Op::I32LtS, Op::I32LeS, Op::I32GtS, Op::I32GeS)),
+                    ],
+                    // Everything else compares by its bits, which for heap objects and functions is by identity
+                    _ => vec![Inst::Op(comparison(op, Op::I32Eq, Op::I32Ne, Op::I32LtU, Op::I32LeU, Op::I32GtU,
+                                                  Op::I32GeU))],
+                };
+                self.body.extend(insts);
+            }
+            InstKind::Call(Callee::Direct(ref name), ref args) => {
+                for &arg in args {
+                    self.get(arg);
+                }
+                self.body.push(Inst::Call(cx.functions[name.as_str()]));
+                // An extern that returns `unit` doesn't return anything
+                if cx.module.extern_(name).map_or(false, |e| e.ret == Ty::Unit) {
+                    self.body.push(Inst::I32Const(0));
+                }
+            }
+            InstKind::Call(Callee::Indirect(f), ref args) => {
+                for &arg in args {
+                    self.get(arg);
+                }
+                self.get(f);
+                let ty = FuncType {
+                    params: args.iter().map(|&a| val_type(types(a))).collect(),
+                    result: Some(val_type(inst.ty)),
+                };
+                self.body.push(Inst::CallIndirect(cx.wasm.type_index(ty)));
+            }
+            InstKind::New(ref fields) => {
+                let object = self.values[&inst.value];
+                self.body.extend(vec![Inst::I32Const(fields.len() as i32), Inst::Call(cx.runtime.new)]);
+                self.body.push(Inst::LocalSet(object));
+                for (i, &field) in fields.iter().enumerate() {
+                    self.body.push(Inst::LocalGet(object));
+                    self.get(field);
+                    self.body.push(Inst::Store(val_type(types(field)), i as u32 * 8));
+                }
+                return Ok(());
+            }
+            InstKind::Get(object, field) => {
+                self.get(object);
+                self.body.push(Inst::Load(val_type(inst.ty), field * 8));
+            }
+            InstKind::Set(object, field, value) => {
+                self.get(object);
+                self.get(value);
+                self.body.extend(vec![Inst::Store(val_type(types(value)), field * 8), Inst::I32Const(0)]);
+            }
+            InstKind::Phi(_) => self.body.push(Inst::LocalGet(self.phis[&inst.value])),
+            InstKind::Func(ref name) => self.body.push(Inst::I32Const(cx.table[name.as_str()] as i32)),
+            InstKind::VTable(n) => self.body.push(Inst::I32Const(cx.vtables[n as usize] as i32)),
+        }
+        let local = self.values[&inst.value];
+        self.body.push(Inst::LocalSet(local));
+        Ok(())
+    }
+
+    /// Writes the jump from a block to another, setting the phis of the target first, from inside a number of `if`s.
+    /// The jump is left out if the target comes next and `fall_through` allows it
+    fn jump(&mut self, from: usize, to: BlockId, ifs: u32, fall_through: bool) {
+        for inst in &self.function.block(to).insts {
+            if let InstKind::Phi(ref incoming) = inst.kind {
+                if let Some(&(_, v)) = incoming.iter().find(|&&(b, _)| b.index() == from) {
+                    self.body.push(Inst::LocalGet(self.values[&v]));
+                    self.body.push(Inst::LocalSet(self.phis[&inst.value]));
+                }
+            }
+        }
+        let (from, to) = (from as u32, to.index() as u32);
+        if to > from {
+            if !fall_through || to != from + 1 {
+                self.body.push(Inst::Br(to - from - 1 + ifs));
+            }
+        } else {
+            let blocks = self.function.blocks.len() as u32;
+            self.body.push(Inst::I32Const(to as i32));
+            self.body.push(Inst::LocalSet(self.dispatch.expect("Expected a loop for jumps backwards")));
+            self.body.push(Inst::Br(blocks - 1 - from + ifs));
+        }
+    }
+
+    fn terminator(&mut self, b: usize) {
+        match self.function.blocks[b].terminator {
+            Terminator::Jump(to) => self.jump(b, to, 0, true),
+            Terminator::Branch(cond, then, otherwise) => {
+                self.get(cond);
+                self.body.push(Inst::If);
+                self.jump(b, then, 1, false);
+                self.body.push(Inst::End);
+                self.jump(b, otherwise, 0, true);
+            }
+            Terminator::Return(v) => {
+                self.get(v);
+                self.body.push(Inst::Return);
+            }
+            Terminator::Unreachable => self.body.push(Inst::Unreachable),
+        }
+    }
+}
+
+/// Picks the instruction for a comparison from those for each operator
+fn comparison(op: BinOp, eq: Op, ne: Op, lt: Op, le: Op, gt: Op, ge: Op) -> Op {
+    match op {
+        BinOp::Equal => eq,
+        BinOp::NotEqual => ne,
+        BinOp::Less => lt,
+        BinOp::LessEqual => le,
+        BinOp::Greater => gt,
+        _ => ge,
+    }
+}
+
+/// Turns the name of a function into an identifier for the text format, which can't hold some characters that names
+/// can, like parentheses and commas
+pub fn identifier(name: &str, taken: &mut HashSet<String>) -> String {
+    let ident: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c) { c } else { '_' })
+        .collect();
+    let mut unique = ident.clone();
+    let mut n = 2;
+    while !taken.insert(unique.clone()) {
+        unique = format!("{}_{}", ident, n);
+        n += 1;
+    }
+    unique
+}
@@ -0,0 +1,313 @@
+//! Compiles the IR to WebAssembly modules, to run programs in browsers and other sandboxes
+//!
+//! `int`s are `i64`s and `float`s are `f64`s, and every other value is an `i32`: strings and objects are addresses in
+//! the module's memory, laid out as they are in native code, and functions are indices into its table. Externs are
+//! imported from the `env` module, with strings passed as addresses of their bytes, which are followed by a NUL as C
+//! expects. The rest of what a program needs is in the module itself, except for reporting a trap and raising a
+//! `float` to a power, which the host provides:
+//!
+//! | Import                     | Signature           | Purpose                                           |
+//! |----------------------------|---------------------|---------------------------------------------------|
+//! | `why.trap(kind, location)` | `(i32, i32) -> ()`  | stops the program, like `why_trap` in native code |
+//! | `why.float_pow(a, b)`      | `(f64, f64) -> f64` | raises a `float` to a power                       |
+//!
+//! A module exports its memory as `memory` and the program as `main`, whose result is the value of its final
+//! expression. `wat` writes a module in the text format, for reading and for tests.
+
+mod encode;
+mod inst;
+mod lower;
+mod module;
+mod runtime;
+mod wat;
+
+pub use self::encode::{put_signed, put_unsigned};
+pub use self::inst::{FuncType, Inst, Op, ValType};
+pub use self::lower::{val_type, DATA_START};
+pub use self::module::{Export, ExportKind, Func, Import, WasmModule};
+pub use self::wat::wat;
+
+use std::collections::{HashMap, HashSet};
+
+use arith::OverflowMode;
+use ir::{Module, Ty};
+use text::Document;
+use x86::CodegenError;
+use self::lower::{identifier, lower_function, Context, Data};
+use self::runtime::{runtime_functions, Runtime, DEFINED};
+
+/// Compiles a module to WebAssembly, with failures reported at locations in the document if there is one
+pub fn wasm_module(module: &Module, document: Option<&Document>) -> Result<WasmModule, CodegenError> {
+    if module.overflow == OverflowMode::Promote {
+        return Err(CodegenError {
+            function: "main".into(),
+            message: "integers that promote to arbitrary precision aren't supported in WebAssembly".into(),
+        });
+    }
+
+    let mut wasm = WasmModule {
+        types: Vec::new(),
+        imports: Vec::new(),
+        functions: Vec::new(),
+        table: Vec::new(),
+        memory_pages: 0,
+        heap_start: 0,
+        exports: Vec::new(),
+        data: Vec::new(),
+        data_start: DATA_START,
+    };
+    let mut taken: HashSet<String> = DEFINED.iter().map(|&name| name.to_string()).collect();
+
+    // Functions are numbered with the imports first: the externs, then the runtime's, then the program's functions,
+    // then the rest of the runtime
+    let mut functions = HashMap::new();
+    for e in &module.externs {
+        let ty = FuncType {
+            params: e.params.iter().map(|&ty| val_type(ty)).collect(),
+            result: if e.ret == Ty::Unit { None } else { Some(val_type(e.ret)) },
+        };
+        functions.insert(e.name.as_str(), wasm.imports.len() as u32);
+        let ty = wasm.type_index(ty);
+        let ident = identifier(&e.name, &mut taken);
+        wasm.imports.push(Import { module: "env".into(), name: e.name.clone(), ty, ident });
+    }
+    let imported = [
+        ("trap", FuncType { params: vec![ValType::I32, ValType::I32], result: None }),
+        ("float_pow", FuncType { params: vec![ValType::F64, ValType::F64], result: Some(ValType::F64) }),
+    ];
+    for &(name, ref ty) in &imported {
+        let ty = wasm.type_index(ty.clone());
+        let ident = identifier(&format!("why_{}", name), &mut taken);
+        wasm.imports.push(Import { module: "why".into(), name: name.to_string(), ty, ident });
+    }
+
+    let first = wasm.imports.len() as u32;
+    let mut idents = Vec::new();
+    for (i, function) in module.functions.iter().enumerate() {
+        functions.insert(function.name.as_str(), first + i as u32);
+        idents.push(identifier(&function.name, &mut taken));
+    }
+    let defined = first + module.functions.len() as u32;
+    let runtime = Runtime {
+        trap: first - 2,
+        float_pow: first - 1,
+        new: defined,
+        str_concat: defined + 1,
+        str_cmp: defined + 2,
+        mul_overflows: defined + 3,
+        add: defined + 4,
+        sub: defined + 5,
+        mul: defined + 6,
+        div: defined + 7,
+        int_pow: defined + 8,
+    };
+
+    // The table holds the program's functions, then the externs, so any can be a function value
+    let mut table = HashMap::new();
+    for name in module.functions.iter().map(|f| &f.name).chain(module.externs.iter().map(|e| &e.name)) {
+        table.insert(name.as_str(), wasm.table.len() as u32);
+        wasm.table.push(functions[name.as_str()]);
+    }
+
+    let mut data = Data::new();
+    let vtables: Vec<u32> = module.vtables.iter()
+        .map(|vtable| data.vtable(&vtable.iter().map(|f| table[f.as_str()]).collect::<Vec<_>>()))
+        .collect();
+
+    for (function, ident) in module.functions.iter().zip(idents) {
+        let lowered = {
+            let mut cx = Context {
+                module,
+                document,
+                data: &mut data,
+                wasm: &mut wasm,
+                functions: &functions,
+                table: &table,
+                vtables: &vtables,
+                runtime: &runtime,
+            };
+            lower_function(&mut cx, function)?
+        };
+        let ty = wasm.type_index(FuncType {
+            params: function.params.iter().map(|&(_, ty)| val_type(ty)).collect(),
+            result: Some(val_type(function.ret)),
+        });
+        wasm.functions.push(Func { ident, ty, locals: lowered.locals, body: lowered.body });
+    }
+    for f in runtime_functions(&runtime, module.overflow) {
+        let ty = wasm.type_index(f.ty);
+        wasm.functions.push(Func { ident: f.name.into(), ty, locals: f.locals, body: f.body });
+    }
+
+    wasm.heap_start = data.end();
+    wasm.memory_pages = wasm.heap_start / 65536 + 1;
+    wasm.data = data.bytes;
+    wasm.exports.push(Export { name: "memory".into(), kind: ExportKind::Memory, index: 0 });
+    if let Some(&main) = functions.get("main") {
+        wasm.exports.push(Export { name: "main".into(), kind: ExportKind::Func, index: main });
+    }
+    Ok(wasm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::parse_module;
+
+    fn compile(text: &str) -> WasmModule {
+        let module = parse_module(text).expect("Expected the module to parse");
+        wasm_module(&module, None).expect("Expected the module to compile")
+    }
+
+    /// Reads an unsigned LEB128 number, returning it and where it ends
+    fn unsigned_at(bytes: &[u8], mut at: usize) -> (u64, usize) {
+        let (mut value, mut shift) = (0, 0);
+        loop {
+            value |= ((bytes[at] & 0x7f) as u64) << shift;
+            shift += 7;
+            at += 1;
+            if bytes[at - 1] & 0x80 == 0 {
+                return (value, at);
+            }
+        }
+    }
+
+    #[test]
+    pub fn numbers_are_encoded_as_leb128() {
+        let unsigned = |value| {
+            let mut out = Vec::new();
+            put_unsigned(&mut out, value);
+            out
+        };
+        let signed = |value| {
+            let mut out = Vec::new();
+            put_signed(&mut out, value);
+            out
+        };
+        assert_eq!(vec![0], unsigned(0));
+        assert_eq!(vec![0x7f], unsigned(127));
+        assert_eq!(vec![0xe5, 0x8e, 0x26], unsigned(624485));
+        assert_eq!(vec![0x3f], signed(63));
+        assert_eq!(vec![0xc0, 0], signed(64));
+        assert_eq!(vec![0x7f], signed(-1));
+        assert_eq!(vec![0x40], signed(-64));
+        assert_eq!(vec![0xbf, 0x7f], signed(-65));
+        assert_eq!(vec![0xc0, 0xbb, 0x78], signed(-123456));
+        assert_eq!(10, signed(i64::min_value()).len());
+    }
+
+    #[test]
+    pub fn functions_and_externs_become_functions_and_imports() {
+        let wasm = compile("\
+overflow wrap
+extern @puts(str) -> int
+
+fn @\"add<Int>\"(%0: int, %1: int) -> int {
+bb0:
+    %2: int = add %0, %1
+    ret %2
+}
+
+fn @main() -> int {
+bb0:
+    %0: str = const \"hi\"
+    %1: int = call @puts(%0)
+    %2: int = call @\"add<Int>\"(%1, %1)
+    ret %2
+}
+");
+        let text = wat(&wasm);
+        assert!(text.contains("  (import \"env\" \"puts\" (func $puts (type 0)))\n"), "{}", text);
+        assert!(text.contains("  (import \"why\" \"trap\" (func $why_trap (type 1)))\n"), "{}", text);
+        assert!(text.contains("\
+  (func $add<Int> (type 3) (param i64 i64) (result i64)
+    (local i64)
+    local.get 0
+    local.get 1
+    i64.add
+    local.set 2
+    local.get 2
+    return
+  )
+"), "{}", text);
+        assert!(text.contains("    i32.const 32\n    local.set 0\n    local.get 0\n    call $puts\n"), "{}", text);
+        assert!(text.contains("  (export \"memory\" (memory 0))\n  (export \"main\" (func $main))\n"), "{}", text);
+        assert!(text.contains("  (elem (i32.const 0) func $add<Int> $main $puts)\n"), "{}", text);
+        // The string's header and length come before its bytes, and the data is padded to a word
+        let data = "  (data (i32.const 16) \"\\00\\00\\00\\00\\00\\00\\00\\00\\02\\00\\00\\00\\00\\00\\00\\00\
+                    hi\\00\\00\\00";
+        assert!(text.contains(data), "{}", text);
+    }
+
+    #[test]
+    pub fn jumps_back_go_around_a_loop() {
+        // The phis swap their values each time around, which setting one after the other would get wrong
+        let wasm = compile("\
+overflow trap
+
+fn @swap(%0: int, %1: int, %2: bool) -> int {
+bb0:
+    jump bb1
+bb1:
+    %3: int = phi [bb0: %0], [bb1: %4]
+    %4: int = phi [bb0: %1], [bb1: %3]
+    branch %2, bb1, bb2
+bb2:
+    ret %3
+}
+");
+        let text = wat(&wasm);
+        assert!(text.contains("\
+    (local i64 i64 i64 i64 i32)
+    loop
+      block
+        block
+          block
+            local.get 7
+            br_table 0 1 2 0
+          end
+          local.get 0
+          local.set 4
+          local.get 1
+          local.set 6
+        end
+        local.get 4
+        local.set 3
+        local.get 6
+        local.set 5
+        local.get 2
+        if
+          local.get 5
+          local.set 4
+          local.get 3
+          local.set 6
+          i32.const 1
+          local.set 7
+          br 2
+        end
+      end
+      local.get 3
+      return
+    end
+    unreachable
+  )
+"), "{}", text);
+    }
+
+    #[test]
+    pub fn sections_come_in_order() {
+        let module = "overflow saturate\n\nfn @main() -> float {\nbb0:\n    %0: float = const 1.5\n    ret %0\n}\n";
+        let bytes = compile(module).to_bytes();
+        assert_eq!(b"\0asm\x01\0\0\0", &bytes[..8]);
+        let mut ids = Vec::new();
+        let mut at = 8;
+        while at < bytes.len() {
+            ids.push(bytes[at]);
+            let (size, start) = unsigned_at(&bytes, at + 1);
+            at = start + size as usize;
+        }
+        assert_eq!(bytes.len(), at);
+        // There's no data section, since there's no data
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 9, 10], ids);
+    }
+}
@@ -0,0 +1,83 @@
+use wasm::inst::{FuncType, Inst, ValType};
+
+/// A function the host provides
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+
+    /// The index of the function's type
+    pub ty: u32,
+
+    /// The name the function goes by in the text format
+    pub ident: String,
+}
+
+/// A function defined by the module, whose body leaves out the final `end`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Func {
+    pub ident: String,
+    pub ty: u32,
+
+    /// The types of the locals after the parameters
+    pub locals: Vec<ValType>,
+    pub body: Vec<Inst>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExportKind {
+    Func,
+    Memory,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Export {
+    pub name: String,
+    pub kind: ExportKind,
+    pub index: u32,
+}
+
+/// A WebAssembly module with one memory, one table of functions and one global, which holds where the heap ends
+#[derive(Debug, PartialEq, Clone)]
+pub struct WasmModule {
+    pub types: Vec<FuncType>,
+    pub imports: Vec<Import>,
+    pub functions: Vec<Func>,
+
+    /// The functions in the table, by index, which is what a function value holds
+    pub table: Vec<u32>,
+
+    /// The size the memory starts at, in 64 KiB pages
+    pub memory_pages: u32,
+
+    /// The address the heap starts at
+    pub heap_start: u32,
+    pub exports: Vec<Export>,
+
+    /// The bytes the memory starts with at `data_start`, with zeroes everywhere else
+    pub data: Vec<u8>,
+    pub data_start: u32,
+}
+
+impl WasmModule {
+    /// Gets the index of a function type, adding it if no type is the same
+    pub fn type_index(&mut self, ty: FuncType) -> u32 {
+        match self.types.iter().position(|t| *t == ty) {
+            Some(i) => i as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Gets the name of a function in the text format, by its index
+    pub fn function_ident(&self, index: u32) -> &str {
+        let index = index as usize;
+        if index < self.imports.len() {
+            &self.imports[index].ident
+        } else {
+            &self.functions[index - self.imports.len()].ident
+        }
+    }
+}
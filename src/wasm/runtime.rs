@@ -0,0 +1,235 @@
+//! The runtime functions a compiled module defines for itself, so the host only has to provide `why.trap` and
+//! `why.float_pow`
+//!
+//! Objects are allocated by bumping the global that holds where the heap ends, growing the memory when it runs out,
+//! and are never freed.
+
+use arith::OverflowMode;
+use wasm::inst::Inst::*;
+use wasm::inst::Op::*;
+use wasm::inst::{FuncType, Inst, ValType};
+
+const I32: ValType = ValType::I32;
+const I64: ValType = ValType::I64;
+
+const OVERFLOW: i32 = 0;
+const DIVIDE_BY_ZERO: i32 = 1;
+const NEGATIVE_POWER: i32 = 2;
+
+/// The indices of the runtime functions, imported and defined
+#[derive(Debug, Clone, Copy)]
+pub struct Runtime {
+    pub trap: u32,
+    pub float_pow: u32,
+    pub new: u32,
+    pub str_concat: u32,
+    pub str_cmp: u32,
+    pub mul_overflows: u32,
+    pub add: u32,
+    pub sub: u32,
+    pub mul: u32,
+    pub div: u32,
+    pub int_pow: u32,
+}
+
+/// A function of the runtime, defined in the module
+pub struct RuntimeFn {
+    pub name: &'static str,
+    pub ty: FuncType,
+    pub locals: Vec<ValType>,
+    pub body: Vec<Inst>,
+}
+
+/// The names of the runtime functions the module defines, in the order they're defined
+pub const DEFINED: [&'static str; 9] = [
+    "why_new", "why_str_concat", "why_str_cmp", "why_mul_overflows", "why_add", "why_sub", "why_mul", "why_div",
+    "why_int_pow",
+];
+
+fn func(params: &[ValType], result: ValType) -> FuncType {
+    FuncType { params: params.to_vec(), result: Some(result) }
+}
+
+/// Stops the program with a trap of the kind at the location in the local
+fn trap(runtime: &Runtime, kind: i32, location: u32) -> Vec<Inst> {
+    vec![I32Const(kind), LocalGet(location), Call(runtime.trap), Unreachable]
+}
+
+/// Loads the length of the string in a local, which is in the word before its bytes
+fn length(string: u32) -> Vec<Inst> {
+    vec![LocalGet(string), I32Const(8), Op(I32Sub), Load(I64, 0), Op(I32WrapI64)]
+}
+
+/// Handles an overflowing `+`, `-` or `*` by the mode, given the values to saturate to when the condition is true and
+/// false
+fn overflowed(runtime: &Runtime, mode: OverflowMode, condition: Vec<Inst>, saturated: (i64, i64)) -> Vec<Inst> {
+    if mode == OverflowMode::Trap {
+        return trap(runtime, OVERFLOW, 2);
+    }
+    let mut body = condition;
+    body.extend(vec![If, I64Const(saturated.0), Return, End, I64Const(saturated.1), Return]);
+    body
+}
+
+/// Checks whether the result of `+`, `-` or `*` in local 3 overflowed, by the condition, and returns it if it didn't
+fn checked(mode: OverflowMode, result: Vec<Inst>, overflows: Vec<Inst>, handle: Vec<Inst>) -> Vec<Inst> {
+    let mut body = result;
+    body.push(LocalSet(3));
+    if mode != OverflowMode::Wrap {
+        body.extend(overflows);
+        body.push(If);
+        body.extend(handle);
+        body.push(End);
+    }
+    body.push(LocalGet(3));
+    body
+}
+
+/// Builds the functions of the runtime, for the overflow mode of the module
+pub fn runtime_functions(runtime: &Runtime, mode: OverflowMode) -> Vec<RuntimeFn> {
+    let (min, max) = (i64::min_value(), i64::max_value());
+    let mut functions = Vec::new();
+
+    // Allocates an object of a count of words, with at least one so every object has its own address
+    functions.push(RuntimeFn {
+        name: "why_new",
+        ty: func(&[I32], I32),
+        locals: vec![I32],
+        body: vec![
+            GlobalGet(0), LocalSet(1),
+            GlobalGet(0), LocalGet(0), LocalGet(0), Op(I32Eqz), Op(I32Add), I32Const(3), Op(I32Shl), Op(I32Add),
+            GlobalSet(0),
+            Block,
+            GlobalGet(0), MemorySize, I32Const(16), Op(I32Shl), Op(I32LeU), BrIf(0),
+            GlobalGet(0), MemorySize, I32Const(16), Op(I32Shl), Op(I32Sub), I32Const(16), Op(I32ShrU), I32Const(1),
+            Op(I32Add), MemoryGrow, I32Const(-1), Op(I32Ne), BrIf(0),
+            Unreachable,
+            End,
+            LocalGet(1),
+        ],
+    });
+
+    // Concatenates two strings into a new one, with a header word, a length word and a NUL after its bytes
+    let mut body = length(0);
+    body.push(LocalSet(2));
+    body.extend(length(1));
+    body.push(LocalSet(3));
+    body.extend(vec![
+        LocalGet(2), LocalGet(3), Op(I32Add), I32Const(8), Op(I32Add), I32Const(3), Op(I32ShrU), I32Const(2),
+        Op(I32Add), Call(runtime.new), LocalSet(4),
+        LocalGet(4), LocalGet(2), LocalGet(3), Op(I32Add), Op(I64ExtendI32U), Store(I64, 8),
+        LocalGet(4), I32Const(16), Op(I32Add), LocalTee(4), LocalGet(0), LocalGet(2), MemoryCopy,
+        LocalGet(4), LocalGet(2), Op(I32Add), LocalGet(1), LocalGet(3), MemoryCopy,
+        LocalGet(4),
+    ]);
+    functions.push(RuntimeFn { name: "why_str_concat", ty: func(&[I32, I32], I32), locals: vec![I32; 3], body });
+
+    // Compares two strings by their bytes, then their lengths, giving -1, 0 or 1
+    let mut body = length(0);
+    body.push(LocalSet(2));
+    body.extend(length(1));
+    body.push(LocalSet(3));
+    body.extend(vec![
+        Block, Loop,
+        LocalGet(4), LocalGet(2), Op(I32GeU), BrIf(1),
+        LocalGet(4), LocalGet(3), Op(I32GeU), BrIf(1),
+        LocalGet(0), LocalGet(4), Op(I32Add), Load8U(0), LocalSet(5),
+        LocalGet(1), LocalGet(4), Op(I32Add), Load8U(0), LocalSet(6),
+        LocalGet(5), LocalGet(6), Op(I32Ne),
+        If, LocalGet(5), LocalGet(6), Op(I32GtU), LocalGet(5), LocalGet(6), Op(I32LtU), Op(I32Sub), Return, End,
+        LocalGet(4), I32Const(1), Op(I32Add), LocalSet(4),
+        Br(0),
+        End, End,
+        LocalGet(2), LocalGet(3), Op(I32GtU), LocalGet(2), LocalGet(3), Op(I32LtU), Op(I32Sub),
+    ]);
+    functions.push(RuntimeFn { name: "why_str_cmp", ty: func(&[I32, I32], I32), locals: vec![I32; 5], body });
+
+    // A wrapped product divides back to one of its factors unless it overflowed, except when dividing overflows too
+    functions.push(RuntimeFn {
+        name: "why_mul_overflows",
+        ty: func(&[I64, I64], I32),
+        locals: Vec::new(),
+        body: vec![
+            LocalGet(0), Op(I64Eqz), If, I32Const(0), Return, End,
+            LocalGet(0), I64Const(-1), Op(I64Eq), If, LocalGet(1), I64Const(min), Op(I64Eq), Return, End,
+            LocalGet(0), LocalGet(1), Op(I64Mul), LocalGet(0), Op(I64DivS), LocalGet(1), Op(I64Ne),
+        ],
+    });
+
+    // A sum overflows when its sign differs from both operands', and a difference when it differs from the first and
+    // the operands' signs differ
+    let arithmetic = func(&[I64, I64, I32], I64);
+    let add_overflows = vec![
+        LocalGet(0), LocalGet(3), Op(I64Xor), LocalGet(1), LocalGet(3), Op(I64Xor), Op(I64And), I64Const(0), Op(I64LtS),
+    ];
+    let sub_overflows = vec![
+        LocalGet(0), LocalGet(1), Op(I64Xor), LocalGet(0), LocalGet(3), Op(I64Xor), Op(I64And), I64Const(0), Op(I64LtS),
+    ];
+    let negative = |local| vec![LocalGet(local), I64Const(0), Op(I64LtS)];
+    let signs_differ = vec![LocalGet(0), LocalGet(1), Op(I64Xor), I64Const(0), Op(I64LtS)];
+    let operations = vec![
+        ("why_add", Op(I64Add), add_overflows, overflowed(runtime, mode, negative(1), (min, max))),
+        ("why_sub", Op(I64Sub), sub_overflows, overflowed(runtime, mode, negative(1), (max, min))),
+        ("why_mul", Op(I64Mul), vec![LocalGet(0), LocalGet(1), Call(runtime.mul_overflows)],
+         overflowed(runtime, mode, signs_differ, (min, max))),
+    ];
+    for (name, op, overflows, handle) in operations {
+        functions.push(RuntimeFn {
+            name,
+            ty: arithmetic.clone(),
+            locals: vec![I64],
+            body: checked(mode, vec![LocalGet(0), LocalGet(1), op], overflows, handle),
+        });
+    }
+
+    let mut body = vec![LocalGet(1), Op(I64Eqz), If];
+    body.extend(trap(runtime, DIVIDE_BY_ZERO, 2));
+    body.extend(vec![End, LocalGet(0), I64Const(min), Op(I64Eq), LocalGet(1), I64Const(-1), Op(I64Eq), Op(I32And), If]);
+    body.extend(match mode {
+        OverflowMode::Trap => trap(runtime, OVERFLOW, 2),
+        OverflowMode::Wrap => vec![I64Const(min), Return],
+        _ => vec![I64Const(max), Return],
+    });
+    body.extend(vec![End, LocalGet(0), LocalGet(1), Op(I64DivS)]);
+    functions.push(RuntimeFn { name: "why_div", ty: arithmetic.clone(), locals: Vec::new(), body });
+
+    // Raises to a power by squaring, noting whether any product overflows. Every square is used by the result, so if
+    // squaring overflows, so does the result, and the wrapped result is right whether or not anything did
+    let mut body = vec![LocalGet(1), I64Const(0), Op(I64LtS), If];
+    body.extend(trap(runtime, NEGATIVE_POWER, 2));
+    body.extend(vec![
+        End,
+        I64Const(1), LocalSet(3), LocalGet(0), LocalSet(4), LocalGet(1), LocalSet(5),
+        Block, Loop,
+        LocalGet(5), Op(I64Eqz), BrIf(1),
+        LocalGet(5), I64Const(1), Op(I64And), Op(I32WrapI64),
+        If,
+        LocalGet(6), LocalGet(3), LocalGet(4), Call(runtime.mul_overflows), Op(I32Or), LocalSet(6),
+        LocalGet(3), LocalGet(4), Op(I64Mul), LocalSet(3),
+        End,
+        LocalGet(5), I64Const(1), Op(I64ShrU), LocalTee(5), Op(I64Eqz), BrIf(1),
+        LocalGet(6), LocalGet(4), LocalGet(4), Call(runtime.mul_overflows), Op(I32Or), LocalSet(6),
+        LocalGet(4), LocalGet(4), Op(I64Mul), LocalSet(4),
+        Br(0),
+        End, End,
+    ]);
+    match mode {
+        OverflowMode::Trap => {
+            body.extend(vec![LocalGet(6), If]);
+            body.extend(trap(runtime, OVERFLOW, 2));
+            body.push(End);
+        }
+        OverflowMode::Wrap => {}
+        _ => body.extend(vec![
+            LocalGet(6), If,
+            LocalGet(0), I64Const(0), Op(I64LtS), LocalGet(1), I64Const(1), Op(I64And), Op(I32WrapI64), Op(I32And),
+            If, I64Const(min), Return, End,
+            I64Const(max), Return,
+            End,
+        ]),
+    }
+    body.push(LocalGet(3));
+    functions.push(RuntimeFn { name: "why_int_pow", ty: arithmetic, locals: vec![I64, I64, I64, I32], body });
+
+    functions
+}
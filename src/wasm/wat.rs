@@ -0,0 +1,135 @@
+use std::fmt::Write;
+
+use wasm::inst::{FuncType, Inst};
+use wasm::module::{ExportKind, WasmModule};
+
+/// Writes bytes as a string in the text format, escaping everything but printable ASCII
+fn quote(bytes: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(byte as char);
+            }
+            0x20...0x7e => quoted.push(byte as char),
+            _ => write!(quoted, "\\{:02x}", byte).unwrap(),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn signature(ty: &FuncType) -> String {
+    let mut text = String::new();
+    if !ty.params.is_empty() {
+        let params: Vec<&str> = ty.params.iter().map(|t| t.name()).collect();
+        write!(text, " (param {})", params.join(" ")).unwrap();
+    }
+    if let Some(result) = ty.result {
+        write!(text, " (result {})", result.name()).unwrap();
+    }
+    text
+}
+
+fn inst_text(module: &WasmModule, inst: &Inst) -> String {
+    match *inst {
+        Inst::Unreachable => "unreachable".into(),
+        Inst::Block => "block".into(),
+        Inst::Loop => "loop".into(),
+        Inst::If => "if".into(),
+        Inst::Else => "else".into(),
+        Inst::End => "end".into(),
+        Inst::Br(depth) => format!("br {}", depth),
+        Inst::BrIf(depth) => format!("br_if {}", depth),
+        Inst::BrTable(ref depths, default) => {
+            let depths: Vec<String> = depths.iter().chain(Some(&default)).map(|d| d.to_string()).collect();
+            format!("br_table {}", depths.join(" "))
+        }
+        Inst::Return => "return".into(),
+        Inst::Call(function) => format!("call ${}", module.function_ident(function)),
+        Inst::CallIndirect(ty) => format!("call_indirect (type {})", ty),
+        Inst::Drop => "drop".into(),
+        Inst::LocalGet(n) => format!("local.get {}", n),
+        Inst::LocalSet(n) => format!("local.set {}", n),
+        Inst::LocalTee(n) => format!("local.tee {}", n),
+        Inst::GlobalGet(_) => "global.get $heap".into(),
+        Inst::GlobalSet(_) => "global.set $heap".into(),
+        Inst::Load(..) | Inst::Load8U(_) | Inst::Store(..) => {
+            let name = match *inst {
+                Inst::Load(ty, _) => format!("{}.load", ty.name()),
+                Inst::Load8U(_) => "i32.load8_u".into(),
+                Inst::Store(ty, _) => format!("{}.store", ty.name()),
+                _ => unreachable!(),
+            };
+            match inst.memarg().unwrap() {
+                (_, 0) => name,
+                (_, offset) => format!("{} offset={}", name, offset),
+            }
+        }
+        Inst::MemorySize => "memory.size".into(),
+        Inst::MemoryGrow => "memory.grow".into(),
+        Inst::MemoryCopy => "memory.copy".into(),
+        Inst::I32Const(value) => format!("i32.const {}", value),
+        Inst::I64Const(value) => format!("i64.const {}", value),
+        Inst::F64Const(value) if value.is_nan() => "f64.const nan".into(),
+        Inst::F64Const(value) => format!("f64.const {:?}", value),
+        Inst::Op(op) => op.name().into(),
+    }
+}
+
+/// Writes a module in the text format, with instructions in order rather than folded into expressions
+///
+/// Functions are named, but locals, types and branch targets are numbered as they are in the binary format.
+pub fn wat(module: &WasmModule) -> String {
+    let mut out = String::from("(module\n");
+    for (i, ty) in module.types.iter().enumerate() {
+        writeln!(out, "  (type (;{};) (func{}))", i, signature(ty)).unwrap();
+    }
+    for import in &module.imports {
+        writeln!(out, "  (import {} {} (func ${} (type {})))", quote(import.module.as_bytes()),
+                 quote(import.name.as_bytes()), import.ident, import.ty).unwrap();
+    }
+
+    for function in &module.functions {
+        let ty = &module.types[function.ty as usize];
+        writeln!(out, "  (func ${} (type {}){}", function.ident, function.ty, signature(ty)).unwrap();
+        if !function.locals.is_empty() {
+            let locals: Vec<&str> = function.locals.iter().map(|t| t.name()).collect();
+            writeln!(out, "    (local {})", locals.join(" ")).unwrap();
+        }
+        let mut depth = 2;
+        for inst in &function.body {
+            if let Inst::Else | Inst::End = *inst {
+                depth -= 1;
+            }
+            writeln!(out, "{:indent$}{}", "", inst_text(module, inst), indent = depth * 2).unwrap();
+            if let Inst::Block | Inst::Loop | Inst::If | Inst::Else = *inst {
+                depth += 1;
+            }
+        }
+        writeln!(out, "  )").unwrap();
+    }
+
+    if !module.table.is_empty() {
+        writeln!(out, "  (table {} funcref)", module.table.len()).unwrap();
+    }
+    writeln!(out, "  (memory {})", module.memory_pages).unwrap();
+    writeln!(out, "  (global $heap (mut i32) (i32.const {}))", module.heap_start).unwrap();
+    for export in &module.exports {
+        let item = match export.kind {
+            ExportKind::Func => format!("func ${}", module.function_ident(export.index)),
+            ExportKind::Memory => format!("memory {}", export.index),
+        };
+        writeln!(out, "  (export {} ({}))", quote(export.name.as_bytes()), item).unwrap();
+    }
+    if !module.table.is_empty() {
+        let functions: Vec<String> = module.table.iter().map(|&f| format!("${}", module.function_ident(f))).collect();
+        writeln!(out, "  (elem (i32.const 0) func {})", functions.join(" ")).unwrap();
+    }
+    if !module.data.is_empty() {
+        writeln!(out, "  (data (i32.const {}) {})", module.data_start, quote(&module.data)).unwrap();
+    }
+    out.push_str(")\n");
+    out
+}
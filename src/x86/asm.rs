@@ -0,0 +1,136 @@
+use std::fmt::Write;
+
+use text::{Document, TextSpan};
+use x86::{source_location, stack_maps, Datum, Inst, Label, MapWord, Program, Sym};
+
+/// Writes a compiled program as GNU assembler source, which assembles to the same code `object_file` lays out
+///
+/// Code is annotated with `# file.why:line:column` comments where the source it came from changes, so the output can
+/// be read alongside the program. Labels are prefixed with the number of their function to make them unique in the
+/// file.
+pub fn assembly(program: &Program, document: Option<&Document>) -> String {
+    let mut out = String::new();
+    if let Some(document) = document {
+        writeln!(out, "    .file {}", quote(&document.path().display().to_string())).unwrap();
+    }
+
+    writeln!(out, "    .text").unwrap();
+    for (i, function) in program.functions.iter().enumerate() {
+        let symbol = Sym::Global(function.symbol.clone());
+        writeln!(out).unwrap();
+        writeln!(out, "    .globl {}", symbol).unwrap();
+        writeln!(out, "    .type {}, @function", symbol).unwrap();
+        writeln!(out, "    .p2align 4").unwrap();
+        writeln!(out, "{}:", symbol).unwrap();
+        let mut last = None;
+        if let Some(span) = function.span {
+            annotate(&mut out, document, span, &mut last);
+        }
+        for (n, inst) in function.insts.iter().enumerate() {
+            match *inst {
+                Inst::Label(Label(n)) => writeln!(out, ".L{}_{}:", i, n).unwrap(),
+                Inst::Jmp(Label(n)) => writeln!(out, "    jmp .L{}_{}", i, n).unwrap(),
+                Inst::Jcc(cond, Label(n)) => writeln!(out, "    j{} .L{}_{}", cond.suffix(), i, n).unwrap(),
+                Inst::Source(span) => annotate(&mut out, document, span, &mut last),
+                _ => writeln!(out, "    {}", inst).unwrap(),
+            }
+            // Where each call returns to, for the stack maps
+            for k in (0..function.safepoints.len()).filter(|&k| function.safepoints[k].inst == n) {
+                writeln!(out, ".L{}_r{}:", i, k).unwrap();
+            }
+        }
+        writeln!(out, "    .size {}, .-{}", symbol, symbol).unwrap();
+    }
+
+    let items = program.data.items();
+    let (vtables, constants): (Vec<_>, Vec<_>) = items.iter().enumerate().partition(|&(_, d)| match *d {
+        Datum::VTable(_) => true,
+        _ => false,
+    });
+    let main = program.function("why_main");
+    if !constants.is_empty() || main.is_some() {
+        writeln!(out).unwrap();
+        writeln!(out, "    .section .rodata").unwrap();
+    }
+    if let Some(main) = main {
+        writeln!(out, "    .globl why_main_type").unwrap();
+        writeln!(out, "    .type why_main_type, @object").unwrap();
+        writeln!(out, "why_main_type:").unwrap();
+        writeln!(out, "    .byte {}", main.ret as u8).unwrap();
+        writeln!(out, "    .globl why_main_layout").unwrap();
+        writeln!(out, "    .type why_main_layout, @object").unwrap();
+        writeln!(out, "why_main_layout:").unwrap();
+        writeln!(out, "    .asciz {}", quote(&program.layout)).unwrap();
+    }
+    for (n, datum) in constants {
+        match *datum {
+            Datum::Str(ref s) => {
+                writeln!(out, "    .p2align 3").unwrap();
+                writeln!(out, "    .quad 0").unwrap();
+                writeln!(out, "    .quad {}", s.len()).unwrap();
+                writeln!(out, "{}:", Sym::Data(n as u32)).unwrap();
+                writeln!(out, "    .asciz {}", quote(s)).unwrap();
+            }
+            Datum::Location(ref location) => {
+                writeln!(out, "{}:", Sym::Data(n as u32)).unwrap();
+                writeln!(out, "    .asciz {}", quote(location)).unwrap();
+            }
+            Datum::VTable(_) => {}
+        }
+    }
+    if !vtables.is_empty() || main.is_some() {
+        writeln!(out).unwrap();
+        writeln!(out, "    .data").unwrap();
+    }
+    if main.is_some() {
+        writeln!(out, "    .p2align 3").unwrap();
+        writeln!(out, "    .globl why_stack_maps").unwrap();
+        writeln!(out, "    .type why_stack_maps, @object").unwrap();
+        writeln!(out, "why_stack_maps:").unwrap();
+        for word in stack_maps(program) {
+            match word {
+                MapWord::Value(value) => writeln!(out, "    .quad {}", value as i64).unwrap(),
+                MapWord::Return(f, k) => writeln!(out, "    .quad .L{}_r{}", f, k).unwrap(),
+            }
+        }
+    }
+    for (n, datum) in vtables {
+        if let Datum::VTable(ref functions) = *datum {
+            writeln!(out, "    .p2align 3").unwrap();
+            writeln!(out, "{}:", Sym::Data(n as u32)).unwrap();
+            for function in functions {
+                writeln!(out, "    .quad {}", Sym::Global(function.clone())).unwrap();
+            }
+        }
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "    .section .note.GNU-stack,\"\",@progbits").unwrap();
+    out
+}
+
+/// Writes where the code that follows came from, unless it's the same place as the last comment
+fn annotate(out: &mut String, document: Option<&Document>, span: TextSpan, last: &mut Option<String>) {
+    let location = source_location(document, Some(span));
+    if last.as_ref() != Some(&location) {
+        writeln!(out, "    # {}", location).unwrap();
+        *last = Some(location);
+    }
+}
+
+/// Quotes a string for the assembler, escaping everything but printable ASCII
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in s.as_bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(byte as char);
+            }
+            0x20...0x7e => quoted.push(byte as char),
+            _ => write!(quoted, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,398 @@
+//! Writes DWARF 4 debug information for a compiled program, so a debugger can show where in the source the code is
+//! and the values of the variables there
+//!
+//! `.debug_line` maps the address of the code of each call, trapping operation and function back to the line and
+//! column it came from. `.debug_info` describes the program as one compile unit, with an entry for each function and,
+//! under it, each of its variables, and `.debug_abbrev` holds the abbreviations those entries are written with. A
+//! variable is found in the register or stack slot its value was given, but only over the code the value is live
+//! across, which is listed in `.debug_loc`; elsewhere it's shown as optimized out.
+
+use std::env;
+
+use elf::{put16, put32, put64, ObjectFile, Relocation, RelocationKind, Section, SectionKind, Target};
+use ir::Ty;
+use text::{Document, TextSpan};
+use wasm::{put_signed, put_unsigned};
+use x86::{CompiledFunction, DebugVariable, Inst, Mem, Operand, Reg};
+
+const DW_TAG_FORMAL_PARAMETER: u64 = 0x05;
+const DW_TAG_POINTER_TYPE: u64 = 0x0f;
+const DW_TAG_COMPILE_UNIT: u64 = 0x11;
+const DW_TAG_BASE_TYPE: u64 = 0x24;
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+const DW_TAG_VARIABLE: u64 = 0x34;
+
+const DW_AT_LOCATION: u64 = 0x02;
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_BYTE_SIZE: u64 = 0x0b;
+const DW_AT_STMT_LIST: u64 = 0x10;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_COMP_DIR: u64 = 0x1b;
+const DW_AT_PRODUCER: u64 = 0x25;
+const DW_AT_DECL_COLUMN: u64 = 0x39;
+const DW_AT_DECL_FILE: u64 = 0x3a;
+const DW_AT_DECL_LINE: u64 = 0x3b;
+const DW_AT_ENCODING: u64 = 0x3e;
+const DW_AT_EXTERNAL: u64 = 0x3f;
+const DW_AT_FRAME_BASE: u64 = 0x40;
+const DW_AT_TYPE: u64 = 0x49;
+
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_REF4: u64 = 0x13;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_FORM_EXPRLOC: u64 = 0x18;
+const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+
+const DW_ATE_BOOLEAN: u8 = 0x02;
+const DW_ATE_FLOAT: u8 = 0x04;
+const DW_ATE_SIGNED: u8 = 0x05;
+const DW_ATE_UNSIGNED_CHAR: u8 = 0x08;
+
+const DW_OP_REG0: u8 = 0x50;
+const DW_OP_FBREG: u8 = 0x91;
+
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_COLUMN: u8 = 0x05;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+
+/// The abbreviation codes of the entries, which are their positions in `ABBREVIATIONS` plus one
+const COMPILE_UNIT: u64 = 1;
+const BASE_TYPE: u64 = 2;
+const POINTER_TYPE: u64 = 3;
+const OPAQUE_POINTER_TYPE: u64 = 4;
+const FUNCTION: u64 = 5;
+const UNIT_FUNCTION: u64 = 6;
+const PARAMETER: u64 = 7;
+const VARIABLE: u64 = 8;
+
+/// The tag of each kind of entry, whether it has children, and its attributes and their forms
+const ABBREVIATIONS: [(u64, bool, &'static [(u64, u64)]); 8] = [
+    (DW_TAG_COMPILE_UNIT, true, &[
+        (DW_AT_PRODUCER, DW_FORM_STRING), (DW_AT_NAME, DW_FORM_STRING), (DW_AT_COMP_DIR, DW_FORM_STRING),
+        (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET), (DW_AT_LOW_PC, DW_FORM_ADDR), (DW_AT_HIGH_PC, DW_FORM_DATA8),
+    ]),
+    (DW_TAG_BASE_TYPE, false, &[
+        (DW_AT_NAME, DW_FORM_STRING), (DW_AT_ENCODING, DW_FORM_DATA1), (DW_AT_BYTE_SIZE, DW_FORM_DATA1),
+    ]),
+    (DW_TAG_POINTER_TYPE, false, &[
+        (DW_AT_NAME, DW_FORM_STRING), (DW_AT_BYTE_SIZE, DW_FORM_DATA1), (DW_AT_TYPE, DW_FORM_REF4),
+    ]),
+    (DW_TAG_POINTER_TYPE, false, &[(DW_AT_NAME, DW_FORM_STRING), (DW_AT_BYTE_SIZE, DW_FORM_DATA1)]),
+    (DW_TAG_SUBPROGRAM, true, &[
+        (DW_AT_NAME, DW_FORM_STRING), (DW_AT_EXTERNAL, DW_FORM_FLAG_PRESENT), (DW_AT_DECL_FILE, DW_FORM_DATA1),
+        (DW_AT_DECL_LINE, DW_FORM_UDATA), (DW_AT_TYPE, DW_FORM_REF4), (DW_AT_LOW_PC, DW_FORM_ADDR),
+        (DW_AT_HIGH_PC, DW_FORM_DATA8), (DW_AT_FRAME_BASE, DW_FORM_EXPRLOC),
+    ]),
+    (DW_TAG_SUBPROGRAM, true, &[
+        (DW_AT_NAME, DW_FORM_STRING), (DW_AT_EXTERNAL, DW_FORM_FLAG_PRESENT), (DW_AT_DECL_FILE, DW_FORM_DATA1),
+        (DW_AT_DECL_LINE, DW_FORM_UDATA), (DW_AT_LOW_PC, DW_FORM_ADDR), (DW_AT_HIGH_PC, DW_FORM_DATA8),
+        (DW_AT_FRAME_BASE, DW_FORM_EXPRLOC),
+    ]),
+    (DW_TAG_FORMAL_PARAMETER, false, &[
+        (DW_AT_NAME, DW_FORM_STRING), (DW_AT_DECL_FILE, DW_FORM_DATA1), (DW_AT_DECL_LINE, DW_FORM_UDATA),
+        (DW_AT_DECL_COLUMN, DW_FORM_UDATA), (DW_AT_TYPE, DW_FORM_REF4), (DW_AT_LOCATION, DW_FORM_SEC_OFFSET),
+    ]),
+    (DW_TAG_VARIABLE, false, &[
+        (DW_AT_NAME, DW_FORM_STRING), (DW_AT_DECL_FILE, DW_FORM_DATA1), (DW_AT_DECL_LINE, DW_FORM_UDATA),
+        (DW_AT_DECL_COLUMN, DW_FORM_UDATA), (DW_AT_TYPE, DW_FORM_REF4), (DW_AT_LOCATION, DW_FORM_SEC_OFFSET),
+    ]),
+];
+
+/// A function laid out in `.text`, with where each of its instructions starts in its code
+pub struct Placed<'a> {
+    pub function: &'a CompiledFunction,
+    pub start: u64,
+    pub size: u64,
+    pub offsets: Vec<usize>,
+}
+
+impl<'a> Placed<'a> {
+    /// Gets where an instruction starts, from the start of `.text`, or where the function ends for the index past its
+    /// last
+    fn address(&self, inst: usize) -> u64 {
+        self.start + self.offsets.get(inst).map_or(self.size, |&offset| offset as u64)
+    }
+}
+
+/// Gets the line and column of a span, counting from one
+fn position(document: &Document, span: TextSpan) -> (u64, u64) {
+    let (line, column) = document.line_map().map_offset(span.start() as u64);
+    (line + 1, column + 1)
+}
+
+/// The number DWARF gives a register, which isn't the one its encoding uses
+fn dwarf_register(reg: Reg) -> u8 {
+    match reg {
+        Reg::Rax => 0,
+        Reg::Rdx => 1,
+        Reg::Rcx => 2,
+        Reg::Rbx => 3,
+        Reg::Rsi => 4,
+        Reg::Rdi => 5,
+        Reg::Rbp => 6,
+        Reg::Rsp => 7,
+        other => other.number(),
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(s.as_bytes());
+    out.push(0);
+}
+
+/// Writes an address in `.text`, which the linker fills in
+fn put_address(section: &mut Section, text: usize, offset: u64) {
+    section.relocations.push(Relocation {
+        offset: section.bytes.len() as u64,
+        target: Target::Section(text),
+        kind: RelocationKind::Abs64,
+        addend: offset as i64,
+    });
+    put64(&mut section.bytes, 0);
+}
+
+/// Writes an offset into another debug section, which the linker fills in once it has put the sections together
+fn put_section_offset(section: &mut Section, target: usize, offset: usize) {
+    section.relocations.push(Relocation {
+        offset: section.bytes.len() as u64,
+        target: Target::Section(target),
+        kind: RelocationKind::Abs32,
+        addend: offset as i64,
+    });
+    put32(&mut section.bytes, 0);
+}
+
+/// Writes the location list of a variable, which is empty if its value isn't kept anywhere. Addresses in the list are
+/// relative to the start of `.text`, which is where the compile unit starts
+fn put_location_list(out: &mut Vec<u8>, place: &Placed, variable: &DebugVariable) {
+    if let Some((ref operand, ref live)) = variable.place {
+        let mut expr = Vec::new();
+        match *operand {
+            Operand::Reg(reg) => expr.push(DW_OP_REG0 + dwarf_register(reg)),
+            // The frame base is `rbp`, which is what stack slots are addressed from
+            Operand::Mem(Mem::Base(Reg::Rbp, disp)) => {
+                expr.push(DW_OP_FBREG);
+                put_signed(&mut expr, disp as i64);
+            }
+            _ => {}
+        }
+        let (start, end) = (place.address(live.start), place.address(live.end));
+        if !expr.is_empty() && start < end {
+            put64(out, start);
+            put64(out, end);
+            put16(out, expr.len() as u16);
+            out.extend_from_slice(&expr);
+        }
+    }
+    put64(out, 0);
+    put64(out, 0);
+}
+
+fn abbreviations() -> Vec<u8> {
+    let mut out = Vec::new();
+    for (i, &(tag, children, attributes)) in ABBREVIATIONS.iter().enumerate() {
+        put_unsigned(&mut out, i as u64 + 1);
+        put_unsigned(&mut out, tag);
+        out.push(children as u8);
+        for &(attribute, form) in attributes {
+            put_unsigned(&mut out, attribute);
+            put_unsigned(&mut out, form);
+        }
+        out.extend_from_slice(&[0, 0]);
+    }
+    out.push(0);
+    out
+}
+
+/// Writes the line number program, with a sequence of rows for each function
+fn line_program(section: &mut Section, text: usize, placed: &[Placed], document: &Document) {
+    put32(&mut section.bytes, 0);
+    put16(&mut section.bytes, 4);
+    let header_start = section.bytes.len();
+    put32(&mut section.bytes, 0);
+    // Instructions are bytes, and every row is a statement. Rows are added with the standard opcodes rather than the
+    // special ones, so the line base and range only have to be valid
+    section.bytes.extend_from_slice(&[1, 1, 1, -5i8 as u8, 14, 13]);
+    section.bytes.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+    // There are no include directories, and the only file is the program's, numbered one
+    section.bytes.push(0);
+    put_string(&mut section.bytes, &document.path().display().to_string());
+    section.bytes.extend_from_slice(&[0, 0, 0, 0]);
+    let header_length = (section.bytes.len() - header_start - 4) as u32;
+    section.bytes[header_start..header_start + 4].copy_from_slice(&u32_bytes(header_length));
+
+    for place in placed {
+        let mut rows: Vec<(usize, (u64, u64))> = Vec::new();
+        let sources = place.function.insts.iter().zip(&place.offsets).filter_map(|(inst, &offset)| match *inst {
+            Inst::Source(span) => Some((offset, span)),
+            _ => None,
+        });
+        for (offset, span) in place.function.span.map(|span| (0, span)).into_iter().chain(sources) {
+            let position = position(document, span);
+            match rows.last_mut() {
+                Some(last) if last.0 == offset => last.1 = position,
+                Some(last) if last.1 == position => {}
+                _ => rows.push((offset, position)),
+            }
+        }
+
+        let out = &mut section.bytes;
+        out.extend_from_slice(&[0, 9, DW_LNE_SET_ADDRESS]);
+        let at = out.len();
+        put64(out, 0);
+        section.relocations.push(Relocation {
+            offset: at as u64,
+            target: Target::Section(text),
+            kind: RelocationKind::Abs64,
+            addend: place.start as i64,
+        });
+        let (mut address, mut line) = (0, 1);
+        for (offset, (row_line, column)) in rows {
+            out.push(DW_LNS_ADVANCE_PC);
+            put_unsigned(out, (offset - address) as u64);
+            out.push(DW_LNS_ADVANCE_LINE);
+            put_signed(out, row_line as i64 - line as i64);
+            out.push(DW_LNS_SET_COLUMN);
+            put_unsigned(out, column);
+            out.push(DW_LNS_COPY);
+            address = offset;
+            line = row_line;
+        }
+        out.push(DW_LNS_ADVANCE_PC);
+        put_unsigned(out, place.size - address as u64);
+        out.extend_from_slice(&[0, 1, DW_LNE_END_SEQUENCE]);
+    }
+
+    let length = (section.bytes.len() - 4) as u32;
+    section.bytes[0..4].copy_from_slice(&u32_bytes(length));
+}
+
+/// Writes the compile unit, with the types values can have, then an entry for each function and its variables
+fn compile_unit(section: &mut Section, locations: &mut Vec<u8>, sections: &Sections, placed: &[Placed],
+                document: &Document) {
+    let text = sections.text;
+    put32(&mut section.bytes, 0);
+    put16(&mut section.bytes, 4);
+    put_section_offset(section, sections.abbrev, 0);
+    section.bytes.push(8);
+
+    let dir = env::current_dir().map(|d| d.display().to_string()).unwrap_or_default();
+    let end = placed.iter().map(|p| p.start + p.size).max().unwrap_or(0);
+    put_unsigned(&mut section.bytes, COMPILE_UNIT);
+    put_string(&mut section.bytes, "whyc");
+    put_string(&mut section.bytes, &document.path().display().to_string());
+    put_string(&mut section.bytes, &dir);
+    put_section_offset(section, sections.line, 0);
+    put_address(section, text, 0);
+    put64(&mut section.bytes, end);
+
+    // The types are referred to by their offsets in the unit
+    let int = base_type(section, "Int", DW_ATE_SIGNED, 8);
+    let float = base_type(section, "Float", DW_ATE_FLOAT, 8);
+    let bool_ = base_type(section, "Bool", DW_ATE_BOOLEAN, 1);
+    // A string points at its bytes, so debuggers can show it like a C string
+    let char_ = base_type(section, "char", DW_ATE_UNSIGNED_CHAR, 1);
+    let str_ = pointer_type(section, "Str", Some(char_));
+    let ptr = pointer_type(section, Ty::Ptr.name(), None);
+    let fn_ = pointer_type(section, Ty::Fn.name(), None);
+    let type_of = |ty: Ty| match ty {
+        Ty::Unit => None,
+        Ty::Int => Some(int),
+        Ty::Float => Some(float),
+        Ty::Bool => Some(bool_),
+        Ty::Str => Some(str_),
+        Ty::Ptr => Some(ptr),
+        Ty::Fn => Some(fn_),
+    };
+
+    for place in placed {
+        let function = place.function;
+        let returns = type_of(function.ret);
+        put_unsigned(&mut section.bytes, if returns.is_some() { FUNCTION } else { UNIT_FUNCTION });
+        put_string(&mut section.bytes, &function.name);
+        section.bytes.push(1);
+        put_unsigned(&mut section.bytes, function.span.map_or(0, |span| position(document, span).0));
+        if let Some(returns) = returns {
+            put32(&mut section.bytes, returns);
+        }
+        put_address(section, text, place.start);
+        put64(&mut section.bytes, place.size);
+        section.bytes.extend_from_slice(&[1, DW_OP_REG0 + dwarf_register(Reg::Rbp)]);
+
+        for variable in &function.variables {
+            let ty = match type_of(variable.ty) {
+                Some(ty) => ty,
+                None => continue,
+            };
+            let (line, column) = position(document, variable.span);
+            put_unsigned(&mut section.bytes, if variable.param { PARAMETER } else { VARIABLE });
+            put_string(&mut section.bytes, &variable.name);
+            section.bytes.push(1);
+            put_unsigned(&mut section.bytes, line);
+            put_unsigned(&mut section.bytes, column);
+            put32(&mut section.bytes, ty);
+            put_section_offset(section, sections.loc, locations.len());
+            put_location_list(locations, place, variable);
+        }
+        section.bytes.push(0);
+    }
+    section.bytes.push(0);
+
+    let length = (section.bytes.len() - 4) as u32;
+    section.bytes[0..4].copy_from_slice(&u32_bytes(length));
+}
+
+fn base_type(section: &mut Section, name: &str, encoding: u8, size: u8) -> u32 {
+    let at = section.bytes.len() as u32;
+    put_unsigned(&mut section.bytes, BASE_TYPE);
+    put_string(&mut section.bytes, name);
+    section.bytes.extend_from_slice(&[encoding, size]);
+    at
+}
+
+/// Writes a pointer type, which points at a value of another type unless it's opaque
+fn pointer_type(section: &mut Section, name: &str, target: Option<u32>) -> u32 {
+    let at = section.bytes.len() as u32;
+    put_unsigned(&mut section.bytes, if target.is_some() { POINTER_TYPE } else { OPAQUE_POINTER_TYPE });
+    put_string(&mut section.bytes, name);
+    section.bytes.push(8);
+    if let Some(target) = target {
+        put32(&mut section.bytes, target);
+    }
+    at
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+/// The positions of the sections debug information refers to
+struct Sections {
+    text: usize,
+    abbrev: usize,
+    line: usize,
+    loc: usize,
+}
+
+/// Adds `.debug_abbrev`, `.debug_info`, `.debug_line` and `.debug_loc` to an object whose functions have been laid out
+/// in the section `text`
+pub fn add_debug_sections(object: &mut ObjectFile, text: usize, placed: &[Placed], document: &Document) {
+    let abbrev = object.add_section(".debug_abbrev", SectionKind::Debug, 1);
+    let info = object.add_section(".debug_info", SectionKind::Debug, 1);
+    let line = object.add_section(".debug_line", SectionKind::Debug, 1);
+    let loc = object.add_section(".debug_loc", SectionKind::Debug, 1);
+    let sections = Sections { text, abbrev, line, loc };
+    object.sections[abbrev].bytes = abbreviations();
+    let mut locations = Vec::new();
+    compile_unit(&mut object.sections[info], &mut locations, &sections, placed, document);
+    object.sections[loc].bytes = locations;
+    line_program(&mut object.sections[line], text, placed, document);
+}
@@ -0,0 +1,618 @@
+use arith::OverflowMode;
+use parser::BinOp;
+use text::TextSpan;
+use x86::{Allocation, AluOp, Callee, Class, Cond, Inst, Label, Loc, MFunction, MInst, MTerminator, Mem, Operand, Reg,
+          SseOp, Sym, TrapKind, VReg, FLOAT_ARGS, INT_ARGS};
+
+/// Holds one value while the others of a cycle of copies move, and the results of sequences that need a register
+const SCRATCH: Reg = Reg::R11;
+
+/// Holds a value read from memory on its way to memory
+const SCRATCH2: Reg = Reg::R10;
+
+/// Turns a function whose registers have been allocated into machine instructions
+///
+/// The frame saves `rbp` and the callee-saved registers the function uses, then holds the stack slots, keeping the
+/// stack aligned to 16 bytes at every call. Failures jump to code at the end of the function that calls `why_trap`
+/// with what went wrong and where.
+///
+/// Along with the instructions comes the index of the first one emitted for each instruction and terminator of the
+/// function, in the order liveness numbers them, so debug information can tell which code a value is live over. Last
+/// is the index of the machine call made for each call of the function, so the collector can be told what's live
+/// across it.
+pub fn emit_function(function: &MFunction, allocation: &Allocation) -> (Vec<Inst>, Vec<usize>, Vec<usize>) {
+    let saved = allocation.saved.len() as u32;
+    let mut frame = 8 * allocation.slots;
+    if (8 * saved + frame) % 16 != 0 {
+        frame += 8;
+    }
+    let mut emitter = Emitter {
+        allocation,
+        saved: &allocation.saved,
+        overflow: function.overflow,
+        insts: Vec::new(),
+        starts: Vec::new(),
+        calls: Vec::new(),
+        next_label: function.blocks.len() as u32,
+        source: None,
+        traps: Vec::new(),
+    };
+
+    emitter.push(Inst::Push(Operand::Reg(Reg::Rbp)));
+    emitter.push(Inst::Mov(Operand::Reg(Reg::Rbp), Operand::Reg(Reg::Rsp)));
+    for &reg in &allocation.saved {
+        emitter.push(Inst::Push(Operand::Reg(reg)));
+    }
+    if frame > 0 {
+        emitter.push(Inst::Alu(AluOp::Sub, Operand::Reg(Reg::Rsp), Operand::Imm(frame as i32)));
+    }
+
+    for (b, block) in function.blocks.iter().enumerate() {
+        emitter.push(Inst::Label(Label(b as u32)));
+        for inst in &block.insts {
+            emitter.starts.push(emitter.insts.len());
+            emitter.emit_inst(inst);
+        }
+        emitter.starts.push(emitter.insts.len());
+        emitter.emit_terminator(&block.terminator, b + 1);
+    }
+
+    let traps = ::std::mem::replace(&mut emitter.traps, Vec::new());
+    for (label, kind, location, source) in traps {
+        emitter.push(Inst::Label(label));
+        if let Some(span) = source {
+            emitter.push(Inst::Source(span));
+        }
+        emitter.push(Inst::Mov(Operand::Reg(Reg::Rdi), Operand::Imm(kind as i32)));
+        emitter.push(Inst::Lea(Reg::Rsi, Mem::Rip(location)));
+        emitter.push(Inst::Call(Sym::Global("why_trap".into())));
+        emitter.push(Inst::Ud2);
+    }
+    emitter.remove_jumps_to_next();
+    (emitter.insts, emitter.starts, emitter.calls)
+}
+
+/// Where a copy reads from
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Source {
+    Loc(Loc),
+    /// Memory no copy writes, like the stack arguments of the function
+    Mem(Mem),
+    Xmm(u8),
+}
+
+struct Emitter<'a> {
+    allocation: &'a Allocation,
+    saved: &'a [Reg],
+    overflow: OverflowMode,
+    insts: Vec<Inst>,
+
+    /// The index of the first instruction of each instruction and terminator of the function
+    starts: Vec<usize>,
+
+    /// The index of the machine call of each call of the function
+    calls: Vec<usize>,
+    next_label: u32,
+
+    /// The source of the code being emitted, which is also that of the failures it reports
+    source: Option<TextSpan>,
+    traps: Vec<(Label, TrapKind, Sym, Option<TextSpan>)>,
+}
+
+impl<'a> Emitter<'a> {
+    fn push(&mut self, inst: Inst) {
+        self.insts.push(inst);
+    }
+
+    fn label(&mut self) -> Label {
+        self.next_label += 1;
+        Label(self.next_label - 1)
+    }
+
+    /// Gets the label of the code that reports a failure, sharing it between failures of the same kind and place
+    fn trap(&mut self, kind: TrapKind, location: &Sym) -> Label {
+        if let Some(&(label, ..)) = self.traps.iter().find(|&&(_, k, ref l, _)| k == kind && l == location) {
+            return label;
+        }
+        let label = self.label();
+        let source = self.source;
+        self.traps.push((label, kind, location.clone(), source));
+        label
+    }
+
+    fn loc(&self, vreg: VReg) -> Loc {
+        self.allocation.loc(vreg)
+    }
+
+    fn operand(&self, loc: Loc) -> Operand {
+        match loc {
+            Loc::Reg(reg) => Operand::Reg(reg),
+            Loc::Stack(slot) => Operand::Mem(self.slot(slot)),
+        }
+    }
+
+    fn slot(&self, slot: u32) -> Mem {
+        self.allocation.slot(slot)
+    }
+
+    fn op(&self, vreg: VReg) -> Operand {
+        self.operand(self.loc(vreg))
+    }
+
+    /// Moves between any two operands, going through a register if both are in memory
+    fn mov(&mut self, dst: Operand, src: Operand) {
+        match (&dst, &src) {
+            _ if dst == src => {}
+            (&Operand::Mem(_), &Operand::Mem(_)) => {
+                self.push(Inst::Mov(Operand::Reg(SCRATCH2), src));
+                self.push(Inst::Mov(dst, Operand::Reg(SCRATCH2)));
+            }
+            _ => self.push(Inst::Mov(dst, src)),
+        }
+    }
+
+    /// Gets a register to compute a result in: the destination's if it has one, otherwise the scratch register, which
+    /// `finish` then stores
+    fn result_reg(&self, dst: VReg) -> Reg {
+        match self.loc(dst) {
+            Loc::Reg(reg) => reg,
+            Loc::Stack(_) => SCRATCH,
+        }
+    }
+
+    fn finish(&mut self, dst: VReg, reg: Reg) {
+        let dst = self.op(dst);
+        self.mov(dst, Operand::Reg(reg));
+    }
+
+    fn emit_inst(&mut self, inst: &MInst) {
+        match *inst {
+            MInst::Params(ref params) => self.emit_params(params),
+            MInst::Imm(dst, value) => {
+                if value as i32 as i64 == value {
+                    let dst = self.op(dst);
+                    self.push(Inst::Mov(dst, Operand::Imm(value as i32)));
+                } else {
+                    let reg = self.result_reg(dst);
+                    self.push(Inst::MovAbs(reg, value));
+                    self.finish(dst, reg);
+                }
+            }
+            MInst::Addr(dst, ref sym) => {
+                let reg = self.result_reg(dst);
+                self.push(Inst::Lea(reg, Mem::Rip(sym.clone())));
+                self.finish(dst, reg);
+            }
+            MInst::Int(BinOp::Divide, dst, a, b, ref location) => self.emit_divide(dst, a, b, location),
+            MInst::Int(op, dst, a, b, ref location) => self.emit_int(op, dst, a, b, location),
+            MInst::Float(op, dst, a, b) => {
+                let (a, b) = (self.op(a), self.op(b));
+                self.push(Inst::MovqToXmm(0, a));
+                self.push(Inst::MovqToXmm(1, b));
+                let op = match op {
+                    BinOp::Add => SseOp::Add,
+                    BinOp::Subtract => SseOp::Sub,
+                    BinOp::Multiply => SseOp::Mul,
+                    _ => SseOp::Div,
+                };
+                self.push(Inst::Sse(op, 0, 1));
+                let dst = self.op(dst);
+                self.push(Inst::MovqFromXmm(dst, 0));
+            }
+            MInst::IntCmp(op, dst, a, b) => {
+                self.compare(a, b);
+                self.push(Inst::Set(int_cond(op), SCRATCH));
+                self.zero_extend(dst, SCRATCH);
+            }
+            MInst::FloatCmp(op, dst, a, b) => self.emit_float_compare(op, dst, a, b),
+            MInst::Call(ref callee, ref args, ret) => self.emit_call(callee, args, ret),
+            MInst::Load(dst, base, disp) => {
+                let base = self.base_reg(base, SCRATCH);
+                let reg = self.result_reg(dst);
+                self.push(Inst::Mov(Operand::Reg(reg), Operand::Mem(Mem::Base(base, disp))));
+                self.finish(dst, reg);
+            }
+            MInst::Store(base, disp, value) => {
+                let base = self.base_reg(base, SCRATCH);
+                let value = match self.op(value) {
+                    Operand::Mem(mem) => {
+                        self.push(Inst::Mov(Operand::Reg(SCRATCH2), Operand::Mem(mem)));
+                        Operand::Reg(SCRATCH2)
+                    }
+                    value => value,
+                };
+                self.push(Inst::Mov(Operand::Mem(Mem::Base(base, disp)), value));
+            }
+            MInst::ZeroExtend(dst, src) => {
+                let src = self.base_reg(src, SCRATCH);
+                self.zero_extend(dst, src);
+            }
+            MInst::SignExtend(dst, src) => {
+                let src = self.base_reg(src, SCRATCH);
+                let reg = self.result_reg(dst);
+                self.push(Inst::MovsxDword(reg, src));
+                self.finish(dst, reg);
+            }
+            MInst::Source(span) => {
+                self.source = Some(span);
+                self.push(Inst::Source(span));
+            }
+            MInst::Copies(ref copies) => {
+                let moves = copies.iter().map(|&(dst, src)| (self.loc(dst), Source::Loc(self.loc(src)))).collect();
+                self.parallel_move(moves);
+            }
+        }
+    }
+
+    /// Gets a register holding an address, loading it into `scratch` if it was spilled
+    fn base_reg(&mut self, vreg: VReg, scratch: Reg) -> Reg {
+        match self.loc(vreg) {
+            Loc::Reg(reg) => reg,
+            Loc::Stack(slot) => {
+                let slot = self.slot(slot);
+                self.push(Inst::Mov(Operand::Reg(scratch), Operand::Mem(slot)));
+                scratch
+            }
+        }
+    }
+
+    /// Sets the flags by comparing two values
+    fn compare(&mut self, a: VReg, b: VReg) {
+        let (a, b) = (self.op(a), self.op(b));
+        let a = match a {
+            Operand::Reg(_) => a,
+            _ => {
+                self.push(Inst::Mov(Operand::Reg(SCRATCH), a));
+                Operand::Reg(SCRATCH)
+            }
+        };
+        self.push(Inst::Alu(AluOp::Cmp, a, b));
+    }
+
+    /// Stores the low byte of a register, set by a `set`, as a whole word
+    fn zero_extend(&mut self, dst: VReg, byte: Reg) {
+        let reg = self.result_reg(dst);
+        self.push(Inst::MovzxByte(reg, byte));
+        self.finish(dst, reg);
+    }
+
+    fn emit_params(&mut self, params: &[(VReg, Class)]) {
+        let (mut ints, mut floats, mut stack) = (0, 0, 0);
+        let mut moves = Vec::new();
+        for &(v, class) in params {
+            let source = match class {
+                Class::Int if ints < INT_ARGS.len() => {
+                    ints += 1;
+                    Source::Loc(Loc::Reg(INT_ARGS[ints - 1]))
+                }
+                Class::Float if floats < FLOAT_ARGS => {
+                    floats += 1;
+                    Source::Xmm(floats - 1)
+                }
+                _ => {
+                    // Above the saved `rbp` and the return address
+                    stack += 1;
+                    Source::Mem(Mem::Base(Reg::Rbp, 16 + 8 * (stack - 1)))
+                }
+            };
+            if let Some(loc) = self.allocation.locs[v.index()] {
+                moves.push((loc, source));
+            }
+        }
+        self.parallel_move(moves);
+    }
+
+    /// Makes a set of copies as if they all read their sources before any wrote its destination
+    ///
+    /// A copy is made once nothing left to copy reads its destination. When every copy left reads another's
+    /// destination, they form cycles, and one value is set aside in the scratch register to break one.
+    fn parallel_move(&mut self, moves: Vec<(Loc, Source)>) {
+        let mut moves: Vec<(Loc, Source)> = moves.into_iter().filter(|&(dst, ref src)| *src != Source::Loc(dst)).collect();
+        while !moves.is_empty() {
+            let ready = moves.iter().position(|&(dst, _)| !moves.iter().any(|&(_, ref src)| *src == Source::Loc(dst)));
+            match ready {
+                Some(i) => {
+                    let (dst, src) = moves.remove(i);
+                    let dst = self.operand(dst);
+                    match src {
+                        Source::Loc(loc) => {
+                            let src = self.operand(loc);
+                            self.mov(dst, src);
+                        }
+                        Source::Mem(mem) => self.mov(dst, Operand::Mem(mem)),
+                        Source::Xmm(xmm) => self.push(Inst::MovqFromXmm(dst, xmm)),
+                    }
+                }
+                None => {
+                    let blocked = moves[0].0;
+                    let operand = self.operand(blocked);
+                    self.push(Inst::Mov(Operand::Reg(SCRATCH), operand));
+                    for &mut (_, ref mut src) in moves.iter_mut() {
+                        if *src == Source::Loc(blocked) {
+                            *src = Source::Loc(Loc::Reg(SCRATCH));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Computes `+`, `-` or `*`, handling overflow as the module says
+    fn emit_int(&mut self, op: BinOp, dst: VReg, a: VReg, b: VReg, location: &Sym) {
+        let (dst_op, a_op, b_op) = (self.op(dst), self.op(a), self.op(b));
+        let commutative = op != BinOp::Subtract;
+        // Compute in the destination if the second operand can still be read after the first is moved there
+        let reg = match dst_op {
+            Operand::Reg(reg) if self.overflow != OverflowMode::Saturate && (dst_op != b_op || commutative) => reg,
+            _ => SCRATCH,
+        };
+        let other = if Operand::Reg(reg) == b_op {
+            a_op.clone()
+        } else {
+            self.mov(Operand::Reg(reg), a_op.clone());
+            b_op.clone()
+        };
+        match op {
+            BinOp::Add => self.push(Inst::Alu(AluOp::Add, Operand::Reg(reg), other)),
+            BinOp::Subtract => self.push(Inst::Alu(AluOp::Sub, Operand::Reg(reg), other)),
+            _ => self.push(Inst::IMul(reg, other)),
+        }
+
+        match self.overflow {
+            OverflowMode::Trap => {
+                let trap = self.trap(TrapKind::Overflow, location);
+                self.push(Inst::Jcc(Cond::O, trap));
+            }
+            OverflowMode::Saturate => {
+                // The result is too large if the exact result would be positive: if the first operand is for `+` and
+                // `-`, or if the operands have the same sign for `*`
+                let done = self.label();
+                self.push(Inst::Jcc(Cond::No, done));
+                self.push(Inst::Mov(Operand::Reg(SCRATCH), a_op));
+                if op == BinOp::Multiply {
+                    self.push(Inst::Alu(AluOp::Xor, Operand::Reg(SCRATCH), b_op));
+                }
+                self.saturate(SCRATCH);
+                self.push(Inst::Label(done));
+            }
+            _ => {}
+        }
+        self.finish(dst, reg);
+    }
+
+    /// Turns a register whose sign is that of the exact result into the largest or smallest `int`
+    fn saturate(&mut self, reg: Reg) {
+        self.push(Inst::Sar(Operand::Reg(reg), 63));
+        self.push(Inst::MovAbs(SCRATCH2, ::std::i64::MAX));
+        self.push(Inst::Alu(AluOp::Xor, Operand::Reg(reg), Operand::Reg(SCRATCH2)));
+    }
+
+    /// Divides with `idiv`, which faults on both division by zero and `i64::MIN / -1`, so both are checked first.
+    /// Dividing by -1 is negation, whose overflow is handled like any other
+    fn emit_divide(&mut self, dst: VReg, a: VReg, b: VReg, location: &Sym) {
+        let (a, b) = (self.op(a), self.op(b));
+        self.push(Inst::Mov(Operand::Reg(Reg::Rax), a));
+        self.push(Inst::Mov(Operand::Reg(SCRATCH), b));
+        self.push(Inst::Test(Operand::Reg(SCRATCH), SCRATCH));
+        let by_zero = self.trap(TrapKind::DivideByZero, location);
+        self.push(Inst::Jcc(Cond::E, by_zero));
+
+        let (divide, done) = (self.label(), self.label());
+        self.push(Inst::Alu(AluOp::Cmp, Operand::Reg(SCRATCH), Operand::Imm(-1)));
+        self.push(Inst::Jcc(Cond::Ne, divide));
+        self.push(Inst::Neg(Operand::Reg(Reg::Rax)));
+        match self.overflow {
+            OverflowMode::Trap => {
+                let trap = self.trap(TrapKind::Overflow, location);
+                self.push(Inst::Jcc(Cond::O, trap));
+            }
+            OverflowMode::Saturate => {
+                self.push(Inst::Jcc(Cond::No, done));
+                self.push(Inst::MovAbs(Reg::Rax, ::std::i64::MAX));
+            }
+            _ => {}
+        }
+        self.push(Inst::Jmp(done));
+        self.push(Inst::Label(divide));
+        self.push(Inst::Cqo);
+        self.push(Inst::IDiv(Operand::Reg(SCRATCH)));
+        self.push(Inst::Label(done));
+        self.finish(dst, Reg::Rax);
+    }
+
+    /// Compares floats with `ucomisd`, which sets the parity flag when either is NaN. Only `!=` holds then, and `<` and
+    /// `<=` swap their operands so that NaN fails them the same way it fails `>` and `>=`
+    fn emit_float_compare(&mut self, op: BinOp, dst: VReg, a: VReg, b: VReg) {
+        let (a, b) = (self.op(a), self.op(b));
+        self.push(Inst::MovqToXmm(0, a));
+        self.push(Inst::MovqToXmm(1, b));
+        match op {
+            BinOp::Less | BinOp::LessEqual => self.push(Inst::Sse(SseOp::Ucomi, 1, 0)),
+            _ => self.push(Inst::Sse(SseOp::Ucomi, 0, 1)),
+        }
+        match op {
+            BinOp::Equal | BinOp::NotEqual => {
+                let (cond, parity, combine) = if op == BinOp::Equal {
+                    (Cond::E, Cond::Np, AluOp::And)
+                } else {
+                    (Cond::Ne, Cond::P, AluOp::Or)
+                };
+                self.push(Inst::Set(cond, SCRATCH));
+                self.push(Inst::Set(parity, SCRATCH2));
+                self.push(Inst::MovzxByte(SCRATCH, SCRATCH));
+                self.push(Inst::MovzxByte(SCRATCH2, SCRATCH2));
+                self.push(Inst::Alu(combine, Operand::Reg(SCRATCH), Operand::Reg(SCRATCH2)));
+                self.finish(dst, SCRATCH);
+            }
+            _ => {
+                let cond = match op {
+                    BinOp::Less | BinOp::Greater => Cond::A,
+                    _ => Cond::Ae,
+                };
+                self.push(Inst::Set(cond, SCRATCH));
+                self.zero_extend(dst, SCRATCH);
+            }
+        }
+    }
+
+    /// Calls by the System V convention: arguments that don't fit in registers are pushed last to first, floats are
+    /// moved to `xmm` registers before the integer registers are overwritten, and `al` holds how many `xmm` registers
+    /// are used in case the callee takes variable arguments
+    fn emit_call(&mut self, callee: &Callee, args: &[(VReg, Class)], ret: Option<(VReg, Class)>) {
+        let (mut ints, mut floats, mut stack) = (Vec::new(), Vec::new(), Vec::new());
+        for &(v, class) in args {
+            match class {
+                Class::Int if ints.len() < INT_ARGS.len() => ints.push(v),
+                Class::Float if floats.len() < FLOAT_ARGS as usize => floats.push(v),
+                _ => stack.push(v),
+            }
+        }
+
+        let padding = if stack.len() % 2 == 1 { 8 } else { 0 };
+        if padding > 0 {
+            self.push(Inst::Alu(AluOp::Sub, Operand::Reg(Reg::Rsp), Operand::Imm(padding)));
+        }
+        for &v in stack.iter().rev() {
+            let operand = self.op(v);
+            self.push(Inst::Push(operand));
+        }
+        if let Callee::Indirect(f) = *callee {
+            let f = self.op(f);
+            self.push(Inst::Mov(Operand::Reg(Reg::Rax), f));
+        }
+        for (i, &v) in floats.iter().enumerate() {
+            let operand = self.op(v);
+            self.push(Inst::MovqToXmm(i as u8, operand));
+        }
+        let moves = ints.iter().zip(INT_ARGS.iter()).map(|(&v, &reg)| (Loc::Reg(reg), Source::Loc(self.loc(v)))).collect();
+        self.parallel_move(moves);
+
+        match *callee {
+            Callee::Direct(ref sym) => {
+                self.push(Inst::Mov(Operand::Reg(Reg::Rax), Operand::Imm(floats.len() as i32)));
+                self.calls.push(self.insts.len());
+                self.push(Inst::Call(sym.clone()));
+            }
+            Callee::Indirect(_) => {
+                self.calls.push(self.insts.len());
+                self.push(Inst::CallIndirect(Operand::Reg(Reg::Rax)));
+            }
+        }
+        let popped = 8 * stack.len() as i32 + padding;
+        if popped > 0 {
+            self.push(Inst::Alu(AluOp::Add, Operand::Reg(Reg::Rsp), Operand::Imm(popped)));
+        }
+
+        match ret {
+            Some((v, Class::Int)) => {
+                let dst = self.op(v);
+                self.push(Inst::Mov(dst, Operand::Reg(Reg::Rax)));
+            }
+            Some((v, Class::Float)) => {
+                let dst = self.op(v);
+                self.push(Inst::MovqFromXmm(dst, 0));
+            }
+            None => {}
+        }
+    }
+
+    fn emit_terminator(&mut self, terminator: &MTerminator, next: usize) {
+        match *terminator {
+            MTerminator::Jump(target) => self.push(Inst::Jmp(Label(target as u32))),
+            MTerminator::Branch(cond, then, otherwise) => {
+                let cond = self.op(cond);
+                self.push(Inst::Alu(AluOp::Cmp, cond, Operand::Imm(0)));
+                self.branch(Cond::Ne, then, otherwise, next);
+            }
+            MTerminator::CmpBranch(op, a, b, then, otherwise) => {
+                self.compare(a, b);
+                self.branch(int_cond(op), then, otherwise, next);
+            }
+            MTerminator::Return(value) => {
+                match value {
+                    Some((v, Class::Int)) => {
+                        let v = self.op(v);
+                        self.push(Inst::Mov(Operand::Reg(Reg::Rax), v));
+                    }
+                    Some((v, Class::Float)) => {
+                        let v = self.op(v);
+                        self.push(Inst::MovqToXmm(0, v));
+                    }
+                    None => {}
+                }
+                if self.saved.is_empty() {
+                    self.push(Inst::Mov(Operand::Reg(Reg::Rsp), Operand::Reg(Reg::Rbp)));
+                } else {
+                    let top = Mem::Base(Reg::Rbp, -8 * self.saved.len() as i32);
+                    self.push(Inst::Lea(Reg::Rsp, top));
+                }
+                for &reg in self.saved.iter().rev() {
+                    self.push(Inst::Pop(reg));
+                }
+                self.push(Inst::Pop(Reg::Rbp));
+                self.push(Inst::Ret);
+            }
+            MTerminator::Unreachable => self.push(Inst::Ud2),
+        }
+    }
+
+    /// Jumps to one block if a condition holds and another if not, falling through to whichever comes next
+    fn branch(&mut self, cond: Cond, then: usize, otherwise: usize, next: usize) {
+        if then == next {
+            self.push(Inst::Jcc(negate(cond), Label(otherwise as u32)));
+        } else {
+            self.push(Inst::Jcc(cond, Label(then as u32)));
+            self.push(Inst::Jmp(Label(otherwise as u32)));
+        }
+    }
+
+    /// Removes the jumps to the label right after them
+    fn remove_jumps_to_next(&mut self) {
+        let insts = ::std::mem::replace(&mut self.insts, Vec::new());
+        // Where each instruction ends up, so the starts and calls can be moved back past the jumps that are removed
+        let mut moved = Vec::with_capacity(insts.len() + 1);
+        for (i, inst) in insts.iter().enumerate() {
+            moved.push(self.insts.len());
+            if let Inst::Jmp(label) = *inst {
+                if insts.get(i + 1) == Some(&Inst::Label(label)) {
+                    continue;
+                }
+            }
+            self.insts.push(inst.clone());
+        }
+        moved.push(self.insts.len());
+        for start in self.starts.iter_mut().chain(&mut self.calls) {
+            *start = moved[*start];
+        }
+    }
+}
+
+fn int_cond(op: BinOp) -> Cond {
+    match op {
+        BinOp::Equal => Cond::E,
+        BinOp::NotEqual => Cond::Ne,
+        BinOp::Less => Cond::L,
+        BinOp::LessEqual => Cond::Le,
+        BinOp::Greater => Cond::G,
+        _ => Cond::Ge,
+    }
+}
+
+fn negate(cond: Cond) -> Cond {
+    match cond {
+        Cond::O => Cond::No,
+        Cond::No => Cond::O,
+        Cond::B => Cond::Ae,
+        Cond::Ae => Cond::B,
+        Cond::E => Cond::Ne,
+        Cond::Ne => Cond::E,
+        Cond::Be => Cond::A,
+        Cond::A => Cond::Be,
+        Cond::P => Cond::Np,
+        Cond::Np => Cond::P,
+        Cond::L => Cond::Ge,
+        Cond::Ge => Cond::L,
+        Cond::Le => Cond::G,
+        Cond::G => Cond::Le,
+    }
+}
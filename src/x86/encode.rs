@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use x86::{AluOp, Inst, Label, Mem, Operand, Reg, SseOp, Sym};
+
+/// How the linker fills in the address of a symbol
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FixupKind {
+    /// The 32-bit distance to the symbol
+    Pc32,
+
+    /// The 32-bit distance to the function, or to a stub that jumps to it if it's in a shared library
+    Plt32,
+}
+
+/// A place in the code where the address of a symbol goes once it's known
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Fixup {
+    pub offset: usize,
+    pub sym: Sym,
+    pub kind: FixupKind,
+
+    /// What's added to the address, which for distances makes up for them being measured from the end of the
+    /// instruction rather than from the fixup
+    pub addend: i64,
+}
+
+/// The machine code of a function
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Code {
+    pub bytes: Vec<u8>,
+    pub fixups: Vec<Fixup>,
+
+    /// Where each instruction starts, for debug information
+    pub offsets: Vec<usize>,
+}
+
+/// Encodes instructions as machine code
+///
+/// Jumps within the function always take a 32-bit distance, so each instruction's size is known when it's encoded
+/// and the distances are filled in once every label has been seen.
+pub fn encode(insts: &[Inst]) -> Code {
+    let mut encoder = Encoder { bytes: Vec::new(), fixups: Vec::new(), labels: HashMap::new(), jumps: Vec::new() };
+    let mut offsets = Vec::new();
+    for inst in insts {
+        offsets.push(encoder.bytes.len());
+        encoder.inst(inst);
+    }
+    for (at, label) in encoder.jumps {
+        let target = encoder.labels[&label] as i64;
+        let distance = (target - (at as i64 + 4)) as i32;
+        encoder.bytes[at..at + 4].copy_from_slice(&le32(distance));
+    }
+    Code { bytes: encoder.bytes, fixups: encoder.fixups, offsets }
+}
+
+fn le32(value: i32) -> [u8; 4] {
+    let v = value as u32;
+    [v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]
+}
+
+/// The operand of an instruction that's encoded by its ModRM byte
+enum Rm<'a> {
+    Reg(u8),
+    Mem(&'a Mem),
+}
+
+fn rm<'a>(operand: &'a Operand) -> Rm<'a> {
+    match *operand {
+        Operand::Reg(reg) => Rm::Reg(reg.number()),
+        Operand::Mem(ref mem) => Rm::Mem(mem),
+        Operand::Imm(_) => panic!("Expected a register or memory operand, not an immediate"),
+    }
+}
+
+fn alu_extension(op: AluOp) -> u8 {
+    match op {
+        AluOp::Add => 0,
+        AluOp::Or => 1,
+        AluOp::And => 4,
+        AluOp::Sub => 5,
+        AluOp::Xor => 6,
+        AluOp::Cmp => 7,
+    }
+}
+
+/// Gets the mandatory prefix and the opcode of an SSE2 operation
+fn sse_opcode(op: SseOp) -> (u8, u8) {
+    match op {
+        SseOp::Add => (0xf2, 0x58),
+        SseOp::Sub => (0xf2, 0x5c),
+        SseOp::Mul => (0xf2, 0x59),
+        SseOp::Div => (0xf2, 0x5e),
+        SseOp::Ucomi => (0x66, 0x2e),
+    }
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+    fixups: Vec<Fixup>,
+    labels: HashMap<Label, usize>,
+
+    /// Where the distances to labels go
+    jumps: Vec<(usize, Label)>,
+}
+
+impl Encoder {
+    fn byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    fn imm32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&le32(value));
+    }
+
+    /// Writes an instruction made of an optional prefix, a REX prefix if it's needed, the opcode and a ModRM operand,
+    /// followed by `imm` bytes of immediate the caller writes
+    ///
+    /// `wide` asks for 64-bit operands, and `byte_reg` for a REX prefix on registers 4 to 7 so they mean the low bytes
+    /// of `rsp`, `rbp`, `rsi` and `rdi` rather than `ah` to `bh`.
+    fn modrm(&mut self, prefix: Option<u8>, wide: bool, byte_reg: bool, opcode: &[u8], reg: u8, operand: Rm, imm: usize) {
+        if let Some(prefix) = prefix {
+            self.byte(prefix);
+        }
+        let base = match operand {
+            Rm::Reg(r) => r,
+            Rm::Mem(&Mem::Base(base, _)) => base.number(),
+            Rm::Mem(&Mem::Rip(_)) => 0,
+        };
+        let rex = 0x40 | (wide as u8) << 3 | (reg >> 3) << 2 | (base >> 3);
+        let low_byte = match operand {
+            Rm::Reg(r) => byte_reg && r >= 4,
+            _ => false,
+        };
+        if rex != 0x40 || low_byte || (byte_reg && reg >= 4) {
+            self.byte(rex);
+        }
+        self.bytes.extend_from_slice(opcode);
+
+        let reg = (reg & 7) << 3;
+        match operand {
+            Rm::Reg(r) => self.byte(0xc0 | reg | (r & 7)),
+            Rm::Mem(&Mem::Base(base, disp)) => {
+                let low = base.number() & 7;
+                // `rbp` and `r13` have no form without a displacement, and `rsp` and `r12` need a SIB byte
+                let short = disp as i8 as i32 == disp;
+                let mode = if disp == 0 && low != 5 { 0x00 } else if short { 0x40 } else { 0x80 };
+                self.byte(mode | reg | low);
+                if low == 4 {
+                    self.byte(0x24);
+                }
+                match mode {
+                    0x40 => self.byte(disp as u8),
+                    0x80 => self.imm32(disp),
+                    _ => {}
+                }
+            }
+            Rm::Mem(&Mem::Rip(ref sym)) => {
+                self.byte(reg | 0x05);
+                let offset = self.bytes.len();
+                self.fixups.push(Fixup {
+                    offset,
+                    sym: sym.clone(),
+                    kind: FixupKind::Pc32,
+                    addend: -4 - imm as i64,
+                });
+                self.imm32(0);
+            }
+        }
+    }
+
+    /// Writes a jump's opcode followed by a distance to the label
+    fn jump(&mut self, opcode: &[u8], label: Label) {
+        self.bytes.extend_from_slice(opcode);
+        let at = self.bytes.len();
+        self.jumps.push((at, label));
+        self.imm32(0);
+    }
+
+    /// Writes an opcode that holds a register in its low three bits
+    fn short_reg(&mut self, opcode: u8, reg: Reg) {
+        if reg.number() >= 8 {
+            self.byte(0x41);
+        }
+        self.byte(opcode + (reg.number() & 7));
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match *inst {
+            Inst::Source(_) => {}
+            Inst::Label(label) => {
+                let at = self.bytes.len();
+                self.labels.insert(label, at);
+            }
+            Inst::Mov(ref dst, Operand::Imm(imm)) => {
+                self.modrm(None, true, false, &[0xc7], 0, rm(dst), 4);
+                self.imm32(imm);
+            }
+            Inst::Mov(Operand::Reg(dst), ref src) => self.modrm(None, true, false, &[0x8b], dst.number(), rm(src), 0),
+            Inst::Mov(ref dst, Operand::Reg(src)) => self.modrm(None, true, false, &[0x89], src.number(), rm(dst), 0),
+            Inst::Mov(..) => panic!("Expected a move with a register or immediate, not {}", inst),
+            Inst::MovAbs(reg, imm) => {
+                self.byte(0x48 | reg.number() >> 3);
+                self.byte(0xb8 + (reg.number() & 7));
+                self.bytes.extend_from_slice(&[
+                    imm as u8, (imm >> 8) as u8, (imm >> 16) as u8, (imm >> 24) as u8,
+                    (imm >> 32) as u8, (imm >> 40) as u8, (imm >> 48) as u8, (imm >> 56) as u8,
+                ]);
+            }
+            Inst::Lea(reg, ref mem) => self.modrm(None, true, false, &[0x8d], reg.number(), Rm::Mem(mem), 0),
+            Inst::Alu(op, ref dst, Operand::Imm(imm)) => {
+                let ext = alu_extension(op);
+                if imm as i8 as i32 == imm {
+                    self.modrm(None, true, false, &[0x83], ext, rm(dst), 1);
+                    self.byte(imm as u8);
+                } else {
+                    self.modrm(None, true, false, &[0x81], ext, rm(dst), 4);
+                    self.imm32(imm);
+                }
+            }
+            Inst::Alu(op, ref dst, Operand::Reg(src)) => {
+                self.modrm(None, true, false, &[alu_extension(op) << 3 | 0x01], src.number(), rm(dst), 0)
+            }
+            Inst::Alu(op, Operand::Reg(dst), ref src) => {
+                self.modrm(None, true, false, &[alu_extension(op) << 3 | 0x03], dst.number(), rm(src), 0)
+            }
+            Inst::Alu(..) => panic!("Expected an operation with a register or immediate, not {}", inst),
+            Inst::Test(ref a, b) => self.modrm(None, true, false, &[0x85], b.number(), rm(a), 0),
+            Inst::IMul(reg, ref src) => self.modrm(None, true, false, &[0x0f, 0xaf], reg.number(), rm(src), 0),
+            Inst::Neg(ref op) => self.modrm(None, true, false, &[0xf7], 3, rm(op), 0),
+            Inst::Not(ref op) => self.modrm(None, true, false, &[0xf7], 2, rm(op), 0),
+            Inst::Sar(ref op, n) => {
+                self.modrm(None, true, false, &[0xc1], 7, rm(op), 1);
+                self.byte(n);
+            }
+            Inst::Cqo => self.bytes.extend_from_slice(&[0x48, 0x99]),
+            Inst::IDiv(ref op) => self.modrm(None, true, false, &[0xf7], 7, rm(op), 0),
+            Inst::Set(cond, reg) => self.modrm(None, false, true, &[0x0f, 0x90 | cond.code()], 0, Rm::Reg(reg.number()), 0),
+            Inst::MovzxByte(dst, src) => {
+                self.modrm(None, true, true, &[0x0f, 0xb6], dst.number(), Rm::Reg(src.number()), 0)
+            }
+            Inst::MovsxDword(dst, src) => {
+                self.modrm(None, true, false, &[0x63], dst.number(), Rm::Reg(src.number()), 0)
+            }
+            Inst::Jmp(label) => self.jump(&[0xe9], label),
+            Inst::Jcc(cond, label) => self.jump(&[0x0f, 0x80 | cond.code()], label),
+            Inst::Call(ref sym) => {
+                self.byte(0xe8);
+                let offset = self.bytes.len();
+                self.fixups.push(Fixup { offset, sym: sym.clone(), kind: FixupKind::Plt32, addend: -4 });
+                self.imm32(0);
+            }
+            Inst::CallIndirect(ref op) => self.modrm(None, false, false, &[0xff], 2, rm(op), 0),
+            Inst::Ret => self.byte(0xc3),
+            Inst::Push(Operand::Reg(reg)) => self.short_reg(0x50, reg),
+            Inst::Push(Operand::Imm(imm)) => {
+                self.byte(0x68);
+                self.imm32(imm);
+            }
+            Inst::Push(ref op) => self.modrm(None, false, false, &[0xff], 6, rm(op), 0),
+            Inst::Pop(reg) => self.short_reg(0x58, reg),
+            Inst::MovqToXmm(xmm, ref src) => self.modrm(Some(0x66), true, false, &[0x0f, 0x6e], xmm, rm(src), 0),
+            Inst::MovqFromXmm(ref dst, xmm) => self.modrm(Some(0x66), true, false, &[0x0f, 0x7e], xmm, rm(dst), 0),
+            Inst::Sse(op, dst, src) => {
+                let (prefix, opcode) = sse_opcode(op);
+                self.modrm(Some(prefix), false, false, &[0x0f, opcode], dst, Rm::Reg(src), 0)
+            }
+            Inst::Ud2 => self.bytes.extend_from_slice(&[0x0f, 0x0b]),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use std::fmt;
+
+use text::TextSpan;
+
+/// A general purpose register, numbered as it's encoded
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum Reg {
+    Rax, Rcx, Rdx, Rbx, Rsp, Rbp, Rsi, Rdi,
+    R8, R9, R10, R11, R12, R13, R14, R15,
+}
+
+impl Reg {
+    pub fn number(self) -> u8 { self as u8 }
+
+    pub fn name(self) -> &'static str {
+        const NAMES: [&'static str; 16] = [
+            "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi",
+            "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+        ];
+        NAMES[self as usize]
+    }
+
+    /// Gets the name of the register's low byte
+    pub fn byte_name(self) -> &'static str {
+        const NAMES: [&'static str; 16] = [
+            "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil",
+            "r8b", "r9b", "r10b", "r11b", "r12b", "r13b", "r14b", "r15b",
+        ];
+        NAMES[self as usize]
+    }
+
+    /// Gets the name of the register's low 32 bits
+    pub fn dword_name(self) -> &'static str {
+        const NAMES: [&'static str; 16] = [
+            "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi",
+            "r8d", "r9d", "r10d", "r11d", "r12d", "r13d", "r14d", "r15d",
+        ];
+        NAMES[self as usize]
+    }
+
+    /// Returns `true` for the registers a System V function must restore before it returns
+    pub fn is_callee_saved(self) -> bool {
+        match self {
+            Reg::Rbx | Reg::Rsp | Reg::Rbp | Reg::R12 | Reg::R13 | Reg::R14 | Reg::R15 => true,
+            _ => false,
+        }
+    }
+}
+
+/// The registers that pass the first integer and pointer arguments of a System V call, in order
+pub const INT_ARGS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
+/// How many `xmm` registers pass float arguments
+pub const FLOAT_ARGS: u8 = 8;
+
+/// A label within a function
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct Label(pub u32);
+
+/// Something whose address is only known once the program is linked
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+pub enum Sym {
+    /// A function or extern, by the name it's linked by
+    Global(String),
+
+    /// A constant of the module, by its position in the module's data
+    Data(u32),
+}
+
+/// A memory operand
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Mem {
+    /// The address in a register plus a displacement
+    Base(Reg, i32),
+
+    /// The address of a symbol, relative to the next instruction
+    Rip(Sym),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Operand {
+    Reg(Reg),
+    Mem(Mem),
+
+    /// An immediate, which is sign extended from 32 bits
+    Imm(i32),
+}
+
+/// A condition of a jump or `set`, by the flags it tests
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Cond {
+    O, No, B, Ae, E, Ne, Be, A, P, Np, L, Ge, Le, G,
+}
+
+impl Cond {
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Cond::O => "o",
+            Cond::No => "no",
+            Cond::B => "b",
+            Cond::Ae => "ae",
+            Cond::E => "e",
+            Cond::Ne => "ne",
+            Cond::Be => "be",
+            Cond::A => "a",
+            Cond::P => "p",
+            Cond::Np => "np",
+            Cond::L => "l",
+            Cond::Ge => "ge",
+            Cond::Le => "le",
+            Cond::G => "g",
+        }
+    }
+
+    /// Gets the low four bits of the opcodes that test the condition
+    pub fn code(self) -> u8 {
+        match self {
+            Cond::O => 0x0,
+            Cond::No => 0x1,
+            Cond::B => 0x2,
+            Cond::Ae => 0x3,
+            Cond::E => 0x4,
+            Cond::Ne => 0x5,
+            Cond::Be => 0x6,
+            Cond::A => 0x7,
+            Cond::P => 0xa,
+            Cond::Np => 0xb,
+            Cond::L => 0xc,
+            Cond::Ge => 0xd,
+            Cond::Le => 0xe,
+            Cond::G => 0xf,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AluOp {
+    Add, Or, And, Sub, Xor, Cmp,
+}
+
+impl AluOp {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            AluOp::Add => "add",
+            AluOp::Or => "or",
+            AluOp::And => "and",
+            AluOp::Sub => "sub",
+            AluOp::Xor => "xor",
+            AluOp::Cmp => "cmp",
+        }
+    }
+}
+
+/// An SSE2 operation on the low double of two `xmm` registers
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SseOp {
+    Add, Sub, Mul, Div, Ucomi,
+}
+
+impl SseOp {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            SseOp::Add => "addsd",
+            SseOp::Sub => "subsd",
+            SseOp::Mul => "mulsd",
+            SseOp::Div => "divsd",
+            SseOp::Ucomi => "ucomisd",
+        }
+    }
+}
+
+/// An x86-64 instruction, always on 64-bit operands unless it says otherwise. Operands are in Intel order, with the
+/// destination first
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Inst {
+    Label(Label),
+    Mov(Operand, Operand),
+    MovAbs(Reg, i64),
+    Lea(Reg, Mem),
+    Alu(AluOp, Operand, Operand),
+    Test(Operand, Reg),
+    IMul(Reg, Operand),
+    Neg(Operand),
+    Not(Operand),
+    Sar(Operand, u8),
+    Cqo,
+    IDiv(Operand),
+    /// Sets the low byte of a register to whether a condition holds
+    Set(Cond, Reg),
+    /// Zero extends the low byte of a register into the whole of another
+    MovzxByte(Reg, Reg),
+    /// Sign extends the low 32 bits of a register into the whole of another
+    MovsxDword(Reg, Reg),
+    Jmp(Label),
+    Jcc(Cond, Label),
+    Call(Sym),
+    CallIndirect(Operand),
+    Ret,
+    Push(Operand),
+    Pop(Reg),
+    /// Moves the bits of a value into the low half of an `xmm` register
+    MovqToXmm(u8, Operand),
+    /// Moves the low half of an `xmm` register into a value
+    MovqFromXmm(Operand, u8),
+    Sse(SseOp, u8, u8),
+    Ud2,
+    /// Marks where the instructions that follow came from, without being encoded
+    Source(TextSpan),
+}
+
+pub fn write_sym(f: &mut fmt::Formatter, sym: &Sym) -> fmt::Result {
+    match *sym {
+        Sym::Global(ref name) if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') => {
+            write!(f, "{}", name)
+        }
+        Sym::Global(ref name) => write!(f, "\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\"")),
+        Sym::Data(n) => write!(f, ".Ldata{}", n),
+    }
+}
+
+impl fmt::Display for Sym {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_sym(f, self)
+    }
+}
+
+/// Writes the operand in AT&T syntax
+impl fmt::Display for Mem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mem::Base(reg, 0) => write!(f, "(%{})", reg.name()),
+            Mem::Base(reg, disp) => write!(f, "{}(%{})", disp, reg.name()),
+            Mem::Rip(ref sym) => write!(f, "{}(%rip)", sym),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Reg(reg) => write!(f, "%{}", reg.name()),
+            Operand::Mem(ref mem) => write!(f, "{}", mem),
+            Operand::Imm(imm) => write!(f, "${}", imm),
+        }
+    }
+}
+
+/// Writes the instruction in AT&T syntax, as the GNU assembler reads it. Labels are written `.L<n>`, which are only
+/// unique within the function, so `assembly` writes labels itself
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Inst::Label(Label(n)) => write!(f, ".L{}:", n),
+            Inst::Mov(ref dst, ref src) => write!(f, "movq {}, {}", src, dst),
+            Inst::MovAbs(reg, imm) => write!(f, "movabsq ${}, %{}", imm, reg.name()),
+            Inst::Lea(reg, ref mem) => write!(f, "leaq {}, %{}", mem, reg.name()),
+            Inst::Alu(op, ref dst, ref src) => write!(f, "{}q {}, {}", op.mnemonic(), src, dst),
+            Inst::Test(ref a, b) => write!(f, "testq %{}, {}", b.name(), a),
+            Inst::IMul(reg, ref src) => write!(f, "imulq {}, %{}", src, reg.name()),
+            Inst::Neg(ref op) => write!(f, "negq {}", op),
+            Inst::Not(ref op) => write!(f, "notq {}", op),
+            Inst::Sar(ref op, n) => write!(f, "sarq ${}, {}", n, op),
+            Inst::Cqo => write!(f, "cqto"),
+            Inst::IDiv(ref op) => write!(f, "idivq {}", op),
+            Inst::Set(cond, reg) => write!(f, "set{} %{}", cond.suffix(), reg.byte_name()),
+            Inst::MovzxByte(dst, src) => write!(f, "movzbq %{}, %{}", src.byte_name(), dst.name()),
+            Inst::MovsxDword(dst, src) => write!(f, "movslq %{}, %{}", src.dword_name(), dst.name()),
+            Inst::Jmp(Label(n)) => write!(f, "jmp .L{}", n),
+            Inst::Jcc(cond, Label(n)) => write!(f, "j{} .L{}", cond.suffix(), n),
+            Inst::Call(ref sym) => write!(f, "call {}", sym),
+            Inst::CallIndirect(ref op) => write!(f, "call *{}", op),
+            Inst::Ret => write!(f, "ret"),
+            Inst::Push(ref op) => write!(f, "pushq {}", op),
+            Inst::Pop(reg) => write!(f, "popq %{}", reg.name()),
+            Inst::MovqToXmm(xmm, ref src) => write!(f, "movq {}, %xmm{}", src, xmm),
+            Inst::MovqFromXmm(ref dst, xmm) => write!(f, "movq %xmm{}, {}", xmm, dst),
+            Inst::Sse(op, dst, src) => write!(f, "{} %xmm{}, %xmm{}", op.mnemonic(), src, dst),
+            Inst::Ud2 => write!(f, "ud2"),
+            Inst::Source(span) => write!(f, "# {}..{}", span.start(), span.end()),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use cfg::{solve, Analysis, BitSet, Direction, Graph, Meet};
+use x86::{MFunction, VReg};
+
+impl Graph for MFunction {
+    fn num_blocks(&self) -> usize { self.blocks.len() }
+    fn entry(&self) -> usize { 0 }
+
+    fn successors(&self, block: usize) -> Vec<usize> {
+        self.blocks[block].terminator.successors()
+    }
+}
+
+/// Which virtual registers will be read before they're written again
+struct Live;
+
+impl Analysis<MFunction> for Live {
+    fn direction(&self) -> Direction { Direction::Backward }
+    fn meet(&self) -> Meet { Meet::Union }
+    fn domain_size(&self, function: &MFunction) -> usize { function.vregs as usize }
+    fn boundary(&self, function: &MFunction) -> BitSet { BitSet::empty(function.vregs as usize) }
+
+    fn transfer(&self, function: &MFunction, block: usize, facts: &mut BitSet) {
+        let block = &function.blocks[block];
+        for v in block.terminator.uses() {
+            facts.insert(v.index());
+        }
+        for inst in block.insts.iter().rev() {
+            for v in inst.defs() {
+                facts.remove(v.index());
+            }
+            for v in inst.uses() {
+                facts.insert(v.index());
+            }
+        }
+    }
+}
+
+/// The positions from the first write of a virtual register to its last read, with no holes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Interval {
+    pub vreg: VReg,
+    pub start: u32,
+    pub end: u32,
+}
+
+/// The live intervals of a function, ordered by where they start
+///
+/// Instructions are numbered in the order the blocks are laid out in, two apart, and each block's terminator gets a
+/// number after its instructions. An instruction reads its operands and writes its results at its own number, so an
+/// interval that ends at an instruction can share a register with one that starts there.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Liveness {
+    pub intervals: Vec<Interval>,
+
+    /// The numbers of the calls, which overwrite every register a System V function needn't save
+    pub calls: Vec<u32>,
+}
+
+impl Liveness {
+    /// Returns `true` if the interval holds a value from before a call to after it
+    pub fn crosses_call(&self, interval: &Interval) -> bool {
+        self.calls.iter().any(|&c| interval.start < c && c < interval.end)
+    }
+}
+
+/// Gets the virtual registers live after each call, which hold their values across it, in the order the calls are
+/// laid out in
+///
+/// Unlike an interval, which spans every position from a value's first write to its last read, a value live here
+/// has been written on every path to the call.
+pub fn live_after_calls(function: &MFunction) -> Vec<Vec<VReg>> {
+    let live = solve(function, &Live);
+    let mut calls = Vec::new();
+    for (b, block) in function.blocks.iter().enumerate() {
+        let mut facts = live.after[b].clone();
+        for v in block.terminator.uses() {
+            facts.insert(v.index());
+        }
+        let mut block_calls = Vec::new();
+        for inst in block.insts.iter().rev() {
+            for v in inst.defs() {
+                facts.remove(v.index());
+            }
+            if inst.is_call() {
+                block_calls.push(facts.iter().map(|v| VReg(v as u32)).collect());
+            }
+            for v in inst.uses() {
+                facts.insert(v.index());
+            }
+        }
+        calls.extend(block_calls.into_iter().rev());
+    }
+    calls
+}
+
+pub fn live_intervals(function: &MFunction) -> Liveness {
+    let live = solve(function, &Live);
+    let mut ranges: Vec<Option<(u32, u32)>> = vec![None; function.vregs as usize];
+    let mut extend = |v: usize, pos: u32| {
+        ranges[v] = Some(match ranges[v] {
+            Some((start, end)) => (start.min(pos), end.max(pos)),
+            None => (pos, pos),
+        });
+    };
+
+    let mut calls = Vec::new();
+    let mut pos = 0;
+    for (b, block) in function.blocks.iter().enumerate() {
+        let start = pos;
+        for v in live.before[b].iter() {
+            extend(v, start);
+        }
+        for inst in &block.insts {
+            for v in inst.uses().into_iter().chain(inst.defs()) {
+                extend(v.index(), pos);
+            }
+            if inst.is_call() {
+                calls.push(pos);
+            }
+            pos += 2;
+        }
+        for v in block.terminator.uses() {
+            extend(v.index(), pos);
+        }
+        // Values live out of the block outlast everything in it
+        for v in live.after[b].iter() {
+            extend(v, pos + 1);
+        }
+        pos += 2;
+    }
+
+    let mut intervals: Vec<Interval> = ranges.into_iter().enumerate()
+        .filter_map(|(v, range)| range.map(|(start, end)| Interval { vreg: VReg(v as u32), start, end }))
+        .collect();
+    intervals.sort_by_key(|i| (i.start, i.vreg));
+    Liveness { intervals, calls }
+}
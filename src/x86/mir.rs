@@ -0,0 +1,153 @@
+use arith::OverflowMode;
+use parser::BinOp;
+use text::TextSpan;
+use x86::Sym;
+
+/// A virtual register, which the allocator gives a register or stack slot
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct VReg(pub u32);
+
+impl VReg {
+    pub fn index(self) -> usize { self.0 as usize }
+}
+
+/// Which registers a value is passed in by the System V calling convention
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Class {
+    /// General purpose registers, for everything but floats
+    Int,
+
+    /// `xmm` registers, for floats
+    Float,
+}
+
+/// Why a program stopped, as passed to `why_trap`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TrapKind {
+    Overflow = 0,
+    DivideByZero = 1,
+    NegativePower = 2,
+}
+
+/// An instruction of a function before its registers are allocated. Most stand for a short sequence of machine
+/// instructions, which is only chosen once it's known where each operand is
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MInst {
+    /// Defines the parameters, from where the caller passed them
+    Params(Vec<(VReg, Class)>),
+    Imm(VReg, i64),
+    /// Takes the address of a function or constant
+    Addr(VReg, Sym),
+    /// `int` arithmetic other than `**`, which handles overflow as the function says. The symbol is the location a
+    /// failure is reported at
+    Int(BinOp, VReg, VReg, VReg, Sym),
+    Float(BinOp, VReg, VReg, VReg),
+    /// Compares two `int`s, `bool`s or `unit`s, giving a `bool`
+    IntCmp(BinOp, VReg, VReg, VReg),
+    FloatCmp(BinOp, VReg, VReg, VReg),
+    Call(Callee, Vec<(VReg, Class)>, Option<(VReg, Class)>),
+    /// Reads the word at a displacement from an address
+    Load(VReg, VReg, i32),
+    /// Writes a word at a displacement from an address
+    Store(VReg, i32, VReg),
+    /// Keeps only the low byte of a value, which is all a C function returning `bool` sets
+    ZeroExtend(VReg, VReg),
+    /// Keeps only the low 32 bits of a value, sign extended, which is all a C function returning `int` sets
+    SignExtend(VReg, VReg),
+    /// Copies values all at once, so a copy can read a register another copy writes
+    Copies(Vec<(VReg, VReg)>),
+    /// Marks where the instructions that follow came from
+    Source(TextSpan),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Callee {
+    Direct(Sym),
+    Indirect(VReg),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MTerminator {
+    Jump(usize),
+    Branch(VReg, usize, usize),
+    /// Compares two `int`s and branches on the result, without making a `bool` of it
+    CmpBranch(BinOp, VReg, VReg, usize, usize),
+    Return(Option<(VReg, Class)>),
+    Unreachable,
+}
+
+impl MTerminator {
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            MTerminator::Jump(b) => vec![b],
+            MTerminator::Branch(_, t, f) | MTerminator::CmpBranch(_, _, _, t, f) => vec![t, f],
+            MTerminator::Return(_) | MTerminator::Unreachable => vec![],
+        }
+    }
+
+    pub fn uses(&self) -> Vec<VReg> {
+        match *self {
+            MTerminator::Branch(c, _, _) => vec![c],
+            MTerminator::CmpBranch(_, a, b, _, _) => vec![a, b],
+            MTerminator::Return(Some((v, _))) => vec![v],
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MBlock {
+    pub insts: Vec<MInst>,
+    pub terminator: MTerminator,
+}
+
+/// A function whose instructions have been chosen, but whose values are still in virtual registers. Blocks are in
+/// the order they're laid out in, starting with the entry
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MFunction {
+    pub name: String,
+    pub blocks: Vec<MBlock>,
+    pub vregs: u32,
+    pub overflow: OverflowMode,
+}
+
+impl MInst {
+    /// Gets the registers the instruction reads
+    pub fn uses(&self) -> Vec<VReg> {
+        match *self {
+            MInst::Params(_) | MInst::Imm(..) | MInst::Addr(..) | MInst::Source(_) => vec![],
+            MInst::Int(_, _, a, b, _) | MInst::Float(_, _, a, b) | MInst::IntCmp(_, _, a, b)
+            | MInst::FloatCmp(_, _, a, b) => vec![a, b],
+            MInst::Call(ref callee, ref args, _) => {
+                let mut uses: Vec<VReg> = args.iter().map(|&(v, _)| v).collect();
+                if let Callee::Indirect(v) = *callee {
+                    uses.push(v);
+                }
+                uses
+            }
+            MInst::Load(_, base, _) | MInst::ZeroExtend(_, base) | MInst::SignExtend(_, base) => vec![base],
+            MInst::Store(base, _, value) => vec![base, value],
+            MInst::Copies(ref copies) => copies.iter().map(|&(_, src)| src).collect(),
+        }
+    }
+
+    /// Gets the registers the instruction writes
+    pub fn defs(&self) -> Vec<VReg> {
+        match *self {
+            MInst::Params(ref params) => params.iter().map(|&(v, _)| v).collect(),
+            MInst::Imm(d, _) | MInst::Addr(d, _) | MInst::Int(_, d, _, _, _) | MInst::Float(_, d, _, _)
+            | MInst::IntCmp(_, d, _, _) | MInst::FloatCmp(_, d, _, _) | MInst::Load(d, _, _)
+            | MInst::ZeroExtend(d, _) | MInst::SignExtend(d, _) => vec![d],
+            MInst::Call(_, _, ret) => ret.into_iter().map(|(v, _)| v).collect(),
+            MInst::Store(..) | MInst::Source(_) => vec![],
+            MInst::Copies(ref copies) => copies.iter().map(|&(dst, _)| dst).collect(),
+        }
+    }
+
+    pub fn is_call(&self) -> bool {
+        match *self {
+            MInst::Call(..) => true,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,564 @@
+//! Compiles the IR to x86-64 machine code for System V platforms
+//!
+//! Instructions are first chosen with values left in virtual registers. Liveness then gives each one an interval, and
+//! linear scan allocation puts each interval in a register or, when there are too few, a stack slot. Last, each
+//! function gets its frame and the chosen instructions become machine instructions. Failures like overflow call
+//! `why_trap` in the runtime, and so do the operations too large to write inline, like `**` and string concatenation.
+//!
+//! A compiled program becomes an ELF object with `object_file`, which a C compiler can link with the runtime in
+//! `RUNTIME_LIBRARY` and any libraries the externs come from, or assembler source with `assembly`, for reading or to
+//! build with `as` instead.
+//! Given the source, the object also gets DWARF debug information, so debuggers can show the lines and variables of
+//! the program.
+
+mod asm;
+mod dwarf;
+mod emit;
+mod encode;
+#[path = "runtime/gc.rs"]
+pub mod gc;
+mod inst;
+mod liveness;
+mod mir;
+mod object;
+mod program;
+mod regalloc;
+mod select;
+#[path = "runtime/show.rs"]
+pub mod show;
+mod stackmap;
+
+pub use self::asm::assembly;
+pub use self::emit::emit_function;
+pub use self::encode::{encode, Code, Fixup, FixupKind};
+pub use self::inst::{AluOp, Cond, Inst, Label, Mem, Operand, Reg, SseOp, Sym, FLOAT_ARGS, INT_ARGS};
+pub use self::liveness::{live_after_calls, live_intervals, Interval, Liveness};
+pub use self::mir::{Callee, Class, MBlock, MFunction, MInst, MTerminator, TrapKind, VReg};
+pub use self::object::object_file;
+pub use self::program::{symbol_name, CodegenError, CompiledFunction, Data, Datum, DebugVariable, Program, Safepoint};
+pub use self::regalloc::{allocate, Allocation, Loc, ALLOCATABLE};
+pub use self::select::{class, select_function, source_location};
+pub use self::stackmap::{function_stack_maps, stack_maps, MapWord};
+
+use std::slice;
+
+use ir;
+use text::Document;
+
+/// The runtime native programs are linked with, as a static library. It's built from `runtime/lib.rs` by `build.rs`
+pub const RUNTIME_LIBRARY: &'static [u8] = include_bytes!(concat!(env!("OUT_DIR"), "/libwhyrt.a"));
+
+/// The object with the C `main` of native executables, which runs the program with the runtime. Objects linked into a
+/// C program with their own `main` leave it out
+pub const RUNTIME_MAIN: &'static [u8] = include_bytes!(concat!(env!("OUT_DIR"), "/whymain.o"));
+
+/// Compiles every function of a module, with failures reported at locations in the document if there is one
+pub fn compile_module(module: &ir::Module, document: Option<&Document>) -> Result<Program, CodegenError> {
+    compile_functions(module, &module.functions, document)
+}
+
+/// Compiles one function of a module on its own, as a program that imports the other functions it uses, or `None` if
+/// the module has no function with the name
+pub fn compile_function(module: &ir::Module, name: &str, document: Option<&Document>)
+                        -> Option<Result<Program, CodegenError>> {
+    module.function(name).map(|function| compile_functions(module, slice::from_ref(function), document))
+}
+
+fn compile_functions(module: &ir::Module, compiled: &[ir::Function], document: Option<&Document>)
+                     -> Result<Program, CodegenError> {
+    let mut data = Data::new();
+    let mut functions = Vec::new();
+    for function in compiled {
+        let selected = select_function(function, module, &mut data, document)?;
+        let allocation = allocate(&selected);
+        let (insts, starts, calls) = emit_function(&selected, &allocation);
+        let variables = debug_variables(function, &selected, &allocation, &starts, insts.len());
+        let safepoints = safepoints(function, &selected, &allocation, calls);
+        functions.push(CompiledFunction {
+            name: function.name.clone(),
+            symbol: selected.name.clone(),
+            insts,
+            spilled: allocation.spilled,
+            saved: allocation.saved.clone(),
+            safepoints,
+            ret: function.ret,
+            span: function.span,
+            variables,
+        });
+    }
+
+    // Externs are imported even if nothing calls them
+    let mut imports: Vec<String> = module.externs.iter().map(|e| e.name.clone()).collect();
+    for function in &functions {
+        for inst in &function.insts {
+            let sym = match *inst {
+                Inst::Call(ref sym) | Inst::Lea(_, Mem::Rip(ref sym)) => sym,
+                _ => continue,
+            };
+            if let Sym::Global(ref name) = *sym {
+                if !functions.iter().any(|f| f.symbol == *name) && !imports.contains(name) {
+                    imports.push(name.clone());
+                }
+            }
+        }
+    }
+    imports.sort();
+    Ok(Program { functions, data, imports, layout: module.layout.clone() })
+}
+
+/// Finds where the variables of a function are kept and the instructions they're live over, leaving out those whose
+/// values were optimized away
+fn debug_variables(function: &ir::Function, selected: &MFunction, allocation: &Allocation, starts: &[usize],
+                   end: usize) -> Vec<DebugVariable> {
+    let types = function.value_types();
+    let liveness = live_intervals(selected);
+    // An interval starts where its value is written, so it's held from the next instruction, and it ends where its
+    // value is last read, or just after a terminator it outlives
+    let start = |position: u32| starts.get(position as usize / 2 + 1).cloned().unwrap_or(end);
+    let mut variables = Vec::new();
+    for (i, variable) in function.variables.iter().enumerate() {
+        let ty = match types.get(variable.value.index()) {
+            Some(&Some(ty)) => ty,
+            _ => continue,
+        };
+        // A pattern that matches a parameter whole binds its value again, but only the first variable is the parameter
+        let param = function.params.iter().any(|&(v, _)| v == variable.value)
+            && function.variables.iter().position(|v| v.value == variable.value) == Some(i);
+        let interval = liveness.intervals.iter().find(|interval| interval.vreg.index() == variable.value.index());
+        let place = match (allocation.locs.get(variable.value.index()), interval) {
+            (Some(&Some(loc)), Some(interval)) => {
+                let live = start(interval.start)..start(interval.end);
+                if live.start < live.end { Some((place(allocation, loc), live)) } else { None }
+            }
+            _ => None,
+        };
+        variables.push(DebugVariable { name: variable.name.clone(), ty, span: variable.span, param, place });
+    }
+    variables
+}
+
+/// Finds where the strings and objects live across each call are kept, given the index of the machine call of each
+fn safepoints(function: &ir::Function, selected: &MFunction, allocation: &Allocation, calls: Vec<usize>)
+              -> Vec<Safepoint> {
+    let types = function.value_types();
+    // Virtual registers past the function's values are temporaries, which never hold references across calls
+    let is_reference = |v: VReg| types.get(v.index()).map_or(false, |ty| ty.map_or(false, |ty| ty.is_reference()));
+    calls.into_iter().zip(live_after_calls(selected)).map(|(inst, live)| Safepoint {
+        inst,
+        roots: live.into_iter().filter(|&v| is_reference(v)).map(|v| place(allocation, allocation.loc(v))).collect(),
+    }).collect()
+}
+
+fn place(allocation: &Allocation, loc: Loc) -> Operand {
+    match loc {
+        Loc::Reg(reg) => Operand::Reg(reg),
+        Loc::Stack(slot) => Operand::Mem(allocation.slot(slot)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arith::OverflowMode;
+    use tokenizer::Tokenizer;
+    use parser::Parser;
+    use resolve::resolve_program;
+    use typeck;
+    use mono::monomorphize;
+    use ir::{lower_program, parse_module, Module};
+    use elf::{RelocationKind, Target};
+    use text::Document;
+    use x86::{allocate, compile_module, encode, live_intervals, object_file, select_function, Cond, Data, FixupKind, Inst,
+              Label, Loc, MFunction, MapWord, Mem, Operand, Program, Reg, SseOp, Sym, stack_maps};
+
+    fn lower(code: &str) -> Module {
+        let program = Parser::new(Tokenizer::new(code)).program().expect("Expected the parse to succeed");
+        let resolution = resolve_program(&program);
+        let results = typeck::check_program(&program);
+        let mono = monomorphize(&program, &results);
+        lower_program(&program, &resolution, &results, &mono, OverflowMode::Trap)
+    }
+
+    fn compile(text: &str) -> Program {
+        let module = parse_module(text).expect("Expected the module to parse");
+        compile_module(&module, None).expect("Expected the module to compile")
+    }
+
+    /// Returns `true` if an instruction reads or writes a stack slot
+    fn uses_slot(inst: &Inst) -> bool {
+        let slot = |op: &Operand| match *op {
+            Operand::Mem(Mem::Base(Reg::Rbp, disp)) => disp < 0,
+            _ => false,
+        };
+        match *inst {
+            Inst::Mov(ref a, ref b) | Inst::Alu(_, ref a, ref b) => slot(a) || slot(b),
+            Inst::IMul(_, ref a) | Inst::Push(ref a) | Inst::MovqToXmm(_, ref a) | Inst::MovqFromXmm(ref a, _) => slot(a),
+            _ => false,
+        }
+    }
+
+    #[test]
+    pub fn arithmetic_compiles_to_instructions_on_registers() {
+        let program = compile("\
+overflow wrap
+
+fn @add(%0: int, %1: int) -> int {
+bb0:
+    %2: int = add %0, %1 !0..1
+    ret %2
+}
+");
+        assert_eq!("    pushq %rbp
+    movq %rsp, %rbp
+.L0:
+    # 0..1
+    addq %rsi, %rdi
+    movq %rdi, %rax
+    movq %rbp, %rsp
+    popq %rbp
+    ret
+", program.function("w_add").unwrap().to_string());
+        assert_eq!(Vec::<String>::new(), program.imports);
+    }
+
+    #[test]
+    pub fn chains_of_arithmetic_stay_in_registers() {
+        let program = compile_module(&lower("\
+def chain(x, y) { let a = x + y; let b = a * x; let c = b - y; let d = c * c; d / (x + 1) }
+chain(3, 4)"), None).unwrap();
+        let chain = program.function("w_chain").unwrap();
+        assert_eq!(0, chain.spilled);
+        assert!(!chain.insts.iter().any(uses_slot), "{}", chain);
+        // Overflow and division by zero are reported from shared stubs at the end of the function
+        assert_eq!(vec!["why_trap".to_string()], program.imports);
+    }
+
+    #[test]
+    pub fn values_live_across_calls_are_in_callee_saved_registers() {
+        let module = parse_module("\
+overflow trap
+
+fn @id(%0: int) -> int {
+bb0:
+    ret %0
+}
+
+fn @f(%0: int) -> int {
+bb0:
+    %1: int = call @id(%0)
+    %2: int = call @id(%1)
+    %3: int = add %0, %1 !0..1
+    %4: int = add %3, %2 !0..1
+    ret %4
+}
+").unwrap();
+        let function = select_function(module.function("f").unwrap(), &module, &mut Data::new(), None).unwrap();
+        let liveness = live_intervals(&function);
+        let allocation = allocate(&function);
+        let mut crossing = 0;
+        for interval in &liveness.intervals {
+            if liveness.crosses_call(interval) {
+                crossing += 1;
+                match allocation.loc(interval.vreg) {
+                    Loc::Reg(reg) => assert!(reg.is_callee_saved(), "{:?} is in {:?}", interval, reg),
+                    loc => panic!("Expected {:?} to be in a register, not {:?}", interval, loc),
+                }
+            }
+        }
+        // %0 lives across both calls, and %1 across the second
+        assert_eq!(2, crossing);
+        assert_eq!(vec![Reg::Rbx, Reg::R12], allocation.saved);
+    }
+
+    /// Checks that no two virtual registers share a stack slot while they're both live
+    fn assert_slots_are_not_shared(function: &MFunction) {
+        let liveness = live_intervals(function);
+        let allocation = allocate(function);
+        for a in &liveness.intervals {
+            for b in liveness.intervals.iter().filter(|b| b.vreg != a.vreg && a.start < b.end && b.start < a.end) {
+                if let (Loc::Stack(x), Loc::Stack(y)) = (allocation.loc(a.vreg), allocation.loc(b.vreg)) {
+                    assert!(x != y, "{:?} and {:?} share slot {}", a, b, x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn values_spilled_after_they_start_get_a_slot_free_for_all_of_them() {
+        // %11 goes to the stack when it starts, and once it's read, %21 is pushed out of its register for %32, which
+        // ends sooner. %21 was live before %11 ended, so it can't have %11's slot
+        let module = parse_module("\
+overflow wrap
+
+fn @f(%0: int) -> int {
+bb0:
+    %1: int = add %0, %0
+    %2: int = add %1, %1
+    %3: int = add %2, %2
+    %4: int = add %3, %3
+    %5: int = add %4, %4
+    %6: int = add %5, %5
+    %7: int = add %6, %6
+    %8: int = add %7, %7
+    %9: int = add %8, %8
+    %10: int = add %9, %9
+    %11: int = mul %10, %10
+    %12: int = add %1, %2
+    %13: int = add %12, %3
+    %14: int = add %13, %4
+    %15: int = add %14, %5
+    %16: int = add %15, %6
+    %17: int = add %16, %7
+    %18: int = add %17, %8
+    %19: int = add %18, %9
+    %20: int = add %19, %10
+    %21: int = sub %20, %20
+    %22: int = add %20, %11
+    %23: int = add %22, %22
+    %24: int = add %23, %23
+    %25: int = add %24, %24
+    %26: int = add %25, %25
+    %27: int = add %26, %26
+    %28: int = add %27, %27
+    %29: int = add %28, %28
+    %30: int = add %29, %29
+    %31: int = add %30, %30
+    %32: int = mul %31, %31
+    %33: int = add %32, %22
+    %34: int = add %33, %23
+    %35: int = add %34, %24
+    %36: int = add %35, %25
+    %37: int = add %36, %26
+    %38: int = add %37, %27
+    %39: int = add %38, %28
+    %40: int = add %39, %29
+    %41: int = add %40, %30
+    %42: int = add %41, %31
+    %43: int = add %42, %21
+    ret %43
+}
+").unwrap();
+        let function = select_function(module.function("f").unwrap(), &module, &mut Data::new(), None).unwrap();
+        assert_eq!(3, allocate(&function).spilled);
+        assert_slots_are_not_shared(&function);
+    }
+
+    #[test]
+    pub fn values_that_dont_fit_in_registers_are_spilled() {
+        let program = compile_module(&lower("\
+def id(x) { x }
+def f(x) {
+    let a = id(x + 1); let b = id(x + 2); let c = id(x + 3); let d = id(x + 4); let e = id(x + 5); let g = id(x + 6);
+    let h = id(x + 7); let i = id(x + 8); let j = id(x + 9); let k = id(x + 10); let l = id(x + 11); let m = id(x + 12);
+    a * b + c * d + e * g + h * i + j * k + l * m
+}
+f(1)"), None).unwrap();
+        let f = program.function("w_f").unwrap();
+        // Only five callee-saved registers can hold the twelve values that live across calls
+        assert_eq!(7, f.spilled);
+        assert!(f.insts.iter().any(uses_slot), "{}", f);
+        let pushes = f.insts.iter().filter(|i| match **i {
+            Inst::Push(Operand::Reg(_)) => true,
+            _ => false,
+        }).count();
+        assert_eq!(6, pushes);
+    }
+
+    #[test]
+    pub fn copies_into_phis_are_made_all_at_once() {
+        let program = compile("\
+overflow wrap
+
+fn @swap(%0: int, %1: int, %2: int) -> int {
+bb0:
+    jump bb1
+bb1:
+    %3: int = phi [bb0: %0], [bb2: %4]
+    %4: int = phi [bb0: %1], [bb2: %3]
+    %5: int = phi [bb0: %2], [bb2: %7]
+    %6: int = const 0
+    %10: bool = ne %5, %6
+    branch %10, bb2, bb3
+bb2:
+    %8: int = const 1
+    %7: int = sub %5, %8 !0..1
+    jump bb1
+bb3:
+    %9: int = sub %3, %4 !0..1
+    ret %9
+}
+");
+        let swap = program.function("w_swap").unwrap().to_string();
+        assert!(swap.contains("%r11"), "{}", swap);
+    }
+
+    #[test]
+    pub fn instructions_encode_as_the_assembler_would() {
+        let insts = vec![
+            Inst::Mov(Operand::Reg(Reg::R12), Operand::Mem(Mem::Base(Reg::R13, -8))),
+            Inst::Mov(Operand::Mem(Mem::Base(Reg::Rsp, 0)), Operand::Reg(Reg::Rsi)),
+            Inst::Mov(Operand::Mem(Mem::Base(Reg::Rbp, -200)), Operand::Imm(-1)),
+            Inst::Set(Cond::L, Reg::Rsi),
+            Inst::MovzxByte(Reg::Rdi, Reg::R11),
+            Inst::MovsxDword(Reg::Rdi, Reg::R11),
+            Inst::Push(Operand::Reg(Reg::R12)),
+            Inst::Pop(Reg::R15),
+            Inst::MovqFromXmm(Operand::Reg(Reg::R8), 1),
+            Inst::Sse(SseOp::Ucomi, 0, 1),
+            Inst::Alu(::x86::AluOp::Sub, Operand::Reg(Reg::Rsp), Operand::Imm(1000)),
+            Inst::Alu(::x86::AluOp::Cmp, Operand::Reg(Reg::R11), Operand::Imm(-1)),
+            Inst::IDiv(Operand::Reg(Reg::R11)),
+            Inst::MovAbs(Reg::R10, ::std::i64::MAX),
+            Inst::CallIndirect(Operand::Reg(Reg::Rax)),
+        ];
+        assert_eq!(vec![
+            0x4d, 0x8b, 0x65, 0xf8,
+            0x48, 0x89, 0x34, 0x24,
+            0x48, 0xc7, 0x85, 0x38, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0x40, 0x0f, 0x9c, 0xc6,
+            0x49, 0x0f, 0xb6, 0xfb,
+            0x49, 0x63, 0xfb,
+            0x41, 0x54,
+            0x41, 0x5f,
+            0x66, 0x49, 0x0f, 0x7e, 0xc8,
+            0x66, 0x0f, 0x2e, 0xc1,
+            0x48, 0x81, 0xec, 0xe8, 0x03, 0x00, 0x00,
+            0x49, 0x83, 0xfb, 0xff,
+            0x49, 0xf7, 0xfb,
+            0x49, 0xba, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f,
+            0xff, 0xd0,
+        ], encode(&insts).bytes);
+    }
+
+    #[test]
+    pub fn jumps_and_symbols_are_filled_in_later() {
+        let code = encode(&[
+            Inst::Label(Label(0)),
+            Inst::Jcc(Cond::E, Label(1)),
+            Inst::Lea(Reg::Rsi, Mem::Rip(Sym::Data(0))),
+            Inst::Call(Sym::Global("why_trap".into())),
+            Inst::Jmp(Label(0)),
+            Inst::Label(Label(1)),
+            Inst::Ret,
+        ]);
+        assert_eq!(vec![
+            0x0f, 0x84, 0x11, 0x00, 0x00, 0x00,
+            0x48, 0x8d, 0x35, 0x00, 0x00, 0x00, 0x00,
+            0xe8, 0x00, 0x00, 0x00, 0x00,
+            0xe9, 0xe9, 0xff, 0xff, 0xff,
+            0xc3,
+        ], code.bytes);
+        assert_eq!(9, code.fixups[0].offset);
+        let fixup = &code.fixups[0];
+        assert_eq!((Sym::Data(0), FixupKind::Pc32, -4), (fixup.sym.clone(), fixup.kind, fixup.addend));
+        assert_eq!(14, code.fixups[1].offset);
+        assert_eq!(FixupKind::Plt32, code.fixups[1].kind);
+    }
+
+    #[test]
+    pub fn externs_are_undefined_in_objects() {
+        let program = compile_module(&lower("\
+extern labs(x: Int) -> CLong;
+extern abort();
+def f(x) { labs(x) + 1 }
+f(0 - 2)"), None).unwrap();
+        let object = object_file(&program, None);
+        assert_eq!(vec!["abort", "labs", "why_trap"], object.undefined_symbols());
+        let symbols: Vec<&str> = object.symbols.iter().map(|s| s.name.as_str()).collect();
+        // The runtime prints the program's value by the type of `why_main`, and finds references by the stack maps
+        assert_eq!(vec!["why_main", "w_f", "why_main_type", "why_main_layout", "why_stack_maps"], symbols);
+        // Calls go through the linker, and the location of the overflow check is in `.rodata`
+        let text = &object.sections[0];
+        let labs = Target::Symbol("labs".into());
+        assert!(text.relocations.iter().any(|r| r.target == labs && r.kind == RelocationKind::Plt32));
+        assert!(text.relocations.iter().any(|r| r.target == Target::Section(2) && r.kind == RelocationKind::Pc32));
+    }
+
+    #[test]
+    pub fn externs_are_called_with_the_c_convention() {
+        let program = compile_module(&lower("\
+extern ldexp(x: Float, e: Int) -> Float;
+extern feof(f: Int) -> Bool;
+extern abs(x: Int) -> CInt;
+match feof(abs(0)) { true => ldexp(1.5, 3), false => 0.0 }"), None).unwrap();
+        let main = program.function("why_main").unwrap().to_string();
+        // The float goes in `xmm0` and the int in `rdi`, each the first of their kind, and `rax` counts the floats
+        assert!(main.contains(", %xmm0\n    movq %rsi, %rdi\n    movq $1, %rax\n    call ldexp\n"), "{}", main);
+        // A C `int` is only 32 bits, and a C `bool` only a byte
+        assert!(main.contains("call abs\n    movq %rax, %rcx\n    movslq %ecx, %rcx\n"), "{}", main);
+        assert!(main.contains("call feof\n    movq %rax, %rcx\n    movzbq %cl, %rcx\n"), "{}", main);
+    }
+
+    #[test]
+    pub fn variables_are_found_where_their_values_live() {
+        let program = compile_module(&lower("\
+def f(x: Int) -> Int {
+    let y = x * 2;
+    let unused = 5;
+    y + 1
+}
+f(3)"), None).unwrap();
+        let f = program.function("w_f").unwrap();
+        let names: Vec<(&str, bool)> = f.variables.iter().map(|v| (v.name.as_str(), v.param)).collect();
+        assert_eq!(vec![("x", true), ("y", false), ("unused", false)], names);
+        // `x` arrives in `rdi` and is read by the multiplication, which leaves `y` there, so each has its own code
+        let (ref x, ref x_live) = *f.variables[0].place.as_ref().unwrap();
+        let (ref y, ref y_live) = *f.variables[1].place.as_ref().unwrap();
+        assert_eq!((&Operand::Reg(Reg::Rdi), &Operand::Reg(Reg::Rdi)), (x, y));
+        assert!(x_live.start < x_live.end && x_live.end <= y_live.start && y_live.end <= f.insts.len(), "{}", f);
+        // A value nothing reads is never kept anywhere
+        assert_eq!(None, f.variables[2].place);
+    }
+
+    #[test]
+    pub fn references_live_across_calls_are_in_the_stack_maps() {
+        let program = compile_module(&lower("\
+def f(s: Str, n: Int) -> Str {
+    let t = s + \"a\";
+    let u = t + \"b\";
+    match n + 1 { 0 => t, _ => t + u }
+}
+f(\"x\", 2)"), None).unwrap();
+        let function = program.function("w_f").unwrap();
+        // `u` is only made in the arm that needs it, so only `t` is live across a concatenation, and never the count
+        let roots: Vec<usize> = function.safepoints.iter().map(|s| s.roots.len()).collect();
+        assert_eq!(vec![0, 1, 0], roots);
+        for root in function.safepoints.iter().flat_map(|s| &s.roots) {
+            match *root {
+                Operand::Reg(reg) => assert!(reg.is_callee_saved()),
+                Operand::Mem(Mem::Base(Reg::Rbp, _)) => {}
+                ref other => panic!("Expected a root in a register or stack slot, but got {:?}", other),
+            }
+        }
+        let maps = stack_maps(&program);
+        let count = program.functions.iter().map(|f| f.safepoints.len()).sum::<usize>();
+        assert_eq!(MapWord::Value(count as u64), maps[0]);
+        assert_eq!(count, maps.iter().filter(|w| match **w { MapWord::Return(..) => true, _ => false }).count());
+    }
+
+    #[test]
+    pub fn objects_get_debug_information_with_the_source() {
+        let code = "def f(x: Int) -> Int {\n    x * 2\n}\nf(3)";
+        let program = compile_module(&lower(code), None).unwrap();
+        let document = Document::new("test.why", code);
+        let object = object_file(&program, Some(&document));
+        let names: Vec<&str> = object.sections.iter().map(|s| s.name.as_str()).collect();
+        let expected = vec![".text", ".data", ".rodata", ".debug_abbrev", ".debug_info", ".debug_line", ".debug_loc"];
+        assert_eq!(expected, names);
+        // The compile unit refers to the abbreviations, line program and location lists by offsets the linker fills in
+        let info = &object.sections[4];
+        let offsets: Vec<&Target> = info.relocations.iter()
+            .filter(|r| r.kind == RelocationKind::Abs32)
+            .map(|r| &r.target)
+            .collect();
+        assert_eq!(vec![&Target::Section(3), &Target::Section(5), &Target::Section(6)], offsets);
+        assert!(info.relocations.iter().any(|r| r.target == Target::Section(0) && r.addend > 0));
+        let contains = |bytes: &[u8], s: &[u8]| bytes.windows(s.len()).any(|w| w == s);
+        // `f` is a function returning a type, and `x` is its parameter, named right after their abbreviations
+        assert!(contains(&info.bytes, b"\x05f\0") && contains(&info.bytes, b"\x07x\0"));
+        // The multiplication is on line 2, column 5
+        let line = &object.sections[5].bytes;
+        assert!(contains(line, b"test.why\0"));
+        assert!(contains(line, &[3, 1, 5, 5, 1]));
+        assert!(object_file(&program, None).sections.iter().all(|s| !s.name.starts_with(".debug")));
+    }
+}
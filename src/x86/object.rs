@@ -0,0 +1,161 @@
+use elf::{ObjectFile, Relocation, RelocationKind, SectionKind, Symbol, Target};
+use text::Document;
+use x86::dwarf::{add_debug_sections, Placed};
+use x86::{encode, stack_maps, Datum, FixupKind, MapWord, Program, Sym};
+
+/// Lays a compiled program out as an object file
+///
+/// Functions go in `.text` as global symbols, strings and locations in `.rodata`, and vtables in `.data`, since the
+/// addresses in them are filled in by the linker. The imports are left undefined. `why_main_type` is the position of
+/// the type `why_main` returns in `Ty`, so the runtime knows how to print it, with `why_main_layout` describing the
+/// type of an object it returns field by field, and `why_stack_maps` says where the
+/// references live across each call are, so its collector can find them. Given the document the program was
+/// compiled from, the object also gets DWARF sections describing its lines and variables.
+pub fn object_file(program: &Program, document: Option<&Document>) -> ObjectFile {
+    let mut object = ObjectFile::new();
+    let text = object.add_section(".text", SectionKind::Text, 16);
+    let data = object.add_section(".data", SectionKind::Data, 8);
+    let rodata = object.add_section(".rodata", SectionKind::ReadOnly, 8);
+
+    // Where each datum is, by section and offset
+    let mut places = Vec::new();
+    for datum in program.data.items() {
+        match *datum {
+            Datum::Str(ref s) => {
+                let bytes = &mut object.sections[rodata].bytes;
+                pad(bytes, 8, 0);
+                bytes.extend_from_slice(&[0; 8]);
+                bytes.extend_from_slice(&u64_bytes(s.len() as u64));
+                places.push((rodata, bytes.len()));
+                bytes.extend_from_slice(s.as_bytes());
+                bytes.push(0);
+            }
+            Datum::Location(ref location) => {
+                let bytes = &mut object.sections[rodata].bytes;
+                places.push((rodata, bytes.len()));
+                bytes.extend_from_slice(location.as_bytes());
+                bytes.push(0);
+            }
+            Datum::VTable(ref functions) => {
+                let section = &mut object.sections[data];
+                pad(&mut section.bytes, 8, 0);
+                places.push((data, section.bytes.len()));
+                for function in functions {
+                    section.relocations.push(Relocation {
+                        offset: section.bytes.len() as u64,
+                        target: Target::Symbol(function.clone()),
+                        kind: RelocationKind::Abs64,
+                        addend: 0,
+                    });
+                    section.bytes.extend_from_slice(&[0; 8]);
+                }
+            }
+        }
+    }
+
+    let mut placed = Vec::new();
+    for function in &program.functions {
+        let code = encode(&function.insts);
+        let section = &mut object.sections[text];
+        pad(&mut section.bytes, 16, 0xcc);
+        let start = section.bytes.len();
+        for fixup in code.fixups {
+            let (target, addend) = match fixup.sym {
+                Sym::Global(name) => (Target::Symbol(name), fixup.addend),
+                Sym::Data(n) => {
+                    let (section, offset) = places[n as usize];
+                    (Target::Section(section), fixup.addend + offset as i64)
+                }
+            };
+            let kind = match fixup.kind {
+                FixupKind::Pc32 => RelocationKind::Pc32,
+                FixupKind::Plt32 => RelocationKind::Plt32,
+            };
+            section.relocations.push(Relocation { offset: (start + fixup.offset) as u64, target, kind, addend });
+        }
+        section.bytes.extend_from_slice(&code.bytes);
+        object.symbols.push(Symbol {
+            name: function.symbol.clone(),
+            section: text,
+            offset: start as u64,
+            size: code.bytes.len() as u64,
+            function: true,
+            global: true,
+        });
+        placed.push(Placed { function, start: start as u64, size: code.bytes.len() as u64, offsets: code.offsets });
+    }
+
+    // The runtime's `main` prints what `why_main` returns by its type, and its collector finds references on the
+    // stack with the stack maps
+    if let Some(main) = program.function("why_main") {
+        let bytes = &mut object.sections[rodata].bytes;
+        object.symbols.push(Symbol {
+            name: "why_main_type".into(),
+            section: rodata,
+            offset: bytes.len() as u64,
+            size: 1,
+            function: false,
+            global: true,
+        });
+        bytes.push(main.ret as u8);
+        object.symbols.push(Symbol {
+            name: "why_main_layout".into(),
+            section: rodata,
+            offset: bytes.len() as u64,
+            size: program.layout.len() as u64 + 1,
+            function: false,
+            global: true,
+        });
+        bytes.extend_from_slice(program.layout.as_bytes());
+        bytes.push(0);
+
+        let section = &mut object.sections[data];
+        pad(&mut section.bytes, 8, 0);
+        let start = section.bytes.len();
+        for word in stack_maps(program) {
+            match word {
+                MapWord::Value(value) => section.bytes.extend_from_slice(&u64_bytes(value)),
+                MapWord::Return(f, k) => {
+                    let (place, inst) = (&placed[f], program.functions[f].safepoints[k].inst);
+                    let offset = place.offsets.get(inst + 1).map_or(place.size, |&offset| offset as u64);
+                    section.relocations.push(Relocation {
+                        offset: section.bytes.len() as u64,
+                        target: Target::Section(text),
+                        kind: RelocationKind::Abs64,
+                        addend: (place.start + offset) as i64,
+                    });
+                    section.bytes.extend_from_slice(&[0; 8]);
+                }
+            }
+        }
+        let size = (section.bytes.len() - start) as u64;
+        object.symbols.push(Symbol {
+            name: "why_stack_maps".into(),
+            section: data,
+            offset: start as u64,
+            size,
+            function: false,
+            global: true,
+        });
+    }
+
+    if let Some(document) = document {
+        add_debug_sections(&mut object, text, &placed, document);
+    }
+    object.imports = program.imports.clone();
+    object
+}
+
+fn pad(bytes: &mut Vec<u8>, align: usize, fill: u8) {
+    while bytes.len() % align != 0 {
+        bytes.push(fill);
+    }
+}
+
+fn u64_bytes(value: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (value >> (8 * i)) as u8;
+    }
+    bytes
+}
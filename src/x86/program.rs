@@ -0,0 +1,153 @@
+use std::fmt;
+use std::ops::Range;
+
+use ir::{Module, Ty};
+use text::TextSpan;
+use x86::{Inst, Operand, Reg};
+
+/// A constant the code refers to by address
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Datum {
+    /// A string, laid out like the runtime's: a header word, the length, then the bytes and a terminating zero. Its
+    /// address is that of the bytes, so it can be passed straight to C
+    Str(String),
+
+    /// Where a failure is reported, as a zero-terminated `file:line:column`
+    Location(String),
+
+    /// The addresses of the functions of a vtable
+    VTable(Vec<String>),
+}
+
+/// The constants of a program, each stored once however many functions use it
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Data {
+    items: Vec<Datum>,
+}
+
+impl Data {
+    pub fn new() -> Data {
+        Data::default()
+    }
+
+    /// Gets the position of a constant, adding it if it isn't already there
+    pub fn intern(&mut self, datum: Datum) -> u32 {
+        match self.items.iter().position(|d| *d == datum) {
+            Some(i) => i as u32,
+            None => {
+                self.items.push(datum);
+                self.items.len() as u32 - 1
+            }
+        }
+    }
+
+    pub fn items(&self) -> &[Datum] {
+        &self.items
+    }
+}
+
+/// A variable of the source and where its value is kept, for debug information
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DebugVariable {
+    pub name: String,
+    pub ty: Ty,
+
+    /// Where the variable is declared
+    pub span: TextSpan,
+
+    /// Whether the variable is a parameter of the function
+    pub param: bool,
+
+    /// The register or stack slot that holds the value, and the instructions it holds it over, if the value was used
+    pub place: Option<(Operand, Range<usize>)>,
+}
+
+/// A call the collector can run during, and where the strings and objects live across it are kept
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Safepoint {
+    /// The index of the call instruction
+    pub inst: usize,
+
+    /// The callee-saved registers and stack slots holding references
+    pub roots: Vec<Operand>,
+}
+
+/// A function compiled to machine instructions
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CompiledFunction {
+    /// The function's name in the module, which debug information gives it, and the symbol it's linked by
+    pub name: String,
+    pub symbol: String,
+    pub insts: Vec<Inst>,
+
+    /// How many values didn't fit in registers
+    pub spilled: usize,
+
+    /// The callee-saved registers the function saves below `rbp`, in order, and its calls, so the collector can find
+    /// the references of every frame
+    pub saved: Vec<Reg>,
+    pub safepoints: Vec<Safepoint>,
+
+    /// The type of the value the function returns, the source it was compiled from, and its variables, for debug
+    /// information
+    pub ret: Ty,
+    pub span: Option<TextSpan>,
+    pub variables: Vec<DebugVariable>,
+}
+
+/// A module compiled to machine instructions
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Program {
+    pub functions: Vec<CompiledFunction>,
+    pub data: Data,
+
+    /// The symbols the program uses but doesn't define, from externs and the runtime
+    pub imports: Vec<String>,
+
+    /// The type of the object `why_main` returns, described as `show` reads it, or empty if it returns no object
+    pub layout: String,
+}
+
+impl Program {
+    pub fn function(&self, symbol: &str) -> Option<&CompiledFunction> {
+        self.functions.iter().find(|f| f.symbol == symbol)
+    }
+}
+
+/// Writes the instructions one per line, indented apart from labels
+impl fmt::Display for CompiledFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for inst in &self.insts {
+            match *inst {
+                Inst::Label(_) => writeln!(f, "{}", inst)?,
+                _ => writeln!(f, "    {}", inst)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A function the backend can't compile
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CodegenError {
+    pub function: String,
+    pub message: String,
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "in `{}`: {}", self.function, self.message)
+    }
+}
+
+/// Gets the symbol a function or extern of the module is linked by
+///
+/// Externs keep their names, and `main` is `why_main` so the runtime's C `main` can call it. Other functions start
+/// with `w_`, as in C, so they can't clash with the C library or the runtime.
+pub fn symbol_name(module: &Module, function: &str) -> String {
+    match function {
+        _ if module.extern_(function).is_some() => function.into(),
+        "main" => "why_main".into(),
+        _ => format!("w_{}", function),
+    }
+}
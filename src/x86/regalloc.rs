@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use x86::{live_intervals, Interval, Liveness, MFunction, MInst, Mem, Reg, VReg, INT_ARGS};
+
+/// The registers values can be allocated to, in the order they're preferred. `rax`, `rdx`, `r10` and `r11` are left
+/// out, since the code for division, calls and copies needs them for itself
+pub const ALLOCATABLE: [Reg; 10] = [
+    Reg::Rcx, Reg::Rsi, Reg::Rdi, Reg::R8, Reg::R9,
+    Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15,
+];
+
+/// Where a virtual register lives for the whole of its interval
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Loc {
+    Reg(Reg),
+
+    /// A slot in the stack frame, numbered from zero
+    Stack(u32),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Allocation {
+    /// Where each virtual register lives, if it's used at all
+    pub locs: Vec<Option<Loc>>,
+
+    /// How many stack slots spilled values need
+    pub slots: u32,
+
+    /// The callee-saved registers the function uses, which it has to save and restore
+    pub saved: Vec<Reg>,
+
+    /// How many intervals didn't get a register
+    pub spilled: usize,
+}
+
+impl Allocation {
+    pub fn loc(&self, vreg: VReg) -> Loc {
+        self.locs[vreg.index()].expect("Expected every virtual register that's used to have a location")
+    }
+
+    /// Gets where a stack slot is in the frame, which is below the saved `rbp` and callee-saved registers
+    pub fn slot(&self, slot: u32) -> Mem {
+        Mem::Base(Reg::Rbp, -8 * (self.saved.len() as i32 + 1 + slot as i32))
+    }
+}
+
+/// Allocates registers by linear scan
+///
+/// Intervals are given registers in the order they start. One that holds a value across a call can only have a
+/// register the callee saves. When no register is free, whichever of the intervals that could use the register ends
+/// last goes to the stack, since that frees a register for the longest. An interval is given the register of the one it
+/// was copied or computed from if it's free, so chains of arithmetic stay in one register. A slot is reused once the
+/// interval that last had it has ended, which for one spilled after it started means before its start, not the
+/// current one's.
+pub fn allocate(function: &MFunction) -> Allocation {
+    let liveness = live_intervals(function);
+    let hints = hints(function);
+    let mut allocation = Allocation {
+        locs: vec![None; function.vregs as usize],
+        slots: 0,
+        saved: Vec::new(),
+        spilled: 0,
+    };
+
+    let mut active: Vec<(Interval, Reg)> = Vec::new();
+    let mut free_slots: Vec<u32> = Vec::new();
+    // The interval that last had each slot
+    let mut slot_users: Vec<Interval> = Vec::new();
+    let mut stack: Vec<(Interval, u32)> = Vec::new();
+    for &interval in &liveness.intervals {
+        // An interval that ends where this one starts frees its register, unless it only holds a value that's never read
+        active.retain(|&(other, _)| !expires(&other, &interval));
+        stack.retain(|&(other, slot)| {
+            let expired = expires(&other, &interval);
+            if expired {
+                free_slots.push(slot);
+            }
+            !expired
+        });
+
+        let allowed = allowed_regs(&liveness, &interval);
+        let taken = |reg: Reg, active: &[(Interval, Reg)]| active.iter().any(|&(_, r)| r == reg);
+        let hinted = hints.get(&interval.vreg).and_then(|hint| match *hint {
+            Hint::Reg(reg) => Some(reg),
+            Hint::Like(other) => match allocation.locs[other.index()] {
+                Some(Loc::Reg(reg)) => Some(reg),
+                _ => None,
+            },
+        }).filter(|&reg| allowed.contains(&reg) && !taken(reg, &active));
+        let choice = hinted.or_else(|| allowed.iter().cloned().find(|&reg| !taken(reg, &active)));
+
+        let reg = match choice {
+            Some(reg) => Some(reg),
+            None => {
+                // Spill whichever interval that could give this one its register ends last
+                let victim = active.iter().enumerate()
+                    .filter(|&(_, &(_, reg))| allowed.contains(&reg))
+                    .max_by_key(|&(_, &(other, _))| other.end)
+                    .map(|(i, &(other, _))| (i, other.end));
+                match victim {
+                    Some((i, end)) if end > interval.end => {
+                        let (other, reg) = active.remove(i);
+                        let slot = take_slot(&mut free_slots, &mut slot_users, &other);
+                        allocation.locs[other.vreg.index()] = Some(Loc::Stack(slot));
+                        allocation.spilled += 1;
+                        stack.push((other, slot));
+                        Some(reg)
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        match reg {
+            Some(reg) => {
+                allocation.locs[interval.vreg.index()] = Some(Loc::Reg(reg));
+                if reg.is_callee_saved() && !allocation.saved.contains(&reg) {
+                    allocation.saved.push(reg);
+                }
+                active.push((interval, reg));
+            }
+            None => {
+                let slot = take_slot(&mut free_slots, &mut slot_users, &interval);
+                allocation.locs[interval.vreg.index()] = Some(Loc::Stack(slot));
+                allocation.spilled += 1;
+                stack.push((interval, slot));
+            }
+        }
+    }
+    allocation.saved.sort();
+    allocation.slots = slot_users.len() as u32;
+    allocation
+}
+
+/// Takes a free slot that no interval has while this one is live, or a new slot if there's none
+fn take_slot(free_slots: &mut Vec<u32>, slot_users: &mut Vec<Interval>, interval: &Interval) -> u32 {
+    let slot = match free_slots.iter().rposition(|&slot| expires(&slot_users[slot as usize], interval)) {
+        Some(i) => free_slots.remove(i),
+        None => {
+            slot_users.push(*interval);
+            slot_users.len() as u32 - 1
+        }
+    };
+    slot_users[slot as usize] = *interval;
+    slot
+}
+
+/// Returns `true` if an interval no longer needs its location once another starts
+fn expires(interval: &Interval, next: &Interval) -> bool {
+    interval.end < next.start || (interval.end == next.start && interval.start < interval.end)
+}
+
+fn allowed_regs(liveness: &Liveness, interval: &Interval) -> Vec<Reg> {
+    if liveness.crosses_call(interval) {
+        ALLOCATABLE.iter().cloned().filter(|r| r.is_callee_saved()).collect()
+    } else {
+        ALLOCATABLE.to_vec()
+    }
+}
+
+/// A register that would save a move if a virtual register were given it
+enum Hint {
+    Reg(Reg),
+    Like(VReg),
+}
+
+fn hints(function: &MFunction) -> HashMap<VReg, Hint> {
+    let mut hints = HashMap::new();
+    for block in &function.blocks {
+        for inst in &block.insts {
+            match *inst {
+                MInst::Params(ref params) => {
+                    let ints = params.iter().filter(|&&(_, class)| class == ::x86::Class::Int);
+                    for (&(v, _), &reg) in ints.zip(INT_ARGS.iter()) {
+                        hints.insert(v, Hint::Reg(reg));
+                    }
+                }
+                MInst::Int(_, dst, a, _, _) => {
+                    hints.insert(dst, Hint::Like(a));
+                }
+                MInst::Copies(ref copies) => {
+                    for &(dst, src) in copies {
+                        hints.insert(dst, Hint::Like(src));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    hints
+}
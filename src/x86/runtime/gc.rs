@@ -0,0 +1,442 @@
+//! A precise mark-and-sweep collector for the strings and objects of a program
+//!
+//! The heap is a list of chunks of memory from the C library, each holding a run of blocks. A block starts with a
+//! header word, with its size in bytes and whether it's marked, free or a string, then a word that's a string's
+//! length, an object's mask of the fields that are references, or a free block's next in the free list. A string or
+//! object's address is that of what follows, so they're laid out as compiled code expects. The strings among the
+//! program's constants look the same but aren't in any chunk, so they're never collected, and neither are vtables.
+//!
+//! A collection marks everything reachable from the roots, then sweeps every chunk, joining runs of unmarked blocks
+//! into free blocks. The roots are the references the runtime is working with and those live across each call on the
+//! stack, which the compiler's stack maps in `why_stack_maps` say where to find. The stack is walked from the frame
+//! of the call into the runtime by the `rbp` of each frame, which compiled code always keeps, until it gets back to
+//! `main`. Callee-saved registers are found where the frames below saved them, or where the runtime's entry saved
+//! them. A frame the stack maps don't know, like one of a C function calling back into the program, stops the walk,
+//! and since the roots can't all be found, the collection is given up and the heap grows instead.
+//!
+//! A collection runs once as many bytes have been allocated since the last one as were live after it, and at least a
+//! megabyte, or before every allocation if stressed, so a missing root shows up right away.
+//!
+//! The runtime of executables has a heap for the program, and `whyc run` has one for each program it loads, so this
+//! is a module of each and only uses `core` and the C library. Each gives its heap the stack maps and where `main`
+//! returns to, and enters it through functions that save the registers of the code calling them.
+
+use core::fmt;
+use core::ptr;
+
+extern "C" {
+    fn calloc(count: usize, size: usize) -> *mut u8;
+    fn realloc(memory: *mut u8, size: usize) -> *mut u8;
+    fn free(memory: *mut u8);
+    fn getenv(name: *const u8) -> *const u8;
+    fn write(fd: i32, bytes: *const u8, count: usize) -> isize;
+    fn abort() -> !;
+}
+
+const MARKED: u64 = 1;
+const FREE: u64 = 2;
+const STRING: u64 = 4;
+const FLAGS: u64 = 7;
+
+/// The bytes of the header and second word of a block
+const HEADER: usize = 16;
+
+/// The smallest block, which has room for a word
+const MIN_BLOCK: usize = HEADER + 8;
+
+/// The size of a chunk, unless a block needs more
+const CHUNK: usize = 1 << 20;
+
+/// How many callee-saved registers the stack maps track: `rbx`, `r12`, `r13`, `r14` and `r15`
+const SAVED: usize = 5;
+
+/// A chunk of the heap, whose blocks follow it up to `top`, with room for more up to `end`
+struct Chunk {
+    next: *mut Chunk,
+    top: *mut u64,
+    end: *mut u64,
+}
+
+impl Chunk {
+    fn blocks(&self) -> *mut u64 {
+        unsafe { (self as *const Chunk).offset(1) as *mut u64 }
+    }
+}
+
+/// How much the program has allocated and collected
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub collections: u64,
+
+    /// Collections given up because the stack couldn't be walked
+    pub abandoned: u64,
+    pub allocated: u64,
+    pub allocated_bytes: u64,
+    pub freed: u64,
+    pub freed_bytes: u64,
+    pub heap_bytes: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} collections", self.collections)?;
+        if self.abandoned > 0 {
+            write!(f, " ({} given up)", self.abandoned)?;
+        }
+        write!(f, ", {} objects allocated ({} bytes), {} freed ({} bytes), {} live ({} bytes), {} bytes of heap",
+               self.allocated, self.allocated_bytes, self.freed, self.freed_bytes, self.allocated - self.freed,
+               self.allocated_bytes - self.freed_bytes, self.heap_bytes)
+    }
+}
+
+#[derive(Debug)]
+pub struct Heap {
+    /// The stack maps of the program, laid out as the compiler lays out `why_stack_maps`
+    maps: *const u64,
+
+    /// Where `main` returns to, which is where walking the stack stops
+    returned: u64,
+    chunks: *mut Chunk,
+
+    /// The first free block, whose second word points to the next
+    free: *mut u64,
+    since_collection: usize,
+    threshold: usize,
+    stress: Option<bool>,
+
+    /// Objects found by marking whose fields haven't been marked yet
+    gray: *mut u64,
+    gray_len: usize,
+    gray_capacity: usize,
+    pub stats: Stats,
+}
+
+/// Returns `true` if an environment variable is set to something other than `0`
+pub fn env_flag(name: &[u8]) -> bool {
+    unsafe {
+        let value = getenv(name.as_ptr());
+        !value.is_null() && *value != 0 && !(*value == b'0' && *value.offset(1) == 0)
+    }
+}
+
+fn out_of_memory() -> ! {
+    let message = b"error: out of memory\n";
+    unsafe {
+        write(2, message.as_ptr(), message.len());
+        abort()
+    }
+}
+
+impl Heap {
+    /// Makes an empty heap for a program with the stack maps given, whose `main` returns to `returned`. It collects
+    /// before every allocation if `stress` is set, or if it's `None` and `WHY_GC_STRESS` is set
+    pub fn new(maps: *const u64, returned: u64, stress: Option<bool>) -> Heap {
+        Heap {
+            maps,
+            returned,
+            chunks: ptr::null_mut(),
+            free: ptr::null_mut(),
+            since_collection: 0,
+            threshold: CHUNK,
+            stress,
+            gray: ptr::null_mut(),
+            gray_len: 0,
+            gray_capacity: 0,
+            stats: Stats {
+                collections: 0,
+                abandoned: 0,
+                allocated: 0,
+                allocated_bytes: 0,
+                freed: 0,
+                freed_bytes: 0,
+                heap_bytes: 0,
+            },
+        }
+    }
+
+    /// Gives the heap a new table of stack maps, for code compiled while the program runs
+    pub fn set_maps(&mut self, maps: *const u64) {
+        self.maps = maps;
+    }
+
+    /// Allocates an object of `count` words, with a bit set in `pointers` for each that's a reference
+    pub unsafe fn new_object(&mut self, count: i64, pointers: u64, frame: *const u64) -> *mut u64 {
+        self.allocate(count as usize, false, pointers, frame, &[])
+    }
+
+    /// Allocates a string of the bytes of `a` followed by those of `b`
+    pub unsafe fn concat(&mut self, a: *const u8, b: *const u8, frame: *const u64) -> *const u8 {
+        let (a_len, b_len) = (*(a as *const u64).offset(-1) as usize, *(b as *const u64).offset(-1) as usize);
+        let len = a_len + b_len;
+        // The length, then the bytes and a zero, like the strings in a compiled program, rounded up to words
+        let roots = [a as u64, b as u64];
+        let bytes = self.allocate((len + 8) / 8, true, len as u64, frame, &roots) as *mut u8;
+        ptr::copy_nonoverlapping(a, bytes, a_len);
+        ptr::copy_nonoverlapping(b, bytes.add(a_len), b_len);
+        bytes
+    }
+
+    /// Allocates a string of the bytes of a C string up to its zero, which is empty for a null pointer
+    pub unsafe fn from_c(&mut self, s: *const u8, frame: *const u64) -> *const u8 {
+        let mut len = 0;
+        while !s.is_null() && *s.add(len) != 0 {
+            len += 1;
+        }
+        let bytes = self.allocate((len + 8) / 8, true, len as u64, frame, &[]) as *mut u8;
+        if len > 0 {
+            ptr::copy_nonoverlapping(s, bytes, len);
+        }
+        bytes
+    }
+
+    /// Allocates a zeroed block with room for `words` words, with at least one so every block has its own address,
+    /// returning the address of its contents, which `second` comes right before. `frame` is the frame of the runtime's
+    /// entry, and `roots` are references the runtime still needs.
+    pub unsafe fn allocate(&mut self, words: usize, string: bool, second: u64, frame: *const u64, roots: &[u64])
+                           -> *mut u64 {
+        let size = HEADER + 8 * if words == 0 { 1 } else { words };
+        let stress = *self.stress.get_or_insert_with(|| env_flag(b"WHY_GC_STRESS\0"));
+        if stress || self.since_collection + size > self.threshold {
+            self.collect(frame, roots);
+        }
+
+        let block = match self.take_free(size) {
+            Some(block) => block,
+            None => self.bump(size),
+        };
+        let size = (*block & !FLAGS) as usize;
+        ptr::write_bytes(block.offset(1), 0, size / 8 - 1);
+        *block = size as u64 | if string { STRING } else { 0 };
+        *block.offset(1) = second;
+        self.since_collection += size;
+        self.stats.allocated += 1;
+        self.stats.allocated_bytes += size as u64;
+        block.offset(2)
+    }
+
+    /// Takes the first free block that's large enough, splitting off what's left if it's enough for another block
+    unsafe fn take_free(&mut self, size: usize) -> Option<*mut u64> {
+        let mut link: *mut *mut u64 = &mut self.free;
+        while !(*link).is_null() {
+            let block = *link;
+            let available = (*block & !FLAGS) as usize;
+            if available >= size {
+                let next = *block.offset(1) as *mut u64;
+                if available - size >= MIN_BLOCK {
+                    let rest = block.offset(size as isize / 8);
+                    *rest = (available - size) as u64 | FREE;
+                    *rest.offset(1) = next as u64;
+                    *link = rest;
+                    *block = size as u64;
+                } else {
+                    *link = next;
+                }
+                return Some(block);
+            }
+            link = block.offset(1) as *mut *mut u64;
+        }
+        None
+    }
+
+    /// Takes a block from the end of the newest chunk, adding a chunk if it's full
+    unsafe fn bump(&mut self, size: usize) -> *mut u64 {
+        let chunk = self.chunks;
+        if chunk.is_null() || ((*chunk).end as usize - (*chunk).top as usize) < size {
+            let bytes = ::core::mem::size_of::<Chunk>() + if size > CHUNK { size } else { CHUNK };
+            let new = calloc(bytes, 1) as *mut Chunk;
+            if new.is_null() {
+                out_of_memory();
+            }
+            (*new).next = chunk;
+            (*new).top = (*new).blocks();
+            (*new).end = (new as *mut u8).add(bytes) as *mut u64;
+            self.chunks = new;
+            self.stats.heap_bytes += bytes as u64;
+        }
+        let chunk = self.chunks;
+        let block = (*chunk).top;
+        (*chunk).top = block.offset(size as isize / 8);
+        *block = size as u64;
+        block
+    }
+
+    /// Returns `true` if a value is the address of a string or object in the heap
+    unsafe fn contains(&self, value: u64) -> bool {
+        let mut chunk = self.chunks;
+        while !chunk.is_null() {
+            let blocks = (*chunk).blocks() as u64;
+            if value % 8 == 0 && blocks + HEADER as u64 <= value && value < (*chunk).top as u64 {
+                return true;
+            }
+            chunk = (*chunk).next;
+        }
+        false
+    }
+
+    /// Marks a value if it's in the heap, noting an object so its fields are marked too
+    unsafe fn mark(&mut self, value: u64) {
+        if !self.contains(value) {
+            return;
+        }
+        let block = (value as *mut u64).offset(-2);
+        if *block & MARKED != 0 {
+            return;
+        }
+        *block |= MARKED;
+        if *block & STRING == 0 {
+            if self.gray_len == self.gray_capacity {
+                self.gray_capacity = if self.gray_capacity == 0 { 256 } else { 2 * self.gray_capacity };
+                self.gray = realloc(self.gray as *mut u8, 8 * self.gray_capacity) as *mut u64;
+                if self.gray.is_null() {
+                    out_of_memory();
+                }
+            }
+            *self.gray.add(self.gray_len) = value;
+            self.gray_len += 1;
+        }
+    }
+
+    /// Marks the fields of every object marked so far, and those of every object they mark
+    unsafe fn trace(&mut self) {
+        while self.gray_len > 0 {
+            self.gray_len -= 1;
+            let object = *self.gray.add(self.gray_len) as *const u64;
+            let pointers = *object.offset(-1);
+            for field in 0..64 {
+                if pointers & 1 << field != 0 {
+                    self.mark(*object.offset(field));
+                }
+            }
+        }
+    }
+
+    /// Marks the references live across each call on the stack, starting from the frame of the runtime's entry,
+    /// returning `false` if a frame can't be walked
+    unsafe fn mark_stack(&mut self, frame: *const u64) -> bool {
+        // Where the value each callee-saved register has in the frame being scanned is, starting with where the entry
+        // pushed them
+        let mut registers = [ptr::null::<u64>(); SAVED];
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = frame.offset(-(i as isize) - 1);
+        }
+        let (mut rbp, mut ret) = (*frame as *const u64, *frame.offset(1));
+        while ret != self.returned {
+            let map = match self.find_map(ret) {
+                Some(map) => map,
+                None => return false,
+            };
+            let (saved, in_registers, slots) = (*map.offset(1), *map.offset(2), *map.offset(3));
+            for (bit, &register) in registers.iter().enumerate() {
+                if in_registers & 1 << bit != 0 {
+                    self.mark(*register);
+                }
+            }
+            for i in 0..slots as isize {
+                let disp = *map.offset(4 + i) as i64 as isize;
+                self.mark(*((rbp as *const u8).offset(disp) as *const u64));
+            }
+            // The registers the frame saved hold its caller's values, below its `rbp` in order
+            let mut below = 0;
+            for (bit, register) in registers.iter_mut().enumerate() {
+                if saved & 1 << bit != 0 {
+                    below += 1;
+                    *register = rbp.offset(-below);
+                }
+            }
+            ret = *rbp.offset(1);
+            rbp = *rbp as *const u64;
+        }
+        true
+    }
+
+    /// Frees every string and object that isn't reachable from the roots
+    pub unsafe fn collect(&mut self, frame: *const u64, roots: &[u64]) {
+        self.since_collection = 0;
+        if !self.mark_stack(frame) {
+            self.stats.abandoned += 1;
+            self.gray_len = 0;
+            self.sweep(false);
+            return;
+        }
+        for &root in roots {
+            self.mark(root);
+        }
+        self.trace();
+        let live = self.sweep(true);
+        self.stats.collections += 1;
+        self.threshold = if live > CHUNK { live } else { CHUNK };
+    }
+
+    /// Clears the marks, and if `free` is set, rebuilds the free list from the blocks that weren't marked, returning
+    /// how many bytes are still live
+    unsafe fn sweep(&mut self, free: bool) -> usize {
+        if free {
+            self.free = ptr::null_mut();
+        }
+        let mut live = 0;
+        let mut chunk = self.chunks;
+        while !chunk.is_null() {
+            let mut block = (*chunk).blocks();
+            let mut run: *mut u64 = ptr::null_mut();
+            while block < (*chunk).top {
+                let header = *block;
+                let size = header & !FLAGS;
+                if header & MARKED != 0 || !free {
+                    *block = header & !MARKED;
+                    live += size as usize;
+                    self.free_run(run, block);
+                    run = ptr::null_mut();
+                } else {
+                    if header & FREE == 0 {
+                        self.stats.freed += 1;
+                        self.stats.freed_bytes += size;
+                    }
+                    if run.is_null() {
+                        run = block;
+                    }
+                }
+                block = block.offset(size as isize / 8);
+            }
+            self.free_run(run, (*chunk).top);
+            chunk = (*chunk).next;
+        }
+        live
+    }
+
+    /// Joins the blocks from `run` up to `end` into one free block
+    unsafe fn free_run(&mut self, run: *mut u64, end: *mut u64) {
+        if run.is_null() {
+            return;
+        }
+        *run = (end as u64 - run as u64) | FREE;
+        *run.offset(1) = self.free as u64;
+        self.free = run;
+    }
+
+    /// Finds the stack map of the call returning to an address
+    unsafe fn find_map(&self, ret: u64) -> Option<*const u64> {
+        let mut map = self.maps.offset(1);
+        for _ in 0..*self.maps {
+            if *map == ret {
+                return Some(map);
+            }
+            map = map.offset(4 + *map.offset(3) as isize);
+        }
+        None
+    }
+}
+
+/// Gives the memory of the heap back to the C library, which only `whyc run` does, as its programs finish
+impl Drop for Heap {
+    fn drop(&mut self) {
+        unsafe {
+            let mut chunk = self.chunks;
+            while !chunk.is_null() {
+                let next = (*chunk).next;
+                free(chunk as *mut u8);
+                chunk = next;
+            }
+            free(self.gray as *mut u8);
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! The runtime for Why programs compiled to native code, which whyc links into every executable
+//!
+//! This isn't a module of the compiler: `build.rs` compiles it on its own as a `no_std` static library, and the
+//! compiler embeds the library as `x86::RUNTIME_LIBRARY`. It only needs the C library, for memory, flushing what
+//! externs wrote, and `pow`.
+//!
+//! The C `main` of an executable, in `main.rs`, calls `why_run_main`, which calls the program's `why_main` and prints
+//! the value of its final expression. The object says what that value is by the byte at `why_main_type`, the position
+//! of its type in `unit int float bool str ptr fn`, and it's printed as `whyc run` prints it. An object is printed
+//! field by field with `show`, from the description of its type at `why_main_layout`. Every value but a float is passed
+//! as an `i64`. A string points at its bytes, which are followed by a zero and preceded by its length and a header
+//! word, and an object points at its first field.
+//!
+//! Strings and objects are allocated in a heap that `gc` collects. The functions that allocate are entered through
+//! `collecting!`, which saves the callee-saved registers and passes the frame it makes, so the collector can walk the
+//! stack. With `WHY_GC_STATS` set, a program reports what it allocated and collected when it finishes.
+//!
+//! A program that fails reports where with a `file:line:column` location from the object, and exits with status 101.
+
+#![no_std]
+
+mod gc;
+mod show;
+
+use core::arch::global_asm;
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::{mem, ptr, slice};
+
+use gc::Heap;
+
+extern "C" {
+    fn write(fd: i32, bytes: *const u8, count: usize) -> isize;
+    fn fflush(stream: *mut u8) -> i32;
+    fn exit(status: i32) -> !;
+    fn abort() -> !;
+    fn pow(a: f64, b: f64) -> f64;
+
+    fn why_main() -> i64;
+    static why_main_type: u8;
+    static why_main_layout: u8;
+    static why_stack_maps: u64;
+    static why_main_returned: u8;
+
+    /// Calls `why_main` from a frame the collector knows is the last
+    fn why_start() -> i64;
+}
+
+// The collector stops walking the stack once it gets back to `why_start`
+global_asm!(
+    ".globl why_start",
+    ".hidden why_start",
+    ".type why_start, @function",
+    "why_start:",
+    "    push rbp",
+    "    mov rbp, rsp",
+    "    call {main}",
+    ".globl why_main_returned",
+    ".hidden why_main_returned",
+    "why_main_returned:",
+    "    pop rbp",
+    "    ret",
+    main = sym why_main,
+);
+
+/// Defines a runtime function that allocates, which calls `$target` with its arguments and the frame it makes in
+/// `$frame`, the register after them. The frame holds the callee-saved registers of the code that called it, in the
+/// order the stack maps number them
+macro_rules! collecting {
+    ($name:expr, $target:ident, $frame:expr) => {
+        global_asm!(
+            concat!(".globl ", $name),
+            concat!(".type ", $name, ", @function"),
+            concat!($name, ":"),
+            "    push rbp",
+            "    mov rbp, rsp",
+            "    push rbx",
+            "    push r12",
+            "    push r13",
+            "    push r14",
+            "    push r15",
+            "    sub rsp, 8",
+            concat!("    mov ", $frame, ", rbp"),
+            "    call {target}",
+            "    leave",
+            "    ret",
+            target = sym $target,
+        );
+    };
+}
+
+collecting!("why_new", new, "rdx");
+collecting!("why_str_concat", str_concat, "rdx");
+collecting!("why_str_from_c", str_from_c, "rsi");
+
+const STDOUT: i32 = 1;
+const STDERR: i32 = 2;
+
+/// The messages of the kinds of trap, by the number code passes to `why_trap`
+const TRAPS: [&'static str; 3] = [
+    "attempt to compute a result that overflows",
+    "attempt to divide by zero",
+    "attempt to raise to a negative power",
+];
+
+/// Writes straight to a file descriptor, after anything the C library is holding on to
+struct Output(i32);
+
+impl Output {
+    fn new(fd: i32) -> Output {
+        unsafe { fflush(ptr::null_mut()) };
+        Output(fd)
+    }
+
+    fn write_bytes(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let written = unsafe { write(self.0, bytes.as_ptr(), bytes.len()) };
+            if written <= 0 {
+                return;
+            }
+            bytes = &bytes[written as usize..];
+        }
+    }
+}
+
+impl Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Gets the bytes of a string, whose length is in the word before them
+unsafe fn str_bytes<'a>(s: *const u8) -> &'a [u8] {
+    let len = *(s as *const u64).offset(-1);
+    slice::from_raw_parts(s, len as usize)
+}
+
+/// Gets the bytes of a zero-terminated location
+unsafe fn location_bytes<'a>(location: *const u8) -> &'a [u8] {
+    let mut len = 0;
+    while *location.offset(len as isize) != 0 {
+        len += 1;
+    }
+    slice::from_raw_parts(location, len)
+}
+
+/// Reports a failure at a location and stops the program
+fn fail(location: *const u8, message: fmt::Arguments) -> ! {
+    let mut out = Output::new(STDERR);
+    out.write_bytes(unsafe { location_bytes(location) });
+    let _ = writeln!(out, ": error: {}", message);
+    unsafe { exit(101) }
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    let _ = writeln!(Output::new(STDERR), "error: the runtime failed: {}", info);
+    unsafe { abort() }
+}
+
+/// Writes the value of a program's final expression, given the position of its type, like `jit::format_value`
+fn print_value(out: &mut Output, value: i64, ty: u8) {
+    let _ = match ty {
+        0 => out.write_str("()"),
+        1 => write!(out, "{}", value),
+        2 => write!(out, "{:?}", f64::from_bits(value as u64)),
+        3 => write!(out, "{}", value != 0),
+        4 => {
+            out.write_bytes(unsafe { str_bytes(value as *const u8) });
+            Ok(())
+        }
+        5 => unsafe {
+            let layout = &why_main_layout as *const u8;
+            let mut len = 0;
+            while *layout.offset(len) != 0 {
+                len += 1;
+            }
+            let layout = slice::from_raw_parts(layout, len as usize);
+            show::show(out, layout, value, &|out: &mut Output, i| write!(out, "{}", i))
+        },
+        _ => out.write_str("<fn>"),
+    };
+    let _ = out.write_str("\n");
+}
+
+/// Runs the program for the `main` an executable is linked with
+#[no_mangle]
+pub extern "C" fn why_run_main() -> i32 {
+    unsafe {
+        // A `float` comes back in `xmm0` rather than `rax`
+        let ty = why_main_type;
+        let value = if ty == 2 {
+            let main: extern "C" fn() -> f64 = mem::transmute(why_start as unsafe extern "C" fn() -> i64);
+            main().to_bits() as i64
+        } else {
+            why_start()
+        };
+        print_value(&mut Output::new(STDOUT), value, ty);
+    }
+    if gc::env_flag(b"WHY_GC_STATS\0") {
+        let _ = writeln!(Output::new(STDERR), "heap: {}", heap().stats);
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn why_trap(kind: i64, location: *const u8) -> ! {
+    fail(location, format_args!("{}", TRAPS[kind as usize]))
+}
+
+/// Stops the program with a message, which is a string of the program
+#[no_mangle]
+pub extern "C" fn why_panic(message: *const u8, location: *const u8) -> ! {
+    let message = unsafe { str_bytes(message) };
+    fail(location, format_args!("{}", ::core::str::from_utf8(message).unwrap_or("the program panicked")))
+}
+
+/// Stops the program after an index that's out of the bounds of what it indexed
+#[no_mangle]
+pub extern "C" fn why_bounds_fail(index: i64, length: i64, location: *const u8) -> ! {
+    fail(location, format_args!("index {} is out of bounds for a length of {}", index, length))
+}
+
+struct Global(UnsafeCell<Option<Heap>>);
+
+// The program only runs on one thread
+unsafe impl Sync for Global {}
+
+static HEAP: Global = Global(UnsafeCell::new(None));
+
+/// Gets the program's heap, making it the first time
+fn heap() -> &'static mut Heap {
+    unsafe {
+        let returned = &why_main_returned as *const u8 as u64;
+        (*HEAP.0.get()).get_or_insert_with(|| Heap::new(&why_stack_maps, returned, None))
+    }
+}
+
+extern "C" fn new(count: i64, pointers: u64, frame: *const u64) -> *mut u64 {
+    unsafe { heap().new_object(count, pointers, frame) }
+}
+
+extern "C" fn str_concat(a: *const u8, b: *const u8, frame: *const u64) -> *const u8 {
+    unsafe { heap().concat(a, b, frame) }
+}
+
+extern "C" fn str_from_c(s: *const u8, frame: *const u64) -> *const u8 {
+    unsafe { heap().from_c(s, frame) }
+}
+
+#[no_mangle]
+pub extern "C" fn why_str_cmp(a: *const u8, b: *const u8) -> i64 {
+    unsafe { str_bytes(a).cmp(str_bytes(b)) as i64 }
+}
+
+/// Raises an `int` to a power by squaring, handling overflow by the mode: trap (0), wrap (1) or saturate (2)
+#[no_mangle]
+pub extern "C" fn why_int_pow(a: i64, b: i64, mode: i64, location: *const u8) -> i64 {
+    if b < 0 {
+        why_trap(2, location);
+    }
+    // Every square of the base is used by the result, so if squaring overflows, so does the result, and the wrapped
+    // result is right whether or not anything did
+    let (mut result, mut base, mut exp, mut overflowed) = (1i64, a, b as u64, false);
+    while exp > 0 {
+        if exp & 1 == 1 {
+            let (product, overflow) = result.overflowing_mul(base);
+            result = product;
+            overflowed |= overflow;
+        }
+        exp >>= 1;
+        if exp > 0 {
+            let (square, overflow) = base.overflowing_mul(base);
+            base = square;
+            overflowed |= overflow;
+        }
+    }
+    match mode {
+        _ if !overflowed => result,
+        0 => why_trap(0, location),
+        1 => result,
+        _ if a < 0 && b % 2 == 1 => i64::min_value(),
+        _ => i64::max_value(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn why_float_pow(a: f64, b: f64) -> f64 {
+    unsafe { pow(a, b) }
+}
@@ -0,0 +1,15 @@
+//! The C `main` of native executables, which only calls the runtime's `why_run_main`
+//!
+//! This is built by `build.rs` as an object of its own, apart from the runtime library, so an object compiled with
+//! `--emit=obj` can be linked with the runtime into a C program that has its own `main`.
+
+#![no_std]
+
+use core::arch::global_asm;
+
+global_asm!(
+    ".globl main",
+    ".type main, @function",
+    "main:",
+    "    jmp why_run_main",
+);
@@ -0,0 +1,238 @@
+//! Writes a value field by field, from a description of its type that the compiler gives alongside the program
+//!
+//! The runtime and `whyc run` both print the value of a program's final expression with this, so they print it the
+//! same. It's a module of each, and only uses `core`.
+//!
+//! A description is the type of the value, then each struct and enum the value can hold on a line of its own, since
+//! those can refer to themselves:
+//!
+//! | Text                                   | Type                                                                  |
+//! |----------------------------------------|-----------------------------------------------------------------------|
+//! | `u`, `i`, `f`, `b`, `s`, `F`           | `()`, `Int`, `Float`, `Bool`, `Str` and functions                     |
+//! | `(` types `)`                          | a tuple of the types                                                  |
+//! | `D` trait `;`                          | a `dyn` value of the trait                                            |
+//! | number `;`                             | the struct or enum on that line, counting the one after the type as 0 |
+//! | `S` name `;` (field `:` type)*         | a struct, on its line                                                 |
+//! | `E` (`|` variant `(` types `)`)*       | an enum, by its variants in the order of their tags, on its line      |
+//!
+//! So `Option<(Int, Str)>` is `0;` then `E|Some((is))|None()`. A value is a word: a float's bits, or the address of a
+//! string or object. An object's fields are a word each, and an enum's start with its tag.
+
+use core::fmt::{self, Write};
+
+/// How deep values are written before the rest is left out, in case they refer to themselves
+const DEPTH: usize = 32;
+
+/// Writes a value of the described type, writing its `int`s with `int`, since how they're stored can vary
+pub fn show<W: Write, I: Fn(&mut W, i64) -> fmt::Result>(out: &mut W, layout: &[u8], value: i64, int: &I)
+    -> fmt::Result {
+    let mut shower = Shower { layout, out, int, depth: 0 };
+    let mut at = 0;
+    shower.value(&mut at, value, false)
+}
+
+struct Shower<'a, 'w, W: 'w, I: 'a> {
+    layout: &'a [u8],
+    out: &'w mut W,
+    int: &'a I,
+    depth: usize,
+}
+
+impl<'a, 'w, W: Write, I: Fn(&mut W, i64) -> fmt::Result> Shower<'a, 'w, W, I> {
+    /// Writes a value of the type described at `at`, moving `at` past the description. Strings are quoted within
+    /// other values
+    fn value(&mut self, at: &mut usize, value: i64, nested: bool) -> fmt::Result {
+        let kind = self.byte(at);
+        match kind {
+            b'u' => self.out.write_str("()"),
+            b'i' => (self.int)(self.out, value),
+            b'f' => write!(self.out, "{:?}", f64::from_bits(value as u64)),
+            b'b' => write!(self.out, "{}", value != 0),
+            b's' => self.string(value, nested),
+            b'F' => self.out.write_str("<fn>"),
+            b'D' => {
+                let name = self.name(at, b';');
+                *at += 1;
+                self.out.write_str("<dyn ")?;
+                self.out.write_str(name)?;
+                self.out.write_str(">")
+            }
+            b'(' if self.depth >= DEPTH => {
+                self.skip_list(at, b')');
+                self.out.write_str("(...)")
+            }
+            b'(' => {
+                self.depth += 1;
+                let fields = value as *const i64;
+                let mut count = 0;
+                self.out.write_str("(")?;
+                while self.peek(*at) != b')' {
+                    if count > 0 {
+                        self.out.write_str(", ")?;
+                    }
+                    let field = unsafe { *fields.offset(count) };
+                    self.value(at, field, true)?;
+                    count += 1;
+                }
+                *at += 1;
+                self.depth -= 1;
+                self.out.write_str(if count == 1 { ",)" } else { ")" })
+            }
+            _ => {
+                *at -= 1;
+                let line = self.number(at);
+                let mut def = self.line(line);
+                if self.depth >= DEPTH {
+                    return self.out.write_str("...");
+                }
+                self.depth += 1;
+                let result = match self.byte(&mut def) {
+                    b'S' => self.struct_(&mut def, value),
+                    _ => self.enum_(&mut def, value),
+                };
+                self.depth -= 1;
+                result
+            }
+        }
+    }
+
+    /// Writes a struct as `P { x: 1, y: 2 }`
+    fn struct_(&mut self, at: &mut usize, value: i64) -> fmt::Result {
+        let name = self.name(at, b';');
+        *at += 1;
+        self.out.write_str(name)?;
+        self.out.write_str(" {")?;
+        let fields = value as *const i64;
+        let mut count = 0;
+        while *at < self.layout.len() && self.peek(*at) != b'\n' {
+            let field = self.name(at, b':');
+            *at += 1;
+            self.out.write_str(if count > 0 { ", " } else { " " })?;
+            self.out.write_str(field)?;
+            self.out.write_str(": ")?;
+            let word = unsafe { *fields.offset(count) };
+            self.value(at, word, true)?;
+            count += 1;
+        }
+        self.out.write_str(if count > 0 { " }" } else { "}" })
+    }
+
+    /// Writes the variant an enum's tag picks, as `Some(1)` or `None`
+    fn enum_(&mut self, at: &mut usize, value: i64) -> fmt::Result {
+        let fields = value as *const i64;
+        let tag = unsafe { *fields };
+        for _ in 0..tag {
+            *at += 1;
+            self.name(at, b'(');
+            *at += 1;
+            self.skip_list(at, b')');
+        }
+        *at += 1;
+        let name = self.name(at, b'(');
+        *at += 1;
+        self.out.write_str(name)?;
+        if self.peek(*at) == b')' {
+            return Ok(());
+        }
+        self.out.write_str("(")?;
+        let mut count = 1;
+        while self.peek(*at) != b')' {
+            if count > 1 {
+                self.out.write_str(", ")?;
+            }
+            let field = unsafe { *fields.offset(count) };
+            self.value(at, field, true)?;
+            count += 1;
+        }
+        self.out.write_str(")")
+    }
+
+    fn string(&mut self, value: i64, nested: bool) -> fmt::Result {
+        let bytes = unsafe {
+            let len = *(value as *const u64).offset(-1);
+            ::core::slice::from_raw_parts(value as *const u8, len as usize)
+        };
+        if nested {
+            self.out.write_char('"')?;
+        }
+        for chunk in bytes.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match c {
+                    '"' | '\\' if nested => write!(self.out, "\\{}", c)?,
+                    '\n' if nested => self.out.write_str("\\n")?,
+                    '\t' if nested => self.out.write_str("\\t")?,
+                    '\r' if nested => self.out.write_str("\\r")?,
+                    '\0' if nested => self.out.write_str("\\0")?,
+                    c => self.out.write_char(c)?,
+                }
+            }
+            if !chunk.invalid().is_empty() {
+                self.out.write_char('\u{fffd}')?;
+            }
+        }
+        if nested {
+            self.out.write_char('"')?;
+        }
+        Ok(())
+    }
+
+    /// Moves past the types of a tuple or variant, up to and past the byte that ends them
+    fn skip_list(&self, at: &mut usize, end: u8) {
+        while self.peek(*at) != end {
+            self.skip(at);
+        }
+        *at += 1;
+    }
+
+    /// Moves past the description of a type
+    fn skip(&self, at: &mut usize) {
+        match self.byte(at) {
+            b'(' => self.skip_list(at, b')'),
+            b'D' | b'0'...b'9' => {
+                self.name(at, b';');
+                *at += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn byte(&self, at: &mut usize) -> u8 {
+        let byte = self.peek(*at);
+        *at += 1;
+        byte
+    }
+
+    fn peek(&self, at: usize) -> u8 {
+        self.layout.get(at).cloned().unwrap_or(b')')
+    }
+
+    /// Reads the text up to the byte that ends it, leaving `at` at that byte
+    fn name(&self, at: &mut usize, end: u8) -> &'a str {
+        let start = *at;
+        while *at < self.layout.len() && self.layout[*at] != end {
+            *at += 1;
+        }
+        ::core::str::from_utf8(&self.layout[start..*at]).unwrap_or("?")
+    }
+
+    /// Reads the number of a line and the `;` after it
+    fn number(&self, at: &mut usize) -> usize {
+        let digits = self.name(at, b';');
+        *at += 1;
+        digits.parse().unwrap_or(0)
+    }
+
+    /// Gets where the struct or enum on a line starts
+    fn line(&self, line: usize) -> usize {
+        let mut lines = 0;
+        for (i, &byte) in self.layout.iter().enumerate() {
+            if byte == b'\n' {
+                if lines == line {
+                    return i + 1;
+                }
+                lines += 1;
+            }
+        }
+        self.layout.len()
+    }
+}
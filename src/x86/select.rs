@@ -0,0 +1,357 @@
+use std::collections::HashSet;
+
+use arith::OverflowMode;
+use ir::{self, Const, InstKind, Terminator, Ty};
+use parser::BinOp;
+use text::{Document, TextSpan};
+use x86::{symbol_name, Callee, Class, CodegenError, Data, Datum, MBlock, MFunction, MInst, MTerminator, Sym, VReg};
+
+/// Chooses the instructions for a function, adding the constants it refers to to the data
+///
+/// Each value of the function keeps its number as a virtual register, and the registers the selector needs for
+/// itself are numbered after them. Edges into a block with phis get a block of their own to copy the phis' values in.
+///
+/// In the `promote` mode, an `int` is a word holding either an integer of 63 bits shifted left with the low bit set,
+/// or the address of an arbitrary precision integer, and everything but moving one around is done by the runtime's
+/// `why_big_*` functions. Only the runtime of `whyc run` defines those.
+pub fn select_function(function: &ir::Function, module: &ir::Module, data: &mut Data, document: Option<&Document>)
+                       -> Result<MFunction, CodegenError> {
+    let types: Vec<Ty> = function.value_types().into_iter().map(|t| t.unwrap_or(Ty::Unit)).collect();
+    let mut selector = Selector {
+        function,
+        module,
+        data,
+        document,
+        types,
+        next_vreg: function.value_bound() as u32,
+        blocks: function.blocks.iter().map(|_| MBlock { insts: Vec::new(), terminator: MTerminator::Unreachable }).collect(),
+        fused: fused_comparisons(function, module.overflow),
+    };
+
+    let params = function.params.iter().map(|&(v, ty)| (vreg(v), class(ty))).collect();
+    selector.blocks[0].insts.push(MInst::Params(params));
+    for b in 0..function.blocks.len() {
+        selector.select_block(b)?;
+    }
+
+    Ok(MFunction {
+        name: symbol_name(module, &function.name),
+        vregs: selector.next_vreg,
+        blocks: selector.blocks,
+        overflow: module.overflow,
+    })
+}
+
+fn vreg(value: ir::Value) -> VReg {
+    VReg(value.0)
+}
+
+pub fn class(ty: Ty) -> Class {
+    if ty == Ty::Float { Class::Float } else { Class::Int }
+}
+
+/// Finds the comparisons of `int`s, `bool`s and `unit`s that are only used by the branch at the end of their block,
+/// which can branch on the flags the comparison sets instead of making a `bool`. Promoted `int`s are compared by the
+/// runtime, so their comparisons are never fused.
+fn fused_comparisons(function: &ir::Function, overflow: OverflowMode) -> HashSet<ir::Value> {
+    let uses = function.use_counts();
+    let types = function.value_types();
+    let mut fused = HashSet::new();
+    for block in &function.blocks {
+        if let Terminator::Branch(cond, _, _) = block.terminator {
+            let compare = block.insts.iter().find(|i| i.value == cond).map_or(false, |inst| match inst.kind {
+                InstKind::Binary(op, a, _) => op.is_comparison() && match types[a.index()] {
+                    Some(Ty::Int) => overflow != OverflowMode::Promote,
+                    Some(Ty::Bool) | Some(Ty::Unit) => true,
+                    _ => false,
+                },
+                _ => false,
+            });
+            if compare && uses[cond.index()] == 1 {
+                fused.insert(cond);
+            }
+        }
+    }
+    fused
+}
+
+struct Selector<'a> {
+    function: &'a ir::Function,
+    module: &'a ir::Module,
+    data: &'a mut Data,
+    document: Option<&'a Document>,
+    types: Vec<Ty>,
+    next_vreg: u32,
+    blocks: Vec<MBlock>,
+    fused: HashSet<ir::Value>,
+}
+
+impl<'a> Selector<'a> {
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, CodegenError> {
+        Err(CodegenError { function: self.function.name.clone(), message: message.into() })
+    }
+
+    fn temp(&mut self) -> VReg {
+        self.next_vreg += 1;
+        VReg(self.next_vreg - 1)
+    }
+
+    fn ty(&self, value: ir::Value) -> Ty {
+        self.types[value.index()]
+    }
+
+    /// Gets the data symbol of the place a failure is reported
+    fn location(&mut self, span: Option<TextSpan>) -> Sym {
+        let location = source_location(self.document, span);
+        Sym::Data(self.data.intern(Datum::Location(location)))
+    }
+
+    fn select_block(&mut self, b: usize) -> Result<(), CodegenError> {
+        let function = self.function;
+        let block = &function.blocks[b];
+        let mut insts = ::std::mem::replace(&mut self.blocks[b].insts, Vec::new());
+        for inst in &block.insts {
+            if self.fused.contains(&inst.value) {
+                continue;
+            }
+            if let Some(span) = inst.span {
+                insts.push(MInst::Source(span));
+            }
+            self.select_inst(inst, &mut insts)?;
+        }
+
+        let terminator = match block.terminator {
+            Terminator::Jump(target) => {
+                let copies = self.phi_copies(b, target.index());
+                if !copies.is_empty() {
+                    insts.push(MInst::Copies(copies));
+                }
+                MTerminator::Jump(target.index())
+            }
+            Terminator::Branch(cond, then, otherwise) => {
+                let then = self.edge(b, then.index());
+                let otherwise = self.edge(b, otherwise.index());
+                match block.insts.iter().find(|i| i.value == cond) {
+                    Some(&ir::Inst { kind: InstKind::Binary(op, x, y), .. }) if self.fused.contains(&cond) => {
+                        MTerminator::CmpBranch(op, vreg(x), vreg(y), then, otherwise)
+                    }
+                    _ => MTerminator::Branch(vreg(cond), then, otherwise),
+                }
+            }
+            Terminator::Return(v) => MTerminator::Return(Some((vreg(v), class(self.ty(v))))),
+            Terminator::Unreachable => MTerminator::Unreachable,
+        };
+        self.blocks[b] = MBlock { insts, terminator };
+        Ok(())
+    }
+
+    /// Gets the copies that give the phis of a block their values when control comes from a predecessor
+    fn phi_copies(&self, pred: usize, block: usize) -> Vec<(VReg, VReg)> {
+        self.function.blocks[block].insts.iter().filter_map(|inst| match inst.kind {
+            InstKind::Phi(ref incoming) => incoming.iter()
+                .find(|&&(p, _)| p.index() == pred)
+                .map(|&(_, v)| (vreg(inst.value), vreg(v))),
+            _ => None,
+        }).collect()
+    }
+
+    /// Gets the block a branch jumps to for an edge, which is a new block holding copies if the target has phis
+    fn edge(&mut self, pred: usize, target: usize) -> usize {
+        let copies = self.phi_copies(pred, target);
+        if copies.is_empty() {
+            return target;
+        }
+        self.blocks.push(MBlock { insts: vec![MInst::Copies(copies)], terminator: MTerminator::Jump(target) });
+        self.blocks.len() - 1
+    }
+
+    fn call_runtime(&mut self, name: &str, args: Vec<(VReg, Class)>, ret: Option<(VReg, Class)>, insts: &mut Vec<MInst>) {
+        insts.push(MInst::Call(Callee::Direct(Sym::Global(name.into())), args, ret));
+    }
+
+    fn promoting(&self) -> bool {
+        self.module.overflow == OverflowMode::Promote
+    }
+
+    fn select_inst(&mut self, inst: &ir::Inst, insts: &mut Vec<MInst>) -> Result<(), CodegenError> {
+        let dst = vreg(inst.value);
+        match inst.kind {
+            InstKind::Const(Const::Int(ref i)) if self.promoting() => match i.to_i64() {
+                Some(i) if i >> 62 == 0 || i >> 62 == -1 => insts.push(MInst::Imm(dst, i << 1 | 1)),
+                // Larger constants are parsed from their digits each time they're made
+                _ => {
+                    let digits = self.temp();
+                    insts.push(MInst::Addr(digits, Sym::Data(self.data.intern(Datum::Str(i.to_string())))));
+                    self.call_runtime("why_big_parse", vec![(digits, Class::Int)], Some((dst, Class::Int)), insts);
+                }
+            },
+            InstKind::Const(ref c) => insts.push(match *c {
+                Const::Unit => MInst::Imm(dst, 0),
+                Const::Int(ref i) => match i.to_i64() {
+                    Some(i) => MInst::Imm(dst, i),
+                    None => return self.error(format!("the integer {} doesn't fit in 64 bits", i)),
+                },
+                Const::Float(f) => MInst::Imm(dst, f.to_bits() as i64),
+                Const::Bool(b) => MInst::Imm(dst, b as i64),
+                Const::Str(ref s) => MInst::Addr(dst, Sym::Data(self.data.intern(Datum::Str(s.clone())))),
+            }),
+            InstKind::Binary(op, a, b) => self.select_binary(inst, op, vreg(a), vreg(b), self.ty(a), insts),
+            InstKind::Call(ref callee, ref args) => {
+                let mut args: Vec<_> = args.iter().map(|&a| (vreg(a), class(self.ty(a)))).collect();
+                let callee = match *callee {
+                    ir::Callee::Direct(ref name) => Callee::Direct(Sym::Global(symbol_name(self.module, name))),
+                    ir::Callee::Indirect(f) => Callee::Indirect(vreg(f)),
+                };
+                let extern_ = match inst.kind {
+                    InstKind::Call(ir::Callee::Direct(ref name), _) => self.module.extern_(name),
+                    _ => None,
+                };
+                let extern_ret = extern_.map(|e| e.ret);
+                if self.promoting() && extern_.is_some() {
+                    // C takes and returns `int`s as plain words, and one that doesn't fit stops the program
+                    for (i, arg) in args.iter_mut().enumerate() {
+                        if extern_.map_or(false, |e| e.params.get(i) == Some(&Ty::Int)) {
+                            let (word, location) = (self.temp(), self.temp());
+                            let sym = self.location(inst.span);
+                            insts.push(MInst::Addr(location, sym));
+                            let demote = vec![(arg.0, Class::Int), (location, Class::Int)];
+                            self.call_runtime("why_big_to_word", demote, Some((word, Class::Int)), insts);
+                            arg.0 = word;
+                        }
+                    }
+                }
+                let c_int = extern_.map_or(false, |e| e.c_int);
+                if extern_ret == Some(Ty::Int) && self.promoting() {
+                    let word = self.temp();
+                    insts.push(MInst::Call(callee, args, Some((word, Class::Int))));
+                    let word = if c_int {
+                        // A C `int` is only the low half of `rax`
+                        let extended = self.temp();
+                        insts.push(MInst::SignExtend(extended, word));
+                        extended
+                    } else {
+                        word
+                    };
+                    self.call_runtime("why_big_from_word", vec![(word, Class::Int)], Some((dst, Class::Int)), insts);
+                } else if c_int {
+                    let ret = self.temp();
+                    insts.push(MInst::Call(callee, args, Some((ret, Class::Int))));
+                    insts.push(MInst::SignExtend(dst, ret));
+                } else if extern_ret == Some(Ty::Unit) {
+                    // A C function returning `void` leaves anything in `rax`
+                    insts.push(MInst::Call(callee, args, None));
+                    insts.push(MInst::Imm(dst, 0));
+                } else if extern_ret == Some(Ty::Bool) {
+                    // and one returning `bool` only sets `al`
+                    let ret = self.temp();
+                    insts.push(MInst::Call(callee, args, Some((ret, Class::Int))));
+                    insts.push(MInst::ZeroExtend(dst, ret));
+                } else if extern_ret == Some(Ty::Str) {
+                    // A C string has no length word and may be null, so it's copied to a string of the program
+                    let ret = self.temp();
+                    insts.push(MInst::Call(callee, args, Some((ret, Class::Int))));
+                    self.call_runtime("why_str_from_c", vec![(ret, Class::Int)], Some((dst, Class::Int)), insts);
+                } else {
+                    insts.push(MInst::Call(callee, args, Some((dst, class(inst.ty)))));
+                }
+            }
+            InstKind::New(ref fields) => {
+                if fields.len() > 64 {
+                    return self.error("objects with more than 64 fields aren't supported by native code");
+                }
+                // The runtime needs to know which fields refer to other heap objects to find the live ones
+                let pointers = fields.iter().enumerate()
+                    .filter(|&(_, &f)| self.ty(f).is_reference())
+                    .fold(0u64, |mask, (i, _)| mask | 1 << i);
+                let (count, mask) = (self.temp(), self.temp());
+                insts.push(MInst::Imm(count, fields.len() as i64));
+                insts.push(MInst::Imm(mask, pointers as i64));
+                self.call_runtime("why_new", vec![(count, Class::Int), (mask, Class::Int)], Some((dst, Class::Int)), insts);
+                for (i, &field) in fields.iter().enumerate() {
+                    insts.push(MInst::Store(dst, 8 * i as i32, vreg(field)));
+                }
+            }
+            InstKind::Get(object, field) => insts.push(MInst::Load(dst, vreg(object), 8 * field as i32)),
+            InstKind::Set(object, field, value) => {
+                insts.push(MInst::Store(vreg(object), 8 * field as i32, vreg(value)));
+                insts.push(MInst::Imm(dst, 0));
+            }
+            // Phis are given their values by the blocks that jump to them
+            InstKind::Phi(_) => {}
+            InstKind::Func(ref name) => insts.push(MInst::Addr(dst, Sym::Global(symbol_name(self.module, name)))),
+            InstKind::VTable(n) => {
+                let functions = self.module.vtables[n as usize].iter().map(|f| symbol_name(self.module, f)).collect();
+                insts.push(MInst::Addr(dst, Sym::Data(self.data.intern(Datum::VTable(functions)))));
+            }
+        }
+        Ok(())
+    }
+
+    fn select_binary(&mut self, inst: &ir::Inst, op: BinOp, a: VReg, b: VReg, ty: Ty, insts: &mut Vec<MInst>) {
+        let dst = vreg(inst.value);
+        match ty {
+            Ty::Int if self.promoting() && op.is_comparison() => {
+                let (order, zero) = (self.temp(), self.temp());
+                let args = vec![(a, Class::Int), (b, Class::Int)];
+                self.call_runtime("why_big_cmp", args, Some((order, Class::Int)), insts);
+                insts.push(MInst::Imm(zero, 0));
+                insts.push(MInst::IntCmp(op, dst, order, zero));
+            }
+            Ty::Int if self.promoting() => {
+                let (code, location) = (self.temp(), self.temp());
+                insts.push(MInst::Imm(code, op as i64));
+                let sym = self.location(inst.span);
+                insts.push(MInst::Addr(location, sym));
+                let args = vec![(a, Class::Int), (b, Class::Int), (code, Class::Int), (location, Class::Int)];
+                self.call_runtime("why_big_binary", args, Some((dst, Class::Int)), insts);
+            }
+            Ty::Int if op == BinOp::Power => {
+                let (mode, location) = (self.temp(), self.temp());
+                let code = match self.module.overflow {
+                    OverflowMode::Trap => 0,
+                    OverflowMode::Wrap => 1,
+                    _ => 2,
+                };
+                insts.push(MInst::Imm(mode, code));
+                let sym = self.location(inst.span);
+                insts.push(MInst::Addr(location, sym));
+                let args = vec![(a, Class::Int), (b, Class::Int), (mode, Class::Int), (location, Class::Int)];
+                self.call_runtime("why_int_pow", args, Some((dst, Class::Int)), insts);
+            }
+            Ty::Int if !op.is_comparison() => {
+                let sym = self.location(inst.span);
+                insts.push(MInst::Int(op, dst, a, b, sym));
+            }
+            Ty::Float if op == BinOp::Power => {
+                let args = vec![(a, Class::Float), (b, Class::Float)];
+                self.call_runtime("why_float_pow", args, Some((dst, Class::Float)), insts);
+            }
+            Ty::Float if op.is_comparison() => insts.push(MInst::FloatCmp(op, dst, a, b)),
+            Ty::Float => insts.push(MInst::Float(op, dst, a, b)),
+            Ty::Str if op == BinOp::Add => {
+                self.call_runtime("why_str_concat", vec![(a, Class::Int), (b, Class::Int)], Some((dst, Class::Int)), insts);
+            }
+            Ty::Str => {
+                // Strings compare by their contents, which the runtime orders like `strcmp`
+                let (order, zero) = (self.temp(), self.temp());
+                self.call_runtime("why_str_cmp", vec![(a, Class::Int), (b, Class::Int)], Some((order, Class::Int)), insts);
+                insts.push(MInst::Imm(zero, 0));
+                insts.push(MInst::IntCmp(op, dst, order, zero));
+            }
+            // Everything else compares by its bits, which for heap objects and functions is by identity
+            _ => insts.push(MInst::IntCmp(op, dst, a, b)),
+        }
+    }
+}
+
+/// Describes where a span starts, as `file:line:column` counting from 1, or by offset if there's no document
+pub fn source_location(document: Option<&Document>, span: Option<TextSpan>) -> String {
+    match (document, span) {
+        (Some(document), Some(span)) => {
+            let (line, column) = document.line_map().map_offset(span.start() as u64);
+            format!("{}:{}:{}", document.path().display(), line + 1, column + 1)
+        }
+        (None, Some(span)) => format!("offset {}", span.start()),
+        (_, None) => "an unknown location".into(),
+    }
+}
@@ -0,0 +1,63 @@
+use x86::{Mem, Operand, Program, Reg};
+
+/// A word of the stack maps
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MapWord {
+    Value(u64),
+
+    /// Where a call returns to, by the position of its function in the program and of the call in the function's
+    /// safepoints
+    Return(usize, usize),
+}
+
+/// Gets the bit of a callee-saved register in the masks of the stack maps
+fn saved_bit(reg: Reg) -> u64 {
+    match reg {
+        Reg::Rbx => 1,
+        Reg::R12 => 2,
+        Reg::R13 => 4,
+        Reg::R14 => 8,
+        Reg::R15 => 16,
+        _ => panic!("Expected {} to be a callee-saved register", reg.name()),
+    }
+}
+
+/// Lays out the table of stack maps, `why_stack_maps`, that the runtime's collector walks the stack with
+///
+/// The table starts with how many calls there are. Each then has where it returns to; the callee-saved registers its
+/// function saves below `rbp`, and those holding references across it, as masks with a bit for each of `rbx`, `r12`,
+/// `r13`, `r14` and `r15` in that order; then how many stack slots hold references and the displacement of each
+/// from `rbp`. Calls are in the order they're laid out in.
+pub fn stack_maps(program: &Program) -> Vec<MapWord> {
+    let mut words = vec![MapWord::Value(0)];
+    let mut count = 0;
+    for (f, function) in program.functions.iter().enumerate() {
+        words.extend(function_stack_maps(program, f));
+        count += function.safepoints.len() as u64;
+    }
+    words[0] = MapWord::Value(count);
+    words
+}
+
+/// Lays out the stack maps of the calls of one function of a program, as they are in `why_stack_maps`
+pub fn function_stack_maps(program: &Program, f: usize) -> Vec<MapWord> {
+    let function = &program.functions[f];
+    let saved = function.saved.iter().fold(0, |mask, &reg| mask | saved_bit(reg));
+    let mut words = Vec::new();
+    for (k, safepoint) in function.safepoints.iter().enumerate() {
+        let (mut regs, mut slots) = (0, Vec::new());
+        for root in &safepoint.roots {
+            match *root {
+                Operand::Reg(reg) => regs |= saved_bit(reg),
+                Operand::Mem(Mem::Base(Reg::Rbp, disp)) => slots.push(disp as i64 as u64),
+                ref other => panic!("Expected a root in a register or stack slot, but got {:?}", other),
+            }
+        }
+        words.push(MapWord::Return(f, k));
+        words.push(MapWord::Value(saved));
+        words.push(MapWord::Value(regs));
+        words.push(MapWord::Value(slots.len() as u64));
+        words.extend(slots.into_iter().map(MapWord::Value));
+    }
+    words
+}